@@ -1,8 +1,30 @@
-from .rattler_build import get_rattler_build_version_py, build_recipes_py
+from .rattler_build import (
+    get_rattler_build_version_py,
+    build_recipes_py,
+    build_recipes_async_py,
+    render_recipes_py,
+    render_recipes_async_py,
+    test_package_py,
+    test_package_async_py,
+    RenderedOutput,
+    ResolvedEnvironment,
+    ResolvedPackage,
+)
 from pathlib import Path
-from typing import Union
+from typing import List, Optional, Union
 
-__all__ = ["rattler_build_version", "build_recipe"]
+__all__ = [
+    "rattler_build_version",
+    "build_recipe",
+    "build_recipe_async",
+    "render",
+    "render_async",
+    "test_package",
+    "test_package_async",
+    "RenderedOutput",
+    "ResolvedEnvironment",
+    "ResolvedPackage",
+]
 
 
 def rattler_build_version() -> str:
@@ -13,3 +35,52 @@ def build_recipe(recipe_path: Union[str, Path], output_dir: Union[str, Path, Non
     output_dir = None if output_dir is None else str(output_dir)
     recipes = [str(recipe_path)]
     build_recipes_py(recipes, output_dir)
+
+
+async def build_recipe_async(recipe_path: Union[str, Path], output_dir: Union[str, Path, None] = None) -> None:
+    """Build a recipe without blocking the event loop.
+
+    The build runs on a Tokio runtime in the background, so multiple builds can
+    be awaited concurrently (e.g. with `asyncio.gather`).
+    """
+    output_dir = None if output_dir is None else str(output_dir)
+    await build_recipes_async_py([str(recipe_path)], output_dir)
+
+
+def test_package(package_file: Union[str, Path], channels: Optional[List[str]] = None) -> None:
+    """Run the tests that are embedded in a package file (`.conda` or `.tar.bz2`)."""
+    test_package_py(str(package_file), channels)
+
+
+async def test_package_async(package_file: Union[str, Path], channels: Optional[List[str]] = None) -> None:
+    """Run the tests of a package file without blocking the event loop."""
+    await test_package_async_py(str(package_file), channels)
+
+
+def render(
+    recipe_path: Union[str, Path],
+    variant_config: Optional[List[Union[str, Path]]] = None,
+    with_solve: bool = True,
+    channels: Optional[List[str]] = None,
+) -> List[RenderedOutput]:
+    """Render a recipe into its outputs.
+
+    Every `RenderedOutput` carries the package name, version, build string and the
+    variant it was rendered with. By default, the build and host environments as
+    well as the run requirements are resolved as well. Pass `with_solve=False` to
+    only render the recipe, without solving (and without accessing the channels).
+    Skipped variants are included with the reason in `skip_reason`.
+    """
+    variant_config = None if variant_config is None else [str(path) for path in variant_config]
+    return render_recipes_py([str(recipe_path)], variant_config, with_solve, channels)
+
+
+async def render_async(
+    recipe_path: Union[str, Path],
+    variant_config: Optional[List[Union[str, Path]]] = None,
+    with_solve: bool = True,
+    channels: Optional[List[str]] = None,
+) -> List[RenderedOutput]:
+    """Render a recipe into its outputs without blocking the event loop (see `render`)."""
+    variant_config = None if variant_config is None else [str(path) for path in variant_config]
+    return await render_recipes_async_py([str(recipe_path)], variant_config, with_solve, channels)
@@ -1,34 +1,299 @@
-use std::path::PathBuf;
+use std::{collections::BTreeMap, future::Future, path::PathBuf};
 
-use ::rattler_build::{build_recipes, get_rattler_build_version, opt::BuildData};
+use ::rattler_build::{
+    build_recipes,
+    console_utils::LoggingOutputHandler,
+    get_rattler_build_version, get_tool_config,
+    metadata::Output,
+    opt::{BuildData, CommonOpts, TestOpts},
+    render::resolved_dependencies::{DependencyInfo, ResolvedDependencies},
+    render_recipes, run_test_from_args,
+};
 use pyo3::prelude::*;
 
+fn runtime_error(e: impl ToString) -> PyErr {
+    PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string())
+}
+
 // Bind the get version function to the Python module
 #[pyfunction]
 fn get_rattler_build_version_py() -> PyResult<String> {
     Ok(get_rattler_build_version().to_string())
 }
 
+async fn build_recipes_impl(recipes: Vec<String>, output_dir: Option<String>) -> PyResult<()> {
+    let recipes = recipes.into_iter().map(PathBuf::from).collect();
+    let mut build_data = BuildData::default();
+    build_data.common.output_dir = output_dir.map(PathBuf::from);
+    build_recipes(recipes, build_data, &None)
+        .await
+        .map_err(runtime_error)
+}
+
 #[pyfunction]
 #[pyo3(signature = (recipes, output_dir=None))]
 fn build_recipes_py(recipes: Vec<String>, output_dir: Option<String>) -> PyResult<()> {
     let rt = tokio::runtime::Runtime::new().unwrap();
-    let recipes = recipes.into_iter().map(PathBuf::from).collect();
-    let mut build_data = BuildData::default();
-    build_data.common.output_dir = output_dir.map(PathBuf::from);
-    rt.block_on(async {
-        if let Err(e) = build_recipes(recipes, build_data, &None).await {
-            return Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(
-                e.to_string(),
-            ));
+    rt.block_on(build_recipes_impl(recipes, output_dir))
+}
+
+/// Run a future that is not `Send` (the build and test futures of
+/// rattler-build hold non-`Send` state across await points) on its own thread
+/// with a current-thread runtime. The returned future is `Send` and resolves to
+/// the result, so that it can be handed to the Python event loop.
+fn spawn_local<F, Fut, T>(make_future: F) -> impl Future<Output = PyResult<T>> + Send + 'static
+where
+    F: FnOnce() -> Fut + Send + 'static,
+    Fut: Future<Output = PyResult<T>> + 'static,
+    T: Send + 'static,
+{
+    let (sender, receiver) = tokio::sync::oneshot::channel();
+    std::thread::spawn(move || {
+        let result = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(runtime_error)
+            .and_then(|rt| rt.block_on(make_future()));
+        let _ = sender.send(result);
+    });
+    async move { receiver.await.map_err(runtime_error)? }
+}
+
+/// Like `build_recipes_py`, but returns an awaitable that runs the build on a
+/// background thread without blocking the Python event loop.
+#[pyfunction]
+#[pyo3(signature = (recipes, output_dir=None))]
+fn build_recipes_async_py(
+    py: Python<'_>,
+    recipes: Vec<String>,
+    output_dir: Option<String>,
+) -> PyResult<Bound<'_, PyAny>> {
+    pyo3_async_runtimes::tokio::future_into_py(
+        py,
+        spawn_local(move || build_recipes_impl(recipes, output_dir)),
+    )
+}
+
+async fn test_package_impl(package_file: String, channels: Option<Vec<String>>) -> PyResult<()> {
+    let args = TestOpts {
+        channel: channels,
+        package_file: Some(PathBuf::from(package_file)),
+        all_packages: None,
+        junit_report: None,
+        exclude_newer: None,
+        keep_test_prefix: false,
+        keep_test_prefixes: None,
+        compression_threads: None,
+        common: CommonOpts {
+            output_dir: None,
+            use_zstd: true,
+            use_bz2: true,
+            experimental: false,
+            auth_file: None,
+            channel_priority: "strict".parse().map_err(runtime_error)?,
+        },
+    };
+    run_test_from_args(args, LoggingOutputHandler::default())
+        .await
+        .map_err(runtime_error)
+}
+
+#[pyfunction]
+#[pyo3(signature = (package_file, channels=None))]
+fn test_package_py(package_file: String, channels: Option<Vec<String>>) -> PyResult<()> {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(test_package_impl(package_file, channels))
+}
+
+/// Like `test_package_py`, but returns an awaitable.
+#[pyfunction]
+#[pyo3(signature = (package_file, channels=None))]
+fn test_package_async_py(
+    py: Python<'_>,
+    package_file: String,
+    channels: Option<Vec<String>>,
+) -> PyResult<Bound<'_, PyAny>> {
+    pyo3_async_runtimes::tokio::future_into_py(
+        py,
+        spawn_local(move || test_package_impl(package_file, channels)),
+    )
+}
+
+/// A package that was resolved for one of the environments of an output.
+#[pyclass(name = "ResolvedPackage", frozen, get_all)]
+#[derive(Clone)]
+struct PyResolvedPackage {
+    name: String,
+    version: String,
+    build: String,
+    channel: Option<String>,
+    url: String,
+}
+
+#[pymethods]
+impl PyResolvedPackage {
+    fn __repr__(&self) -> String {
+        format!(
+            "ResolvedPackage(name={:?}, version={:?}, build={:?})",
+            self.name, self.version, self.build
+        )
+    }
+}
+
+/// The requested specs and the resolved packages of an environment.
+#[pyclass(name = "ResolvedEnvironment", frozen, get_all)]
+#[derive(Clone)]
+struct PyResolvedEnvironment {
+    specs: Vec<String>,
+    packages: Vec<PyResolvedPackage>,
+}
+
+impl From<&ResolvedDependencies> for PyResolvedEnvironment {
+    fn from(dependencies: &ResolvedDependencies) -> Self {
+        Self {
+            specs: render_specs(&dependencies.specs),
+            packages: dependencies
+                .resolved
+                .iter()
+                .map(|record| PyResolvedPackage {
+                    name: record.package_record.name.as_normalized().to_string(),
+                    version: record.package_record.version.to_string(),
+                    build: record.package_record.build.clone(),
+                    channel: record.channel.clone(),
+                    url: record.url.to_string(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A rendered output of a recipe.
+#[pyclass(name = "RenderedOutput", frozen, get_all)]
+#[derive(Clone)]
+struct PyRenderedOutput {
+    name: String,
+    version: String,
+    build_string: String,
+    target_platform: String,
+    variant: BTreeMap<String, String>,
+    build: Option<PyResolvedEnvironment>,
+    host: Option<PyResolvedEnvironment>,
+    run: Vec<String>,
+    run_constraints: Vec<String>,
+    skip_reason: Option<String>,
+}
+
+#[pymethods]
+impl PyRenderedOutput {
+    fn __repr__(&self) -> String {
+        format!(
+            "RenderedOutput(name={:?}, version={:?}, build_string={:?})",
+            self.name, self.version, self.build_string
+        )
+    }
+}
+
+impl From<&Output> for PyRenderedOutput {
+    fn from(output: &Output) -> Self {
+        let dependencies = output.finalized_dependencies.as_ref();
+        Self {
+            name: output.name().as_normalized().to_string(),
+            version: output.version().to_string(),
+            build_string: output.build_string().into_owned(),
+            target_platform: output.target_platform().to_string(),
+            variant: output
+                .variant()
+                .iter()
+                .map(|(key, value)| (key.normalize(), value.clone()))
+                .collect(),
+            build: dependencies
+                .and_then(|deps| deps.build.as_ref())
+                .map(PyResolvedEnvironment::from),
+            host: dependencies
+                .and_then(|deps| deps.host.as_ref())
+                .map(PyResolvedEnvironment::from),
+            run: dependencies
+                .map(|deps| render_specs(&deps.run.depends))
+                .unwrap_or_default(),
+            run_constraints: dependencies
+                .map(|deps| render_specs(&deps.run.constraints))
+                .unwrap_or_default(),
+            skip_reason: output.recipe.build().skip_reason().map(ToString::to_string),
         }
-        Ok(())
-    })
+    }
+}
+
+fn render_specs(specs: &[DependencyInfo]) -> Vec<String> {
+    specs.iter().map(|spec| spec.spec().to_string()).collect()
+}
+
+async fn render_recipes_impl(
+    recipes: Vec<String>,
+    variant_config: Option<Vec<String>>,
+    with_solve: bool,
+    channels: Option<Vec<String>>,
+) -> PyResult<Vec<PyRenderedOutput>> {
+    let recipes = recipes.into_iter().map(PathBuf::from).collect::<Vec<_>>();
+    let mut build_data = BuildData::default();
+    build_data.render_only = true;
+    build_data.with_solve = with_solve;
+    if let Some(variant_config) = variant_config {
+        build_data.variant_config = variant_config.into_iter().map(PathBuf::from).collect();
+    }
+    if let Some(channels) = channels {
+        build_data.channel = channels;
+    }
+    let tool_config = get_tool_config(&build_data, &None).map_err(runtime_error)?;
+    let outputs = render_recipes(&recipes, &build_data, &tool_config)
+        .await
+        .map_err(runtime_error)?;
+    Ok(outputs.iter().map(PyRenderedOutput::from).collect())
+}
+
+#[pyfunction]
+#[pyo3(signature = (recipes, variant_config=None, with_solve=true, channels=None))]
+fn render_recipes_py(
+    recipes: Vec<String>,
+    variant_config: Option<Vec<String>>,
+    with_solve: bool,
+    channels: Option<Vec<String>>,
+) -> PyResult<Vec<PyRenderedOutput>> {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(render_recipes_impl(
+        recipes,
+        variant_config,
+        with_solve,
+        channels,
+    ))
+}
+
+/// Like `render_recipes_py`, but returns an awaitable.
+#[pyfunction]
+#[pyo3(signature = (recipes, variant_config=None, with_solve=true, channels=None))]
+fn render_recipes_async_py(
+    py: Python<'_>,
+    recipes: Vec<String>,
+    variant_config: Option<Vec<String>>,
+    with_solve: bool,
+    channels: Option<Vec<String>>,
+) -> PyResult<Bound<'_, PyAny>> {
+    pyo3_async_runtimes::tokio::future_into_py(
+        py,
+        spawn_local(move || render_recipes_impl(recipes, variant_config, with_solve, channels)),
+    )
 }
 
 #[pymodule]
 fn rattler_build<'py>(_py: Python<'py>, m: Bound<'py, PyModule>) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(get_rattler_build_version_py, &m).unwrap())?;
     m.add_function(wrap_pyfunction!(build_recipes_py, &m).unwrap())?;
+    m.add_function(wrap_pyfunction!(build_recipes_async_py, &m).unwrap())?;
+    m.add_function(wrap_pyfunction!(render_recipes_py, &m).unwrap())?;
+    m.add_function(wrap_pyfunction!(render_recipes_async_py, &m).unwrap())?;
+    m.add_function(wrap_pyfunction!(test_package_py, &m).unwrap())?;
+    m.add_function(wrap_pyfunction!(test_package_async_py, &m).unwrap())?;
+    m.add_class::<PyRenderedOutput>()?;
+    m.add_class::<PyResolvedEnvironment>()?;
+    m.add_class::<PyResolvedPackage>()?;
     Ok(())
 }
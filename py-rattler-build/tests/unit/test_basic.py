@@ -4,6 +4,8 @@ import rattler_build
 import tomli as tomllib
 import shutil
 
+import pytest
+
 
 def test_version_match_parent_cargo() -> None:
     parent_cargo_toml = Path(__file__).parents[3].joinpath("Cargo.toml").read_text()
@@ -24,3 +26,32 @@ def test_build_recipe(tmp_path: Path, recipes_dir: Path) -> None:
     output_dir = tmp_path.joinpath("output")
     rattler_build.build_recipe(recipe_path, output_dir)
     assert output_dir.joinpath("noarch").is_dir()
+
+
+def test_render_recipe(recipes_dir: Path) -> None:
+    outputs = rattler_build.render(recipes_dir.joinpath("dummy", "recipe.yaml"), with_solve=False)
+    assert len(outputs) == 1
+    output = outputs[0]
+    assert isinstance(output, rattler_build.RenderedOutput)
+    assert output.name == "dummy-x"
+    assert output.version == "0.1.0"
+    assert isinstance(output.variant, dict)
+    assert output.build is None
+    assert output.host is None
+
+
+@pytest.mark.asyncio
+async def test_build_recipe_async(tmp_path: Path, recipes_dir: Path) -> None:
+    recipe_name = "recipe.yaml"
+    recipe_path = tmp_path.joinpath(recipe_name)
+    shutil.copy(recipes_dir.joinpath("dummy", recipe_name), recipe_path)
+    output_dir = tmp_path.joinpath("output")
+    await rattler_build.build_recipe_async(recipe_path, output_dir)
+    assert output_dir.joinpath("noarch").is_dir()
+
+
+@pytest.mark.asyncio
+async def test_render_recipe_async(recipes_dir: Path) -> None:
+    outputs = await rattler_build.render_async(recipes_dir.joinpath("dummy", "recipe.yaml"))
+    assert len(outputs) == 1
+    assert outputs[0].name == "dummy-x"
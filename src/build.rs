@@ -1,12 +1,16 @@
 //! The build module contains the code for running the build process for a given
 //! [`Output`]
-use std::{path::PathBuf, vec};
+use std::{path::PathBuf, time::Instant, vec};
 
 use miette::{Context, IntoDiagnostic};
 use rattler_conda_types::{Channel, MatchSpec};
 
 use crate::{
+    build_profile::ProfileCategory,
+    build_state::{BuildPhase, BuildState, PrefixGuard},
     metadata::{build_reindexed_channels, Output},
+    packaging,
+    provenance::Provenance,
     recipe::parser::TestType,
     render::solver::load_repodatas,
     tool_configuration,
@@ -51,6 +55,12 @@ pub async fn skip_existing(
         all_channels
     };
 
+    let channel_names = channels
+        .iter()
+        .map(|c| c.to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+
     let existing = load_repodatas(
         &channels,
         first_output.host_platform().platform,
@@ -85,6 +95,15 @@ pub async fn skip_existing(
             // The identifier should always be set at this point
             tracing::info!("Skipping build for {}", output.identifier());
         }
+        tool_configuration.cache_statistics.record_existing(
+            exists,
+            &output.identifier(),
+            &if exists {
+                format!("the package already exists in {}", channel_names)
+            } else {
+                format!("the package was not found in {}", channel_names)
+            },
+        );
         !exists
     });
 
@@ -98,38 +117,101 @@ pub async fn run_build(
     output: Output,
     tool_configuration: &tool_configuration::Configuration,
 ) -> miette::Result<(Output, PathBuf)> {
+    let directories = output.build_configuration.directories.clone();
+    fs_err::create_dir_all(&directories.build_dir).into_diagnostic()?;
+    let _prefix_guard = PrefixGuard::acquire(&directories.build_dir).into_diagnostic()?;
+
+    let mut state = if tool_configuration.resume {
+        BuildState::resume(&output)
+    } else {
+        BuildState::new(&output)
+    };
+
+    // the work directory is kept if the sources of the earlier build are reused
     output
         .build_configuration
         .directories
-        .create_build_dir(true)
+        .create_build_dir(!state.is_completed(BuildPhase::FetchSources))
         .into_diagnostic()?;
 
-    let span = tracing::info_span!("Running build for", recipe = output.identifier());
+    // keep a complete log of the build, independent of the console verbosity
+    let span = tracing::info_span!(
+        "Running build for",
+        recipe = output.identifier(),
+        log_file = output
+            .log_file()
+            .map(|path| tracing::field::display(path.display())),
+    );
+    #[cfg(feature = "otlp")]
+    crate::otlp::record_output_attributes(&span, &output);
     let _enter = span.enter();
     output.record_build_start();
 
-    let directories = output.build_configuration.directories.clone();
+    // record the commit of the recipe and the builder in the rendered recipe
+    let output = Output {
+        provenance: Some(Provenance::collect(&output)),
+        ..output
+    };
 
     let output = if output.recipe.cache.is_some() {
         output.build_or_fetch_cache(tool_configuration).await?
+    } else if state.is_completed(BuildPhase::FetchSources) {
+        tracing::info!("Reusing the sources of the interrupted build");
+        Output {
+            finalized_sources: state.finalized_sources.clone(),
+            ..output
+        }
     } else {
-        output
+        state.start(BuildPhase::FetchSources);
+        let output = output
             .fetch_sources(tool_configuration)
             .await
-            .into_diagnostic()?
+            .inspect_err(|err| state.fail(BuildPhase::FetchSources, err))
+            .into_diagnostic()?;
+        state.complete(BuildPhase::FetchSources, &output);
+        output
     };
 
-    let output = output
-        .resolve_dependencies(tool_configuration)
-        .await
-        .into_diagnostic()?;
+    let output = if state.is_completed(BuildPhase::ResolveDependencies) {
+        tracing::info!("Reusing the resolved dependencies of the interrupted build");
+        Output {
+            finalized_dependencies: state.finalized_dependencies.clone(),
+            ..output
+        }
+    } else {
+        state.start(BuildPhase::ResolveDependencies);
+        let output = output
+            .resolve_dependencies(tool_configuration)
+            .await
+            .inspect_err(|err| state.fail(BuildPhase::ResolveDependencies, err))
+            .into_diagnostic()?;
+        state.complete(BuildPhase::ResolveDependencies, &output);
+        output
+    };
 
-    output
-        .install_environments(tool_configuration)
-        .await
-        .into_diagnostic()?;
+    if state.is_completed(BuildPhase::InstallEnvironments) {
+        tracing::info!("Reusing the environments of the interrupted build");
+    } else {
+        state.start(BuildPhase::InstallEnvironments);
+        output
+            .install_environments(tool_configuration)
+            .await
+            .inspect_err(|err| state.fail(BuildPhase::InstallEnvironments, err))
+            .into_diagnostic()?;
+        state.complete(BuildPhase::InstallEnvironments, &output);
+    }
 
-    output.run_build_script().await.into_diagnostic()?;
+    if state.is_completed(BuildPhase::RunBuildScript) {
+        tracing::info!("Reusing the result of the build script of the interrupted build");
+    } else {
+        state.start(BuildPhase::RunBuildScript);
+        output
+            .run_build_script(&tool_configuration.cancellation_token)
+            .await
+            .inspect_err(|err| state.fail(BuildPhase::RunBuildScript, err))
+            .into_diagnostic()?;
+        state.complete(BuildPhase::RunBuildScript, &output);
+    }
 
     // Package all the new files
     let (result, paths_json) = output
@@ -139,7 +221,16 @@ pub async fn run_build(
 
     output.record_artifact(&result, &paths_json);
 
-    let span = tracing::info_span!("Running package tests");
+    if let Some(template) = &tool_configuration.filename_template {
+        let artifact =
+            packaging::copy_with_filename_template(&output, &result, template).into_diagnostic()?;
+        tracing::info!("Artifact written to '{}'", artifact.display());
+    }
+
+    let span = tracing::info_span!(
+        "Running package tests",
+        profile = ProfileCategory::Tests.as_str()
+    );
     let enter = span.enter();
 
     // We run all the package content tests
@@ -161,5 +252,70 @@ pub async fn run_build(
         directories.clean().into_diagnostic()?;
     }
 
+    state.remove();
+
     Ok((output, result))
 }
+
+/// Run the build for the given output up to the point where the build script
+/// would be executed. This fetches the sources, resolves the dependencies,
+/// installs the environments and writes the build script to the work
+/// directory, and reports how long each of these steps took.
+///
+/// The work directory is always kept so that the build script can be
+/// inspected.
+pub async fn run_dry_build(
+    output: Output,
+    tool_configuration: &tool_configuration::Configuration,
+) -> miette::Result<Output> {
+    output
+        .build_configuration
+        .directories
+        .create_build_dir(true)
+        .into_diagnostic()?;
+
+    let span = tracing::info_span!("Running dry run for", recipe = output.identifier());
+    let _enter = span.enter();
+    output.record_build_start();
+
+    let mut timings = Vec::new();
+
+    let start = Instant::now();
+    if output.recipe.cache.is_some() {
+        tracing::warn!("The `cache` build is not executed in a dry run");
+    }
+    let output = output
+        .fetch_sources(tool_configuration)
+        .await
+        .into_diagnostic()?;
+    timings.push(("Fetching sources", start.elapsed()));
+
+    let start = Instant::now();
+    let output = output
+        .resolve_dependencies(tool_configuration)
+        .await
+        .into_diagnostic()?;
+    timings.push(("Resolving dependencies", start.elapsed()));
+
+    let start = Instant::now();
+    output
+        .install_environments(tool_configuration)
+        .await
+        .into_diagnostic()?;
+    timings.push(("Installing environments", start.elapsed()));
+
+    let start = Instant::now();
+    output.write_build_script().await.into_diagnostic()?;
+    timings.push(("Creating build script", start.elapsed()));
+
+    tracing::info!("Dry run finished for {}", output.identifier());
+    for (step, duration) in timings {
+        tracing::info!("  {:<25} {:.2?}", step, duration);
+    }
+    tracing::info!(
+        "The work directory was kept at '{}'",
+        output.build_configuration.directories.work_dir.display()
+    );
+
+    Ok(output)
+}
@@ -0,0 +1,284 @@
+//! Track the outputs that failed to build with `--continue-on-failure`.
+//!
+//! When an output fails, all outputs that (transitively) depend on it cannot
+//! be built either. Instead of attempting them (and failing with a confusing
+//! solver error), they are skipped and reported separately in the summary.
+//! Outputs are identified by their identifier (name, version and build
+//! string), so that a failed variant of a package only affects the outputs
+//! with a matching variant.
+use std::collections::{BTreeMap, HashMap};
+
+use rattler_conda_types::PackageName;
+
+use crate::{metadata::Output, normalized_key::NormalizedKey, recipe::parser::Dependency};
+
+/// The variant of an output
+type Variant = BTreeMap<NormalizedKey, String>;
+
+/// Why an output was not built
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuildFailure {
+    /// The build of the output failed
+    Failed(String),
+    /// The output was built, but its tests failed
+    TestFailed(String),
+    /// The output was skipped because it depends on an output that was not
+    /// built
+    Skipped {
+        /// The dependency that was not built
+        dependency: PackageName,
+    },
+}
+
+/// The failed and skipped outputs (by identifier), see [`BuildFailures::summary`]
+#[derive(Debug, Default)]
+pub struct Summary<'a> {
+    /// The outputs that failed to build, with the error
+    pub failed: Vec<(&'a str, &'a str)>,
+    /// The outputs whose tests failed, with the error
+    pub test_failed: Vec<(&'a str, &'a str)>,
+    /// The outputs that were skipped, with the dependency that was not built
+    pub skipped: Vec<(&'a str, &'a PackageName)>,
+}
+
+/// The outputs that were not built, in the order in which they were attempted
+#[derive(Debug, Default)]
+pub struct BuildFailures {
+    /// The name and variant of the outputs that were not built, by identifier
+    not_built: HashMap<String, (PackageName, Variant)>,
+    outputs: Vec<(String, BuildFailure)>,
+}
+
+/// The names of the packages that an output depends on in its build, host and
+/// run requirements
+pub fn dependency_names(output: &Output) -> impl Iterator<Item = PackageName> + '_ {
+    output
+        .recipe
+        .requirements()
+        .run_build_host()
+        .filter_map(|dep| match dep {
+            Dependency::Spec(spec) => spec.name.clone(),
+            Dependency::PinSubpackage(pin) => Some(pin.pin_value().name.clone()),
+            Dependency::PinCompatible(pin) => Some(pin.pin_value().name.clone()),
+        })
+}
+
+/// Returns true if an output with `variant` can depend on the output with
+/// `other`: the variants agree on all keys they have in common, except for the
+/// target platform (a `noarch` output can depend on a platform output)
+fn variants_match(variant: &Variant, other: &Variant) -> bool {
+    variant.iter().all(|(key, value)| {
+        key.normalize() == "target_platform" || other.get(key).map_or(true, |o| o == value)
+    })
+}
+
+impl BuildFailures {
+    /// Returns true if all outputs were built and tested
+    pub fn is_empty(&self) -> bool {
+        self.outputs.is_empty()
+    }
+
+    /// Returns the first dependency of the output with `name` and `variant`
+    /// that was not built (in a matching variant)
+    pub fn failed_dependency(
+        &self,
+        name: &PackageName,
+        variant: &Variant,
+        dependencies: impl IntoIterator<Item = PackageName>,
+    ) -> Option<PackageName> {
+        dependencies.into_iter().find(|dependency| {
+            dependency != name
+                && self
+                    .not_built
+                    .values()
+                    .any(|(not_built, not_built_variant)| {
+                        not_built == dependency && variants_match(variant, not_built_variant)
+                    })
+        })
+    }
+
+    /// Record that the build of an output failed
+    pub fn record_failed(
+        &mut self,
+        identifier: String,
+        name: PackageName,
+        variant: Variant,
+        error: String,
+    ) {
+        self.not_built.insert(identifier.clone(), (name, variant));
+        self.outputs.push((identifier, BuildFailure::Failed(error)));
+    }
+
+    /// Record that an output was skipped because of a failed dependency
+    pub fn record_skipped(
+        &mut self,
+        identifier: String,
+        name: PackageName,
+        variant: Variant,
+        dependency: PackageName,
+    ) {
+        self.not_built.insert(identifier.clone(), (name, variant));
+        self.outputs
+            .push((identifier, BuildFailure::Skipped { dependency }));
+    }
+
+    /// Record that the tests of an output failed. The package was built, so
+    /// the outputs that depend on it are not skipped.
+    pub fn record_test_failed(&mut self, identifier: String, error: String) {
+        self.outputs
+            .push((identifier, BuildFailure::TestFailed(error)));
+    }
+
+    /// The outputs that failed to build, the outputs whose tests failed (with
+    /// the error) and the outputs that were skipped (with the dependency that
+    /// was not built)
+    pub fn summary(&self) -> Summary<'_> {
+        let mut summary = Summary::default();
+        for (identifier, failure) in &self.outputs {
+            let identifier = identifier.as_str();
+            match failure {
+                BuildFailure::Failed(error) => summary.failed.push((identifier, error.as_str())),
+                BuildFailure::TestFailed(error) => {
+                    summary.test_failed.push((identifier, error.as_str()))
+                }
+                BuildFailure::Skipped { dependency } => {
+                    summary.skipped.push((identifier, dependency))
+                }
+            }
+        }
+        summary
+    }
+
+    /// Log the failed and skipped outputs
+    pub fn log_summary(&self) {
+        let summary = self.summary();
+        for (identifier, error) in summary.failed {
+            tracing::error!("Failed to build {}: {}", identifier, error);
+        }
+        for (identifier, error) in summary.test_failed {
+            tracing::error!("Tests of {} failed: {}", identifier, error);
+        }
+        for (identifier, dependency) in summary.skipped {
+            tracing::warn!(
+                "Skipped {} due to failed dependency {}",
+                identifier,
+                dependency.as_normalized()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(name: &str) -> PackageName {
+        name.parse().unwrap()
+    }
+
+    fn variant(entries: &[(&str, &str)]) -> Variant {
+        entries
+            .iter()
+            .map(|(key, value)| (NormalizedKey::from(*key), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn skip_transitive_dependents() {
+        let mut failures = BuildFailures::default();
+        failures.record_failed(
+            "libfoo-1.0-h1_0".to_string(),
+            name("libfoo"),
+            variant(&[("target_platform", "linux-64")]),
+            "compilation failed".to_string(),
+        );
+
+        // direct dependent
+        let dependency = failures
+            .failed_dependency(
+                &name("foo"),
+                &variant(&[("target_platform", "linux-64")]),
+                [name("python"), name("libfoo")],
+            )
+            .unwrap();
+        assert_eq!(dependency, name("libfoo"));
+        failures.record_skipped(
+            "foo-1.0-h2_0".to_string(),
+            name("foo"),
+            Variant::new(),
+            dependency,
+        );
+
+        // transitive dependent (a noarch output)
+        let dependency = failures
+            .failed_dependency(
+                &name("foo-tests"),
+                &variant(&[("target_platform", "noarch")]),
+                [name("foo")],
+            )
+            .unwrap();
+        assert_eq!(dependency, name("foo"));
+        failures.record_skipped(
+            "foo-tests-1.0-h3_0".to_string(),
+            name("foo-tests"),
+            Variant::new(),
+            dependency,
+        );
+
+        // unrelated outputs are still built
+        assert!(failures
+            .failed_dependency(&name("bar"), &Variant::new(), [name("python")])
+            .is_none());
+
+        let summary = failures.summary();
+        assert_eq!(
+            summary.failed,
+            vec![("libfoo-1.0-h1_0", "compilation failed")]
+        );
+        assert_eq!(
+            summary.skipped,
+            vec![
+                ("foo-1.0-h2_0", &name("libfoo")),
+                ("foo-tests-1.0-h3_0", &name("foo"))
+            ]
+        );
+    }
+
+    #[test]
+    fn failed_variants() {
+        let mut failures = BuildFailures::default();
+        failures.record_failed(
+            "libfoo-1.0-py311h1_0".to_string(),
+            name("libfoo"),
+            variant(&[("python", "3.11")]),
+            "compilation failed".to_string(),
+        );
+
+        // only the outputs with the same variant depend on the failed output
+        assert!(failures
+            .failed_dependency(
+                &name("foo"),
+                &variant(&[("python", "3.12")]),
+                [name("libfoo")]
+            )
+            .is_none());
+        assert_eq!(
+            failures.failed_dependency(
+                &name("foo"),
+                &variant(&[("python", "3.11")]),
+                [name("libfoo")]
+            ),
+            Some(name("libfoo"))
+        );
+
+        // failed tests are reported, but do not skip the dependents
+        failures.record_test_failed("libbar-1.0-h1_0".to_string(), "test failed".to_string());
+        assert!(failures
+            .failed_dependency(&name("bar"), &Variant::new(), [name("libbar")])
+            .is_none());
+        assert_eq!(
+            failures.summary().test_failed,
+            vec![("libbar-1.0-h1_0", "test failed")]
+        );
+    }
+}
@@ -0,0 +1,303 @@
+//! Aggregates the time that a build spends in its phases (fetching repodata,
+//! solving, downloading and installing packages, fetching sources, running the
+//! script, packaging and testing) from the tracing spans of these phases.
+//!
+//! The spans of a phase set their category with the `profile` field, e.g.
+//! `tracing::debug_span!("Solving environment", profile =
+//! ProfileCategory::Solve.as_str())`.
+//!
+//! The time of a span is only counted for its own category: the time of a
+//! nested span of another category (e.g. solving the test environment while
+//! running the tests) is subtracted from the outer span.
+use std::{
+    collections::BTreeMap,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::field;
+use tracing_core::{span::Id, Field, Subscriber};
+use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer};
+
+/// A phase of the build
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProfileCategory {
+    /// Fetching the repodata of the channels
+    RepodataFetch,
+    /// Solving the environments
+    Solve,
+    /// Downloading the packages into the package cache
+    Download,
+    /// Installing the packages into the prefixes
+    Install,
+    /// Fetching the sources
+    SourceFetch,
+    /// Running the build script
+    Script,
+    /// Creating the package
+    Packaging,
+    /// Running the tests
+    Tests,
+}
+
+impl ProfileCategory {
+    /// The value of the `profile` field of the spans of this category
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::RepodataFetch => "repodata_fetch",
+            Self::Solve => "solve",
+            Self::Download => "download",
+            Self::Install => "install",
+            Self::SourceFetch => "source_fetch",
+            Self::Script => "script",
+            Self::Packaging => "packaging",
+            Self::Tests => "tests",
+        }
+    }
+}
+
+impl FromStr for ProfileCategory {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "repodata_fetch" => Self::RepodataFetch,
+            "solve" => Self::Solve,
+            "download" => Self::Download,
+            "install" => Self::Install,
+            "source_fetch" => Self::SourceFetch,
+            "script" => Self::Script,
+            "packaging" => Self::Packaging,
+            "tests" => Self::Tests,
+            _ => return Err(format!("unknown profile category: {s}")),
+        })
+    }
+}
+
+impl std::fmt::Display for ProfileCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::RepodataFetch => "Repodata fetch",
+            Self::Solve => "Solve",
+            Self::Download => "Download",
+            Self::Install => "Install",
+            Self::SourceFetch => "Source fetch",
+            Self::Script => "Build script",
+            Self::Packaging => "Packaging",
+            Self::Tests => "Tests",
+        })
+    }
+}
+
+/// The time spent in each phase of a build
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BuildProfile(BTreeMap<ProfileCategory, Duration>);
+
+impl BuildProfile {
+    /// Add time to a category
+    pub fn add(&mut self, category: ProfileCategory, duration: Duration) {
+        *self.0.entry(category).or_default() += duration;
+    }
+
+    /// Add all times of another profile
+    pub fn merge(&mut self, other: BuildProfile) {
+        for (category, duration) in other.0 {
+            self.add(category, duration);
+        }
+    }
+
+    /// Returns true if no time was recorded
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The recorded time per category
+    pub fn iter(&self) -> impl Iterator<Item = (ProfileCategory, Duration)> + '_ {
+        self.0
+            .iter()
+            .map(|(category, duration)| (*category, *duration))
+    }
+
+    /// The total recorded time
+    pub fn total(&self) -> Duration {
+        self.0.values().sum()
+    }
+
+    /// Format the profile as a table with the time and share of each category
+    pub fn to_table(&self) -> comfy_table::Table {
+        let total = self.total().as_secs_f64();
+        let mut table = comfy_table::Table::new();
+        table
+            .load_preset(comfy_table::presets::UTF8_FULL_CONDENSED)
+            .apply_modifier(comfy_table::modifiers::UTF8_ROUND_CORNERS)
+            .set_header(vec!["Phase", "Time", "Share"]);
+        for (category, duration) in self.iter() {
+            let share = if total > 0.0 {
+                duration.as_secs_f64() / total * 100.0
+            } else {
+                0.0
+            };
+            table.add_row(vec![
+                category.to_string(),
+                format!("{:.2?}", duration),
+                format!("{share:.0}%"),
+            ]);
+        }
+        table
+    }
+}
+
+/// Reads the `profile` field of a span
+#[derive(Default)]
+struct ProfileVisitor {
+    category: Option<ProfileCategory>,
+}
+
+impl field::Visit for ProfileVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "profile" {
+            self.category = value.parse().ok();
+        }
+    }
+
+    fn record_debug(&mut self, _field: &Field, _value: &dyn std::fmt::Debug) {}
+}
+
+/// The start of a span of a profiled category
+struct SpanTiming {
+    category: ProfileCategory,
+    start: Instant,
+    /// The time spent in nested spans of (other) profiled categories
+    nested: Duration,
+}
+
+/// A tracing layer that aggregates the time of the spans with a `profile`
+/// field.
+/// The profile is collected per output with [`Profiler::take`].
+#[derive(Debug, Clone, Default)]
+pub struct Profiler {
+    profile: Arc<Mutex<BuildProfile>>,
+}
+
+impl Profiler {
+    /// Return the profile that was recorded since the last call and start a
+    /// new one
+    pub fn take(&self) -> BuildProfile {
+        std::mem::take(&mut *self.profile.lock().unwrap())
+    }
+}
+
+impl<S> Layer<S> for Profiler
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(
+        &self,
+        attrs: &tracing_core::span::Attributes<'_>,
+        id: &Id,
+        ctx: Context<'_, S>,
+    ) {
+        let mut visitor = ProfileVisitor::default();
+        attrs.record(&mut visitor);
+        let Some(category) = visitor.category else {
+            return;
+        };
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanTiming {
+                category,
+                start: Instant::now(),
+                nested: Duration::ZERO,
+            });
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(timing) = span.extensions_mut().remove::<SpanTiming>() else {
+            return;
+        };
+        let elapsed = timing.start.elapsed();
+
+        // the time is not counted for the closest profiled parent span
+        if let Some(parent) = span
+            .scope()
+            .skip(1)
+            .find(|parent| parent.extensions().get::<SpanTiming>().is_some())
+        {
+            if let Some(parent_timing) = parent.extensions_mut().get_mut::<SpanTiming>() {
+                parent_timing.nested += elapsed;
+            }
+        }
+
+        self.profile
+            .lock()
+            .unwrap()
+            .add(timing.category, elapsed.saturating_sub(timing.nested));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+
+    #[test]
+    fn test_nested_spans_are_subtracted() {
+        let profiler = Profiler::default();
+        let subscriber = tracing_subscriber::registry().with(profiler.clone());
+
+        tracing::subscriber::with_default(subscriber, || {
+            let tests =
+                tracing::info_span!("Running tests", profile = ProfileCategory::Tests.as_str());
+            let _tests = tests.enter();
+            std::thread::sleep(Duration::from_millis(20));
+            {
+                let solve =
+                    tracing::debug_span!("Solving", profile = ProfileCategory::Solve.as_str());
+                let _solve = solve.enter();
+                std::thread::sleep(Duration::from_millis(50));
+            }
+        });
+
+        let profile = profiler.take();
+        let times = profile.iter().collect::<BTreeMap<_, _>>();
+        assert!(times[&ProfileCategory::Solve] >= Duration::from_millis(50));
+        assert!(times[&ProfileCategory::Tests] >= Duration::from_millis(20));
+        assert!(times[&ProfileCategory::Tests] < times[&ProfileCategory::Solve]);
+        assert!(profiler.take().is_empty());
+    }
+
+    #[test]
+    fn test_spans_without_category_are_ignored() {
+        let profiler = Profiler::default();
+        let subscriber = tracing_subscriber::registry().with(profiler.clone());
+
+        tracing::subscriber::with_default(subscriber, || {
+            let _span = tracing::info_span!("Solving environment").entered();
+        });
+
+        assert!(profiler.take().is_empty());
+    }
+
+    #[test]
+    fn test_category_roundtrip() {
+        for category in [
+            ProfileCategory::RepodataFetch,
+            ProfileCategory::Solve,
+            ProfileCategory::Download,
+            ProfileCategory::Install,
+            ProfileCategory::SourceFetch,
+            ProfileCategory::Script,
+            ProfileCategory::Packaging,
+            ProfileCategory::Tests,
+        ] {
+            assert_eq!(category.as_str().parse(), Ok(category));
+        }
+    }
+}
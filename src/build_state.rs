@@ -0,0 +1,362 @@
+//! The state of a build, to resume interrupted builds (`--resume`).
+//!
+//! While an output is built, `state.json` in its build directory records the
+//! phases that completed, the phase that is running and the phase that
+//! failed (if any). The state is written after every phase, so it is also
+//! left behind when the build is interrupted (Ctrl-C) or crashes. With
+//! `--resume`, the completed phases are skipped if the recipe did not change.
+//!
+//! A lock file (the prefix guard) makes sure that only one build uses a build
+//! directory (and its prefixes) at a time.
+use std::{
+    fmt::Display,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use fs_err as fs;
+use rattler_digest::{compute_bytes_digest, Sha256};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    metadata::Output, recipe::parser::Source, render::resolved_dependencies::FinalizedDependencies,
+};
+
+/// The name of the state file in the build directory
+pub const STATE_FILE: &str = "state.json";
+
+/// The name of the lock file in the build directory
+const LOCK_FILE: &str = "build.lock";
+
+/// A phase of a build, in the order in which they run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BuildPhase {
+    /// Fetching the sources into the work directory
+    FetchSources,
+    /// Resolving the build and host environments
+    ResolveDependencies,
+    /// Installing the build and host environments
+    InstallEnvironments,
+    /// Running the build script
+    RunBuildScript,
+}
+
+/// A phase that failed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FailedPhase {
+    /// The phase that failed
+    pub phase: BuildPhase,
+    /// The error message
+    pub error: String,
+}
+
+/// The state of the build of an output (`state.json`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BuildState {
+    /// The identifier of the output (`name-version-build_string`)
+    pub identifier: String,
+    /// A hash of the rendered recipe, the variant and the target platform.
+    /// A build is only resumed if it did not change.
+    pub fingerprint: String,
+    /// The phases that completed
+    #[serde(default)]
+    pub completed: Vec<BuildPhase>,
+    /// The phase that is running (or was running when the build was
+    /// interrupted)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub running: Option<BuildPhase>,
+    /// The phase that failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failed: Option<FailedPhase>,
+    /// The sources after fetching them (e.g. with the resolved git revision)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finalized_sources: Option<Vec<Source>>,
+    /// The resolved dependencies
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finalized_dependencies: Option<FinalizedDependencies>,
+
+    /// The build directory the state is written to
+    #[serde(skip)]
+    build_dir: PathBuf,
+}
+
+impl BuildState {
+    /// A new state without completed phases for the output
+    pub fn new(output: &Output) -> Self {
+        Self {
+            identifier: output.identifier(),
+            fingerprint: Self::fingerprint(output),
+            completed: Vec::new(),
+            running: None,
+            failed: None,
+            finalized_sources: None,
+            finalized_dependencies: None,
+            build_dir: output.build_configuration.directories.build_dir.clone(),
+        }
+    }
+
+    /// The state of an earlier build of the output to resume. Starts from
+    /// scratch if there is no state or the recipe changed.
+    pub fn resume(output: &Output) -> Self {
+        let new = Self::new(output);
+        let Some(state) = Self::load(&new.build_dir) else {
+            tracing::info!("No earlier build to resume, starting from scratch");
+            return new;
+        };
+        if state.identifier != new.identifier || state.fingerprint != new.fingerprint {
+            tracing::warn!("The recipe changed since the interrupted build, starting from scratch");
+            return new;
+        }
+
+        match state.completed.last() {
+            Some(phase) => tracing::info!("Resuming the build after {:?}", phase),
+            None => tracing::info!("No phase of the earlier build completed"),
+        }
+        Self {
+            running: None,
+            failed: None,
+            build_dir: new.build_dir,
+            ..state
+        }
+    }
+
+    /// A hash of everything that determines the result of the phases
+    fn fingerprint(output: &Output) -> String {
+        let input = serde_json::json!({
+            "recipe": output.recipe,
+            "variant": output.build_configuration.variant,
+            "target_platform": output.build_configuration.target_platform,
+        });
+        format!(
+            "{:x}",
+            compute_bytes_digest::<Sha256>(input.to_string().as_bytes())
+        )
+    }
+
+    /// Load the state from a build directory
+    pub fn load(build_dir: &Path) -> Option<Self> {
+        let content = fs::read_to_string(build_dir.join(STATE_FILE)).ok()?;
+        serde_json::from_str(&content)
+            .map_err(|e| tracing::warn!("Ignoring the invalid build state: {}", e))
+            .ok()
+    }
+
+    /// Find the most recent build directory in `bld_dir` with a state for the
+    /// output with the given identifier
+    pub fn find_build_dir(bld_dir: &Path, identifier: &str) -> Option<PathBuf> {
+        fs::read_dir(bld_dir)
+            .ok()?
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| Self::load(path).is_some_and(|state| state.identifier == identifier))
+            .max_by_key(|path| {
+                fs::metadata(path.join(STATE_FILE))
+                    .and_then(|metadata| metadata.modified())
+                    .ok()
+            })
+    }
+
+    /// Returns true if the phase completed
+    pub fn is_completed(&self, phase: BuildPhase) -> bool {
+        self.completed.contains(&phase)
+    }
+
+    /// Record that a phase started
+    pub fn start(&mut self, phase: BuildPhase) {
+        self.running = Some(phase);
+        self.save();
+    }
+
+    /// Record that a phase completed, with the finalized sources and
+    /// dependencies of the output
+    pub fn complete(&mut self, phase: BuildPhase, output: &Output) {
+        self.running = None;
+        self.failed = None;
+        if !self.is_completed(phase) {
+            self.completed.push(phase);
+        }
+        self.finalized_sources = output.finalized_sources.clone();
+        self.finalized_dependencies = output.finalized_dependencies.clone();
+        self.save();
+    }
+
+    /// Record that a phase failed
+    pub fn fail(&mut self, phase: BuildPhase, error: &impl Display) {
+        self.running = None;
+        self.failed = Some(FailedPhase {
+            phase,
+            error: error.to_string(),
+        });
+        self.save();
+    }
+
+    /// Remove the state after a successful build
+    pub fn remove(&self) {
+        let path = self.build_dir.join(STATE_FILE);
+        if path.exists() {
+            if let Err(e) = fs::remove_file(&path) {
+                tracing::warn!("Failed to remove the build state: {}", e);
+            }
+        }
+    }
+
+    /// Write the state atomically (a failure to write it is not fatal)
+    fn save(&self) {
+        let write = || -> io::Result<()> {
+            let content = serde_json::to_string_pretty(self)?;
+            let tmp_path = self.build_dir.join(format!("{STATE_FILE}.tmp"));
+            fs::write(&tmp_path, content)?;
+            fs::rename(&tmp_path, self.build_dir.join(STATE_FILE))
+        };
+        if let Err(e) = write() {
+            tracing::warn!("Failed to write the build state: {}", e);
+        }
+    }
+}
+
+/// Makes sure that only one build uses a build directory (and its prefixes)
+/// at a time. The guard holds an exclusive lock (of the operating system) on
+/// the lock file, which contains the process id of the build. The lock is
+/// released when the guard is dropped or the process exits, so a build that
+/// crashed never leaves a stale lock behind.
+#[derive(Debug)]
+pub struct PrefixGuard {
+    #[cfg(unix)]
+    _lock: nix::fcntl::Flock<std::fs::File>,
+    #[cfg(not(unix))]
+    _lock: std::fs::File,
+}
+
+impl PrefixGuard {
+    /// Lock the build directory. Fails if another build holds the lock.
+    pub fn acquire(build_dir: &Path) -> io::Result<Self> {
+        let lock_file = build_dir.join(LOCK_FILE);
+        let mut options = std::fs::OpenOptions::new();
+        options.read(true).write(true).create(true).truncate(false);
+        #[cfg(windows)]
+        {
+            use std::os::windows::fs::OpenOptionsExt;
+            // without sharing, the file cannot be opened while another build
+            // holds it open
+            options.share_mode(0);
+        }
+        let file = options.open(&lock_file).map_err(|e| {
+            #[cfg(windows)]
+            if e.raw_os_error()
+                == Some(windows_sys::Win32::Foundation::ERROR_SHARING_VIOLATION as i32)
+            {
+                return locked_error(build_dir, &lock_file);
+            }
+            io::Error::new(
+                e.kind(),
+                format!("failed to open {}: {e}", lock_file.display()),
+            )
+        })?;
+
+        #[cfg(unix)]
+        let file = {
+            use nix::{
+                errno::Errno,
+                fcntl::{Flock, FlockArg},
+            };
+            Flock::lock(file, FlockArg::LockExclusiveNonblock).map_err(|(_, errno)| {
+                if errno == Errno::EWOULDBLOCK {
+                    locked_error(build_dir, &lock_file)
+                } else {
+                    io::Error::from(errno)
+                }
+            })?
+        };
+
+        let mut lock = file;
+        lock.set_len(0)?;
+        write!(lock, "{}", std::process::id())?;
+        Ok(Self { _lock: lock })
+    }
+}
+
+/// The error when another build holds the lock of the build directory
+fn locked_error(build_dir: &Path, lock_file: &Path) -> io::Error {
+    let owner = fs::read_to_string(lock_file)
+        .ok()
+        .and_then(|content| content.trim().parse::<u32>().ok())
+        .map(|pid| format!(" (process {pid})"))
+        .unwrap_or_default();
+    io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!(
+            "the build directory {} is used by another build{owner}",
+            build_dir.display()
+        ),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefix_guard() {
+        let dir = tempfile::tempdir().unwrap();
+        let guard = PrefixGuard::acquire(dir.path()).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.path().join(LOCK_FILE)).unwrap(),
+            std::process::id().to_string()
+        );
+
+        // the build directory cannot be locked twice
+        let err = PrefixGuard::acquire(dir.path()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+
+        // the lock is released when the guard is dropped
+        drop(guard);
+        assert!(PrefixGuard::acquire(dir.path()).is_ok());
+
+        // a lock file that is left behind (e.g. by a crashed build) is not locked
+        fs::write(dir.path().join(LOCK_FILE), u32::MAX.to_string()).unwrap();
+        assert!(PrefixGuard::acquire(dir.path()).is_ok());
+    }
+
+    #[test]
+    fn test_state_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let bld_dir = dir.path().join("bld");
+        let build_dir = bld_dir.join("rattler-build_foo");
+        fs::create_dir_all(&build_dir).unwrap();
+        fs::create_dir_all(bld_dir.join("rattler-build_bar")).unwrap();
+        let mut state = BuildState {
+            identifier: "foo-1.0-h123_0".to_string(),
+            fingerprint: "abc".to_string(),
+            completed: vec![BuildPhase::FetchSources],
+            running: None,
+            failed: None,
+            finalized_sources: None,
+            finalized_dependencies: None,
+            build_dir: build_dir.clone(),
+        };
+        state.start(BuildPhase::ResolveDependencies);
+        state.fail(BuildPhase::ResolveDependencies, &"no solution");
+
+        let json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(build_dir.join(STATE_FILE)).unwrap()).unwrap();
+        assert_eq!(json["completed"], serde_json::json!(["fetch_sources"]));
+        assert_eq!(json["failed"]["phase"], "resolve_dependencies");
+        assert_eq!(json["failed"]["error"], "no solution");
+        assert!(json.get("running").is_none());
+
+        let loaded = BuildState::load(&build_dir).unwrap();
+        assert!(loaded.is_completed(BuildPhase::FetchSources));
+        assert!(!loaded.is_completed(BuildPhase::ResolveDependencies));
+
+        // the build directory with a state for the output
+        assert_eq!(
+            BuildState::find_build_dir(&bld_dir, "foo-1.0-h123_0"),
+            Some(build_dir.clone())
+        );
+        assert_eq!(BuildState::find_build_dir(&bld_dir, "bar-1.0-h123_0"), None);
+
+        state.remove();
+        assert!(BuildState::load(&build_dir).is_none());
+    }
+}
@@ -0,0 +1,312 @@
+//! Find outputs that were built by earlier invocations in the output directory.
+//!
+//! `pin_subpackage` can only be resolved for outputs that are rendered in the
+//! same invocation. When only some outputs of a multi-output recipe are built
+//! (e.g. because the others are skipped), the exact pins to the siblings that
+//! were built earlier are taken from the packages in the output directory,
+//! using the `info/recipe/rendered_recipe.yaml` file of every package.
+//!
+//! When several recipes are rendered together (e.g. all recipes of a
+//! `--recipe-dir` with `--render-only`), pins to outputs of the other recipes
+//! are resolved against their rendered outputs.
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use fs_err as fs;
+use rattler_conda_types::{package::ArchiveType, PackageName, Platform, Version};
+use serde::Deserialize;
+
+use crate::{metadata::PackageIdentifier, normalized_key::NormalizedKey, rebuild};
+
+/// An output that was built earlier and is stored in the output directory
+#[derive(Debug, Clone)]
+pub struct BuiltOutput {
+    /// The name, version and build string of the package
+    pub identifier: PackageIdentifier,
+    /// The variant the package was built with
+    pub variant: BTreeMap<NormalizedKey, String>,
+    /// The path of the package
+    pub path: PathBuf,
+    /// When the package was written
+    pub modified: SystemTime,
+}
+
+/// The parts of `rendered_recipe.yaml` that identify a built output
+#[derive(Deserialize)]
+struct RenderedOutput {
+    recipe: RenderedRecipe,
+    #[serde(default)]
+    build_configuration: RenderedBuildConfiguration,
+}
+
+#[derive(Deserialize)]
+struct RenderedRecipe {
+    package: RenderedPackage,
+    build: RenderedBuild,
+}
+
+#[derive(Deserialize)]
+struct RenderedPackage {
+    name: PackageName,
+    version: Version,
+}
+
+#[derive(Deserialize)]
+struct RenderedBuild {
+    string: String,
+}
+
+#[derive(Default, Deserialize)]
+struct RenderedBuildConfiguration {
+    #[serde(default)]
+    variant: BTreeMap<NormalizedKey, String>,
+}
+
+/// Read the identifier and variant of a package from its rendered recipe
+fn read_built_output(path: &Path) -> Result<BuiltOutput, String> {
+    let rendered =
+        rebuild::read_recipe_file(path, "rendered_recipe.yaml").map_err(|e| e.to_string())?;
+    let rendered: RenderedOutput = serde_yaml::from_str(&rendered).map_err(|e| e.to_string())?;
+    let modified = fs::metadata(path)
+        .and_then(|m| m.modified())
+        .unwrap_or(SystemTime::UNIX_EPOCH);
+
+    Ok(BuiltOutput {
+        identifier: PackageIdentifier {
+            name: rendered.recipe.package.name,
+            version: rendered.recipe.package.version,
+            build_string: rendered.recipe.build.string,
+        },
+        variant: rendered.build_configuration.variant,
+        path: path.to_path_buf(),
+        modified,
+    })
+}
+
+/// Find all packages (in all subdirectories) of the output directory that
+/// were built by rattler-build. Packages without a readable rendered recipe
+/// are ignored.
+pub fn find_built_outputs(output_dir: &Path) -> Vec<BuiltOutput> {
+    let Ok(subdirs) = fs::read_dir(output_dir) else {
+        return Vec::new();
+    };
+
+    let mut built = Vec::new();
+    for subdir in subdirs.filter_map(Result::ok) {
+        let Ok(packages) = fs::read_dir(subdir.path()) else {
+            continue;
+        };
+        for package in packages.filter_map(Result::ok) {
+            let path = package.path();
+            if ArchiveType::try_from(path.as_path()).is_none() {
+                continue;
+            }
+            match read_built_output(&path) {
+                Ok(output) => built.push(output),
+                Err(e) => tracing::debug!("Ignoring {} for subpackage pins: {}", path.display(), e),
+            }
+        }
+    }
+    built
+}
+
+/// Returns true if the variants agree on all keys they have in common
+fn variants_compatible(
+    a: &BTreeMap<NormalizedKey, String>,
+    b: &BTreeMap<NormalizedKey, String>,
+) -> bool {
+    a.iter()
+        .all(|(key, value)| b.get(key).map_or(true, |other| other == value))
+}
+
+/// Add the built outputs for the pinned subpackages that are not rendered in
+/// this invocation. If a package was built multiple times, the most recent
+/// build whose variant is compatible with `variant` is used.
+pub fn add_built_subpackages<'a>(
+    subpackages: &mut BTreeMap<PackageName, PackageIdentifier>,
+    pinned: impl IntoIterator<Item = &'a PackageName>,
+    built: &[BuiltOutput],
+    variant: &BTreeMap<NormalizedKey, String>,
+) {
+    for name in pinned {
+        if subpackages.contains_key(name) {
+            continue;
+        }
+
+        let Some(latest) = built
+            .iter()
+            .filter(|candidate| {
+                &candidate.identifier.name == name
+                    && variants_compatible(&candidate.variant, variant)
+            })
+            .max_by_key(|candidate| candidate.modified)
+        else {
+            continue;
+        };
+
+        tracing::info!(
+            "Using previously built {}-{}-{} from {} for subpackage pins",
+            latest.identifier.name.as_normalized(),
+            latest.identifier.version,
+            latest.identifier.build_string,
+            latest.path.display()
+        );
+        subpackages.insert(name.clone(), latest.identifier.clone());
+    }
+}
+
+/// An output of another recipe that was rendered in the same invocation
+#[derive(Debug, Clone)]
+pub struct SiblingOutput {
+    /// The name, version and build string of the output
+    pub identifier: PackageIdentifier,
+    /// The platform the output is rendered for
+    pub target_platform: Platform,
+    /// The variant the output is rendered with
+    pub variant: BTreeMap<NormalizedKey, String>,
+    /// The recipe the output belongs to
+    pub recipe_path: PathBuf,
+}
+
+/// Add the outputs of other recipes for the pinned subpackages that are not
+/// outputs of this recipe. The pinned output has to be rendered for the same
+/// platform (or be `noarch`) with a compatible variant. Returns every pin that
+/// had to be looked up, with the output it was resolved to (if any).
+pub fn add_sibling_subpackages<'a, 'b>(
+    subpackages: &mut BTreeMap<PackageName, PackageIdentifier>,
+    pinned: impl IntoIterator<Item = &'a PackageName>,
+    siblings: &'b [SiblingOutput],
+    recipe_path: &Path,
+    target_platform: Platform,
+    variant: &BTreeMap<NormalizedKey, String>,
+) -> Vec<(PackageName, Option<&'b SiblingOutput>)> {
+    let mut lookups = Vec::new();
+    for name in pinned {
+        if subpackages.contains_key(name) || lookups.iter().any(|(n, _)| n == name) {
+            continue;
+        }
+
+        let sibling = siblings.iter().find(|sibling| {
+            &sibling.identifier.name == name
+                && sibling.recipe_path != recipe_path
+                && (sibling.target_platform == target_platform
+                    || sibling.target_platform == Platform::NoArch)
+                && variants_compatible(&sibling.variant, variant)
+        });
+        if let Some(sibling) = sibling {
+            subpackages.insert(name.clone(), sibling.identifier.clone());
+        }
+        lookups.push((name.clone(), sibling));
+    }
+    lookups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn built(name: &str, build_string: &str, python: &str, age: u64) -> BuiltOutput {
+        BuiltOutput {
+            identifier: PackageIdentifier {
+                name: name.parse().unwrap(),
+                version: "1.0".parse().unwrap(),
+                build_string: build_string.to_string(),
+            },
+            variant: BTreeMap::from([(NormalizedKey::from("python"), python.to_string())]),
+            path: PathBuf::from(format!("{name}-1.0-{build_string}.conda")),
+            modified: SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1000 - age),
+        }
+    }
+
+    #[test]
+    fn add_compatible_built_subpackages() {
+        let built = vec![
+            built("libfoo", "h1_0", "3.12", 10),
+            built("libfoo", "h2_0", "3.12", 5),
+            built("libfoo", "h3_0", "3.11", 0),
+            built("foo", "h4_0", "3.12", 0),
+        ];
+        let variant = BTreeMap::from([(NormalizedKey::from("python"), "3.12".to_string())]);
+
+        let mut subpackages = BTreeMap::new();
+        let foo: PackageName = "foo".parse().unwrap();
+        subpackages.insert(
+            foo.clone(),
+            PackageIdentifier {
+                name: foo.clone(),
+                version: "2.0".parse().unwrap(),
+                build_string: "h5_0".to_string(),
+            },
+        );
+
+        let libfoo: PackageName = "libfoo".parse().unwrap();
+        let libbar: PackageName = "libbar".parse().unwrap();
+        add_built_subpackages(&mut subpackages, [&foo, &libfoo, &libbar], &built, &variant);
+
+        // outputs of this invocation take precedence
+        assert_eq!(subpackages[&foo].build_string, "h5_0");
+        // the most recent compatible build is used
+        assert_eq!(subpackages[&libfoo].build_string, "h2_0");
+        // packages that were never built stay unresolved
+        assert!(!subpackages.contains_key(&libbar));
+    }
+
+    #[test]
+    fn add_pins_to_sibling_recipes() {
+        let sibling =
+            |name: &str, build_string: &str, platform: Platform, python: &str| SiblingOutput {
+                identifier: PackageIdentifier {
+                    name: name.parse().unwrap(),
+                    version: "1.0".parse().unwrap(),
+                    build_string: build_string.to_string(),
+                },
+                target_platform: platform,
+                variant: BTreeMap::from([(NormalizedKey::from("python"), python.to_string())]),
+                recipe_path: PathBuf::from(format!("recipes/{name}/recipe.yaml")),
+            };
+        let siblings = vec![
+            sibling("libfoo", "h1_0", Platform::Linux64, "3.11"),
+            sibling("libfoo", "h2_0", Platform::Linux64, "3.12"),
+            sibling("libfoo", "h3_0", Platform::Osx64, "3.12"),
+            sibling("data", "h4_0", Platform::NoArch, "3.12"),
+        ];
+        let variant = BTreeMap::from([(NormalizedKey::from("python"), "3.12".to_string())]);
+
+        let libfoo: PackageName = "libfoo".parse().unwrap();
+        let data: PackageName = "data".parse().unwrap();
+        let libbar: PackageName = "libbar".parse().unwrap();
+        let mut subpackages = BTreeMap::new();
+        let lookups = add_sibling_subpackages(
+            &mut subpackages,
+            [&libfoo, &data, &libbar, &libfoo],
+            &siblings,
+            Path::new("recipes/foo/recipe.yaml"),
+            Platform::Linux64,
+            &variant,
+        );
+
+        // the output for the same platform and a compatible variant is used
+        assert_eq!(subpackages[&libfoo].build_string, "h2_0");
+        // noarch outputs match every platform
+        assert_eq!(subpackages[&data].build_string, "h4_0");
+        // every pin is reported once, unresolved pins without an output
+        assert_eq!(lookups.len(), 3);
+        assert!(lookups[2].1.is_none());
+        assert!(!subpackages.contains_key(&libbar));
+
+        // outputs of the same recipe are not siblings
+        let mut subpackages = BTreeMap::new();
+        add_sibling_subpackages(
+            &mut subpackages,
+            [&libfoo],
+            &siblings,
+            Path::new("recipes/libfoo/recipe.yaml"),
+            Platform::Linux64,
+            &variant,
+        );
+        assert!(subpackages.is_empty());
+    }
+}
@@ -205,6 +205,7 @@ impl Output {
             let target_platform = self.build_configuration.target_platform;
             let mut env_vars = env_vars::vars(&self, "BUILD");
             env_vars.extend(env_vars::os_vars(self.prefix(), &target_platform));
+            env_vars.extend(env_vars::variant_os_vars(&self));
 
             // Reindex the channels
             let channels = build_reindexed_channels(&self.build_configuration, tool_configuration)
@@ -233,12 +234,14 @@ impl Output {
                 .script()
                 .run_script(
                     env_vars,
+                    &[],
                     &self.build_configuration.directories.work_dir,
                     &self.build_configuration.directories.recipe_dir,
                     &self.build_configuration.directories.host_prefix,
                     Some(&self.build_configuration.directories.build_prefix),
                     Some(jinja),
                     None, // sandbox config
+                    &tool_configuration.cancellation_token,
                 )
                 .await
                 .into_diagnostic()?;
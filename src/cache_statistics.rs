@@ -0,0 +1,138 @@
+//! Statistics about work that is skipped because of the source cache or
+//! `--skip-existing`. With `--explain-cache`, every decision is explained in
+//! the log and the statistics are printed at the end of the run.
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use indicatif::HumanBytes;
+
+use crate::source::cache::SourceCache;
+
+/// Counts the hits and misses of the caches during a run
+#[derive(Debug, Default)]
+pub struct CacheStatistics {
+    explain: bool,
+    source_hits: AtomicUsize,
+    source_misses: AtomicUsize,
+    existing_skipped: AtomicUsize,
+    existing_built: AtomicUsize,
+}
+
+impl CacheStatistics {
+    /// Create new statistics. If `explain` is set, every decision is logged.
+    pub fn new(explain: bool) -> Self {
+        Self {
+            explain,
+            ..Self::default()
+        }
+    }
+
+    /// Returns true if the decisions are explained in the log
+    pub fn explain(&self) -> bool {
+        self.explain
+    }
+
+    /// Record a lookup of a source in the source cache
+    pub fn record_source(&self, hit: bool, source: &str, key: &str, reason: &str) {
+        let counter = if hit {
+            &self.source_hits
+        } else {
+            &self.source_misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+
+        if self.explain {
+            tracing::info!(
+                "Source cache {} for {} (key: {}): {}",
+                if hit { "hit" } else { "miss" },
+                source,
+                key,
+                reason
+            );
+        }
+    }
+
+    /// Record whether an output was skipped because it already exists
+    pub fn record_existing(&self, skipped: bool, identifier: &str, reason: &str) {
+        let counter = if skipped {
+            &self.existing_skipped
+        } else {
+            &self.existing_built
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+
+        if self.explain {
+            tracing::info!(
+                "{} {}: {}",
+                if skipped { "Skipping" } else { "Building" },
+                identifier,
+                reason
+            );
+        }
+    }
+
+    /// A summary of the statistics
+    pub fn summary(&self) -> String {
+        format!(
+            "source cache: {} hit(s), {} miss(es); existing packages: {} skipped, {} to build",
+            self.source_hits.load(Ordering::Relaxed),
+            self.source_misses.load(Ordering::Relaxed),
+            self.existing_skipped.load(Ordering::Relaxed),
+            self.existing_built.load(Ordering::Relaxed),
+        )
+    }
+
+    /// Log the summary and the content of the source cache (only if the
+    /// decisions are explained)
+    pub fn log_summary(&self, source_cache: Option<&SourceCache>) {
+        if !self.explain {
+            return;
+        }
+        tracing::info!("Cache statistics: {}", self.summary());
+
+        let Some(source_cache) = source_cache else {
+            return;
+        };
+        match source_cache.entries() {
+            Ok(entries) => {
+                let total = entries.iter().map(|entry| entry.size).sum::<u64>();
+                tracing::info!(
+                    "Source cache at {} contains {} entries ({})",
+                    source_cache.path().display(),
+                    entries.len(),
+                    HumanBytes(total)
+                );
+                for entry in entries {
+                    let age = entry
+                        .age()
+                        .map(|age| format!("{}h old", age.as_secs() / 3600))
+                        .unwrap_or_else(|| "unknown age".to_string());
+                    tracing::info!(
+                        "  {:>10}  {:>12}  {}",
+                        HumanBytes(entry.size),
+                        age,
+                        entry.name
+                    );
+                }
+            }
+            Err(e) => tracing::warn!("Could not read the source cache: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_statistics() {
+        let statistics = CacheStatistics::new(false);
+        statistics.record_source(true, "https://example.com/foo.tar.gz", "foo", "valid");
+        statistics.record_source(false, "https://example.com/bar.tar.gz", "bar", "missing");
+        statistics.record_source(false, "https://example.com/baz.tar.gz", "baz", "missing");
+        statistics.record_existing(true, "foo-1.0-h123_0", "exists");
+        assert_eq!(
+            statistics.summary(),
+            "source cache: 1 hit(s), 2 miss(es); existing packages: 1 skipped, 0 to build"
+        );
+    }
+}
@@ -0,0 +1,208 @@
+//! Find files that are packaged by more than one output of the same recipe.
+//!
+//! Installing two such packages into the same environment clobbers the files
+//! (the last installed package wins). conda only warns about this at install
+//! time, so the outputs of a multi-output recipe are checked when they are
+//! packaged, before the archive is written. Only outputs with compatible
+//! variants (the same value for all variant keys they share) and the same
+//! target platform are compared, since other outputs cannot end up in the
+//! same environment.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+    sync::Mutex,
+};
+
+use rattler_conda_types::Platform;
+
+use crate::{
+    metadata::Output, normalized_key::NormalizedKey, packaging::PackagingError,
+    tool_configuration::ClobberCheck,
+};
+
+/// The files of a packaged output
+#[derive(Debug)]
+struct PackagedOutput {
+    identifier: String,
+    name: String,
+    recipe_path: PathBuf,
+    target_platform: Platform,
+    variant: BTreeMap<NormalizedKey, String>,
+    files: BTreeSet<PathBuf>,
+}
+
+impl PackagedOutput {
+    fn new(output: &Output, files: BTreeSet<PathBuf>) -> Self {
+        Self {
+            identifier: output.identifier(),
+            name: output.name().as_normalized().to_string(),
+            recipe_path: output.build_configuration.directories.recipe_path.clone(),
+            target_platform: output.build_configuration.target_platform,
+            variant: output.variant().clone(),
+            files,
+        }
+    }
+
+    /// Returns true if the two outputs could be installed into the same
+    /// environment
+    fn can_be_installed_with(&self, other: &PackagedOutput) -> bool {
+        self.name != other.name
+            && self.recipe_path == other.recipe_path
+            && self.target_platform == other.target_platform
+            && self.variant.iter().all(|(key, value)| {
+                other
+                    .variant
+                    .get(key)
+                    .map_or(true, |other_value| other_value == value)
+            })
+    }
+}
+
+/// Files that are packaged by two outputs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Clobber {
+    /// The output that was packaged first
+    pub first: String,
+    /// The output that was packaged later
+    pub second: String,
+    /// The files that are packaged by both outputs
+    pub paths: Vec<PathBuf>,
+}
+
+impl std::fmt::Display for Clobber {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} file(s) are packaged by both {} and {}:",
+            self.paths.len(),
+            self.first,
+            self.second
+        )?;
+        for path in &self.paths {
+            write!(f, "\n - {}", path.display())?;
+        }
+        Ok(())
+    }
+}
+
+/// Keeps track of the files of all packaged outputs
+#[derive(Debug, Default)]
+pub struct PackagedFiles {
+    outputs: Mutex<Vec<PackagedOutput>>,
+}
+
+impl PackagedFiles {
+    /// Compare the files of an output that is about to be packaged (relative
+    /// to the prefix, without the `info` folder) with the outputs of the same
+    /// recipe that were packaged before. Clobbered files are reported as a
+    /// warning or an error, depending on `behavior`. The files are remembered
+    /// unless the check fails.
+    pub fn check(
+        &self,
+        output: &Output,
+        files: BTreeSet<PathBuf>,
+        behavior: ClobberCheck,
+    ) -> Result<(), PackagingError> {
+        if behavior == ClobberCheck::Ignore {
+            return Ok(());
+        }
+
+        let packaged = PackagedOutput::new(output, files);
+        let clobbers = self.clobbers(&packaged);
+        if !clobbers.is_empty() {
+            let report = clobbers
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join("\n");
+            if behavior == ClobberCheck::Error {
+                return Err(PackagingError::ClobberedFiles(report));
+            }
+            tracing::warn!("{}", report);
+            output.record_warning(&report);
+        }
+        self.outputs.lock().unwrap().push(packaged);
+        Ok(())
+    }
+
+    /// The files that an output shares with the outputs that were packaged
+    /// before
+    fn clobbers(&self, packaged: &PackagedOutput) -> Vec<Clobber> {
+        self.outputs
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|other| other.can_be_installed_with(packaged))
+            .filter_map(|other| {
+                let paths = other
+                    .files
+                    .intersection(&packaged.files)
+                    .cloned()
+                    .collect::<Vec<_>>();
+                (!paths.is_empty()).then(|| Clobber {
+                    first: other.identifier.clone(),
+                    second: packaged.identifier.clone(),
+                    paths,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packaged(name: &str, variant: &[(&str, &str)], files: &[&str]) -> PackagedOutput {
+        PackagedOutput {
+            identifier: format!("{name}-1.0-0"),
+            name: name.to_string(),
+            recipe_path: PathBuf::from("recipe/recipe.yaml"),
+            target_platform: Platform::Linux64,
+            variant: variant
+                .iter()
+                .map(|(key, value)| (NormalizedKey::from(*key), value.to_string()))
+                .collect(),
+            files: files.iter().map(PathBuf::from).collect(),
+        }
+    }
+
+    #[test]
+    fn test_can_be_installed_with() {
+        let lib = packaged("libfoo", &[("python", "3.12")], &[]);
+        assert!(lib.can_be_installed_with(&packaged("foo-dev", &[], &[])));
+        assert!(lib.can_be_installed_with(&packaged("foo-dev", &[("python", "3.12")], &[])));
+        assert!(!lib.can_be_installed_with(&packaged("foo-dev", &[("python", "3.11")], &[])));
+        // variants of the same package replace each other
+        assert!(!lib.can_be_installed_with(&packaged("libfoo", &[], &[])));
+    }
+
+    fn check_packaged(packaged_files: &PackagedFiles, packaged: PackagedOutput) -> Vec<Clobber> {
+        let clobbers = packaged_files.clobbers(&packaged);
+        packaged_files.outputs.lock().unwrap().push(packaged);
+        clobbers
+    }
+
+    #[test]
+    fn test_check() {
+        let packaged_files = PackagedFiles::default();
+        assert!(check_packaged(
+            &packaged_files,
+            packaged("libfoo", &[], &["include/foo.h", "lib/libfoo.so"])
+        )
+        .is_empty());
+        assert!(
+            check_packaged(&packaged_files, packaged("libfoo", &[], &["include/foo.h"])).is_empty()
+        );
+
+        let clobbers = check_packaged(
+            &packaged_files,
+            packaged("foo-dev", &[], &["include/foo.h", "include/bar.h"]),
+        );
+        assert_eq!(clobbers.len(), 2);
+        assert_eq!(
+            clobbers[0].to_string(),
+            "1 file(s) are packaged by both libfoo-1.0-0 and foo-dev-1.0-0:\n - include/foo.h"
+        );
+    }
+}
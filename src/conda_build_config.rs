@@ -1,7 +1,12 @@
 //! Module to load deprecated `conda_build_config.yaml` files and apply the selector_config to it
-use std::{collections::BTreeMap, path::Path};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+};
 
 use minijinja::{Environment, Value};
+use rattler_conda_types::Platform;
+use serde::Serialize;
 
 use crate::{
     selectors::SelectorConfig,
@@ -33,38 +38,28 @@ impl<'a> ParsedLine<'a> {
     }
 }
 
+fn condition_template(condition: &str) -> String {
+    format!("{{% if {} %}}true{{% else %}}false{{% endif %}}", condition)
+}
+
 fn evaluate_condition(
     condition: &str,
     env: &Environment,
     context: &BTreeMap<String, Value>,
-) -> bool {
+) -> Result<bool, minijinja::Error> {
     if condition.is_empty() {
-        return true;
+        return Ok(true);
     }
 
-    let template_str = format!("{{% if {} %}}true{{% else %}}false{{% endif %}}", condition);
-    let template = env.template_from_str(&template_str).unwrap();
+    let template_str = condition_template(condition);
+    let template = env.template_from_str(&template_str)?;
 
-    template.render(context).unwrap() == "true"
+    Ok(template.render(context)? == "true")
 }
 
-/// Load an old-school conda_build_config.yaml file, and apply the selector_config to it
-/// The parser supports only a small subset of (potential) conda_build_config.yaml features.
-/// Especially, only `os.environ.get(...)` is supported.
-pub fn load_conda_build_config(
-    path: &Path,
-    selector_config: &SelectorConfig,
-) -> Result<VariantConfig, VariantConfigError> {
-    // load the text, parse it and load as VariantConfig using serde_yaml
-
-    let mut input = fs_err::read_to_string(path)
-        .map_err(|e| VariantConfigError::IOError(path.to_path_buf(), e))?;
-
-    let mut context = selector_config.clone().into_context();
-
-    let short_target_platform = selector_config.target_platform.to_string().replace("-", "");
-    context.insert(short_target_platform, Value::from(true));
-
+/// The environment that selectors are evaluated in. Python calls that are
+/// supported are rewritten by [`translate_python`].
+fn selector_environment() -> Environment<'static> {
     let mut env = Environment::new();
 
     env.add_function(
@@ -75,16 +70,67 @@ pub fn load_conda_build_config(
         },
     );
 
+    env
+}
+
+/// Rewrite the (few) supported Python constructs to Jinja.
+fn translate_python(input: &str) -> String {
     // replace all `os.environ.get` calls with `environ_get`
-    input = input.replace("os.environ.get", "environ_get");
     // replace calls to `.startswith` with `is startingwith`
-    input = input.replace(".startswith", " is startingwith");
+    input
+        .replace("os.environ.get", "environ_get")
+        .replace(".startswith", " is startingwith")
+}
+
+/// Load an old-school conda_build_config.yaml file, and apply the selector_config to it
+/// The parser supports only a small subset of (potential) conda_build_config.yaml features.
+/// Especially, only `os.environ.get(...)` is supported.
+pub fn load_conda_build_config(
+    path: &Path,
+    selector_config: &SelectorConfig,
+) -> Result<VariantConfig, VariantConfigError> {
+    let value = load_conda_build_config_mapping(path, selector_config)?;
+
+    let config: VariantConfig =
+        serde_yaml::from_value(serde_yaml::Value::Mapping(value)).map_err(|e| {
+            VariantConfigError::IOError(
+                path.to_path_buf(),
+                std::io::Error::new(std::io::ErrorKind::InvalidData, e),
+            )
+        })?;
+
+    Ok(config)
+}
+
+/// Load a file in the `conda_build_config.yaml` format with the selectors applied, without
+/// converting it to a [`VariantConfig`] (e.g. for conda-forge migration files that contain
+/// additional keys). Keys without a value are removed.
+pub(crate) fn load_conda_build_config_mapping(
+    path: &Path,
+    selector_config: &SelectorConfig,
+) -> Result<serde_yaml::Mapping, VariantConfigError> {
+    // load the text, parse it and load as VariantConfig using serde_yaml
+
+    let input = fs_err::read_to_string(path)
+        .map_err(|e| VariantConfigError::IOError(path.to_path_buf(), e))?;
+    let input = translate_python(&input);
+
+    let context = selector_context(selector_config);
+    let env = selector_environment();
 
     let mut lines = Vec::new();
-    for line in input.lines() {
+    for (index, line) in input.lines().enumerate() {
         let parsed = ParsedLine::from_str(line);
         let mut line_content = if let Some(condition) = &parsed.condition {
-            if evaluate_condition(condition, &env, &context) {
+            let selected = evaluate_condition(condition, &env, &context).map_err(|source| {
+                VariantConfigError::InvalidSelector {
+                    path: path.to_path_buf(),
+                    line: index + 1,
+                    selector: condition.to_string(),
+                    source,
+                }
+            })?;
+            if selected {
                 parsed.content.to_string()
             } else {
                 continue;
@@ -116,7 +162,7 @@ pub fn load_conda_build_config(
     })?;
 
     if value.is_null() {
-        return Ok(VariantConfig::default());
+        return Ok(serde_yaml::Mapping::new());
     }
 
     // filter all empty maps
@@ -136,15 +182,158 @@ pub fn load_conda_build_config(
         .filter(|(_, v)| !v.is_null())
         .collect::<serde_yaml::Mapping>();
 
-    let config: VariantConfig =
-        serde_yaml::from_value(serde_yaml::Value::Mapping(value)).map_err(|e| {
-            VariantConfigError::IOError(
-                path.to_path_buf(),
-                std::io::Error::new(std::io::ErrorKind::InvalidData, e),
-            )
-        })?;
+    Ok(value)
+}
 
-    Ok(config)
+/// The context that selectors are evaluated in.
+fn selector_context(selector_config: &SelectorConfig) -> BTreeMap<String, Value> {
+    let mut context = selector_config.clone().into_context();
+
+    let short_target_platform = selector_config.target_platform.to_string().replace("-", "");
+    context.insert(short_target_platform, Value::from(true));
+
+    context
+}
+
+/// Top-level keys of conda-build that rattler-build does not support, with an
+/// explanation.
+const UNSUPPORTED_KEYS: &[(&str, &str)] = &[
+    (
+        "extend_keys",
+        "`extend_keys` is not supported (lists of keys are replaced, not extended)",
+    ),
+    (
+        "ignore_version",
+        "`ignore_version` is not supported (all variant keys that are used are part of the hash)",
+    ),
+    (
+        "ignore_build_only_deps",
+        "`ignore_build_only_deps` is not supported",
+    ),
+];
+
+/// A construct in a `conda_build_config.yaml` file that rattler-build does not
+/// support (or that behaves differently than in conda-build).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CompatibilityIssue {
+    /// The line of the construct (1-based), or `None` if it concerns the whole
+    /// file
+    pub line: Option<usize>,
+    /// The content of the line
+    pub content: String,
+    /// What is not supported
+    pub message: String,
+}
+
+/// Check a `conda_build_config.yaml` file for constructs that
+/// [`load_conda_build_config`] does not support: selectors that are not valid
+/// Jinja expressions or that use variables that are not defined for any
+/// platform, Jinja expressions in values, unsupported Python calls and
+/// unsupported top-level keys. Finally the file is loaded with the given
+/// selector config to find the remaining errors.
+pub fn compatibility_report(
+    path: &Path,
+    selector_config: &SelectorConfig,
+) -> Result<Vec<CompatibilityIssue>, VariantConfigError> {
+    let input = fs_err::read_to_string(path)
+        .map_err(|e| VariantConfigError::IOError(path.to_path_buf(), e))?;
+
+    let env = selector_environment();
+    let context = selector_context(selector_config);
+    let known_names = known_selector_names();
+
+    let mut issues = Vec::new();
+    for (index, line) in input.lines().enumerate() {
+        let mut report = |message: String| {
+            issues.push(CompatibilityIssue {
+                line: Some(index + 1),
+                content: line.trim_end().to_string(),
+                message,
+            })
+        };
+
+        if line.contains("environ[") || line.contains("os.getenv") {
+            report(
+                "only `os.environ.get(...)` is supported to read environment variables".to_string(),
+            );
+        }
+
+        let parsed = ParsedLine::from_str(line);
+        if parsed.content.contains("{{") || parsed.content.contains("{%") {
+            report("Jinja expressions in values are not supported".to_string());
+        }
+
+        if !line.starts_with([' ', '\t', '-', '#']) {
+            if let Some((key, _)) = parsed.content.split_once(':') {
+                if let Some((_, message)) = UNSUPPORTED_KEYS
+                    .iter()
+                    .find(|(name, _)| *name == key.trim())
+                {
+                    report(message.to_string());
+                }
+            }
+        }
+
+        let Some(condition) = parsed.condition.filter(|c| !c.is_empty()) else {
+            continue;
+        };
+        let translated = translate_python(condition);
+        match env.template_from_str(&condition_template(&translated)) {
+            Err(e) => report(format!("selector `{condition}` is not supported: {e}")),
+            Ok(template) => {
+                let unknown = template
+                    .undeclared_variables(false)
+                    .into_iter()
+                    .filter(|name| !known_names.contains(name))
+                    .collect::<BTreeSet<_>>();
+                if !unknown.is_empty() {
+                    report(format!(
+                        "selector `{condition}` uses {}, which {} not defined in rattler-build (undefined variables are false)",
+                        unknown
+                            .iter()
+                            .map(|name| format!("`{name}`"))
+                            .collect::<Vec<_>>()
+                            .join(", "),
+                        if unknown.len() == 1 { "is" } else { "are" }
+                    ));
+                } else if let Err(e) = template.render(&context) {
+                    report(format!("selector `{condition}` is not supported: {e}"));
+                }
+            }
+        }
+    }
+
+    // errors that are only found when loading the file (e.g. invalid YAML or
+    // values that are not lists)
+    if issues.is_empty() {
+        if let Err(e) = load_conda_build_config(path, selector_config) {
+            issues.push(CompatibilityIssue {
+                line: None,
+                content: String::new(),
+                message: e.to_string(),
+            });
+        }
+    }
+
+    Ok(issues)
+}
+
+/// The variables that selectors can use on any platform.
+fn known_selector_names() -> BTreeSet<String> {
+    let mut names = BTreeSet::from(["environ_get".to_string()]);
+    for platform in Platform::all() {
+        let selector_config = SelectorConfig {
+            target_platform: platform,
+            host_platform: platform,
+            build_platform: platform,
+            ..Default::default()
+        };
+        names.extend(selector_context(&selector_config).into_keys());
+    }
+    for name in ["linux", "osx", "win", "unix"] {
+        names.insert(name.to_string());
+    }
+    names
 }
 
 #[cfg(test)]
@@ -173,22 +362,22 @@ mod tests {
         let mut context = BTreeMap::new();
         context.insert("py3k".to_string(), Value::from(true));
         let env = Environment::new();
-        assert!(evaluate_condition("py3k", &env, &context));
+        assert!(evaluate_condition("py3k", &env, &context).unwrap());
 
         let mut context = BTreeMap::new();
         context.insert("py3k".to_string(), Value::from(false));
         let env = Environment::new();
-        assert!(!evaluate_condition("py3k", &env, &context));
+        assert!(!evaluate_condition("py3k", &env, &context).unwrap());
 
         let mut context = BTreeMap::new();
         context.insert("py3k".to_string(), Value::from(true));
         let env = Environment::new();
-        assert!(!evaluate_condition("not py3k", &env, &context));
+        assert!(!evaluate_condition("not py3k", &env, &context).unwrap());
 
         let mut context = BTreeMap::new();
         context.insert("py3k".to_string(), Value::from(false));
         let env = Environment::new();
-        assert!(evaluate_condition("not py3k", &env, &context));
+        assert!(evaluate_condition("not py3k", &env, &context).unwrap());
     }
 
     #[rstest]
@@ -239,6 +428,60 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_compatibility_report() {
+        let path = test_data_dir().join("conda_build_config/unsupported.yaml");
+        let selector_config = SelectorConfig {
+            target_platform: Platform::Linux64,
+            host_platform: Platform::Linux64,
+            build_platform: Platform::Linux64,
+            ..Default::default()
+        };
+
+        let issues = compatibility_report(&path, &selector_config).unwrap();
+        let lines = issues
+            .iter()
+            .map(|issue| (issue.line.unwrap(), issue.message.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(lines.len(), 5, "{lines:#?}");
+        assert_eq!(lines[0].0, 4);
+        assert!(lines[0].1.contains("`py2k`"));
+        // the selector is not valid Jinja and uses an unsupported environ access
+        assert_eq!(lines[1].0, 8);
+        assert!(lines[1].1.contains("os.environ.get"));
+        assert_eq!(lines[2].0, 8);
+        assert!(lines[2].1.starts_with("selector"));
+        assert_eq!(
+            lines[3],
+            (10, "Jinja expressions in values are not supported")
+        );
+        assert_eq!(lines[4].0, 11);
+        assert!(lines[4].1.contains("extend_keys"));
+
+        // the selector that fails to evaluate is reported with its line
+        let selector_config = SelectorConfig {
+            target_platform: Platform::Win64,
+            host_platform: Platform::Win64,
+            build_platform: Platform::Win64,
+            ..Default::default()
+        };
+        let err = load_conda_build_config(&path, &selector_config).unwrap_err();
+        assert!(
+            matches!(
+                &err,
+                VariantConfigError::InvalidSelector { line: 8, selector, .. }
+                    if selector.starts_with("win and int(")
+            ),
+            "{err:?}"
+        );
+
+        // a supported file only reports load errors
+        let path = test_data_dir().join("conda_build_config/test_1.yaml");
+        assert!(compatibility_report(&path, &selector_config)
+            .unwrap()
+            .is_empty());
+    }
+
     fn test_data_dir() -> PathBuf {
         std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("test-data/")
     }
@@ -2,7 +2,8 @@
 use std::{
     borrow::Cow,
     future::Future,
-    io,
+    io::{self, Write as _},
+    path::Path,
     str::FromStr,
     sync::{Arc, Mutex},
     time::{Duration, Instant},
@@ -16,19 +17,19 @@ use indicatif::{
 use tracing::{field, Level};
 use tracing_core::{span::Id, Event, Field, Subscriber};
 use tracing_subscriber::{
-    filter::{Directive, ParseError},
+    filter::{Directive, ParseError, Targets},
     fmt::{
         self,
         format::{self, Format},
         FmtContext, FormatEvent, FormatFields, MakeWriter,
     },
     layer::{Context, SubscriberExt},
-    registry::LookupSpan,
+    registry::{LookupSpan, SpanRef},
     util::SubscriberInitExt,
     EnvFilter, Layer,
 };
 
-use crate::consts;
+use crate::{build_profile::Profiler, consts};
 
 /// A custom formatter for tracing events.
 pub struct TracingFormatter;
@@ -266,6 +267,131 @@ where
     }
 }
 
+/// The log file of a span (e.g. the span of the build of an output), stored
+/// in the extensions of the span.
+struct LogFile(Mutex<io::BufWriter<fs_err::File>>);
+
+impl LogFile {
+    fn open(path: &Path, append: bool) -> io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs_err::create_dir_all(parent)?;
+        }
+        let file = fs_err::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(path)?;
+        Ok(Self(Mutex::new(io::BufWriter::new(file))))
+    }
+
+    fn write_lines(&self, depth: usize, level: &Level, message: &str) {
+        let mut file = self.0.lock().unwrap();
+        let timestamp = chrono::Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ");
+        let indent = "  ".repeat(depth);
+        let message = console::strip_ansi_codes(message);
+        for line in message.lines() {
+            // errors while writing the log file should never fail the build
+            let _ = writeln!(file, "{timestamp} {level:<5} {indent}{line}");
+        }
+    }
+}
+
+/// Reads the `log_file` and `append_log` fields of a span
+#[derive(Default)]
+struct LogFileVisitor {
+    path: Option<String>,
+    append: bool,
+}
+
+impl field::Visit for LogFileVisitor {
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        if field.name() == "append_log" {
+            self.append = value;
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "log_file" {
+            self.path = Some(format!("{:?}", value));
+        }
+    }
+}
+
+/// A layer that writes an uncolored copy of all log messages to the log file
+/// of the closest span that has one, independent of the console verbosity.
+/// The log file is set with the `log_file` field of a span, and is appended to
+/// instead of overwritten if the span also sets `append_log = true`.
+#[derive(Debug, Default, Clone, Copy)]
+struct LogFileLayer;
+
+impl LogFileLayer {
+    fn write_lines<'a, S>(
+        scope: impl Iterator<Item = SpanRef<'a, S>>,
+        depth: usize,
+        level: &Level,
+        message: &str,
+    ) where
+        S: Subscriber + for<'l> LookupSpan<'l>,
+    {
+        for span in scope {
+            if let Some(log_file) = span.extensions().get::<LogFile>() {
+                log_file.write_lines(depth, level, message);
+                return;
+            }
+        }
+    }
+}
+
+impl<S> Layer<S> for LogFileLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(
+        &self,
+        attrs: &tracing_core::span::Attributes<'_>,
+        id: &Id,
+        ctx: Context<'_, S>,
+    ) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+
+        let mut visitor = LogFileVisitor::default();
+        attrs.record(&mut visitor);
+        if let Some(path) = visitor.path {
+            // the build does not fail if its log file cannot be written, the
+            // log file is then missing from the build summary
+            if let Ok(log_file) = LogFile::open(Path::new(&path), visitor.append) {
+                span.extensions_mut().insert(log_file);
+            }
+        }
+
+        let mut s = Vec::new();
+        attrs.record(&mut CustomVisitor::new(&mut s));
+        let depth = span.scope().count() - 1;
+        let message = format!("── {}{}", span.name(), String::from_utf8_lossy(&s));
+        Self::write_lines(span.scope(), depth, attrs.metadata().level(), &message);
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let Some(scope) = ctx.event_scope(event) else {
+            return;
+        };
+        let spans = scope.collect::<Vec<_>>();
+        let depth = spans.len();
+
+        let mut s = Vec::new();
+        event.record(&mut CustomVisitor::new(&mut s));
+        Self::write_lines(
+            spans.into_iter(),
+            depth,
+            event.metadata().level(),
+            &String::from_utf8_lossy(&s),
+        );
+    }
+}
+
 /// A custom output handler for fancy logging.
 #[derive(Debug)]
 pub struct LoggingOutputHandler {
@@ -273,6 +399,7 @@ pub struct LoggingOutputHandler {
     wrap_lines: bool,
     progress_bars: MultiProgress,
     writer: io::Stderr,
+    profiler: Profiler,
 }
 
 impl Clone for LoggingOutputHandler {
@@ -282,6 +409,7 @@ impl Clone for LoggingOutputHandler {
             state: self.state.clone(),
             progress_bars: self.progress_bars.clone(),
             writer: io::stderr(),
+            profiler: self.profiler.clone(),
         }
     }
 }
@@ -294,6 +422,7 @@ impl Default for LoggingOutputHandler {
             state: Arc::new(Mutex::new(SharedState::default())),
             progress_bars: MultiProgress::new(),
             writer: io::stderr(),
+            profiler: Profiler::default(),
         }
     }
 }
@@ -307,6 +436,12 @@ impl LoggingOutputHandler {
         format!("{} {}", indent_str, template)
     }
 
+    /// The profiler that aggregates the time spent in the phases of the
+    /// builds.
+    pub fn profiler(&self) -> &Profiler {
+        &self.profiler
+    }
+
     /// Return the multi-progress instance.
     pub fn multi_progress(&self) -> &MultiProgress {
         &self.progress_bars
@@ -569,6 +704,7 @@ pub fn init_logging(
     verbosity: &Verbosity<InfoLevel>,
     color: &Color,
     wrap_lines: Option<bool>,
+    #[cfg(feature = "otlp")] otlp_endpoint: Option<&str>,
     #[cfg(feature = "tui")] tui_log_sender: Option<
         tokio::sync::mpsc::UnboundedSender<crate::tui::event::Event>,
     >,
@@ -594,9 +730,24 @@ pub fn init_logging(
         console::set_colors_enabled_stderr(use_colors);
     }
 
-    // Setup tracing subscriber
-    let registry =
-        tracing_subscriber::registry().with(get_default_env_filter(verbosity.log_level_filter())?);
+    // Setup tracing subscriber. The console layers are filtered by the
+    // verbosity, the log file always contains (at least) all info messages.
+    let console_filter = || get_default_env_filter(verbosity.log_level_filter());
+    let log_file_filter = get_default_env_filter(
+        verbosity
+            .log_level_filter()
+            .max(clap_verbosity_flag::log::LevelFilter::Info),
+    )?;
+    // The profiler also sees the `debug` spans of the phases that are not
+    // shown on the console
+    let registry = tracing_subscriber::registry()
+        .with(LogFileLayer.with_filter(log_file_filter))
+        .with(
+            log_handler
+                .profiler
+                .clone()
+                .with_filter(Targets::new().with_target("rattler_build", Level::DEBUG)),
+        );
 
     let log_style = if verbosity.log_level_filter() >= clap_verbosity_flag::log::LevelFilter::Debug
     {
@@ -605,7 +756,25 @@ pub fn init_logging(
         *log_style
     };
 
-    let registry = registry.with(GitHubActionsLayer(github_integration_enabled()));
+    let registry = registry
+        .with(GitHubActionsLayer(github_integration_enabled()).with_filter(console_filter()?));
+
+    #[cfg(feature = "otlp")]
+    let otlp_filter = console_filter()?;
+    #[cfg(feature = "otlp")]
+    let registry = registry.with(otlp_endpoint.and_then(|endpoint| {
+        crate::otlp::layer(endpoint)
+            .map_err(|e| {
+                eprintln!(
+                    "{} failed to set up the OTLP exporter for {}: {}",
+                    style("warning:").yellow(),
+                    endpoint,
+                    e
+                )
+            })
+            .ok()
+            .map(|layer| layer.with_filter(otlp_filter))
+    }));
 
     #[cfg(feature = "tui")]
     {
@@ -620,7 +789,8 @@ pub fn init_logging(
                         .with_writer(writer)
                         .without_time()
                         .with_level(false)
-                        .with_target(false),
+                        .with_target(false)
+                        .with_filter(console_filter()?),
                 )
                 .init();
             return Ok(log_handler);
@@ -629,21 +799,29 @@ pub fn init_logging(
 
     match log_style {
         LogStyle::Fancy => {
-            registry.with(log_handler.clone()).init();
+            registry
+                .with(log_handler.clone().with_filter(console_filter()?))
+                .init();
         }
         LogStyle::Plain => {
             registry
                 .with(
                     fmt::layer()
                         .with_writer(log_handler.clone())
-                        .event_format(TracingFormatter),
+                        .event_format(TracingFormatter)
+                        .with_filter(console_filter()?),
                 )
                 .init();
         }
         LogStyle::Json => {
             log_handler.set_progress_bars_hidden(true);
             registry
-                .with(fmt::layer().json().with_writer(io::stderr))
+                .with(
+                    fmt::layer()
+                        .json()
+                        .with_writer(io::stderr)
+                        .with_filter(console_filter()?),
+                )
                 .init();
         }
     }
@@ -0,0 +1,330 @@
+//! Compare the contents of two conda packages (`.conda` or `.tar.bz2`).
+//!
+//! The packages are extracted into temporary directories and compared file by
+//! file (size and SHA256 hash). For text files in `info/` (e.g. `index.json`,
+//! `paths.json` or `about.json`) a unified diff of the contents is produced.
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display, Formatter},
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+use rattler_digest::{compute_file_digest, Sha256};
+use serde::Serialize;
+
+/// The output format of the diff
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DiffFormat {
+    /// A human readable report with unified diffs of the metadata files
+    #[default]
+    Text,
+    /// A JSON document
+    Json,
+}
+
+/// Errors that can occur while comparing two packages
+#[derive(Debug, thiserror::Error)]
+pub enum DiffError {
+    /// An IO error occurred
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    /// The package could not be extracted
+    #[error("failed to extract package {0}: {1}")]
+    Extract(PathBuf, String),
+
+    /// Failed to walk the extracted package
+    #[error(transparent)]
+    WalkDir(#[from] walkdir::Error),
+}
+
+/// Size and hash of a single file in a package
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileInfo {
+    /// The size of the file in bytes
+    pub size: u64,
+    /// The hex encoded SHA256 hash of the file
+    pub sha256: String,
+}
+
+/// A file that is present in both packages but with different contents
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangedFile {
+    /// The path of the file relative to the package root
+    pub path: PathBuf,
+    /// The file in the old package
+    pub old: FileInfo,
+    /// The file in the new package
+    pub new: FileInfo,
+    /// A unified diff of the contents (only for text files in `info/`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diff: Option<String>,
+}
+
+/// The differences between two packages
+#[derive(Debug, Clone, Serialize)]
+pub struct PackageDiff {
+    /// The old package
+    pub old: PathBuf,
+    /// The new package
+    pub new: PathBuf,
+    /// Files that only exist in the new package
+    pub added: BTreeMap<PathBuf, FileInfo>,
+    /// Files that only exist in the old package
+    pub removed: BTreeMap<PathBuf, FileInfo>,
+    /// Files that exist in both packages but differ
+    pub changed: Vec<ChangedFile>,
+}
+
+impl PackageDiff {
+    /// Returns true if the two packages have identical contents
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl Display for PackageDiff {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "--- {}", self.old.display())?;
+        writeln!(f, "+++ {}", self.new.display())?;
+
+        if self.is_empty() {
+            return writeln!(f, "No differences found");
+        }
+
+        if !self.added.is_empty() {
+            writeln!(f, "\nAdded files:")?;
+            for (path, info) in &self.added {
+                writeln!(f, "  + {} ({} bytes)", path.display(), info.size)?;
+            }
+        }
+
+        if !self.removed.is_empty() {
+            writeln!(f, "\nRemoved files:")?;
+            for (path, info) in &self.removed {
+                writeln!(f, "  - {} ({} bytes)", path.display(), info.size)?;
+            }
+        }
+
+        if !self.changed.is_empty() {
+            writeln!(f, "\nChanged files:")?;
+            for changed in &self.changed {
+                writeln!(
+                    f,
+                    "  ~ {} (size: {} -> {}, sha256: {} -> {})",
+                    changed.path.display(),
+                    changed.old.size,
+                    changed.new.size,
+                    &changed.old.sha256[..12],
+                    &changed.new.sha256[..12],
+                )?;
+            }
+
+            for changed in &self.changed {
+                if let Some(diff) = &changed.diff {
+                    writeln!(f, "\n{}", diff.trim_end())?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Collect all files of an extracted package with their size and hash
+fn collect_files(root: &Path) -> Result<BTreeMap<PathBuf, FileInfo>, DiffError> {
+    let mut files = BTreeMap::new();
+    for entry in walkdir::WalkDir::new(root) {
+        let entry = entry?;
+        if entry.file_type().is_dir() {
+            continue;
+        }
+
+        let path = entry.path();
+        let relative = path.strip_prefix(root).expect("path is inside root");
+        let info = if entry.file_type().is_symlink() {
+            // compare symlinks by their target
+            let target = fs_err::read_link(path)?;
+            let target = target.to_string_lossy();
+            FileInfo {
+                size: target.len() as u64,
+                sha256: format!(
+                    "{:x}",
+                    rattler_digest::compute_bytes_digest::<Sha256>(target.as_bytes())
+                ),
+            }
+        } else {
+            FileInfo {
+                size: entry.metadata()?.len(),
+                sha256: format!("{:x}", compute_file_digest::<Sha256>(path)?),
+            }
+        };
+        files.insert(relative.to_path_buf(), info);
+    }
+    Ok(files)
+}
+
+/// Read a text file for diffing. JSON files are re-formatted so that
+/// differences show up line by line. Returns `None` for binary files.
+fn read_text(path: &Path) -> Result<Option<String>, DiffError> {
+    let content = fs_err::read(path)?;
+    if !content_inspector::inspect(&content).is_text() {
+        return Ok(None);
+    }
+    let text = String::from_utf8_lossy(&content).into_owned();
+
+    if path.extension().is_some_and(|ext| ext == "json") {
+        if let Ok(value) = serde_json::from_str::<serde_json::Value>(&text) {
+            return Ok(Some(format!(
+                "{}\n",
+                serde_json::to_string_pretty(&value).unwrap_or(text)
+            )));
+        }
+    }
+
+    Ok(Some(text))
+}
+
+/// Create a unified diff of a text file in `info/`
+fn text_diff(old_root: &Path, new_root: &Path, path: &Path) -> Result<Option<String>, DiffError> {
+    if !path.starts_with("info") {
+        return Ok(None);
+    }
+
+    let old = old_root.join(path);
+    let new = new_root.join(path);
+    if old.is_symlink() || new.is_symlink() {
+        return Ok(None);
+    }
+
+    let (Some(old), Some(new)) = (read_text(&old)?, read_text(&new)?) else {
+        return Ok(None);
+    };
+
+    let name = path.to_string_lossy().replace('\\', "/");
+    let patch = diffy::create_patch(&old, &new);
+    let diff = patch.to_string().replacen(
+        "--- original\n+++ modified\n",
+        &format!("--- a/{name}\n+++ b/{name}\n"),
+        1,
+    );
+    Ok(Some(diff))
+}
+
+/// Compare two directories that contain extracted packages
+pub fn diff_directories(old_root: &Path, new_root: &Path) -> Result<PackageDiff, DiffError> {
+    let mut old_files = collect_files(old_root)?;
+    let new_files = collect_files(new_root)?;
+
+    let mut added = BTreeMap::new();
+    let mut changed = Vec::new();
+    for (path, new) in new_files {
+        match old_files.remove(&path) {
+            None => {
+                added.insert(path, new);
+            }
+            Some(old) if old != new => {
+                let diff = text_diff(old_root, new_root, &path)?;
+                changed.push(ChangedFile {
+                    path,
+                    old,
+                    new,
+                    diff,
+                });
+            }
+            Some(_) => {}
+        }
+    }
+
+    Ok(PackageDiff {
+        old: old_root.to_path_buf(),
+        new: new_root.to_path_buf(),
+        added,
+        removed: old_files,
+        changed,
+    })
+}
+
+/// Extract both packages and compare their contents
+pub fn diff_packages(old: &Path, new: &Path) -> Result<PackageDiff, DiffError> {
+    let old_dir = tempfile::tempdir()?;
+    let new_dir = tempfile::tempdir()?;
+
+    for (package, dir) in [(old, &old_dir), (new, &new_dir)] {
+        rattler_package_streaming::fs::extract(package, dir.path())
+            .map_err(|e| DiffError::Extract(package.to_path_buf(), e.to_string()))?;
+    }
+
+    let diff = diff_directories(old_dir.path(), new_dir.path())?;
+
+    Ok(PackageDiff {
+        old: old.to_path_buf(),
+        new: new.to_path_buf(),
+        ..diff
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_directories() {
+        let old = tempfile::tempdir().unwrap();
+        let new = tempfile::tempdir().unwrap();
+
+        for dir in [old.path(), new.path()] {
+            fs_err::create_dir_all(dir.join("info")).unwrap();
+            fs_err::create_dir_all(dir.join("bin")).unwrap();
+            fs_err::write(dir.join("bin/same"), "same").unwrap();
+        }
+
+        fs_err::write(
+            old.path().join("info/index.json"),
+            r#"{"name": "foo", "version": "1.0"}"#,
+        )
+        .unwrap();
+        fs_err::write(
+            new.path().join("info/index.json"),
+            r#"{"name": "foo", "version": "2.0"}"#,
+        )
+        .unwrap();
+        fs_err::write(old.path().join("bin/removed"), "old").unwrap();
+        fs_err::write(new.path().join("bin/added"), "new").unwrap();
+        fs_err::write(old.path().join("bin/changed"), "a").unwrap();
+        fs_err::write(new.path().join("bin/changed"), "bb").unwrap();
+
+        let PackageDiff {
+            added,
+            removed,
+            changed,
+            ..
+        } = diff_directories(old.path(), new.path()).unwrap();
+        assert_eq!(
+            added.keys().collect::<Vec<_>>(),
+            vec![Path::new("bin/added")]
+        );
+        assert_eq!(
+            removed.keys().collect::<Vec<_>>(),
+            vec![Path::new("bin/removed")]
+        );
+        assert_eq!(changed.len(), 2);
+
+        let binary = changed
+            .iter()
+            .find(|c| c.path == Path::new("bin/changed"))
+            .unwrap();
+        assert_eq!((binary.old.size, binary.new.size), (1, 2));
+        assert!(binary.diff.is_none());
+
+        let index = changed
+            .iter()
+            .find(|c| c.path == Path::new("info/index.json"))
+            .unwrap();
+        let diff = index.diff.as_ref().unwrap();
+        assert!(diff.starts_with("--- a/info/index.json\n+++ b/info/index.json\n"));
+        assert!(diff.contains("-  \"version\": \"1.0\""));
+        assert!(diff.contains("+  \"version\": \"2.0\""));
+    }
+}
@@ -35,7 +35,8 @@ fn get_sitepackages_dir(prefix: &Path, platform: Platform, py_ver: &str) -> Path
 /// - `PY3K`: 1 if Python 3, 0 if Python 2
 /// - `PY_VER`: Python version (major.minor), e.g. 3.8
 /// - `STDLIB_DIR`: Python standard library directory
-/// - `SP_DIR`: Python site-packages directory
+/// - `SP_DIR`: Python site-packages directory (the `python_site_packages_path`
+///   of the python package, if set)
 /// - `NPY_VER`: NumPy version (major.minor), e.g. 1.19
 /// - `NPY_DISTUTILS_APPEND_FLAGS`: 1 (https://github.com/conda/conda-build/pull/3015)
 pub fn python_vars(output: &Output) -> HashMap<String, Option<String>> {
@@ -70,11 +71,15 @@ pub fn python_vars(output: &Output) -> HashMap<String, Option<String>> {
             output.host_platform().platform,
             &py_ver_str,
         );
-        let site_packages_dir = get_sitepackages_dir(
-            output.prefix(),
-            output.host_platform().platform,
-            &py_ver_str,
-        );
+        // interpreters like PyPy or GraalPy export their site-packages path
+        let site_packages_dir = match output.python_site_packages_path() {
+            Some(site_packages_path) => output.prefix().join(site_packages_path),
+            None => get_sitepackages_dir(
+                output.prefix(),
+                output.host_platform().platform,
+                &py_ver_str,
+            ),
+        };
         let py3k = if py_ver[0] == "3" { "1" } else { "0" };
         insert!(result, "PY3K", py3k);
         insert!(result, "PY_VER", py_ver_str);
@@ -183,6 +188,17 @@ pub fn os_vars(prefix: &Path, platform: &Platform) -> HashMap<String, Option<Str
     vars
 }
 
+/// Return the operating system specific environment variables that depend on
+/// the variant of the output (e.g. `MACOSX_DEPLOYMENT_TARGET`). These take
+/// precedence over the defaults from [`os_vars`].
+pub fn variant_os_vars(output: &Output) -> HashMap<String, Option<String>> {
+    if output.target_platform().is_osx() {
+        macos::env::variant_env_vars(&output.build_configuration.variant)
+    } else {
+        HashMap::new()
+    }
+}
+
 /// Set environment variables that help to force color output.
 fn force_color_vars() -> HashMap<String, Option<String>> {
     let mut vars = HashMap::new();
@@ -0,0 +1,280 @@
+//! Inspect and extract the contents of a conda package (`.conda` or
+//! `.tar.bz2`).
+//!
+//! The package is streamed entry by entry, so listing the files, extracting a
+//! subset of them (`--only 'info/*'`) or writing a single file to stdout does
+//! not require unpacking the whole package first.
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use rattler_conda_types::package::ArchiveType;
+use sha2::{Digest, Sha256};
+
+/// What to do with the files of the package
+#[derive(Debug, Clone, Copy)]
+pub enum ExtractMode<'a> {
+    /// Extract the files into the given directory
+    Extract(&'a Path),
+    /// Only list the files with their size and hash
+    List,
+    /// Return the content of a single file
+    Stdout,
+}
+
+/// Errors that can occur while inspecting or extracting a package
+#[derive(Debug, thiserror::Error)]
+pub enum ExtractError {
+    /// An IO error occurred
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    /// The file is not a conda package
+    #[error("not a conda package (expected `.conda` or `.tar.bz2`): {0}")]
+    NotAPackage(PathBuf),
+
+    /// The package could not be read
+    #[error("failed to read package {0}: {1}")]
+    ReadPackage(PathBuf, String),
+
+    /// An `--only` pattern is not a valid glob
+    #[error("invalid glob pattern: {0}")]
+    Glob(#[from] globset::Error),
+
+    /// Writing to stdout requires a single file
+    #[error("expected exactly one file to write to stdout, but {0} files matched (select the file with `--only`)")]
+    NotASingleFile(usize),
+}
+
+/// A file in the package
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListedFile {
+    /// The path of the file relative to the package root
+    pub path: PathBuf,
+    /// The size of the file in bytes
+    pub size: u64,
+    /// The hex encoded SHA256 hash of the file (not set for symlinks)
+    pub sha256: Option<String>,
+    /// The target of the symlink
+    pub link_target: Option<PathBuf>,
+}
+
+/// The result of processing a package
+#[derive(Debug, Default)]
+pub struct ExtractResult {
+    /// All files that matched the filter
+    pub files: Vec<ListedFile>,
+    /// The content of the (single) matched file in [`ExtractMode::Stdout`]
+    pub content: Option<Vec<u8>>,
+}
+
+/// Build the filter for the `--only` patterns. No patterns match all files.
+fn build_filter(patterns: &[String]) -> Result<Option<GlobSet>, ExtractError> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern)?);
+    }
+    Ok(Some(builder.build()?))
+}
+
+/// Process all entries of a tar archive
+fn process_archive<R: Read>(
+    mut archive: tar::Archive<R>,
+    filter: Option<&GlobSet>,
+    mode: ExtractMode,
+    result: &mut ExtractResult,
+) -> Result<(), ExtractError> {
+    archive.set_preserve_permissions(true);
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let entry_type = entry.header().entry_type();
+        if entry_type.is_dir() {
+            continue;
+        }
+
+        let path = entry.path()?.into_owned();
+        if filter.is_some_and(|filter| !filter.is_match(&path)) {
+            continue;
+        }
+
+        let link_target = entry.link_name()?.map(|target| target.into_owned());
+        let mut file = ListedFile {
+            path,
+            size: entry.size(),
+            sha256: None,
+            link_target,
+        };
+
+        match mode {
+            ExtractMode::Extract(dest) => {
+                entry.unpack_in(dest)?;
+            }
+            ExtractMode::List => {
+                if file.link_target.is_none() {
+                    let mut hasher = Sha256::new();
+                    io::copy(&mut entry, &mut hasher)?;
+                    file.sha256 = Some(format!("{:x}", hasher.finalize()));
+                }
+            }
+            ExtractMode::Stdout => {
+                if result.content.is_none() && file.link_target.is_none() {
+                    let mut content = Vec::with_capacity(file.size as usize);
+                    entry.read_to_end(&mut content)?;
+                    result.content = Some(content);
+                }
+            }
+        }
+
+        result.files.push(file);
+    }
+
+    Ok(())
+}
+
+/// Stream the package and list, extract or read the files that match the
+/// `only` patterns.
+pub fn extract_package(
+    package: &Path,
+    only: &[String],
+    mode: ExtractMode,
+) -> Result<ExtractResult, ExtractError> {
+    let filter = build_filter(only)?;
+    let archive_type =
+        ArchiveType::try_from(package).ok_or_else(|| ExtractError::NotAPackage(package.into()))?;
+    let read_error = |e: rattler_package_streaming::ExtractError| {
+        ExtractError::ReadPackage(package.to_path_buf(), e.to_string())
+    };
+
+    if let ExtractMode::Extract(dest) = mode {
+        fs_err::create_dir_all(dest)?;
+    }
+
+    let mut result = ExtractResult::default();
+    match archive_type {
+        ArchiveType::TarBz2 => {
+            let archive = rattler_package_streaming::read::stream_tar_bz2(File::open(package)?);
+            process_archive(archive, filter.as_ref(), mode, &mut result)?;
+        }
+        ArchiveType::Conda => {
+            let info = rattler_package_streaming::seek::stream_conda_info(File::open(package)?)
+                .map_err(read_error)?;
+            process_archive(info, filter.as_ref(), mode, &mut result)?;
+            let content =
+                rattler_package_streaming::seek::stream_conda_content(File::open(package)?)
+                    .map_err(read_error)?;
+            process_archive(content, filter.as_ref(), mode, &mut result)?;
+        }
+    }
+
+    if matches!(mode, ExtractMode::Stdout) && (result.files.len() != 1 || result.content.is_none())
+    {
+        return Err(ExtractError::NotASingleFile(result.files.len()));
+    }
+
+    Ok(result)
+}
+
+/// The default destination directory: the file name of the package without
+/// the archive extension
+pub fn default_destination(package: &Path) -> PathBuf {
+    let file_name = package
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    match ArchiveType::split_str(&file_name) {
+        Some((name, _)) => PathBuf::from(name),
+        None => PathBuf::from(file_name),
+    }
+}
+
+/// Print the files as a table with one line per file: the SHA256 hash, the
+/// size in bytes and the path (and the target for symlinks)
+pub fn print_listing(files: &[ListedFile], mut out: impl Write) -> io::Result<()> {
+    for file in files {
+        let path = file.path.to_string_lossy().replace('\\', "/");
+        match &file.link_target {
+            Some(target) => writeln!(
+                out,
+                "{:<64}  {:>12}  {} -> {}",
+                "-",
+                file.size,
+                path,
+                target.display()
+            )?,
+            None => writeln!(
+                out,
+                "{:<64}  {:>12}  {}",
+                file.sha256.as_deref().unwrap_or("-"),
+                file.size,
+                path
+            )?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_package(dir: &Path) -> PathBuf {
+        let package = dir.join("foo-1.0-h123_0.tar.bz2");
+        let file = File::create(&package).unwrap();
+        let encoder = bzip2::write::BzEncoder::new(file, bzip2::Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+        for (path, content) in [
+            ("info/index.json", "{\"name\": \"foo\"}"),
+            ("info/paths.json", "{}"),
+            ("bin/foo", "#!/bin/sh\necho foo\n"),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, path, content.as_bytes())
+                .unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+        package
+    }
+
+    #[test]
+    fn test_extract_package() {
+        let dir = tempfile::tempdir().unwrap();
+        let package = create_package(dir.path());
+
+        let listed = extract_package(&package, &[], ExtractMode::List).unwrap();
+        assert_eq!(listed.files.len(), 3);
+        assert!(listed.files.iter().all(|f| f.sha256.is_some()));
+
+        let dest = dir.path().join("extracted");
+        let only = vec!["info/*".to_string()];
+        let extracted = extract_package(&package, &only, ExtractMode::Extract(&dest)).unwrap();
+        assert_eq!(extracted.files.len(), 2);
+        assert!(dest.join("info/index.json").is_file());
+        assert!(!dest.join("bin/foo").exists());
+
+        let only = vec!["bin/foo".to_string()];
+        let single = extract_package(&package, &only, ExtractMode::Stdout).unwrap();
+        assert_eq!(single.content.unwrap(), b"#!/bin/sh\necho foo\n");
+
+        let only = vec!["info/*".to_string()];
+        assert!(matches!(
+            extract_package(&package, &only, ExtractMode::Stdout),
+            Err(ExtractError::NotASingleFile(2))
+        ));
+
+        assert_eq!(
+            default_destination(&package),
+            PathBuf::from("foo-1.0-h123_0")
+        );
+    }
+}
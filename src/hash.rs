@@ -1,5 +1,5 @@
 //! Compute the build string / hash info for a given variant
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
 use rattler_conda_types::NoArchType;
 use serde::{Deserialize, Serialize};
@@ -80,10 +80,48 @@ fn short_version_from_spec(input: &str, length: u32) -> String {
     result
 }
 
+/// The default number of characters of the sha1sum that are used for the hash
+pub const DEFAULT_HASH_LENGTH: usize = 7;
+
+/// The maximum number of characters of the hash (the length of a sha1 hex digest)
+pub const MAX_HASH_LENGTH: usize = 40;
+
+fn default_hash_length() -> usize {
+    DEFAULT_HASH_LENGTH
+}
+
+/// Configures how the hash of a variant is computed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HashConfig {
+    /// The number of characters of the sha1sum that are used for the hash
+    #[serde(default = "default_hash_length")]
+    pub length: usize,
+
+    /// Variant keys that are not part of the hash input
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub exclude_keys: BTreeSet<NormalizedKey>,
+}
+
+impl Default for HashConfig {
+    fn default() -> Self {
+        Self {
+            length: DEFAULT_HASH_LENGTH,
+            exclude_keys: BTreeSet::new(),
+        }
+    }
+}
+
+impl HashConfig {
+    /// Returns true if this is the default hash configuration
+    pub fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
 /// The hash info for a given variant
 #[derive(Debug, PartialEq, Clone, Eq, Hash, Serialize, Deserialize)]
 pub struct HashInfo {
-    /// The hash (first 7 letters of the sha1sum)
+    /// The hash (by default the first 7 letters of the sha1sum)
     pub hash: String,
 
     /// The hash prefix (e.g. `py38` or `np111`)
@@ -97,6 +135,20 @@ pub struct HashInput(String);
 impl HashInput {
     /// Create a new hash input from a variant
     pub fn from_variant(variant: &BTreeMap<NormalizedKey, String>) -> Self {
+        Self::from_variant_with_config(variant, &HashConfig::default())
+    }
+
+    /// Create a new hash input from a variant, leaving out the keys that are
+    /// excluded by the hash configuration
+    pub fn from_variant_with_config(
+        variant: &BTreeMap<NormalizedKey, String>,
+        config: &HashConfig,
+    ) -> Self {
+        let variant = variant
+            .iter()
+            .filter(|(key, _)| !config.exclude_keys.contains(key))
+            .collect::<BTreeMap<_, _>>();
+
         let mut buf = Vec::new();
         let mut ser = serde_json::Serializer::with_formatter(&mut buf, PythonFormatter {});
 
@@ -164,21 +216,30 @@ impl HashInfo {
         result
     }
 
-    fn hash_from_input(hash_input: &HashInput) -> String {
+    /// Compute the hash for the given hash input, truncated to `length` characters
+    pub fn hash_from_input(hash_input: &HashInput, length: usize) -> String {
         let mut hasher = Sha1::new();
         hasher.update(hash_input.as_bytes());
         let result = hasher.finalize();
 
-        const HASH_LENGTH: usize = 7;
-
         let res = format!("{:x}", result);
-        res[..HASH_LENGTH].to_string()
+        res[..length.clamp(1, MAX_HASH_LENGTH)].to_string()
     }
 
     /// Compute the build string for a given variant
     pub fn from_variant(variant: &BTreeMap<NormalizedKey, String>, noarch: &NoArchType) -> Self {
+        Self::from_variant_with_config(variant, noarch, &HashConfig::default())
+    }
+
+    /// Compute the build string for a given variant with a custom hash configuration
+    pub fn from_variant_with_config(
+        variant: &BTreeMap<NormalizedKey, String>,
+        noarch: &NoArchType,
+        config: &HashConfig,
+    ) -> Self {
+        let hash_input = HashInput::from_variant_with_config(variant, config);
         Self {
-            hash: Self::hash_from_input(&HashInput::from_variant(variant)),
+            hash: Self::hash_from_input(&hash_input, config.length),
             prefix: Self::hash_prefix(variant, noarch),
         }
     }
@@ -208,4 +269,41 @@ mod tests {
         let build_string_from_output = HashInfo::from_variant(&input, &NoArchType::none());
         assert_eq!(build_string_from_output.to_string(), "py311h507f6e9");
     }
+
+    #[test]
+    fn test_hash_config() {
+        let mut input = BTreeMap::new();
+        input.insert("python".into(), "3.11".to_string());
+        input.insert("target_platform".into(), "linux-64".to_string());
+        input.insert("channel_targets".into(), "conda-forge main".to_string());
+
+        let default = HashInfo::from_variant(&input, &NoArchType::none());
+        let config = HashConfig::default();
+        assert!(config.is_default());
+        assert_eq!(
+            HashInfo::from_variant_with_config(&input, &NoArchType::none(), &config),
+            default
+        );
+
+        let config = HashConfig {
+            length: 12,
+            ..Default::default()
+        };
+        let long = HashInfo::from_variant_with_config(&input, &NoArchType::none(), &config);
+        assert_eq!(long.hash.len(), 12);
+        assert!(long.hash.starts_with(&default.hash));
+
+        let config = HashConfig {
+            exclude_keys: ["channel-targets".into()].into_iter().collect(),
+            ..Default::default()
+        };
+        let hash_input = HashInput::from_variant_with_config(&input, &config);
+        assert_eq!(
+            hash_input.as_str(),
+            r#"{"python": "3.11", "target_platform": "linux-64"}"#
+        );
+        let excluded = HashInfo::from_variant_with_config(&input, &NoArchType::none(), &config);
+        assert_ne!(excluded.hash, default.hash);
+        assert_eq!(excluded.prefix, "py311");
+    }
 }
@@ -3,14 +3,27 @@
 //! rattler-build library.
 
 pub mod build;
+mod build_failures;
+pub mod build_profile;
+pub mod build_state;
+mod built_outputs;
 pub mod cache;
+pub mod cache_statistics;
+pub mod clobber;
 pub mod conda_build_config;
 pub mod console_utils;
+pub mod diff;
+pub mod extract;
 pub mod metadata;
+pub mod migration;
 mod normalized_key;
 pub mod opt;
+#[cfg(feature = "otlp")]
+pub mod otlp;
 pub mod package_test;
 pub mod packaging;
+pub mod project_config;
+pub mod provenance;
 pub mod recipe;
 pub mod render;
 pub mod script;
@@ -29,13 +42,18 @@ mod variant_render;
 mod consts;
 mod env_vars;
 pub mod hash;
+pub mod link_check;
 mod linux;
+pub mod lsp;
 mod macos;
 mod post_process;
 pub mod rebuild;
 #[cfg(feature = "recipe-generation")]
 pub mod recipe_generator;
 mod run_exports;
+pub mod self_test;
+pub mod serve;
+pub mod signing;
 mod unix;
 pub mod upload;
 mod windows;
@@ -43,35 +61,39 @@ mod windows;
 mod package_cache_reporter;
 
 use std::{
-    collections::{BTreeMap, HashMap},
+    collections::{BTreeMap, BTreeSet, HashMap},
     env::current_dir,
     path::{Path, PathBuf},
     sync::{Arc, Mutex},
 };
 
-use build::{run_build, skip_existing};
+use build::{run_build, run_dry_build, skip_existing};
+use build_state::BuildState;
 use console_utils::LoggingOutputHandler;
 use dunce::canonicalize;
 use fs_err as fs;
 use futures::FutureExt;
+use itertools::Itertools;
 use metadata::{
-    build_reindexed_channels, BuildConfiguration, BuildSummary, Directories, Output,
-    PackageIdentifier, PackagingSettings,
+    build_dir_name, build_reindexed_channels, log_file_path, BuildConfiguration, BuildSummary,
+    Directories, Output, PackageIdentifier, PackagingSettings,
 };
 use miette::{Context, IntoDiagnostic};
+use migration::Migration;
 use opt::*;
-use package_test::TestConfiguration;
-use petgraph::{algo::toposort, graph::DiGraph, visit::DfsPostOrder};
+use package_test::{KeepTestPrefixes, TestConfiguration};
 use rattler_conda_types::{
     package::ArchiveType, Channel, GenericVirtualPackage, MatchSpec, PackageName, Platform,
 };
 use rattler_solve::SolveStrategy;
 use rattler_virtual_packages::{VirtualPackage, VirtualPackageOverrides};
-use recipe::parser::{find_outputs_from_src, Dependency, TestType};
-use selectors::SelectorConfig;
+use recipe::parser::{find_outputs_from_recipe_file, ChannelPriority, Dependency, TestType};
+use render::cache::RenderCache;
+use selectors::{RepodataAccess, SelectorConfig};
+use source::cache::SourceCache;
 use system_tools::SystemTools;
 use tool_configuration::{Configuration, TestStrategy};
-use tracing::warn;
+use tracing::Instrument;
 use variant_config::VariantConfig;
 
 use crate::metadata::PlatformWithVirtualPackages;
@@ -133,15 +155,25 @@ pub fn get_tool_config(
 
     let configuration_builder = Configuration::builder()
         .with_keep_build(build_data.keep_build)
+        .with_resume(build_data.resume)
         .with_compression_threads(build_data.compression_threads)
+        .with_filename_template(build_data.filename_template.clone())
         .with_reqwest_client(client)
         .with_testing(!build_data.no_test)
         .with_test_strategy(build_data.test)
         .with_zstd_repodata_enabled(build_data.common.use_zstd)
         .with_bz2_repodata_enabled(build_data.common.use_zstd)
         .with_skip_existing(build_data.skip_existing)
+        .with_explain_cache(build_data.explain_cache)
         .with_noarch_build_platform(build_data.noarch_build_platform)
-        .with_channel_priority(build_data.common.channel_priority.value);
+        .with_channel_priority(build_data.common.channel_priority.value)
+        .with_dry_run(build_data.dry_run)
+        .with_continue_on_failure(build_data.continue_on_failure)
+        .with_clobber_check(build_data.clobber_check)
+        .with_hash_config(build_data.hash_config.clone())
+        .with_explain_conflicts(build_data.explain_conflicts)
+        .with_env_access(build_data.env_access.clone())
+        .with_cancellation_token(build_data.cancellation_token.clone());
 
     let configuration_builder = if let Some(fancy_log_handler) = fancy_log_handler {
         configuration_builder.with_logging_output_handler(fancy_log_handler.clone())
@@ -209,13 +241,24 @@ pub async fn get_build_output(
         experimental: build_data.common.experimental,
         // allow undefined while finding the variants
         allow_undefined: true,
+        strict_jinja: build_data.strict_jinja,
+        hash_config: tool_config.hash_config.clone(),
+        env_access: tool_config.env_access.clone(),
+        repodata_access: Some(RepodataAccess::new(
+            tool_config.repodata_gateway.clone(),
+            tool_config.channel_config.clone(),
+        )),
     };
 
     let span = tracing::info_span!("Finding outputs from recipe");
     let enter = span.enter();
 
     // First find all outputs from the recipe
-    let outputs = find_outputs_from_src(&recipe_text)?;
+    let outputs = find_outputs_from_recipe_file(
+        &recipe_text,
+        recipe_path.parent().unwrap_or(Path::new(".")),
+        build_data.common.experimental,
+    )?;
 
     // Check if there is a `variants.yaml` or `conda_build_config.yaml` file next to the
     // recipe that we should potentially use.
@@ -226,7 +269,16 @@ pub async fn get_build_output(
         consts::VARIANTS_CONFIG_FILE,
         consts::CONDA_BUILD_CONFIG_FILE,
     ] {
-        if let Some(variant_path) = recipe_path.parent().map(|parent| parent.join(file)) {
+        if let Some(mut variant_path) = recipe_path.parent().map(|parent| parent.join(file)) {
+            // a `variants-<platform>.yaml` file is also used without a `variants.yaml` file
+            // (otherwise it is loaded together with the `variants.yaml` file)
+            if let Some(platform_file) =
+                VariantConfig::platform_file(&variant_path, selector_config.target_platform)
+            {
+                if !variant_path.is_file() && platform_file.is_file() {
+                    variant_path = platform_file;
+                }
+            }
             if variant_path.is_file() {
                 if !build_data.ignore_recipe_variants {
                     let mut configs = build_data.variant_config.clone();
@@ -248,13 +300,62 @@ pub async fn get_build_output(
     let mut variant_configs = detected_variant_config.unwrap_or_default();
     variant_configs.extend(build_data.variant_config.clone());
 
-    let variant_config =
+    let mut variant_config =
         VariantConfig::from_files(&variant_configs, &selector_config).into_diagnostic()?;
+    for migration in &build_data.migrations {
+        tracing::info!("Applying migration: {:?}", migration);
+        Migration::from_file(migration, &selector_config)
+            .into_diagnostic()?
+            .apply(&mut variant_config);
+    }
 
-    let outputs_and_variants =
-        variant_config.find_variants(&outputs, &recipe_text, &selector_config)?;
+    let channel_sources = variant_config.channel_sources().into_diagnostic()?;
+
+    // `--render-only --render-cache` reuses the variants of an earlier render of
+    // the same recipe
+    let render_cache = if build_data.render_only && build_data.render_cache {
+        let key = RenderCache::key(
+            &recipe_text,
+            recipe_path.parent(),
+            &outputs,
+            &variant_config,
+            &selector_config,
+        );
+        RenderCache::default_cache().zip(key)
+    } else {
+        None
+    };
+    let cached = render_cache
+        .as_ref()
+        .and_then(|(cache, key)| cache.get(key, &outputs));
+    let outputs_and_variants = match cached {
+        Some(cached) => {
+            tracing::debug!("Using the cached variants of {}", recipe_path.display());
+            cached
+        }
+        None => {
+            let outputs_and_variants = variant_config.find_variants(
+                &outputs,
+                &recipe_text,
+                recipe_path.parent(),
+                &selector_config,
+            )?;
+            if let Some((cache, key)) = &render_cache {
+                cache.insert(key, &outputs_and_variants, &outputs);
+            }
+            outputs_and_variants
+        }
+    };
 
-    tracing::info!("Found {} variants\n", outputs_and_variants.len());
+    let skipped = outputs_and_variants
+        .iter()
+        .filter(|output| output.recipe.build().skip())
+        .count();
+    tracing::info!(
+        "Found {} variants ({} skipped)\n",
+        outputs_and_variants.len(),
+        skipped
+    );
     for discovered_output in &outputs_and_variants {
         tracing::info!(
             "Build variant: {}-{}-{}",
@@ -271,54 +372,163 @@ pub async fn get_build_output(
         for (key, value) in discovered_output.used_vars.iter() {
             table.add_row(vec![&key.normalize(), value]);
         }
+        if let Some(reason) = discovered_output.recipe.build().skip_reason() {
+            table.add_row(vec!["(skipped)".to_string(), reason.to_string()]);
+        }
         tracing::info!("\n{}\n", table);
     }
     drop(enter);
 
     let mut subpackages = BTreeMap::new();
     let mut outputs = Vec::new();
+    // The build directories by name, with the output that they belong to
+    let mut build_dirs: HashMap<String, (String, String, Option<String>)> = HashMap::new();
+
+    let built_outputs = if build_data.reuse_built_subpackages {
+        built_outputs::find_built_outputs(&output_dir)
+    } else {
+        Vec::new()
+    };
 
     let global_build_name = outputs_and_variants
         .first()
         .map(|o| o.name.clone())
         .unwrap_or_default();
+    let global_version = outputs_and_variants
+        .first()
+        .map(|o| o.version.clone())
+        .unwrap_or_default();
 
     for discovered_output in outputs_and_variants {
         let recipe = &discovered_output.recipe;
 
+        // Skipped variants are only kept for `--render-only`, so that the
+        // output shows why they are skipped
         if recipe.build().skip() {
-            tracing::info!(
-                "Skipping build for variant: {:#?}",
-                discovered_output.used_vars
+            if !build_data.render_only {
+                tracing::info!(
+                    "Skipping build for variant: {:#?}",
+                    discovered_output.used_vars
+                );
+                continue;
+            }
+        } else {
+            subpackages.insert(
+                recipe.package().name().clone(),
+                PackageIdentifier {
+                    name: recipe.package().name().clone(),
+                    version: recipe.package().version().version().clone(),
+                    build_string: discovered_output.build_string.clone(),
+                },
             );
-            continue;
         }
 
-        subpackages.insert(
-            recipe.package().name().clone(),
-            PackageIdentifier {
-                name: recipe.package().name().clone(),
-                version: recipe.package().version().version().clone(),
-                build_string: discovered_output.build_string.clone(),
-            },
+        // Outputs of a recipe with a cache share one build directory (the
+        // cache key contains the prefix), all other outputs get a directory
+        // per variant
+        let (build_name, version, variant_hash) = if recipe.cache.is_some() {
+            (global_build_name.clone(), global_version.clone(), None)
+        } else {
+            (
+                recipe.package().name().as_normalized().to_string(),
+                discovered_output.version.clone(),
+                Some(discovered_output.hash.to_string()),
+            )
+        };
+        let timestamp = chrono::Utc::now();
+        let identifier = format!(
+            "{}-{}-{}",
+            recipe.package().name().as_normalized(),
+            recipe.package().version(),
+            discovered_output.build_string
         );
-
-        let build_name = if recipe.cache.is_some() {
-            global_build_name.clone()
+        // `--resume` reuses the build directory of the interrupted build
+        let resumed_dir = if build_data.resume {
+            BuildState::find_build_dir(&output_dir.join("bld"), &identifier)
         } else {
-            recipe.package().name().as_normalized().to_string()
+            None
         };
+        let dir_name = match (resumed_dir, &build_data.build_id_format) {
+            (Some(dir), _) => dir
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            (None, Some(format)) => {
+                format.render(&build_name, &version, variant_hash.as_deref(), &timestamp)
+            }
+            (None, None) => build_dir_name(
+                &build_name,
+                variant_hash.as_deref(),
+                build_data.no_build_id,
+                &timestamp,
+            ),
+        };
+        // Only the outputs that share a cache build may share a build
+        // directory, e.g. `--build-id-format {name}` would mix the variants
+        let build_dir_owner = (build_name, version, variant_hash);
+        match build_dirs.get(&dir_name) {
+            Some(owner) if owner != &build_dir_owner => {
+                return Err(miette::miette!(
+                    "the build directory `{}` of {}-{}-{} is already used by another output, \
+                     use a build id format that is unique per output (e.g. `{{name}}-{{hash}}`)",
+                    dir_name,
+                    discovered_output.name,
+                    discovered_output.version,
+                    discovered_output.build_string
+                ));
+            }
+            Some(_) => {}
+            None => {
+                build_dirs.insert(dir_name.clone(), build_dir_owner);
+            }
+        }
 
-        // Add the channels from the args and by default always conda-forge
-        let channels = build_data
-            .channel
-            .clone()
-            .into_iter()
+        // Add the channels of the `channel_sources` variant and the channels
+        // from the args (by default always conda-forge)
+        let channels = channel_sources
+            .iter()
+            .chain(build_data.channel.iter())
+            .unique()
             .map(|c| Channel::from_str(c, &tool_config.channel_config).map(|c| c.base_url))
             .collect::<Result<Vec<_>, _>>()
             .into_diagnostic()?;
 
-        let timestamp = chrono::Utc::now();
+        // the channel priority of the recipe overrides the one of the variant
+        // configuration, which overrides the one of the tool
+        let channel_priority = match recipe.build().channel_priority() {
+            Some(channel_priority) => Some(channel_priority),
+            None => discovered_output
+                .used_vars
+                .get(&NormalizedKey::from("channel_priority"))
+                .map(|value| value.parse::<ChannelPriority>())
+                .transpose()
+                .map_err(|err| miette::miette!("invalid variant `channel_priority`: {}", err))?,
+        };
+
+        let mut output_subpackages = subpackages.clone();
+        built_outputs::add_built_subpackages(
+            &mut output_subpackages,
+            recipe
+                .requirements()
+                .all_pin_subpackage()
+                .map(|pin| &pin.name),
+            &built_outputs,
+            &discovered_output.used_vars,
+        );
+
+        let package_format = build_data
+            .package_format
+            .with_recipe_overrides(
+                recipe.build().package_format(),
+                recipe.build().compression(),
+            )
+            .map_err(|err| {
+                miette::miette!(
+                    "invalid package format of {}: {}",
+                    recipe.package().name().as_normalized(),
+                    err
+                )
+            })?;
 
         let output = metadata::Output {
             recipe: recipe.clone(),
@@ -333,33 +543,33 @@ pub async fn get_build_output(
                     virtual_packages: virtual_packages.clone(),
                 },
                 hash: discovered_output.hash.clone(),
+                hash_config: tool_config.hash_config.clone(),
+                env_access: tool_config.env_access.clone(),
                 variant: discovered_output.used_vars.clone(),
-                directories: Directories::setup(
-                    &build_name,
-                    recipe_path,
-                    &output_dir,
-                    build_data.no_build_id,
-                    &timestamp,
-                )
-                .into_diagnostic()?,
+                pin_run_as_build: variant_config.pin_run_as_build.clone().unwrap_or_default(),
+                directories: Directories::setup(&dir_name, recipe_path, &output_dir)
+                    .into_diagnostic()?,
                 channels,
-                channel_priority: tool_config.channel_priority,
+                channel_priority: channel_priority.map_or(tool_config.channel_priority, Into::into),
                 solve_strategy: SolveStrategy::Highest,
                 timestamp,
-                subpackages: subpackages.clone(),
+                subpackages: output_subpackages,
                 packaging_settings: PackagingSettings::from_args(
-                    build_data.package_format.archive_type,
-                    build_data.package_format.compression_level,
-                ),
+                    package_format.archive_type,
+                    package_format.compression_level,
+                )
+                .with_auto_compression(package_format.auto_compression),
                 store_recipe: !build_data.no_include_recipe,
                 force_colors: build_data.color_build_log && console::colors_enabled(),
                 sandbox_config: build_data.sandbox_configuration.clone(),
+                isolate_env: build_data.isolate_env,
+                log_file: Some(log_file_path(&output_dir, &identifier)),
             },
             finalized_dependencies: None,
             finalized_sources: None,
             finalized_cache_dependencies: None,
             finalized_cache_sources: None,
-            system_tools: SystemTools::new(),
+            system_tools: SystemTools::new().with_overrides(build_data.system_tools.clone()),
             build_summary: Arc::new(Mutex::new(BuildSummary::default())),
             extra_meta: Some(
                 build_data
@@ -369,6 +579,7 @@ pub async fn get_build_output(
                     .into_iter()
                     .collect(),
             ),
+            provenance: None,
         };
 
         outputs.push(output);
@@ -444,6 +655,9 @@ pub async fn run_build_from_args(
     let mut outputs = Vec::new();
     let mut test_queue = Vec::new();
 
+    let source_cache = build_output
+        .first()
+        .map(|o| SourceCache::new(&o.build_configuration.directories.output_dir));
     let outputs_to_build = skip_existing(build_output, &tool_configuration).await?;
 
     let all_output_names = outputs_to_build
@@ -451,17 +665,71 @@ pub async fn run_build_from_args(
         .map(|o| o.name())
         .collect::<Vec<_>>();
 
+    let mut failures = build_failures::BuildFailures::default();
+
     for (index, output) in outputs_to_build.iter().enumerate() {
+        if tool_configuration.cancellation_token.is_cancelled() {
+            return Err(miette::miette!("The build was cancelled"));
+        }
+
+        if let Some(dependency) = failures.failed_dependency(
+            output.name(),
+            output.variant(),
+            build_failures::dependency_names(output),
+        ) {
+            tracing::warn!(
+                "Skipping {} due to failed dependency {}",
+                output.identifier(),
+                dependency.as_normalized()
+            );
+            failures.record_skipped(
+                output.identifier(),
+                output.name().clone(),
+                output.variant().clone(),
+                dependency,
+            );
+            continue;
+        }
+
+        if tool_configuration.dry_run {
+            let output = run_dry_build(output.clone(), &tool_configuration)
+                .boxed_local()
+                .await?;
+            output.record_build_end();
+            outputs.push(output);
+            continue;
+        }
+
+        // start the profile of this output
+        let profiler = tool_configuration.fancy_log_handler.profiler();
+        profiler.take();
         let (output, archive) = match run_build(output.clone(), &tool_configuration)
             .boxed_local()
             .await
         {
             Ok((output, archive)) => {
                 output.record_build_end();
+                output.record_profile(profiler.take());
                 (output, archive)
             }
             Err(e) => {
-                return Err(e);
+                if let Some(log_file) = output.log_file() {
+                    tracing::error!(
+                        "The full log of the build was written to {}",
+                        log_file.display()
+                    );
+                }
+                if !tool_configuration.continue_on_failure {
+                    return Err(e);
+                }
+                tracing::error!("Failed to build {}: {:?}", output.identifier(), e);
+                failures.record_failed(
+                    output.identifier(),
+                    output.name().clone(),
+                    output.variant().clone(),
+                    e.to_string(),
+                );
+                continue;
             }
         };
 
@@ -471,8 +739,20 @@ pub async fn run_build_from_args(
         // all dependencies that are needed for the test are already built.
 
         // Decide whether the tests should be skipped or not
+        let noarch_platform = output.noarch_platform();
         let (skip_test, skip_test_reason) = match tool_configuration.test_strategy {
             TestStrategy::Skip => (true, "the argument --test=skip was set".to_string()),
+            _ if noarch_platform.is_some_and(|noarch_platform| {
+                !noarch_platform.matches(output.build_configuration.build_platform.platform)
+            }) =>
+            {
+                let reason = format!(
+                    "the package is built for `noarch_platform={}` and cannot be installed on {}",
+                    output.variant()[&NormalizedKey::from(selectors::NOARCH_PLATFORM_KEY)],
+                    output.build_configuration.build_platform.platform
+                );
+                (true, reason)
+            }
             TestStrategy::Native => {
                 // Skip if `host_platform != build_platform` and `target_platform != noarch`
                 if output.build_configuration.target_platform != Platform::NoArch
@@ -486,7 +766,21 @@ pub async fn run_build_from_args(
                     (false, "".to_string())
                 }
             }
-            TestStrategy::NativeAndEmulated => (false, "".to_string()),
+            TestStrategy::NativeAndEmulated => {
+                let host_platform = output.build_configuration.host_platform.platform;
+                let build_platform = output.build_configuration.build_platform.platform;
+                if output.build_configuration.target_platform == Platform::NoArch {
+                    (false, "".to_string())
+                } else {
+                    match package_test::check_emulation(build_platform, host_platform) {
+                        Ok(_) => (false, "".to_string()),
+                        Err(reason) => {
+                            let reason = format!("the argument --test=native-and-emulated was set and the {} package cannot be emulated on {}: {}", host_platform, build_platform, reason);
+                            (true, reason)
+                        }
+                    }
+                }
+            }
         };
         if skip_test {
             tracing::info!("Skipping tests because {}", skip_test_reason);
@@ -509,34 +803,14 @@ pub async fn run_build_from_args(
                 to_test
             };
 
-            // let testable = can_test(&test_queue, &all_output_names, &outputs_to_build);
-            for (output, archive) in &to_test {
-                package_test::run_test(
-                    archive,
-                    &TestConfiguration {
-                        test_prefix: output.build_configuration.directories.work_dir.join("test"),
-                        target_platform: Some(output.build_configuration.target_platform),
-                        host_platform: Some(output.build_configuration.host_platform.clone()),
-                        current_platform: output.build_configuration.build_platform.clone(),
-                        keep_test_prefix: tool_configuration.no_clean,
-                        channels: build_reindexed_channels(
-                            &output.build_configuration,
-                            &tool_configuration,
-                        )
-                        .into_diagnostic()
-                        .context("failed to reindex output channel")?,
-                        channel_priority: tool_configuration.channel_priority,
-                        solve_strategy: SolveStrategy::Highest,
-                        tool_configuration: tool_configuration.clone(),
-                    },
-                    None,
-                )
-                .await
-                .into_diagnostic()?;
-            }
+            test_outputs(&to_test, &tool_configuration, &mut failures).await?;
         }
     }
 
+    // outputs that were still waiting for their dependencies are tested now
+    // (this happens when the last outputs were skipped or failed)
+    let test_result = test_outputs(&test_queue, &tool_configuration, &mut failures).await;
+
     let span = tracing::info_span!("Build summary");
     let _enter = span.enter();
     for output in outputs {
@@ -547,6 +821,94 @@ pub async fn run_build_from_args(
         });
     }
 
+    tool_configuration
+        .cache_statistics
+        .log_summary(source_cache.as_ref());
+
+    test_result?;
+
+    if !failures.is_empty() {
+        failures.log_summary();
+        let summary = failures.summary();
+        return Err(miette::miette!(
+            "{} output(s) failed to build, {} output(s) failed their tests and {} output(s) were skipped due to failed dependencies",
+            summary.failed.len(),
+            summary.test_failed.len(),
+            summary.skipped.len()
+        ));
+    }
+
+    Ok(())
+}
+
+/// Run the tests of the given (built) outputs. With `--continue-on-failure`
+/// failed tests are recorded in `failures` instead of returning an error.
+async fn test_outputs(
+    to_test: &[(Output, PathBuf)],
+    tool_configuration: &Configuration,
+    failures: &mut build_failures::BuildFailures,
+) -> miette::Result<()> {
+    for (output, archive) in to_test {
+        if let Err(e) = test_output(output, archive, tool_configuration).await {
+            if !tool_configuration.continue_on_failure {
+                return Err(e);
+            }
+            tracing::error!("Failed to test {}: {:?}", output.identifier(), e);
+            failures.record_test_failed(output.identifier(), e.to_string());
+        }
+    }
+
+    Ok(())
+}
+
+/// Run the tests of a single (built) output
+async fn test_output(
+    output: &Output,
+    archive: &Path,
+    tool_configuration: &Configuration,
+) -> miette::Result<()> {
+    // append the test output to the log of the build
+    let span = tracing::info_span!(
+        "Running tests for",
+        recipe = output.identifier(),
+        log_file = output
+            .log_file()
+            .map(|path| tracing::field::display(path.display())),
+        append_log = true,
+    );
+    let _enter = span.enter();
+    let profiler = tool_configuration.fancy_log_handler.profiler();
+    profiler.take();
+    let skipped = package_test::run_test(
+        archive,
+        &TestConfiguration {
+            test_prefix: output.build_configuration.directories.work_dir.join("test"),
+            target_platform: Some(output.build_configuration.target_platform),
+            host_platform: Some(output.build_configuration.host_platform.clone()),
+            current_platform: output.build_configuration.build_platform.clone(),
+            keep_test_prefixes: if tool_configuration.no_clean {
+                KeepTestPrefixes::All
+            } else {
+                KeepTestPrefixes::None
+            },
+            channels: build_reindexed_channels(&output.build_configuration, tool_configuration)
+                .into_diagnostic()
+                .context("failed to reindex output channel")?,
+            channel_priority: output.build_configuration.channel_priority,
+            solve_strategy: SolveStrategy::Highest,
+            exclude_newer: None,
+            recipe_dir: Some(output.build_configuration.directories.recipe_dir.clone()),
+            tool_configuration: tool_configuration.clone(),
+        },
+        None,
+    )
+    .await
+    .into_diagnostic()?;
+    output.record_profile(profiler.take());
+    for test in skipped {
+        output.record_warning(&test.to_string());
+    }
+
     Ok(())
 }
 
@@ -584,8 +946,6 @@ pub async fn run_test_from_args(
     args: TestOpts,
     fancy_log_handler: LoggingOutputHandler,
 ) -> miette::Result<()> {
-    let package_file = canonicalize(args.package_file).into_diagnostic()?;
-
     // Determine virtual packages of the system. These packages define the
     // capabilities of the system. Some packages depend on these virtual
     // packages to indicate compatibility with the hardware of the system.
@@ -616,35 +976,111 @@ pub async fn run_test_from_args(
         .collect::<Result<Vec<_>, _>>()
         .into_diagnostic()?;
 
-    let tempdir = tempfile::tempdir().into_diagnostic()?;
+    let keep_test_prefixes = match args.keep_test_prefixes {
+        Some(keep) => keep,
+        None if args.keep_test_prefix => KeepTestPrefixes::All,
+        None => KeepTestPrefixes::None,
+    };
 
+    let tempdir = tempfile::tempdir().into_diagnostic()?;
+    // the prefix is created inside of the temporary directory, which is removed at the end
+    // unless a test prefix was kept
     let test_options = TestConfiguration {
-        test_prefix: tempdir.path().to_path_buf(),
+        test_prefix: tempdir.path().join("test"),
         target_platform: None,
         host_platform: None,
         current_platform,
-        keep_test_prefix: false,
+        keep_test_prefixes,
         channels,
         channel_priority: tool_config.channel_priority,
         solve_strategy: SolveStrategy::Highest,
+        exclude_newer: args.exclude_newer,
+        recipe_dir: None,
         tool_configuration: tool_config,
     };
 
+    if let Some(pattern) = &args.all_packages {
+        let packages = package_test::bulk::find_packages(pattern).into_diagnostic()?;
+        if packages.is_empty() {
+            return Err(miette::miette!("No packages found in {}", pattern));
+        }
+        tracing::info!("Testing {} packages", packages.len());
+
+        let results = package_test::bulk::run_tests(&packages, &test_options).await;
+        keep_retained_test_prefixes(tempdir, &test_options.test_prefix);
+        let results = results.into_diagnostic()?;
+        if let Some(junit_report) = &args.junit_report {
+            fs::write(junit_report, package_test::bulk::junit_report(&results))
+                .into_diagnostic()?;
+        }
+
+        let failed = results
+            .iter()
+            .filter(|r| !matches!(r.outcome, package_test::bulk::TestOutcome::Passed(_)))
+            .map(|r| r.package.as_str())
+            .collect::<Vec<_>>();
+        if !failed.is_empty() {
+            return Err(miette::miette!(
+                "{} of {} packages failed or were not tested: {}",
+                failed.len(),
+                results.len(),
+                failed.join(", ")
+            ));
+        }
+        return Ok(());
+    }
+
+    let package_file = args
+        .package_file
+        .ok_or_else(|| miette::miette!("No package file given"))?;
+    let package_file = canonicalize(package_file).into_diagnostic()?;
     let package_name = package_file
         .file_name()
         .ok_or_else(|| miette::miette!("Could not get file name from package file"))?
         .to_string_lossy()
         .to_string();
 
+    // instrument the future instead of entering the span, so that the future
+    // stays `Send` (it is spawned by the Python bindings)
     let span = tracing::info_span!("Running tests for", package = %package_name);
-    let _enter = span.enter();
-    package_test::run_test(&package_file, &test_options, None)
-        .await
-        .into_diagnostic()?;
+    let start = std::time::Instant::now();
+    let result = package_test::run_test(&package_file, &test_options, None)
+        .instrument(span)
+        .await;
+    keep_retained_test_prefixes(tempdir, &test_options.test_prefix);
+
+    if let Some(junit_report) = &args.junit_report {
+        let outcome = match &result {
+            Ok(skipped) => package_test::bulk::TestOutcome::Passed(skipped.clone()),
+            Err(e) => package_test::bulk::TestOutcome::Failed(e.to_string()),
+        };
+        let result = package_test::bulk::PackageTestResult {
+            package: package_name,
+            subdir: test_options
+                .target_platform
+                .map(|p| p.to_string())
+                .unwrap_or_default(),
+            duration: start.elapsed(),
+            outcome,
+        };
+        fs::write(junit_report, package_test::bulk::junit_report(&[result])).into_diagnostic()?;
+    }
+    result.into_diagnostic()?;
 
     Ok(())
 }
 
+/// Keeps the temporary directory of the test prefixes if a test prefix was
+/// retained in it, otherwise it is removed when `tempdir` is dropped.
+fn keep_retained_test_prefixes(tempdir: tempfile::TempDir, test_prefix: &Path) {
+    let retained = fs::read_dir(test_prefix)
+        .map(|mut entries| entries.next().is_some())
+        .unwrap_or(false);
+    if retained {
+        let _ = tempdir.into_path();
+    }
+}
+
 /// Rebuild.
 pub async fn rebuild_from_args(
     args: RebuildOpts,
@@ -679,6 +1115,10 @@ pub async fn rebuild_from_args(
     fs::create_dir_all(&output_dir).into_diagnostic()?;
     output.build_configuration.directories.output_dir =
         canonicalize(output_dir).into_diagnostic()?;
+    output.build_configuration.log_file = Some(log_file_path(
+        &output.build_configuration.directories.output_dir,
+        &output.identifier(),
+    ));
 
     let tool_config = Configuration::builder()
         .with_logging_output_handler(fancy_log_handler)
@@ -705,6 +1145,212 @@ pub async fn rebuild_from_args(
     Ok(())
 }
 
+/// Compare two packages and print the differences
+pub fn diff_from_args(args: DiffOpts) -> miette::Result<()> {
+    let diff = diff::diff_packages(&args.old, &args.new).into_diagnostic()?;
+
+    match args.format {
+        diff::DiffFormat::Text => print!("{}", diff),
+        diff::DiffFormat::Json => {
+            println!("{}", serde_json::to_string_pretty(&diff).into_diagnostic()?)
+        }
+    }
+
+    Ok(())
+}
+
+/// Print or write the JSON schema of the recipe format
+pub fn schema_from_args(args: SchemaOpts) -> miette::Result<()> {
+    let schema =
+        serde_json::to_string_pretty(&recipe::parser::recipe_schema()).into_diagnostic()?;
+    match args.output {
+        Some(path) => {
+            fs::write(&path, schema).into_diagnostic()?;
+            tracing::info!("Wrote the recipe schema to {}", path.display());
+        }
+        None => println!("{}", schema),
+    }
+    Ok(())
+}
+
+/// Report the unsupported constructs of `conda_build_config.yaml` files
+pub fn check_conda_build_config_from_args(args: CheckCondaBuildConfigOpts) -> miette::Result<()> {
+    let platform = args.target_platform.unwrap_or_else(Platform::current);
+    let selector_config = SelectorConfig {
+        target_platform: platform,
+        host_platform: platform,
+        build_platform: Platform::current(),
+        ..Default::default()
+    };
+
+    let mut reports = BTreeMap::new();
+    for file in &args.files {
+        let issues = conda_build_config::compatibility_report(file, &selector_config)?;
+        reports.insert(file.display().to_string(), issues);
+    }
+
+    if args.json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&reports).into_diagnostic()?
+        );
+        return Ok(());
+    }
+
+    let mut total = 0;
+    for (file, issues) in &reports {
+        for issue in issues {
+            match issue.line {
+                Some(line) => println!("{file}:{line}: {}\n    {}", issue.message, issue.content),
+                None => println!("{file}: {}", issue.message),
+            }
+        }
+        total += issues.len();
+    }
+
+    let affected = reports.values().filter(|issues| !issues.is_empty()).count();
+    if total == 0 {
+        println!(
+            "No unsupported constructs found in {} file(s)",
+            reports.len()
+        );
+    } else {
+        println!(
+            "Found {total} unsupported construct(s) in {affected} of {} file(s)",
+            reports.len()
+        );
+    }
+    Ok(())
+}
+
+/// Format recipe files, or check that they are formatted
+pub fn fmt_from_args(args: FmtOpts) -> miette::Result<()> {
+    let mut unformatted = Vec::new();
+    for recipe in &args.recipes {
+        let path = get_recipe_path(recipe)?;
+        let src = fs::read_to_string(&path).into_diagnostic()?;
+        let formatted = recipe::formatter::format_recipe(&src)
+            .map_err(miette::Report::new)
+            .wrap_err_with(|| format!("failed to format {}", path.display()))?;
+        if formatted == src {
+            continue;
+        }
+        if args.check {
+            println!("{} is not formatted", path.display());
+        } else {
+            fs::write(&path, formatted).into_diagnostic()?;
+            tracing::info!("Formatted {}", path.display());
+        }
+        unformatted.push(path);
+    }
+
+    if args.check && !unformatted.is_empty() {
+        return Err(miette::miette!(
+            "{} recipe(s) are not formatted, run `rattler-build fmt` to format them",
+            unformatted.len()
+        ));
+    }
+    Ok(())
+}
+
+/// Run the language server for recipe files on stdin / stdout
+pub fn lsp_from_args() -> miette::Result<()> {
+    let stdin = std::io::stdin();
+    let stdout = std::io::stdout();
+    lsp::run(&mut stdin.lock(), &mut stdout.lock()).into_diagnostic()
+}
+
+/// Run the experimental HTTP server for recipes
+pub fn serve_from_args(args: ServeOpts) -> miette::Result<()> {
+    if !args.experimental {
+        miette::bail!(
+            "`rattler-build serve` is experimental, provide the `--experimental` flag to enable it"
+        );
+    }
+    let listener = std::net::TcpListener::bind(args.address)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("failed to listen on {}", args.address))?;
+    tracing::info!("Listening on http://{}", args.address);
+    let config = serve::ServeConfig {
+        allowed_hosts: args.allowed_hosts,
+        experimental: args.experimental,
+        threads: args.threads.unwrap_or_else(|| {
+            std::thread::available_parallelism().map_or(4, std::num::NonZeroUsize::get)
+        }),
+    };
+    serve::run(listener, config).into_diagnostic()
+}
+
+/// Sign the packages of a local channel or generate a new signing key
+pub fn sign_from_args(args: SignOpts) -> miette::Result<()> {
+    if args.generate_key {
+        if args.sign_key.exists() {
+            return Err(miette::miette!(
+                "the key file {} already exists",
+                args.sign_key.display()
+            ));
+        }
+        let key = signing::SigningKey::generate();
+        key.write_pem(&args.sign_key).into_diagnostic()?;
+        tracing::info!("Wrote a new signing key to {}", args.sign_key.display());
+        println!("{}", key.public_key());
+        return Ok(());
+    }
+
+    let key = signing::SigningKey::from_file(&args.sign_key).into_diagnostic()?;
+    let channel = args
+        .channel
+        .expect("clap requires a channel without --generate-key");
+    let count = signing::sign_channel(&channel, &key).into_diagnostic()?;
+    tracing::info!(
+        "Signed {} package(s) in {} with public key {}",
+        count,
+        channel.display(),
+        key.public_key()
+    );
+    Ok(())
+}
+
+/// List, extract or print the files of a package
+pub fn extract_from_args(args: ExtractOpts) -> miette::Result<()> {
+    use std::io::Write;
+
+    let dest = args
+        .dest
+        .clone()
+        .unwrap_or_else(|| extract::default_destination(&args.package_file));
+    let mode = if args.list {
+        extract::ExtractMode::List
+    } else if args.to_stdout {
+        extract::ExtractMode::Stdout
+    } else {
+        extract::ExtractMode::Extract(&dest)
+    };
+
+    let result =
+        extract::extract_package(&args.package_file, &args.only, mode).into_diagnostic()?;
+
+    match mode {
+        extract::ExtractMode::List => {
+            extract::print_listing(&result.files, std::io::stdout().lock()).into_diagnostic()?
+        }
+        extract::ExtractMode::Stdout => {
+            let content = result.content.unwrap_or_default();
+            std::io::stdout()
+                .lock()
+                .write_all(&content)
+                .into_diagnostic()?
+        }
+        extract::ExtractMode::Extract(dest) => tracing::info!(
+            "Extracted {} files to {}",
+            result.files.len(),
+            dest.display()
+        ),
+    }
+
+    Ok(())
+}
+
 /// Upload.
 pub async fn upload_from_args(args: UploadOpts) -> miette::Result<()> {
     if args.package_files.is_empty() {
@@ -720,152 +1366,143 @@ pub async fn upload_from_args(args: UploadOpts) -> miette::Result<()> {
         }
     }
 
-    let store = tool_configuration::get_auth_store(args.common.auth_file).into_diagnostic()?;
-
-    match args.server_type {
-        ServerType::Quetz(quetz_opts) => {
-            upload::upload_package_to_quetz(
-                &store,
-                quetz_opts.api_key,
-                &args.package_files,
-                quetz_opts.url.into(),
-                quetz_opts.channel,
-            )
-            .await
+    let store =
+        tool_configuration::get_auth_store(args.common.auth_file.clone()).into_diagnostic()?;
+
+    let mut backend = match (args.server_type, args.to) {
+        (Some(server_type), None) => upload::backend::backend_from_server_type(server_type)?,
+        (None, Some(to)) => {
+            let backend = args
+                .backend
+                .as_deref()
+                .unwrap_or_else(|| upload::default_backend_for_url(&to));
+            upload::create_upload_backend(backend, to)?
         }
-        ServerType::Artifactory(artifactory_opts) => {
-            let token = match (
-                artifactory_opts.username,
-                artifactory_opts.password,
-                artifactory_opts.token,
-            ) {
-                (_, _, Some(token)) => Some(token),
-                (Some(_), Some(password), _) => {
-                    warn!("Using username and password for Artifactory authentication is deprecated, using password as token. Please use an API token instead.");
-                    Some(password)
-                }
-                (Some(_), None, _) => {
-                    return Err(miette::miette!(
-                        "Artifactory username provided without a password"
-                    ));
-                }
-                (None, Some(_), _) => {
-                    return Err(miette::miette!(
-                        "Artifactory password provided without a username"
-                    ));
-                }
-                _ => None,
-            };
-            upload::upload_package_to_artifactory(
-                &store,
-                token,
-                &args.package_files,
-                artifactory_opts.url.into(),
-                artifactory_opts.channel,
-            )
-            .await
-        }
-        ServerType::Prefix(prefix_opts) => {
-            upload::upload_package_to_prefix(
-                &store,
-                prefix_opts.api_key,
-                &args.package_files,
-                prefix_opts.url.into(),
-                prefix_opts.channel,
-            )
-            .await
-        }
-        ServerType::Anaconda(anaconda_opts) => {
-            upload::upload_package_to_anaconda(
-                &store,
-                anaconda_opts.api_key,
-                &args.package_files,
-                anaconda_opts.url.into(),
-                anaconda_opts.owner,
-                anaconda_opts.channel,
-                anaconda_opts.force,
-            )
-            .await
+        (Some(_), Some(_)) => {
+            return Err(miette::miette!(
+                "`--to` cannot be combined with a server type"
+            ));
         }
-        ServerType::CondaForge(conda_forge_opts) => {
-            upload::conda_forge::upload_packages_to_conda_forge(
-                conda_forge_opts,
-                &args.package_files,
-            )
-            .await
+        (None, None) => {
+            return Err(miette::miette!(
+                "Either a server type or `--to <URL>` is required"
+            ));
         }
+    };
+
+    if let Some(sign_key) = &args.sign_key {
+        let key = signing::SigningKey::from_file(sign_key).into_diagnostic()?;
+        backend.set_signing_key(key)?;
+    }
+
+    if args.dry_run {
+        let client = tool_configuration::reqwest_client_from_auth_storage(args.common.auth_file)
+            .into_diagnostic()?;
+        let tool_config = tool_configuration::Configuration::builder()
+            .with_reqwest_client(client)
+            .finish();
+        return upload::plan::dry_run(backend.as_ref(), &args.package_files, &tool_config).await;
     }
+
+    tracing::debug!("Uploading with the {} backend", backend.name());
+    backend.upload(&store, &args.package_files).await
 }
 
 /// Sort the build outputs (recipes) topologically based on their dependencies.
+/// Among the outputs whose dependencies are built, the output that comes first
+/// is built first, so that the original order (e.g. from `prioritize_variants`
+/// in the variant config) is only a tie-breaker inside the topological order.
 pub fn sort_build_outputs_topologically(
     outputs: &mut Vec<Output>,
     up_to: Option<&str>,
 ) -> miette::Result<()> {
-    let mut graph = DiGraph::<usize, ()>::new();
-    let mut name_to_index = HashMap::new();
-
-    // Index outputs by their produced names for quick lookup
+    // Index outputs by their produced names for quick lookup (a package can
+    // have multiple variants)
+    let mut name_to_indices = HashMap::<&PackageName, Vec<usize>>::new();
     for (idx, output) in outputs.iter().enumerate() {
-        let idx = graph.add_node(idx);
-        name_to_index.insert(output.name().clone(), idx);
-    }
-
-    // Add edges based on dependencies
-    for output in outputs.iter() {
-        let output_idx = *name_to_index
-            .get(output.name())
-            .expect("We just inserted it");
-        for dep in output.recipe.requirements().run_build_host() {
-            let dep_name = match dep {
-                Dependency::Spec(spec) => spec
-                    .name
-                    .clone()
-                    .expect("MatchSpec should always have a name"),
-                Dependency::PinSubpackage(pin) => pin.pin_value().name.clone(),
-                Dependency::PinCompatible(pin) => pin.pin_value().name.clone(),
-            };
+        name_to_indices.entry(output.name()).or_default().push(idx);
+    }
 
-            if let Some(&dep_idx) = name_to_index.get(&dep_name) {
-                // do not point to self (circular dependency) - this can happen with
-                // pin_subpackage in run_exports, for example.
-                if output_idx == dep_idx {
-                    continue;
-                }
-                graph.add_edge(output_idx, dep_idx, ());
+    // The outputs that have to be built before each output
+    let dependencies = outputs
+        .iter()
+        .map(|output| {
+            output
+                .recipe
+                .requirements()
+                .run_build_host()
+                .map(|dep| match dep {
+                    Dependency::Spec(spec) => spec
+                        .name
+                        .clone()
+                        .expect("MatchSpec should always have a name"),
+                    Dependency::PinSubpackage(pin) => pin.pin_value().name.clone(),
+                    Dependency::PinCompatible(pin) => pin.pin_value().name.clone(),
+                })
+                // do not point to self (circular dependency) - this can happen
+                // with pin_subpackage in run_exports, for example.
+                .filter(|dep_name| dep_name != output.name())
+                .filter_map(|dep_name| name_to_indices.get(&dep_name))
+                .flatten()
+                .copied()
+                .collect::<BTreeSet<usize>>()
+        })
+        .collect::<Vec<_>>();
+
+    // The outputs to build: all of them, or the "up-to" package and everything
+    // it depends on
+    let mut selected = vec![up_to.is_none(); outputs.len()];
+    if let Some(up_to) = up_to {
+        let mut stack = name_to_indices
+            .iter()
+            .find(|(name, _)| name.as_normalized() == up_to)
+            .map(|(_, indices)| indices.clone())
+            .ok_or_else(|| {
+                miette::miette!("The package '{}' was not found in the outputs", up_to)
+            })?;
+        while let Some(idx) = stack.pop() {
+            if !selected[idx] {
+                selected[idx] = true;
+                stack.extend(dependencies[idx].iter().copied());
             }
         }
     }
 
-    let sorted_indices = if let Some(up_to) = up_to {
-        // Find the node index for the "up-to" package
-        let up_to_index = name_to_index.get(up_to).copied().ok_or_else(|| {
-            miette::miette!("The package '{}' was not found in the outputs", up_to)
-        })?;
-
-        // Perform a DFS post-order traversal from the "up-to" node to find all
-        // dependencies
-        let mut dfs = DfsPostOrder::new(&graph, up_to_index);
-        let mut sorted_indices = Vec::new();
-        while let Some(nx) = dfs.next(&graph) {
-            sorted_indices.push(nx);
+    // Kahn's algorithm, taking the first ready output in the original order
+    let mut missing = dependencies.iter().map(BTreeSet::len).collect::<Vec<_>>();
+    let mut dependents = vec![Vec::new(); outputs.len()];
+    for (idx, deps) in dependencies.iter().enumerate() {
+        for &dep in deps {
+            dependents[dep].push(idx);
         }
+    }
+    let mut ready = (0..outputs.len())
+        .filter(|&idx| selected[idx] && missing[idx] == 0)
+        .collect::<BTreeSet<_>>();
+    let mut sorted_indices = Vec::new();
+    while let Some(idx) = ready.pop_first() {
+        sorted_indices.push(idx);
+        for &dependent in &dependents[idx] {
+            missing[dependent] -= 1;
+            if selected[dependent] && missing[dependent] == 0 {
+                ready.insert(dependent);
+            }
+        }
+    }
 
-        sorted_indices
-    } else {
-        // Perform topological sort
-        let mut sorted_indices = toposort(&graph, None).map_err(|cycle| {
-            let node = cycle.node_id();
-            let name = outputs[node.index()].name();
-            miette::miette!("Cycle detected in dependencies: {}", name.as_source())
-        })?;
-        sorted_indices.reverse();
-        sorted_indices
-    };
+    if sorted_indices.len() != selected.iter().filter(|selected| **selected).count() {
+        let idx = (0..outputs.len())
+            .find(|&idx| selected[idx] && missing[idx] > 0)
+            .expect("an output is not sorted");
+        return Err(miette::miette!(
+            "Cycle detected in dependencies: {}",
+            outputs[idx].name().as_source()
+        ));
+    }
 
     sorted_indices
         .iter()
-        .map(|idx| &outputs[idx.index()])
+        .map(|idx| &outputs[*idx])
         .for_each(|output| {
             tracing::debug!("Ordered output: {:?}", output.name().as_normalized());
         });
@@ -873,7 +1510,7 @@ pub fn sort_build_outputs_topologically(
     // Reorder outputs based on the sorted indices
     *outputs = sorted_indices
         .iter()
-        .map(|node| outputs[node.index()].clone())
+        .map(|idx| outputs[*idx].clone())
         .collect();
 
     Ok(())
@@ -884,6 +1521,238 @@ pub fn get_rattler_build_version() -> &'static str {
     env!("CARGO_PKG_VERSION")
 }
 
+/// Render rattler-build recipes into their outputs. If `with_solve` is set in
+/// the build data, the dependencies of all outputs are resolved as well.
+pub async fn render_recipes(
+    recipe_paths: &[PathBuf],
+    build_data: &BuildData,
+    tool_config: &Configuration,
+) -> miette::Result<Vec<Output>> {
+    let mut outputs = Vec::new();
+    for recipe_path in recipe_paths {
+        let output = get_build_output(build_data, recipe_path, tool_config).await?;
+        outputs.extend(output);
+    }
+
+    if recipe_paths.len() > 1 {
+        resolve_sibling_subpackages(&mut outputs);
+    }
+
+    if !build_data.with_solve {
+        return Ok(outputs);
+    }
+
+    let mut updated_outputs = Vec::new();
+    for output in outputs {
+        if output.recipe.build().skip() {
+            updated_outputs.push(output);
+            continue;
+        }
+        updated_outputs.push(
+            output
+                .resolve_dependencies(tool_config)
+                .await
+                .into_diagnostic()?,
+        );
+    }
+    Ok(updated_outputs)
+}
+
+/// Resolve the `pin_subpackage` references to outputs of other recipes that
+/// were rendered together (e.g. all recipes of a `--recipe-dir`) and report
+/// which output each pin resolves to.
+fn resolve_sibling_subpackages(outputs: &mut [Output]) {
+    let siblings = outputs
+        .iter()
+        .filter(|output| !output.recipe.build().skip())
+        .map(|output| built_outputs::SiblingOutput {
+            identifier: PackageIdentifier {
+                name: output.name().clone(),
+                version: output.version().clone(),
+                build_string: output.build_string().into_owned(),
+            },
+            target_platform: output.build_configuration.target_platform,
+            variant: output.build_configuration.variant.clone(),
+            recipe_path: output.build_configuration.directories.recipe_path.clone(),
+        })
+        .collect::<Vec<_>>();
+
+    for output in outputs.iter_mut() {
+        if output.recipe.build().skip() {
+            continue;
+        }
+
+        let mut subpackages = output.build_configuration.subpackages.clone();
+        let lookups = built_outputs::add_sibling_subpackages(
+            &mut subpackages,
+            output
+                .recipe
+                .requirements()
+                .all_pin_subpackage()
+                .map(|pin| &pin.name),
+            &siblings,
+            &output.build_configuration.directories.recipe_path,
+            output.build_configuration.target_platform,
+            &output.build_configuration.variant,
+        );
+
+        for (name, sibling) in lookups {
+            match sibling {
+                Some(sibling) => tracing::info!(
+                    "{}: pin_subpackage({}) resolved to {}-{}-{} from {}",
+                    output.identifier(),
+                    name.as_normalized(),
+                    sibling.identifier.name.as_normalized(),
+                    sibling.identifier.version,
+                    sibling.identifier.build_string,
+                    sibling.recipe_path.display()
+                ),
+                None => tracing::warn!(
+                    "{}: pin_subpackage({}) does not match any rendered output",
+                    output.identifier(),
+                    name.as_normalized()
+                ),
+            }
+        }
+        output.build_configuration.subpackages = subpackages;
+    }
+}
+
+/// Print how the hash of every output of the given recipes is computed: the
+/// hash input, the variant keys that were excluded from it, the resulting hash
+/// and the final build string.
+pub async fn explain_hash(
+    recipe_paths: Vec<PathBuf>,
+    build_data: BuildData,
+    log_handler: &Option<console_utils::LoggingOutputHandler>,
+) -> miette::Result<()> {
+    let tool_config = get_tool_config(&build_data, log_handler)?;
+    let outputs = render_recipes(&recipe_paths, &build_data, &tool_config).await?;
+
+    for output in outputs {
+        let build_configuration = &output.build_configuration;
+        let hash_config = &build_configuration.hash_config;
+        let excluded_keys = build_configuration
+            .variant
+            .keys()
+            .filter(|key| hash_config.exclude_keys.contains(key))
+            .map(|key| key.normalize())
+            .collect::<Vec<_>>();
+
+        println!("{}", output.identifier());
+        println!("  hash input:    {}", output.hash_input().as_str());
+        if !excluded_keys.is_empty() {
+            println!("  excluded keys: {}", excluded_keys.join(", "));
+        }
+        println!("  hash length:   {}", hash_config.length);
+        println!("  hash:          {}", build_configuration.hash);
+        println!("  build string:  {}", output.build_string());
+    }
+
+    Ok(())
+}
+
+/// Render the given recipes for every platform and compare the rendered
+/// outputs with the golden snapshots (or write them when `update` is set).
+pub async fn self_test(
+    recipe_paths: Vec<PathBuf>,
+    build_data: BuildData,
+    platforms: Vec<Platform>,
+    snapshot_dir: Option<PathBuf>,
+    update: bool,
+    log_handler: &Option<console_utils::LoggingOutputHandler>,
+) -> miette::Result<()> {
+    let tool_config = get_tool_config(&build_data, log_handler)?;
+    let platforms = if platforms.is_empty() {
+        vec![build_data.target_platform]
+    } else {
+        platforms
+    };
+
+    // Group the rendered snapshots by the directory they are stored in
+    let mut rendered: BTreeMap<PathBuf, BTreeMap<PathBuf, String>> = BTreeMap::new();
+    for recipe_path in &recipe_paths {
+        let dir = snapshot_dir.clone().unwrap_or_else(|| {
+            recipe_path
+                .parent()
+                .unwrap_or(Path::new("."))
+                .join(self_test::DEFAULT_SNAPSHOT_DIR)
+        });
+        let snapshots = rendered.entry(dir).or_default();
+
+        for platform in &platforms {
+            let mut build_data = build_data.clone();
+            build_data.target_platform = *platform;
+            build_data.host_platform = *platform;
+
+            let outputs =
+                render_recipes(std::slice::from_ref(recipe_path), &build_data, &tool_config)
+                    .await?;
+            for output in &outputs {
+                snapshots.insert(
+                    self_test::snapshot_path(output),
+                    self_test::render_snapshot(output).into_diagnostic()?,
+                );
+            }
+        }
+    }
+
+    let mut failed = 0;
+    for (dir, snapshots) in &rendered {
+        if update {
+            self_test::update_snapshots(dir, &platforms, snapshots).into_diagnostic()?;
+            tracing::info!(
+                "Updated {} snapshot(s) in {}",
+                snapshots.len(),
+                dir.display()
+            );
+            continue;
+        }
+
+        let mismatches =
+            self_test::compare_snapshots(dir, &platforms, snapshots).into_diagnostic()?;
+        if mismatches.is_empty() {
+            tracing::info!("{} snapshot(s) in {} match", snapshots.len(), dir.display());
+            continue;
+        }
+
+        println!("{}:", dir.display());
+        for mismatch in &mismatches {
+            println!("{}", mismatch);
+        }
+        failed += mismatches.len();
+    }
+
+    if failed > 0 {
+        miette::bail!(
+            "{} snapshot(s) differ from the rendered recipes, run with `--update` to accept the changes",
+            failed
+        );
+    }
+
+    Ok(())
+}
+
+/// Check the links in the `about` section of the outputs and fail if any of
+/// them is broken.
+async fn check_about_links(outputs: &[Output], tool_config: &Configuration) -> miette::Result<()> {
+    let broken = link_check::check_links(outputs, &tool_config.client).await;
+    if broken.is_empty() {
+        tracing::info!("All links in the about sections resolve");
+        return Ok(());
+    }
+
+    Err(miette::miette!(
+        "Found {} broken link(s) in the about section:\n{}",
+        broken.len(),
+        broken
+            .iter()
+            .map(|link| format!("  - {link}"))
+            .collect::<Vec<_>>()
+            .join("\n")
+    ))
+}
+
 /// Build rattler-build recipes
 pub async fn build_recipes(
     recipe_paths: Vec<std::path::PathBuf>,
@@ -891,40 +1760,58 @@ pub async fn build_recipes(
     log_handler: &Option<console_utils::LoggingOutputHandler>,
 ) -> Result<(), miette::Error> {
     let tool_config = get_tool_config(&build_data, log_handler)?;
-    let mut outputs = Vec::new();
-    for recipe_path in &recipe_paths {
-        let output = get_build_output(&build_data, recipe_path, &tool_config).await?;
-        outputs.extend(output);
-    }
 
-    if build_data.render_only {
-        let outputs = if build_data.with_solve {
-            let mut updated_outputs = Vec::new();
-            for output in outputs {
-                updated_outputs.push(
-                    output
-                        .resolve_dependencies(&tool_config)
-                        .await
-                        .into_diagnostic()?,
-                );
-            }
-            updated_outputs
-        } else {
-            outputs
-        };
+    // Read the signing key before building so that a broken key fails early
+    let signing_key = build_data
+        .sign_key
+        .as_deref()
+        .map(signing::SigningKey::from_file)
+        .transpose()
+        .into_diagnostic()?;
 
+    if build_data.render_only {
+        let outputs = render_recipes(&recipe_paths, &build_data, &tool_config).await?;
+        if build_data.check_links {
+            check_about_links(&outputs, &tool_config).await?;
+        }
         println!(
             "{}",
-            serde_json::to_string_pretty(&outputs).into_diagnostic()?
+            Output::format_rendered(&outputs, build_data.output_format).into_diagnostic()?
         );
         return Ok(());
     }
 
+    let mut outputs = Vec::new();
+    for recipe_path in &recipe_paths {
+        let output = get_build_output(&build_data, recipe_path, &tool_config).await?;
+        outputs.extend(output);
+    }
+
+    if build_data.check_links {
+        check_about_links(&outputs, &tool_config).await?;
+    }
+
     // Skip noarch builds before the topological sort
     outputs = skip_noarch(outputs, &tool_config).await?;
 
     sort_build_outputs_topologically(&mut outputs, build_data.up_to.as_deref())?;
+    let output_dir = outputs
+        .first()
+        .map(|output| output.build_configuration.directories.output_dir.clone());
+    let dry_run = tool_config.dry_run;
     run_build_from_args(outputs, tool_config).await?;
 
+    if let (Some(key), Some(output_dir), false) = (signing_key, output_dir, dry_run) {
+        // Sign after all packages are built, because every reindex of the
+        // output directory rewrites the repodata
+        let count = signing::sign_channel(&output_dir, &key).into_diagnostic()?;
+        tracing::info!(
+            "Signed {} package(s) in {} with public key {}",
+            count,
+            output_dir.display(),
+            key.public_key()
+        );
+    }
+
     Ok(())
 }
@@ -0,0 +1,220 @@
+//! Validation of the links in the `about` section of a recipe.
+//!
+//! The homepage, documentation and repository URLs end up in the metadata of
+//! the package and are shown by package indexes. A typo in one of them is
+//! easy to miss and hard to fix once the package is published, so with
+//! `--check-links` every link is validated before the build starts: first
+//! offline (scheme, host and the shape of repository URLs) and then by sending
+//! a `HEAD` request to make sure the link resolves. The offline validation is
+//! also part of the diagnostics of `rattler-build lsp` (and `serve`), where
+//! problems are reported as warnings.
+use std::{
+    collections::BTreeSet,
+    fmt::{self, Display, Formatter},
+};
+
+use futures::future::join_all;
+use reqwest::{Method, StatusCode};
+use reqwest_middleware::ClientWithMiddleware;
+use url::Url;
+
+use crate::{metadata::Output, recipe::parser::About};
+
+/// Hosts that serve repositories at `https://<host>/<owner>/<repository>`
+const REPOSITORY_HOSTS: &[&str] = &["github.com", "gitlab.com", "bitbucket.org", "codeberg.org"];
+
+/// A link in the `about` section of an output
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct AboutLink {
+    /// The name of the package the link belongs to
+    pub package: String,
+    /// The key of the link in the `about` section
+    pub field: &'static str,
+    /// The link itself
+    pub url: Url,
+}
+
+/// A link that failed validation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BrokenLink {
+    /// The link that failed validation
+    pub link: AboutLink,
+    /// Why the link is considered broken
+    pub reason: String,
+}
+
+impl Display for BrokenLink {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: about.{} `{}`: {}",
+            self.link.package, self.link.field, self.link.url, self.reason
+        )
+    }
+}
+
+/// Returns the links of an `about` section that are checked
+pub fn links_of(about: &About) -> impl Iterator<Item = (&'static str, &Url)> {
+    [
+        ("homepage", about.homepage.as_ref()),
+        ("documentation", about.documentation.as_ref()),
+        ("repository", about.repository.as_ref()),
+    ]
+    .into_iter()
+    .filter_map(|(field, url)| url.map(|url| (field, url)))
+}
+
+/// Collect the links of all outputs. Outputs that are rendered for multiple
+/// variants share their links, so every link is only returned once per
+/// package.
+pub fn about_links(outputs: &[Output]) -> Vec<AboutLink> {
+    outputs
+        .iter()
+        .flat_map(|output| {
+            let package = output.name().as_normalized().to_string();
+            links_of(&output.recipe.about).map(move |(field, url)| AboutLink {
+                package: package.clone(),
+                field,
+                url: url.clone(),
+            })
+        })
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+/// Validate a link without network access
+pub fn validate_link(link: &AboutLink) -> Result<(), String> {
+    let url = &link.url;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!(
+            "unsupported scheme `{}` (expected `http` or `https`)",
+            url.scheme()
+        ));
+    }
+
+    let Some(host) = url.host_str() else {
+        return Err("the URL has no host".to_string());
+    };
+    if url.domain().is_some() && host != "localhost" && !host.contains('.') {
+        return Err(format!("the host `{host}` has no top-level domain"));
+    }
+
+    if link.field == "repository" {
+        let host = host.strip_prefix("www.").unwrap_or(host);
+        if REPOSITORY_HOSTS.contains(&host) {
+            let segments = url
+                .path_segments()
+                .map(|segments| segments.filter(|s| !s.is_empty()).count())
+                .unwrap_or(0);
+            if segments < 2 {
+                return Err(format!(
+                    "expected a link to a repository (`https://{host}/<owner>/<repository>`)"
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Check that a URL resolves. Servers that do not support `HEAD` requests are
+/// retried with a `GET` request.
+pub async fn check_url(client: &ClientWithMiddleware, url: &Url) -> Result<(), String> {
+    let mut status = None;
+    for method in [Method::HEAD, Method::GET] {
+        let response = client
+            .request(method, url.clone())
+            .send()
+            .await
+            .map_err(|e| format!("request failed: {e}"))?;
+
+        let response_status = response.status();
+        status = Some(response_status);
+        if response_status != StatusCode::METHOD_NOT_ALLOWED
+            && response_status != StatusCode::NOT_IMPLEMENTED
+        {
+            break;
+        }
+    }
+
+    match status {
+        Some(status) if status.is_client_error() || status.is_server_error() => {
+            Err(format!("the server responded with `{status}`"))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Validate all links of the outputs and return the broken ones. Every distinct
+/// URL is requested only once.
+pub async fn check_links(outputs: &[Output], client: &ClientWithMiddleware) -> Vec<BrokenLink> {
+    let links = about_links(outputs);
+
+    let mut broken = Vec::new();
+    let mut urls = BTreeSet::new();
+    for link in &links {
+        match validate_link(link) {
+            Ok(()) => {
+                urls.insert(link.url.clone());
+            }
+            Err(reason) => broken.push(BrokenLink {
+                link: link.clone(),
+                reason,
+            }),
+        }
+    }
+
+    let results = join_all(urls.iter().map(|url| async move {
+        tracing::info!("Checking link {url}");
+        (url, check_url(client, url).await)
+    }))
+    .await;
+
+    for (url, result) in results {
+        if let Err(reason) = result {
+            broken.extend(
+                links
+                    .iter()
+                    .filter(|link| &link.url == url)
+                    .map(|link| BrokenLink {
+                        link: link.clone(),
+                        reason: reason.clone(),
+                    }),
+            );
+        }
+    }
+
+    broken
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(field: &'static str, url: &str) -> AboutLink {
+        AboutLink {
+            package: "foo".to_string(),
+            field,
+            url: Url::parse(url).unwrap(),
+        }
+    }
+
+    #[test]
+    fn validate_links() {
+        assert!(validate_link(&link("homepage", "https://example.com")).is_ok());
+        assert!(validate_link(&link("homepage", "http://localhost:8000/docs")).is_ok());
+        assert!(validate_link(&link("documentation", "https://127.0.0.1/docs")).is_ok());
+        assert!(validate_link(&link("repository", "https://github.com/foo/bar")).is_ok());
+        assert!(validate_link(&link("repository", "https://git.example.com/bar")).is_ok());
+
+        let err = validate_link(&link("homepage", "htps://example.com")).unwrap_err();
+        assert!(err.contains("unsupported scheme `htps`"));
+        let err = validate_link(&link("homepage", "https://examplecom/foo")).unwrap_err();
+        assert!(err.contains("no top-level domain"));
+        let err = validate_link(&link("repository", "https://github.com/foo")).unwrap_err();
+        assert!(err.contains("expected a link to a repository"));
+        // The repository shape is only checked for the repository link
+        assert!(validate_link(&link("homepage", "https://github.com/foo")).is_ok());
+    }
+}
@@ -0,0 +1,137 @@
+//! Verify that ELF files do not require a newer glibc than the one that is
+//! targeted by the build.
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt::Write,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use goblin::elf::Elf;
+use rattler_conda_types::Version;
+
+use crate::{
+    metadata::Output, normalized_key::NormalizedKey, post_process::checks::LinkingCheckError,
+};
+
+/// Get the targeted glibc version. The `c_stdlib_version` variant is used if
+/// the `c_stdlib` is `sysroot` (or not set), otherwise the version of the
+/// `__glibc` virtual package of the host platform.
+pub fn glibc_target(output: &Output) -> Option<Version> {
+    let variant = &output.build_configuration.variant;
+    let stdlib = variant.get(&NormalizedKey::from("c_stdlib"));
+    if stdlib.map_or(true, |stdlib| stdlib == "sysroot") {
+        if let Some(version) = variant.get(&NormalizedKey::from("c_stdlib_version")) {
+            return Version::from_str(version).ok();
+        }
+    }
+
+    output
+        .build_configuration
+        .host_platform
+        .virtual_packages
+        .iter()
+        .find(|vpkg| vpkg.name.as_normalized() == "__glibc")
+        .map(|vpkg| vpkg.version.clone())
+}
+
+/// Returns all imported symbols of an ELF file that require a versioned glibc
+/// symbol (e.g. `memcpy@GLIBC_2.14`), together with the required version.
+pub fn glibc_symbol_versions(path: &Path) -> Result<Vec<(String, Version)>, LinkingCheckError> {
+    let data = fs_err::read(path)?;
+    let elf = Elf::parse(&data)
+        .map_err(|e| LinkingCheckError::SharedObject(format!("{}: {e}", path.display())))?;
+
+    let (Some(verneed), Some(versym)) = (&elf.verneed, &elf.versym) else {
+        return Ok(Vec::new());
+    };
+
+    // map the version index to the glibc version that it refers to
+    let mut glibc_versions = HashMap::new();
+    for need in verneed.iter() {
+        for aux in need.iter() {
+            let Some(name) = elf.dynstrtab.get_at(aux.vna_name) else {
+                continue;
+            };
+            // skips e.g. `GLIBC_PRIVATE`
+            if let Some(Ok(version)) = name.strip_prefix("GLIBC_").map(Version::from_str) {
+                glibc_versions.insert(aux.vna_other, version);
+            }
+        }
+    }
+
+    let mut symbols = Vec::new();
+    for (idx, sym) in elf.dynsyms.iter().enumerate() {
+        if !sym.is_import() {
+            continue;
+        }
+        let Some(version) = versym
+            .get_at(idx)
+            .and_then(|versym| glibc_versions.get(&versym.version()))
+        else {
+            continue;
+        };
+        if let Some(name) = elf.dynstrtab.get_at(sym.st_name) {
+            symbols.push((name.to_string(), version.clone()));
+        }
+    }
+
+    Ok(symbols)
+}
+
+/// Verify that none of the given binaries references a glibc symbol version
+/// that is newer than the targeted glibc version. All offending symbols are
+/// reported per binary.
+pub fn check_glibc_version(
+    output: &Output,
+    binaries: &HashSet<PathBuf>,
+    tmp_prefix: &Path,
+) -> Result<(), LinkingCheckError> {
+    let Some(glibc_version) = glibc_target(output) else {
+        return Ok(());
+    };
+
+    let mut violations = BTreeMap::new();
+    for binary in binaries {
+        let offending = glibc_symbol_versions(binary)?
+            .into_iter()
+            .filter(|(_, version)| version > &glibc_version)
+            .collect::<BTreeSet<_>>();
+
+        if !offending.is_empty() {
+            let file = binary.strip_prefix(tmp_prefix).unwrap_or(binary);
+            violations.insert(file.to_path_buf(), offending);
+        }
+    }
+
+    if violations.is_empty() {
+        return Ok(());
+    }
+
+    let mut report = String::new();
+    for (file, symbols) in &violations {
+        writeln!(report, "{}:", file.display()).unwrap();
+        for (symbol, version) in symbols {
+            writeln!(report, "  - {symbol} (GLIBC_{version})").unwrap();
+        }
+    }
+    Err(LinkingCheckError::GlibcVersion {
+        glibc_version: glibc_version.to_string(),
+        report,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glibc_symbol_versions() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("test-data/binary_files/zlink");
+        let symbols = glibc_symbol_versions(&path).unwrap();
+        assert!(symbols.contains(&("printf".to_string(), Version::from_str("2.2.5").unwrap())));
+        assert!(symbols
+            .iter()
+            .all(|(_, version)| version <= &Version::from_str("2.17").unwrap()));
+    }
+}
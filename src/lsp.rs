@@ -0,0 +1,863 @@
+//! A language server for `recipe.yaml` files (`rattler-build lsp`).
+//!
+//! The server speaks JSON-RPC over stdin / stdout and provides:
+//!
+//! - diagnostics from the recipe parser (with the `include`d recipe
+//!   fragments), with the suggested fixes of the parser as quick fixes (code
+//!   actions), and warnings about malformed links in the `about` section
+//! - hover documentation of the keys of the recipe
+//! - completion of the known fields (from the recipe schema), `if / then /
+//!   else` selectors and Jinja variables (selector variables, `context`
+//!   variables and the keys of a `variants.yaml` next to the recipe)
+//!
+//! The text of open documents is synced in full on every change.
+use std::{
+    collections::{BTreeSet, HashMap},
+    io::{self, BufRead, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use serde_json::{json, Value};
+use url::Url;
+
+use crate::{
+    _partialerror,
+    link_check::{links_of, validate_link, AboutLink},
+    recipe::{
+        custom_yaml::{parse_yaml, HasSpan, Node},
+        error::ErrorKind,
+        parser::{
+            find_outputs_from_recipe_file, find_outputs_from_src, included_files, recipe_schema,
+        },
+        ParsingError, Recipe,
+    },
+    selectors::SelectorConfig,
+};
+
+/// The error code for requests with an unknown method
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// The variant config files that are read for the completion of variables
+const VARIANT_FILES: [&str; 2] = ["variants.yaml", "conda_build_config.yaml"];
+
+/// Errors of the language server
+#[derive(Debug, thiserror::Error)]
+pub enum LspError {
+    /// An IO error occurred
+    #[error(transparent)]
+    Io(#[from] io::Error),
+
+    /// A message of the client could not be read
+    #[error("invalid message: {0}")]
+    InvalidMessage(String),
+}
+
+/// Read a message (`Content-Length` header and JSON body) from the client.
+/// Returns `None` at the end of the input.
+pub fn read_message(reader: &mut impl BufRead) -> Result<Option<Value>, LspError> {
+    let mut content_length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = Some(
+                    value
+                        .trim()
+                        .parse::<usize>()
+                        .map_err(|e| LspError::InvalidMessage(e.to_string()))?,
+                );
+            }
+        }
+    }
+
+    let length = content_length
+        .ok_or_else(|| LspError::InvalidMessage("missing Content-Length header".to_string()))?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| LspError::InvalidMessage(e.to_string()))
+}
+
+/// Write a message to the client
+pub fn write_message(writer: &mut impl Write, message: &Value) -> Result<(), LspError> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Run the language server until the client sends `exit` or closes the input
+pub fn run(reader: &mut impl BufRead, writer: &mut impl Write) -> Result<(), LspError> {
+    let mut server = Server::default();
+    while let Some(message) = read_message(reader)? {
+        if message["method"] == "exit" {
+            break;
+        }
+        for response in server.handle(&message) {
+            write_message(writer, &response)?;
+        }
+    }
+    Ok(())
+}
+
+/// The state of the language server: the text of the open documents
+#[derive(Debug, Default)]
+pub struct Server {
+    documents: HashMap<String, String>,
+}
+
+impl Server {
+    /// Handle a message of the client and return the messages to send back
+    /// (responses and notifications).
+    pub fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let Some(method) = message["method"].as_str() else {
+            // a response of the client to a request of the server
+            return Vec::new();
+        };
+        let id = message.get("id").cloned();
+        let params = &message["params"];
+        let uri = params["textDocument"]["uri"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+
+        let result = match method {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "hoverProvider": true,
+                    "completionProvider": { "triggerCharacters": [" ", "{"] },
+                    "codeActionProvider": true
+                },
+                "serverInfo": {
+                    "name": "rattler-build",
+                    "version": env!("CARGO_PKG_VERSION")
+                }
+            }),
+            "shutdown" => Value::Null,
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.documents.insert(uri.clone(), text.to_string());
+                return vec![publish_diagnostics(&uri, text)];
+            }
+            "textDocument/didChange" => {
+                let changes = params["contentChanges"].as_array();
+                let Some(text) = changes
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str())
+                else {
+                    return Vec::new();
+                };
+                self.documents.insert(uri.clone(), text.to_string());
+                return vec![publish_diagnostics(&uri, text)];
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                return vec![notification(
+                    "textDocument/publishDiagnostics",
+                    json!({ "uri": uri, "diagnostics": [] }),
+                )];
+            }
+            "textDocument/hover" => self
+                .documents
+                .get(&uri)
+                .and_then(|text| hover(text, &params["position"]))
+                .unwrap_or(Value::Null),
+            "textDocument/completion" => self
+                .documents
+                .get(&uri)
+                .map(|text| completion(&uri, text, &params["position"]))
+                .unwrap_or_else(|| json!([])),
+            "textDocument/codeAction" => code_actions(&uri, params),
+            _ => {
+                return match id {
+                    Some(id) => vec![json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": {
+                            "code": METHOD_NOT_FOUND,
+                            "message": format!("unknown method `{method}`")
+                        }
+                    })],
+                    // unknown notifications are ignored
+                    None => Vec::new(),
+                };
+            }
+        };
+
+        match id {
+            Some(id) => vec![json!({ "jsonrpc": "2.0", "id": id, "result": result })],
+            None => Vec::new(),
+        }
+    }
+}
+
+/// A notification to the client
+fn notification(method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
+
+/// The position (line and UTF-16 character) of a byte offset in the text
+fn position(text: &str, offset: usize) -> Value {
+    let mut offset = offset.min(text.len());
+    while !text.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+    json!({
+        "line": before.matches('\n').count(),
+        "character": before[line_start..].encode_utf16().count()
+    })
+}
+
+/// The line and the byte offset in the line of a position, `None` if the
+/// position is after the end of the text
+fn line_at<'a>(text: &'a str, position: &Value) -> Option<(usize, &'a str, usize)> {
+    let line_index = position["line"].as_u64()? as usize;
+    let character = position["character"].as_u64()? as usize;
+    let line = text.split('\n').nth(line_index)?.trim_end_matches('\r');
+
+    let mut utf16 = 0;
+    let column = line
+        .char_indices()
+        .find(|(_, c)| {
+            let found = utf16 >= character;
+            utf16 += c.len_utf16();
+            found
+        })
+        .map_or(line.len(), |(index, _)| index);
+    Some((line_index, line, column))
+}
+
+/// Parse the recipe and return the errors of all outputs, and the warnings
+/// about the links in the `about` section of the outputs that parse. The
+/// `include`s of the recipe are resolved relative to `recipe_dir` (if any).
+fn parse_errors(recipe_dir: Option<&Path>, text: &str) -> (Vec<ParsingError>, Vec<ParsingError>) {
+    let selector_config = SelectorConfig {
+        experimental: true,
+        allow_undefined: true,
+        ..SelectorConfig::default()
+    };
+    let outputs = match recipe_dir {
+        Some(recipe_dir) => find_outputs_from_recipe_file(text, recipe_dir, true),
+        None => find_outputs_from_src(text),
+    };
+    let outputs = match outputs {
+        Ok(outputs) => outputs,
+        Err(err) => return (vec![err], Vec::new()),
+    };
+
+    let mut errors = Vec::new();
+    let mut warnings = Vec::new();
+    for output in &outputs {
+        match Recipe::from_node(output, selector_config.clone()) {
+            Ok(recipe) => warnings.extend(link_warnings(text, output, &recipe)),
+            Err(errs) => errors.extend(ParsingError::from_partial_vec(text, errs)),
+        }
+    }
+    (errors, warnings)
+}
+
+/// Validate the links in the `about` section of an output without network
+/// access (the same validation as `--check-links` does before the requests)
+fn link_warnings(text: &str, output: &Node, recipe: &Recipe) -> Vec<ParsingError> {
+    let about = output
+        .as_mapping()
+        .and_then(|output| output.get("about"))
+        .and_then(|about| about.as_mapping());
+    links_of(&recipe.about)
+        .filter_map(|(field, url)| {
+            let link = AboutLink {
+                package: recipe.package.name().as_normalized().to_string(),
+                field,
+                url: url.clone(),
+            };
+            let reason = validate_link(&link).err()?;
+            let span = about
+                .and_then(|about| about.get(field))
+                .map_or(*output.span(), |node| *node.span());
+            Some(ParsingError::from_partial(
+                text,
+                _partialerror!(
+                    span,
+                    ErrorKind::InvalidValue((format!("about.{field}"), reason.into())),
+                    label = "this link looks broken"
+                ),
+            ))
+        })
+        .collect()
+}
+
+/// The diagnostics of a recipe. The fix of an error (if any) is stored in the
+/// `data` of the diagnostic and turned into a quick fix by `code_actions`.
+pub(crate) fn diagnostics(recipe_dir: Option<&Path>, text: &str) -> Vec<Value> {
+    // errors in included fragments point to the `include` entry of the
+    // fragment, their fixes can not be applied to the recipe
+    let included = included_files(text).unwrap_or_default();
+    let is_include_entry = |err: &ParsingError| {
+        let span = text.get(err.span.offset()..err.span.offset() + err.span.len());
+        included
+            .iter()
+            .any(|path| span == Some(&*path.to_string_lossy()))
+    };
+
+    let to_diagnostic = |err: &ParsingError, severity: u8| {
+        let mut message = err.kind().to_string();
+        if let Some(help) = &err.help {
+            message.push('\n');
+            message.push_str(help);
+        }
+        let mut diagnostic = json!({
+            "range": {
+                "start": position(text, err.span.offset()),
+                "end": position(text, err.span.offset() + err.span.len())
+            },
+            "severity": severity,
+            "source": "rattler-build",
+            "message": message
+        });
+        if let Some(edit) = err.text_edit().filter(|_| !is_include_entry(err)) {
+            diagnostic["data"] = json!(edit);
+        }
+        diagnostic
+    };
+
+    let (errors, warnings) = parse_errors(recipe_dir, text);
+    let mut diagnostics = errors
+        .iter()
+        .map(|err| to_diagnostic(err, 1))
+        .collect::<Vec<_>>();
+    for warning in &warnings {
+        // outputs share the `about` section of the recipe
+        let diagnostic = to_diagnostic(warning, 2);
+        if !diagnostics.contains(&diagnostic) {
+            diagnostics.push(diagnostic);
+        }
+    }
+    diagnostics
+}
+
+/// The notification with the diagnostics of a document
+fn publish_diagnostics(uri: &str, text: &str) -> Value {
+    notification(
+        "textDocument/publishDiagnostics",
+        json!({ "uri": uri, "diagnostics": diagnostics(recipe_dir(uri).as_deref(), text) }),
+    )
+}
+
+/// Quick fixes for the diagnostics that have a suggested fix
+fn code_actions(uri: &str, params: &Value) -> Value {
+    let diagnostics = params["context"]["diagnostics"].as_array();
+    diagnostics
+        .into_iter()
+        .flatten()
+        .filter(|diagnostic| diagnostic["data"]["replacement"].is_string())
+        .map(|diagnostic| {
+            json!({
+                "title": diagnostic["data"]["message"],
+                "kind": "quickfix",
+                "diagnostics": [diagnostic],
+                "edit": {
+                    "changes": {
+                        uri: [{
+                            "range": diagnostic["range"],
+                            "newText": diagnostic["data"]["replacement"]
+                        }]
+                    }
+                }
+            })
+        })
+        .collect()
+}
+
+/// Where the cursor is in the recipe, taken from the spans of the parsed YAML
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Location {
+    /// The keys of the mappings whose values contain the cursor, from the
+    /// outermost to the innermost. The keys of selectors are skipped.
+    path: Vec<String>,
+    /// The key under the cursor (if any)
+    key: Option<String>,
+}
+
+/// Locate a position (1-based line and column, as in the spans of the
+/// parser) in the YAML node of a recipe. The value of a key contains the rest
+/// of the line of the key and the following lines that are indented more than
+/// the key.
+fn locate(node: &marked_yaml::Node, line: usize, column: usize) -> Location {
+    let starts_before = |span: &marked_yaml::Span| {
+        span.start()
+            .is_some_and(|start| (start.line(), start.column()) <= (line, column))
+    };
+
+    let mut location = Location::default();
+    let mut node = node;
+    loop {
+        match node {
+            marked_yaml::Node::Mapping(mapping) => {
+                let Some((key, value)) = mapping
+                    .iter()
+                    .rev()
+                    .find(|(key, _)| starts_before(key.span()))
+                else {
+                    break;
+                };
+                let Some(start) = key.span().start() else {
+                    break;
+                };
+                let key_end = start.column() + key.as_str().chars().count();
+                if start.line() == line && column < key_end {
+                    location.key = Some(key.as_str().to_string());
+                    break;
+                }
+                let in_value = if start.line() == line {
+                    column > key_end
+                } else {
+                    column > start.column()
+                };
+                if !in_value {
+                    break;
+                }
+                if !matches!(key.as_str(), "if" | "then" | "else") {
+                    location.path.push(key.as_str().to_string());
+                }
+                node = value;
+            }
+            marked_yaml::Node::Sequence(sequence) => {
+                let Some(item) = sequence
+                    .iter()
+                    .rev()
+                    .find(|item| starts_before(item.span()))
+                else {
+                    break;
+                };
+                node = item;
+            }
+            marked_yaml::Node::Scalar(_) => break,
+        }
+    }
+    location
+}
+
+/// The YAML node of a recipe, `None` if the recipe is not valid YAML
+fn parse_node(text: &str) -> Option<marked_yaml::Node> {
+    parse_yaml(0, text).ok()
+}
+
+/// The 1-based line and column of a line index and byte offset in the line
+fn marker_position(line_index: usize, line: &str, column: usize) -> (usize, usize) {
+    (line_index + 1, line[..column].chars().count() + 1)
+}
+
+/// The directory of a recipe that is opened from a file
+fn recipe_dir(uri: &str) -> Option<PathBuf> {
+    let path = Url::parse(uri).ok()?.to_file_path().ok()?;
+    path.parent().map(Path::to_path_buf)
+}
+
+/// The mapping schema of a schema (that may be a choice of schemas)
+fn object_schema(schema: &Value) -> Option<&Value> {
+    if schema.get("properties").is_some() {
+        return Some(schema);
+    }
+    schema
+        .get("anyOf")?
+        .as_array()?
+        .iter()
+        .find_map(object_schema)
+}
+
+/// The schema of the value at a path of keys
+fn schema_at<'a>(schema: &'a Value, path: &[String]) -> Option<&'a Value> {
+    path.iter().try_fold(schema, |schema, key| {
+        object_schema(schema)?["properties"].get(key)
+    })
+}
+
+/// The markdown documentation of the value at a path of keys
+fn documentation(path: &[String], schema: &Value) -> String {
+    let mut docs = format!("**`{}`**", path.join("."));
+    if let Some(description) = schema["description"].as_str() {
+        docs.push_str("\n\n");
+        docs.push_str(description);
+    }
+    if let Some(values) = schema["enum"].as_array() {
+        let values = values
+            .iter()
+            .filter_map(Value::as_str)
+            .map(|value| format!("`{value}`"))
+            .collect::<Vec<_>>();
+        docs.push_str(&format!("\n\nOne of {}", values.join(", ")));
+    } else if let Some(keys) = object_schema(schema).and_then(|s| s["properties"].as_object()) {
+        if !keys.is_empty() {
+            let keys = keys
+                .keys()
+                .map(|key| format!("`{key}`"))
+                .collect::<Vec<_>>();
+            docs.push_str(&format!("\n\nValid keys: {}", keys.join(", ")));
+        }
+    }
+    docs
+}
+
+/// The documentation of the key under the cursor
+fn hover(text: &str, position: &Value) -> Option<Value> {
+    let (line_index, line, column) = line_at(text, position)?;
+    let (line, column) = marker_position(line_index, line, column);
+    let Location { mut path, key } = locate(&parse_node(text)?, line, column);
+    path.push(key?);
+
+    let schema = recipe_schema();
+    let schema = schema_at(&schema, &path)?;
+    Some(json!({
+        "contents": { "kind": "markdown", "value": documentation(&path, schema) }
+    }))
+}
+
+/// The variables that can be used in Jinja expressions: the selector
+/// variables, the variables of the `context` section and the keys of the
+/// variant config next to the recipe.
+fn jinja_variables(uri: &str, node: Option<&marked_yaml::Node>) -> BTreeSet<String> {
+    let mut variables = SelectorConfig::default()
+        .into_context()
+        .into_keys()
+        .collect::<BTreeSet<_>>();
+
+    let context = node
+        .and_then(|node| node.as_mapping())
+        .and_then(|root| root.get("context"))
+        .and_then(|context| context.as_mapping());
+    variables.extend(
+        context
+            .into_iter()
+            .flat_map(|context| context.keys())
+            .map(|key| key.as_str().to_string()),
+    );
+
+    for variant_file in recipe_dir(uri)
+        .iter()
+        .flat_map(|dir| VARIANT_FILES.map(|f| dir.join(f)))
+    {
+        let Ok(content) = fs_err::read_to_string(&variant_file) else {
+            continue;
+        };
+        if let Ok(serde_yaml::Value::Mapping(variants)) = serde_yaml::from_str(&content) {
+            variables.extend(
+                variants
+                    .keys()
+                    .filter_map(|key| key.as_str().map(String::from)),
+            );
+        }
+    }
+    variables
+}
+
+/// The completions at the cursor: Jinja variables in `${{ ... }}`, an
+/// `if / then` selector for list items and otherwise the keys that are valid
+/// at the cursor.
+fn completion(uri: &str, text: &str, position: &Value) -> Value {
+    let Some((line_index, line, column)) = line_at(text, position) else {
+        return json!([]);
+    };
+    let before = &line[..column];
+
+    // the line that is being edited is usually not valid YAML yet, so the
+    // recipe is parsed without it
+    let without_line = text
+        .split('\n')
+        .enumerate()
+        .map(|(index, line)| if index == line_index { "" } else { line })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let node = parse_node(&without_line);
+
+    if before.rfind("${{") > before.rfind("}}") {
+        return jinja_variables(uri, node.as_ref())
+            .into_iter()
+            .map(|variable| json!({ "label": variable, "kind": 6 }))
+            .collect();
+    }
+
+    let trimmed = before.trim_start();
+    if trimmed.starts_with("- ") || trimmed == "-" {
+        return json!([{
+            "label": "if",
+            "kind": 15,
+            "detail": "if / then selector",
+            "insertText": "if: ${1:condition}\nthen: ${2:value}",
+            "insertTextFormat": 2
+        }]);
+    }
+
+    let (line, column) = marker_position(line_index, line, before.len() - trimmed.len());
+    let path = node
+        .map(|node| locate(&node, line, column).path)
+        .unwrap_or_default();
+    let schema = recipe_schema();
+    let keys = schema_at(&schema, &path)
+        .and_then(object_schema)
+        .and_then(|schema| schema["properties"].as_object());
+    keys.into_iter()
+        .flatten()
+        .map(|(key, schema)| {
+            json!({
+                "label": key,
+                "kind": 10,
+                "insertText": format!("{key}: "),
+                "documentation": {
+                    "kind": "markdown",
+                    "value": documentation(&[key.clone()], schema)
+                }
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RECIPE: &str = "context:\n  version: 1.0\npackage:\n  name: test\n  version: ${{ version }}\nbuild:\n  noarch: python\n";
+
+    fn open(server: &mut Server, text: &str) -> Vec<Value> {
+        server.handle(&json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": { "textDocument": { "uri": "file:///recipe/recipe.yaml", "text": text } }
+        }))
+    }
+
+    fn request(server: &mut Server, method: &str, line: usize, character: usize) -> Value {
+        let mut responses = server.handle(&json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": {
+                "textDocument": { "uri": "file:///recipe/recipe.yaml" },
+                "position": { "line": line, "character": character }
+            }
+        }));
+        assert_eq!(responses.len(), 1);
+        responses.remove(0)["result"].take()
+    }
+
+    #[test]
+    fn test_message_roundtrip() {
+        let message = json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize" });
+        let mut buffer = Vec::new();
+        write_message(&mut buffer, &message).unwrap();
+        write_message(&mut buffer, &json!({ "jsonrpc": "2.0", "method": "exit" })).unwrap();
+
+        let mut reader = io::Cursor::new(buffer);
+        assert_eq!(read_message(&mut reader).unwrap(), Some(message));
+        assert_eq!(
+            read_message(&mut reader).unwrap().unwrap()["method"],
+            "exit"
+        );
+        assert_eq!(read_message(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let mut server = Server::default();
+        let notifications = open(&mut server, RECIPE);
+        assert_eq!(notifications[0]["params"]["diagnostics"], json!([]));
+
+        let text = RECIPE.replace("noarch", "noarh");
+        let notifications = open(&mut server, &text);
+        let diagnostics = &notifications[0]["params"]["diagnostics"];
+        assert_eq!(diagnostics.as_array().unwrap().len(), 1);
+        assert_eq!(
+            diagnostics[0]["range"],
+            json!({
+                "start": { "line": 6, "character": 2 },
+                "end": { "line": 6, "character": 7 }
+            })
+        );
+        assert_eq!(diagnostics[0]["data"]["replacement"], "noarch");
+
+        let actions = code_actions(
+            "file:///recipe/recipe.yaml",
+            &json!({ "context": { "diagnostics": diagnostics } }),
+        );
+        assert_eq!(actions[0]["title"], "did you mean `noarch`?");
+        assert_eq!(
+            actions[0]["edit"]["changes"]["file:///recipe/recipe.yaml"][0]["newText"],
+            "noarch"
+        );
+    }
+
+    #[test]
+    fn test_link_diagnostics() {
+        let mut server = Server::default();
+        let text = format!(
+            "{RECIPE}about:\n  homepage: htps://example.com\n  repository: https://github.com/foo\n"
+        );
+        let notifications = open(&mut server, &text);
+        let diagnostics = notifications[0]["params"]["diagnostics"]
+            .as_array()
+            .unwrap();
+        assert_eq!(diagnostics.len(), 2);
+        // broken links are warnings
+        assert!(diagnostics.iter().all(|d| d["severity"] == 2));
+        assert_eq!(
+            diagnostics[0]["range"]["start"],
+            json!({ "line": 8, "character": 12 })
+        );
+        assert!(diagnostics[0]["message"]
+            .as_str()
+            .unwrap()
+            .contains("unsupported scheme `htps`"));
+        assert_eq!(diagnostics[1]["range"]["start"]["line"], 9);
+
+        // the `about` section of the recipe is reported once for all outputs
+        let text = concat!(
+            "recipe:\n  name: foo\n  version: 1.0\n",
+            "about:\n  homepage: https://examplecom\n",
+            "outputs:\n  - package:\n      name: a\n  - package:\n      name: b\n",
+        );
+        let notifications = open(&mut server, text);
+        let diagnostics = notifications[0]["params"]["diagnostics"]
+            .as_array()
+            .unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0]["message"]
+            .as_str()
+            .unwrap()
+            .contains("no top-level domain"));
+    }
+
+    #[test]
+    fn test_locate() {
+        let text = concat!(
+            "build:\n",
+            "  python:\n",
+            "    entry_points:\n",
+            "      - foo = foo:main\n",
+            "  dynamic_linking:\n",
+            "    rpaths:\n",
+            "      - if: unix\n",
+            "        then: lib\n",
+        );
+        let node = parse_node(text).unwrap();
+        let location = |line, column| {
+            let Location { path, key } = locate(&node, line, column);
+            (path.join("."), key)
+        };
+
+        assert_eq!(
+            location(3, 5),
+            ("build.python".to_string(), Some("entry_points".to_string()))
+        );
+        assert_eq!(
+            location(4, 9),
+            ("build.python.entry_points".to_string(), None)
+        );
+        // the keys of selectors are not part of the path
+        assert_eq!(
+            location(8, 9),
+            (
+                "build.dynamic_linking.rpaths".to_string(),
+                Some("then".to_string())
+            )
+        );
+        // a new line is in the mapping of its indentation
+        assert_eq!(location(9, 3), ("build".to_string(), None));
+        assert_eq!(location(9, 5), ("build.dynamic_linking".to_string(), None));
+        assert_eq!(location(9, 1), (String::new(), None));
+    }
+
+    #[test]
+    fn test_diagnostics_of_includes() {
+        let dir = tempfile::tempdir().unwrap();
+        fs_err::write(dir.path().join("build.yaml"), "build:\n  noarh: python\n").unwrap();
+        let uri = Url::from_file_path(dir.path().join("recipe.yaml")).unwrap();
+        let text =
+            format!("include:\n  - build.yaml\n{RECIPE}").replace("build:\n  noarch: python\n", "");
+
+        let mut server = Server::default();
+        let notifications = server.handle(&json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": { "textDocument": { "uri": uri.as_str(), "text": text } }
+        }));
+        // the error of the included fragment points to the include entry,
+        // without a quick fix that would replace the entry
+        let diagnostics = &notifications[0]["params"]["diagnostics"];
+        assert_eq!(diagnostics.as_array().unwrap().len(), 1);
+        assert_eq!(diagnostics[0]["range"]["start"]["line"], 1);
+        assert!(diagnostics[0]["message"]
+            .as_str()
+            .unwrap()
+            .contains("noarh"));
+        assert!(diagnostics[0]["data"].is_null());
+    }
+
+    #[test]
+    fn test_hover() {
+        let mut server = Server::default();
+        open(&mut server, RECIPE);
+        let hover = request(&mut server, "textDocument/hover", 6, 4);
+        let docs = hover["contents"]["value"].as_str().unwrap();
+        assert!(docs.starts_with("**`build.noarch`**"));
+        assert!(docs.contains("`python`, `generic`"));
+
+        // no documentation for values
+        assert_eq!(
+            request(&mut server, "textDocument/hover", 6, 12),
+            Value::Null
+        );
+    }
+
+    #[test]
+    fn test_completion() {
+        let mut server = Server::default();
+        open(
+            &mut server,
+            &format!("{RECIPE}  \nrequirements:\n  host:\n    - "),
+        );
+
+        let completions = request(&mut server, "textDocument/completion", 7, 2);
+        let labels = completions
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["label"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert!(labels.contains(&"number"));
+        assert!(labels.contains(&"dynamic_linking"));
+
+        let completions = request(&mut server, "textDocument/completion", 10, 6);
+        assert_eq!(completions[0]["label"], "if");
+
+        let completions = request(&mut server, "textDocument/completion", 4, 16);
+        let labels = completions
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["label"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert!(labels.contains(&"version"));
+        assert!(labels.contains(&"unix"));
+        assert!(labels.contains(&"target_platform"));
+
+        // positions after the end of the text
+        assert_eq!(
+            request(&mut server, "textDocument/completion", 42, 0),
+            json!([])
+        );
+        assert_eq!(
+            request(&mut server, "textDocument/hover", 42, 0),
+            Value::Null
+        );
+    }
+}
@@ -0,0 +1,217 @@
+//! Determine the macOS deployment target of a build and verify that the built
+//! Mach-O files do not require a newer macOS version than that.
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::{self, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use goblin::mach::{load_command::CommandVariant, Mach, MachO, SingleArch};
+
+use crate::{
+    metadata::Output, normalized_key::NormalizedKey, post_process::checks::LinkingCheckError,
+};
+
+/// A macOS version as encoded in the Mach-O load commands (`xxxx.yy.zz`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MacOSVersion {
+    major: u32,
+    minor: u32,
+    patch: u32,
+}
+
+impl MacOSVersion {
+    /// Decode a version from the nibble encoding used in `LC_BUILD_VERSION`
+    /// and `LC_VERSION_MIN_MACOSX`
+    fn from_encoded(version: u32) -> Self {
+        Self {
+            major: version >> 16,
+            minor: (version >> 8) & 0xff,
+            patch: version & 0xff,
+        }
+    }
+}
+
+impl FromStr for MacOSVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.trim().split('.').map(|part| {
+            part.parse::<u32>()
+                .map_err(|_| format!("invalid macOS version: {s}"))
+        });
+        let major = parts
+            .next()
+            .ok_or_else(|| format!("invalid macOS version: {s}"))??;
+        let minor = parts.next().transpose()?.unwrap_or(0);
+        let patch = parts.next().transpose()?.unwrap_or(0);
+        if parts.next().is_some() {
+            return Err(format!("invalid macOS version: {s}"));
+        }
+        Ok(Self {
+            major,
+            minor,
+            patch,
+        })
+    }
+}
+
+impl fmt::Display for MacOSVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)?;
+        if self.patch != 0 {
+            write!(f, ".{}", self.patch)?;
+        }
+        Ok(())
+    }
+}
+
+/// Get the deployment target from the variant. An explicit
+/// `MACOSX_DEPLOYMENT_TARGET` takes precedence over `c_stdlib_version`, which
+/// is only used if the `c_stdlib` is `macosx_deployment_target` (or not set).
+pub fn deployment_target_from_variant(variant: &BTreeMap<NormalizedKey, String>) -> Option<String> {
+    if let Some(target) = variant.get(&NormalizedKey::from("MACOSX_DEPLOYMENT_TARGET")) {
+        return Some(target.clone());
+    }
+
+    let stdlib = variant.get(&NormalizedKey::from("c_stdlib"));
+    if stdlib.map_or(true, |stdlib| stdlib == "macosx_deployment_target") {
+        return variant
+            .get(&NormalizedKey::from("c_stdlib_version"))
+            .cloned();
+    }
+
+    None
+}
+
+fn minimum_os_version_of_macho(macho: &MachO) -> Option<MacOSVersion> {
+    macho
+        .load_commands
+        .iter()
+        .find_map(|cmd| match &cmd.command {
+            CommandVariant::BuildVersion(build_version) => {
+                Some(MacOSVersion::from_encoded(build_version.minos))
+            }
+            CommandVariant::VersionMinMacosx(version_min) => {
+                Some(MacOSVersion::from_encoded(version_min.version))
+            }
+            _ => None,
+        })
+}
+
+/// Read the minimum macOS version of a Mach-O file from its `LC_BUILD_VERSION`
+/// (or the older `LC_VERSION_MIN_MACOSX`) load command. For universal binaries
+/// the highest minimum version of all architectures is returned.
+pub fn minimum_os_version(path: &Path) -> Result<Option<MacOSVersion>, LinkingCheckError> {
+    let data = fs_err::read(path)?;
+    let mach = Mach::parse(&data)
+        .map_err(|e| LinkingCheckError::SharedObject(format!("{}: {e}", path.display())))?;
+
+    match mach {
+        Mach::Binary(macho) => Ok(minimum_os_version_of_macho(&macho)),
+        Mach::Fat(multi) => {
+            let mut version = None;
+            for arch in &multi {
+                let arch = arch.map_err(|e| {
+                    LinkingCheckError::SharedObject(format!("{}: {e}", path.display()))
+                })?;
+                if let SingleArch::MachO(macho) = arch {
+                    version = version.max(minimum_os_version_of_macho(&macho));
+                }
+            }
+            Ok(version)
+        }
+    }
+}
+
+/// Verify that none of the given binaries requires a newer macOS version than
+/// the deployment target that is defined by the variant of the output. All
+/// binaries are checked and reported before failing.
+pub fn check_deployment_target(
+    output: &Output,
+    binaries: &HashSet<PathBuf>,
+    tmp_prefix: &Path,
+) -> Result<(), LinkingCheckError> {
+    let Some(target) = deployment_target_from_variant(&output.build_configuration.variant) else {
+        return Ok(());
+    };
+    let deployment_target =
+        MacOSVersion::from_str(&target).map_err(LinkingCheckError::SharedObject)?;
+
+    let mut violations = BTreeMap::new();
+    for binary in binaries {
+        let Some(minimum_os) = minimum_os_version(binary)? else {
+            continue;
+        };
+
+        let file = binary.strip_prefix(tmp_prefix).unwrap_or(binary);
+        tracing::debug!(
+            "{} requires macOS {} (deployment target: {})",
+            file.display(),
+            minimum_os,
+            deployment_target
+        );
+
+        if minimum_os > deployment_target {
+            violations.insert(file.to_path_buf(), minimum_os);
+        }
+    }
+
+    if violations.is_empty() {
+        return Ok(());
+    }
+
+    let mut report = String::new();
+    for (file, minimum_os) in &violations {
+        writeln!(report, "  - {} requires macOS {minimum_os}", file.display()).unwrap();
+    }
+    Err(LinkingCheckError::DeploymentTarget {
+        deployment_target: deployment_target.to_string(),
+        report,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_macos_version() {
+        assert_eq!(
+            MacOSVersion::from_encoded(0x000a_0d00),
+            "10.13".parse().unwrap()
+        );
+        assert_eq!(MacOSVersion::from_encoded(0x000b_0000).to_string(), "11.0");
+        assert_eq!(
+            "10.9.5".parse::<MacOSVersion>().unwrap().to_string(),
+            "10.9.5"
+        );
+        assert!("10.9".parse::<MacOSVersion>().unwrap() < "10.13".parse().unwrap());
+        assert!("10.x".parse::<MacOSVersion>().is_err());
+    }
+
+    #[test]
+    fn test_deployment_target_from_variant() {
+        let mut variant = BTreeMap::new();
+        assert_eq!(deployment_target_from_variant(&variant), None);
+
+        variant.insert("c_stdlib".into(), "macosx_deployment_target".to_string());
+        variant.insert("c_stdlib_version".into(), "10.13".to_string());
+        assert_eq!(
+            deployment_target_from_variant(&variant).as_deref(),
+            Some("10.13")
+        );
+
+        variant.insert("MACOSX_DEPLOYMENT_TARGET".into(), "11.0".to_string());
+        assert_eq!(
+            deployment_target_from_variant(&variant).as_deref(),
+            Some("11.0")
+        );
+
+        let mut variant = BTreeMap::new();
+        variant.insert("c_stdlib".into(), "sysroot".to_string());
+        variant.insert("c_stdlib_version".into(), "2.17".to_string());
+        assert_eq!(deployment_target_from_variant(&variant), None);
+    }
+}
@@ -1,8 +1,12 @@
 //! macOS specific environment variables
 use rattler_conda_types::Platform;
-use std::{collections::HashMap, path::Path};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+};
 
-use crate::unix;
+use super::deployment_target::deployment_target_from_variant;
+use crate::{normalized_key::NormalizedKey, unix};
 
 /// Get default env vars for macOS
 pub fn default_env_vars(
@@ -26,3 +30,19 @@ pub fn default_env_vars(
     vars.insert("BUILD".to_string(), Some(build.to_string()));
     vars
 }
+
+/// Get the macOS env vars that depend on the variant. This sets the
+/// `MACOSX_DEPLOYMENT_TARGET` from `c_stdlib_version` (or an explicit
+/// `MACOSX_DEPLOYMENT_TARGET` variant key).
+pub fn variant_env_vars(
+    variant: &BTreeMap<NormalizedKey, String>,
+) -> HashMap<String, Option<String>> {
+    let mut vars = HashMap::new();
+    if let Some(deployment_target) = deployment_target_from_variant(variant) {
+        vars.insert(
+            "MACOSX_DEPLOYMENT_TARGET".to_string(),
+            Some(deployment_target),
+        );
+    }
+    vars
+}
@@ -270,7 +270,7 @@ impl Relinker for Dylib {
     }
 }
 
-fn codesign(path: &Path, system_tools: &SystemTools) -> Result<(), RelinkError> {
+pub(crate) fn codesign(path: &Path, system_tools: &SystemTools) -> Result<(), RelinkError> {
     tracing::info!("codesigning {:?}", path.file_name().unwrap_or_default());
     system_tools
         .call(Tool::Codesign)?
@@ -1,2 +1,3 @@
+pub mod deployment_target;
 pub mod env;
 pub mod link;
@@ -1,20 +1,25 @@
 //! This is the main entry point for the `rattler-build` binary.
 
 use std::{
-    fs::File,
-    io::{self, IsTerminal},
+    fs,
+    io::{self, IsTerminal, Read},
+    path::{Path, PathBuf},
 };
 
 use clap::{CommandFactory, Parser};
-use miette::IntoDiagnostic;
+use miette::{Context, IntoDiagnostic};
 use rattler_build::{
-    build_recipes,
+    build_recipes, check_conda_build_config_from_args,
     console_utils::init_logging,
-    get_recipe_path,
-    opt::{App, BuildData, ShellCompletion, SubCommands},
-    rebuild_from_args, run_test_from_args, upload_from_args,
+    diff_from_args, explain_hash, extract_from_args, fmt_from_args, get_recipe_path, lsp_from_args,
+    opt::{App, BuildData, BuildOpts, ShellCompletion, SubCommands},
+    project_config::ProjectConfig,
+    rebuild_from_args, run_test_from_args, schema_from_args, self_test, serve_from_args,
+    sign_from_args, upload_from_args,
+    utils::split_yaml_documents,
 };
 use tempfile::{tempdir, TempDir};
+use tokio_util::sync::CancellationToken;
 
 fn main() -> miette::Result<()> {
     // Initialize sandbox in sync/single-threaded context before tokio runtime
@@ -33,6 +38,24 @@ fn main() -> miette::Result<()> {
         .block_on(async { async_main().await })
 }
 
+/// Returns a token that is cancelled when the user presses Ctrl-C, so that the
+/// running downloads remove their partial files and the build stops with an
+/// error. A second Ctrl-C exits immediately.
+fn ctrl_c_token() -> CancellationToken {
+    let token = CancellationToken::new();
+    let cancel = token.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            tracing::warn!("Cancelling the build, press Ctrl-C again to exit immediately");
+            cancel.cancel();
+            if tokio::signal::ctrl_c().await.is_ok() {
+                std::process::exit(130);
+            }
+        }
+    });
+    token
+}
+
 async fn async_main() -> miette::Result<()> {
     let app = App::parse();
     let log_handler = if !app.is_tui() {
@@ -42,6 +65,8 @@ async fn async_main() -> miette::Result<()> {
                 &app.verbose,
                 &app.color,
                 app.wrap_log_lines,
+                #[cfg(feature = "otlp")]
+                app.otlp_endpoint.as_deref(),
                 #[cfg(feature = "tui")]
                 None,
             )
@@ -54,7 +79,7 @@ async fn async_main() -> miette::Result<()> {
         None
     };
 
-    match app.subcommand {
+    let result = match app.subcommand {
         Some(SubCommands::Completion(ShellCompletion { shell })) => {
             let mut cmd = App::command();
             fn print_completions<G: clap_complete::Generator>(gen: G, cmd: &mut clap::Command) {
@@ -72,10 +97,11 @@ async fn async_main() -> miette::Result<()> {
         Some(SubCommands::Build(build_args)) => {
             let recipes = build_args.recipe.clone();
             let recipe_dir = build_args.recipe_dir.clone();
-            let build_data = BuildData::from(build_args);
+            let mut build_data = build_data_from_args(build_args)?;
 
             // Get all recipe paths and keep tempdir alive until end of the function
-            let (recipe_paths, _temp_dir) = recipe_paths(recipes, recipe_dir)?;
+            let (recipe_paths, _temp_dir) =
+                recipe_paths(recipes, recipe_dir, &mut build_data.variant_config)?;
 
             if recipe_paths.is_empty() {
                 miette::bail!("Couldn't detect any recipes.")
@@ -90,6 +116,8 @@ async fn async_main() -> miette::Result<()> {
                         &app.verbose,
                         &app.color,
                         Some(true),
+                        #[cfg(feature = "otlp")]
+                        app.otlp_endpoint.as_deref(),
                         Some(tui.event_handler.sender.clone()),
                     )
                     .into_diagnostic()?;
@@ -98,8 +126,45 @@ async fn async_main() -> miette::Result<()> {
                 return Ok(());
             }
 
+            build_data.cancellation_token = ctrl_c_token();
             build_recipes(recipe_paths, build_data, &log_handler).await
         }
+        Some(SubCommands::ExplainHash(build_args)) => {
+            let recipes = build_args.recipe.clone();
+            let recipe_dir = build_args.recipe_dir.clone();
+            let mut build_data = build_data_from_args(build_args)?;
+
+            let (recipe_paths, _temp_dir) =
+                recipe_paths(recipes, recipe_dir, &mut build_data.variant_config)?;
+
+            if recipe_paths.is_empty() {
+                miette::bail!("Couldn't detect any recipes.")
+            }
+
+            explain_hash(recipe_paths, build_data, &log_handler).await
+        }
+        Some(SubCommands::SelfTest(args)) => {
+            let recipes = args.build.recipe.clone();
+            let recipe_dir = args.build.recipe_dir.clone();
+            let mut build_data = build_data_from_args(args.build)?;
+
+            let (recipe_paths, _temp_dir) =
+                recipe_paths(recipes, recipe_dir, &mut build_data.variant_config)?;
+
+            if recipe_paths.is_empty() {
+                miette::bail!("Couldn't detect any recipes.")
+            }
+
+            self_test(
+                recipe_paths,
+                build_data,
+                args.platforms,
+                args.snapshot_dir,
+                args.update,
+                &log_handler,
+            )
+            .await
+        }
         Some(SubCommands::Test(test_args)) => {
             run_test_from_args(test_args, log_handler.expect("logger is not initialized")).await
         }
@@ -111,6 +176,14 @@ async fn async_main() -> miette::Result<()> {
             .await
         }
         Some(SubCommands::Upload(upload_args)) => upload_from_args(upload_args).await,
+        Some(SubCommands::Diff(diff_args)) => diff_from_args(diff_args),
+        Some(SubCommands::Extract(extract_args)) => extract_from_args(extract_args),
+        Some(SubCommands::Sign(sign_args)) => sign_from_args(sign_args),
+        Some(SubCommands::CheckCondaBuildConfig(args)) => check_conda_build_config_from_args(args),
+        Some(SubCommands::Fmt(fmt_args)) => fmt_from_args(fmt_args),
+        Some(SubCommands::Schema(schema_args)) => schema_from_args(schema_args),
+        Some(SubCommands::Lsp) => lsp_from_args(),
+        Some(SubCommands::Serve(serve_args)) => serve_from_args(serve_args),
         #[cfg(feature = "recipe-generation")]
         Some(SubCommands::GenerateRecipe(args)) => {
             rattler_build::recipe_generator::generate_recipe(args).await
@@ -120,30 +193,89 @@ async fn async_main() -> miette::Result<()> {
             _ = App::command().print_long_help();
             Ok(())
         }
-    }
+    };
+
+    #[cfg(feature = "otlp")]
+    rattler_build::otlp::shutdown();
+
+    result
 }
 
+/// Create the build data from the command line options and the configuration
+/// of the project (`rattler-build.toml` or `pyproject.toml`) next to the
+/// recipe.
+fn build_data_from_args(args: BuildOpts) -> miette::Result<BuildData> {
+    let recipe_folder = ProjectConfig::recipe_folder(&args.recipe, args.recipe_dir.as_deref());
+    let config = ProjectConfig::discover(&recipe_folder)?.map(|(path, config)| {
+        tracing::info!("Using the build defaults from {}", path.display());
+        config
+    });
+    Ok(BuildData::from_opts_and_config(args, config))
+}
+
+/// Collect the recipe paths. If the recipe or the variant configuration
+/// (`--variant-config -`) is read from stdin, the stdin can contain multiple
+/// YAML documents: the recipe comes first (if it is read from stdin) and all
+/// following documents are variant configurations. They are written to a
+/// temporary directory that has to be kept alive while building.
 fn recipe_paths(
-    recipes: Vec<std::path::PathBuf>,
-    recipe_dir: Option<std::path::PathBuf>,
-) -> Result<(Vec<std::path::PathBuf>, Option<TempDir>), miette::Error> {
+    recipes: Vec<PathBuf>,
+    recipe_dir: Option<PathBuf>,
+    variant_config: &mut Vec<PathBuf>,
+) -> Result<(Vec<PathBuf>, Option<TempDir>), miette::Error> {
     let mut recipe_paths = Vec::new();
     let mut temp_dir_opt = None;
-    if !std::io::stdin().is_terminal()
+
+    let recipe_from_stdin = !std::io::stdin().is_terminal()
         && recipes.len() == 1
-        && get_recipe_path(&recipes[0]).is_err()
-    {
+        && get_recipe_path(&recipes[0]).is_err();
+    let variants_from_stdin = variant_config.iter().any(|path| path == Path::new("-"));
+
+    if recipe_from_stdin || variants_from_stdin {
         let temp_dir = tempdir().into_diagnostic()?;
+        let mut stdin = String::new();
+        io::stdin()
+            .read_to_string(&mut stdin)
+            .into_diagnostic()
+            .context("failed to read from stdin")?;
+        let mut documents = split_yaml_documents(&stdin).into_iter();
 
-        let recipe_path = temp_dir.path().join("recipe.yaml");
-        io::copy(
-            &mut io::stdin(),
-            &mut File::create(&recipe_path).into_diagnostic()?,
-        )
-        .into_diagnostic()?;
-        recipe_paths.push(get_recipe_path(&recipe_path)?);
+        if recipe_from_stdin {
+            let recipe = documents
+                .next()
+                .ok_or_else(|| miette::miette!("No recipe was passed on stdin"))?;
+            let recipe_path = temp_dir.path().join("recipe.yaml");
+            fs::write(&recipe_path, recipe).into_diagnostic()?;
+            recipe_paths.push(get_recipe_path(&recipe_path)?);
+        }
+
+        let mut stdin_variants = Vec::new();
+        for (idx, document) in documents.enumerate() {
+            let path = temp_dir.path().join(format!("variants-{idx}.yaml"));
+            fs::write(&path, document).into_diagnostic()?;
+            stdin_variants.push(path);
+        }
+        if variants_from_stdin && stdin_variants.is_empty() {
+            miette::bail!(
+                "`--variant-config -` was given, but no variant configuration was passed on stdin"
+            );
+        }
+
+        // The variant configurations from stdin replace the `-` placeholder,
+        // or are applied last if there is none
+        match variant_config
+            .iter()
+            .position(|path| path == Path::new("-"))
+        {
+            Some(position) => {
+                variant_config.splice(position..=position, stdin_variants);
+            }
+            None => variant_config.extend(stdin_variants),
+        }
         temp_dir_opt = Some(temp_dir);
-    } else {
+    }
+
+    if !recipe_from_stdin {
         for recipe_path in &recipes {
             recipe_paths.push(get_recipe_path(recipe_path)?);
         }
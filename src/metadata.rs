@@ -29,18 +29,24 @@ use serde::{Deserialize, Deserializer, Serialize};
 use serde_json::Value;
 
 use crate::{
+    build_profile::BuildProfile,
     console_utils::github_integration_enabled,
-    hash::HashInfo,
+    hash::{HashConfig, HashInfo},
     normalized_key::NormalizedKey,
+    packaging::CompressionSettings,
+    provenance::Provenance,
     recipe::{
         jinja::SelectorConfig,
         parser::{Recipe, Source},
     },
     render::resolved_dependencies::FinalizedDependencies,
     script::SandboxConfiguration,
+    selectors::{EnvAccess, NoArchPlatform, NOARCH_PLATFORM_KEY},
+    signing,
     system_tools::SystemTools,
     tool_configuration,
-    utils::remove_dir_all_force,
+    utils::{is_read_only, remove_dir_all_force, to_lexical_absolute},
+    variant_config::Pin,
 };
 /// A Git revision
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -69,7 +75,9 @@ impl Display for GitRev {
 /// Directories used during the build process
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Directories {
-    /// The directory where the recipe is located
+    /// The directory where the recipe is located. The recipe directory is
+    /// only read (it may be mounted read-only), all files that are created
+    /// during the build are written to the build and output directories.
     #[serde(skip)]
     pub recipe_dir: PathBuf,
     /// The path where the recipe is located
@@ -94,46 +102,235 @@ pub struct Directories {
     pub output_dir: PathBuf,
 }
 
-fn get_build_dir(
-    output_dir: &Path,
+/// The path of the log file of the output with the given identifier:
+/// `<output_dir>/logs/<identifier>.log`
+pub fn log_file_path(output_dir: &Path, identifier: &str) -> PathBuf {
+    output_dir.join("logs").join(format!("{identifier}.log"))
+}
+
+/// The name of the build directory (in `<output_dir>/bld`) of an output:
+/// `rattler-build_<name>_<variant hash>_<timestamp>`. The variant hash (the
+/// hash part of the build string, e.g. `py312h1a2b3c4`) keeps the directories
+/// of different variants apart, and the timestamp is left out with
+/// `--no-build-id`, so that the name is stable between builds of the same
+/// variant.
+pub fn build_dir_name(
     name: &str,
+    variant_hash: Option<&str>,
     no_build_id: bool,
     timestamp: &DateTime<Utc>,
-) -> Result<PathBuf, std::io::Error> {
-    let since_the_epoch = timestamp.timestamp();
+) -> String {
+    let mut dirname = format!("rattler-build_{}", name);
+    if let Some(variant_hash) = variant_hash {
+        dirname.push('_');
+        dirname.push_str(variant_hash);
+    }
+    if !no_build_id {
+        dirname.push_str(&format!("_{}", timestamp.timestamp()));
+    }
+    dirname
+}
 
-    let dirname = if no_build_id {
-        format!("rattler-build_{}", name)
-    } else {
-        format!("rattler-build_{}_{:?}", name, since_the_epoch)
-    };
-    Ok(output_dir.join("bld").join(dirname))
+/// The placeholders that can be used in a [`BuildIdFormat`]
+const BUILD_ID_PLACEHOLDERS: [&str; 4] = ["name", "version", "hash", "timestamp"];
+
+/// A template for the name of the build directory (`--build-id-format`), e.g.
+/// `{name}-{version}-{timestamp}` or `{name}-{hash}`. Short names help with
+/// path length limits on Windows, and stable names (without `{timestamp}`) can
+/// be used as CI cache keys. The template has to contain `{name}`, so that the
+/// outputs of different recipes never share a build directory.
+///
+/// `{hash}` is the variant hash of the build string (empty for outputs that
+/// share a cache build) and `{timestamp}` the start of the build in seconds
+/// since the epoch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildIdFormat(String);
+
+impl FromStr for BuildIdFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rest = s;
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| format!("unclosed `{{` in build id format `{}`", s))?;
+            let placeholder = &rest[start + 1..start + end];
+            if !BUILD_ID_PLACEHOLDERS.contains(&placeholder) {
+                return Err(format!(
+                    "unknown placeholder `{{{}}}` in build id format `{}`, valid placeholders are {}",
+                    placeholder,
+                    s,
+                    BUILD_ID_PLACEHOLDERS
+                        .iter()
+                        .map(|p| format!("`{{{}}}`", p))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            }
+            rest = &rest[start + end + 1..];
+        }
+        if rest.contains('}') {
+            return Err(format!("unopened `}}` in build id format `{}`", s));
+        }
+        if s.contains(['/', '\\']) {
+            return Err(format!(
+                "the build id format `{}` must be a single directory name",
+                s
+            ));
+        }
+        if !s.contains("{name}") {
+            return Err(format!(
+                "the build id format `{}` must contain `{{name}}`, otherwise the outputs of \
+                 different recipes can end up in the same build directory",
+                s
+            ));
+        }
+        Ok(Self(s.to_string()))
+    }
+}
+
+impl BuildIdFormat {
+    /// The name of the build directory for the given output
+    pub fn render(
+        &self,
+        name: &str,
+        version: &str,
+        variant_hash: Option<&str>,
+        timestamp: &DateTime<Utc>,
+    ) -> String {
+        self.0
+            .replace("{name}", name)
+            .replace("{version}", version)
+            .replace("{hash}", variant_hash.unwrap_or_default())
+            .replace("{timestamp}", &timestamp.timestamp().to_string())
+    }
+}
+
+/// The placeholders that can be used in a [`FilenameTemplate`] (besides
+/// `{sha256:N}`)
+const FILENAME_PLACEHOLDERS: [&str; 5] = ["name", "version", "build", "subdir", "sha256"];
+
+/// A template for an additional, content-addressed file name of the package
+/// archive (`--filename-template`), e.g. `{name}-{version}-{build}-{sha256:8}`.
+///
+/// `{sha256}` is the SHA256 hash of the archive and `{sha256:N}` its first `N`
+/// characters. The archive extension (`.conda` or `.tar.bz2`) is appended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilenameTemplate(String);
+
+impl FromStr for FilenameTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rest = s;
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| format!("unclosed `{{` in filename template `{}`", s))?;
+            let placeholder = &rest[start + 1..start + end];
+            let valid = match placeholder.split_once(':') {
+                Some(("sha256", length)) => length
+                    .parse::<usize>()
+                    .is_ok_and(|length| (1..=64).contains(&length)),
+                Some(_) => false,
+                None => FILENAME_PLACEHOLDERS.contains(&placeholder),
+            };
+            if !valid {
+                return Err(format!(
+                    "unknown placeholder `{{{}}}` in filename template `{}`, valid placeholders are {} and `{{sha256:N}}` (with N between 1 and 64)",
+                    placeholder,
+                    s,
+                    FILENAME_PLACEHOLDERS
+                        .iter()
+                        .map(|p| format!("`{{{}}}`", p))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            }
+            rest = &rest[start + end + 1..];
+        }
+        if rest.contains('}') {
+            return Err(format!("unopened `}}` in filename template `{}`", s));
+        }
+        if s.trim().is_empty() || s.contains(['/', '\\']) {
+            return Err(format!(
+                "the filename template `{}` must be a single, non-empty file name",
+                s
+            ));
+        }
+        Ok(Self(s.to_string()))
+    }
+}
+
+impl FilenameTemplate {
+    /// The file name (with the extension) for a package archive with the
+    /// given SHA256 hash (hex encoded)
+    pub fn render(
+        &self,
+        identifier: &PackageIdentifier,
+        subdir: &str,
+        sha256: &str,
+        extension: &str,
+    ) -> String {
+        let mut filename = self
+            .0
+            .replace("{name}", identifier.name.as_normalized())
+            .replace("{version}", &identifier.version.to_string())
+            .replace("{build}", &identifier.build_string)
+            .replace("{subdir}", subdir)
+            .replace("{sha256}", sha256);
+        while let Some(start) = filename.find("{sha256:") {
+            let end = start + filename[start..].find('}').expect("validated template");
+            let length = filename[start + "{sha256:".len()..end]
+                .parse::<usize>()
+                .expect("validated template");
+            filename.replace_range(start..=end, &sha256[..length.min(sha256.len())]);
+        }
+        filename.push_str(extension);
+        filename
+    }
 }
 
 impl Directories {
-    /// Create all directories needed for the building of a package
+    /// Create all directories needed for the building of a package. The build
+    /// directory is `<output_dir>/bld/<build_dir_name>` (see
+    /// [`build_dir_name`] and [`BuildIdFormat`]). Variants that are built
+    /// concurrently need different names so that they do not share work and
+    /// host directories.
     pub fn setup(
-        name: &str,
+        build_dir_name: &str,
         recipe_path: &Path,
         output_dir: &Path,
-        no_build_id: bool,
-        timestamp: &DateTime<Utc>,
     ) -> Result<Directories, std::io::Error> {
+        let recipe_dir = recipe_path
+            .parent()
+            .ok_or_else(|| {
+                std::io::Error::new(std::io::ErrorKind::NotFound, "Parent directory not found")
+            })?
+            .to_path_buf();
+
         if !output_dir.exists() {
+            // never try to write into a read-only recipe directory (e.g. when
+            // it is mounted read-only in CI)
+            let absolute_output_dir = to_lexical_absolute(output_dir, &std::env::current_dir()?);
+            if absolute_output_dir.starts_with(&recipe_dir) && is_read_only(&recipe_dir) {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::PermissionDenied,
+                    format!(
+                        "the output directory {} is inside the read-only recipe directory {}, use `--output-dir` to choose a writable directory",
+                        absolute_output_dir.display(),
+                        recipe_dir.display()
+                    ),
+                ));
+            }
             fs::create_dir_all(output_dir)?;
         }
         let output_dir = canonicalize(output_dir)?;
 
-        let build_dir = get_build_dir(&output_dir, name, no_build_id, timestamp)
-            .expect("Could not create build directory");
+        let build_dir = output_dir.join("bld").join(build_dir_name);
         // TODO move this into build_dir, and keep build_dir consistent.
         let cache_dir = output_dir.join("build_cache");
-        let recipe_dir = recipe_path
-            .parent()
-            .ok_or_else(|| {
-                std::io::Error::new(std::io::ErrorKind::NotFound, "Parent directory not found")
-            })?
-            .to_path_buf();
 
         let host_prefix = if cfg!(target_os = "windows") {
             build_dir.join("h_env")
@@ -229,6 +426,10 @@ pub struct PackagingSettings {
     /// The compression level from 1-9 or -7-22 for `tar.bz2` and `conda`
     /// archives
     pub compression_level: i32,
+    /// Select the compression level (and number of threads) automatically
+    /// based on the content of the package
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub auto_compression: bool,
 }
 
 impl PackagingSettings {
@@ -243,6 +444,15 @@ impl PackagingSettings {
         Self {
             archive_type,
             compression_level,
+            auto_compression: false,
+        }
+    }
+
+    /// Enable or disable the automatic selection of the compression level
+    pub fn with_auto_compression(self, auto_compression: bool) -> Self {
+        Self {
+            auto_compression,
+            ..self
         }
     }
 }
@@ -322,8 +532,18 @@ pub struct BuildConfiguration {
     pub build_platform: PlatformWithVirtualPackages,
     /// The selected variant for this build
     pub variant: BTreeMap<NormalizedKey, String>,
+    /// Packages whose run dependency is pinned to the version in the host
+    /// environment (`pin_run_as_build` of the variant config)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub pin_run_as_build: BTreeMap<String, Pin>,
     /// THe computed hash of the variant
     pub hash: HashInfo,
+    /// The configuration that was used to compute the hash of the variant
+    #[serde(default, skip_serializing_if = "HashConfig::is_default")]
+    pub hash_config: HashConfig,
+    /// The environment variables that the recipe can read
+    #[serde(default, skip_serializing_if = "EnvAccess::is_default")]
+    pub env_access: EnvAccess,
     /// The directories for the build (work, source, build, host, ...)
     pub directories: Directories,
     /// The channels to use when resolving environments
@@ -351,6 +571,16 @@ pub struct BuildConfiguration {
     /// The configuration for the sandbox
     #[serde(skip_serializing, default)]
     pub sandbox_config: Option<SandboxConfiguration>,
+
+    /// Whether to run the build script with a fresh home and temporary
+    /// directory
+    #[serde(skip_serializing, default)]
+    pub isolate_env: bool,
+
+    /// The file that receives a complete log of the build and the tests (see
+    /// [`log_file_path`])
+    #[serde(skip_serializing, default)]
+    pub log_file: Option<PathBuf>,
 }
 
 impl BuildConfiguration {
@@ -374,6 +604,10 @@ impl BuildConfiguration {
             hash: Some(self.hash.clone()),
             experimental: false,
             allow_undefined: false,
+            strict_jinja: false,
+            hash_config: self.hash_config.clone(),
+            env_access: self.env_access.clone(),
+            repodata_access: None,
         }
     }
 }
@@ -405,6 +639,10 @@ pub struct BuildSummary {
     pub paths: Option<PathsJson>,
     ///  Whether the build was successful or not
     pub failed: bool,
+    /// The compression settings that were used for the artifact
+    pub compression: Option<String>,
+    /// The time spent in the phases of the build (and the tests)
+    pub profile: BuildProfile,
 }
 
 /// A output. This is the central element that is passed to the `run_build`
@@ -442,6 +680,10 @@ pub struct Output {
     /// that created this artifact
     #[serde(skip)]
     pub extra_meta: Option<BTreeMap<String, Value>>,
+    /// The commit of the recipe and the system that built this output. This is
+    /// recorded when the output is built.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Provenance>,
 }
 
 impl Output {
@@ -497,12 +739,40 @@ impl Output {
         summary.paths = Some(paths.clone());
     }
 
+    /// Record the compression settings that were used for the artifact
+    pub fn record_compression(&self, compression: &CompressionSettings) {
+        let mut summary = self.build_summary.lock().unwrap();
+        summary.compression = Some(compression.to_string());
+    }
+
+    /// The path of the log file of this output, if it is written
+    pub fn log_file(&self) -> Option<&Path> {
+        self.build_configuration.log_file.as_deref()
+    }
+
+    /// Add the time spent in the phases of the build (or the tests) to the
+    /// profile of the build
+    pub fn record_profile(&self, profile: BuildProfile) {
+        self.build_summary.lock().unwrap().profile.merge(profile);
+    }
+
     /// Record the end of the build
     pub fn record_build_end(&self) {
         let mut summary = self.build_summary.lock().unwrap();
         summary.build_end = Some(chrono::Utc::now());
     }
 
+    /// The platform (family) that this `noarch` output is built for (from the
+    /// `noarch_platform` variant)
+    pub fn noarch_platform(&self) -> Option<NoArchPlatform> {
+        if self.build_configuration.target_platform != Platform::NoArch {
+            return None;
+        }
+        self.variant()
+            .get(&NormalizedKey::from(NOARCH_PLATFORM_KEY))
+            .and_then(|value| value.parse().ok())
+    }
+
     /// Shorthand to retrieve the variant configuration for this output
     pub fn variant(&self) -> &BTreeMap<NormalizedKey, String> {
         &self.build_configuration.variant
@@ -549,6 +819,18 @@ impl Output {
         record.map(|r| (r, is_requested))
     }
 
+    /// The site-packages path of the python interpreter in the host
+    /// environment, if the `python` package exports one with
+    /// `python_site_packages_path` (e.g. `lib/pypy3.10/site-packages` for
+    /// PyPy). Other interpreters use the default location.
+    pub fn python_site_packages_path(&self) -> Option<&str> {
+        self.find_resolved_package("python")?
+            .0
+            .package_record
+            .python_site_packages_path
+            .as_deref()
+    }
+
     /// Print a nice summary of the build
     pub fn log_build_summary(&self) -> Result<(), std::io::Error> {
         let summary = self.build_summary.lock().unwrap();
@@ -559,11 +841,26 @@ impl Output {
         if let Some(artifact) = &summary.artifact {
             let bytes = HumanBytes(fs::metadata(artifact).map(|m| m.len()).unwrap_or(0));
             tracing::info!("Artifact: {} ({})", artifact.display(), bytes);
+            if let Some(compression) = &summary.compression {
+                tracing::info!("Compression: {}", compression);
+            }
         } else {
             tracing::info!("No artifact was created");
         }
+        let log_file = self.log_file().filter(|log_file| log_file.exists());
+        if let Some(log_file) = log_file {
+            tracing::info!("Log file: {}", log_file.display());
+        }
         tracing::info!("{}", self);
 
+        if !summary.profile.is_empty() {
+            tracing::info!(
+                "Time spent per phase (total {:.2?}):\n{}",
+                summary.profile.total(),
+                summary.profile.to_table()
+            );
+        }
+
         if !summary.warnings.is_empty() {
             tracing::warn!("Warnings:");
             for warning in &summary.warnings {
@@ -590,9 +887,15 @@ impl Output {
                     article.display(),
                     bytes
                 )?;
+                if let Some(compression) = &summary.compression {
+                    writeln!(summary_file, "**Compression**: {}", compression)?;
+                }
             } else {
                 writeln!(summary_file, "**No artifact was created**")?;
             }
+            if let Some(log_file) = log_file {
+                writeln!(summary_file, "**Log file**: {}", log_file.display())?;
+            }
 
             if let Some(paths) = &summary.paths {
                 if paths.paths.is_empty() {
@@ -638,6 +941,15 @@ impl Output {
                 }
             }
 
+            if !summary.profile.is_empty() {
+                writeln!(summary_file, "| Phase | Time |")?;
+                writeln!(summary_file, "| --- | --- |")?;
+                for (category, duration) in summary.profile.iter() {
+                    writeln!(summary_file, "| {} | {:.2?} |", category, duration)?;
+                }
+                writeln!(summary_file)?;
+            }
+
             if !summary.warnings.is_empty() {
                 writeln!(summary_file, "> [!WARNING]")?;
                 writeln!(summary_file, "> **Warnings during build:**\n>")?;
@@ -725,6 +1037,80 @@ impl Display for Output {
     }
 }
 
+/// The format in which rendered outputs are printed (`--render-only`)
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RenderFormat {
+    /// The fully serialized outputs as JSON
+    #[default]
+    Json,
+    /// The final rendered recipe of every output as a YAML document
+    Yaml,
+    /// A compact table with the outputs, their variants and build strings
+    Table,
+}
+
+/// Error that can occur when formatting rendered outputs
+#[derive(Debug, thiserror::Error)]
+pub enum RenderFormatError {
+    /// Failed to serialize the outputs to JSON
+    #[error("failed to serialize outputs to JSON: {0}")]
+    Json(#[from] serde_json::Error),
+
+    /// Failed to serialize the recipes to YAML
+    #[error("failed to serialize recipes to YAML: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+}
+
+impl Output {
+    /// Format a list of rendered outputs in the given format
+    pub fn format_rendered(
+        outputs: &[Output],
+        format: RenderFormat,
+    ) -> Result<String, RenderFormatError> {
+        match format {
+            RenderFormat::Json => Ok(serde_json::to_string_pretty(outputs)?),
+            RenderFormat::Yaml => {
+                let documents = outputs
+                    .iter()
+                    .map(|output| serde_yaml::to_string(&output.recipe))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(documents.join("---\n"))
+            }
+            RenderFormat::Table => Ok(Self::format_outputs_table(outputs).to_string()),
+        }
+    }
+
+    fn format_outputs_table(outputs: &[Output]) -> comfy_table::Table {
+        let mut table = comfy_table::Table::new();
+        table
+            .load_preset(comfy_table::presets::UTF8_FULL_CONDENSED)
+            .apply_modifier(comfy_table::modifiers::UTF8_ROUND_CORNERS)
+            .set_header(vec!["Package", "Version", "Build", "Platform", "Variant"]);
+
+        for output in outputs {
+            let variant = output
+                .build_configuration
+                .variant
+                .iter()
+                .map(|(k, v)| format!("{}={}", k.normalize(), v))
+                .collect::<Vec<_>>()
+                .join("\n");
+            let build = match output.recipe.build().skip_reason() {
+                Some(reason) => format!("{}\n(skipped: {})", output.build_string(), reason),
+                None => output.build_string().into_owned(),
+            };
+            table.add_row(vec![
+                output.name().as_normalized().to_string(),
+                output.version().to_string(),
+                build,
+                output.target_platform().to_string(),
+                variant,
+            ]);
+        }
+        table
+    }
+}
+
 /// Builds the channel list and reindexes the output channel.
 pub fn build_reindexed_channels(
     build_configuration: &BuildConfiguration,
@@ -747,6 +1133,13 @@ pub fn build_reindexed_channels(
     // Reindex the output channel from the files on disk
     index(output_dir, Some(&build_configuration.target_platform))?;
 
+    // Reindexing rewrites the repodata, keep the signatures of the packages
+    for subdir in [build_configuration.target_platform, Platform::NoArch] {
+        if let Err(e) = signing::restore_signatures(&output_dir.join(subdir.as_str())) {
+            tracing::warn!("Failed to restore the package signatures: {}", e);
+        }
+    }
+
     Ok(iter::once(output_channel.base_url)
         .chain(build_configuration.channels.iter().cloned())
         .collect())
@@ -759,17 +1152,130 @@ mod tests {
     #[test]
     fn setup_build_dir_test() {
         // without build_id (aka timestamp)
-        let dir = tempfile::tempdir().unwrap();
-        let p1 = get_build_dir(dir.path(), "name", true, &Utc::now()).unwrap();
-        let f1 = p1.file_name().unwrap();
-        assert!(f1.eq("rattler-build_name"));
+        let f1 = build_dir_name("name", None, true, &Utc::now());
+        assert_eq!(f1, "rattler-build_name");
 
         // with build_id (aka timestamp)
         let timestamp = &Utc::now();
-        let p2 = get_build_dir(dir.path(), "name", false, timestamp).unwrap();
-        let f2 = p2.file_name().unwrap();
+        let f2 = build_dir_name("name", None, false, timestamp);
         let epoch = timestamp.timestamp();
-        assert!(f2.eq(format!("rattler-build_name_{epoch}").as_str()));
+        assert_eq!(f2, format!("rattler-build_name_{epoch}"));
+
+        // with the variant hash, with and without build_id
+        let f3 = build_dir_name("name", Some("py312h1234567"), true, timestamp);
+        assert_eq!(f3, "rattler-build_name_py312h1234567");
+        let f4 = build_dir_name("name", Some("py312h1234567"), false, timestamp);
+        assert_eq!(f4, format!("rattler-build_name_py312h1234567_{epoch}"));
+
+        // different variants never share a build directory
+        let f5 = build_dir_name("name", Some("py311h7654321"), true, timestamp);
+        assert_ne!(f3, f5);
+
+        // the build directory is in `<output_dir>/bld`
+        let dir = tempfile::tempdir().unwrap();
+        let directories =
+            Directories::setup(&f3, &dir.path().join("recipe.yaml"), dir.path()).unwrap();
+        assert_eq!(
+            directories.build_dir,
+            canonicalize(dir.path()).unwrap().join("bld").join(&f3)
+        );
+    }
+
+    #[test]
+    fn test_build_id_format() {
+        let timestamp = &Utc::now();
+        let epoch = timestamp.timestamp();
+
+        let format: BuildIdFormat = "{name}-{version}-{timestamp}".parse().unwrap();
+        assert_eq!(
+            format.render("foo", "1.0", Some("h1234567"), timestamp),
+            format!("foo-1.0-{epoch}")
+        );
+
+        let format: BuildIdFormat = "{name}{hash}".parse().unwrap();
+        assert_eq!(
+            format.render("foo", "1.0", Some("py312h1234567"), timestamp),
+            "foopy312h1234567"
+        );
+        // outputs that share a cache build have no variant hash
+        assert_eq!(format.render("foo", "1.0", None, timestamp), "foo");
+
+        // without the name, different recipes could share a build directory
+        assert!("{hash}".parse::<BuildIdFormat>().is_err());
+        assert!("{version}-{timestamp}".parse::<BuildIdFormat>().is_err());
+
+        assert!("{name}-{build}".parse::<BuildIdFormat>().is_err());
+        assert!("{name".parse::<BuildIdFormat>().is_err());
+        assert!("name}".parse::<BuildIdFormat>().is_err());
+        assert!("bld/{name}".parse::<BuildIdFormat>().is_err());
+        assert!("".parse::<BuildIdFormat>().is_err());
+    }
+
+    #[test]
+    fn test_filename_template() {
+        let identifier = PackageIdentifier {
+            name: "foo".parse().unwrap(),
+            version: "1.0".parse().unwrap(),
+            build_string: "h1234567_0".to_string(),
+        };
+        let sha256 = "0123456789abcdef".repeat(4);
+
+        let template: FilenameTemplate = "{name}-{version}-{build}-{sha256:8}".parse().unwrap();
+        assert_eq!(
+            template.render(&identifier, "linux-64", &sha256, ".conda"),
+            "foo-1.0-h1234567_0-01234567.conda"
+        );
+
+        let template: FilenameTemplate = "{subdir}_{sha256}".parse().unwrap();
+        assert_eq!(
+            template.render(&identifier, "noarch", &sha256, ".tar.bz2"),
+            format!("noarch_{sha256}.tar.bz2")
+        );
+
+        assert!("{name}-{hash}".parse::<FilenameTemplate>().is_err());
+        assert!("{sha256:0}".parse::<FilenameTemplate>().is_err());
+        assert!("{sha256:65}".parse::<FilenameTemplate>().is_err());
+        assert!("{sha512:8}".parse::<FilenameTemplate>().is_err());
+        assert!("{name".parse::<FilenameTemplate>().is_err());
+        assert!("pkgs/{name}".parse::<FilenameTemplate>().is_err());
+        assert!("".parse::<FilenameTemplate>().is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn setup_read_only_recipe_dir() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let recipe_dir = dir.path().join("recipe");
+        fs::create_dir_all(&recipe_dir).unwrap();
+        fs::write(recipe_dir.join("recipe.yaml"), "").unwrap();
+        fs::set_permissions(&recipe_dir, std::fs::Permissions::from_mode(0o555)).unwrap();
+
+        let recipe_path = recipe_dir.join("recipe.yaml");
+
+        // a writable output directory outside of the recipe works
+        let directories = Directories::setup(
+            "rattler-build_name",
+            &recipe_path,
+            &dir.path().join("output"),
+        )
+        .unwrap();
+        assert_eq!(directories.recipe_dir, recipe_dir);
+        assert!(!directories.build_dir.starts_with(&recipe_dir));
+
+        // an output directory inside the read-only recipe is an error, and
+        // nothing is written into the recipe directory
+        let err = Directories::setup(
+            "rattler-build_name",
+            &recipe_path,
+            &recipe_dir.join("output"),
+        )
+        .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+        assert!(!recipe_dir.join("output").exists());
+
+        fs::set_permissions(&recipe_dir, std::fs::Permissions::from_mode(0o755)).unwrap();
     }
 }
 
@@ -787,7 +1293,7 @@ mod test {
     use rstest::*;
     use url::Url;
 
-    use super::{Directories, Output};
+    use super::{Directories, Output, RenderFormat};
     use crate::render::resolved_dependencies::{self, SourceDependency};
 
     #[test]
@@ -795,11 +1301,9 @@ mod test {
         let tempdir = tempfile::tempdir().unwrap();
 
         let directories = Directories::setup(
-            "name",
+            &super::build_dir_name("name", Some("h1234567"), false, &chrono::Utc::now()),
             &tempdir.path().join("recipe"),
             &tempdir.path().join("output"),
-            false,
-            &chrono::Utc::now(),
         )
         .unwrap();
         directories.create_build_dir(false).unwrap();
@@ -893,4 +1397,26 @@ mod test {
         let git_source_output: Output = serde_yaml::from_str(&recipe_1).unwrap();
         assert_yaml_snapshot!(git_source_output);
     }
+
+    #[test]
+    fn format_rendered_outputs() {
+        let test_data_dir =
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("test-data/rendered_recipes");
+        let recipe = std::fs::read_to_string(test_data_dir.join("rich_recipe.yaml")).unwrap();
+        let output: Output = serde_yaml::from_str(&recipe).unwrap();
+        let outputs = vec![output];
+
+        let json = Output::format_rendered(&outputs, RenderFormat::Json).unwrap();
+        let parsed: Vec<Output> = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.len(), 1);
+
+        let yaml = Output::format_rendered(&outputs, RenderFormat::Yaml).unwrap();
+        let recipe: crate::recipe::Recipe = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(recipe.package().name(), outputs[0].name());
+
+        let table = Output::format_rendered(&outputs, RenderFormat::Table).unwrap();
+        assert!(table.contains("Package"));
+        assert!(table.contains(outputs[0].name().as_normalized()));
+        assert!(table.contains(outputs[0].build_string().as_ref()));
+    }
 }
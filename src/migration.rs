@@ -0,0 +1,448 @@
+//! Apply conda-forge migration files on top of the variant configuration.
+//!
+//! conda-forge rolls out new pins with migration files (e.g.
+//! `.ci_support/migrations/python312.yaml`). These are `conda_build_config.yaml`
+//! files with an additional `__migrator` entry. They are merged into the
+//! variant configuration with the same semantics that conda-smithy uses when it
+//! renders the CI configuration of a feedstock, so that migrations can be
+//! tested locally.
+use std::{cmp::Ordering, collections::BTreeMap, path::Path, str::FromStr};
+
+use rattler_conda_types::Version;
+use serde::Deserialize;
+
+use crate::{
+    conda_build_config::load_conda_build_config_mapping,
+    normalized_key::NormalizedKey,
+    selectors::SelectorConfig,
+    variant_config::{VariantConfig, VariantConfigError},
+};
+
+/// A special operation of a migration
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MigrationOperation {
+    /// Add the values of the primary key (e.g. a new python version)
+    KeyAdd,
+    /// Remove the values of the primary key (e.g. an old python version)
+    KeyRemove,
+}
+
+/// The `__migrator` entry of a migration file. Only the fields that change the
+/// variants are read, all other fields are ignored.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Migrator {
+    /// Add or remove the values of the primary key instead of merging the keys
+    #[serde(default)]
+    pub operation: Option<MigrationOperation>,
+
+    /// The key that is changed by the operation
+    #[serde(default)]
+    pub primary_key: Option<NormalizedKey>,
+
+    /// A custom ordering of the values of a key (later values are newer)
+    #[serde(default)]
+    pub ordering: BTreeMap<NormalizedKey, Vec<String>>,
+
+    /// Keys that are zipped together with the primary key by the `key_add`
+    /// operation
+    #[serde(default)]
+    pub additional_zip_keys: Vec<NormalizedKey>,
+}
+
+/// A conda-forge migration file
+#[derive(Debug, Clone, Default)]
+pub struct Migration {
+    /// The `__migrator` entry
+    pub migrator: Migrator,
+    /// The variants of the migration
+    pub config: VariantConfig,
+}
+
+impl Migration {
+    /// Load a migration file. Selectors are evaluated the same way as in
+    /// `conda_build_config.yaml` files.
+    pub fn from_file(
+        path: &Path,
+        selector_config: &SelectorConfig,
+    ) -> Result<Self, VariantConfigError> {
+        let invalid = |e: serde_yaml::Error| {
+            VariantConfigError::InvalidMigration(path.to_path_buf(), e.to_string())
+        };
+
+        let mut mapping = load_conda_build_config_mapping(path, selector_config)?;
+        let migrator: Migrator = mapping
+            .remove("__migrator")
+            .map(serde_yaml::from_value)
+            .transpose()
+            .map_err(invalid)?
+            .unwrap_or_default();
+        mapping.remove("migrator_ts");
+        let config =
+            serde_yaml::from_value(serde_yaml::Value::Mapping(mapping)).map_err(invalid)?;
+
+        if migrator.operation.is_some() && migrator.primary_key.is_none() {
+            return Err(VariantConfigError::InvalidMigration(
+                path.to_path_buf(),
+                "`__migrator.operation` requires a `primary_key`".to_string(),
+            ));
+        }
+
+        Ok(Self { migrator, config })
+    }
+
+    /// Apply the migration to the variant configuration
+    pub fn apply(&self, config: &mut VariantConfig) {
+        match (self.migrator.operation, &self.migrator.primary_key) {
+            (Some(MigrationOperation::KeyAdd), Some(primary_key)) => {
+                self.key_add(config, primary_key)
+            }
+            (Some(MigrationOperation::KeyRemove), Some(primary_key)) => {
+                self.key_remove(config, primary_key)
+            }
+            _ => self.merge(config),
+        }
+    }
+
+    /// Merge the keys of the migration into the configuration. The values of a
+    /// key are merged pairwise (keeping the newer value), the pins of the
+    /// migration replace existing pins and the zip keys are merged.
+    fn merge(&self, config: &mut VariantConfig) {
+        for (key, values) in &self.config.variants {
+            let merged = match config.variants.get(key) {
+                Some(existing) => add_values(existing, values, self.migrator.ordering.get(key)),
+                None => values.clone(),
+            };
+            config.variants.insert(key.clone(), merged);
+        }
+
+        if let Some(pins) = &self.config.pin_run_as_build {
+            config
+                .pin_run_as_build
+                .get_or_insert_with(Default::default)
+                .extend(pins.clone());
+        }
+
+        if let Some(zip_keys) = &self.config.zip_keys {
+            let existing = config.zip_keys.take().unwrap_or_default();
+            config.zip_keys = Some(merge_zip_keys(existing, zip_keys));
+        }
+    }
+
+    /// Add the values of the primary key (and the matching values of the keys
+    /// that are zipped with it) that are not in the configuration yet.
+    fn key_add(&self, config: &mut VariantConfig, primary_key: &NormalizedKey) {
+        let Some(new_values) = self.config.variants.get(primary_key) else {
+            return;
+        };
+        let ordering = self.migrator.ordering.get(primary_key);
+
+        if !self.migrator.additional_zip_keys.is_empty() {
+            let zip_keys = config.zip_keys.get_or_insert_with(Vec::new);
+            let group = match zip_keys.iter().position(|zip| zip.contains(primary_key)) {
+                Some(idx) => &mut zip_keys[idx],
+                None => {
+                    zip_keys.push(vec![primary_key.clone()]);
+                    zip_keys.last_mut().expect("we just pushed a group")
+                }
+            };
+            for key in &self.migrator.additional_zip_keys {
+                if !group.contains(key) {
+                    group.push(key.clone());
+                }
+            }
+        }
+        let zipped = zipped_keys(config, primary_key);
+
+        for (index, value) in new_values.iter().enumerate() {
+            let values = config.variants.entry(primary_key.clone()).or_default();
+            if values.contains(value) {
+                continue;
+            }
+            let position = values
+                .iter()
+                .position(|v| compare_values(value, v, ordering) == Ordering::Less)
+                .unwrap_or(values.len());
+            values.insert(position, value.clone());
+
+            for key in &zipped {
+                let Some(new_value) = self.config.variants.get(key).and_then(|v| v.get(index))
+                else {
+                    continue;
+                };
+                let values = config.variants.entry(key.clone()).or_default();
+                values.insert(position.min(values.len()), new_value.clone());
+            }
+        }
+    }
+
+    /// Remove the values of the primary key (and the values at the same
+    /// position of the keys that are zipped with it).
+    fn key_remove(&self, config: &mut VariantConfig, primary_key: &NormalizedKey) {
+        let Some(removed_values) = self.config.variants.get(primary_key) else {
+            return;
+        };
+        let zipped = zipped_keys(config, primary_key);
+
+        for value in removed_values {
+            let Some(index) = config
+                .variants
+                .get(primary_key)
+                .and_then(|values| values.iter().position(|v| v == value))
+            else {
+                continue;
+            };
+            for key in std::iter::once(primary_key).chain(&zipped) {
+                if let Some(values) = config.variants.get_mut(key) {
+                    if index < values.len() {
+                        values.remove(index);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The keys that are zipped with the given key
+fn zipped_keys(config: &VariantConfig, key: &NormalizedKey) -> Vec<NormalizedKey> {
+    config
+        .zip_keys
+        .iter()
+        .flatten()
+        .filter(|zip| zip.contains(key))
+        .flatten()
+        .filter(|k| *k != key)
+        .cloned()
+        .collect()
+}
+
+/// Merge the values of a key pairwise, keeping the newer value of each pair and
+/// the remaining values of the longer list (conda-smithy's `variant_key_add`).
+fn add_values(
+    existing: &[String],
+    migration: &[String],
+    ordering: Option<&Vec<String>>,
+) -> Vec<String> {
+    let common = existing.len().min(migration.len());
+    let mut values = existing
+        .iter()
+        .zip(migration)
+        .map(|(left, right)| {
+            if compare_values(left, right, ordering) == Ordering::Less {
+                right.clone()
+            } else {
+                left.clone()
+            }
+        })
+        .collect::<Vec<_>>();
+    values.extend_from_slice(&existing[common..]);
+    values.extend_from_slice(&migration[common..]);
+    values
+}
+
+/// Compare two values by their position in the ordering (values that are not
+/// in the ordering are the oldest) or otherwise as versions.
+fn compare_values(a: &str, b: &str, ordering: Option<&Vec<String>>) -> Ordering {
+    if let Some(ordering) = ordering {
+        let position = |value: &str| ordering.iter().position(|v| v == value);
+        return position(a).cmp(&position(b));
+    }
+    match (Version::from_str(a), Version::from_str(b)) {
+        (Ok(a), Ok(b)) => a.cmp(&b),
+        _ => a.cmp(b),
+    }
+}
+
+/// Merge the zip keys. A group of the migration replaces the existing group
+/// that it extends, other groups are added.
+fn merge_zip_keys(
+    mut existing: Vec<Vec<NormalizedKey>>,
+    migration: &[Vec<NormalizedKey>],
+) -> Vec<Vec<NormalizedKey>> {
+    for group in migration {
+        match existing
+            .iter_mut()
+            .find(|zip| zip.iter().all(|key| group.contains(key)))
+        {
+            Some(zip) => *zip = group.clone(),
+            None => existing.push(group.clone()),
+        }
+    }
+    existing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(variants: &[(&str, &[&str])], zip_keys: &[&[&str]]) -> VariantConfig {
+        VariantConfig {
+            variants: variants
+                .iter()
+                .map(|(key, values)| {
+                    (
+                        NormalizedKey::from(*key),
+                        values.iter().map(|v| v.to_string()).collect(),
+                    )
+                })
+                .collect(),
+            zip_keys: (!zip_keys.is_empty()).then(|| {
+                zip_keys
+                    .iter()
+                    .map(|zip| zip.iter().map(|key| NormalizedKey::from(*key)).collect())
+                    .collect()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn values<'a>(config: &'a VariantConfig, key: &str) -> &'a [String] {
+        &config.variants[&NormalizedKey::from(key)]
+    }
+
+    #[test]
+    fn merge_version_migration() {
+        let mut base = config(&[("libfoo", &["1.2"]), ("python", &["3.10", "3.11"])], &[]);
+        let migration = Migration {
+            migrator: Migrator::default(),
+            config: config(&[("libfoo", &["1.10"]), ("libbar", &["2"])], &[]),
+        };
+        migration.apply(&mut base);
+
+        // versions are compared as versions, not as strings
+        assert_eq!(values(&base, "libfoo"), ["1.10"]);
+        assert_eq!(values(&base, "libbar"), ["2"]);
+        assert_eq!(values(&base, "python"), ["3.10", "3.11"]);
+
+        // a custom ordering is used instead of the version
+        let migration = Migration {
+            migrator: Migrator {
+                ordering: [("libfoo".into(), vec!["1.10".to_string(), "1.2".to_string()])]
+                    .into_iter()
+                    .collect(),
+                ..Default::default()
+            },
+            config: config(&[("libfoo", &["1.2", "1.3"])], &[]),
+        };
+        migration.apply(&mut base);
+        assert_eq!(values(&base, "libfoo"), ["1.2", "1.3"]);
+    }
+
+    #[test]
+    fn merge_zip_keys_groups() {
+        let mut base = config(
+            &[
+                ("c_compiler_version", &["12"]),
+                ("cxx_compiler_version", &["12"]),
+            ],
+            &[
+                &["c_compiler_version", "cxx_compiler_version"],
+                &["python", "numpy"],
+            ],
+        );
+        let migration = Migration {
+            migrator: Migrator::default(),
+            config: config(
+                &[],
+                &[
+                    &[
+                        "c_compiler_version",
+                        "cxx_compiler_version",
+                        "cuda_compiler_version",
+                    ],
+                    &["libfoo", "libbar"],
+                ],
+            ),
+        };
+        migration.apply(&mut base);
+        assert_eq!(
+            base.zip_keys,
+            Some(vec![
+                vec![
+                    "c_compiler_version".into(),
+                    "cxx_compiler_version".into(),
+                    "cuda_compiler_version".into()
+                ],
+                vec!["python".into(), "numpy".into()],
+                vec!["libfoo".into(), "libbar".into()],
+            ])
+        );
+    }
+
+    #[test]
+    fn key_add_and_remove() {
+        let mut base = config(
+            &[
+                ("python", &["3.10.* *_cpython", "3.12.* *_cpython"]),
+                ("numpy", &["1.22", "1.26"]),
+            ],
+            &[&["python", "numpy"]],
+        );
+        let add = Migration {
+            migrator: Migrator {
+                operation: Some(MigrationOperation::KeyAdd),
+                primary_key: Some("python".into()),
+                ..Default::default()
+            },
+            config: config(
+                &[("python", &["3.11.* *_cpython"]), ("numpy", &["1.23"])],
+                &[],
+            ),
+        };
+        add.apply(&mut base);
+        assert_eq!(
+            values(&base, "python"),
+            ["3.10.* *_cpython", "3.11.* *_cpython", "3.12.* *_cpython"]
+        );
+        assert_eq!(values(&base, "numpy"), ["1.22", "1.23", "1.26"]);
+
+        let remove = Migration {
+            migrator: Migrator {
+                operation: Some(MigrationOperation::KeyRemove),
+                primary_key: Some("python".into()),
+                ..Default::default()
+            },
+            config: config(&[("python", &["3.10.* *_cpython"])], &[]),
+        };
+        remove.apply(&mut base);
+        assert_eq!(
+            values(&base, "python"),
+            ["3.11.* *_cpython", "3.12.* *_cpython"]
+        );
+        assert_eq!(values(&base, "numpy"), ["1.23", "1.26"]);
+    }
+
+    #[test]
+    fn load_migration_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("python313.yaml");
+        fs_err::write(
+            &path,
+            r#"
+__migrator:
+  operation: key_add
+  primary_key: python
+  build_number: 1
+  commit_message: Rebuild for python 3.13
+python:
+  - 3.13.* *_cp313
+numpy:
+  - 2
+migrator_ts: 1724712607
+"#,
+        )
+        .unwrap();
+
+        let migration = Migration::from_file(&path, &SelectorConfig::default()).unwrap();
+        assert_eq!(
+            migration.migrator.operation,
+            Some(MigrationOperation::KeyAdd)
+        );
+        assert_eq!(values(&migration.config, "python"), ["3.13.* *_cp313"]);
+        assert_eq!(values(&migration.config, "numpy"), ["2"]);
+        assert!(!migration
+            .config
+            .variants
+            .contains_key(&NormalizedKey::from("migrator_ts")));
+    }
+}
@@ -1,7 +1,8 @@
 //! Command-line options.
 
-use std::{error::Error, path::PathBuf, str::FromStr};
+use std::{collections::BTreeMap, error::Error, net::SocketAddr, path::PathBuf, str::FromStr};
 
+use chrono::{DateTime, Utc};
 use clap::{arg, builder::ArgPredicate, crate_version, Parser, ValueEnum};
 use clap_complete::{shells, Generator};
 use clap_complete_nushell::Nushell;
@@ -10,14 +11,25 @@ use rattler_conda_types::{package::ArchiveType, Platform};
 use rattler_package_streaming::write::CompressionLevel;
 use rattler_solve::ChannelPriority;
 use serde_json::{json, Value};
+use serde_with::DeserializeFromStr;
+use tokio_util::sync::CancellationToken;
 use url::Url;
 
 #[cfg(feature = "recipe-generation")]
 use crate::recipe_generator::GenerateRecipeOpts;
 use crate::{
     console_utils::{Color, LogStyle},
+    diff::DiffFormat,
+    hash::HashConfig,
+    metadata::{BuildIdFormat, FilenameTemplate, RenderFormat},
+    normalized_key::NormalizedKey,
+    package_test::KeepTestPrefixes,
+    project_config::ProjectConfig,
+    recipe::parser::{Compression, PackageFormat},
     script::{SandboxArguments, SandboxConfiguration},
-    tool_configuration::{SkipExisting, TestStrategy},
+    selectors::EnvAccess,
+    system_tools::Tool,
+    tool_configuration::{ClobberCheck, SkipExisting, TestStrategy},
 };
 
 /// Application subcommands.
@@ -49,11 +61,92 @@ pub enum SubCommands {
     /// Upload a package
     Upload(UploadOpts),
 
+    /// Compare the contents of two packages
+    ///
+    /// Lists added, removed and changed files (by size and SHA256 hash) and
+    /// shows a unified diff for the metadata files in `info/` (e.g.
+    /// `index.json`, `paths.json` and `about.json`).
+    Diff(DiffOpts),
+
+    /// List, extract or print the files of a package
+    ///
+    /// Without options, the whole package is extracted into a directory named
+    /// after the package file. `--only` selects files by glob, `--list` prints
+    /// the size and SHA256 hash of every file without extracting anything, and
+    /// `--to-stdout` writes the content of a single file to stdout.
+    Extract(ExtractOpts),
+
+    /// Explain how the hash in the build string of every output of a recipe
+    /// is computed
+    ///
+    /// Takes the same arguments as `build` and prints the hash input (the used
+    /// variant), the excluded variant keys, the hash and the final build
+    /// string of every output without building anything.
+    ExplainHash(BuildOpts),
+
+    /// Compare the rendered outputs of a recipe with golden snapshots
+    ///
+    /// Renders the recipe for every given platform and compares the variant
+    /// and the final rendered recipe of every output with the snapshot files
+    /// in `<snapshot dir>/<platform>/<name>-<version>-<build string>.yaml`.
+    /// Fails if a snapshot changed, is missing or is stale. Only the snapshots
+    /// of the given platforms (and `noarch`) are checked, so the snapshots of
+    /// other platforms are kept. Use `--update` to write the current renders
+    /// as the new snapshots.
+    SelfTest(SelfTestOpts),
+
+    /// Sign the packages of a local channel for conda content trust
+    ///
+    /// Adds an ed25519 signature of every package record to the
+    /// `repodata.json` files of the channel, which conda and mamba clients
+    /// verify with the public key. With `--generate-key`, a new signing key
+    /// is written to the `--sign-key` file and its public key is printed
+    /// instead.
+    Sign(SignOpts),
+
+    /// Report the constructs of `conda_build_config.yaml` files that are not
+    /// supported
+    ///
+    /// Lists every line with a selector that is not a valid Jinja expression
+    /// or uses variables that rattler-build does not define, Jinja expressions
+    /// in values, unsupported Python calls and unsupported keys (such as
+    /// `extend_keys`). This helps to estimate the effort of migrating the
+    /// variant configuration of conda-build to rattler-build.
+    CheckCondaBuildConfig(CheckCondaBuildConfigOpts),
+
+    /// Format recipe files
+    ///
+    /// Rewrites the recipes with a canonical layout: two spaces of indentation,
+    /// lists in block style, the keys of the recipe sections in the order of
+    /// the schema and Jinja expressions without unnecessary quotes. Comments,
+    /// blank lines and block scalars (such as scripts) are kept.
+    Fmt(FmtOpts),
+
+    /// Print the JSON schema of the recipe format
+    ///
+    /// The schema is generated from the keys that this version of the parser
+    /// accepts and can be used for autocompletion and validation in editors.
+    Schema(SchemaOpts),
+
+    /// Start a language server for `recipe.yaml` files
+    ///
+    /// The server communicates over stdin / stdout and provides diagnostics
+    /// (with quick fixes), hover documentation and completion of the known
+    /// fields, selectors and Jinja variables.
+    Lsp,
+
+    /// Start an HTTP server that renders and lints recipes (experimental)
+    ///
+    /// The server answers JSON requests on the endpoints `/render`,
+    /// `/variants`, `/lint` and `/health`, so that tools do not have to run
+    /// `rattler-build` for every recipe. It listens on localhost by default.
+    Serve(ServeOpts),
+
     /// Generate shell completion script
     Completion(ShellCompletion),
 
     #[cfg(feature = "recipe-generation")]
-    /// Generate a recipe from PyPI or CRAN
+    /// Generate a recipe from PyPI, CRAN or npm
     GenerateRecipe(GenerateRecipeOpts),
 
     /// Handle authentication to external channels
@@ -151,6 +244,13 @@ pub struct App {
         global = true
     )]
     pub color: Color,
+
+    /// Export the tracing spans of the build (e.g. source fetching, solving,
+    /// building and testing) to this OpenTelemetry collector (OTLP over HTTP,
+    /// e.g. `http://localhost:4318/v1/traces`)
+    #[cfg(feature = "otlp")]
+    #[clap(long, env = "RATTLER_BUILD_OTLP_ENDPOINT", global = true)]
+    pub otlp_endpoint: Option<String>,
 }
 
 impl App {
@@ -166,11 +266,10 @@ impl App {
 /// Common opts that are shared between [`Rebuild`] and [`Build`]` subcommands
 #[derive(Parser, Clone, Debug)]
 pub struct CommonOpts {
-    /// Output directory for build artifacts.
+    /// Output directory for build artifacts. Defaults to `./output`.
     #[clap(
         long,
         env = "CONDA_BLD_PATH",
-        default_value = "./output",
         verbatim_doc_comment,
         help_heading = "Modifying result"
     )]
@@ -221,12 +320,63 @@ impl FromStr for ChannelPriorityWrapper {
 }
 
 /// Container for the CLI package format and compression level
-#[derive(Clone, PartialEq, Eq, Debug)]
+#[derive(Clone, PartialEq, Eq, Debug, DeserializeFromStr)]
 pub struct PackageFormatAndCompression {
     /// The archive type that is selected
     pub archive_type: ArchiveType,
     /// The compression level that is selected
     pub compression_level: CompressionLevel,
+    /// Whether the compression level should be selected automatically based
+    /// on the content of the package (`auto`)
+    pub auto_compression: bool,
+}
+
+impl PackageFormatAndCompression {
+    /// Apply the `package_format` and `compression` of the recipe of an
+    /// output. A numeric compression level from the command line is only kept
+    /// if the package format does not change.
+    pub fn with_recipe_overrides(
+        &self,
+        package_format: Option<PackageFormat>,
+        compression: Option<Compression>,
+    ) -> Result<Self, String> {
+        let archive_type = package_format.map_or(self.archive_type, Into::into);
+        let (compression_level, auto_compression) = match compression {
+            Some(Compression::Auto) => (CompressionLevel::Default, true),
+            Some(Compression::Lowest) => (CompressionLevel::Lowest, false),
+            Some(Compression::Default) => (CompressionLevel::Default, false),
+            Some(Compression::Highest) => (CompressionLevel::Highest, false),
+            Some(Compression::Level(number)) => {
+                validate_compression_level(archive_type, number)?;
+                (CompressionLevel::Numeric(number), false)
+            }
+            None => match self.compression_level {
+                CompressionLevel::Numeric(_) if archive_type != self.archive_type => {
+                    (CompressionLevel::Default, self.auto_compression)
+                }
+                level => (level, self.auto_compression),
+            },
+        };
+
+        Ok(Self {
+            archive_type,
+            compression_level,
+            auto_compression,
+        })
+    }
+}
+
+/// Check that a numeric compression level is valid for the archive type
+fn validate_compression_level(archive_type: ArchiveType, number: i32) -> Result<(), String> {
+    match archive_type {
+        ArchiveType::TarBz2 if !(1..=9).contains(&number) => {
+            Err("Compression level for .tar.bz2 must be between 1 and 9".to_string())
+        }
+        ArchiveType::Conda if !(-7..=22).contains(&number) => {
+            Err("Compression level for conda packages (zstd) must be between -7 and 22".to_string())
+        }
+        _ => Ok(()),
+    }
 }
 
 // deserializer for the package format and compression level
@@ -251,27 +401,15 @@ impl FromStr for PackageFormatAndCompression {
             _ => return Err(format!("Unknown package format: {}", package_format)),
         };
 
+        let auto_compression = compression == "auto";
         let compression_level = match compression {
+            "auto" => CompressionLevel::Default,
             "max" | "highest" => CompressionLevel::Highest,
             "default" | "normal" => CompressionLevel::Default,
             "fast" | "lowest" | "min" => CompressionLevel::Lowest,
             number if number.parse::<i32>().is_ok() => {
                 let number = number.parse::<i32>().unwrap_or_default();
-                match archive_type {
-                    ArchiveType::TarBz2 => {
-                        if !(1..=9).contains(&number) {
-                            return Err("Compression level for .tar.bz2 must be between 1 and 9"
-                                .to_string());
-                        }
-                    }
-                    ArchiveType::Conda => {
-                        if !(-7..=22).contains(&number) {
-                            return Err(
-                                "Compression level for conda packages (zstd) must be between -7 and 22".to_string()
-                            );
-                        }
-                    }
-                }
+                validate_compression_level(archive_type, number)?;
                 CompressionLevel::Numeric(number)
             }
             _ => return Err(format!("Unknown compression level: {}", compression)),
@@ -280,6 +418,7 @@ impl FromStr for PackageFormatAndCompression {
         Ok(PackageFormatAndCompression {
             archive_type,
             compression_level,
+            auto_compression,
         })
     }
 }
@@ -323,14 +462,26 @@ pub struct BuildOpts {
     #[arg(short = 'c', long)]
     pub channel: Option<Vec<String>>,
 
-    /// Variant configuration files for the build.
+    /// Variant configuration files for the build. Use `-` to read the variant
+    /// configuration from stdin.
     #[arg(short = 'm', long)]
     pub variant_config: Option<Vec<PathBuf>>,
 
+    /// conda-forge migration files (e.g. `.ci_support/migrations/*.yaml`) that are applied on top
+    /// of the variant configuration, in the given order.
+    #[arg(long = "migration")]
+    pub migrations: Vec<PathBuf>,
+
     /// Do not read the `variants.yaml` file next to a recipe.
     #[arg(long)]
     pub ignore_recipe_variants: bool,
 
+    /// Fail when the recipe uses an undefined variable (e.g. a typo like
+    /// `${{ verison }}`) instead of rendering it as an empty string. Undefined
+    /// variables can still be tested in conditions (`if: foo`).
+    #[arg(long)]
+    pub strict_jinja: bool,
+
     /// Render the recipe files without executing the build.
     #[arg(long)]
     pub render_only: bool,
@@ -339,18 +490,91 @@ pub struct BuildOpts {
     #[arg(long, requires("render_only"))]
     pub with_solve: bool,
 
+    /// The format in which the rendered outputs are printed.
+    #[arg(long, requires("render_only"))]
+    pub output_format: Option<RenderFormat>,
+
+    /// Cache the variants that are found by `--render-only` and reuse them
+    /// when the same recipe is rendered again with the same variant
+    /// configuration and platforms.
+    #[arg(long, requires("render_only"))]
+    pub render_cache: bool,
+
+    /// Check that the homepage, documentation and repository links in the
+    /// `about` section are valid and resolve before building.
+    #[arg(long)]
+    pub check_links: bool,
+
+    /// Resolve `pin_subpackage` to outputs of the recipe that are not built in
+    /// this invocation from the packages that were built earlier in the
+    /// output directory.
+    #[arg(long)]
+    pub reuse_built_subpackages: bool,
+
+    /// Sign the built packages for conda content trust with this ed25519 key
+    /// (PKCS#8 PEM or hex encoded). The signatures are added to the
+    /// `repodata.json` files of the output directory.
+    #[arg(long)]
+    pub sign_key: Option<PathBuf>,
+
+    /// Fetch the sources, install the environments and create the build
+    /// script, but do not run the build script or create the package.
+    #[arg(long, conflicts_with = "render_only")]
+    pub dry_run: bool,
+
+    /// When an environment cannot be solved, retry the solve with relaxed
+    /// specs to find the specs that conflict with the rest of the environment.
+    #[arg(long)]
+    pub explain_conflicts: bool,
+
+    /// Continue building (and testing) the remaining outputs when an output
+    /// fails. Outputs that depend on a failed output are skipped.
+    #[arg(long, visible_alias = "keep-going")]
+    pub continue_on_failure: bool,
+
+    /// What to do when files are packaged by more than one output of a
+    /// recipe (they would clobber each other when installed together)
+    #[arg(long)]
+    pub clobber_check: Option<ClobberCheck>,
+
     /// Keep intermediate build artifacts after the build.
     #[arg(long)]
     pub keep_build: bool,
 
+    /// Resume an interrupted or failed build from the last completed phase.
+    /// Reuses the build directory of the earlier build and skips fetching the
+    /// sources, resolving and installing the environments and running the
+    /// build script if they completed and the recipe did not change.
+    #[arg(long)]
+    pub resume: bool,
+
     /// Don't use build id(timestamp) when creating build directory name.
     #[arg(long)]
     pub no_build_id: bool,
 
+    /// A template for the name of the build directory, e.g.
+    /// `{name}-{version}-{timestamp}` or `{name}-{hash}`. Available
+    /// placeholders are `{name}` (required), `{version}`, `{hash}` (the variant
+    /// hash of the build string) and `{timestamp}`. Useful for short paths on
+    /// Windows or stable CI cache keys. The build fails if two outputs get the
+    /// same build directory.
+    #[arg(long, conflicts_with = "no_build_id")]
+    pub build_id_format: Option<BuildIdFormat>,
+
+    /// Also write every package to `<output-dir>/artifacts/` with a file name
+    /// from this template, e.g. `{name}-{version}-{build}-{sha256:8}`.
+    /// Available placeholders are `{name}`, `{version}`, `{build}`,
+    /// `{subdir}`, `{sha256}` and `{sha256:N}` (the first N characters of the
+    /// hash of the archive). The packages in the local channel keep their
+    /// standard names.
+    #[arg(long, help_heading = "Modifying result")]
+    pub filename_template: Option<FilenameTemplate>,
+
     /// The package format to use for the build. Can be one of `tar-bz2` or
     /// `conda`. You can also add a compression level to the package format,
     /// e.g. `tar-bz2:<number>` (from 1 to 9) or `conda:<number>` (from -7 to
-    /// 22).
+    /// 22). With `conda:auto` (or `tar-bz2:auto`) the compression level is
+    /// selected based on the content of the package.
     #[arg(long, help_heading = "Modifying result", verbatim_doc_comment)]
     pub package_format: Option<PackageFormatAndCompression>,
 
@@ -375,6 +599,12 @@ pub struct BuildOpts {
     #[arg(long, default_value = "true", help_heading = "Modifying result")]
     pub color_build_log: bool,
 
+    /// Run the build script with a fresh `HOME` and temporary directory in the
+    /// build directory and cleared XDG directories, and report the environment
+    /// variables that are inherited from the host.
+    #[arg(long)]
+    pub isolate_env: bool,
+
     #[allow(missing_docs)]
     #[clap(flatten)]
     pub common: CommonOpts,
@@ -392,6 +622,11 @@ pub struct BuildOpts {
     )]
     pub skip_existing: Option<SkipExisting>,
 
+    /// Explain why the source cache is (not) used and why existing packages
+    /// are (not) skipped, and print cache statistics at the end of the run
+    #[arg(long, help_heading = "Modifying result")]
+    pub explain_cache: bool,
+
     /// Define a "noarch platform" for which the noarch packages will be built
     /// for. The noarch builds will be skipped on the other platforms.
     #[arg(long, help_heading = "Modifying result")]
@@ -401,10 +636,81 @@ pub struct BuildOpts {
     #[arg(long, value_parser = parse_key_val)]
     pub extra_meta: Option<Vec<(String, Value)>>,
 
+    /// Use this executable for a system tool instead of searching for it
+    /// (e.g. `--system-tool patchelf=/opt/bin/patchelf`). Can be given
+    /// multiple times. The versions of all used system tools are recorded in
+    /// about.json
+    #[arg(long = "system-tool", value_parser = parse_system_tool)]
+    pub system_tools: Vec<(Tool, PathBuf)>,
+
+    #[allow(missing_docs)]
+    #[clap(flatten)]
+    pub hash_arguments: HashArguments,
+
+    #[allow(missing_docs)]
+    #[clap(flatten)]
+    pub env_arguments: EnvArguments,
+
     #[allow(missing_docs)]
     #[clap(flatten)]
     pub sandbox_arguments: SandboxArguments,
 }
+
+/// Options that control how the hash of a variant is computed.
+#[derive(Parser, Clone, Debug, Default)]
+pub struct HashArguments {
+    /// The number of characters of the variant hash in the build string
+    #[arg(
+        long,
+        value_parser = clap::value_parser!(u8).range(1..=40),
+        help_heading = "Modifying result"
+    )]
+    pub hash_length: Option<u8>,
+
+    /// Exclude a variant key from the hash input (can be given multiple times)
+    #[arg(long = "hash-exclude-key", help_heading = "Modifying result")]
+    pub hash_exclude_keys: Vec<String>,
+}
+
+impl From<HashArguments> for HashConfig {
+    fn from(args: HashArguments) -> Self {
+        let default = HashConfig::default();
+        HashConfig {
+            length: args.hash_length.map(usize::from).unwrap_or(default.length),
+            exclude_keys: args
+                .hash_exclude_keys
+                .into_iter()
+                .map(NormalizedKey::from)
+                .collect(),
+        }
+    }
+}
+/// Options that control which environment variables a recipe can read.
+#[derive(Parser, Clone, Debug, Default)]
+pub struct EnvArguments {
+    /// Allow the recipe to read this environment variable with `env.get` (can
+    /// be given multiple times). Without this option, all environment
+    /// variables can be read
+    #[arg(long = "env-allow", help_heading = "Modifying result")]
+    pub env_allow: Vec<String>,
+
+    /// Add the value of this environment variable to the variant (and the
+    /// hash) when the recipe reads it. Implies `--env-allow` (can be given
+    /// multiple times)
+    #[arg(long = "env-hash", help_heading = "Modifying result")]
+    pub env_hash: Vec<String>,
+}
+
+impl From<EnvArguments> for EnvAccess {
+    fn from(args: EnvArguments) -> Self {
+        EnvAccess {
+            allow: args.env_allow.into_iter().collect(),
+            hash: args.env_hash.into_iter().collect(),
+            deny_all: false,
+        }
+    }
+}
+
 #[allow(missing_docs)]
 #[derive(Clone, Debug)]
 pub struct BuildData {
@@ -414,23 +720,44 @@ pub struct BuildData {
     pub host_platform: Platform,
     pub channel: Vec<String>,
     pub variant_config: Vec<PathBuf>,
+    pub migrations: Vec<PathBuf>,
     pub ignore_recipe_variants: bool,
+    pub strict_jinja: bool,
     pub render_only: bool,
     pub with_solve: bool,
+    pub output_format: RenderFormat,
+    pub render_cache: bool,
+    pub check_links: bool,
+    pub reuse_built_subpackages: bool,
+    pub sign_key: Option<PathBuf>,
+    pub dry_run: bool,
+    pub explain_conflicts: bool,
+    pub continue_on_failure: bool,
+    pub clobber_check: ClobberCheck,
     pub keep_build: bool,
+    pub resume: bool,
     pub no_build_id: bool,
+    pub build_id_format: Option<BuildIdFormat>,
+    pub filename_template: Option<FilenameTemplate>,
     pub package_format: PackageFormatAndCompression,
     pub compression_threads: Option<u32>,
     pub no_include_recipe: bool,
     pub no_test: bool,
     pub test: TestStrategy,
     pub color_build_log: bool,
+    pub isolate_env: bool,
     pub common: CommonOpts,
     pub tui: bool,
     pub skip_existing: SkipExisting,
+    pub explain_cache: bool,
     pub noarch_build_platform: Option<Platform>,
     pub extra_meta: Option<Vec<(String, Value)>>,
+    pub system_tools: BTreeMap<Tool, PathBuf>,
+    pub hash_config: HashConfig,
+    pub env_access: EnvAccess,
     pub sandbox_configuration: Option<SandboxConfiguration>,
+    /// Cancels the running downloads and the remaining builds (e.g. on Ctrl-C)
+    pub cancellation_token: CancellationToken,
 }
 
 impl Default for BuildData {
@@ -442,20 +769,36 @@ impl Default for BuildData {
             host_platform: Platform::current(),
             channel: vec!["conda-forge".to_string()],
             variant_config: vec![],
+            migrations: vec![],
             ignore_recipe_variants: false,
+            strict_jinja: false,
             render_only: false,
             with_solve: false,
+            output_format: RenderFormat::default(),
+            render_cache: false,
+            check_links: false,
+            reuse_built_subpackages: false,
+            sign_key: None,
+            dry_run: false,
+            explain_conflicts: false,
+            continue_on_failure: false,
+            clobber_check: ClobberCheck::default(),
             keep_build: false,
+            resume: false,
             no_build_id: false,
+            build_id_format: None,
+            filename_template: None,
             package_format: PackageFormatAndCompression {
                 archive_type: ArchiveType::Conda,
                 compression_level: CompressionLevel::Default,
+                auto_compression: false,
             },
             compression_threads: None,
             no_include_recipe: false,
             no_test: false,
             test: TestStrategy::NativeAndEmulated,
             color_build_log: true,
+            isolate_env: false,
             common: CommonOpts {
                 output_dir: Some(PathBuf::from("./output")),
                 use_zstd: true,
@@ -468,16 +811,34 @@ impl Default for BuildData {
             },
             tui: false,
             skip_existing: SkipExisting::None,
+            explain_cache: false,
             noarch_build_platform: None,
             extra_meta: None,
+            system_tools: BTreeMap::new(),
+            hash_config: HashConfig::default(),
+            env_access: EnvAccess::default(),
             sandbox_configuration: None,
+            cancellation_token: CancellationToken::new(),
         }
     }
 }
 
 impl From<BuildOpts> for BuildData {
     fn from(opts: BuildOpts) -> Self {
+        Self::from_opts_and_config(opts, None)
+    }
+}
+
+impl BuildData {
+    /// Create the build data from the command line options. Options that are
+    /// not given on the command line are taken from the project configuration
+    /// (`rattler-build.toml` or `[tool.rattler-build]` in `pyproject.toml`),
+    /// if any, and otherwise use the defaults.
+    pub fn from_opts_and_config(opts: BuildOpts, config: Option<ProjectConfig>) -> Self {
         let build_data_default = BuildData::default();
+        let config = config.unwrap_or_default();
+        let mut common = opts.common;
+        common.output_dir = common.output_dir.or(config.output_dir);
         BuildData {
             up_to: opts.up_to.or(build_data_default.up_to),
             build_platform: opts
@@ -491,18 +852,44 @@ impl From<BuildOpts> for BuildData {
                 .host_platform
                 .or(opts.target_platform)
                 .unwrap_or(build_data_default.host_platform),
-            channel: opts.channel.unwrap_or(build_data_default.channel),
+            channel: opts
+                .channel
+                .or(config.channels)
+                .unwrap_or(build_data_default.channel),
             variant_config: opts
                 .variant_config
+                .or(config.variant_config)
                 .unwrap_or(build_data_default.variant_config),
+            migrations: opts.migrations,
             ignore_recipe_variants: opts.ignore_recipe_variants
                 || build_data_default.ignore_recipe_variants,
+            strict_jinja: opts.strict_jinja || build_data_default.strict_jinja,
             render_only: opts.render_only || build_data_default.render_only,
             with_solve: opts.with_solve || build_data_default.with_solve,
+            output_format: opts
+                .output_format
+                .unwrap_or(build_data_default.output_format),
+            render_cache: opts.render_cache || build_data_default.render_cache,
+            check_links: opts.check_links || build_data_default.check_links,
+            reuse_built_subpackages: opts.reuse_built_subpackages
+                || build_data_default.reuse_built_subpackages,
+            sign_key: opts.sign_key.or(build_data_default.sign_key),
+            dry_run: opts.dry_run || build_data_default.dry_run,
+            explain_conflicts: opts.explain_conflicts || build_data_default.explain_conflicts,
+            continue_on_failure: opts.continue_on_failure || build_data_default.continue_on_failure,
+            clobber_check: opts
+                .clobber_check
+                .unwrap_or(build_data_default.clobber_check),
             keep_build: opts.keep_build || build_data_default.keep_build,
+            resume: opts.resume || build_data_default.resume,
             no_build_id: opts.no_build_id || build_data_default.no_build_id,
+            build_id_format: opts.build_id_format.or(build_data_default.build_id_format),
+            filename_template: opts
+                .filename_template
+                .or(build_data_default.filename_template),
             package_format: opts
                 .package_format
+                .or(config.package_format)
                 .unwrap_or(build_data_default.package_format),
             compression_threads: opts
                 .compression_threads
@@ -511,16 +898,29 @@ impl From<BuildOpts> for BuildData {
             no_test: opts.no_test || build_data_default.no_test,
             test: opts.test.unwrap_or(TestStrategy::NativeAndEmulated),
             color_build_log: opts.color_build_log || build_data_default.color_build_log,
-            common: opts.common,
+            isolate_env: opts.isolate_env || build_data_default.isolate_env,
+            common,
             tui: opts.tui || build_data_default.tui,
             skip_existing: opts
                 .skip_existing
                 .unwrap_or(build_data_default.skip_existing),
+            explain_cache: opts.explain_cache || build_data_default.explain_cache,
             noarch_build_platform: opts
                 .noarch_build_platform
                 .or(build_data_default.noarch_build_platform),
             extra_meta: opts.extra_meta.or(build_data_default.extra_meta),
+            // the tools given on the command line replace the ones of the
+            // configuration file
+            system_tools: config
+                .system_tools
+                .unwrap_or(build_data_default.system_tools)
+                .into_iter()
+                .chain(opts.system_tools)
+                .collect(),
+            hash_config: opts.hash_arguments.into(),
+            env_access: opts.env_arguments.into(),
             sandbox_configuration: opts.sandbox_arguments.into(),
+            cancellation_token: build_data_default.cancellation_token,
         }
     }
 }
@@ -544,6 +944,30 @@ fn parse_key_val(s: &str) -> Result<(String, Value), Box<dyn Error + Send + Sync
     Ok((key.to_string(), json!(value)))
 }
 
+/// Parse a `tool=path` pair
+fn parse_system_tool(s: &str) -> Result<(Tool, PathBuf), Box<dyn Error + Send + Sync + 'static>> {
+    let (tool, path) = s
+        .split_once('=')
+        .ok_or_else(|| format!("invalid TOOL=path: no `=` found in `{}`", s))?;
+    let tool = tool.parse()?;
+    if tool == Tool::RattlerBuild {
+        return Err("rattler-build itself cannot be replaced with `--system-tool`".into());
+    }
+    Ok((tool, PathBuf::from(path)))
+}
+
+/// Parse a timestamp (RFC 3339) or a date (the start of the day in UTC)
+fn parse_exclude_newer(s: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(s) {
+        return Ok(timestamp.with_timezone(&Utc));
+    }
+    chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .map(|date| date.and_time(chrono::NaiveTime::MIN).and_utc())
+        .map_err(|_| {
+            format!("invalid date `{s}`, expected e.g. `2024-06-01` or `2024-06-01T12:00:00Z`")
+        })
+}
+
 /// Test options.
 #[derive(Parser)]
 pub struct TestOpts {
@@ -552,8 +976,37 @@ pub struct TestOpts {
     pub channel: Option<Vec<String>>,
 
     /// The package file to test
-    #[arg(short, long)]
-    pub package_file: PathBuf,
+    #[arg(short, long, required_unless_present = "all_packages")]
+    pub package_file: Option<PathBuf>,
+
+    /// Test all packages in a directory (recursively) or matching a path with
+    /// wildcards in the file name (e.g. `output/linux-64/*.conda`). The
+    /// packages are tested in the order of their run dependencies
+    #[arg(long, conflicts_with = "package_file")]
+    pub all_packages: Option<String>,
+
+    /// Write a JUnit XML report of the test results to this file
+    #[arg(long)]
+    pub junit_report: Option<PathBuf>,
+
+    /// Ignore packages that were published after this date (e.g.
+    /// `2024-06-01` or `2024-06-01T12:00:00Z`) when resolving the test
+    /// environment, to reproduce the dependencies of an older package
+    #[arg(long, value_parser = parse_exclude_newer)]
+    pub exclude_newer: Option<DateTime<Utc>>,
+
+    /// Keep the test prefix after the tests ran. A `test-environment.json`
+    /// file in the prefix lists the installed packages, their versions and
+    /// channels. Same as `--keep-test-prefixes all`
+    #[arg(long, conflicts_with = "keep_test_prefixes")]
+    pub keep_test_prefix: bool,
+
+    /// Which test prefixes to keep after the tests ran: `all`, `failing`
+    /// (only the prefix of the failing test) or a number N (the prefixes of
+    /// the last N tests). The kept prefixes are printed with the index of
+    /// the test they belong to
+    #[arg(long, value_name = "all|failing|N")]
+    pub keep_test_prefixes: Option<KeepTestPrefixes>,
 
     /// The number of threads to use for compression.
     #[clap(long, env = "RATTLER_COMPRESSION_THREADS")]
@@ -564,6 +1017,142 @@ pub struct TestOpts {
     pub common: CommonOpts,
 }
 
+/// Diff options.
+#[derive(Parser)]
+pub struct DiffOpts {
+    /// The old package file (`.conda` or `.tar.bz2`)
+    pub old: PathBuf,
+
+    /// The new package file (`.conda` or `.tar.bz2`)
+    pub new: PathBuf,
+
+    /// The output format
+    #[arg(long, default_value = "text")]
+    pub format: DiffFormat,
+}
+
+/// Schema options.
+#[derive(Parser)]
+pub struct SchemaOpts {
+    /// Write the schema to this file instead of printing it to stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+/// Options for the HTTP server.
+#[derive(Parser)]
+pub struct ServeOpts {
+    /// The address to listen on
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    pub address: SocketAddr,
+
+    /// Accept requests with this host name in the `Host` header, in addition
+    /// to IP addresses and `localhost` (can be given multiple times)
+    #[arg(long = "allowed-host")]
+    pub allowed_hosts: Vec<String>,
+
+    /// The number of threads that answer requests (defaults to the number of
+    /// CPUs)
+    #[arg(long)]
+    pub threads: Option<usize>,
+
+    /// Enable experimental features (required for this command)
+    #[arg(long, env = "RATTLER_BUILD_EXPERIMENTAL")]
+    pub experimental: bool,
+}
+
+/// Options for the `conda_build_config.yaml` compatibility report.
+#[derive(Parser)]
+pub struct CheckCondaBuildConfigOpts {
+    /// The `conda_build_config.yaml` files to check
+    #[arg(required = true)]
+    pub files: Vec<PathBuf>,
+
+    /// The platform to evaluate the selectors for (defaults to the current
+    /// platform)
+    #[arg(long)]
+    pub target_platform: Option<Platform>,
+
+    /// Print the report as JSON
+    #[arg(long)]
+    pub json: bool,
+}
+
+/// Options for formatting recipes.
+#[derive(Parser)]
+pub struct FmtOpts {
+    /// The recipe files (or directories with a `recipe.yaml`) to format
+    #[arg(default_value = ".")]
+    pub recipes: Vec<PathBuf>,
+
+    /// Do not write the files, but fail if any of them is not formatted
+    #[arg(long)]
+    pub check: bool,
+}
+
+/// Sign options.
+#[derive(Parser)]
+pub struct SignOpts {
+    /// The directory of the local channel to sign
+    #[arg(required_unless_present = "generate_key")]
+    pub channel: Option<PathBuf>,
+
+    /// The ed25519 key to sign with (PKCS#8 PEM or hex encoded)
+    #[arg(long)]
+    pub sign_key: PathBuf,
+
+    /// Generate a new signing key and write it to the `--sign-key` file
+    #[arg(long, conflicts_with = "channel")]
+    pub generate_key: bool,
+}
+
+/// Self-test options.
+#[derive(Parser)]
+pub struct SelfTestOpts {
+    #[allow(missing_docs)]
+    #[clap(flatten)]
+    pub build: BuildOpts,
+
+    /// The platforms to render the recipe for (defaults to the target
+    /// platform)
+    #[arg(long = "platform")]
+    pub platforms: Vec<Platform>,
+
+    /// The directory with the snapshots (defaults to `snapshots/` next to the
+    /// recipe)
+    #[arg(long)]
+    pub snapshot_dir: Option<PathBuf>,
+
+    /// Write the current renders as the new snapshots instead of comparing
+    #[arg(long)]
+    pub update: bool,
+}
+
+/// Extract options.
+#[derive(Parser)]
+pub struct ExtractOpts {
+    /// The package file (`.conda` or `.tar.bz2`)
+    pub package_file: PathBuf,
+
+    /// The directory to extract the package into (defaults to the name of the
+    /// package file without extension)
+    #[arg(short, long, conflicts_with_all = ["list", "to_stdout"])]
+    pub dest: Option<PathBuf>,
+
+    /// Only extract (or list) the files that match the glob, e.g. `info/*`
+    /// (can be used multiple times)
+    #[arg(long)]
+    pub only: Vec<String>,
+
+    /// Print the size and SHA256 hash of every file instead of extracting
+    #[arg(long, conflicts_with = "to_stdout")]
+    pub list: bool,
+
+    /// Write the content of a single file to stdout (select it with `--only`)
+    #[arg(long)]
+    pub to_stdout: bool,
+}
+
 /// Rebuild options.
 #[derive(Parser)]
 pub struct RebuildOpts {
@@ -597,7 +1186,32 @@ pub struct UploadOpts {
 
     /// The server type
     #[clap(subcommand)]
-    pub server_type: ServerType,
+    pub server_type: Option<ServerType>,
+
+    /// Upload to this URL with the backend selected by `--backend` instead of
+    /// using a server type subcommand
+    #[arg(long)]
+    pub to: Option<Url>,
+
+    /// The upload backend to use together with `--to` (`generic-put`, `gcs`,
+    /// `azure`, `file` or a backend registered by library code). Defaults to
+    /// `gcs` for `gs://` URLs, `azure` for `az://` URLs, `file` for `file://`
+    /// URLs and `generic-put` otherwise
+    #[arg(long, requires = "to")]
+    pub backend: Option<String>,
+
+    /// Sign the uploaded packages for conda content trust with this ed25519
+    /// key (PKCS#8 PEM or hex encoded). Only supported by backends that write
+    /// the repodata of the channel (`gcs`, `azure` and `file`), other servers
+    /// compute the repodata themselves
+    #[arg(long, requires = "to")]
+    pub sign_key: Option<PathBuf>,
+
+    /// Print the upload plan (packages, sizes, destination and whether the
+    /// packages already exist there) without uploading anything. Fails if a
+    /// package already exists and the server would reject it
+    #[arg(long, global = true)]
+    pub dry_run: bool,
 
     /// Common options.
     #[clap(flatten)]
@@ -690,8 +1304,10 @@ pub struct AnacondaOpts {
     #[arg(short, long, env = "ANACONDA_OWNER")]
     pub owner: String,
 
-    /// The channel / label to upload the package to (e.g. main / rc)
-    #[arg(short, long, env = "ANACONDA_CHANNEL", default_value = "main")]
+    /// The channel / label to upload the package to (e.g. main / rc).
+    /// Defaults to the label of the `channel_targets` variant of the package
+    /// or `main`
+    #[arg(short, long, env = "ANACONDA_CHANNEL")]
     pub channel: Vec<String>,
 
     /// The Anaconda API key, if none is provided, the token is read from the
@@ -769,6 +1385,7 @@ mod test {
     use rattler_package_streaming::write::CompressionLevel;
 
     use super::PackageFormatAndCompression;
+    use crate::recipe::parser::{Compression, PackageFormat};
 
     #[test]
     fn test_parse_packaging() {
@@ -777,7 +1394,8 @@ mod test {
             package_format,
             PackageFormatAndCompression {
                 archive_type: ArchiveType::TarBz2,
-                compression_level: CompressionLevel::Default
+                compression_level: CompressionLevel::Default,
+                auto_compression: false,
             }
         );
 
@@ -786,7 +1404,8 @@ mod test {
             package_format,
             PackageFormatAndCompression {
                 archive_type: ArchiveType::Conda,
-                compression_level: CompressionLevel::Default
+                compression_level: CompressionLevel::Default,
+                auto_compression: false,
             }
         );
 
@@ -795,7 +1414,8 @@ mod test {
             package_format,
             PackageFormatAndCompression {
                 archive_type: ArchiveType::TarBz2,
-                compression_level: CompressionLevel::Numeric(1)
+                compression_level: CompressionLevel::Numeric(1),
+                auto_compression: false,
             }
         );
 
@@ -804,7 +1424,8 @@ mod test {
             package_format,
             PackageFormatAndCompression {
                 archive_type: ArchiveType::TarBz2,
-                compression_level: CompressionLevel::Highest
+                compression_level: CompressionLevel::Highest,
+                auto_compression: false,
             }
         );
 
@@ -813,7 +1434,8 @@ mod test {
             package_format,
             PackageFormatAndCompression {
                 archive_type: ArchiveType::TarBz2,
-                compression_level: CompressionLevel::Numeric(5)
+                compression_level: CompressionLevel::Numeric(5),
+                auto_compression: false,
             }
         );
 
@@ -822,7 +1444,8 @@ mod test {
             package_format,
             PackageFormatAndCompression {
                 archive_type: ArchiveType::Conda,
-                compression_level: CompressionLevel::Numeric(1)
+                compression_level: CompressionLevel::Numeric(1),
+                auto_compression: false,
             }
         );
 
@@ -831,7 +1454,8 @@ mod test {
             package_format,
             PackageFormatAndCompression {
                 archive_type: ArchiveType::Conda,
-                compression_level: CompressionLevel::Highest
+                compression_level: CompressionLevel::Highest,
+                auto_compression: false,
             }
         );
 
@@ -840,7 +1464,18 @@ mod test {
             package_format,
             PackageFormatAndCompression {
                 archive_type: ArchiveType::Conda,
-                compression_level: CompressionLevel::Numeric(-5)
+                compression_level: CompressionLevel::Numeric(-5),
+                auto_compression: false,
+            }
+        );
+
+        let package_format = PackageFormatAndCompression::from_str("conda:auto").unwrap();
+        assert_eq!(
+            package_format,
+            PackageFormatAndCompression {
+                archive_type: ArchiveType::Conda,
+                compression_level: CompressionLevel::Default,
+                auto_compression: true,
             }
         );
 
@@ -849,8 +1484,63 @@ mod test {
             package_format,
             PackageFormatAndCompression {
                 archive_type: ArchiveType::Conda,
-                compression_level: CompressionLevel::Lowest
+                compression_level: CompressionLevel::Lowest,
+                auto_compression: false,
+            }
+        );
+    }
+
+    #[test]
+    fn test_package_format_recipe_overrides() {
+        let cli = PackageFormatAndCompression::from_str("conda:-5").unwrap();
+
+        // without overrides the settings of the command line are used
+        assert_eq!(cli.with_recipe_overrides(None, None).unwrap(), cli);
+
+        // a numeric level of another package format is not kept
+        let package_format = cli
+            .with_recipe_overrides(Some(PackageFormat::TarBz2), None)
+            .unwrap();
+        assert_eq!(
+            package_format,
+            PackageFormatAndCompression {
+                archive_type: ArchiveType::TarBz2,
+                compression_level: CompressionLevel::Default,
+                auto_compression: false,
+            }
+        );
+
+        let package_format = cli
+            .with_recipe_overrides(None, Some(Compression::Auto))
+            .unwrap();
+        assert_eq!(
+            package_format,
+            PackageFormatAndCompression {
+                archive_type: ArchiveType::Conda,
+                compression_level: CompressionLevel::Default,
+                auto_compression: true,
             }
         );
+
+        assert!(cli
+            .with_recipe_overrides(Some(PackageFormat::TarBz2), Some(Compression::Level(12)))
+            .is_err());
+    }
+
+    #[test]
+    fn test_parse_exclude_newer() {
+        assert_eq!(
+            super::parse_exclude_newer("2024-06-01")
+                .unwrap()
+                .to_rfc3339(),
+            "2024-06-01T00:00:00+00:00"
+        );
+        assert_eq!(
+            super::parse_exclude_newer("2024-06-01T12:30:00+02:00")
+                .unwrap()
+                .to_rfc3339(),
+            "2024-06-01T10:30:00+00:00"
+        );
+        assert!(super::parse_exclude_newer("June 2024").is_err());
     }
 }
@@ -0,0 +1,77 @@
+//! Export the tracing spans of a build (source fetching, solving, installing,
+//! running the build script, packaging and testing) to an OpenTelemetry
+//! collector (`--otlp-endpoint`).
+//!
+//! The spans that are shown in the console are bridged to OpenTelemetry, so
+//! the duration of every phase ends up in the exported trace.
+use opentelemetry::{trace::TracerProvider as _, KeyValue};
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{runtime, trace::TracerProvider, Resource};
+use tracing_core::Subscriber;
+use tracing_opentelemetry::{OpenTelemetryLayer, OpenTelemetrySpanExt};
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::metadata::Output;
+
+/// Create a tracing layer that exports all spans to the OTLP (HTTP) endpoint,
+/// e.g. `http://localhost:4318/v1/traces`.
+pub fn layer<S>(
+    endpoint: &str,
+) -> Result<OpenTelemetryLayer<S, opentelemetry_sdk::trace::Tracer>, String>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint)
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    let provider = TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(Resource::new(vec![
+            KeyValue::new("service.name", "rattler-build"),
+            KeyValue::new("service.version", env!("CARGO_PKG_VERSION")),
+        ]))
+        .build();
+
+    let tracer = provider.tracer("rattler-build");
+    opentelemetry::global::set_tracer_provider(provider);
+
+    Ok(tracing_opentelemetry::layer().with_tracer(tracer))
+}
+
+/// Attach the package name, variant and platforms of the output to the span,
+/// so that the exported traces can be aggregated per package.
+pub fn record_output_attributes(span: &tracing::Span, output: &Output) {
+    let build_configuration = &output.build_configuration;
+    span.set_attribute("package.name", output.name().as_normalized().to_string());
+    span.set_attribute("package.version", output.version().to_string());
+    span.set_attribute("package.build_string", output.build_string().to_string());
+    span.set_attribute(
+        "package.variant",
+        build_configuration
+            .variant
+            .iter()
+            .map(|(key, value)| format!("{}={}", key.normalize(), value))
+            .collect::<Vec<_>>()
+            .join(","),
+    );
+    span.set_attribute(
+        "platform.target",
+        build_configuration.target_platform.to_string(),
+    );
+    span.set_attribute(
+        "platform.host",
+        build_configuration.host_platform.platform.to_string(),
+    );
+    span.set_attribute(
+        "platform.build",
+        build_configuration.build_platform.platform.to_string(),
+    );
+}
+
+/// Flush all pending spans and shut down the exporter
+pub fn shutdown() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
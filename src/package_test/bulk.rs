@@ -0,0 +1,380 @@
+//! Test many packages at once (`rattler-build test --all-packages <dir>`). The
+//! packages are added to a temporary local channel (so that they can depend on
+//! each other), tested in the order of their run dependencies and the results
+//! can be written as a JUnit XML report for CI.
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use petgraph::{algo::toposort, graph::DiGraph};
+use rattler_conda_types::{
+    package::{ArchiveType, IndexJson},
+    Channel, MatchSpec, ParseStrictness,
+};
+use tracing::Instrument;
+use walkdir::WalkDir;
+
+use super::run_test::{create_local_channel, find_local_packages};
+use super::{run_test, SkippedTest, TestConfiguration, TestError};
+
+/// The outcome of testing a single package
+#[derive(Debug)]
+pub enum TestOutcome {
+    /// All tests passed, some tests may have been skipped
+    Passed(Vec<SkippedTest>),
+    /// The tests failed
+    Failed(String),
+    /// The package was not tested because a dependency failed its tests
+    Skipped(String),
+}
+
+/// The result of testing a single package
+#[derive(Debug)]
+pub struct PackageTestResult {
+    /// The package identifier (`name-version-build`)
+    pub package: String,
+    /// The subdir of the package
+    pub subdir: String,
+    /// How long the tests took
+    pub duration: Duration,
+    /// The outcome of the tests
+    pub outcome: TestOutcome,
+}
+
+/// Find the packages to test. `pattern` is either a directory (all packages in
+/// it are used, recursively) or a path with wildcards in the file name (e.g.
+/// `output/linux-64/*.conda`).
+pub fn find_packages(pattern: &str) -> Result<Vec<PathBuf>, TestError> {
+    let path = Path::new(pattern);
+    if !path.is_dir() {
+        return find_local_packages(&[pattern.to_string()], &std::env::current_dir()?);
+    }
+
+    let mut packages = WalkDir::new(path)
+        .into_iter()
+        .filter_map(Result::ok)
+        .map(|entry| entry.into_path())
+        .filter(|path| path.is_file() && ArchiveType::try_from(path).is_some())
+        .collect::<Vec<_>>();
+    packages.sort();
+    Ok(packages)
+}
+
+/// Read the `index.json` of a package
+fn read_index_json(package: &Path) -> Result<IndexJson, TestError> {
+    rattler_package_streaming::seek::read_package_file(package).map_err(|e| {
+        TestError::TestFailed(format!(
+            "failed to read package {}: {}",
+            package.display(),
+            e
+        ))
+    })
+}
+
+/// The name of the package that a dependency refers to
+fn dependency_name(dependency: &str) -> Option<String> {
+    MatchSpec::from_str(dependency, ParseStrictness::Lenient)
+        .ok()?
+        .name
+        .map(|name| name.as_normalized().to_string())
+}
+
+/// Returns true if the run dependency `dependency` of a package for `subdir`
+/// can be satisfied by the package `candidate`
+fn satisfied_by(dependency: &MatchSpec, subdir: &str, candidate: &IndexJson) -> bool {
+    let candidate_subdir = candidate.subdir.as_deref().unwrap_or_default();
+    dependency.name.as_ref() == Some(&candidate.name)
+        && (subdir == candidate_subdir || subdir == "noarch" || candidate_subdir == "noarch")
+        && dependency
+            .version
+            .as_ref()
+            .map_or(true, |version| version.matches(&candidate.version))
+        && dependency
+            .build
+            .as_ref()
+            .map_or(true, |build| build.matches(&candidate.build))
+}
+
+/// Sort the packages so that the run dependencies of a package are tested
+/// before the package itself. If the dependencies contain a cycle, the
+/// original order is kept.
+fn sort_topologically(packages: Vec<(PathBuf, IndexJson)>) -> Vec<(PathBuf, IndexJson)> {
+    let mut graph = DiGraph::<usize, ()>::new();
+    let nodes = (0..packages.len())
+        .map(|idx| graph.add_node(idx))
+        .collect::<Vec<_>>();
+
+    let mut by_name = HashMap::<&str, Vec<usize>>::new();
+    for (idx, (_, index_json)) in packages.iter().enumerate() {
+        by_name
+            .entry(index_json.name.as_normalized())
+            .or_default()
+            .push(idx);
+    }
+
+    for (idx, (_, index_json)) in packages.iter().enumerate() {
+        for name in index_json.depends.iter().filter_map(|d| dependency_name(d)) {
+            for dependency in by_name.get(name.as_str()).into_iter().flatten() {
+                if *dependency != idx {
+                    graph.add_edge(nodes[*dependency], nodes[idx], ());
+                }
+            }
+        }
+    }
+
+    let order = match toposort(&graph, None) {
+        Ok(order) => order.into_iter().map(|node| graph[node]).collect(),
+        Err(cycle) => {
+            tracing::warn!(
+                "The dependencies of {} contain a cycle, testing the packages in the given order",
+                packages[graph[cycle.node_id()]].0.display()
+            );
+            (0..packages.len()).collect::<Vec<_>>()
+        }
+    };
+
+    let mut packages = packages.into_iter().map(Some).collect::<Vec<_>>();
+    order
+        .into_iter()
+        .filter_map(|idx| packages[idx].take())
+        .collect()
+}
+
+/// Test all given packages in the order of their run dependencies. The
+/// packages are added to a temporary channel that is used before the
+/// configured channels. A package is not tested if one of its dependencies
+/// failed its tests.
+pub async fn run_tests(
+    packages: &[PathBuf],
+    config: &TestConfiguration,
+) -> Result<Vec<PackageTestResult>, TestError> {
+    let (channel_dir, _) = create_local_channel(packages)?;
+    let mut config = config.clone();
+    config
+        .channels
+        .insert(0, Channel::from_directory(channel_dir.path()).base_url);
+    let test_prefix = config.test_prefix.clone();
+
+    let packages = packages
+        .iter()
+        .map(|package| Ok((package.clone(), read_index_json(package)?)))
+        .collect::<Result<Vec<_>, TestError>>()?;
+
+    // the packages that failed or were not tested, by identifier
+    let mut failed = Vec::<(String, IndexJson)>::new();
+    let mut results = Vec::new();
+    for (package, index_json) in sort_topologically(packages) {
+        let identifier = format!(
+            "{}-{}-{}",
+            index_json.name.as_normalized(),
+            index_json.version,
+            index_json.build
+        );
+        let subdir = index_json.subdir.clone().unwrap_or_default();
+
+        let failed_dependencies = index_json
+            .depends
+            .iter()
+            .filter_map(|d| MatchSpec::from_str(d, ParseStrictness::Lenient).ok())
+            .flat_map(|dependency| {
+                failed
+                    .iter()
+                    .filter(|(_, candidate)| satisfied_by(&dependency, &subdir, candidate))
+                    .map(|(identifier, _)| identifier.as_str())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let start = Instant::now();
+        let outcome = if !failed_dependencies.is_empty() {
+            TestOutcome::Skipped(format!(
+                "the tests of {} failed",
+                failed_dependencies.join(", ")
+            ))
+        } else {
+            let span = tracing::info_span!("Running tests for", package = %identifier);
+            config.test_prefix = test_prefix.join(&identifier);
+            match run_test(&package, &config, None).instrument(span).await {
+                Ok(skipped) => TestOutcome::Passed(skipped),
+                Err(e) => TestOutcome::Failed(e.to_string()),
+            }
+        };
+
+        match &outcome {
+            TestOutcome::Passed(_) => tracing::info!("{} passed", identifier),
+            TestOutcome::Failed(e) => {
+                tracing::error!("{} failed: {}", identifier, e);
+                failed.push((identifier.clone(), index_json));
+            }
+            TestOutcome::Skipped(reason) => {
+                tracing::warn!("{} was not tested because {}", identifier, reason);
+                failed.push((identifier.clone(), index_json));
+            }
+        }
+
+        results.push(PackageTestResult {
+            package: identifier,
+            subdir,
+            duration: start.elapsed(),
+            outcome,
+        });
+    }
+
+    Ok(results)
+}
+
+/// Escape a string for XML attributes and text
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// A JUnit XML report of the test results (one test case per package)
+pub fn junit_report(results: &[PackageTestResult]) -> String {
+    let failures = results
+        .iter()
+        .filter(|r| matches!(r.outcome, TestOutcome::Failed(_)))
+        .count();
+    let skipped = results
+        .iter()
+        .filter(|r| matches!(r.outcome, TestOutcome::Skipped(_)))
+        .count();
+    let time = results
+        .iter()
+        .map(|r| r.duration.as_secs_f64())
+        .sum::<f64>();
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let summary = format!(
+        "tests=\"{}\" failures=\"{}\" errors=\"0\" skipped=\"{}\" time=\"{:.3}\"",
+        results.len(),
+        failures,
+        skipped,
+        time
+    );
+    let _ = writeln!(xml, "<testsuites name=\"rattler-build\" {}>", summary);
+    let _ = writeln!(xml, "  <testsuite name=\"rattler-build test\" {}>", summary);
+    for result in results {
+        let _ = write!(
+            xml,
+            "    <testcase classname=\"{}\" name=\"{}\" time=\"{:.3}\"",
+            escape_xml(&result.subdir),
+            escape_xml(&result.package),
+            result.duration.as_secs_f64()
+        );
+        match &result.outcome {
+            TestOutcome::Passed(skipped) if skipped.is_empty() => {
+                let _ = writeln!(xml, "/>");
+            }
+            TestOutcome::Passed(skipped) => {
+                let _ = writeln!(xml, ">");
+                let out = skipped
+                    .iter()
+                    .map(|s| s.to_string())
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                let _ = writeln!(xml, "      <system-out>{}</system-out>", escape_xml(&out));
+                let _ = writeln!(xml, "    </testcase>");
+            }
+            TestOutcome::Failed(message) => {
+                let _ = writeln!(xml, ">");
+                let _ = writeln!(xml, "      <failure message=\"{}\"/>", escape_xml(message));
+                let _ = writeln!(xml, "    </testcase>");
+            }
+            TestOutcome::Skipped(reason) => {
+                let _ = writeln!(xml, ">");
+                let _ = writeln!(xml, "      <skipped message=\"{}\"/>", escape_xml(reason));
+                let _ = writeln!(xml, "    </testcase>");
+            }
+        }
+    }
+    let _ = writeln!(xml, "  </testsuite>");
+    let _ = writeln!(xml, "</testsuites>");
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index_json(name: &str, depends: &[&str]) -> IndexJson {
+        serde_json::from_value(serde_json::json!({
+            "name": name,
+            "version": "1.0",
+            "build": "h123_0",
+            "build_number": 0,
+            "subdir": "linux-64",
+            "depends": depends,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_sort_topologically() {
+        let packages = vec![
+            (
+                PathBuf::from("app"),
+                index_json("app", &["libfoo >=1", "python"]),
+            ),
+            (PathBuf::from("libfoo"), index_json("libfoo", &["libbar"])),
+            (PathBuf::from("libbar"), index_json("libbar", &[])),
+        ];
+        let order = sort_topologically(packages)
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            order,
+            vec![
+                PathBuf::from("libbar"),
+                PathBuf::from("libfoo"),
+                PathBuf::from("app")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_satisfied_by() {
+        let libfoo = index_json("libfoo", &[]);
+        let spec = |s: &str| MatchSpec::from_str(s, ParseStrictness::Lenient).unwrap();
+
+        assert!(satisfied_by(&spec("libfoo >=1"), "linux-64", &libfoo));
+        assert!(satisfied_by(&spec("libfoo * h123_0"), "noarch", &libfoo));
+        // another version, build or platform of the package
+        assert!(!satisfied_by(&spec("libfoo >=2"), "linux-64", &libfoo));
+        assert!(!satisfied_by(&spec("libfoo * h456_0"), "linux-64", &libfoo));
+        assert!(!satisfied_by(&spec("libfoo"), "osx-64", &libfoo));
+        assert!(!satisfied_by(&spec("libbar"), "linux-64", &libfoo));
+    }
+
+    #[test]
+    fn test_junit_report() {
+        let results = vec![
+            PackageTestResult {
+                package: "libbar-1.0-h123_0".to_string(),
+                subdir: "linux-64".to_string(),
+                duration: Duration::from_millis(1500),
+                outcome: TestOutcome::Failed("command `bar --help` failed".to_string()),
+            },
+            PackageTestResult {
+                package: "libfoo-1.0-h123_0".to_string(),
+                subdir: "linux-64".to_string(),
+                duration: Duration::ZERO,
+                outcome: TestOutcome::Skipped("the tests of libbar failed".to_string()),
+            },
+        ];
+        let report = junit_report(&results);
+        assert!(report.contains(
+            "<testsuites name=\"rattler-build\" tests=\"2\" failures=\"1\" errors=\"0\" skipped=\"1\" time=\"1.500\">"
+        ));
+        assert!(report.contains("<failure message=\"command `bar --help` failed\"/>"));
+        assert!(report.contains("<skipped message=\"the tests of libbar failed\"/>"));
+    }
+}
@@ -0,0 +1,309 @@
+//! Detect whether packages for another platform can be tested on this machine.
+//!
+//! Packages for a different architecture are run through an emulator: QEMU
+//! registered with `binfmt_misc` on Linux, Rosetta 2 on macOS and the built-in
+//! x64 emulation of Windows on ARM. The tests are skipped (with the reason)
+//! when no emulator is set up.
+
+use std::path::Path;
+
+use rattler_conda_types::{GenericVirtualPackage, Platform};
+
+use crate::metadata::PlatformWithVirtualPackages;
+
+/// The directory where `binfmt_misc` lists the registered interpreters
+const BINFMT_MISC_DIR: &str = "/proc/sys/fs/binfmt_misc";
+
+/// The Rosetta 2 runtime on macOS
+const ROSETTA_PATH: &str = "/Library/Apple/usr/share/rosetta/rosetta";
+
+/// How packages of a platform are run on the current machine
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Emulation {
+    /// The packages run natively
+    Native,
+    /// The packages run through the given emulator
+    Emulated(String),
+}
+
+/// Check whether packages for `platform` can be run on the `current` platform.
+/// Returns the reason if they cannot be run.
+pub fn check_emulation(current: Platform, platform: Platform) -> Result<Emulation, String> {
+    check_emulation_with(current, platform, Path::new(BINFMT_MISC_DIR))
+}
+
+fn check_emulation_with(
+    current: Platform,
+    platform: Platform,
+    binfmt_dir: &Path,
+) -> Result<Emulation, String> {
+    match (current, platform) {
+        (current, platform) if current == platform || platform == Platform::NoArch => {
+            Ok(Emulation::Native)
+        }
+        // 32-bit packages run natively on the 64-bit version of the same architecture
+        (Platform::Linux64, Platform::Linux32) | (Platform::Win64, Platform::Win32) => {
+            Ok(Emulation::Native)
+        }
+        (Platform::OsxArm64, Platform::Osx64) => {
+            if Path::new(ROSETTA_PATH).exists() {
+                Ok(Emulation::Emulated("Rosetta 2".to_string()))
+            } else {
+                Err(
+                    "Rosetta 2 is not installed (install it with `softwareupdate --install-rosetta`)"
+                        .to_string(),
+                )
+            }
+        }
+        (Platform::WinArm64, Platform::Win64 | Platform::Win32) => Ok(Emulation::Emulated(
+            "the x64 emulation of Windows".to_string(),
+        )),
+        (current, platform) if current.is_linux() && platform.is_linux() => {
+            qemu_binfmt(binfmt_dir, platform).map(Emulation::Emulated)
+        }
+        _ => Err(format!(
+            "there is no emulator for {platform} packages on {current}"
+        )),
+    }
+}
+
+/// The name of the QEMU user mode emulator for a Linux platform
+fn qemu_arch(platform: Platform) -> Option<&'static str> {
+    match platform {
+        Platform::Linux32 => Some("i386"),
+        Platform::Linux64 => Some("x86_64"),
+        Platform::LinuxAarch64 => Some("aarch64"),
+        Platform::LinuxArmV6l | Platform::LinuxArmV7l => Some("arm"),
+        Platform::LinuxPpc64le => Some("ppc64le"),
+        Platform::LinuxPpc64 => Some("ppc64"),
+        Platform::LinuxS390X => Some("s390x"),
+        Platform::LinuxRiscv64 => Some("riscv64"),
+        _ => None,
+    }
+}
+
+/// Find the QEMU interpreter for the platform in `binfmt_misc` and validate that
+/// it is enabled and usable. Returns a description of the emulator.
+fn qemu_binfmt(binfmt_dir: &Path, platform: Platform) -> Result<String, String> {
+    let arch =
+        qemu_arch(platform).ok_or_else(|| format!("QEMU cannot emulate {platform} packages"))?;
+    let setup_hint = format!(
+        "register QEMU for {arch} with binfmt_misc \
+         (e.g. `docker run --privileged --rm tonistiigi/binfmt --install {arch}`)"
+    );
+
+    let status = fs_err::read_to_string(binfmt_dir.join("status")).map_err(|_| {
+        format!(
+            "binfmt_misc is not mounted at {}, {setup_hint}",
+            binfmt_dir.display()
+        )
+    })?;
+    if status.trim() != "enabled" {
+        return Err(format!("binfmt_misc is disabled, {setup_hint}"));
+    }
+
+    let emulator = format!("qemu-{arch}");
+    let entries = fs_err::read_dir(binfmt_dir).map_err(|e| e.to_string())?;
+    for entry in entries.filter_map(Result::ok) {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name == "status" || name == "register" {
+            continue;
+        }
+        let Ok(content) = fs_err::read_to_string(entry.path()) else {
+            continue;
+        };
+        let entry = BinfmtEntry::parse(&content);
+
+        let interpreter_name = entry
+            .interpreter
+            .as_deref()
+            .and_then(|path| Path::new(path).file_name())
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let matches = |name: &str| name == emulator || name.starts_with(&format!("{emulator}-"));
+        if !matches(&name) && !matches(&interpreter_name) {
+            continue;
+        }
+
+        if !entry.enabled {
+            return Err(format!(
+                "the binfmt_misc entry `{name}` is disabled (enable it with `echo 1 > {}`)",
+                binfmt_dir.join(&name).display()
+            ));
+        }
+        let Some(interpreter) = entry.interpreter else {
+            return Err(format!("the binfmt_misc entry `{name}` has no interpreter"));
+        };
+        // with the `F` (fix binary) flag the interpreter is opened when it is
+        // registered, so it does not need to exist in this file system
+        if !entry.flags.contains('F') && !Path::new(&interpreter).exists() {
+            return Err(format!(
+                "the interpreter {interpreter} of the binfmt_misc entry `{name}` does not exist, \
+                 {setup_hint}"
+            ));
+        }
+        return Ok(format!("QEMU ({interpreter})"));
+    }
+
+    Err(format!("QEMU is not registered for {arch}, {setup_hint}"))
+}
+
+/// An interpreter registered with `binfmt_misc`
+#[derive(Debug, Default)]
+struct BinfmtEntry {
+    enabled: bool,
+    interpreter: Option<String>,
+    flags: String,
+}
+
+impl BinfmtEntry {
+    fn parse(content: &str) -> Self {
+        let mut entry = BinfmtEntry::default();
+        for line in content.lines() {
+            if line == "enabled" {
+                entry.enabled = true;
+            } else if let Some(interpreter) = line.strip_prefix("interpreter ") {
+                entry.interpreter = Some(interpreter.trim().to_string());
+            } else if let Some(flags) = line.strip_prefix("flags:") {
+                entry.flags = flags.trim().to_string();
+            }
+        }
+        entry
+    }
+}
+
+/// The platform and virtual packages to solve a test environment for on the
+/// `current` platform. If the platform is emulated, the `__archspec` (which
+/// describes the CPU of the current machine) is replaced by the architecture
+/// of the emulated platform.
+pub fn test_platform(
+    current: Platform,
+    platform: &PlatformWithVirtualPackages,
+) -> PlatformWithVirtualPackages {
+    let mut platform = platform.clone();
+    if platform.platform != current && platform.platform != Platform::NoArch {
+        platform
+            .virtual_packages
+            .retain(|package| package.name.as_normalized() != "__archspec");
+        if let Some(arch) = platform.platform.arch() {
+            platform.virtual_packages.push(GenericVirtualPackage {
+                name: "__archspec".parse().unwrap(),
+                version: "1".parse().unwrap(),
+                build_string: arch.to_string(),
+            });
+        }
+    }
+    platform
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binfmt_dir(entries: &[(&str, &str)]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        fs_err::write(dir.path().join("status"), "enabled\n").unwrap();
+        fs_err::write(dir.path().join("register"), "").unwrap();
+        for (name, content) in entries {
+            fs_err::write(dir.path().join(name), content).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn test_check_emulation() {
+        let dir = binfmt_dir(&[
+            (
+                "qemu-aarch64",
+                "enabled\ninterpreter /usr/bin/qemu-aarch64-static\nflags: F\noffset 0\n",
+            ),
+            (
+                "qemu-ppc64le",
+                "disabled\ninterpreter /usr/bin/qemu-ppc64le-static\nflags: F\n",
+            ),
+            (
+                "qemu-s390x",
+                "enabled\ninterpreter /does/not/exist/qemu-s390x\nflags: \n",
+            ),
+        ]);
+        let check = |current, platform| check_emulation_with(current, platform, dir.path());
+
+        assert_eq!(
+            check(Platform::Linux64, Platform::Linux64),
+            Ok(Emulation::Native)
+        );
+        assert_eq!(
+            check(Platform::Linux64, Platform::NoArch),
+            Ok(Emulation::Native)
+        );
+        assert_eq!(
+            check(Platform::Linux64, Platform::Linux32),
+            Ok(Emulation::Native)
+        );
+        assert_eq!(
+            check(Platform::Linux64, Platform::LinuxAarch64),
+            Ok(Emulation::Emulated(
+                "QEMU (/usr/bin/qemu-aarch64-static)".to_string()
+            ))
+        );
+
+        let reason = check(Platform::Linux64, Platform::LinuxPpc64le).unwrap_err();
+        assert!(reason.contains("`qemu-ppc64le` is disabled"), "{reason}");
+        let reason = check(Platform::Linux64, Platform::LinuxS390X).unwrap_err();
+        assert!(reason.contains("does not exist"), "{reason}");
+        let reason = check(Platform::Linux64, Platform::LinuxRiscv64).unwrap_err();
+        assert!(
+            reason.contains("QEMU is not registered for riscv64"),
+            "{reason}"
+        );
+        assert!(check(Platform::Linux64, Platform::Win64).is_err());
+
+        // without binfmt_misc there is no emulation
+        let missing = dir.path().join("missing");
+        let reason =
+            check_emulation_with(Platform::Linux64, Platform::LinuxAarch64, &missing).unwrap_err();
+        assert!(reason.contains("binfmt_misc is not mounted"), "{reason}");
+    }
+
+    #[test]
+    fn test_test_platform() {
+        let current = PlatformWithVirtualPackages {
+            platform: Platform::Linux64,
+            virtual_packages: vec![
+                GenericVirtualPackage {
+                    name: "__glibc".parse().unwrap(),
+                    version: "2.35".parse().unwrap(),
+                    build_string: "0".to_string(),
+                },
+                GenericVirtualPackage {
+                    name: "__archspec".parse().unwrap(),
+                    version: "1".parse().unwrap(),
+                    build_string: "x86_64_v3".to_string(),
+                },
+            ],
+        };
+
+        let native = test_platform(Platform::Linux64, &current);
+        assert_eq!(native.virtual_packages, current.virtual_packages);
+
+        let emulated = test_platform(
+            Platform::Linux64,
+            &PlatformWithVirtualPackages {
+                platform: Platform::LinuxAarch64,
+                ..current.clone()
+            },
+        );
+        assert_eq!(emulated.platform, Platform::LinuxAarch64);
+        let archspec = emulated
+            .virtual_packages
+            .iter()
+            .filter(|package| package.name.as_normalized() == "__archspec")
+            .map(|package| package.build_string.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(archspec, vec!["aarch64"]);
+        assert!(emulated
+            .virtual_packages
+            .iter()
+            .any(|package| package.name.as_normalized() == "__glibc"));
+    }
+}
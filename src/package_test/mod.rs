@@ -1,7 +1,10 @@
 //! Tests that are run as part of the package build process.
+pub mod bulk;
 mod content_test;
+mod emulation;
 mod run_test;
 mod serialize_test;
 
-pub use run_test::{run_test, TestConfiguration, TestError};
+pub use emulation::{check_emulation, Emulation};
+pub use run_test::{run_test, KeepTestPrefixes, SkippedTest, TestConfiguration, TestError};
 pub(crate) use serialize_test::write_test_files;
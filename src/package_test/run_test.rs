@@ -6,21 +6,25 @@
 //! * `commands` - run a list of commands and check their exit code
 //! * `imports` - import a list of modules and check if they can be imported
 //! * `files` - check if a list of files exist
+//! * `exists` - check if commands and libraries exist in the test environment
 
 use std::{
-    collections::HashMap,
+    collections::{BTreeSet, HashMap},
     fmt::Write as fmt_write,
     io::Write,
     path::{Path, PathBuf},
     str::FromStr,
+    sync::Mutex,
 };
 
 use dunce::canonicalize;
 use fs_err as fs;
+use globset::Glob;
 use rattler::package_cache::CacheKey;
 use rattler_conda_types::{
-    package::{ArchiveIdentifier, IndexJson, PackageFile},
-    Channel, ChannelUrl, MatchSpec, ParseStrictness, Platform,
+    package::{ArchiveIdentifier, ArchiveType, IndexJson, PackageFile},
+    Channel, ChannelUrl, GenericVirtualPackage, MatchSpec, ParseStrictness, Platform,
+    RepoDataRecord,
 };
 use rattler_index::index;
 use rattler_shell::{
@@ -28,15 +32,19 @@ use rattler_shell::{
     shell::{Shell, ShellEnum},
 };
 use rattler_solve::{ChannelPriority, SolveStrategy};
+use serde::Serialize;
+use tokio_util::sync::CancellationToken;
 
+use super::emulation::{check_emulation, test_platform, Emulation};
 use crate::{
+    build_profile::ProfileCategory,
     env_vars,
     metadata::PlatformWithVirtualPackages,
     recipe::parser::{
-        CommandsTest, DownstreamTest, PerlTest, PythonTest, PythonVersion, Script, ScriptContent,
-        TestType,
+        CommandsTest, DownstreamTest, ExistsTest, PerlTest, PythonTest, PythonVersion, Script,
+        ScriptContent, TestType,
     },
-    render::solver::create_environment,
+    render::solver::{create_environment, install_packages},
     source::copy_dir::CopyDir,
     tool_configuration,
 };
@@ -91,6 +99,13 @@ pub enum TestError {
 
     #[error("could not determine target platform from package file (no index.json?)")]
     CouldNotDetermineTargetPlatform,
+
+    #[error("cannot run the tests of a {platform} package on {current}: {reason}")]
+    EmulationUnavailable {
+        platform: Platform,
+        current: Platform,
+        reason: String,
+    },
 }
 
 #[derive(Debug)]
@@ -105,6 +120,7 @@ impl Tests {
         environment: &Path,
         cwd: &Path,
         pkg_vars: &HashMap<String, String>,
+        cancellation_token: &CancellationToken,
     ) -> Result<(), TestError> {
         tracing::info!("Testing commands:");
 
@@ -135,7 +151,17 @@ impl Tests {
                 })?;
 
                 script
-                    .run_script(env_vars, tmp_dir.path(), cwd, environment, None, None, None)
+                    .run_script(
+                        env_vars,
+                        &[],
+                        tmp_dir.path(),
+                        cwd,
+                        environment,
+                        None,
+                        None,
+                        None,
+                        cancellation_token,
+                    )
                     .await
                     .map_err(|e| TestError::TestFailed(e.to_string()))?;
             }
@@ -147,7 +173,17 @@ impl Tests {
                 };
 
                 script
-                    .run_script(env_vars, tmp_dir.path(), cwd, environment, None, None, None)
+                    .run_script(
+                        env_vars,
+                        &[],
+                        tmp_dir.path(),
+                        cwd,
+                        environment,
+                        None,
+                        None,
+                        None,
+                        cancellation_token,
+                    )
                     .await
                     .map_err(|e| TestError::TestFailed(e.to_string()))?;
             }
@@ -200,8 +236,8 @@ pub struct TestConfiguration {
     pub host_platform: Option<PlatformWithVirtualPackages>,
     /// The platform and virtual packages of the current platform.
     pub current_platform: PlatformWithVirtualPackages,
-    /// If true, the test prefix will not be deleted after the test is run
-    pub keep_test_prefix: bool,
+    /// Which test prefixes are kept after the tests ran
+    pub keep_test_prefixes: KeepTestPrefixes,
     /// The channels to use for the test – do not forget to add the local build
     /// outputs channel if desired
     pub channels: Vec<ChannelUrl>,
@@ -209,10 +245,301 @@ pub struct TestConfiguration {
     pub channel_priority: ChannelPriority,
     /// The solve strategy to use when resolving dependencies
     pub solve_strategy: SolveStrategy,
+    /// Ignore packages that were published after this time when resolving
+    /// the test environments
+    pub exclude_newer: Option<chrono::DateTime<chrono::Utc>>,
+    /// The directory that relative `requirements.local` paths of tests are
+    /// resolved against (the recipe directory during a build). The current
+    /// directory is used if not set.
+    pub recipe_dir: Option<PathBuf>,
     /// The tool configuration
     pub tool_configuration: tool_configuration::Configuration,
 }
 
+/// A test that was skipped instead of run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedTest {
+    /// The package that the test belongs to
+    pub package: String,
+    /// The position of the test in the `tests` section
+    pub index: usize,
+    /// Why the test was skipped
+    pub reason: String,
+}
+
+impl std::fmt::Display for SkippedTest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "test {} of {} was skipped: {}",
+            self.index, self.package, self.reason
+        )
+    }
+}
+
+/// Which test prefixes are kept after the tests of a package ran
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KeepTestPrefixes {
+    /// Remove all test prefixes
+    #[default]
+    None,
+    /// Keep the prefixes of all tests
+    All,
+    /// Keep only the prefixes of the test that failed
+    Failing,
+    /// Keep the prefixes of the last N tests that ran
+    Last(usize),
+}
+
+impl FromStr for KeepTestPrefixes {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(Self::All),
+            "failing" => Ok(Self::Failing),
+            "none" => Ok(Self::None),
+            n => n.parse().map(Self::Last).map_err(|_| {
+                format!("invalid value `{n}`, expected `all`, `failing`, `none` or a number")
+            }),
+        }
+    }
+}
+
+impl KeepTestPrefixes {
+    /// Returns true if the prefix of any test may be kept
+    pub fn keeps_any(&self) -> bool {
+        !matches!(self, Self::None | Self::Last(0))
+    }
+
+    /// The positions of the test runs whose prefixes are kept
+    fn retained(&self, runs: &[TestRun]) -> BTreeSet<usize> {
+        match self {
+            Self::None => BTreeSet::new(),
+            Self::All => (0..runs.len()).collect(),
+            Self::Failing => runs
+                .iter()
+                .enumerate()
+                .filter(|(_, run)| run.failed)
+                .map(|(position, _)| position)
+                .collect(),
+            Self::Last(n) => (runs.len().saturating_sub(*n)..runs.len()).collect(),
+        }
+    }
+}
+
+/// A test that was run, with the prefixes it used
+#[derive(Debug)]
+struct TestRun {
+    /// The position of the test in the `tests` section (or `legacy`)
+    name: String,
+    /// The directory the environments of the test are created in
+    directory: PathBuf,
+    /// The prefixes that the test used
+    prefixes: Vec<PathBuf>,
+    /// Whether the test failed
+    failed: bool,
+}
+
+impl TestRun {
+    fn new(
+        name: impl Into<String>,
+        directory: PathBuf,
+        environments: &TestEnvironments,
+        failed: bool,
+    ) -> Self {
+        let mut prefixes = environments.take_used();
+        if prefixes.is_empty() {
+            prefixes.push(directory.clone());
+        }
+        Self {
+            name: name.into(),
+            directory,
+            prefixes,
+            failed,
+        }
+    }
+}
+
+/// Remove the test prefixes that should not be kept and print which test the
+/// kept prefixes belong to.
+fn clean_up_test_prefixes(
+    prefix: &Path,
+    runs: &[TestRun],
+    keep: KeepTestPrefixes,
+) -> Result<(), TestError> {
+    let retained = keep.retained(runs);
+    if retained.is_empty() {
+        if prefix.exists() {
+            fs::remove_dir_all(prefix)?;
+        }
+        return Ok(());
+    }
+
+    tracing::info!("Keeping test prefixes in {}:", prefix.display());
+    for (position, run) in runs.iter().enumerate() {
+        if retained.contains(&position) {
+            let status = if run.failed { "failed" } else { "passed" };
+            for test_prefix in &run.prefixes {
+                tracing::info!(
+                    "  test {} ({status}) -> {}",
+                    run.name,
+                    test_prefix.display()
+                );
+            }
+        } else if run.directory.exists() {
+            fs::remove_dir_all(&run.directory)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Keeps track of the test environments that are solved while running the
+/// tests of a single package. Tests that request the same set of specs for
+/// the same platform reuse the solution instead of solving again. Every test
+/// still gets its own prefix, so that a test cannot see the changes that an
+/// earlier test made to its environment.
+#[derive(Default)]
+pub struct TestEnvironments {
+    /// The solved environments by key (platform and sorted specs)
+    solved: Mutex<HashMap<String, Vec<RepoDataRecord>>>,
+    /// The prefixes that were created since the last call to `take_used`
+    used: Mutex<Vec<PathBuf>>,
+}
+
+impl TestEnvironments {
+    fn key(specs: &[MatchSpec], platform: &PlatformWithVirtualPackages) -> String {
+        let mut specs = specs.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        specs.sort();
+        let mut virtual_packages = platform
+            .virtual_packages
+            .iter()
+            .map(|vp| vp.to_string())
+            .collect::<Vec<_>>();
+        virtual_packages.sort();
+        format!(
+            "{}:{}:{}",
+            platform.platform,
+            virtual_packages.join(","),
+            specs.join(",")
+        )
+    }
+
+    /// Creates an environment for the given specs in `target_prefix`. If an
+    /// environment with the same specs was solved before, the solution is
+    /// installed without solving again.
+    pub async fn create(
+        &self,
+        specs: &[MatchSpec],
+        platform: &PlatformWithVirtualPackages,
+        target_prefix: &Path,
+        config: &TestConfiguration,
+    ) -> Result<PathBuf, TestError> {
+        let key = Self::key(specs, platform);
+
+        let solved = self.solved.lock().unwrap().get(&key).cloned();
+        let records = match solved {
+            Some(records) => {
+                tracing::info!(
+                    "Reusing the solved test environment for {}",
+                    target_prefix.display()
+                );
+                install_packages(
+                    "test",
+                    &records,
+                    platform.platform,
+                    target_prefix,
+                    &config.tool_configuration,
+                )
+                .await
+                .map_err(TestError::TestEnvironmentSetup)?;
+                records
+            }
+            None => {
+                let records = create_environment(
+                    "test",
+                    specs,
+                    platform,
+                    target_prefix,
+                    &config.channels,
+                    &config.tool_configuration,
+                    config.channel_priority,
+                    config.solve_strategy,
+                    config.exclude_newer,
+                )
+                .await
+                .map_err(TestError::TestEnvironmentSetup)?;
+                self.solved.lock().unwrap().insert(key, records.clone());
+                records
+            }
+        };
+
+        if config.keep_test_prefixes.keeps_any() {
+            write_environment_manifest(
+                target_prefix,
+                specs,
+                platform.platform,
+                &config.channels,
+                &records,
+            )?;
+        }
+        self.mark_used(target_prefix);
+
+        Ok(target_prefix.to_path_buf())
+    }
+
+    fn mark_used(&self, prefix: &Path) {
+        let mut used = self.used.lock().unwrap();
+        if !used.iter().any(|p| p == prefix) {
+            used.push(prefix.to_path_buf());
+        }
+    }
+
+    /// Returns the prefixes that were used since the last call.
+    fn take_used(&self) -> Vec<PathBuf> {
+        std::mem::take(&mut *self.used.lock().unwrap())
+    }
+}
+
+/// The name of the file in a kept test prefix that lists what was installed
+const TEST_ENVIRONMENT_MANIFEST: &str = "test-environment.json";
+
+/// What was installed into a test prefix, so that a kept prefix can be
+/// inspected without solving the environment again
+#[derive(Serialize)]
+struct TestEnvironmentManifest<'a> {
+    /// The platform of the environment
+    platform: Platform,
+    /// The specs that were solved
+    specs: Vec<String>,
+    /// The channels that were used to solve the environment
+    channels: &'a [ChannelUrl],
+    /// The installed packages (the same records as in `conda-meta`)
+    packages: &'a [RepoDataRecord],
+}
+
+/// Write the [`TestEnvironmentManifest`] to `test-environment.json` in the
+/// prefix.
+fn write_environment_manifest(
+    prefix: &Path,
+    specs: &[MatchSpec],
+    platform: Platform,
+    channels: &[ChannelUrl],
+    records: &[RepoDataRecord],
+) -> Result<(), TestError> {
+    let manifest = TestEnvironmentManifest {
+        platform,
+        specs: specs.iter().map(|spec| spec.to_string()).collect(),
+        channels,
+        packages: records,
+    };
+    let path = prefix.join(TEST_ENVIRONMENT_MANIFEST);
+    fs::write(&path, serde_json::to_string_pretty(&manifest)?)?;
+    tracing::info!("Wrote the test environment records to {}", path.display());
+    Ok(())
+}
+
 fn env_vars_from_package(index_json: &IndexJson) -> HashMap<String, String> {
     let mut res = HashMap::new();
 
@@ -256,14 +583,15 @@ fn env_vars_from_package(index_json: &IndexJson) -> HashMap<String, String> {
 ///
 /// # Returns
 ///
-/// * `Ok(())` if the test was successful
+/// * `Ok(skipped)` if the test was successful, with the tests that were
+///   skipped (e.g. because a required virtual package is missing)
 /// * `Err(TestError::TestFailed)` if the test failed
 #[async_recursion::async_recursion]
 pub async fn run_test(
     package_file: &Path,
     config: &TestConfiguration,
     downstream_package: Option<PathBuf>,
-) -> Result<(), TestError> {
+) -> Result<Vec<SkippedTest>, TestError> {
     let tmp_repo = tempfile::tempdir()?;
 
     // create the test prefix
@@ -336,16 +664,36 @@ pub async fn run_test(
     let mut channels = config.channels.clone();
     channels.insert(0, Channel::from_directory(tmp_repo.path()).base_url);
 
-    let host_platform = config.host_platform.clone().unwrap_or_else(|| {
-        if target_platform == Platform::NoArch {
-            config.current_platform.clone()
-        } else {
-            PlatformWithVirtualPackages {
+    // the test environment is solved explicitly for the platform of the package,
+    // which is emulated if it is not the current platform
+    let current_platform = config.current_platform.platform;
+    let host_platform = match &config.host_platform {
+        Some(host_platform) => test_platform(current_platform, host_platform),
+        None if target_platform == Platform::NoArch => config.current_platform.clone(),
+        None => test_platform(
+            current_platform,
+            &PlatformWithVirtualPackages {
                 platform: target_platform,
                 virtual_packages: config.current_platform.virtual_packages.clone(),
-            }
+            },
+        ),
+    };
+    match check_emulation(current_platform, host_platform.platform) {
+        Ok(Emulation::Native) => {}
+        Ok(Emulation::Emulated(emulator)) => tracing::info!(
+            "Testing the {} package on {} with {}",
+            host_platform.platform,
+            current_platform,
+            emulator
+        ),
+        Err(reason) => {
+            return Err(TestError::EmulationUnavailable {
+                platform: host_platform.platform,
+                current: current_platform,
+                reason,
+            })
         }
-    });
+    }
 
     let config = TestConfiguration {
         target_platform: Some(target_platform),
@@ -363,6 +711,12 @@ pub async fn run_test(
 
     let index_json = IndexJson::from_package_directory(&package_folder)?;
     let env = env_vars_from_package(&index_json);
+    let environments = TestEnvironments::default();
+    let mut skipped = Vec::new();
+    // every test gets its own directory in the prefix so that the prefixes of
+    // several tests can be kept
+    let mut runs = Vec::new();
+    let mut failure = None;
     // extract package in place
     if package_folder.join("info/test").exists() {
         let test_dep_json = PathBuf::from("info/test/test_time_dependencies.json");
@@ -377,7 +731,8 @@ pub async fn run_test(
             .map(|s| MatchSpec::from_str(s, ParseStrictness::Lenient))
             .collect::<Result<Vec<_>, _>>()?;
 
-        tracing::info!("Creating test environment in {:?}", prefix);
+        let test_directory = prefix.join("legacy");
+        tracing::info!("Creating test environment in {:?}", test_directory);
         let match_spec = MatchSpec::from_str(
             format!("{}={}={}", pkg.name, pkg.version, pkg.build_string).as_str(),
             ParseStrictness::Lenient,
@@ -385,77 +740,168 @@ pub async fn run_test(
         .map_err(|e| TestError::MatchSpecParse(e.to_string()))?;
         dependencies.push(match_spec);
 
-        create_environment(
-            "test",
-            &dependencies,
-            &host_platform,
-            &prefix,
-            &config.channels,
-            &config.tool_configuration,
-            config.channel_priority,
-            config.solve_strategy,
-        )
-        .await
-        .map_err(TestError::TestEnvironmentSetup)?;
+        let result = async {
+            let test_prefix = environments
+                .create(&dependencies, &host_platform, &test_directory, &config)
+                .await?;
 
-        // These are the legacy tests
-        let (test_folder, tests) = legacy_tests_from_folder(&package_folder).await?;
+            // These are the legacy tests
+            let (test_folder, tests) = legacy_tests_from_folder(&package_folder).await?;
 
-        for test in tests {
-            test.run(&prefix, &test_folder, &env).await?;
+            for test in tests {
+                test.run(
+                    &test_prefix,
+                    &test_folder,
+                    &env,
+                    &config.tool_configuration.cancellation_token,
+                )
+                .await?;
+            }
+            Ok::<_, TestError>(())
         }
+        .await;
 
-        tracing::info!(
-            "{} all tests passed!",
-            console::style(console::Emoji("✔", "")).green()
-        );
+        runs.push(TestRun::new(
+            "legacy",
+            test_directory,
+            &environments,
+            result.is_err(),
+        ));
+        match result {
+            Ok(()) => tracing::info!(
+                "{} all tests passed!",
+                console::style(console::Emoji("✔", "")).green()
+            ),
+            Err(e) => failure = Some(e),
+        }
     }
 
-    if package_folder.join("info/tests/tests.yaml").exists() {
+    if failure.is_none() && package_folder.join("info/tests/tests.yaml").exists() {
         let tests = fs::read_to_string(package_folder.join("info/tests/tests.yaml"))?;
         let tests: Vec<TestType> = serde_yaml::from_str(&tests)?;
 
-        for test in tests {
-            match test {
+        for (index, test) in tests.into_iter().enumerate() {
+            let test_directory = prefix.join(index.to_string());
+            let result = match test {
                 TestType::Command(c) => {
-                    c.run_test(&pkg, &package_folder, &prefix, &config, &env)
-                        .await?
+                    let missing = c.missing_virtual_packages(&config.current_platform)?;
+                    if !missing.is_empty() {
+                        let skipped_test = SkippedTest {
+                            package: pkg.to_string(),
+                            index,
+                            reason: format!(
+                                "the virtual package(s) {} are not available on this machine",
+                                missing
+                                    .iter()
+                                    .map(|spec| format!("`{spec}`"))
+                                    .collect::<Vec<_>>()
+                                    .join(", ")
+                            ),
+                        };
+                        tracing::warn!("Skipping {}", skipped_test);
+                        skipped.push(skipped_test);
+                        continue;
+                    }
+                    c.run_test(
+                        &pkg,
+                        &package_folder,
+                        &test_directory,
+                        &config,
+                        &env,
+                        &environments,
+                    )
+                    .await
                 }
                 TestType::Python { python } => {
                     python
-                        .run_test(&pkg, &package_folder, &prefix, &config)
-                        .await?
+                        .run_test(
+                            &pkg,
+                            &package_folder,
+                            &test_directory,
+                            &config,
+                            &environments,
+                        )
+                        .await
                 }
                 TestType::Perl { perl } => {
-                    perl.run_test(&pkg, &package_folder, &prefix, &config)
-                        .await?
+                    perl.run_test(
+                        &pkg,
+                        &package_folder,
+                        &test_directory,
+                        &config,
+                        &environments,
+                    )
+                    .await
+                }
+                TestType::Exists { exists } => {
+                    exists
+                        .run_test(&pkg, &test_directory, &config, &environments)
+                        .await
                 }
                 TestType::Downstream(downstream) if downstream_package.is_none() => {
                     downstream
-                        .run_test(&pkg, package_file, &prefix, &config)
-                        .await?
+                        .run_test(&pkg, package_file, &test_directory, &config)
+                        .await
                 }
                 TestType::Downstream(_) => {
                     tracing::info!(
                         "Skipping downstream test as we are already testing a downstream package"
-                    )
+                    );
+                    continue;
                 }
                 // This test already runs during the build process and we don't need to run it again
-                TestType::PackageContents { .. } => {}
+                TestType::PackageContents { .. } => continue,
+            };
+
+            runs.push(TestRun::new(
+                index.to_string(),
+                test_directory,
+                &environments,
+                result.is_err(),
+            ));
+            if let Err(e) = result {
+                failure = Some(e);
+                break;
             }
         }
 
-        tracing::info!(
-            "{} all tests passed!",
-            console::style(console::Emoji("✔", "")).green()
-        );
+        if failure.is_none() {
+            if skipped.is_empty() {
+                tracing::info!(
+                    "{} all tests passed!",
+                    console::style(console::Emoji("✔", "")).green()
+                );
+            } else {
+                tracing::info!(
+                    "{} all tests passed ({} skipped)!",
+                    console::style(console::Emoji("✔", "")).green(),
+                    skipped.len()
+                );
+            }
+        }
     }
 
-    if prefix.exists() {
-        fs::remove_dir_all(prefix)?;
+    clean_up_test_prefixes(&prefix, &runs, config.keep_test_prefixes)?;
+
+    match failure {
+        Some(e) => Err(e),
+        None => Ok(skipped),
     }
+}
 
-    Ok(())
+/// Returns true if one of the virtual packages satisfies the spec
+fn virtual_package_available(spec: &MatchSpec, virtual_packages: &[GenericVirtualPackage]) -> bool {
+    virtual_packages.iter().any(|package| {
+        spec.name.as_ref() == Some(&package.name)
+            && spec
+                .version
+                .as_ref()
+                .map_or(true, |version| version.matches(&package.version))
+            && spec
+                .build
+                .as_ref()
+                .map_or(true, |build| build.matches(&package.build_string))
+    })
 }
 
 impl PythonTest {
@@ -466,8 +912,12 @@ impl PythonTest {
         path: &Path,
         prefix: &Path,
         config: &TestConfiguration,
+        environments: &TestEnvironments,
     ) -> Result<(), TestError> {
-        let span = tracing::info_span!("Running python test(s)");
+        let span = tracing::info_span!(
+            "Running python test(s)",
+            profile = ProfileCategory::Tests.as_str()
+        );
         let _guard = span.enter();
 
         // The version spec of the package being built
@@ -517,8 +967,15 @@ impl PythonTest {
 
         // Run tests for each python version
         for (python_version, dependencies) in dependencies_map {
-            self.run_test_inner(python_version, dependencies, path, prefix, config)
-                .await?;
+            self.run_test_inner(
+                python_version,
+                dependencies,
+                path,
+                prefix,
+                config,
+                environments,
+            )
+            .await?;
         }
 
         Ok(())
@@ -531,6 +988,7 @@ impl PythonTest {
         path: &Path,
         prefix: &Path,
         config: &TestConfiguration,
+        environments: &TestEnvironments,
     ) -> Result<(), TestError> {
         let span_message = match python_version.as_str() {
             "" => "Testing with default python version".to_string(),
@@ -539,21 +997,17 @@ impl PythonTest {
         let span = tracing::info_span!("", message = %span_message);
         let _guard = span.enter();
 
-        create_environment(
-            "test",
-            &dependencies,
-            config
-                .host_platform
-                .as_ref()
-                .unwrap_or(&config.current_platform),
-            prefix,
-            &config.channels,
-            &config.tool_configuration,
-            config.channel_priority,
-            config.solve_strategy,
-        )
-        .await
-        .map_err(TestError::TestEnvironmentSetup)?;
+        let prefix = &environments
+            .create(
+                &dependencies,
+                config
+                    .host_platform
+                    .as_ref()
+                    .unwrap_or(&config.current_platform),
+                prefix,
+                config,
+            )
+            .await?;
 
         let mut imports = String::new();
         for import in &self.imports {
@@ -570,12 +1024,14 @@ impl PythonTest {
         script
             .run_script(
                 Default::default(),
+                &[],
                 tmp_dir.path(),
                 path,
                 prefix,
                 None,
                 None,
                 None,
+                &config.tool_configuration.cancellation_token,
             )
             .await
             .map_err(|e| TestError::TestFailed(e.to_string()))?;
@@ -591,7 +1047,17 @@ impl PythonTest {
                 ..Script::default()
             };
             script
-                .run_script(Default::default(), path, path, prefix, None, None, None)
+                .run_script(
+                    Default::default(),
+                    &[],
+                    path,
+                    path,
+                    prefix,
+                    None,
+                    None,
+                    None,
+                    &config.tool_configuration.cancellation_token,
+                )
                 .await
                 .map_err(|e| TestError::TestFailed(e.to_string()))?;
 
@@ -612,8 +1078,12 @@ impl PerlTest {
         path: &Path,
         prefix: &Path,
         config: &TestConfiguration,
+        environments: &TestEnvironments,
     ) -> Result<(), TestError> {
-        let span = tracing::info_span!("Running perl test");
+        let span = tracing::info_span!(
+            "Running perl test",
+            profile = ProfileCategory::Tests.as_str()
+        );
         let _guard = span.enter();
 
         let match_spec = MatchSpec::from_str(
@@ -623,21 +1093,17 @@ impl PerlTest {
 
         let dependencies = vec!["perl".parse().unwrap(), match_spec];
 
-        create_environment(
-            "test",
-            &dependencies,
-            config
-                .host_platform
-                .as_ref()
-                .unwrap_or(&config.current_platform),
-            prefix,
-            &config.channels,
-            &config.tool_configuration,
-            config.channel_priority,
-            config.solve_strategy,
-        )
-        .await
-        .map_err(TestError::TestEnvironmentSetup)?;
+        let prefix = &environments
+            .create(
+                &dependencies,
+                config
+                    .host_platform
+                    .as_ref()
+                    .unwrap_or(&config.current_platform),
+                prefix,
+                config,
+            )
+            .await?;
 
         let mut imports = String::new();
         tracing::info!("Testing perl imports:\n");
@@ -658,12 +1124,14 @@ impl PerlTest {
         script
             .run_script(
                 Default::default(),
+                &[],
                 tmp_dir.path(),
                 path,
                 prefix,
                 None,
                 None,
                 None,
+                &config.tool_configuration.cancellation_token,
             )
             .await
             .map_err(|e| TestError::TestFailed(e.to_string()))?;
@@ -672,7 +1140,249 @@ impl PerlTest {
     }
 }
 
+/// Find an executable with the given name in the `PATH` directories of the
+/// prefix
+fn find_command(prefix: &Path, command: &str, platform: Platform) -> Option<PathBuf> {
+    if platform.is_windows() {
+        // This is usually encoded as `PATHEXT` in the environment
+        let extensions = ["", ".exe", ".bat", ".cmd", ".com", ".ps1"];
+        let directories = [
+            "",
+            "Library/mingw-w64/bin",
+            "Library/usr/bin",
+            "Library/bin",
+            "Scripts",
+            "bin",
+        ];
+        directories
+            .iter()
+            .flat_map(|dir| {
+                extensions
+                    .iter()
+                    .map(move |ext| prefix.join(dir).join(format!("{command}{ext}")))
+            })
+            .find(|path| path.is_file())
+    } else {
+        Some(prefix.join("bin").join(command)).filter(|path| path.is_file())
+    }
+}
+
+/// Find a shared library in the library directory of the prefix. The name can
+/// be given with or without the `lib` prefix (`libbar` or `bar`).
+fn find_library(prefix: &Path, library: &str, platform: Platform) -> Option<PathBuf> {
+    let base = library.strip_prefix("lib").unwrap_or(library);
+    let names = [library.to_string(), format!("lib{base}"), base.to_string()];
+
+    let lib_dir = if platform.is_windows() {
+        prefix.join("Library/bin")
+    } else {
+        prefix.join("lib")
+    };
+
+    let matches = |file_name: &str| {
+        names.iter().any(|name| {
+            if platform.is_windows() {
+                file_name.eq_ignore_ascii_case(&format!("{name}.dll"))
+            } else if platform.is_osx() {
+                file_name == format!("{name}.dylib")
+                    || (file_name.starts_with(&format!("{name}.")) && file_name.ends_with(".dylib"))
+            } else {
+                file_name == format!("{name}.so") || file_name.starts_with(&format!("{name}.so."))
+            }
+        })
+    };
+
+    fs::read_dir(lib_dir)
+        .ok()?
+        .filter_map(Result::ok)
+        .find(|entry| matches(&entry.file_name().to_string_lossy()) && entry.path().is_file())
+        .map(|entry| entry.path())
+}
+
+impl ExistsTest {
+    /// Execute the exists test
+    pub async fn run_test(
+        &self,
+        pkg: &ArchiveIdentifier,
+        prefix: &Path,
+        config: &TestConfiguration,
+        environments: &TestEnvironments,
+    ) -> Result<(), TestError> {
+        let span = tracing::info_span!(
+            "Running exists test",
+            profile = ProfileCategory::Tests.as_str()
+        );
+        let _guard = span.enter();
+
+        let match_spec = MatchSpec::from_str(
+            format!("{}={}={}", pkg.name, pkg.version, pkg.build_string).as_str(),
+            ParseStrictness::Lenient,
+        )?;
+
+        let platform = config
+            .host_platform
+            .as_ref()
+            .unwrap_or(&config.current_platform);
+
+        let prefix = environments
+            .create(&[match_spec], platform, prefix, config)
+            .await?;
+
+        let mut missing = Vec::new();
+        let checks = self
+            .commands
+            .iter()
+            .map(|command| {
+                (
+                    "command",
+                    command,
+                    find_command(&prefix, command, platform.platform),
+                )
+            })
+            .chain(self.libraries.iter().map(|library| {
+                (
+                    "library",
+                    library,
+                    find_library(&prefix, library, platform.platform),
+                )
+            }));
+
+        for (kind, name, found) in checks {
+            match found {
+                Some(path) => tracing::info!(
+                    "{} {kind} `{name}` found at {}",
+                    console::style(console::Emoji("✔", "")).green(),
+                    path.display()
+                ),
+                None => {
+                    tracing::error!(
+                        "{} {kind} `{name}` not found",
+                        console::style(console::Emoji("✘", "")).red()
+                    );
+                    missing.push(format!("{kind} `{name}`"));
+                }
+            }
+        }
+
+        if !missing.is_empty() {
+            return Err(TestError::TestFailed(format!(
+                "not found in the test environment: {}",
+                missing.join(", ")
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// Find the packages that match the `requirements.local` paths of a test.
+/// Wildcards are supported in the file name.
+pub(crate) fn find_local_packages(
+    paths: &[String],
+    base_dir: &Path,
+) -> Result<Vec<PathBuf>, TestError> {
+    let mut packages = Vec::new();
+    for path in paths {
+        let path = base_dir.join(path);
+        let (Some(dir), Some(file_name)) = (path.parent(), path.file_name()) else {
+            return Err(TestError::TestFailed(format!(
+                "invalid local package path: {}",
+                path.display()
+            )));
+        };
+
+        let matcher = Glob::new(&file_name.to_string_lossy())?.compile_matcher();
+        let mut matches = Vec::new();
+        if dir.is_dir() {
+            for entry in fs::read_dir(dir)? {
+                let entry = entry?.path();
+                if entry.is_file()
+                    && ArchiveType::try_from(&entry).is_some()
+                    && entry.file_name().is_some_and(|name| matcher.is_match(name))
+                {
+                    matches.push(entry);
+                }
+            }
+        }
+
+        if matches.is_empty() {
+            return Err(TestError::TestFailed(format!(
+                "no local package found for {}",
+                path.display()
+            )));
+        }
+        matches.sort();
+        packages.extend(matches);
+    }
+    Ok(packages)
+}
+
+/// Copy the local packages into a temporary channel and index it. Returns the
+/// channel directory together with exact specs for all packages.
+pub(crate) fn create_local_channel(
+    packages: &[PathBuf],
+) -> Result<(tempfile::TempDir, Vec<MatchSpec>), TestError> {
+    let channel_dir = tempfile::tempdir()?;
+    let mut subdirs = BTreeSet::new();
+    let mut specs = Vec::new();
+
+    for package in packages {
+        let index_json: IndexJson = rattler_package_streaming::seek::read_package_file(package)
+            .map_err(|e| {
+                TestError::TestFailed(format!(
+                    "failed to read local package {}: {}",
+                    package.display(),
+                    e
+                ))
+            })?;
+        let subdir = index_json
+            .subdir
+            .clone()
+            .unwrap_or_else(|| Platform::NoArch.to_string());
+        let file_name = package
+            .file_name()
+            .ok_or(TestError::MissingPackageFileName)?;
+        fs::create_dir_all(channel_dir.path().join(&subdir))?;
+        fs::copy(package, channel_dir.path().join(&subdir).join(file_name))?;
+
+        specs.push(MatchSpec::from_str(
+            &format!(
+                "{}={}={}",
+                index_json.name.as_normalized(),
+                index_json.version,
+                index_json.build
+            ),
+            ParseStrictness::Lenient,
+        )?);
+        subdirs.insert(subdir);
+    }
+
+    for subdir in subdirs {
+        let platform =
+            Platform::from_str(&subdir).map_err(|_| TestError::CouldNotDetermineTargetPlatform)?;
+        index(channel_dir.path(), Some(&platform))?;
+    }
+
+    Ok((channel_dir, specs))
+}
+
 impl CommandsTest {
+    /// Returns the required virtual packages (`requirements.virtual`) that
+    /// are not available on the given platform
+    pub fn missing_virtual_packages(
+        &self,
+        platform: &PlatformWithVirtualPackages,
+    ) -> Result<Vec<MatchSpec>, TestError> {
+        let mut missing = Vec::new();
+        for spec in &self.requirements.virtual_packages {
+            let spec = MatchSpec::from_str(spec, ParseStrictness::Lenient)?;
+            if !virtual_package_available(&spec, &platform.virtual_packages) {
+                missing.push(spec);
+            }
+        }
+        Ok(missing)
+    }
+
     /// Execute the command test
     pub async fn run_test(
         &self,
@@ -681,10 +1391,15 @@ impl CommandsTest {
         test_directory: &Path,
         config: &TestConfiguration,
         pkg_vars: &HashMap<String, String>,
+        environments: &TestEnvironments,
     ) -> Result<(), TestError> {
         let deps = self.requirements.clone();
 
-        let span = tracing::info_span!("Running script test for", recipe = pkg.to_string());
+        let span = tracing::info_span!(
+            "Running script test for",
+            recipe = pkg.to_string(),
+            profile = ProfileCategory::Tests.as_str()
+        );
         let _guard = span.enter();
 
         let build_prefix = if !deps.build.is_empty() {
@@ -696,19 +1411,16 @@ impl CommandsTest {
                 .map(|s| MatchSpec::from_str(s, ParseStrictness::Lenient))
                 .collect::<Result<Vec<_>, _>>()?;
 
-            create_environment(
-                "test",
-                &build_dependencies,
-                &config.current_platform,
-                &build_prefix,
-                &config.channels,
-                &config.tool_configuration,
-                config.channel_priority,
-                config.solve_strategy,
+            Some(
+                environments
+                    .create(
+                        &build_dependencies,
+                        &config.current_platform,
+                        &build_prefix,
+                        config,
+                    )
+                    .await?,
             )
-            .await
-            .map_err(TestError::TestEnvironmentSetup)?;
-            Some(build_prefix)
         } else {
             None
         };
@@ -725,24 +1437,52 @@ impl CommandsTest {
             ParseStrictness::Lenient,
         )?);
 
+        // locally built packages are served from a temporary channel that
+        // takes precedence over all other channels
+        let local_channel = if !deps.local.is_empty() {
+            let base_dir = match &config.recipe_dir {
+                Some(recipe_dir) => recipe_dir.clone(),
+                None => std::env::current_dir()?,
+            };
+            let packages = find_local_packages(&deps.local, &base_dir)?;
+            tracing::info!("Adding local packages to the test environment:");
+            for package in &packages {
+                tracing::info!("  - {}", package.display());
+            }
+            let (channel_dir, specs) = create_local_channel(&packages)?;
+            dependencies.extend(specs);
+            Some(channel_dir)
+        } else {
+            None
+        };
+
+        let local_config;
+        let run_config = match &local_channel {
+            Some(channel_dir) => {
+                let mut channels = config.channels.clone();
+                channels.insert(0, Channel::from_directory(channel_dir.path()).base_url);
+                local_config = TestConfiguration {
+                    channels,
+                    ..config.clone()
+                };
+                &local_config
+            }
+            None => config,
+        };
+
         let platform = config
             .host_platform
             .as_ref()
             .unwrap_or(&config.current_platform);
 
-        let run_prefix = test_directory.join("run");
-        create_environment(
-            "test",
-            &dependencies,
-            platform,
-            &run_prefix,
-            &config.channels,
-            &config.tool_configuration,
-            config.channel_priority,
-            config.solve_strategy,
-        )
-        .await
-        .map_err(TestError::TestEnvironmentSetup)?;
+        let run_prefix = environments
+            .create(
+                &dependencies,
+                platform,
+                &test_directory.join("run"),
+                run_config,
+            )
+            .await?;
 
         let platform = Platform::current();
         let mut env_vars = env_vars::os_vars(&run_prefix, &platform);
@@ -767,12 +1507,14 @@ impl CommandsTest {
         self.script
             .run_script(
                 env_vars,
+                &[],
                 tmp_dir.path(),
                 path,
                 &run_prefix,
                 build_prefix.as_ref(),
                 None,
                 None,
+                &config.tool_configuration.cancellation_token,
             )
             .await
             .map_err(|e| TestError::TestFailed(e.to_string()))?;
@@ -782,7 +1524,9 @@ impl CommandsTest {
 }
 
 impl DownstreamTest {
-    /// Execute the command test
+    /// Execute the downstream test. The downstream package is resolved together with the
+    /// package under test (the local channel takes the highest priority) and its tests are
+    /// run against the freshly built package.
     pub async fn run_test(
         &self,
         pkg: &ArchiveIdentifier,
@@ -792,7 +1536,11 @@ impl DownstreamTest {
     ) -> Result<(), TestError> {
         let downstream_spec = self.downstream.clone();
 
-        let span = tracing::info_span!("Running downstream test for", package = downstream_spec);
+        let span = tracing::info_span!(
+            "Running downstream test for",
+            package = downstream_spec,
+            profile = ProfileCategory::Tests.as_str()
+        );
         let _guard = span.enter();
 
         // first try to resolve an environment with the downstream spec and our
@@ -814,11 +1562,21 @@ impl DownstreamTest {
             &config.tool_configuration,
             config.channel_priority,
             config.solve_strategy,
+            config.exclude_newer,
         )
         .await;
 
         match resolved {
             Ok(solution) => {
+                if config.keep_test_prefixes.keeps_any() {
+                    write_environment_manifest(
+                        prefix,
+                        &match_specs,
+                        config.current_platform.platform,
+                        &config.channels,
+                        &solution,
+                    )?;
+                }
                 let spec_name = match_specs[0].name.clone().expect("matchspec has a name");
                 // we found a solution, so let's run the downstream test with that particular
                 // package!
@@ -835,15 +1593,33 @@ impl DownstreamTest {
                 let package_file = temp_dir.path().join(&downstream_package.file_name);
 
                 if downstream_package.url.scheme() == "file" {
-                    fs::copy(
-                        downstream_package.url.to_file_path().unwrap(),
-                        &package_file,
-                    )?;
+                    let path = downstream_package.url.to_file_path().map_err(|_| {
+                        TestError::TestFailed(format!(
+                            "invalid file url for downstream package: {}",
+                            downstream_package.url
+                        ))
+                    })?;
+                    fs::copy(path, &package_file)?;
                 } else {
-                    let package_dl = reqwest::get(downstream_package.url.clone()).await.unwrap();
+                    let bytes = reqwest::get(downstream_package.url.clone())
+                        .await
+                        .and_then(|response| response.error_for_status())
+                        .map_err(|e| {
+                            TestError::TestFailed(format!(
+                                "failed to download downstream package: {}",
+                                e
+                            ))
+                        })?
+                        .bytes()
+                        .await
+                        .map_err(|e| {
+                            TestError::TestFailed(format!(
+                                "failed to download downstream package: {}",
+                                e
+                            ))
+                        })?;
                     // write out the package to a temporary directory
                     let mut file = fs::File::create(&package_file)?;
-                    let bytes = package_dl.bytes().await.unwrap();
                     file.write_all(&bytes)?;
                 }
 
@@ -855,15 +1631,270 @@ impl DownstreamTest {
                         tracing::error!("Downstream test with {:?} failed", &package_file);
                     })?;
             }
-            Err(e) => {
+            Err(e) if self.skip_unsolvable => {
                 // ignore the error
                 tracing::warn!(
                     "Downstream test could not run. Environment might be unsolvable: {:?}",
                     e
                 );
             }
+            Err(e) => {
+                return Err(TestError::TestFailed(format!(
+                    "downstream package `{}` could not be resolved with the package under test: {:?}",
+                    downstream_spec, e
+                )));
+            }
         }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_local_packages() {
+        let dir = tempfile::tempdir().unwrap();
+        let outputs = dir.path().join("other-output");
+        fs::create_dir_all(&outputs).unwrap();
+        for file in ["foo-1.0-h123_0.conda", "bar-2.0-0.tar.bz2", "README.md"] {
+            fs::write(outputs.join(file), "").unwrap();
+        }
+        let recipe_dir = dir.path().join("recipe");
+        fs::create_dir_all(&recipe_dir).unwrap();
+
+        let packages =
+            find_local_packages(&["../other-output/*".to_string()], &recipe_dir).unwrap();
+        let names = packages
+            .iter()
+            .map(|p| p.file_name().unwrap().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["bar-2.0-0.tar.bz2", "foo-1.0-h123_0.conda"]);
+
+        assert!(
+            find_local_packages(&["../other-output/baz-*.conda".to_string()], &recipe_dir).is_err()
+        );
+    }
+
+    #[test]
+    fn test_write_environment_manifest() {
+        let prefix = tempfile::tempdir().unwrap();
+        let record = RepoDataRecord {
+            package_record: rattler_conda_types::PackageRecord::new(
+                "zlib".parse().unwrap(),
+                "1.3.1".parse::<rattler_conda_types::Version>().unwrap(),
+                "h123_0".to_string(),
+            ),
+            file_name: "zlib-1.3.1-h123_0.conda".to_string(),
+            url: url::Url::parse(
+                "https://conda.anaconda.org/conda-forge/linux-64/zlib-1.3.1-h123_0.conda",
+            )
+            .unwrap(),
+            channel: Some("https://conda.anaconda.org/conda-forge/".to_string()),
+        };
+        let channels = vec![ChannelUrl::from(
+            url::Url::parse("https://conda.anaconda.org/conda-forge/").unwrap(),
+        )];
+
+        write_environment_manifest(
+            prefix.path(),
+            &["zlib >=1.3".parse().unwrap()],
+            Platform::Linux64,
+            &channels,
+            &[record],
+        )
+        .unwrap();
+
+        let manifest: serde_json::Value = serde_json::from_str(
+            &fs::read_to_string(prefix.path().join(TEST_ENVIRONMENT_MANIFEST)).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(manifest["platform"], "linux-64");
+        assert_eq!(manifest["specs"], serde_json::json!(["zlib >=1.3"]));
+        assert_eq!(
+            manifest["channels"],
+            serde_json::json!(["https://conda.anaconda.org/conda-forge/"])
+        );
+        let packages = manifest["packages"].as_array().unwrap();
+        assert_eq!(packages.len(), 1);
+        assert_eq!(packages[0]["name"], "zlib");
+        assert_eq!(packages[0]["version"], "1.3.1");
+        assert_eq!(packages[0]["fn"], "zlib-1.3.1-h123_0.conda");
+    }
+
+    #[test]
+    fn test_find_command_and_library() {
+        let prefix = tempfile::tempdir().unwrap();
+        let prefix = prefix.path();
+        for dir in ["bin", "lib", "Library/bin", "Scripts"] {
+            fs::create_dir_all(prefix.join(dir)).unwrap();
+        }
+        for file in [
+            "bin/foo",
+            "lib/libbar.so.1.2",
+            "lib/libbaz.1.dylib",
+            "Library/bin/foo.exe",
+            "Library/bin/bar.dll",
+        ] {
+            fs::write(prefix.join(file), "").unwrap();
+        }
+
+        assert!(find_command(prefix, "foo", Platform::Linux64).is_some());
+        assert!(find_command(prefix, "bar", Platform::Linux64).is_none());
+        assert_eq!(
+            find_command(prefix, "foo", Platform::Win64),
+            Some(prefix.join("Library/bin").join("foo.exe"))
+        );
+
+        assert!(find_library(prefix, "libbar", Platform::Linux64).is_some());
+        assert!(find_library(prefix, "bar", Platform::Linux64).is_some());
+        assert!(find_library(prefix, "libbar", Platform::OsxArm64).is_none());
+        assert!(find_library(prefix, "baz", Platform::OsxArm64).is_some());
+        assert!(find_library(prefix, "libbar", Platform::Win64).is_some());
+        assert!(find_library(prefix, "libfoo", Platform::Win64).is_none());
+    }
+
+    #[test]
+    fn test_missing_virtual_packages() {
+        let platform = PlatformWithVirtualPackages {
+            platform: Platform::Linux64,
+            virtual_packages: vec![
+                GenericVirtualPackage {
+                    name: "__glibc".parse().unwrap(),
+                    version: "2.35".parse().unwrap(),
+                    build_string: "0".to_string(),
+                },
+                GenericVirtualPackage {
+                    name: "__cuda".parse().unwrap(),
+                    version: "11.8".parse().unwrap(),
+                    build_string: "0".to_string(),
+                },
+            ],
+        };
+
+        let test = |specs: &[&str]| CommandsTest {
+            requirements: crate::recipe::parser::CommandsTestRequirements {
+                virtual_packages: specs.iter().map(|s| s.to_string()).collect(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        assert!(test(&[])
+            .missing_virtual_packages(&platform)
+            .unwrap()
+            .is_empty());
+        assert!(test(&["__glibc >=2.17", "__cuda"])
+            .missing_virtual_packages(&platform)
+            .unwrap()
+            .is_empty());
+
+        let missing = test(&["__glibc >=2.17", "__cuda >=12", "__osx"])
+            .missing_virtual_packages(&platform)
+            .unwrap();
+        assert_eq!(
+            missing.iter().map(|s| s.to_string()).collect::<Vec<_>>(),
+            vec!["__cuda >=12", "__osx"]
+        );
+    }
+
+    #[test]
+    fn test_keep_test_prefixes() {
+        assert_eq!("all".parse::<KeepTestPrefixes>(), Ok(KeepTestPrefixes::All));
+        assert_eq!(
+            "failing".parse::<KeepTestPrefixes>(),
+            Ok(KeepTestPrefixes::Failing)
+        );
+        assert_eq!(
+            "2".parse::<KeepTestPrefixes>(),
+            Ok(KeepTestPrefixes::Last(2))
+        );
+        assert!("some".parse::<KeepTestPrefixes>().is_err());
+        assert!(!KeepTestPrefixes::Last(0).keeps_any());
+
+        let dir = tempfile::tempdir().unwrap();
+        let prefix = dir.path().join("test");
+        let run = |name: &str, prefixes: &[&str], failed: bool| {
+            for p in prefixes {
+                fs::create_dir_all(prefix.join(p)).unwrap();
+            }
+            TestRun {
+                name: name.to_string(),
+                directory: prefix.join(name),
+                prefixes: prefixes.iter().map(|p| prefix.join(p)).collect(),
+                failed,
+            }
+        };
+        let runs = || {
+            vec![
+                run("0", &["0"], false),
+                run("1", &["1"], false),
+                run("2", &["2/run", "2/bld"], true),
+            ]
+        };
+
+        assert_eq!(
+            KeepTestPrefixes::Failing.retained(&runs()),
+            BTreeSet::from([2])
+        );
+        assert_eq!(
+            KeepTestPrefixes::Last(5).retained(&runs()),
+            BTreeSet::from([0, 1, 2])
+        );
+
+        clean_up_test_prefixes(&prefix, &runs(), KeepTestPrefixes::Failing).unwrap();
+        assert!(!prefix.join("0").exists());
+        assert!(prefix.join("2/run").exists());
+
+        clean_up_test_prefixes(&prefix, &runs(), KeepTestPrefixes::Last(2)).unwrap();
+        assert!(!prefix.join("0").exists());
+        assert!(prefix.join("1").exists());
+        assert!(prefix.join("2/bld").exists());
+
+        clean_up_test_prefixes(&prefix, &runs(), KeepTestPrefixes::None).unwrap();
+        assert!(!prefix.exists());
+    }
+
+    #[test]
+    fn test_environment_keys() {
+        let specs = |specs: &[&str]| {
+            specs
+                .iter()
+                .map(|s| MatchSpec::from_str(s, ParseStrictness::Strict).unwrap())
+                .collect::<Vec<_>>()
+        };
+        let linux = PlatformWithVirtualPackages::from(Platform::Linux64);
+        let osx = PlatformWithVirtualPackages::from(Platform::OsxArm64);
+
+        // the order of the specs does not matter
+        assert_eq!(
+            TestEnvironments::key(&specs(&["python 3.12.*", "pytest"]), &linux),
+            TestEnvironments::key(&specs(&["pytest", "python 3.12.*"]), &linux)
+        );
+        assert_ne!(
+            TestEnvironments::key(&specs(&["python 3.12.*"]), &linux),
+            TestEnvironments::key(&specs(&["python 3.11.*"]), &linux)
+        );
+        assert_ne!(
+            TestEnvironments::key(&specs(&["python"]), &linux),
+            TestEnvironments::key(&specs(&["python"]), &osx)
+        );
+    }
+
+    #[test]
+    fn test_used_environments() {
+        let environments = TestEnvironments::default();
+        environments.mark_used(Path::new("test/0/run"));
+        environments.mark_used(Path::new("test/0/bld"));
+        environments.mark_used(Path::new("test/0/run"));
+        assert_eq!(
+            environments.take_used(),
+            vec![PathBuf::from("test/0/run"), PathBuf::from("test/0/bld")]
+        );
+        // every test only reports its own prefixes
+        environments.mark_used(Path::new("test/1"));
+        assert_eq!(environments.take_used(), vec![PathBuf::from("test/1")]);
+    }
+}
@@ -1,32 +1,45 @@
 //! This module contains the functions to package a conda package from a given
 //! output.
 use std::{
-    collections::HashSet,
+    collections::{BTreeMap, HashSet},
     io::Write,
     path::{Component, Path, PathBuf},
+    str::FromStr,
 };
 
 use fs_err as fs;
 use fs_err::File;
+use itertools::Itertools;
 use rattler_conda_types::{
     package::{ArchiveType, PackageFile, PathsJson},
-    Platform,
+    MatchSpec, PackageName, ParseStrictness, Platform, PrefixRecord,
 };
+use rattler_digest::compute_file_digest;
 use rattler_package_streaming::write::{
     write_conda_package, write_tar_bz2_package, CompressionLevel,
 };
 
+mod compression;
 mod file_finder;
 mod file_mapper;
+mod link_scripts;
 mod metadata;
+mod secret_scan;
+mod size_budget;
+mod symlinks;
+pub use compression::{CompressionSettings, ContentSample};
 pub use file_finder::{content_type, Files, TempFiles};
 pub use metadata::{contains_prefix_binary, contains_prefix_text, create_prefix_placeholder};
 
 use crate::{
-    metadata::Output,
+    build_profile::ProfileCategory,
+    metadata::{FilenameTemplate, Output, PackageIdentifier},
     package_test::write_test_files,
     post_process,
-    recipe::parser::GlobVec,
+    recipe::parser::{included_files, GlobVec, IncludeRecipe, LicenseFileLayout},
+    render::resolved_dependencies::{
+        FinalizedDependencies, FinalizedRunDependencies, SourceDependency,
+    },
     source::{self, copy_dir},
     tool_configuration,
 };
@@ -79,11 +92,35 @@ pub enum PackagingError {
     #[error("Failed to find content type for file: {0:?}")]
     ContentTypeNotFound(PathBuf),
 
-    #[error("No license files were copied")]
-    LicensesNotFound,
+    #[error("No license files were copied:\n{0}")]
+    LicensesNotFound(String),
+
+    #[error("License files have the same name in `info/licenses` (see `about.license_file_layout`):\n{0}")]
+    LicenseFileConflict(String),
+
+    #[error("No license files found for `{0}` (it needs to be a host dependency whose package contains `info/licenses`)")]
+    DependencyLicensesNotFound(String),
 
     #[error("Invalid Metadata: {0}")]
     InvalidMetadata(String),
+
+    #[error("Package contains invalid symlinks (see `build.symlinks`):\n{0}")]
+    InvalidSymlinks(String),
+
+    #[error("Invalid link script (see `build.link_scripts`):\n{0}")]
+    InvalidLinkScript(String),
+
+    #[error("Package exceeds its size budget:\n{0}")]
+    SizeBudgetExceeded(String),
+
+    #[error("Package contains likely secrets (see `build.secret_scan`):\n{0}")]
+    SecretsFound(String),
+
+    #[error("Files are packaged by more than one output (`--clobber-check=error`):\n{0}")]
+    ClobberedFiles(String),
+
+    #[error(transparent)]
+    SplitDebugError(#[from] crate::post_process::split_debug::SplitDebugError),
 }
 
 /// This function copies the license files to the info/licenses folder.
@@ -93,34 +130,34 @@ fn copy_license_files(
     output: &Output,
     tmp_dir_path: &Path,
 ) -> Result<Option<HashSet<PathBuf>>, PackagingError> {
-    if output.recipe.about().license_file.is_empty() {
+    let about = output.recipe.about();
+    if about.license_file.is_empty() {
         Ok(None)
     } else {
         let licenses_folder = tmp_dir_path.join("info/licenses/");
         fs::create_dir_all(&licenses_folder)?;
 
-        let copy_dir = copy_dir::CopyDir::new(
+        let copy_dir_work_dir = copy_dir::CopyDir::new(
             &output.build_configuration.directories.work_dir,
             &licenses_folder,
         )
-        .with_globvec(&output.recipe.about().license_file)
+        .with_globvec(&about.license_file)
         .use_gitignore(false)
         .run()?;
 
-        let copied_files_work_dir = copy_dir.copied_paths();
-        let any_include_matched_recipe_dir = copy_dir.any_include_glob_matched();
+        let copied_files_work_dir = copy_dir_work_dir.copied_paths();
 
-        let copy_dir = copy_dir::CopyDir::new(
+        let copy_dir_recipe_dir = copy_dir::CopyDir::new(
             &output.build_configuration.directories.recipe_dir,
             &licenses_folder,
         )
-        .with_globvec(&output.recipe.about().license_file)
+        .with_globvec(&about.license_file)
         .use_gitignore(false)
         .overwrite(true)
+        .make_writable(true)
         .run()?;
 
-        let copied_files_recipe_dir = copy_dir.copied_paths();
-        let any_include_matched_work_dir = copy_dir.any_include_glob_matched();
+        let copied_files_recipe_dir = copy_dir_recipe_dir.copied_paths();
 
         // if a file was copied from the recipe dir, and the work dir, we should
         // issue a warning
@@ -138,30 +175,186 @@ fn copy_license_files(
             .map(PathBuf::from)
             .collect::<HashSet<PathBuf>>();
 
-        if !any_include_matched_work_dir && !any_include_matched_recipe_dir {
+        if !copy_dir_work_dir.any_include_glob_matched()
+            && !copy_dir_recipe_dir.any_include_glob_matched()
+        {
             let warn_str = "No include glob matched for copying license files";
             tracing::warn!(warn_str);
             output.record_warning(warn_str);
         }
 
         if copied_files.is_empty() {
-            Err(PackagingError::LicensesNotFound)
+            return Err(PackagingError::LicensesNotFound(license_glob_report(
+                &about.license_file,
+                &[&copy_dir_work_dir, &copy_dir_recipe_dir],
+            )));
+        }
+
+        match about.license_file_layout {
+            LicenseFileLayout::Preserve => Ok(Some(copied_files)),
+            LicenseFileLayout::Flatten => {
+                flatten_license_files(&licenses_folder, copied_files).map(Some)
+            }
+        }
+    }
+}
+
+/// Describe which license globs matched a file in the source or recipe
+/// directory and which negated globs excluded them, to explain why no license
+/// file was copied.
+fn license_glob_report(globs: &GlobVec, results: &[&copy_dir::CopyDirResult]) -> String {
+    let matched_include = results
+        .iter()
+        .flat_map(|result| result.matched_include_globs())
+        .collect::<HashSet<_>>();
+    let matched_exclude = results
+        .iter()
+        .flat_map(|result| result.matched_exclude_globs())
+        .collect::<HashSet<_>>();
+
+    let include = globs.include_globs().iter().map(|glob| {
+        if matched_include.contains(glob) {
+            format!(" - `{}` matched files", glob.glob())
+        } else {
+            format!(
+                " - `{}` did not match any file in the source or recipe directory",
+                glob.glob()
+            )
+        }
+    });
+    let exclude = globs.exclude_globs().iter().map(|glob| {
+        if matched_exclude.contains(glob) {
+            format!(" - `!{}` excluded the matched files", glob.glob())
         } else {
-            Ok(Some(copied_files))
+            format!(" - `!{}` did not exclude any file", glob.glob())
+        }
+    });
+    include.chain(exclude).join("\n")
+}
+
+/// Move the copied license files directly into `info/licenses` (for
+/// [`LicenseFileLayout::Flatten`]) and remove the directories that are left
+/// empty.
+fn flatten_license_files(
+    licenses_folder: &Path,
+    copied_files: HashSet<PathBuf>,
+) -> Result<HashSet<PathBuf>, PackagingError> {
+    let mut targets = BTreeMap::<PathBuf, Vec<PathBuf>>::new();
+    for file in copied_files {
+        let Some(file_name) = file.file_name() else {
+            continue;
+        };
+        targets
+            .entry(licenses_folder.join(file_name))
+            .or_default()
+            .push(file);
+    }
+
+    let conflicts = targets
+        .iter()
+        .filter(|(_, sources)| sources.len() > 1)
+        .map(|(target, sources)| {
+            let sources = sources
+                .iter()
+                .map(|source| source.strip_prefix(licenses_folder).unwrap_or(source))
+                .sorted()
+                .map(|source| source.display().to_string())
+                .join(", ");
+            format!(
+                " - {}: {}",
+                target.file_name().unwrap_or_default().to_string_lossy(),
+                sources
+            )
+        })
+        .collect::<Vec<_>>();
+    if !conflicts.is_empty() {
+        return Err(PackagingError::LicenseFileConflict(conflicts.join("\n")));
+    }
+
+    for (target, sources) in &targets {
+        if &sources[0] != target {
+            fs::rename(&sources[0], target)?;
         }
     }
+
+    // remove the directories that are empty now (deepest first)
+    let dirs = walkdir::WalkDir::new(licenses_folder)
+        .min_depth(1)
+        .contents_first(true)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_dir())
+        .map(|entry| entry.into_path())
+        .collect::<Vec<_>>();
+    for dir in dirs {
+        if fs::read_dir(&dir)?.next().is_none() {
+            fs::remove_dir(&dir)?;
+        }
+    }
+
+    Ok(targets.into_keys().collect())
 }
 
-fn write_recipe_folder(
+/// This function copies the license files of the dependencies that are listed
+/// in `about.license_from_dependencies` (e.g. statically linked libraries) to
+/// the `info/licenses/thirdparty/<name>` folder. The license files are taken
+/// from the packages that are installed in the host prefix.
+fn copy_dependency_license_files(
     output: &Output,
     tmp_dir_path: &Path,
+) -> Result<HashSet<PathBuf>, PackagingError> {
+    let dependencies = &output.recipe.about().license_from_dependencies;
+    if dependencies.is_empty() {
+        return Ok(HashSet::new());
+    }
+
+    let host_prefix = &output.build_configuration.directories.host_prefix;
+    let records = if host_prefix.join("conda-meta").exists() {
+        PrefixRecord::collect_from_prefix(host_prefix)?
+    } else {
+        Vec::new()
+    };
+
+    let mut copied_files = HashSet::new();
+    for name in dependencies {
+        let licenses_dir = records
+            .iter()
+            .find(|record| &record.repodata_record.package_record.name == name)
+            .and_then(|record| record.extracted_package_dir.as_ref())
+            .map(|dir| dir.join("info/licenses"))
+            .filter(|dir| dir.is_dir())
+            .ok_or_else(|| {
+                PackagingError::DependencyLicensesNotFound(name.as_normalized().to_string())
+            })?;
+
+        let dest = tmp_dir_path
+            .join("info/licenses/thirdparty")
+            .join(name.as_normalized());
+        let copy_dir = copy_dir::CopyDir::new(&licenses_dir, &dest)
+            .use_gitignore(false)
+            .run()?;
+        if copy_dir.copied_paths().is_empty() {
+            return Err(PackagingError::DependencyLicensesNotFound(
+                name.as_normalized().to_string(),
+            ));
+        }
+        copied_files.extend(copy_dir.copied_paths().iter().cloned());
+    }
+
+    Ok(copied_files)
+}
+
+/// Copy the recipe folder to `info/recipe/` (for [`IncludeRecipe::Full`]).
+fn copy_recipe_folder(
+    output: &Output,
+    recipe_folder: &Path,
 ) -> Result<Vec<PathBuf>, PackagingError> {
-    let recipe_folder = tmp_dir_path.join("info/recipe/");
     let recipe_dir = &output.build_configuration.directories.recipe_dir;
     let recipe_path = &output.build_configuration.directories.recipe_path;
     let output_dir = &output.build_configuration.directories.output_dir;
 
-    let mut copy_builder = copy_dir::CopyDir::new(recipe_dir, &recipe_folder)
+    let mut copy_builder = copy_dir::CopyDir::new(recipe_dir, recipe_folder)
+        .make_writable(true)
         .use_gitignore(true)
         .ignore_hidden_files(true);
 
@@ -192,6 +385,49 @@ fn write_recipe_folder(
         }
     }
 
+    // Copy the recipe fragments that are included from outside of the recipe
+    // directory (e.g. `../common/about.yaml`) to `info/recipe/include/`
+    let recipe_text = fs::read_to_string(recipe_path)?;
+    for include in included_files(&recipe_text).unwrap_or_default() {
+        let in_recipe_dir = include
+            .components()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+        if in_recipe_dir {
+            continue;
+        }
+        let dest = recipe_folder.join("include").join(
+            include
+                .components()
+                .filter(|component| matches!(component, Component::Normal(_)))
+                .collect::<PathBuf>(),
+        );
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(recipe_dir.join(&include), &dest)?;
+        files.push(dest);
+    }
+
+    Ok(files)
+}
+
+/// Write the recipe to `info/recipe/`. The variant config and the rendered
+/// recipe are always written, the recipe folder only for
+/// [`IncludeRecipe::Full`].
+fn write_recipe_folder(
+    output: &Output,
+    tmp_dir_path: &Path,
+    include_recipe: IncludeRecipe,
+) -> Result<Vec<PathBuf>, PackagingError> {
+    let recipe_folder = tmp_dir_path.join("info/recipe/");
+
+    let mut files = if include_recipe == IncludeRecipe::Full {
+        copy_recipe_folder(output, &recipe_folder)?
+    } else {
+        fs::create_dir_all(&recipe_folder)?;
+        Vec::new()
+    };
+
     // write the variant config to the appropriate file
     let variant_config_file = recipe_folder.join("variant_config.yaml");
     let mut variant_config = File::create(&variant_config_file)?;
@@ -235,25 +471,56 @@ pub fn package_conda(
     tool_configuration: &tool_configuration::Configuration,
     files: &Files,
 ) -> Result<(PathBuf, PathsJson), PackagingError> {
-    let local_channel_dir = &output.build_configuration.directories.output_dir;
-    let packaging_settings = &output.build_configuration.packaging_settings;
-
     if output.finalized_dependencies.is_none() {
         return Err(PackagingError::DependenciesNotFinalized);
     }
 
+    symlinks::check_symlinks(
+        output.recipe.build().symlinks(),
+        &files.prefix,
+        &files.new_files,
+    )?;
+    link_scripts::check_link_scripts(output, &files.prefix, &files.new_files)?;
+
     let mut tmp = files.to_temp_folder(output)?;
+    tmp.add_files(link_scripts::write_link_scripts(
+        output,
+        tmp.temp_dir.path(),
+    )?);
 
     tracing::info!("Copying done!");
 
     post_process::relink::relink(&tmp, output)?;
 
+    post_process::run_exports::check_run_exports(&tmp, output);
+
+    if let Some(debug_files) = post_process::split_debug::split_debug_info(&tmp, output)? {
+        let debug_output = output.debug_output()?;
+        tracing::info!("Creating debug package {}", debug_output.identifier());
+        write_package(&debug_output, tool_configuration, debug_files)?;
+    }
+
     tmp.add_files(post_process::python::python(&tmp, output)?);
 
     post_process::regex_replacements::regex_post_process(&tmp, output)?;
 
+    post_process::python::fix_records(&tmp, output)?;
+
     tracing::info!("Post-processing done!");
 
+    write_package(output, tool_configuration, tmp)
+}
+
+/// Write the metadata, test files, licenses and recipe of the output to the
+/// temporary folder and create the package archive from it.
+fn write_package(
+    output: &Output,
+    tool_configuration: &tool_configuration::Configuration,
+    mut tmp: TempFiles,
+) -> Result<(PathBuf, PathsJson), PackagingError> {
+    let local_channel_dir = &output.build_configuration.directories.output_dir;
+    let packaging_settings = &output.build_configuration.packaging_settings;
+
     let info_folder = tmp.temp_dir.path().join("info");
 
     tracing::info!("Writing test files");
@@ -268,10 +535,17 @@ pub fn package_conda(
     if let Some(license_files) = copy_license_files(output, tmp.temp_dir.path())? {
         tmp.add_files(license_files);
     }
+    tmp.add_files(copy_dependency_license_files(output, tmp.temp_dir.path())?);
 
     tracing::info!("Copying recipe files");
-    if output.build_configuration.store_recipe {
-        let recipe_files = write_recipe_folder(output, tmp.temp_dir.path())?;
+    // `--no-include-recipe` overrides `build.include_recipe`
+    let include_recipe = if output.build_configuration.store_recipe {
+        output.recipe.build().include_recipe()
+    } else {
+        IncludeRecipe::None
+    };
+    if include_recipe != IncludeRecipe::None {
+        let recipe_files = write_recipe_folder(output, tmp.temp_dir.path(), include_recipe)?;
         tmp.add_files(recipe_files);
     }
 
@@ -310,6 +584,28 @@ pub fn package_conda(
         }
     });
 
+    let package_files = tmp.files.iter().cloned().collect::<Vec<_>>();
+    size_budget::check_size_budget(
+        output.recipe.build().size_budget(),
+        tmp.temp_dir.path(),
+        &package_files,
+    )?;
+    secret_scan::check_secrets(
+        output.recipe.build().secret_scan(),
+        output.recipe.build().script().secrets(),
+        tmp.temp_dir.path(),
+        &package_files,
+    )?;
+    tool_configuration.packaged_files.check(
+        output,
+        files
+            .iter()
+            .filter(|f| f.components().next() != Some(Component::Normal("info".as_ref())))
+            .map(|f| f.to_path_buf())
+            .collect(),
+        tool_configuration.clobber_check,
+    )?;
+
     let output_folder =
         local_channel_dir.join(output.build_configuration.target_platform.to_string());
     tracing::info!("Creating target folder '{}'", output_folder.display());
@@ -331,6 +627,30 @@ pub fn package_conda(
     ));
     let file = File::create(&out_path)?;
 
+    let files = tmp.files.iter().cloned().collect::<Vec<_>>();
+    let compression = if packaging_settings.auto_compression {
+        let sample = ContentSample::from_files(tmp.temp_dir.path(), &files)?;
+        let settings = CompressionSettings::auto(packaging_settings.archive_type, &sample);
+        tracing::info!(
+            "Automatically selected compression: {} ({} total, {:.0}% text)",
+            settings,
+            indicatif::HumanBytes(sample.total_size),
+            sample.text_ratio * 100.0
+        );
+        CompressionSettings {
+            // an explicit number of threads takes precedence
+            threads: tool_configuration.compression_threads.or(settings.threads),
+            ..settings
+        }
+    } else {
+        CompressionSettings {
+            archive_type: packaging_settings.archive_type,
+            level: packaging_settings.compression_level,
+            threads: tool_configuration.compression_threads,
+        }
+    };
+    output.record_compression(&compression);
+
     tracing::info!("Compressing archive...");
 
     let progress_bar = tool_configuration.fancy_log_handler.add_progress_bar(
@@ -344,8 +664,8 @@ pub fn package_conda(
             write_tar_bz2_package(
                 file,
                 tmp.temp_dir.path(),
-                &tmp.files.iter().cloned().collect::<Vec<_>>(),
-                CompressionLevel::Numeric(packaging_settings.compression_level),
+                &files,
+                CompressionLevel::Numeric(compression.level),
                 Some(&output.build_configuration.timestamp),
                 Some(Box::new(ProgressBar { progress_bar })),
             )?;
@@ -354,9 +674,9 @@ pub fn package_conda(
             write_conda_package(
                 file,
                 tmp.temp_dir.path(),
-                &tmp.files.iter().cloned().collect::<Vec<_>>(),
-                CompressionLevel::Numeric(packaging_settings.compression_level),
-                tool_configuration.compression_threads,
+                &files,
+                CompressionLevel::Numeric(compression.level),
+                compression.threads,
                 &identifier,
                 Some(&output.build_configuration.timestamp),
                 Some(Box::new(ProgressBar { progress_bar })),
@@ -370,6 +690,48 @@ pub fn package_conda(
     Ok((out_path, paths_json))
 }
 
+/// Copy (or hard link) the package archive to `<output_dir>/artifacts/` with a
+/// file name from the template (e.g. with the hash of the archive for
+/// immutable artifact stores). The archive in the local channel keeps its
+/// standard name, so that the channel index is not affected.
+pub fn copy_with_filename_template(
+    output: &Output,
+    archive: &Path,
+    template: &FilenameTemplate,
+) -> Result<PathBuf, PackagingError> {
+    let sha256 = format!("{:x}", compute_file_digest::<sha2::Sha256>(archive)?);
+    let extension = ArchiveType::try_from(archive)
+        .map(ArchiveType::extension)
+        .unwrap_or_default();
+    let identifier = PackageIdentifier {
+        name: output.name().clone(),
+        version: output.version().clone(),
+        build_string: output.build_string().into_owned(),
+    };
+    let filename = template.render(
+        &identifier,
+        &output.target_platform().to_string(),
+        &sha256,
+        extension,
+    );
+
+    let artifacts_dir = output
+        .build_configuration
+        .directories
+        .output_dir
+        .join("artifacts");
+    fs::create_dir_all(&artifacts_dir)?;
+    let destination = artifacts_dir.join(filename);
+    if destination.exists() {
+        fs::remove_file(&destination)?;
+    }
+    if fs::hard_link(archive, &destination).is_err() {
+        fs::copy(archive, &destination)?;
+    }
+
+    Ok(destination)
+}
+
 /// When building package for noarch, we don't create another build-platform
 /// folder together with noarch but conda-build does
 /// because of this we have a failure in conda-smithy CI so we also *mimic* this
@@ -389,6 +751,51 @@ fn create_empty_build_folder(
 }
 
 impl Output {
+    /// The output for the `<name>-dbg` package that contains the split debug
+    /// information (`build.split_debug`). It has the same version and build
+    /// string and depends on exactly this build of the package.
+    fn debug_output(&self) -> Result<Output, PackagingError> {
+        let name = PackageName::try_from(format!("{}-dbg", self.name().as_normalized()))
+            .map_err(|e| PackagingError::InvalidMetadata(e.to_string()))?;
+        let spec = MatchSpec::from_str(
+            &format!(
+                "{} =={} {}",
+                self.name().as_normalized(),
+                self.version(),
+                self.build_string()
+            ),
+            ParseStrictness::Strict,
+        )
+        .map_err(|e| PackagingError::InvalidMetadata(e.to_string()))?;
+
+        let mut recipe = self.recipe.clone();
+        recipe.package.name = name;
+        recipe.tests = Vec::new();
+        recipe.build.split_debug = false;
+        recipe.build.size_budget = Default::default();
+        recipe.build.python = Default::default();
+
+        let finalized_dependencies =
+            self.finalized_dependencies
+                .clone()
+                .map(|dependencies| FinalizedDependencies {
+                    run: FinalizedRunDependencies {
+                        depends: vec![SourceDependency { spec }.into()],
+                        constraints: Vec::new(),
+                        run_exports: Default::default(),
+                        ignored_run_exports: Vec::new(),
+                    },
+                    ..dependencies
+                });
+
+        Ok(Output {
+            recipe,
+            finalized_dependencies,
+            build_summary: Default::default(),
+            ..self.clone()
+        })
+    }
+
     /// Create a conda package from any new files in the host prefix. Note: the
     /// previous stages should have been completed before calling this
     /// function.
@@ -396,7 +803,10 @@ impl Output {
         &self,
         tool_configuration: &tool_configuration::Configuration,
     ) -> Result<(PathBuf, PathsJson), PackagingError> {
-        let span = tracing::info_span!("Packaging new files");
+        let span = tracing::info_span!(
+            "Packaging new files",
+            profile = ProfileCategory::Packaging.as_str()
+        );
         let _enter = span.enter();
         let files_after = Files::from_prefix(
             &self.build_configuration.directories.host_prefix,
@@ -407,3 +817,59 @@ impl Output {
         package_conda(self, tool_configuration, &files_after)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flatten_license_files() {
+        let tmp = tempfile::tempdir().unwrap();
+        let licenses = tmp.path().join("info/licenses");
+        let files = ["LICENSE", "vendor/foo/COPYING", "vendor/bar/NOTICE"]
+            .iter()
+            .map(|file| {
+                let path = licenses.join(file);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(&path, file).unwrap();
+                path
+            })
+            .collect::<HashSet<_>>();
+
+        let flattened = flatten_license_files(&licenses, files).unwrap();
+        let mut names = flattened
+            .iter()
+            .map(|path| path.strip_prefix(&licenses).unwrap().to_path_buf())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(
+            names,
+            vec![
+                PathBuf::from("COPYING"),
+                PathBuf::from("LICENSE"),
+                PathBuf::from("NOTICE")
+            ]
+        );
+        assert_eq!(
+            fs::read_to_string(licenses.join("COPYING")).unwrap(),
+            "vendor/foo/COPYING"
+        );
+        assert!(!licenses.join("vendor").exists());
+
+        // two files with the same name cannot be flattened
+        let files = ["foo/LICENSE", "bar/LICENSE"]
+            .iter()
+            .map(|file| {
+                let path = licenses.join(file);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(&path, file).unwrap();
+                path
+            })
+            .collect::<HashSet<_>>();
+        let err = flatten_license_files(&licenses, files).unwrap_err();
+        assert!(matches!(err, PackagingError::LicenseFileConflict(_)));
+        assert!(err
+            .to_string()
+            .contains("LICENSE: bar/LICENSE, foo/LICENSE"));
+    }
+}
@@ -0,0 +1,177 @@
+//! Automatic selection of the compression settings (`--package-format
+//! conda:auto` or `tar-bz2:auto`) based on the content of the package.
+use std::{
+    fmt,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use fs_err as fs;
+use rattler_conda_types::package::ArchiveType;
+
+/// The number of bytes that are read from every file to determine whether it
+/// is a text or a binary file
+const SAMPLE_SIZE: u64 = 8 * 1024;
+
+/// The maximum number of files that are sampled
+const MAX_SAMPLED_FILES: usize = 2000;
+
+const MB: u64 = 1024 * 1024;
+
+/// A summary of the content of a package
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ContentSample {
+    /// The total size of all files in bytes
+    pub total_size: u64,
+    /// The (size weighted) fraction of the sampled files that are text files
+    pub text_ratio: f64,
+}
+
+impl ContentSample {
+    /// Sample the given files. The total size is computed from all files, the
+    /// text ratio from the beginning of (at most) [`MAX_SAMPLED_FILES`] files.
+    pub fn from_files(root: &Path, files: &[PathBuf]) -> Result<Self, std::io::Error> {
+        let mut total_size = 0;
+        let mut sampled_size = 0;
+        let mut text_size = 0;
+        let mut buffer = Vec::with_capacity(SAMPLE_SIZE as usize);
+
+        for (idx, file) in files.iter().enumerate() {
+            let path = root.join(file);
+            let metadata = fs::symlink_metadata(&path)?;
+            if !metadata.is_file() {
+                continue;
+            }
+            total_size += metadata.len();
+
+            if idx >= MAX_SAMPLED_FILES {
+                continue;
+            }
+
+            buffer.clear();
+            fs::File::open(&path)?
+                .take(SAMPLE_SIZE)
+                .read_to_end(&mut buffer)?;
+            sampled_size += metadata.len();
+            if content_inspector::inspect(&buffer).is_text() {
+                text_size += metadata.len();
+            }
+        }
+
+        let text_ratio = if sampled_size == 0 {
+            1.0
+        } else {
+            text_size as f64 / sampled_size as f64
+        };
+
+        Ok(Self {
+            total_size,
+            text_ratio,
+        })
+    }
+}
+
+/// The selected compression level and number of threads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompressionSettings {
+    /// The archive type that the settings are used for
+    pub archive_type: ArchiveType,
+    /// The compression level (bzip2 or zstd level)
+    pub level: i32,
+    /// The number of threads used for compression (only for `.conda`)
+    pub threads: Option<u32>,
+}
+
+impl CompressionSettings {
+    /// Select the compression settings for the sampled content.
+    ///
+    /// - bzip2 levels only select the block size, therefore the highest level
+    ///   is always used
+    /// - small packages and mostly-text packages are compressed with a high
+    ///   zstd level, as this gives a much better ratio at acceptable cost
+    /// - large, mostly-binary packages use a lower zstd level, as higher levels
+    ///   barely improve the ratio but are very slow
+    /// - packages larger than 64 MB are compressed with all available cores
+    pub fn auto(archive_type: ArchiveType, sample: &ContentSample) -> Self {
+        let level = match archive_type {
+            ArchiveType::TarBz2 => 9,
+            ArchiveType::Conda => {
+                if sample.total_size < 10 * MB {
+                    19
+                } else if sample.text_ratio >= 0.5 {
+                    16
+                } else if sample.total_size > 512 * MB {
+                    9
+                } else {
+                    12
+                }
+            }
+        };
+
+        let threads = (archive_type == ArchiveType::Conda && sample.total_size > 64 * MB)
+            .then(|| num_cpus::get() as u32);
+
+        Self {
+            archive_type,
+            level,
+            threads,
+        }
+    }
+}
+
+impl fmt::Display for CompressionSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.archive_type {
+            ArchiveType::TarBz2 => write!(f, "bzip2 level {}", self.level)?,
+            ArchiveType::Conda => write!(f, "zstd level {}", self.level)?,
+        }
+        if let Some(threads) = self.threads {
+            write!(f, " ({} threads)", threads)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auto_compression() {
+        let sample = |total_size, text_ratio| ContentSample {
+            total_size,
+            text_ratio,
+        };
+
+        let small = CompressionSettings::auto(ArchiveType::Conda, &sample(MB, 0.0));
+        assert_eq!((small.level, small.threads), (19, None));
+
+        let text = CompressionSettings::auto(ArchiveType::Conda, &sample(100 * MB, 0.9));
+        assert_eq!(text.level, 16);
+        assert!(text.threads.is_some());
+
+        let binary = CompressionSettings::auto(ArchiveType::Conda, &sample(20 * MB, 0.1));
+        assert_eq!((binary.level, binary.threads), (12, None));
+
+        let huge = CompressionSettings::auto(ArchiveType::Conda, &sample(1024 * MB, 0.1));
+        assert_eq!(huge.level, 9);
+
+        let bzip2 = CompressionSettings::auto(ArchiveType::TarBz2, &sample(1024 * MB, 0.1));
+        assert_eq!((bzip2.level, bzip2.threads), (9, None));
+    }
+
+    #[test]
+    fn test_content_sample() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("text.txt"), "hello world\n".repeat(100)).unwrap();
+        fs::write(dir.path().join("binary.bin"), vec![0u8; 1200]).unwrap();
+
+        let sample = ContentSample::from_files(
+            dir.path(),
+            &[PathBuf::from("text.txt"), PathBuf::from("binary.bin")],
+        )
+        .unwrap();
+        assert_eq!(sample.total_size, 2400);
+        assert!((sample.text_ratio - 0.5).abs() < f64::EPSILON);
+    }
+}
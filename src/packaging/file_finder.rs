@@ -64,6 +64,51 @@ pub fn record_files(directory: &Path) -> Result<HashSet<PathBuf>, io::Error> {
     Ok(res)
 }
 
+/// Select the new files that match the `build.files` globs. Reports the files
+/// that are left out, and the include globs that do not match any new file
+/// (which is most likely a typo).
+fn select_files(
+    prefix: &Path,
+    new_files: impl Iterator<Item = PathBuf>,
+    files: &GlobVec,
+) -> HashSet<PathBuf> {
+    let strip_prefix = |f: &PathBuf| -> PathBuf {
+        f.strip_prefix(prefix)
+            .expect("File should be in prefix")
+            .to_path_buf()
+    };
+    let (selected, excluded): (HashSet<_>, HashSet<_>) =
+        new_files.partition(|f| files.is_match(&strip_prefix(f)));
+
+    let mut excluded = excluded
+        .iter()
+        .filter(|f| !f.is_dir())
+        .map(strip_prefix)
+        .collect::<Vec<_>>();
+    if !excluded.is_empty() {
+        tracing::info!(
+            "{} new file(s) are not included in the package because of `build.files`",
+            excluded.len()
+        );
+        excluded.sort();
+        for f in excluded {
+            tracing::debug!("Not including {}", f.display());
+        }
+    }
+
+    for glob in files.include_globs() {
+        let matcher = glob.compile_matcher();
+        if !selected.iter().any(|f| matcher.is_match(strip_prefix(f))) {
+            tracing::warn!(
+                "The `build.files` glob `{}` does not match any new file in the prefix",
+                glob.glob()
+            );
+        }
+    }
+
+    selected
+}
+
 impl Files {
     /// Find all files in the given (host) prefix and remove all previously installed files (based on the PrefixRecord
     /// of the conda environment). If always_include is Some, then all files matching the glob pattern will be included
@@ -99,15 +144,13 @@ impl Files {
         };
 
         let current_files = record_files(prefix)?;
-        let mut difference = current_files
-            .difference(&previous_files)
-            // If we have an files glob, we only include files that match the glob
-            .filter(|f| {
-                files.is_empty()
-                    || files.is_match(f.strip_prefix(prefix).expect("File should be in prefix"))
-            })
-            .cloned()
-            .collect::<HashSet<_>>();
+        let new_files = current_files.difference(&previous_files).cloned();
+        // If we have an files glob, we only include files that match the glob
+        let mut difference = if files.is_empty() {
+            new_files.collect::<HashSet<_>>()
+        } else {
+            select_files(prefix, new_files, files)
+        };
 
         if !always_include.is_empty() {
             for file in current_files {
@@ -154,6 +197,16 @@ impl Files {
 }
 
 impl TempFiles {
+    /// Create an empty TempFiles struct for the given temporary directory
+    pub fn new(temp_dir: TempDir, encoded_prefix: PathBuf) -> Self {
+        Self {
+            files: HashSet::new(),
+            temp_dir,
+            encoded_prefix,
+            content_type_map: HashMap::new(),
+        }
+    }
+
     /// Add files to the TempFiles struct
     pub fn add_files<I>(&mut self, files: I)
     where
@@ -171,3 +224,41 @@ impl TempFiles {
         &self.content_type_map
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_files_globs() {
+        let tmp = tempfile::tempdir().unwrap();
+        let prefix = tmp.path();
+        for file in [
+            "include/foo.h",
+            "include/private.h",
+            "lib/libfoo.so",
+            "bin/foo",
+        ] {
+            let path = prefix.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, "").unwrap();
+        }
+
+        let files = GlobVec::from_vec(vec!["include/", "share/**"], Some(vec!["**/private.h"]));
+        let found = Files::from_prefix(prefix, &GlobVec::default(), &files).unwrap();
+        let mut new_files = found
+            .new_files
+            .iter()
+            .filter(|f| f.is_file())
+            .map(|f| f.strip_prefix(prefix).unwrap().to_path_buf())
+            .collect::<Vec<_>>();
+        new_files.sort();
+        assert_eq!(new_files, vec![PathBuf::from("include/foo.h")]);
+
+        // only excluding files keeps all others
+        let files = GlobVec::from_vec(vec![], Some(vec!["bin/**"]));
+        let found = Files::from_prefix(prefix, &GlobVec::default(), &files).unwrap();
+        assert!(found.new_files.contains(&prefix.join("lib/libfoo.so")));
+        assert!(!found.new_files.contains(&prefix.join("bin/foo")));
+    }
+}
@@ -81,12 +81,33 @@ pub fn filter_file(relative_path: &Path) -> bool {
     false
 }
 
+/// Map a file of a `noarch: python` package to its location in the package
+/// (`site-packages/...`). If the python interpreter exports its site-packages
+/// path (e.g. `lib/pypy3.10/site-packages` for PyPy), that path is stripped.
+/// Otherwise, everything before the `site-packages` folder is stripped.
+/// Returns `None` for files that are not in site-packages.
+fn noarch_site_packages_path(
+    path_rel: &Path,
+    python_site_packages_path: Option<&str>,
+) -> Option<PathBuf> {
+    if let Some(rest) = python_site_packages_path
+        .and_then(|site_packages| path_rel.strip_prefix(site_packages).ok())
+    {
+        return Some(Path::new("site-packages").join(rest));
+    }
+
+    let pat = Component::Normal("site-packages".as_ref());
+    let position = path_rel.components().position(|c| c == pat)?;
+    Some(PathBuf::from_iter(path_rel.components().skip(position)))
+}
+
 impl Output {
     /// This function copies the given file to the destination folder and
     /// transforms it on the way if needed.
     ///
-    /// * For `noarch: python` packages, the "lib/pythonX.X" prefix is stripped so that only
-    ///   the "site-packages" part is kept. Additionally, any `__pycache__` directories or
+    /// * For `noarch: python` packages, the "lib/pythonX.X" prefix (or the
+    ///   `python_site_packages_path` of the python interpreter, e.g. for PyPy) is stripped so
+    ///   that only the "site-packages" part is kept. Additionally, any `__pycache__` directories or
     ///   `.pyc` files are skipped.
     /// * For `noarch: python` packages, furthermore `bin` is replaced with `python-scripts`, and
     ///   `Scripts` is replaced with `python-scripts` (on Windows only). All other files are included
@@ -156,25 +177,10 @@ impl Output {
                 return Ok(None);
             }
 
-            if path_rel
-                .components()
-                .any(|c| c == Component::Normal("site-packages".as_ref()))
+            if let Some(site_packages_path) =
+                noarch_site_packages_path(path_rel, self.python_site_packages_path())
             {
-                // check if site-packages is in the path and strip everything before it
-                let pat = std::path::Component::Normal("site-packages".as_ref());
-                let parts = path_rel.components();
-                let mut new_parts = Vec::new();
-                let mut found = false;
-                for part in parts {
-                    if part == pat {
-                        found = true;
-                    }
-                    if found {
-                        new_parts.push(part);
-                    }
-                }
-
-                dest_path = dest_folder.join(PathBuf::from_iter(new_parts));
+                dest_path = dest_folder.join(site_packages_path);
             } else if path_rel.starts_with("bin") || path_rel.starts_with("Scripts") {
                 // Replace bin with python-scripts. These should really be encoded
                 // as entrypoints but sometimes recipe authors forget or don't know
@@ -262,9 +268,6 @@ impl Output {
                         e
                     })?;
                 } else {
-                    if target.is_absolute() {
-                        tracing::warn!("Symlink {:?} points outside of the prefix", path);
-                    }
                     symlink(&target, &dest_path).map_err(|e| {
                         tracing::error!(
                             "Could not create symlink from {:?} to {:?}: {:?}",
@@ -324,6 +327,50 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_noarch_site_packages_path() {
+        let test_cases = vec![
+            (
+                "lib/python3.12/site-packages/foo/__init__.py",
+                None,
+                Some("site-packages/foo/__init__.py"),
+            ),
+            (
+                "Lib/site-packages/foo.py",
+                None,
+                Some("site-packages/foo.py"),
+            ),
+            (
+                "lib/pypy3.10/site-packages/foo.py",
+                None,
+                Some("site-packages/foo.py"),
+            ),
+            (
+                "lib/graalpy24.1/lib-python/site/foo.py",
+                Some("lib/graalpy24.1/lib-python/site"),
+                Some("site-packages/foo.py"),
+            ),
+            (
+                "lib/python3.13t/site-packages/foo.py",
+                Some("lib/python3.13t/site-packages"),
+                Some("site-packages/foo.py"),
+            ),
+            (
+                "share/foo/data.txt",
+                Some("lib/pypy3.10/site-packages"),
+                None,
+            ),
+            ("bin/foo", None, None),
+        ];
+
+        for (path, site_packages_path, expected) in test_cases {
+            assert_eq!(
+                super::noarch_site_packages_path(Path::new(path), site_packages_path),
+                expected.map(PathBuf::from)
+            );
+        }
+    }
+
     #[test]
     fn test_filter_pyc() {
         let mut old_files = HashSet::new();
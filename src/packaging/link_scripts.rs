@@ -0,0 +1,331 @@
+//! Package the link scripts (`pre-link`, `post-link` and `pre-unlink`) and the
+//! prelink message (`about.prelink_message`) of the recipe, and validate link scripts that were placed in
+//! the prefix by the build script.
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use fs_err as fs;
+use rattler_conda_types::Platform;
+
+use crate::metadata::Output;
+
+use super::PackagingError;
+
+/// The actions for which conda executes link scripts
+const LINK_ACTIONS: [&str; 3] = ["pre-link", "post-link", "pre-unlink"];
+
+/// Commands that are known to cause problems in link scripts. Link scripts run
+/// during the installation, often without network access, and block the
+/// installation until they are done.
+const PITFALLS: [(&str, &str); 9] = [
+    ("curl ", "accesses the network (`curl`)"),
+    ("wget ", "accesses the network (`wget`)"),
+    (
+        "Invoke-WebRequest",
+        "accesses the network (`Invoke-WebRequest`)",
+    ),
+    ("pip install", "installs packages with `pip`"),
+    (
+        "conda install",
+        "modifies the environment with `conda install`",
+    ),
+    (
+        "conda update",
+        "modifies the environment with `conda update`",
+    ),
+    (
+        "mamba install",
+        "modifies the environment with `mamba install`",
+    ),
+    ("sleep ", "may run for a long time (`sleep`)"),
+    ("timeout /t", "may run for a long time (`timeout`)"),
+];
+
+/// A link script file name (`.<name>-<action>.<extension>`)
+#[derive(Debug, PartialEq, Eq)]
+struct LinkScriptName<'a> {
+    package: &'a str,
+    action: &'a str,
+    extension: &'a str,
+}
+
+/// Parse the file name of a link script. Returns `None` for other files.
+fn parse_link_script_name(file_name: &str) -> Option<LinkScriptName<'_>> {
+    let (stem, extension) = file_name.strip_prefix('.')?.rsplit_once('.')?;
+    if !matches!(extension, "sh" | "bat") {
+        return None;
+    }
+    LINK_ACTIONS.iter().find_map(|action| {
+        let package = stem.strip_suffix(action)?.strip_suffix('-')?;
+        (!package.is_empty()).then_some(LinkScriptName {
+            package,
+            action,
+            extension,
+        })
+    })
+}
+
+/// The folder in which conda looks for link scripts with the given extension
+fn script_folder(extension: &str) -> &'static str {
+    if extension == "bat" {
+        "Scripts"
+    } else {
+        "bin"
+    }
+}
+
+/// The problems of a link script that are worth a warning
+fn find_pitfalls(content: &str) -> Vec<&'static str> {
+    PITFALLS
+        .iter()
+        .filter(|(pattern, _)| content.contains(pattern))
+        .map(|(_, message)| *message)
+        .collect()
+}
+
+/// Resolve the script files of a link script from the recipe. A path without
+/// extension refers to the `.sh` (or `.bat` on Windows) script; for noarch
+/// packages, both scripts are used if they exist.
+fn resolve_script(
+    recipe_dir: &Path,
+    path: &Path,
+    platform: Platform,
+) -> Result<Vec<PathBuf>, String> {
+    let path = recipe_dir.join(path);
+    let candidates = match path.extension().and_then(|e| e.to_str()) {
+        Some(extension @ ("sh" | "bat")) => {
+            let expected = if platform.is_windows() { "bat" } else { "sh" };
+            if platform != Platform::NoArch && extension != expected {
+                return Err(format!(
+                    "`{}` is not a `.{}` script, which is required for {}",
+                    path.display(),
+                    expected,
+                    platform
+                ));
+            }
+            vec![path]
+        }
+        Some(extension) => {
+            return Err(format!(
+                "`{}` has the extension `.{}`, but link scripts need to be `.sh` or `.bat` scripts",
+                path.display(),
+                extension
+            ))
+        }
+        None if platform == Platform::NoArch => {
+            vec![path.with_extension("sh"), path.with_extension("bat")]
+        }
+        None if platform.is_windows() => vec![path.with_extension("bat")],
+        None => vec![path.with_extension("sh")],
+    };
+
+    let existing = candidates
+        .iter()
+        .filter(|candidate| candidate.is_file())
+        .cloned()
+        .collect::<Vec<_>>();
+    if existing.is_empty() {
+        return Err(format!(
+            "could not find {}",
+            candidates
+                .iter()
+                .map(|candidate| format!("`{}`", candidate.display()))
+                .collect::<Vec<_>>()
+                .join(" or ")
+        ));
+    }
+    Ok(existing)
+}
+
+/// Validate the link scripts that the build script placed in the prefix. Link
+/// scripts with a wrong name or in the wrong folder are never executed, so we
+/// warn about them. It is an error if a link script is also set in the recipe.
+pub fn check_link_scripts(
+    output: &Output,
+    prefix: &Path,
+    files: &HashSet<PathBuf>,
+) -> Result<(), PackagingError> {
+    let name = output.name().as_normalized();
+    let configured = output
+        .recipe
+        .build()
+        .link_scripts()
+        .iter()
+        .map(|(action, _)| action)
+        .collect::<Vec<_>>();
+
+    let mut errors = Vec::new();
+    for file in files {
+        let Some(file_name) = file.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        let Some(script) = parse_link_script_name(file_name) else {
+            continue;
+        };
+        let relative = file.strip_prefix(prefix).unwrap_or(file);
+        let folder = script_folder(script.extension);
+
+        if relative.parent() != Some(Path::new(folder)) {
+            tracing::warn!(
+                "The {} script `{}` is not in `{}/` and will not be executed",
+                script.action,
+                relative.display(),
+                folder
+            );
+        } else if script.package != name {
+            tracing::warn!(
+                "The {} script `{}` does not match the package name `{}` and will not be executed",
+                script.action,
+                relative.display(),
+                name
+            );
+        } else if configured.iter().any(|action| *action == script.action) {
+            errors.push(format!(
+                "`{}` was created by the build script, but `build.link_scripts` also sets a {} script",
+                relative.display(),
+                script.action
+            ));
+        } else {
+            tracing::info!(
+                "Consider setting the {} script `{}` with `build.link_scripts` instead of creating it in the build script",
+                script.action,
+                relative.display()
+            );
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(PackagingError::InvalidLinkScript(errors.join("\n")))
+    }
+}
+
+/// Copy the link scripts of the recipe to their location in the package and
+/// write the prelink message to `info/prelink_messages`. Returns the new files.
+pub fn write_link_scripts(
+    output: &Output,
+    tmp_dir_path: &Path,
+) -> Result<Vec<PathBuf>, PackagingError> {
+    let build = output.recipe.build();
+    let recipe_dir = &output.build_configuration.directories.recipe_dir;
+    let platform = output.build_configuration.target_platform;
+    let name = output.name().as_normalized();
+
+    let mut new_files = Vec::new();
+    for (action, path) in build.link_scripts().iter() {
+        let scripts = resolve_script(recipe_dir, path, platform).map_err(|e| {
+            PackagingError::InvalidLinkScript(format!("invalid {} script: {}", action, e))
+        })?;
+
+        for script in scripts {
+            let extension = script
+                .extension()
+                .and_then(|e| e.to_str())
+                .unwrap_or_default();
+            let content = fs::read_to_string(&script)?;
+            for pitfall in find_pitfalls(&content) {
+                tracing::warn!(
+                    "The {} script `{}` {}. Link scripts run during the installation and should be fast and work offline.",
+                    action,
+                    path.display(),
+                    pitfall
+                );
+            }
+
+            let dest = tmp_dir_path
+                .join(script_folder(extension))
+                .join(format!(".{}-{}.{}", name, action, extension));
+            fs::create_dir_all(dest.parent().expect("destination has a parent"))?;
+            fs::copy(&script, &dest)?;
+            tracing::info!("Packaging {} script as `{}`", action, dest.display());
+            new_files.push(dest);
+        }
+    }
+
+    if build.link_scripts().pre_link.is_some() {
+        tracing::warn!("pre-link scripts are deprecated and not executed by all installers");
+    }
+
+    if let Some(message) = &output.recipe.about().prelink_message {
+        let messages_folder = tmp_dir_path.join("info/prelink_messages");
+        fs::create_dir_all(&messages_folder)?;
+        let message_file = messages_folder.join(format!("{}.txt", name));
+        fs::write(&message_file, message)?;
+        new_files.push(message_file);
+    }
+
+    Ok(new_files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_link_script_name() {
+        assert_eq!(
+            parse_link_script_name(".my-pkg-post-link.sh"),
+            Some(LinkScriptName {
+                package: "my-pkg",
+                action: "post-link",
+                extension: "sh"
+            })
+        );
+        assert_eq!(
+            parse_link_script_name(".foo-pre-unlink.bat"),
+            Some(LinkScriptName {
+                package: "foo",
+                action: "pre-unlink",
+                extension: "bat"
+            })
+        );
+        assert_eq!(parse_link_script_name("foo-post-link.sh"), None);
+        assert_eq!(parse_link_script_name(".foo-post-link.py"), None);
+        assert_eq!(parse_link_script_name(".-post-link.sh"), None);
+    }
+
+    #[test]
+    fn test_find_pitfalls() {
+        assert!(find_pitfalls("echo 'done' >> $PREFIX/.messages.txt").is_empty());
+        assert_eq!(
+            find_pitfalls("curl -O https://example.com/data\nsleep 10"),
+            vec![
+                "accesses the network (`curl`)",
+                "may run for a long time (`sleep`)"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resolve_script() {
+        let recipe_dir = tempfile::tempdir().unwrap();
+        let recipe_dir = recipe_dir.path();
+        fs::write(recipe_dir.join("post-link.sh"), "").unwrap();
+        fs::write(recipe_dir.join("post-link.bat"), "").unwrap();
+        fs::write(recipe_dir.join("pre-unlink.sh"), "").unwrap();
+
+        let resolve = |path: &str, platform| resolve_script(recipe_dir, Path::new(path), platform);
+
+        assert_eq!(
+            resolve("post-link", Platform::Linux64).unwrap(),
+            vec![recipe_dir.join("post-link.sh")]
+        );
+        assert_eq!(
+            resolve("post-link", Platform::Win64).unwrap(),
+            vec![recipe_dir.join("post-link.bat")]
+        );
+        assert_eq!(
+            resolve("post-link", Platform::NoArch).unwrap(),
+            vec![
+                recipe_dir.join("post-link.sh"),
+                recipe_dir.join("post-link.bat")
+            ]
+        );
+        assert!(resolve("pre-unlink", Platform::Win64).is_err());
+        assert!(resolve("post-link.sh", Platform::Win64).is_err());
+        assert!(resolve("post-link.py", Platform::Linux64).is_err());
+    }
+}
@@ -23,7 +23,12 @@ use rattler_conda_types::{
 use rattler_digest::{compute_bytes_digest, compute_file_digest};
 
 use super::{PackagingError, TempFiles};
-use crate::{hash::HashInput, metadata::Output, recipe::parser::PrefixDetection};
+use crate::{
+    hash::HashInput,
+    metadata::Output,
+    normalized_key::NormalizedKey,
+    recipe::parser::{AllOrGlobVec, PrefixDetection},
+};
 
 /// Detect if the file contains the prefix in binary mode.
 #[allow(unused_variables)]
@@ -190,6 +195,25 @@ pub fn create_prefix_placeholder(
     }))
 }
 
+/// Drop the prefix placeholder of a binary that is excluded from binary
+/// relocation (`build.dynamic_linking.binary_relocation`). Returns true if the
+/// binary contains the build prefix, i.e. it breaks when the package is
+/// installed to another prefix.
+fn exclude_from_relocation(
+    prefix_placeholder: &mut Option<PrefixPlaceholder>,
+    relative_path: &Path,
+    binary_relocation: &AllOrGlobVec,
+) -> bool {
+    let is_binary = prefix_placeholder
+        .as_ref()
+        .is_some_and(|placeholder| placeholder.file_mode == FileMode::Binary);
+    if !is_binary || binary_relocation.is_match(relative_path) {
+        return false;
+    }
+    *prefix_placeholder = None;
+    true
+}
+
 impl Output {
     /// Create the run_exports.json file for the given output.
     pub fn run_exports_json(&self) -> Result<&RunExportsJson, PackagingError> {
@@ -203,14 +227,17 @@ impl Output {
 
     /// Returns the contents of the `hash_input.json` file.
     pub fn hash_input(&self) -> HashInput {
-        HashInput::from_variant(&self.build_configuration.variant)
+        HashInput::from_variant_with_config(
+            &self.build_configuration.variant,
+            &self.build_configuration.hash_config,
+        )
     }
 
     /// Create the about.json file for the given output.
     pub fn about_json(&self) -> AboutJson {
         let recipe = &self.recipe;
 
-        let about_json = AboutJson {
+        let mut about_json = AboutJson {
             home: recipe
                 .about()
                 .homepage
@@ -244,6 +271,23 @@ impl Output {
             extra: self.extra_meta.clone().unwrap_or_default(),
         };
 
+        // record the destination channel and label (e.g. `conda-forge main`)
+        // so that uploaders can use it as the default target
+        if let Some(channel_targets) = self.variant().get(&NormalizedKey::from("channel_targets")) {
+            about_json
+                .extra
+                .entry("channel_targets".to_string())
+                .or_insert_with(|| channel_targets.clone().into());
+        }
+
+        // record the versions of rattler-build and the system tools that were
+        // used
+        about_json.extra.insert(
+            "system_tools".to_string(),
+            serde_json::to_value(self.system_tools.versions())
+                .expect("a map of strings is valid JSON"),
+        );
+
         about_json
     }
 
@@ -291,6 +335,27 @@ impl Output {
             *self.recipe.build().noarch()
         };
 
+        let mut depends = finalized_dependencies
+            .run
+            .depends
+            .iter()
+            .map(|dep| dep.spec().to_string())
+            .dedup()
+            .collect::<Vec<_>>();
+
+        // `noarch` packages that are built for a specific platform (family) can
+        // only be installed on platforms with the matching virtual packages
+        if let Some(noarch_platform) = self.noarch_platform() {
+            for virtual_package in noarch_platform.virtual_packages() {
+                if !depends
+                    .iter()
+                    .any(|dep| dep.split_whitespace().next() == Some(virtual_package))
+                {
+                    depends.push(virtual_package.to_string());
+                }
+            }
+        }
+
         Ok(IndexJson {
             name: self.name().clone(),
             version: self.version().clone().into(),
@@ -302,13 +367,7 @@ impl Output {
             license: recipe.about().license.as_ref().map(|l| l.to_string()),
             license_family: recipe.about().license_family.clone(),
             timestamp: Some(self.build_configuration.timestamp),
-            depends: finalized_dependencies
-                .run
-                .depends
-                .iter()
-                .map(|dep| dep.spec().to_string())
-                .dedup()
-                .collect(),
+            depends,
             constrains: finalized_dependencies
                 .run
                 .constraints
@@ -344,6 +403,8 @@ impl Output {
     /// or text, and if it contains the prefix.
     pub fn paths_json(&self, temp_files: &TempFiles) -> Result<PathsJson, PackagingError> {
         let always_copy_files = self.recipe.build().always_copy_files();
+        let binary_relocation = self.recipe.build().dynamic_linking().binary_relocation();
+        let mut unrelocated_binaries = Vec::new();
 
         let mut paths_json = PathsJson {
             paths: Vec::new(),
@@ -423,7 +484,7 @@ impl Output {
             } else if meta.is_file() {
                 let content_type =
                     content_type.ok_or_else(|| PackagingError::ContentTypeNotFound(p.clone()))?;
-                let prefix_placeholder = create_prefix_placeholder(
+                let mut prefix_placeholder = create_prefix_placeholder(
                     &self.build_configuration.target_platform,
                     p,
                     temp_files.temp_dir.path(),
@@ -431,6 +492,13 @@ impl Output {
                     &content_type,
                     self.recipe.build().prefix_detection(),
                 )?;
+                if exclude_from_relocation(
+                    &mut prefix_placeholder,
+                    &relative_path,
+                    binary_relocation,
+                ) {
+                    unrelocated_binaries.push(relative_path.clone());
+                }
 
                 let digest = compute_file_digest::<sha2::Sha256>(p)?;
                 let no_link = always_copy_files.is_match(&relative_path);
@@ -460,6 +528,18 @@ impl Output {
             }
         }
 
+        if !unrelocated_binaries.is_empty() {
+            let warn_str = format!(
+                "The following binaries are excluded from `binary_relocation` but contain the build prefix. They will break when the package is installed to another prefix:\n{}",
+                unrelocated_binaries
+                    .iter()
+                    .map(|path| format!(" - {}", path.display()))
+                    .join("\n")
+            );
+            tracing::warn!(warn_str);
+            self.record_warning(&warn_str);
+        }
+
         Ok(paths_json)
     }
 
@@ -510,8 +590,10 @@ mod test {
     use content_inspector::ContentType;
     use rattler_conda_types::Platform;
 
-    use super::create_prefix_placeholder;
-    use crate::recipe::parser::PrefixDetection;
+    use rattler_conda_types::package::{FileMode, PrefixPlaceholder};
+
+    use super::{create_prefix_placeholder, exclude_from_relocation};
+    use crate::recipe::parser::{AllOrGlobVec, GlobVec, PrefixDetection};
 
     #[test]
     fn detect_prefix() {
@@ -529,4 +611,41 @@ mod test {
         )
         .unwrap();
     }
+
+    #[test]
+    fn exclude_binaries_from_relocation() {
+        let placeholder = |file_mode| {
+            Some(PrefixPlaceholder {
+                file_mode,
+                placeholder: "/build/prefix".to_string(),
+            })
+        };
+        let relocate_lib = AllOrGlobVec::SpecificPaths(GlobVec::from_vec(vec!["lib/**"], None));
+
+        let mut prefix_placeholder = placeholder(FileMode::Binary);
+        let path = std::path::Path::new("lib/libfoo.so");
+        assert!(!exclude_from_relocation(
+            &mut prefix_placeholder,
+            path,
+            &relocate_lib
+        ));
+        assert!(prefix_placeholder.is_some());
+
+        let path = std::path::Path::new("bin/foo");
+        assert!(exclude_from_relocation(
+            &mut prefix_placeholder,
+            path,
+            &relocate_lib
+        ));
+        assert!(prefix_placeholder.is_none());
+
+        // text files are always relocated
+        let mut prefix_placeholder = placeholder(FileMode::Text);
+        assert!(!exclude_from_relocation(
+            &mut prefix_placeholder,
+            path,
+            &AllOrGlobVec::All(false)
+        ));
+        assert!(prefix_placeholder.is_some());
+    }
 }
@@ -0,0 +1,126 @@
+//! Check the size of the package content against the `build.size_budget` of
+//! the recipe.
+use std::path::{Path, PathBuf};
+
+use fs_err as fs;
+use indicatif::HumanBytes;
+
+use crate::recipe::parser::{SizeBudget, SizeBudgetBehavior};
+
+use super::PackagingError;
+
+/// The number of files that are shown in the breakdown
+const BREAKDOWN_SIZE: usize = 10;
+
+/// Collect the sizes of all regular files (symlinks and directories are not
+/// counted), sorted from largest to smallest
+fn file_sizes(root: &Path, files: &[PathBuf]) -> Result<Vec<(PathBuf, u64)>, std::io::Error> {
+    let mut sizes = Vec::with_capacity(files.len());
+    for file in files {
+        let path = root.join(file);
+        let metadata = fs::symlink_metadata(&path)?;
+        if metadata.is_file() {
+            let relative = path.strip_prefix(root).unwrap_or(&path).to_path_buf();
+            sizes.push((relative, metadata.len()));
+        }
+    }
+    sizes.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    Ok(sizes)
+}
+
+/// Returns a description of all violations of the budget, or `None` if the
+/// package is within its budget
+fn violations(budget: &SizeBudget, sizes: &[(PathBuf, u64)]) -> Option<String> {
+    let mut messages = Vec::new();
+
+    let total = sizes.iter().map(|(_, size)| size).sum::<u64>();
+    if let Some(limit) = budget.total {
+        if total > limit.0 {
+            messages.push(format!(
+                "total size of {} exceeds the budget of {}",
+                HumanBytes(total),
+                HumanBytes(limit.0)
+            ));
+        }
+    }
+
+    if let Some(limit) = budget.per_file {
+        for (path, size) in sizes.iter().take_while(|(_, size)| *size > limit.0) {
+            messages.push(format!(
+                "{} ({}) exceeds the per-file budget of {}",
+                path.display(),
+                HumanBytes(*size),
+                HumanBytes(limit.0)
+            ));
+        }
+    }
+
+    if messages.is_empty() {
+        return None;
+    }
+
+    messages.push(format!("largest files ({} total):", HumanBytes(total)));
+    for (path, size) in sizes.iter().take(BREAKDOWN_SIZE) {
+        messages.push(format!("  {:>10}  {}", HumanBytes(*size), path.display()));
+    }
+
+    Some(messages.join("\n"))
+}
+
+/// Check the files of the package against the size budget. Depending on the
+/// configured behavior, exceeding the budget is an error or a warning.
+pub fn check_size_budget(
+    budget: &SizeBudget,
+    root: &Path,
+    files: &[PathBuf],
+) -> Result<(), PackagingError> {
+    if budget.is_default() {
+        return Ok(());
+    }
+
+    let sizes = file_sizes(root, files)?;
+    let Some(report) = violations(budget, &sizes) else {
+        return Ok(());
+    };
+
+    match budget.behavior {
+        SizeBudgetBehavior::Error => Err(PackagingError::SizeBudgetExceeded(report)),
+        SizeBudgetBehavior::Warn => {
+            tracing::warn!("Package exceeds its size budget:\n{}", report);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recipe::parser::FileSize;
+
+    #[test]
+    fn test_size_budget() {
+        let sizes = vec![
+            (PathBuf::from("lib/libfoo.a"), 3000),
+            (PathBuf::from("lib/libfoo.so"), 1000),
+            (PathBuf::from("info/index.json"), 10),
+        ];
+
+        let within = SizeBudget {
+            total: Some(FileSize(5000)),
+            per_file: Some(FileSize(3000)),
+            ..SizeBudget::default()
+        };
+        assert!(violations(&within, &sizes).is_none());
+
+        let exceeded = SizeBudget {
+            total: Some(FileSize(4000)),
+            per_file: Some(FileSize(2000)),
+            ..SizeBudget::default()
+        };
+        let report = violations(&exceeded, &sizes).unwrap();
+        assert!(report.contains("total size of"));
+        assert!(report.contains("lib/libfoo.a"));
+        assert!(!report.contains("lib/libfoo.so (")); // within the per-file budget
+        assert!(report.contains("largest files"));
+    }
+}
@@ -0,0 +1,230 @@
+//! Classify the symlinks of a package and check them against the
+//! `build.symlinks` settings of the recipe.
+use std::{
+    collections::HashSet,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use fs_err as fs;
+
+use crate::{
+    recipe::parser::{SymlinkBehavior, SymlinkSettings},
+    utils::to_lexical_absolute,
+};
+
+use super::PackagingError;
+
+/// The kind of a symlink in the package
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SymlinkKind {
+    /// A relative symlink to a file in the prefix
+    Relative,
+    /// An absolute symlink to a file in the prefix (made relative when packaging)
+    AbsoluteInPrefix,
+    /// A symlink to a file in the prefix that does not exist
+    Dangling,
+    /// A symlink that points outside of the prefix
+    OutsidePrefix,
+}
+
+impl fmt::Display for SymlinkKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SymlinkKind::Relative => write!(f, "relative"),
+            SymlinkKind::AbsoluteInPrefix => write!(f, "made relative"),
+            SymlinkKind::Dangling => write!(f, "dangling"),
+            SymlinkKind::OutsidePrefix => write!(f, "outside prefix"),
+        }
+    }
+}
+
+/// Classify a symlink at `path` (in the prefix) that points to `target`
+pub fn classify_symlink(path: &Path, target: &Path, prefix: &Path) -> SymlinkKind {
+    let parent = path.parent().unwrap_or(prefix);
+    let resolved = to_lexical_absolute(target, parent);
+    if !resolved.starts_with(prefix) {
+        return SymlinkKind::OutsidePrefix;
+    }
+    // follows the whole chain of symlinks
+    if !path.exists() {
+        return SymlinkKind::Dangling;
+    }
+    if target.is_absolute() {
+        SymlinkKind::AbsoluteInPrefix
+    } else {
+        SymlinkKind::Relative
+    }
+}
+
+/// A symlink of the package
+#[derive(Debug)]
+struct Symlink {
+    path: PathBuf,
+    target: PathBuf,
+    kind: SymlinkKind,
+}
+
+/// Collect and classify all symlinks in the given files (sorted by path)
+fn collect_symlinks(prefix: &Path, files: &HashSet<PathBuf>) -> Vec<Symlink> {
+    let mut symlinks = files
+        .iter()
+        .filter(|path| path.is_symlink())
+        .filter_map(|path| {
+            let target = fs::read_link(path).ok()?;
+            Some(Symlink {
+                kind: classify_symlink(path, &target, prefix),
+                path: path.strip_prefix(prefix).unwrap_or(path).to_path_buf(),
+                target,
+            })
+        })
+        .collect::<Vec<_>>();
+    symlinks.sort_by(|a, b| a.path.cmp(&b.path));
+    symlinks
+}
+
+/// The behavior that is configured for a kind of symlink
+fn behavior(settings: &SymlinkSettings, kind: SymlinkKind) -> SymlinkBehavior {
+    match kind {
+        SymlinkKind::Dangling => settings.dangling,
+        SymlinkKind::OutsidePrefix => settings.outside_prefix,
+        SymlinkKind::Relative | SymlinkKind::AbsoluteInPrefix => SymlinkBehavior::Ignore,
+    }
+}
+
+/// A table with the number of symlinks per kind and all problematic symlinks
+fn summary_table(symlinks: &[Symlink]) -> comfy_table::Table {
+    let mut table = comfy_table::Table::new();
+    table
+        .load_preset(comfy_table::presets::UTF8_FULL_CONDENSED)
+        .apply_modifier(comfy_table::modifiers::UTF8_ROUND_CORNERS)
+        .set_header(vec!["Symlink", "Target", "Kind"]);
+
+    for kind in [
+        SymlinkKind::Relative,
+        SymlinkKind::AbsoluteInPrefix,
+        SymlinkKind::Dangling,
+        SymlinkKind::OutsidePrefix,
+    ] {
+        let links = symlinks
+            .iter()
+            .filter(|link| link.kind == kind)
+            .collect::<Vec<_>>();
+        if links.is_empty() {
+            continue;
+        }
+        // list the problematic links, only count the others
+        if matches!(kind, SymlinkKind::Dangling | SymlinkKind::OutsidePrefix) {
+            for link in links {
+                table.add_row(vec![
+                    link.path.display().to_string(),
+                    link.target.display().to_string(),
+                    kind.to_string(),
+                ]);
+            }
+        } else {
+            table.add_row(vec![
+                format!("{} symlinks", links.len()),
+                String::new(),
+                kind.to_string(),
+            ]);
+        }
+    }
+    table
+}
+
+/// Check the symlinks of the package against the settings. A summary of the
+/// symlinks is logged, and depending on the settings, dangling symlinks and
+/// symlinks that point outside of the prefix are an error or a warning.
+pub fn check_symlinks(
+    settings: &SymlinkSettings,
+    prefix: &Path,
+    files: &HashSet<PathBuf>,
+) -> Result<(), PackagingError> {
+    let symlinks = collect_symlinks(prefix, files);
+    if symlinks.is_empty() {
+        return Ok(());
+    }
+    tracing::info!("\nSymlinks in package:\n{}\n", summary_table(&symlinks));
+
+    let mut errors = Vec::new();
+    for link in &symlinks {
+        let message = format!(
+            "{} symlink {} -> {}",
+            link.kind,
+            link.path.display(),
+            link.target.display()
+        );
+        match behavior(settings, link.kind) {
+            SymlinkBehavior::Error => errors.push(message),
+            SymlinkBehavior::Warn => tracing::warn!("Packaging {}", message),
+            SymlinkBehavior::Ignore => {}
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(PackagingError::InvalidSymlinks(errors.join("\n")))
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use fs_err::os::unix::fs::symlink;
+
+    use super::*;
+
+    #[test]
+    fn test_check_symlinks() {
+        let tmp = tempfile::tempdir().unwrap();
+        let prefix = tmp.path().join("prefix");
+        fs::create_dir_all(prefix.join("lib")).unwrap();
+        fs::write(prefix.join("lib/libfoo.so.1"), "").unwrap();
+
+        let links = [
+            ("lib/libfoo.so", PathBuf::from("libfoo.so.1")),
+            ("lib/libfoo.so.1.0", prefix.join("lib/libfoo.so.1")),
+            ("lib/libbar.so", PathBuf::from("libbar.so.1")),
+            ("lib/libc.so", PathBuf::from("/usr/lib/libc.so")),
+            ("lib/escape.so", PathBuf::from("../../outside.so")),
+        ];
+        let mut files = HashSet::new();
+        for (path, target) in &links {
+            let path = prefix.join(path);
+            symlink(target, &path).unwrap();
+            files.insert(path);
+        }
+
+        let kinds = collect_symlinks(&prefix, &files)
+            .into_iter()
+            .map(|link| (link.path, link.kind))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                (PathBuf::from("lib/escape.so"), SymlinkKind::OutsidePrefix),
+                (PathBuf::from("lib/libbar.so"), SymlinkKind::Dangling),
+                (PathBuf::from("lib/libc.so"), SymlinkKind::OutsidePrefix),
+                (PathBuf::from("lib/libfoo.so"), SymlinkKind::Relative),
+                (
+                    PathBuf::from("lib/libfoo.so.1.0"),
+                    SymlinkKind::AbsoluteInPrefix
+                ),
+            ]
+        );
+
+        // the defaults only warn
+        assert!(check_symlinks(&SymlinkSettings::default(), &prefix, &files).is_ok());
+
+        let strict = SymlinkSettings {
+            dangling: SymlinkBehavior::Error,
+            outside_prefix: SymlinkBehavior::Ignore,
+        };
+        let err = check_symlinks(&strict, &prefix, &files).unwrap_err();
+        let PackagingError::InvalidSymlinks(message) = err else {
+            panic!("expected an invalid symlinks error");
+        };
+        assert_eq!(message, "dangling symlink lib/libbar.so -> libbar.so.1");
+    }
+}
@@ -30,6 +30,20 @@ pub enum LinkingCheckError {
 
     #[error("failed to build glob from pattern")]
     GlobError(#[from] globset::Error),
+
+    #[error(
+        "Binaries require a newer macOS than the deployment target {deployment_target}:\n{report}"
+    )]
+    DeploymentTarget {
+        deployment_target: String,
+        report: String,
+    },
+
+    #[error("Binaries require symbols from a glibc newer than {glibc_version}:\n{report}")]
+    GlibcVersion {
+        glibc_version: String,
+        report: String,
+    },
 }
 
 #[derive(Debug)]
@@ -3,3 +3,5 @@ pub mod package_nature;
 pub mod python;
 pub mod regex_replacements;
 pub mod relink;
+pub mod run_exports;
+pub mod split_debug;
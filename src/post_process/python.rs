@@ -4,6 +4,7 @@
 //!   - Fixing up the shebangs in scripts
 //!   - Compiling `.py` files to `.pyc` files
 //!   - Replacing the contents of `.dist-info/INSTALLER` files with "conda"
+//!   - Regenerating the `.dist-info/RECORD` files (with `build.python.fix_record`)
 use fs_err as fs;
 use rattler::install::{get_windows_launcher, python_entry_point_template, PythonInfo};
 use rattler_conda_types::Platform;
@@ -12,10 +13,13 @@ use std::io::{self, BufRead, BufReader, Write};
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rattler_digest::compute_file_digest;
+
 use crate::metadata::Output;
 use crate::packaging::{PackagingError, TempFiles};
 use crate::recipe::parser::GlobVec;
-use crate::utils::to_forward_slash_lossy;
+use crate::utils::{to_forward_slash_lossy, to_lexical_absolute};
 
 pub fn python_bin(prefix: &Path, target_platform: &Platform) -> PathBuf {
     if target_platform.is_windows() {
@@ -201,6 +205,100 @@ pub fn python(temp_files: &TempFiles, output: &Output) -> Result<HashSet<PathBuf
     Ok(result)
 }
 
+/// Split a line of a RECORD file into the path, hash and size. The path is
+/// quoted (CSV) if it contains a comma or a quote.
+fn parse_record_line(line: &str) -> Option<(String, &str, &str)> {
+    let mut fields = line.rsplitn(3, ',');
+    let size = fields.next()?;
+    let hash = fields.next()?;
+    let path = fields.next()?;
+    let path = match path.strip_prefix('"').and_then(|p| p.strip_suffix('"')) {
+        Some(quoted) => quoted.replace("\"\"", "\""),
+        None => path.to_string(),
+    };
+    Some((path, hash, size))
+}
+
+fn format_record_path(path: &str) -> String {
+    if path.contains(',') || path.contains('"') {
+        format!("\"{}\"", path.replace('"', "\"\""))
+    } else {
+        path.to_string()
+    }
+}
+
+/// Regenerate the `RECORD` file of a `.dist-info` directory after the files
+/// were modified (e.g. patched, removed or rewritten like the `INSTALLER`).
+/// Entries of files that are not part of the package anymore are removed, the
+/// hashes and sizes of the other files are recomputed. Entries that point
+/// outside of the package and entries without a hash (e.g. `.pyc` files) are
+/// kept as they are.
+fn regenerate_record(record: &Path, package_root: &Path) -> Result<bool, PackagingError> {
+    let dist_info = record.parent().expect("RECORD is in a dist-info directory");
+    let site_packages = dist_info
+        .parent()
+        .expect("dist-info is in a site-packages directory");
+
+    let content = fs::read_to_string(record)?;
+    let mut lines = Vec::new();
+    for line in content.lines().filter(|line| !line.trim().is_empty()) {
+        let Some((path, hash, size)) = parse_record_line(line) else {
+            lines.push(line.to_string());
+            continue;
+        };
+        let file = to_lexical_absolute(Path::new(&path), site_packages);
+        if !file.starts_with(package_root) || file == record {
+            lines.push(line.to_string());
+            continue;
+        }
+        if !file.is_file() {
+            tracing::debug!("Removing {} from {}", path, record.display());
+            continue;
+        }
+        if hash.is_empty() && size.is_empty() {
+            lines.push(line.to_string());
+            continue;
+        }
+        let digest = compute_file_digest::<sha2::Sha256>(&file)?;
+        lines.push(format!(
+            "{},sha256={},{}",
+            format_record_path(&path),
+            URL_SAFE_NO_PAD.encode(digest),
+            fs::metadata(&file)?.len()
+        ));
+    }
+
+    let mut new_content = lines.join("\n");
+    new_content.push('\n');
+    if new_content == content {
+        return Ok(false);
+    }
+    fs::write(record, new_content)?;
+    Ok(true)
+}
+
+/// Regenerate the `RECORD` files of all packaged `.dist-info` directories
+/// (with `build.python.fix_record: true`).
+pub fn fix_records(temp_files: &TempFiles, output: &Output) -> Result<(), PackagingError> {
+    if !output.recipe.build().python().fix_record {
+        return Ok(());
+    }
+
+    let glob = globset::Glob::new("**/*.dist-info/RECORD")?.compile_matcher();
+    for record in temp_files.files.iter().filter(|p| glob.is_match(p)) {
+        if regenerate_record(record, temp_files.temp_dir.path())? {
+            tracing::info!(
+                "Regenerated {}",
+                record
+                    .strip_prefix(temp_files.temp_dir.path())
+                    .unwrap_or(record)
+                    .display()
+            );
+        }
+    }
+    Ok(())
+}
+
 fn python_in_prefix(prefix: &Path, use_python_app_entrypoint: bool) -> String {
     if use_python_app_entrypoint {
         format!(
@@ -396,6 +494,51 @@ mod tests {
         assert_eq!(new_shebang, (false, "#!/some/path/to/ruby".to_string()));
     }
 
+    #[test]
+    fn test_regenerate_record() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let site_packages = tempdir.path().join("lib/python3.12/site-packages");
+        let dist_info = site_packages.join("foo-1.0.dist-info");
+        fs::create_dir_all(&dist_info).unwrap();
+        fs::create_dir_all(site_packages.join("foo")).unwrap();
+        fs::write(site_packages.join("foo/__init__.py"), "patched\n").unwrap();
+        fs::write(dist_info.join("INSTALLER"), "conda\n").unwrap();
+        fs::create_dir_all(tempdir.path().join("bin")).unwrap();
+        fs::write(tempdir.path().join("bin/foo"), "#!python\n").unwrap();
+
+        let record = dist_info.join("RECORD");
+        fs::write(
+            &record,
+            "foo/__init__.py,sha256=abc,3\n\
+             foo/tests/test_foo.py,sha256=def,10\n\
+             foo/__pycache__/__init__.cpython-312.pyc,,\n\
+             foo-1.0.dist-info/INSTALLER,sha256=ghi,4\n\
+             foo-1.0.dist-info/RECORD,,\n\
+             ../../../bin/foo,sha256=jkl,100\n",
+        )
+        .unwrap();
+
+        assert!(regenerate_record(&record, tempdir.path()).unwrap());
+        assert_eq!(
+            fs::read_to_string(&record).unwrap(),
+            "foo/__init__.py,sha256=EJT0pghSDmzYdEbXFKzB0qn6tiWvLgPlYb-lBjlEPq4,8\n\
+             foo-1.0.dist-info/INSTALLER,sha256=vDMCLty3Y5_1M1W06R2t5QoLvwKZ7-thcdHsC6UCnPw,6\n\
+             foo-1.0.dist-info/RECORD,,\n\
+             ../../../bin/foo,sha256=NKJyCC0fONcaYytG9mysca37TZf8wBDISvRNAW-78pQ,9\n"
+        );
+        // a second run does not change anything
+        assert!(!regenerate_record(&record, tempdir.path()).unwrap());
+    }
+
+    #[test]
+    fn test_parse_record_line() {
+        assert_eq!(
+            parse_record_line("\"foo/a,b.py\",sha256=abc,3"),
+            Some(("foo/a,b.py".to_string(), "sha256=abc", "3"))
+        );
+        assert_eq!(format_record_path("foo/a,b.py"), "\"foo/a,b.py\"");
+    }
+
     #[test]
     fn test_replace_shebang_in_file() {
         let tempdir = tempfile::tempdir().unwrap();
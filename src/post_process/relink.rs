@@ -3,8 +3,8 @@ use fs_err as fs;
 use crate::metadata::Output;
 use crate::packaging::TempFiles;
 
-use crate::linux::link::SharedObject;
-use crate::macos::link::Dylib;
+use crate::linux::{glibc::check_glibc_version, link::SharedObject};
+use crate::macos::{deployment_target::check_deployment_target, link::Dylib};
 use crate::recipe::parser::GlobVec;
 use crate::system_tools::{SystemTools, ToolError};
 use rattler_conda_types::{Arch, Platform};
@@ -178,7 +178,8 @@ pub fn relink(temp_files: &TempFiles, output: &Output) -> Result<(), RelinkError
             continue;
         }
 
-        if !relocation_config.is_match(p) {
+        // the globs are relative to the prefix
+        if !relocation_config.is_match(p.strip_prefix(tmp_prefix).unwrap_or(p)) {
             continue;
         }
         if is_valid_file(target_platform, p)? {
@@ -193,6 +194,11 @@ pub fn relink(temp_files: &TempFiles, output: &Output) -> Result<(), RelinkError
             binaries.insert(p.clone());
         }
     }
+    if target_platform.is_osx() {
+        check_deployment_target(output, &binaries, tmp_prefix)?;
+    } else if target_platform.is_linux() {
+        check_glibc_version(output, &binaries, tmp_prefix)?;
+    }
     perform_linking_checks(output, &binaries, tmp_prefix)?;
 
     Ok(())
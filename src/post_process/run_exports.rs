@@ -0,0 +1,212 @@
+//! Validate the `run_exports` of a package against the shared libraries that
+//! are packaged.
+//!
+//! A package that exports a pin to itself is expected to ship the shared
+//! library that downstream packages link against. We warn if the package does
+//! not contain any shared library, or if the major version of the pin does not
+//! match the version in the SONAME (install name on macOS) of its libraries.
+//!
+//! Many libraries use a SONAME version that is independent of the package
+//! version (e.g. `libcurl 8` ships `libcurl.so.4`), so the check only runs if
+//! it is enabled with `build.dynamic_linking.check_run_exports`.
+
+use std::path::{Path, PathBuf};
+
+use goblin::{mach::Mach, Object};
+use rattler_conda_types::{
+    package::RunExportsJson, MatchSpec, PackageName, ParseStrictness, Version,
+};
+
+use crate::{metadata::Output, packaging::TempFiles};
+
+/// Returns the SONAME of an ELF shared library or the install name of a
+/// Mach-O dylib. Executables and other files have none.
+pub fn soname(path: &Path) -> Option<String> {
+    let data = fs_err::read(path).ok()?;
+    let name = match Object::parse(&data).ok()? {
+        Object::Elf(elf) => elf.soname?,
+        Object::Mach(Mach::Binary(macho)) => macho.name?,
+        _ => return None,
+    };
+    // the install name is a path (e.g. `@rpath/libfoo.1.dylib`)
+    Path::new(name)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+}
+
+/// The major version in a SONAME, e.g. `2` for `libfoo.so.2.1` or
+/// `libfoo.2.dylib`. Unversioned names (`libfoo.so`) have none.
+pub fn soname_major(soname: &str) -> Option<u64> {
+    let version = if let Some((_, version)) = soname.split_once(".so.") {
+        version
+    } else {
+        let stem = soname.strip_suffix(".dylib")?;
+        let (_, version) = stem.split_once('.')?;
+        version
+    };
+    version.split('.').next()?.parse().ok()
+}
+
+/// The major version of a package version (ignoring the epoch)
+fn version_major(version: &Version) -> Option<u64> {
+    let version = version.to_string();
+    let version = version
+        .rsplit_once('!')
+        .map_or(version.as_str(), |(_, v)| v);
+    version.split(['.', '_', '-']).next()?.parse().ok()
+}
+
+/// Compare the pins of a package to itself in its `run_exports` with the
+/// SONAMEs of the packaged shared libraries and return the warnings.
+pub fn run_exports_warnings(
+    name: &PackageName,
+    version: &Version,
+    run_exports: &RunExportsJson,
+    sonames: &[(PathBuf, String)],
+) -> Vec<String> {
+    let self_pins = run_exports
+        .weak
+        .iter()
+        .chain(run_exports.strong.iter())
+        .filter(|spec| {
+            MatchSpec::from_str(spec, ParseStrictness::Lenient)
+                .is_ok_and(|spec| spec.name.as_ref() == Some(name))
+        })
+        .collect::<Vec<_>>();
+    let Some(pin) = self_pins.first() else {
+        return Vec::new();
+    };
+
+    if sonames.is_empty() {
+        return vec![format!(
+            "`run_exports` of {} exports `{}`, but the package does not contain a shared library",
+            name.as_normalized(),
+            pin
+        )];
+    }
+
+    let Some(major) = version_major(version) else {
+        return Vec::new();
+    };
+    let versioned = sonames
+        .iter()
+        .filter_map(|(path, soname)| Some((path, soname, soname_major(soname)?)))
+        .collect::<Vec<_>>();
+    if versioned.is_empty() || versioned.iter().any(|(_, _, soname)| *soname == major) {
+        return Vec::new();
+    }
+
+    let libraries = versioned
+        .iter()
+        .map(|(path, soname, _)| format!("{} ({})", soname, path.display()))
+        .collect::<Vec<_>>()
+        .join(", ");
+    vec![format!(
+        "`run_exports` of {} exports `{}` with major version {}, \
+         which does not match the SONAME of the packaged libraries: {}",
+        name.as_normalized(),
+        pin,
+        major,
+        libraries
+    )]
+}
+
+/// Validate the `run_exports` of the output against all shared libraries in
+/// the package (independent of the `binary_relocation` setting), if enabled in
+/// the recipe. Problems are reported as warnings.
+pub fn check_run_exports(temp_files: &TempFiles, output: &Output) {
+    if !output.recipe.build().dynamic_linking().check_run_exports() {
+        return;
+    }
+    let target_platform = output.build_configuration.target_platform;
+    // only ELF and Mach-O libraries have a SONAME
+    if !(target_platform.is_linux() || target_platform.is_osx()) {
+        return;
+    }
+    let Ok(run_exports) = output.run_exports_json() else {
+        return;
+    };
+    if run_exports.is_empty() {
+        return;
+    }
+
+    let tmp_prefix = temp_files.temp_dir.path();
+    let mut sonames = temp_files
+        .content_type_map()
+        .iter()
+        .filter(|(path, content_type)| {
+            **content_type == Some(content_inspector::ContentType::BINARY)
+                && !path.is_symlink()
+                && path.is_file()
+        })
+        .filter_map(|(path, _)| {
+            let soname = soname(path)?;
+            let path = path.strip_prefix(tmp_prefix).unwrap_or(path);
+            Some((path.to_path_buf(), soname))
+        })
+        .collect::<Vec<_>>();
+    sonames.sort();
+
+    for warning in run_exports_warnings(output.name(), output.version(), run_exports, &sonames) {
+        tracing::warn!("{}", warning);
+        output.record_warning(&warning);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_soname_major() {
+        assert_eq!(soname_major("libfoo.so.2"), Some(2));
+        assert_eq!(soname_major("libfoo.so.2.1.0"), Some(2));
+        assert_eq!(soname_major("libfoo.3.dylib"), Some(3));
+        assert_eq!(soname_major("libfoo.3.1.dylib"), Some(3));
+        assert_eq!(soname_major("libfoo.so"), None);
+        assert_eq!(soname_major("libfoo.dylib"), None);
+    }
+
+    #[test]
+    fn test_soname() {
+        // an executable does not have a SONAME
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("test-data/binary_files/zlink");
+        assert_eq!(soname(&path), None);
+    }
+
+    #[test]
+    fn test_run_exports_warnings() {
+        let name: PackageName = "libfoo".parse().unwrap();
+        let version: Version = "2.1.0".parse().unwrap();
+        let run_exports = RunExportsJson {
+            weak: vec!["libfoo >=2.1.0,<3.0a0".to_string()],
+            ..Default::default()
+        };
+        let lib = |soname: &str| (PathBuf::from("lib").join(soname), soname.to_string());
+
+        // matching major version
+        assert!(
+            run_exports_warnings(&name, &version, &run_exports, &[lib("libfoo.so.2")]).is_empty()
+        );
+        // unversioned SONAMEs are not checked
+        assert!(
+            run_exports_warnings(&name, &version, &run_exports, &[lib("libfoo.so")]).is_empty()
+        );
+
+        let warnings = run_exports_warnings(&name, &version, &run_exports, &[lib("libfoo.so.1")]);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("major version 2"), "{}", warnings[0]);
+        assert!(warnings[0].contains("libfoo.so.1"), "{}", warnings[0]);
+
+        let warnings = run_exports_warnings(&name, &version, &run_exports, &[]);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("does not contain a shared library"));
+
+        // pins to other packages are not checked
+        let run_exports = RunExportsJson {
+            weak: vec!["libbar >=1.0".to_string()],
+            ..Default::default()
+        };
+        assert!(run_exports_warnings(&name, &version, &run_exports, &[]).is_empty());
+    }
+}
@@ -0,0 +1,296 @@
+//! Split the debug information of ELF and Mach-O files into a separate
+//! `<name>-dbg` package (`build.split_debug: true`).
+//!
+//! The debug information is placed where the debuggers look for it without
+//! any configuration, relative to the (installed) binary:
+//!
+//! On Linux the debug sections are extracted with `objcopy --only-keep-debug`
+//! into `<dir>/.debug/<file>.debug`, the binary is stripped and a
+//! `.gnu_debuglink` section that points to the debug file is added. GDB
+//! searches the `.debug` directory next to the binary for the debug link.
+//!
+//! On macOS the DWARF information is collected into a `<dir>/<file>.dSYM`
+//! bundle with `dsymutil`, the binary is stripped with `strip -S` and (ad-hoc)
+//! signed again. LLDB finds the bundle next to the binary.
+use std::{
+    ffi::{OsStr, OsString},
+    path::{Path, PathBuf},
+};
+
+use fs_err as fs;
+use goblin::mach::{symbols::N_OSO, Mach};
+use rattler_conda_types::{Arch, Platform};
+use tempfile::TempDir;
+use thiserror::Error;
+use walkdir::WalkDir;
+
+use crate::{
+    macos::link::codesign,
+    metadata::Output,
+    packaging::TempFiles,
+    post_process::relink::{is_valid_file, RelinkError},
+    system_tools::{SystemTools, Tool, ToolError},
+};
+
+#[derive(Error, Debug)]
+#[allow(missing_docs)]
+pub enum SplitDebugError {
+    #[error(transparent)]
+    Relink(#[from] RelinkError),
+
+    #[error(transparent)]
+    SystemToolError(#[from] ToolError),
+
+    #[error("failed to read or write file: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("failed to walk debug directory: {0}")]
+    WalkDir(#[from] walkdir::Error),
+
+    #[error("failed to strip prefix from path: {0}")]
+    StripPrefixError(#[from] std::path::StripPrefixError),
+
+    #[error("`{0}` failed for {1}: {2}")]
+    ToolFailed(Tool, PathBuf, String),
+}
+
+/// Returns true if the binary contains debug information that can be split
+fn has_debug_info(platform: Platform, path: &Path) -> Result<bool, SplitDebugError> {
+    let data = fs::read(path)?;
+    if platform.is_linux() {
+        let Ok(elf) = goblin::elf::Elf::parse(&data) else {
+            return Ok(false);
+        };
+        Ok(elf.section_headers.iter().any(|header| {
+            elf.shdr_strtab
+                .get_at(header.sh_name)
+                .is_some_and(|name| name.starts_with(".debug_") || name.starts_with(".zdebug_"))
+        }))
+    } else {
+        let Ok(Mach::Binary(macho)) = Mach::parse(&data) else {
+            return Ok(false);
+        };
+        // the DWARF information is either embedded, or referenced from the
+        // object files with `N_OSO` stabs that dsymutil collects
+        let has_dwarf_segment = macho
+            .segments
+            .iter()
+            .any(|segment| segment.name().is_ok_and(|name| name == "__DWARF"));
+        let has_object_stabs = macho
+            .symbols()
+            .any(|symbol| symbol.is_ok_and(|(_, nlist)| nlist.n_type == N_OSO));
+        Ok(has_dwarf_segment || has_object_stabs)
+    }
+}
+
+/// The path (relative to the prefix) of the split debug information of the
+/// binary at `relative`
+fn debug_path(platform: Platform, relative: &Path) -> PathBuf {
+    let mut file_name = relative
+        .file_name()
+        .expect("binary has a file name")
+        .to_os_string();
+    let parent = relative.parent().unwrap_or(Path::new(""));
+    if platform.is_linux() {
+        file_name.push(".debug");
+        parent.join(".debug").join(file_name)
+    } else {
+        file_name.push(".dSYM");
+        parent.join(file_name)
+    }
+}
+
+/// The host triple of the conda-forge compilers for the target platform (the
+/// `$HOST` of the compiler activation), which prefixes the binutils of the
+/// compilers
+fn host_triple(platform: Platform) -> Option<&'static str> {
+    match platform {
+        Platform::Linux64 => Some("x86_64-conda-linux-gnu"),
+        Platform::LinuxAarch64 => Some("aarch64-conda-linux-gnu"),
+        Platform::LinuxPpc64le => Some("powerpc64le-conda-linux-gnu"),
+        Platform::Osx64 => Some("x86_64-apple-darwin13.4.0"),
+        Platform::OsxArm64 => Some("arm64-apple-darwin20.0.0"),
+        _ => None,
+    }
+}
+
+/// Run a tool and turn a non-zero exit code into an error
+fn run_tool(
+    system_tools: &SystemTools,
+    tool: Tool,
+    path: &Path,
+    args: &[&OsStr],
+) -> Result<(), SplitDebugError> {
+    let output = system_tools.call(tool)?.args(args).output()?;
+    if !output.status.success() {
+        return Err(SplitDebugError::ToolFailed(
+            tool,
+            path.to_path_buf(),
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Split the debug information of a single binary into `debug_root`
+fn split_file(
+    platform: Platform,
+    path: &Path,
+    relative: &Path,
+    debug_root: &Path,
+    system_tools: &SystemTools,
+) -> Result<(), SplitDebugError> {
+    let debug_path = debug_root.join(debug_path(platform, relative));
+    let parent = debug_path.parent().expect("debug path has a parent");
+    fs::create_dir_all(parent)?;
+
+    if platform.is_linux() {
+        let debug_file = debug_path.into_os_string();
+        run_tool(
+            system_tools,
+            Tool::Objcopy,
+            path,
+            &[
+                OsStr::new("--only-keep-debug"),
+                path.as_os_str(),
+                debug_file.as_os_str(),
+            ],
+        )?;
+
+        let mut debuglink = OsString::from("--add-gnu-debuglink=");
+        debuglink.push(&debug_file);
+        run_tool(
+            system_tools,
+            Tool::Objcopy,
+            path,
+            &[
+                OsStr::new("--strip-debug"),
+                debuglink.as_os_str(),
+                path.as_os_str(),
+            ],
+        )?;
+    } else {
+        let dsym = debug_path.into_os_string();
+        run_tool(
+            system_tools,
+            Tool::Dsymutil,
+            path,
+            &[path.as_os_str(), OsStr::new("-o"), dsym.as_os_str()],
+        )?;
+        run_tool(
+            system_tools,
+            Tool::Strip,
+            path,
+            &[OsStr::new("-S"), path.as_os_str()],
+        )?;
+        // stripping invalidates the signature
+        codesign(path, system_tools)?;
+    }
+
+    Ok(())
+}
+
+/// Split the debug information of all ELF and Mach-O files in the package.
+/// The binaries are stripped in place, and the debug information is written
+/// to a new temporary directory. Returns `None` if no binary contained debug
+/// information.
+pub fn split_debug_info(
+    temp_files: &TempFiles,
+    output: &Output,
+) -> Result<Option<TempFiles>, SplitDebugError> {
+    let target_platform = output.build_configuration.target_platform;
+    if !output.recipe.build().split_debug()
+        || !(target_platform.is_linux() || target_platform.is_osx())
+        || target_platform.arch() == Some(Arch::Wasm32)
+    {
+        return Ok(None);
+    }
+
+    let tmp_prefix = temp_files.temp_dir.path();
+    let debug_dir = TempDir::with_prefix(format!("{}-dbg", output.name().as_normalized()))?;
+    // allow to use tools from the build prefix such as objcopy or dsymutil,
+    // preferring the ones of the cross compilers for the target platform
+    let system_tools = output
+        .system_tools
+        .with_build_prefix(output.build_prefix())
+        .with_host_triple(host_triple(target_platform));
+
+    let mut binaries = temp_files
+        .content_type_map()
+        .iter()
+        .filter(|(_, content_type)| **content_type == Some(content_inspector::ContentType::BINARY))
+        .map(|(path, _)| path)
+        .collect::<Vec<_>>();
+    binaries.sort();
+
+    let mut split = 0;
+    for path in binaries {
+        let metadata = fs::symlink_metadata(path)?;
+        if metadata.is_symlink() || metadata.is_dir() {
+            continue;
+        }
+        if !is_valid_file(target_platform, path)? || !has_debug_info(target_platform, path)? {
+            continue;
+        }
+
+        let relative = path.strip_prefix(tmp_prefix)?;
+        tracing::info!("Splitting debug information of {}", relative.display());
+        split_file(
+            target_platform,
+            path,
+            relative,
+            debug_dir.path(),
+            &system_tools,
+        )?;
+        split += 1;
+    }
+
+    if split == 0 {
+        tracing::info!("No debug information found to split");
+        return Ok(None);
+    }
+
+    let mut debug_files = TempFiles::new(debug_dir, temp_files.encoded_prefix.clone());
+    let files = WalkDir::new(debug_files.temp_dir.path())
+        .into_iter()
+        .filter_map(|entry| match entry {
+            Ok(entry) if entry.file_type().is_dir() => None,
+            entry => Some(entry.map(|e| e.into_path())),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    debug_files.add_files(files);
+
+    Ok(Some(debug_files))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_debug_path() {
+        assert_eq!(
+            debug_path(Platform::Linux64, Path::new("lib/libz.so.1.3")),
+            Path::new("lib/.debug/libz.so.1.3.debug")
+        );
+        assert_eq!(
+            debug_path(Platform::LinuxAarch64, Path::new("zlink")),
+            Path::new(".debug/zlink.debug")
+        );
+        assert_eq!(
+            debug_path(Platform::OsxArm64, Path::new("lib/libz.1.dylib")),
+            Path::new("lib/libz.1.dylib.dSYM")
+        );
+    }
+
+    #[test]
+    fn test_has_debug_info() {
+        let test_data = Path::new(env!("CARGO_MANIFEST_DIR")).join("test-data/binary_files");
+        // the test binaries are stripped
+        assert!(!has_debug_info(Platform::Linux64, &test_data.join("zlink")).unwrap());
+        assert!(!has_debug_info(Platform::Osx64, &test_data.join("zlink-macos")).unwrap());
+        // other files are ignored
+        assert!(!has_debug_info(Platform::Linux64, &test_data.join("zlink-macos")).unwrap());
+        assert!(!has_debug_info(Platform::Osx64, &test_data.join("binary_file_fallback")).unwrap());
+    }
+}
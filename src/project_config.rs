@@ -0,0 +1,306 @@
+//! Defaults for the build options that a project configures next to its
+//! recipe, either in a `rattler-build.toml` file or in the
+//! `[tool.rattler-build]` table of a `pyproject.toml` file:
+//!
+//! ```toml
+//! [tool.rattler-build]
+//! channels = ["conda-forge", "bioconda"]
+//! variant-config = ["ci/variants.yaml"]
+//! package-format = "conda:max"
+//! output-dir = "dist/conda"
+//!
+//! [tool.rattler-build.system-tools]
+//! patchelf = "/opt/bin/patchelf"
+//! ```
+//!
+//! The options given on the command line take precedence over these defaults.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use miette::{Context, IntoDiagnostic};
+use serde::Deserialize;
+
+use crate::{opt::PackageFormatAndCompression, system_tools::Tool};
+
+/// The name of the rattler-build configuration file of a project
+pub const PROJECT_CONFIG_FILE: &str = "rattler-build.toml";
+
+/// The name of the `pyproject.toml` file whose `[tool.rattler-build]` table
+/// is read
+pub const PYPROJECT_FILE: &str = "pyproject.toml";
+
+/// The build defaults of a project
+#[derive(Debug, Default, Clone, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ProjectConfig {
+    /// The channels to search for dependencies in (`--channel`). Relative
+    /// paths (starting with `./` or `../`) are relative to the configuration
+    /// file
+    pub channels: Option<Vec<String>>,
+    /// The variant configuration files (`--variant-config`), relative to the
+    /// configuration file
+    pub variant_config: Option<Vec<PathBuf>>,
+    /// The package format and compression (`--package-format`)
+    pub package_format: Option<PackageFormatAndCompression>,
+    /// The output directory (`--output-dir`), relative to the configuration
+    /// file
+    pub output_dir: Option<PathBuf>,
+    /// The executables to use for system tools (`--system-tool`), relative to
+    /// the configuration file
+    pub system_tools: Option<BTreeMap<Tool, PathBuf>>,
+}
+
+impl ProjectConfig {
+    /// Read a `rattler-build.toml` file, or the `[tool.rattler-build]` table of
+    /// a `pyproject.toml` file. Returns `None` if a `pyproject.toml` file has
+    /// no such table. Relative paths are resolved against the folder of the
+    /// file.
+    pub fn from_path(path: &Path) -> miette::Result<Option<Self>> {
+        let content = fs_err::read_to_string(path).into_diagnostic()?;
+        let config = if path.file_name() == Some(PYPROJECT_FILE.as_ref()) {
+            let pyproject: toml::Table = toml::from_str(&content)
+                .into_diagnostic()
+                .with_context(|| format!("failed to parse {}", path.display()))?;
+            let Some(table) = pyproject
+                .get("tool")
+                .and_then(|tool| tool.get("rattler-build"))
+            else {
+                return Ok(None);
+            };
+            table.clone().try_into::<Self>()
+        } else {
+            toml::from_str::<Self>(&content)
+        }
+        .into_diagnostic()
+        .with_context(|| format!("invalid rattler-build configuration in {}", path.display()))?;
+
+        if config
+            .system_tools
+            .as_ref()
+            .is_some_and(|tools| tools.contains_key(&Tool::RattlerBuild))
+        {
+            return Err(miette::miette!(
+                "invalid rattler-build configuration in {}: rattler-build itself cannot be replaced in `system-tools`",
+                path.display()
+            ));
+        }
+
+        let base = path.parent().unwrap_or(Path::new("."));
+        Ok(Some(config.relative_to(base)))
+    }
+
+    /// Find the configuration of the project that a recipe belongs to: a
+    /// `rattler-build.toml` file or a `pyproject.toml` file with a
+    /// `[tool.rattler-build]` table in the recipe folder (the first file
+    /// wins).
+    pub fn discover(recipe_folder: &Path) -> miette::Result<Option<(PathBuf, Self)>> {
+        for file in [PROJECT_CONFIG_FILE, PYPROJECT_FILE] {
+            let path = recipe_folder.join(file);
+            if !path.is_file() {
+                continue;
+            }
+            if let Some(config) = Self::from_path(&path)? {
+                return Ok(Some((path, config)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// The folder of the recipe(s) that the configuration is discovered in
+    pub fn recipe_folder(recipes: &[PathBuf], recipe_dir: Option<&Path>) -> PathBuf {
+        if let Some(recipe_dir) = recipe_dir {
+            return recipe_dir.to_path_buf();
+        }
+        match recipes.first() {
+            Some(recipe) if recipe.is_dir() => recipe.clone(),
+            Some(recipe) if recipe.as_os_str() != "-" => recipe
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty())
+                .map(Path::to_path_buf)
+                .unwrap_or_else(|| PathBuf::from(".")),
+            _ => PathBuf::from("."),
+        }
+    }
+
+    fn relative_to(self, base: &Path) -> Self {
+        Self {
+            channels: self.channels.map(|channels| {
+                channels
+                    .into_iter()
+                    .map(|channel| {
+                        if is_relative_path(&channel) {
+                            base.join(channel).to_string_lossy().into_owned()
+                        } else {
+                            channel
+                        }
+                    })
+                    .collect()
+            }),
+            variant_config: self
+                .variant_config
+                .map(|files| files.iter().map(|file| base.join(file)).collect()),
+            output_dir: self.output_dir.map(|dir| base.join(dir)),
+            system_tools: self.system_tools.map(|tools| {
+                tools
+                    .into_iter()
+                    .map(|(tool, path)| (tool, base.join(path)))
+                    .collect()
+            }),
+            ..self
+        }
+    }
+}
+
+/// Returns true if a channel is given as a relative path (channel names and
+/// URLs are not paths)
+fn is_relative_path(channel: &str) -> bool {
+    ["./", "../", ".\\", "..\\"]
+        .iter()
+        .any(|prefix| channel.starts_with(prefix))
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use rattler_conda_types::package::ArchiveType;
+    use rattler_package_streaming::write::CompressionLevel;
+
+    use super::*;
+    use crate::opt::{BuildData, BuildOpts};
+
+    #[test]
+    fn test_discover() {
+        let tmp = tempfile::tempdir().unwrap();
+        assert!(ProjectConfig::discover(tmp.path()).unwrap().is_none());
+
+        // a pyproject.toml without the table is ignored
+        let pyproject = tmp.path().join(PYPROJECT_FILE);
+        fs_err::write(&pyproject, "[project]\nname = \"foo\"\n").unwrap();
+        assert!(ProjectConfig::discover(tmp.path()).unwrap().is_none());
+
+        fs_err::write(
+            &pyproject,
+            r#"
+[project]
+name = "foo"
+
+[tool.rattler-build]
+channels = ["conda-forge", "./local-channel", "/opt/channel"]
+variant-config = ["ci/variants.yaml"]
+package-format = "tar-bz2"
+output-dir = "dist"
+
+[tool.rattler-build.system-tools]
+patchelf = "/opt/bin/patchelf"
+install_name_tool = "bin/install_name_tool"
+"#,
+        )
+        .unwrap();
+        let (path, config) = ProjectConfig::discover(tmp.path()).unwrap().unwrap();
+        assert_eq!(path, pyproject);
+        assert_eq!(
+            config.channels,
+            Some(vec![
+                "conda-forge".to_string(),
+                tmp.path()
+                    .join("./local-channel")
+                    .to_string_lossy()
+                    .into_owned(),
+                "/opt/channel".to_string()
+            ])
+        );
+        assert_eq!(
+            config.variant_config,
+            Some(vec![tmp.path().join("ci/variants.yaml")])
+        );
+        assert_eq!(
+            config.package_format.unwrap().archive_type,
+            ArchiveType::TarBz2
+        );
+        assert_eq!(config.output_dir, Some(tmp.path().join("dist")));
+        assert_eq!(
+            config.system_tools,
+            Some(BTreeMap::from([
+                (Tool::Patchelf, PathBuf::from("/opt/bin/patchelf")),
+                (
+                    Tool::InstallNameTool,
+                    tmp.path().join("bin/install_name_tool")
+                ),
+            ]))
+        );
+
+        // rattler-build.toml takes precedence
+        let config_file = tmp.path().join(PROJECT_CONFIG_FILE);
+        fs_err::write(&config_file, "channels = [\"my-channel\"]\n").unwrap();
+        let (path, config) = ProjectConfig::discover(tmp.path()).unwrap().unwrap();
+        assert_eq!(path, config_file);
+        assert_eq!(config.channels, Some(vec!["my-channel".to_string()]));
+        assert_eq!(config.output_dir, None);
+
+        // unknown keys are rejected
+        fs_err::write(&config_file, "channel = [\"my-channel\"]\n").unwrap();
+        assert!(ProjectConfig::discover(tmp.path()).is_err());
+        fs_err::write(&config_file, "[system-tools]\nrattler-build = \"rb\"\n").unwrap();
+        assert!(ProjectConfig::discover(tmp.path()).is_err());
+    }
+
+    #[test]
+    fn test_merge_with_cli() {
+        let config = ProjectConfig {
+            channels: Some(vec!["bioconda".to_string()]),
+            variant_config: Some(vec![PathBuf::from("ci/variants.yaml")]),
+            package_format: Some("conda:max".parse().unwrap()),
+            output_dir: Some(PathBuf::from("dist")),
+            system_tools: Some(BTreeMap::from([
+                (Tool::Patchelf, PathBuf::from("/opt/bin/patchelf")),
+                (Tool::Git, PathBuf::from("/opt/bin/git")),
+            ])),
+        };
+
+        let opts = BuildOpts::try_parse_from(["build"]).unwrap();
+        let build_data = BuildData::from_opts_and_config(opts, Some(config.clone()));
+        assert_eq!(build_data.channel, vec!["bioconda"]);
+        assert_eq!(
+            build_data.variant_config,
+            vec![PathBuf::from("ci/variants.yaml")]
+        );
+        assert_eq!(
+            build_data.package_format.compression_level,
+            CompressionLevel::Highest
+        );
+        assert_eq!(build_data.common.output_dir, Some(PathBuf::from("dist")));
+
+        let opts = BuildOpts::try_parse_from([
+            "build",
+            "-c",
+            "conda-forge",
+            "-m",
+            "variants.yaml",
+            "--package-format",
+            "tar-bz2",
+            "--output-dir",
+            "out",
+            "--system-tool",
+            "patchelf=/usr/bin/patchelf",
+        ])
+        .unwrap();
+        let build_data = BuildData::from_opts_and_config(opts, Some(config));
+        assert_eq!(build_data.channel, vec!["conda-forge"]);
+        assert_eq!(
+            build_data.variant_config,
+            vec![PathBuf::from("variants.yaml")]
+        );
+        assert_eq!(build_data.package_format.archive_type, ArchiveType::TarBz2);
+        assert_eq!(build_data.common.output_dir, Some(PathBuf::from("out")));
+        assert_eq!(
+            build_data.system_tools,
+            BTreeMap::from([
+                (Tool::Patchelf, PathBuf::from("/usr/bin/patchelf")),
+                (Tool::Git, PathBuf::from("/opt/bin/git")),
+            ])
+        );
+    }
+}
@@ -1,6 +1,9 @@
 //! The rebuild module contains rebuild helper functions.
 
-use std::path::{Path, PathBuf};
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+};
 
 use rattler_conda_types::package::ArchiveType;
 
@@ -78,3 +81,50 @@ pub fn extract_recipe(package: &Path, dest_folder: &Path) -> Result<(), std::io:
     };
     Ok(())
 }
+
+/// Reads a single file of the recipe folder (`info/recipe/<file>`) from a
+/// package archive without extracting anything else.
+pub fn read_recipe_file(package: &Path, file: &str) -> Result<String, std::io::Error> {
+    let archive_type = ArchiveType::try_from(package).ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "package does not point to valid archive",
+        )
+    })?;
+    let reader = std::fs::File::open(package)?;
+    let find_path = Path::new("info/recipe").join(file);
+    let content = match archive_type {
+        ArchiveType::TarBz2 => file_from_archive(
+            rattler_package_streaming::read::stream_tar_bz2(reader),
+            &find_path,
+        )?,
+        ArchiveType::Conda => file_from_archive(
+            rattler_package_streaming::seek::stream_conda_info(reader)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?,
+            &find_path,
+        )?,
+    };
+
+    content.ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("{} not found in {}", find_path.display(), package.display()),
+        )
+    })
+}
+
+/// Reads the content of a single file from a tar archive.
+fn file_from_archive(
+    mut archive: tar::Archive<impl Read>,
+    find_path: &Path,
+) -> Result<Option<String>, std::io::Error> {
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if entry.path()? == find_path {
+            let mut content = String::new();
+            entry.read_to_string(&mut content)?;
+            return Ok(Some(content));
+        }
+    }
+    Ok(None)
+}
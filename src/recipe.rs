@@ -6,6 +6,7 @@ pub mod parser;
 
 pub mod custom_yaml;
 pub mod error;
+pub mod formatter;
 pub mod jinja;
 
 /// A trait to render a certain stage1 node into its final type.
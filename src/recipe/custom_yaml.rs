@@ -17,7 +17,7 @@ use crate::{
     _partialerror,
     normalized_key::NormalizedKey,
     recipe::{
-        error::{jinja_error_to_label, ErrorKind, ParsingError, PartialParsingError},
+        error::{jinja_error_to_label, ErrorKind, Fix, ParsingError, PartialParsingError},
         jinja::Jinja,
     },
 };
@@ -792,6 +792,17 @@ impl TryFrom<marked_yaml::Node> for SequenceNodeInternal {
                         Ok(Self::Conditional(IfSelector::new(
                             cond, then, otherwise, span,
                         )))
+                    } else if map.get("then").is_some()
+                        && matches!(key.as_str().to_lowercase().as_str(), "when" | "if")
+                    {
+                        // a common mistake is to write `when` (or `If`) instead of `if`
+                        Err(_partialerror!(
+                            *key.span(),
+                            ErrorKind::InvalidField(key.as_str().to_string().into()),
+                            help =
+                                "if-selectors are written as `if: <condition>` and `then: <value>`"
+                        )
+                        .with_fix(Fix::new("did you mean `if`?", "if")))
                     } else {
                         Ok(Self::Simple(Node::Mapping(MappingNode::try_from(map)?)))
                     }
@@ -1334,7 +1345,7 @@ impl TryConvertNode<VersionWithSource> for RenderedScalarNode {
                 _partialerror!(
                     *self.span(),
                     ErrorKind::InvalidValue((name.to_string(), err.to_string().into())),
-                    label = "failed to parse `{name}`",
+                    label = format!("failed to parse `{name}`"),
                 )
             })
             .map_err(|e| vec![e])
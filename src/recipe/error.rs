@@ -3,6 +3,7 @@
 use std::{borrow::Cow, convert::Infallible, fmt, str::ParseBoolError};
 
 use miette::{Diagnostic, SourceOffset, SourceSpan};
+use serde::Serialize;
 use thiserror::Error;
 
 /// The Error type for the first stage of the recipe parser.
@@ -28,6 +29,9 @@ pub struct ParsingError {
 
     /// Specific error kind for the error.
     pub kind: ErrorKind,
+
+    /// A fix that replaces the text of the span.
+    pub fix: Option<Fix>,
 }
 
 impl ParsingError {
@@ -37,13 +41,7 @@ impl ParsingError {
         errs: impl IntoIterator<Item = PartialParsingError>,
     ) -> Vec<Self> {
         errs.into_iter()
-            .map(|err| Self {
-                src: src.to_owned(),
-                span: marker_span_to_span(src, err.span),
-                label: err.label,
-                help: err.help,
-                kind: err.kind,
-            })
+            .map(|err| Self::from_partial(src, err))
             .collect()
     }
 
@@ -52,9 +50,13 @@ impl ParsingError {
         Self {
             src: src.to_owned(),
             span: marker_span_to_span(src, err.span),
-            label: err.label,
+            // show the fix at the span if there is no other label
+            label: err
+                .label
+                .or_else(|| err.fix.as_ref().map(|fix| fix.message.clone().into())),
             help: err.help,
             kind: err.kind,
+            fix: err.fix,
         }
     }
 
@@ -62,6 +64,78 @@ impl ParsingError {
     pub fn kind(&self) -> &ErrorKind {
         &self.kind
     }
+
+    /// The fix of the error as an edit of the source, for editor tooling.
+    pub fn text_edit(&self) -> Option<TextEdit> {
+        self.fix.as_ref().map(|fix| TextEdit {
+            offset: self.span.offset(),
+            length: self.span.len(),
+            replacement: fix.replacement.clone(),
+            message: fix.message.clone(),
+        })
+    }
+}
+
+/// A suggested fix for a parser error, e.g. the valid field that an unknown
+/// field was probably meant to be. The fix replaces the text of the span of
+/// the error.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Fix {
+    /// A short description of the fix, e.g. "did you mean `build`?"
+    pub message: String,
+    /// The text that replaces the span of the error
+    pub replacement: String,
+}
+
+impl Fix {
+    /// Create a new fix
+    pub fn new(message: impl Into<String>, replacement: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            replacement: replacement.into(),
+        }
+    }
+}
+
+/// A machine-readable fix for editor tooling: replace `length` bytes at
+/// `offset` in the recipe with `replacement`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TextEdit {
+    /// The byte offset of the text to replace
+    pub offset: usize,
+    /// The length of the text to replace in bytes
+    pub length: usize,
+    /// The replacement text
+    pub replacement: String,
+    /// A short description of the fix
+    pub message: String,
+}
+
+/// The edit distance (Levenshtein distance) between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// The valid option that is closest to an unknown key, if it is close enough
+/// to be a typo (e.g. `requirments` or `run-exports`).
+pub fn did_you_mean<'a>(key: &str, options: &[&'a str]) -> Option<&'a str> {
+    let normalized = key.to_lowercase().replace('-', "_");
+    options
+        .iter()
+        .map(|option| (edit_distance(&normalized, option), *option))
+        .filter(|(distance, option)| *distance <= 2.max(option.len() / 3))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, option)| option)
 }
 
 /// Type that represents the kind of error that can happen in the first stage of the recipe parser.
@@ -188,6 +262,31 @@ pub struct PartialParsingError {
 
     /// Specific error kind for the error.
     pub kind: ErrorKind,
+
+    /// A fix that replaces the text of the span.
+    pub fix: Option<Fix>,
+
+    /// The valid options for an invalid key (e.g. the keys of a section).
+    pub options: &'static [&'static str],
+}
+
+impl PartialParsingError {
+    /// Add a fix to the error.
+    pub fn with_fix(self, fix: Fix) -> Self {
+        Self {
+            fix: Some(fix),
+            ..self
+        }
+    }
+
+    /// Suggest the closest valid option as fix for an unknown key.
+    pub fn with_suggestion(self, key: &str, options: &'static [&'static str]) -> Self {
+        let error = Self { options, ..self };
+        match did_you_mean(key, options) {
+            Some(option) => error.with_fix(Fix::new(format!("did you mean `{option}`?"), option)),
+            None => error,
+        }
+    }
 }
 
 // Implement Display for ErrorKind manually because [`marked_yaml::LoadError`] does not implement
@@ -292,6 +391,7 @@ macro_rules! _error {
             label: None,
             help: None,
             kind: $kind,
+            fix: None,
         }
     }};
     ($src:expr, $span:expr, $kind:expr, label = $label:expr $(,)?) => {{
@@ -301,6 +401,7 @@ macro_rules! _error {
             label: Some($label.into()),
             help: None,
             kind: $kind,
+            fix: None,
         }
     }};
     ($src:expr, $span:expr, $kind:expr, help = $help:expr $(,)?) => {{
@@ -310,6 +411,7 @@ macro_rules! _error {
             label: None,
             help: Some($help.into()),
             kind: $kind,
+            fix: None,
         }
     }};
     ($src:expr, $span:expr, $kind:expr, label = $label:expr, help = $help:expr $(,)?) => {{
@@ -325,6 +427,7 @@ macro_rules! _error {
             label: Some($label.into()),
             help: Some($help.into()),
             kind: $kind,
+            fix: None,
         }
     }};
 }
@@ -339,6 +442,8 @@ macro_rules! _partialerror {
             label: None,
             help: None,
             kind: $kind,
+            fix: None,
+            options: &[],
         }
     }};
     ($span:expr, $kind:expr, label = $label:expr $(,)?) => {{
@@ -347,6 +452,8 @@ macro_rules! _partialerror {
             label: Some($label.into()),
             help: None,
             kind: $kind,
+            fix: None,
+            options: &[],
         }
     }};
     ($span:expr, $kind:expr, help = $help:expr $(,)?) => {{
@@ -355,6 +462,8 @@ macro_rules! _partialerror {
             label: None,
             help: Some($help.into()),
             kind: $kind,
+            fix: None,
+            options: &[],
         }
     }};
     ($span:expr, $kind:expr, label = $label:expr, help = $help:expr $(,)?) => {{
@@ -369,12 +478,28 @@ macro_rules! _partialerror {
             label: Some($label.into()),
             help: Some($help.into()),
             kind: $kind,
+            fix: None,
+            options: &[],
         }
     }};
 }
 
 /// Error handler for [`marked_yaml::LoadError`].
 pub(super) fn load_error_handler(src: &str, err: marked_yaml::LoadError) -> ParsingError {
+    if let Some((span, indentation)) = tab_indentation(src, &err) {
+        let mut error = _error!(
+            src,
+            span,
+            ErrorKind::YamlParsing(err),
+            label = "indented with tabs",
+            help = "YAML does not allow tabs for indentation, use spaces instead"
+        );
+        error.fix = Some(Fix::new(
+            "replace the tabs with spaces",
+            indentation.replace('\t', "  "),
+        ));
+        return error;
+    }
     _error!(
         src,
         marker_to_span(src, marker(&err)),
@@ -389,6 +514,27 @@ pub(super) fn load_error_handler(src: &str, err: marked_yaml::LoadError) -> Pars
     )
 }
 
+/// YAML does not allow tabs for indentation. If the line of a scan error is
+/// indented with tabs, return the span and text of the indentation.
+fn tab_indentation<'a>(
+    src: &'a str,
+    err: &marked_yaml::LoadError,
+) -> Option<(SourceSpan, &'a str)> {
+    let marked_yaml::LoadError::ScanError(mark, _) = err else {
+        return None;
+    };
+    let line_start = SourceOffset::from_location(src, mark.line(), 1).offset();
+    let line = src[line_start..].lines().next().unwrap_or_default();
+    let indentation = &line[..line.len() - line.trim_start().len()];
+    if !indentation.contains('\t') {
+        return None;
+    }
+    Some((
+        SourceSpan::new(line_start.into(), indentation.len()),
+        indentation,
+    ))
+}
+
 /// Convert a [`marked_yaml::Marker`] to a [`SourceSpan`].
 pub(super) fn marker_to_span(src: &str, mark: marked_yaml::Marker) -> SourceSpan {
     let start = SourceOffset::from_location(src, mark.line(), mark.column());
@@ -503,8 +649,23 @@ pub(crate) fn jinja_error_to_label(err: &minijinja::Error) -> String {
 #[cfg(test)]
 mod tests {
 
+    use super::{did_you_mean, TextEdit};
     use crate::{assert_miette_snapshot, recipe::Recipe, variant_config::ParseErrors};
 
+    /// The fixes of the errors of parsing a recipe, applied to the recipe
+    fn fixes(yaml: &str) -> Vec<(TextEdit, String)> {
+        let errors = Recipe::from_yaml(yaml, Default::default()).unwrap_err();
+        errors
+            .iter()
+            .filter_map(|err| err.text_edit())
+            .map(|edit| {
+                let mut fixed = yaml.to_string();
+                fixed.replace_range(edit.offset..edit.offset + edit.length, &edit.replacement);
+                (edit, fixed)
+            })
+            .collect()
+    }
+
     #[test]
     fn miette_output() {
         let fault_yaml = r#"
@@ -522,4 +683,49 @@ mod tests {
             assert_miette_snapshot!(err);
         }
     }
+
+    #[test]
+    fn test_did_you_mean() {
+        let options = ["build", "requirements", "run_exports", "about"];
+        assert_eq!(did_you_mean("requirments", &options), Some("requirements"));
+        assert_eq!(did_you_mean("run-exports", &options), Some("run_exports"));
+        assert_eq!(did_you_mean("Build", &options), Some("build"));
+        assert_eq!(did_you_mean("foobar", &options), None);
+    }
+
+    #[test]
+    fn test_unknown_field_fix() {
+        let yaml = "package:\n  name: test\n  version: 0.1.0\nbuild:\n  numbr: 1\n";
+        let fixes = fixes(yaml);
+        assert_eq!(fixes.len(), 1);
+        assert_eq!(fixes[0].0.message, "did you mean `number`?");
+        assert_eq!(
+            fixes[0].1,
+            "package:\n  name: test\n  version: 0.1.0\nbuild:\n  number: 1\n"
+        );
+
+        let yaml = "package:\n  name: test\n  version: 0.1.0\nrequirments: {}\n";
+        assert_eq!(
+            fixes(yaml)[0].1,
+            "package:\n  name: test\n  version: 0.1.0\nrequirements: {}\n"
+        );
+    }
+
+    #[test]
+    fn test_when_selector_fix() {
+        let yaml = "package:\n  name: test\n  version: 0.1.0\nrequirements:\n  host:\n    - when: unix\n      then: bzip2\n";
+        let fixes = fixes(yaml);
+        assert_eq!(fixes.len(), 1);
+        assert_eq!(fixes[0].0.message, "did you mean `if`?");
+        assert!(fixes[0].1.contains("    - if: unix\n"));
+    }
+
+    #[test]
+    fn test_tab_indentation_fix() {
+        let yaml = "package:\n  name: test\n\tversion: 0.1.0\n";
+        let fixes = fixes(yaml);
+        assert_eq!(fixes.len(), 1);
+        assert_eq!(fixes[0].0.message, "replace the tabs with spaces");
+        assert_eq!(fixes[0].1, "package:\n  name: test\n  version: 0.1.0\n");
+    }
 }
@@ -0,0 +1,821 @@
+//! A formatter for `recipe.yaml` files (`rattler-build fmt`).
+//!
+//! The recipe is parsed with `marked_yaml` and written again with a canonical
+//! layout:
+//!
+//! - two spaces of indentation, with the items of a list indented below their
+//!   key (flow collections are written in block style)
+//! - the keys of the known recipe sections in the order of the schema (unknown
+//!   keys keep their order after the known ones, `context` and `extra` are
+//!   never reordered because later values can refer to earlier ones)
+//! - Jinja expressions without unnecessary quotes
+//! - one blank line between the top-level sections
+//!
+//! YAML parsers drop comments, so they are collected from the source lines and
+//! attached to the node that starts on the next line (or on the same line for
+//! trailing comments). The spans of the nodes are used to put them back in
+//! place, so that comments, blank lines and block scalars (e.g. scripts) are
+//! kept as written and the diff of a formatted recipe stays small.
+
+use std::collections::BTreeMap;
+
+use marked_yaml::{
+    types::{MarkedMappingNode, MarkedScalarNode, MarkedSequenceNode},
+    Node, Span,
+};
+
+use super::{custom_yaml::parse_yaml, parser::TOP_LEVEL_KEYS, ParsingError};
+
+/// The canonical order of the keys of the sections of a recipe. `path` are
+/// the keys that lead to the mapping (sequence items and `if` / `then` /
+/// `else` are skipped). Returns `None` for mappings that keep their order.
+fn key_order(path: &[&str]) -> Option<&'static [&'static str]> {
+    let order: &'static [&'static str] = match path {
+        [] => TOP_LEVEL_KEYS,
+        [.., "package"] | [.., "recipe"] => &["name", "version"],
+        [.., "source"] => &[
+            "url",
+            "git",
+            "hg",
+            "svn",
+            "path",
+            "sha256",
+            "md5",
+            "rev",
+            "tag",
+            "branch",
+            "depth",
+            "lfs",
+            "file_name",
+            "use_gitignore",
+            "patches",
+            "target_directory",
+            "verify",
+        ],
+        [.., "build", "python"] => &[
+            "entry_points",
+            "skip_pyc_compilation",
+            "use_python_app_entrypoint",
+            "site_packages_path",
+            "version_independent",
+        ],
+        [.., "build", "variant"] => &["use_keys", "ignore_keys", "down_prioritize_variant"],
+        [.., "tests", "requirements"] => &["build", "run", "local", "virtual"],
+        [.., "requirements"] => &[
+            "build",
+            "host",
+            "run",
+            "run_constraints",
+            "run_exports",
+            "ignore_run_exports",
+        ],
+        [.., "tests", "python"] => &["imports", "pip_check", "python_version"],
+        [.., "tests", "files"] => &["source", "recipe"],
+        [.., "tests"] => &[
+            "script",
+            "requirements",
+            "files",
+            "python",
+            "perl",
+            "downstream",
+            "skip_unsolvable",
+            "package_contents",
+            "exists",
+        ],
+        [.., "build"] => &[
+            "number",
+            "string",
+            "skip",
+            "noarch",
+            "script",
+            "env",
+            "python",
+            "dynamic_linking",
+            "always_copy_files",
+            "always_include_files",
+            "merge_build_and_host_envs",
+            "variant",
+            "prefix_detection",
+            "post_process",
+            "files",
+            "deduplicate_files",
+            "size_budget",
+            "symlinks",
+            "link_scripts",
+            "split_debug",
+            "channel_priority",
+            "include_recipe",
+            "package_format",
+            "compression",
+        ],
+        [.., "run_exports"] => &[
+            "noarch",
+            "strong",
+            "strong_constraints",
+            "weak",
+            "weak_constraints",
+        ],
+        [.., "ignore_run_exports"] => &["by_name", "from_package"],
+        [.., "script"] => &[
+            "interpreter",
+            "interpreter_args",
+            "env",
+            "secrets",
+            "track_variables",
+            "timeout",
+            "memory_limit",
+            "cpu_limit",
+            "content",
+            "file",
+        ],
+        [.., "about"] => &[
+            "homepage",
+            "repository",
+            "documentation",
+            "license",
+            "license_family",
+            "license_file",
+            "license_file_layout",
+            "license_from_dependencies",
+            "license_url",
+            "summary",
+            "description",
+            "prelink_message",
+        ],
+        _ => return None,
+    };
+    Some(order)
+}
+
+/// Format the source of a recipe. The result is stable: formatting it again
+/// does not change it.
+pub fn format_recipe(src: &str) -> Result<String, ParsingError> {
+    let yaml = parse_yaml(0, src)?;
+    let mut formatter = Formatter::new(src);
+
+    let mut lines = formatter.header.clone();
+    if !lines.is_empty() {
+        lines.push(String::new());
+    }
+    let body = match &yaml {
+        Node::Mapping(map) => formatter.mapping(map, &[], true),
+        node => formatter.node(node, &[]),
+    };
+    lines.extend(body);
+
+    // comments that could not be attached (e.g. at the end of the file)
+    let mut leftover = std::mem::take(&mut formatter.footer);
+    leftover.extend(
+        std::mem::take(&mut formatter.leading)
+            .into_values()
+            .flat_map(|leading| leading.comments),
+    );
+    leftover.extend(
+        std::mem::take(&mut formatter.trailing)
+            .into_values()
+            .map(|comment| comment.trim_start().to_string()),
+    );
+    while leftover.last().is_some_and(|l| l.is_empty()) {
+        leftover.pop();
+    }
+    if !leftover.is_empty() {
+        lines.push(String::new());
+        lines.extend(leftover);
+    }
+
+    let mut result = String::new();
+    for line in lines {
+        result.push_str(line.trim_end());
+        result.push('\n');
+    }
+    Ok(result)
+}
+
+/// The comments (and blank lines) in front of a node
+#[derive(Debug, Default)]
+struct Leading {
+    /// Whether the node (or its comments) is separated by a blank line
+    blank: bool,
+    /// The comment lines, a blank line between comments is an empty string
+    comments: Vec<String>,
+}
+
+/// A block scalar (`|` or `>`) in the source
+#[derive(Debug)]
+struct BlockScalar {
+    /// The header with the indicators (e.g. `|-` or `>2`)
+    header: String,
+    /// The content lines without the indentation of the parent node
+    content: Vec<String>,
+}
+
+struct Formatter<'a> {
+    /// The lines of the source
+    source: Vec<&'a str>,
+    /// The comments at the start of the file that stay at the top
+    header: Vec<String>,
+    /// The comments at the end of the file
+    footer: Vec<String>,
+    /// The comments in front of the node on a line (1-based)
+    leading: BTreeMap<usize, Leading>,
+    /// The trailing comment (including the whitespace before `#`) on a line
+    trailing: BTreeMap<usize, String>,
+    /// The block scalars by the line of their header
+    blocks: BTreeMap<usize, BlockScalar>,
+}
+
+/// Returns where the trailing comment of a line starts (including the
+/// whitespace in front of `#`) and the quote that is still open at the end of
+/// the line. `quote` is the quote that is still open from the previous line.
+fn scan_line(line: &str, mut quote: Option<char>) -> (Option<usize>, Option<char>) {
+    let mut previous = None;
+    let mut chars = line.char_indices().peekable();
+    while let Some((idx, c)) = chars.next() {
+        match quote {
+            Some('"') if c == '\\' => {
+                chars.next();
+            }
+            // an escaped quote in a single-quoted scalar
+            Some('\'') if c == '\'' && chars.peek().is_some_and(|(_, next)| *next == '\'') => {
+                chars.next();
+            }
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None => match c {
+                '"' | '\'' if matches!(previous, None | Some(':' | '-' | '[' | '{' | ',')) => {
+                    quote = Some(c)
+                }
+                '#' if previous.is_none() || line[..idx].ends_with([' ', '\t']) => {
+                    let start = line[..idx].trim_end().len();
+                    return (Some(start), None);
+                }
+                _ => {}
+            },
+        }
+        if !c.is_whitespace() {
+            previous = Some(c);
+        }
+    }
+    (None, quote)
+}
+
+/// The indentation of a line
+fn indentation(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+/// If the line starts a block scalar, returns its header and the indentation
+/// of the parent node (content lines are indented more than that).
+fn block_scalar_header(line: &str) -> Option<(String, usize)> {
+    let token = line.split_whitespace().last()?;
+    let mut indicators = token.chars();
+    if !matches!(indicators.next(), Some('|' | '>'))
+        || !indicators.all(|c| c == '-' || c == '+' || c.is_ascii_digit())
+    {
+        return None;
+    }
+    let before = line[..line.len() - token.len()].trim_end();
+
+    // skip the indicators of sequence items (`- - key: |`)
+    let mut key_column = indentation(line);
+    let mut rest = &line[key_column..];
+    while let Some(stripped) = rest.strip_prefix("- ") {
+        let stripped_trimmed = stripped.trim_start_matches(' ');
+        key_column += rest.len() - stripped_trimmed.len();
+        rest = stripped_trimmed;
+    }
+
+    if before.ends_with(':') {
+        Some((token.to_string(), key_column))
+    } else if before.trim_start().ends_with('-') || before.trim_start().is_empty() {
+        // a sequence item, the parent is the sequence
+        let dash_column = before.rfind('-').unwrap_or(0);
+        Some((token.to_string(), dash_column))
+    } else {
+        None
+    }
+}
+
+impl<'a> Formatter<'a> {
+    fn new(src: &'a str) -> Self {
+        let source = src.lines().collect::<Vec<_>>();
+        let mut formatter = Self {
+            source: source.clone(),
+            header: Vec::new(),
+            footer: Vec::new(),
+            leading: BTreeMap::new(),
+            trailing: BTreeMap::new(),
+            blocks: BTreeMap::new(),
+        };
+
+        let mut pending = Leading::default();
+        let mut seen_node = false;
+        let mut quote = None;
+        let mut block: Option<(usize, String, usize, Vec<String>)> = None;
+
+        for (idx, line) in source.iter().enumerate() {
+            let number = idx + 1;
+
+            if let Some((header_line, header, parent_indent, mut content)) = block.take() {
+                if line.trim().is_empty() || indentation(line) > parent_indent {
+                    content.push(line.to_string());
+                    block = Some((header_line, header, parent_indent, content));
+                    continue;
+                }
+                formatter.finish_block(header_line, header, parent_indent, content, &mut pending);
+            }
+
+            // the line continues a quoted scalar
+            if quote.is_some() {
+                quote = scan_line(line, quote).1;
+                continue;
+            }
+
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                if pending.comments.is_empty() {
+                    pending.blank = seen_node;
+                } else if pending.comments.last().is_some_and(|c| !c.is_empty()) {
+                    pending.comments.push(String::new());
+                }
+                continue;
+            }
+            if trimmed.starts_with('#') {
+                pending.comments.push(trimmed.to_string());
+                continue;
+            }
+
+            if !seen_node {
+                formatter.split_header(&mut pending);
+                seen_node = true;
+            }
+            if pending.blank || !pending.comments.is_empty() {
+                formatter
+                    .leading
+                    .insert(number, std::mem::take(&mut pending));
+            }
+
+            let (comment, open_quote) = scan_line(line, None);
+            quote = open_quote;
+            let code = match comment {
+                Some(start) => {
+                    formatter.trailing.insert(number, line[start..].to_string());
+                    &line[..start]
+                }
+                None => line,
+            };
+            if let Some((header, parent_indent)) = block_scalar_header(code) {
+                block = Some((number, header, parent_indent, Vec::new()));
+            }
+        }
+
+        if let Some((header_line, header, parent_indent, content)) = block.take() {
+            formatter.finish_block(header_line, header, parent_indent, content, &mut pending);
+        }
+        while pending.comments.last().is_some_and(|c| c.is_empty()) {
+            pending.comments.pop();
+        }
+        formatter.footer = pending.comments;
+        formatter
+    }
+
+    /// Comments at the start of the file that are separated from the first
+    /// node by a blank line (or that configure the language server) stay at the
+    /// top of the file.
+    fn split_header(&mut self, pending: &mut Leading) {
+        let split = pending
+            .comments
+            .iter()
+            .rposition(|c| c.is_empty())
+            .or_else(|| {
+                pending
+                    .comments
+                    .iter()
+                    .rposition(|c| c.contains("yaml-language-server"))
+                    .map(|idx| idx + 1)
+            });
+        if let Some(split) = split {
+            let rest = pending.comments.split_off(split);
+            self.header = std::mem::take(&mut pending.comments);
+            while self.header.last().is_some_and(|c| c.is_empty()) {
+                self.header.pop();
+            }
+            pending.comments = rest.into_iter().skip_while(|c| c.is_empty()).collect();
+        }
+    }
+
+    fn finish_block(
+        &mut self,
+        header_line: usize,
+        header: String,
+        parent_indent: usize,
+        mut content: Vec<String>,
+        pending: &mut Leading,
+    ) {
+        // blank lines at the end separate the next node (unless they are kept)
+        if !header.contains('+') {
+            while content.last().is_some_and(|l| l.trim().is_empty()) {
+                content.pop();
+                pending.blank = true;
+            }
+        }
+
+        // with an indentation indicator the content is indented relative to
+        // the parent node, otherwise the common indentation is removed
+        let explicit = header
+            .chars()
+            .find(|c| c.is_ascii_digit())
+            .and_then(|c| c.to_digit(10))
+            .map(|n| n as usize);
+        let strip = match explicit {
+            Some(n) => parent_indent + n,
+            None => content
+                .iter()
+                .filter(|l| !l.trim().is_empty())
+                .map(|l| indentation(l))
+                .min()
+                .unwrap_or(0),
+        };
+        let indent = " ".repeat(explicit.unwrap_or(2));
+        let content = content
+            .into_iter()
+            .map(|line| {
+                if line.trim().is_empty() {
+                    String::new()
+                } else {
+                    format!("{indent}{}", &line[strip.min(indentation(&line))..])
+                }
+            })
+            .collect();
+
+        self.blocks
+            .insert(header_line, BlockScalar { header, content });
+    }
+
+    /// The line of a span (1-based)
+    fn line(span: &Span) -> usize {
+        span.start().map(|m| m.line()).unwrap_or_default()
+    }
+
+    /// The line that a node starts on
+    fn node_line(node: &Node) -> usize {
+        match node {
+            Node::Mapping(map) => map
+                .iter()
+                .next()
+                .map(|(key, _)| Self::line(key.span()))
+                .unwrap_or_else(|| Self::line(map.span())),
+            Node::Sequence(seq) => seq
+                .iter()
+                .next()
+                .map(Self::node_line)
+                .unwrap_or_else(|| Self::line(seq.span())),
+            Node::Scalar(scalar) => Self::line(scalar.span()),
+        }
+    }
+
+    /// Push the comments in front of the node on `line`
+    fn push_leading(&mut self, line: usize, lines: &mut Vec<String>, separate: bool) {
+        let leading = self.leading.remove(&line).unwrap_or_default();
+        if (leading.blank || separate) && !lines.is_empty() {
+            lines.push(String::new());
+        }
+        lines.extend(leading.comments);
+    }
+
+    /// The trailing comment of a line, if it was not used yet
+    fn take_trailing(&mut self, line: usize) -> String {
+        self.trailing.remove(&line).unwrap_or_default()
+    }
+
+    /// The first character of the node in the source
+    fn source_char(&self, span: &Span) -> Option<char> {
+        let marker = span.start()?;
+        self.source
+            .get(marker.line().checked_sub(1)?)?
+            .chars()
+            .nth(marker.column().checked_sub(1)?)
+    }
+
+    /// The source text of a single-line quoted scalar, including the quotes
+    fn quoted_source(&self, span: &Span, quote: char) -> Option<String> {
+        let marker = span.start()?;
+        let line = self.source.get(marker.line().checked_sub(1)?)?;
+        let start = line.char_indices().nth(marker.column().checked_sub(1)?)?.0;
+        let rest = &line[start + 1..];
+        let mut chars = rest.char_indices();
+        while let Some((idx, c)) = chars.next() {
+            if quote == '"' && c == '\\' {
+                chars.next();
+            } else if c == quote {
+                if quote == '\'' && rest[idx + 1..].starts_with('\'') {
+                    chars.next();
+                    continue;
+                }
+                return Some(line[start..start + 1 + idx + 1].to_string());
+            }
+        }
+        None
+    }
+
+    /// The text of a scalar that is not a block scalar
+    fn scalar(&self, scalar: &MarkedScalarNode) -> String {
+        let value = scalar.as_str();
+        match self.source_char(scalar.span()) {
+            Some(quote @ ('"' | '\'')) => {
+                if value.contains("${{") && plain_is_safe(value) {
+                    value.to_string()
+                } else if let Some(source) = self.quoted_source(scalar.span(), quote) {
+                    source
+                } else {
+                    double_quoted(value)
+                }
+            }
+            // a plain scalar is written as it was parsed
+            _ => value.to_string(),
+        }
+    }
+
+    /// The lines of a key (or sequence item) and its value. `prefix` is
+    /// `key:` or `-`, the lines of nested values are indented by the caller.
+    fn entry(&mut self, prefix: &str, line: usize, value: &Node, path: &[&str]) -> Vec<String> {
+        match value {
+            Node::Scalar(scalar) => {
+                let value_line = Self::line(scalar.span());
+                let block = self
+                    .blocks
+                    .remove(&value_line)
+                    .or_else(|| self.blocks.remove(&line));
+                if let Some(block) = block {
+                    let trailing = self.take_trailing(value_line);
+                    let mut lines = vec![format!("{prefix} {}{trailing}", block.header)];
+                    lines.extend(block.content);
+                    return lines;
+                }
+                let mut trailing = self.take_trailing(line);
+                if trailing.is_empty() {
+                    trailing = self.take_trailing(value_line);
+                }
+                let text = self.scalar(scalar);
+                if text.is_empty() {
+                    vec![format!("{prefix}{trailing}")]
+                } else {
+                    vec![format!("{prefix} {text}{trailing}")]
+                }
+            }
+            Node::Mapping(map) if map.is_empty() => {
+                vec![format!("{prefix} {{}}{}", self.take_trailing(line))]
+            }
+            Node::Sequence(seq) if seq.is_empty() => {
+                vec![format!("{prefix} []{}", self.take_trailing(line))]
+            }
+            Node::Mapping(_) | Node::Sequence(_) if prefix == "-" => {
+                // the first line of the nested node follows the dash, comments
+                // of a reordered first key go in front of the item
+                let mut nested = self.node(value, path).into_iter().peekable();
+                let mut lines = Vec::new();
+                while let Some(comment) = nested.next_if(|l| l.is_empty() || l.starts_with('#')) {
+                    if !comment.is_empty() {
+                        lines.push(comment);
+                    }
+                }
+                if let Some(first) = nested.next() {
+                    lines.push(format!("- {first}"));
+                }
+                lines.extend(indent(nested.collect()));
+                lines
+            }
+            _ => {
+                let mut lines = vec![format!("{prefix}{}", self.take_trailing(line))];
+                lines.extend(indent(self.node(value, path)));
+                lines
+            }
+        }
+    }
+
+    fn node(&mut self, node: &Node, path: &[&str]) -> Vec<String> {
+        match node {
+            Node::Mapping(map) => self.mapping(map, path, false),
+            Node::Sequence(seq) => self.sequence(seq, path),
+            Node::Scalar(scalar) => vec![self.scalar(scalar)],
+        }
+    }
+
+    fn mapping(&mut self, map: &MarkedMappingNode, path: &[&str], top_level: bool) -> Vec<String> {
+        let keys = map.keys().map(|k| k.as_str()).collect::<Vec<_>>();
+        let is_conditional =
+            keys.contains(&"if") && keys.iter().all(|k| matches!(*k, "if" | "then" | "else"));
+
+        let mut entries = map.iter().collect::<Vec<_>>();
+        let order = if is_conditional {
+            Some(&["if", "then", "else"][..])
+        } else {
+            key_order(path)
+        };
+        if let Some(order) = order {
+            // a stable sort keeps unknown keys in their order
+            entries.sort_by_key(|(key, _)| {
+                order
+                    .iter()
+                    .position(|k| *k == key.as_str())
+                    .unwrap_or(order.len())
+            });
+        }
+
+        let mut lines = Vec::new();
+        for (key, value) in entries {
+            let line = Self::line(key.span());
+            self.push_leading(line, &mut lines, top_level);
+
+            let child_path = match key.as_str() {
+                // conditionals are transparent
+                "if" | "then" | "else" if is_conditional => path.to_vec(),
+                // the outputs have the same structure as a recipe
+                "outputs" if path.is_empty() => Vec::new(),
+                other => path.iter().copied().chain([other]).collect(),
+            };
+
+            let prefix = format!("{}:", self.scalar(key));
+            lines.extend(self.entry(&prefix, line, value, &child_path));
+        }
+        lines
+    }
+
+    fn sequence(&mut self, seq: &MarkedSequenceNode, path: &[&str]) -> Vec<String> {
+        let mut lines = Vec::new();
+        for item in seq.iter() {
+            let line = Self::node_line(item);
+            self.push_leading(line, &mut lines, false);
+            lines.extend(self.entry("-", line, item, path));
+        }
+        lines
+    }
+}
+
+/// Indent the lines of a nested node
+fn indent(lines: Vec<String>) -> impl Iterator<Item = String> {
+    lines.into_iter().map(|line| {
+        if line.is_empty() {
+            line
+        } else {
+            format!("  {line}")
+        }
+    })
+}
+
+/// Returns true if the value can be written as a plain scalar in block style
+/// and is read back as the same string.
+fn plain_is_safe(value: &str) -> bool {
+    let Some(first) = value.chars().next() else {
+        return false;
+    };
+    let is_special = matches!(
+        value.to_ascii_lowercase().as_str(),
+        "true" | "false" | "yes" | "no" | "on" | "off" | "null" | "~"
+    ) || value.parse::<f64>().is_ok();
+
+    !is_special
+        && value.trim() == value
+        && !"-?:,[]{}#&*!|>'\"%@`".contains(first)
+        && !value.contains(": ")
+        && !value.contains(" #")
+        && !value.ends_with(':')
+        && !value.contains(['\n', '\t', '\r'])
+}
+
+/// Write a value as a double-quoted scalar
+fn double_quoted(value: &str) -> String {
+    let mut result = String::with_capacity(value.len() + 2);
+    result.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\t' => result.push_str("\\t"),
+            '\r' => result.push_str("\\r"),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{recipe::Recipe, selectors::SelectorConfig};
+
+    #[test]
+    fn test_format_recipe() {
+        let src = r#"# yaml-language-server: $schema=https://raw.githubusercontent.com/prefix-dev/recipe-format/main/schema.json
+
+context:
+    version: "0.1.0"
+    name: foo
+
+about:
+    summary: It's a test # a trailing comment
+    homepage: https://example.com
+
+package:
+    version: "${{ version }}"
+    name: ${{ name }}
+
+requirements:
+    run:
+    - python
+    # only on windows
+    - if: win
+      then: [pywin32, "menuinst"]
+    host: [python, pip]
+
+build:
+    script: |
+        # this is not a YAML comment
+        pip install .
+
+    number: 0
+"#;
+
+        let formatted = format_recipe(src).unwrap();
+        insta::assert_snapshot!(formatted, @r###"
+        # yaml-language-server: $schema=https://raw.githubusercontent.com/prefix-dev/recipe-format/main/schema.json
+
+        context:
+          version: "0.1.0"
+          name: foo
+
+        package:
+          name: ${{ name }}
+          version: ${{ version }}
+
+        build:
+          number: 0
+          script: |
+            # this is not a YAML comment
+            pip install .
+
+        requirements:
+          host:
+            - python
+            - pip
+          run:
+            - python
+            # only on windows
+            - if: win
+              then:
+                - pywin32
+                - "menuinst"
+
+        about:
+          homepage: https://example.com
+          summary: It's a test # a trailing comment
+        "###);
+
+        // formatting is stable
+        assert_eq!(format_recipe(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn test_format_test_recipes() {
+        let recipes = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("test-data/recipes");
+
+        let mut checked = 0;
+        for entry in walkdir::WalkDir::new(recipes) {
+            let path = entry.unwrap().into_path();
+            if path.file_name() != Some("recipe.yaml".as_ref()) {
+                continue;
+            }
+            let src = fs_err::read_to_string(&path).unwrap();
+            let Ok(formatted) = format_recipe(&src) else {
+                // some test recipes are invalid on purpose
+                continue;
+            };
+
+            // only the layout changes, the recipe stays the same
+            let original: serde_yaml::Value = serde_yaml::from_str(&src).unwrap();
+            let reformatted: serde_yaml::Value = serde_yaml::from_str(&formatted)
+                .unwrap_or_else(|err| panic!("{}: {err}\n{formatted}", path.display()));
+            assert_eq!(original, reformatted, "{}", path.display());
+
+            // and renders to the same recipe
+            let render = |src: &str| {
+                Recipe::from_yaml(src, SelectorConfig::default())
+                    .ok()
+                    .map(|recipe| serde_yaml::to_value(recipe).unwrap())
+            };
+            assert_eq!(render(&src), render(&formatted), "{}", path.display());
+
+            // formatting is stable
+            assert_eq!(
+                format_recipe(&formatted).unwrap(),
+                formatted,
+                "{}",
+                path.display()
+            );
+            checked += 1;
+        }
+        assert!(checked > 0);
+    }
+}
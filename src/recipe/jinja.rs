@@ -3,18 +3,22 @@
 use fs_err as fs;
 use indexmap::IndexMap;
 use minijinja::syntax::SyntaxConfig;
+use std::future::IntoFuture;
 use std::process::Command;
 use std::sync::Arc;
 use std::{collections::BTreeMap, str::FromStr};
 
 use minijinja::value::{from_args, Kwargs, Object};
-use minijinja::{Environment, Value};
-use rattler_conda_types::{Arch, PackageName, ParseStrictness, Platform, Version, VersionSpec};
+use minijinja::{Environment, UndefinedBehavior, Value};
+use rattler_conda_types::{
+    Arch, Channel, MatchSpec, PackageName, ParseStrictness, Platform, Version, VersionSpec,
+};
 
 use crate::normalized_key::NormalizedKey;
 use crate::render::pin::PinArgs;
 pub use crate::render::pin::{Pin, PinExpression};
 pub use crate::selectors::SelectorConfig;
+use crate::selectors::{EnvAccess, RepodataAccess};
 
 use super::parser::{Dependency, PinCompatible, PinSubpackage};
 
@@ -295,6 +299,118 @@ fn compiler_stdlib_eval(
     }
 }
 
+/// The `CMAKE_SYSTEM_NAME` of a platform
+fn cmake_system_name(platform: Platform) -> Option<&'static str> {
+    if platform.is_linux() {
+        Some("Linux")
+    } else if platform.is_osx() {
+        Some("Darwin")
+    } else if platform.is_windows() {
+        Some("Windows")
+    } else if matches!(platform, Platform::EmscriptenWasm32) {
+        Some("Emscripten")
+    } else {
+        None
+    }
+}
+
+/// Quote an argument for the build script if it contains spaces
+fn quote_arg(arg: String) -> String {
+    if arg.contains(' ') {
+        format!("\"{arg}\"")
+    } else {
+        arg
+    }
+}
+
+/// The arguments to configure a CMake project for the host platform. The
+/// install prefix and the prefix path point to the host prefix and for cross
+/// builds the target system is set and CMake only looks for libraries and
+/// headers in the host prefix.
+fn cmake_args(
+    host_platform: Platform,
+    build_platform: Platform,
+    kwargs: Kwargs,
+) -> Result<String, minijinja::Error> {
+    let generator = kwargs
+        .get::<Option<String>>("generator")?
+        .unwrap_or_else(|| "Ninja".to_string());
+    let build_type = kwargs
+        .get::<Option<String>>("build_type")?
+        .unwrap_or_else(|| "Release".to_string());
+    kwargs.assert_all_used()?;
+
+    let mut args = vec![
+        quote_arg(format!("-G{generator}")),
+        format!("-DCMAKE_BUILD_TYPE={build_type}"),
+    ];
+
+    if host_platform.is_windows() {
+        args.push("-DCMAKE_INSTALL_PREFIX=%LIBRARY_PREFIX%".to_string());
+        args.push("-DCMAKE_PREFIX_PATH=%LIBRARY_PREFIX%".to_string());
+        return Ok(args.join(" "));
+    }
+
+    args.push("-DCMAKE_INSTALL_PREFIX=$PREFIX".to_string());
+    args.push("-DCMAKE_PREFIX_PATH=$PREFIX".to_string());
+    args.push("-DCMAKE_INSTALL_LIBDIR=lib".to_string());
+
+    if host_platform != build_platform {
+        if let Some(system_name) = cmake_system_name(host_platform) {
+            args.push(format!("-DCMAKE_SYSTEM_NAME={system_name}"));
+        }
+        if let Some(arch) = host_platform.arch() {
+            args.push(format!("-DCMAKE_SYSTEM_PROCESSOR={arch}"));
+            if host_platform.is_osx() {
+                args.push(format!("-DCMAKE_OSX_ARCHITECTURES={arch}"));
+            }
+        }
+        args.push("-DCMAKE_FIND_ROOT_PATH=$PREFIX".to_string());
+        args.push("-DCMAKE_FIND_ROOT_PATH_MODE_PROGRAM=NEVER".to_string());
+        args.push("-DCMAKE_FIND_ROOT_PATH_MODE_LIBRARY=ONLY".to_string());
+        args.push("-DCMAKE_FIND_ROOT_PATH_MODE_INCLUDE=ONLY".to_string());
+    }
+
+    Ok(args.join(" "))
+}
+
+/// The arguments to configure a Meson project for the host platform. For
+/// cross builds the cross file that is written by the compiler activation
+/// scripts is used.
+fn meson_args(
+    host_platform: Platform,
+    build_platform: Platform,
+    kwargs: Kwargs,
+) -> Result<String, minijinja::Error> {
+    let backend = kwargs
+        .get::<Option<String>>("backend")?
+        .unwrap_or_else(|| "ninja".to_string());
+    let build_type = kwargs
+        .get::<Option<String>>("build_type")?
+        .unwrap_or_else(|| "release".to_string());
+    kwargs.assert_all_used()?;
+
+    let mut args = vec![
+        format!("--backend={backend}"),
+        format!("--buildtype={build_type}"),
+        "--wrap-mode=nofallback".to_string(),
+    ];
+
+    if host_platform.is_windows() {
+        args.push("--prefix=%LIBRARY_PREFIX%".to_string());
+        return Ok(args.join(" "));
+    }
+
+    args.push("--prefix=$PREFIX".to_string());
+    args.push("--libdir=lib".to_string());
+
+    if host_platform != build_platform {
+        args.push("--cross-file=$BUILD_PREFIX/meson_cross_file.txt".to_string());
+    }
+
+    Ok(args.join(" "))
+}
+
 fn default_tests(env: &mut Environment) {
     env.add_test("undefined", minijinja::tests::is_undefined);
     env.add_test("defined", minijinja::tests::is_defined);
@@ -364,6 +480,127 @@ fn default_filters(env: &mut Environment) {
     env.add_filter("trim", minijinja::filters::trim);
     env.add_filter("unique", minijinja::filters::unique);
     env.add_filter("split", minijinja::filters::split);
+    env.add_filter("float", minijinja::filters::float);
+    env.add_filter("string", minijinja::filters::string);
+
+    env.add_filter("min_pin", |version: String, expression: String| {
+        let (version, expression) = parse_pin_filter_args(&version, &expression)?;
+        expression
+            .lower_bound(&version)
+            .map(|v| v.to_string())
+            .map_err(|e| {
+                minijinja::Error::new(minijinja::ErrorKind::InvalidOperation, e.to_string())
+            })
+    });
+    env.add_filter("max_pin", |version: String, expression: String| {
+        let (version, expression) = parse_pin_filter_args(&version, &expression)?;
+        expression
+            .upper_bound(&version)
+            .map(|v| v.to_string())
+            .map_err(|e| {
+                minijinja::Error::new(minijinja::ErrorKind::InvalidOperation, e.to_string())
+            })
+    });
+}
+
+fn parse_pin_filter_args(
+    version: &str,
+    expression: &str,
+) -> Result<(Version, PinExpression), minijinja::Error> {
+    let version = Version::from_str(version).map_err(|e| {
+        minijinja::Error::new(
+            minijinja::ErrorKind::InvalidOperation,
+            format!("Invalid version `{version}`: {e}"),
+        )
+    })?;
+    let expression = PinExpression::from_str(expression).map_err(|e| {
+        minijinja::Error::new(
+            minijinja::ErrorKind::InvalidOperation,
+            format!("Invalid pin expression `{expression}`: {e}"),
+        )
+    })?;
+    Ok((version, expression))
+}
+
+/// Python string methods that conda-build recipes use (e.g.
+/// `version.split('.')` or `name.replace('-', '_')`)
+fn string_methods(
+    _state: &minijinja::State,
+    value: &Value,
+    method: &str,
+    args: &[Value],
+) -> Result<Value, minijinja::Error> {
+    let Some(s) = value.as_str() else {
+        return Err(minijinja::Error::from(minijinja::ErrorKind::UnknownMethod));
+    };
+
+    match method {
+        "split" => {
+            let (sep, maxsplit): (Option<&str>, Option<i64>) = from_args(args)?;
+            let parts: Vec<&str> = match (sep, maxsplit.filter(|m| *m >= 0)) {
+                (None, None) => s.split_whitespace().collect(),
+                (None, Some(maxsplit)) => {
+                    let mut parts = Vec::new();
+                    let mut rest = s.trim_start();
+                    while !rest.is_empty() && parts.len() < maxsplit as usize {
+                        match rest.split_once(char::is_whitespace) {
+                            Some((part, tail)) => {
+                                parts.push(part);
+                                rest = tail.trim_start();
+                            }
+                            None => {
+                                parts.push(rest);
+                                rest = "";
+                            }
+                        }
+                    }
+                    if !rest.is_empty() {
+                        parts.push(rest);
+                    }
+                    parts
+                }
+                (Some(sep), None) => s.split(sep).collect(),
+                (Some(sep), Some(maxsplit)) => s.splitn(maxsplit as usize + 1, sep).collect(),
+            };
+            Ok(Value::from(parts))
+        }
+        "replace" => {
+            let (old, new, count): (&str, &str, Option<usize>) = from_args(args)?;
+            Ok(Value::from(match count {
+                Some(count) => s.replacen(old, new, count),
+                None => s.replace(old, new),
+            }))
+        }
+        "lower" => {
+            from_args::<()>(args)?;
+            Ok(Value::from(s.to_lowercase()))
+        }
+        "upper" => {
+            from_args::<()>(args)?;
+            Ok(Value::from(s.to_uppercase()))
+        }
+        "strip" | "lstrip" | "rstrip" => {
+            let (chars,): (Option<&str>,) = from_args(args)?;
+            let trim = |c: char| match chars {
+                Some(chars) => chars.contains(c),
+                None => c.is_whitespace(),
+            };
+            Ok(Value::from(match method {
+                "strip" => s.trim_matches(trim),
+                "lstrip" => s.trim_start_matches(trim),
+                _ => s.trim_end_matches(trim),
+            }))
+        }
+        "startswith" => {
+            let (prefix,): (&str,) = from_args(args)?;
+            Ok(Value::from(s.starts_with(prefix)))
+        }
+        "endswith" => {
+            let (suffix,): (&str,) = from_args(args)?;
+            Ok(Value::from(s.ends_with(suffix)))
+        }
+        _ => Err(minijinja::Error::from(minijinja::ErrorKind::UnknownMethod)),
+    }
 }
 
 fn parse_platform(platform: &str) -> Result<Platform, minijinja::Error> {
@@ -375,6 +612,98 @@ fn parse_platform(platform: &str) -> Result<Platform, minijinja::Error> {
     })
 }
 
+/// Query a channel for the highest version of the packages that match `spec`
+/// (e.g. `numpy` or `numpy <2`) for the given platform (or `noarch`), with the
+/// repodata gateway of the build.
+fn latest_version(
+    spec: &str,
+    kwargs: Kwargs,
+    platform: Platform,
+    experimental: bool,
+    repodata_access: Option<&RepodataAccess>,
+) -> Result<Value, minijinja::Error> {
+    if !experimental {
+        return Err(minijinja::Error::new(
+            minijinja::ErrorKind::InvalidOperation,
+            "Experimental feature: provide the `--experimental` flag to enable this feature",
+        ));
+    }
+    let channel: Option<String> = kwargs.get("channel")?;
+    kwargs.assert_all_used()?;
+    let channel = channel.unwrap_or_else(|| "conda-forge".to_string());
+
+    let invalid =
+        |message: String| minijinja::Error::new(minijinja::ErrorKind::InvalidOperation, message);
+
+    let match_spec = MatchSpec::from_str(spec, ParseStrictness::Lenient)
+        .map_err(|e| invalid(format!("Invalid package spec `{spec}`: {e}")))?;
+    if match_spec.name.is_none() {
+        return Err(invalid(format!("The package spec `{spec}` has no name")));
+    }
+    let Some(RepodataAccess {
+        gateway,
+        channel_config,
+        latest_versions,
+    }) = repodata_access
+    else {
+        return Err(invalid(format!(
+            "Cannot query `{channel}` for `{spec}` outside of a build"
+        )));
+    };
+
+    let key = (spec.to_string(), channel.clone(), platform);
+    if let Some(version) = latest_versions.lock().unwrap().get(&key) {
+        return Ok(Value::from(version.clone()));
+    }
+    let query_channel = Channel::from_str(&channel, channel_config)
+        .map_err(|e| invalid(format!("Invalid channel `{channel}`: {e}")))?;
+
+    let query = || {
+        gateway
+            .query(
+                [query_channel],
+                [platform, Platform::NoArch],
+                [match_spec.clone()],
+            )
+            .recursive(false)
+            .into_future()
+    };
+    // rendering is synchronous, so the query runs on its own runtime in a
+    // separate thread (this works from inside and outside of the runtime of
+    // the build)
+    let repodata = std::thread::scope(|scope| {
+        scope
+            .spawn(|| {
+                tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                    .map_err(|e| e.to_string())?
+                    .block_on(query())
+                    .map_err(|e| e.to_string())
+            })
+            .join()
+            .unwrap_or_else(|_| Err("the query panicked".to_string()))
+    })
+    .map_err(|e| invalid(format!("Failed to query `{channel}` for `{spec}`: {e}")))?;
+
+    let version = repodata
+        .iter()
+        .flat_map(|repodata| repodata.iter())
+        .filter(|record| match_spec.matches(&record.package_record))
+        .map(|record| Version::clone(&record.package_record.version))
+        .max()
+        .ok_or_else(|| {
+            invalid(format!(
+                "No package matching `{spec}` found in `{channel}` for {platform}"
+            ))
+        })?
+        .to_string();
+
+    tracing::info!("Latest version of `{spec}` in `{channel}` is {version}");
+    latest_versions.lock().unwrap().insert(key, version.clone());
+    Ok(Value::from(version))
+}
+
 lazy_static::lazy_static! {
     /// The syntax config for MiniJinja / rattler-build
     pub static ref SYNTAX_CONFIG: SyntaxConfig = SyntaxConfig::builder()
@@ -393,12 +722,19 @@ fn set_jinja(config: &SelectorConfig) -> minijinja::Environment<'static> {
         variant,
         experimental,
         allow_undefined,
+        strict_jinja,
+        repodata_access,
         ..
     } = config.clone();
 
     let mut env = Environment::empty();
     default_tests(&mut env);
     default_filters(&mut env);
+    env.set_unknown_method_callback(string_methods);
+    if strict_jinja {
+        // undefined variables can still be tested (`if: foo`, `foo is defined`)
+        env.set_undefined_behavior(UndefinedBehavior::SemiStrict);
+    }
 
     // Ok to unwrap here because we know that the syntax is valid
     env.set_syntax(SYNTAX_CONFIG.clone());
@@ -497,6 +833,14 @@ fn set_jinja(config: &SelectorConfig) -> minijinja::Environment<'static> {
         }
     });
 
+    env.add_function("cmake_args", move |kwargs: Kwargs| {
+        cmake_args(host_platform, build_platform, kwargs)
+    });
+
+    env.add_function("meson_args", move |kwargs: Kwargs| {
+        meson_args(host_platform, build_platform, kwargs)
+    });
+
     env.add_function("pin_subpackage", |name: String, kwargs: Kwargs| {
         jinja_pin_function(name, kwargs, InternalRepr::PinSubpackage)
     });
@@ -550,6 +894,16 @@ fn set_jinja(config: &SelectorConfig) -> minijinja::Environment<'static> {
         Ok(value)
     });
 
+    env.add_function("latest_version", move |spec: String, kwargs: Kwargs| {
+        latest_version(
+            &spec,
+            kwargs,
+            host_platform,
+            experimental,
+            repodata_access.as_ref(),
+        )
+    });
+
     env
 }
 
@@ -657,7 +1011,9 @@ impl Object for Git {
 }
 
 #[derive(Debug)]
-pub(crate) struct Env;
+pub(crate) struct Env {
+    pub(crate) access: EnvAccess,
+}
 impl std::fmt::Display for Env {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_str("Env")
@@ -665,9 +1021,21 @@ impl std::fmt::Display for Env {
 }
 
 impl Env {
+    fn check_allowed(&self, env_var: &str) -> Result<(), minijinja::Error> {
+        if self.access.is_allowed(env_var) {
+            Ok(())
+        } else {
+            Err(minijinja::Error::new(
+                minijinja::ErrorKind::InvalidOperation,
+                format!("Environment variable {env_var} is not in the allow-list (add it with `--env-allow {env_var}`)"),
+            ))
+        }
+    }
+
     fn get(&self, env_var: &str, kwargs: Kwargs) -> Result<Value, minijinja::Error> {
         let default_value = kwargs.get::<String>("default").ok();
         kwargs.assert_all_used()?;
+        self.check_allowed(env_var)?;
 
         match std::env::var(env_var) {
             Ok(r) => Ok(Value::from(r)),
@@ -682,6 +1050,7 @@ impl Env {
     }
 
     fn exists(&self, env_var: &str) -> Result<Value, minijinja::Error> {
+        self.check_allowed(env_var)?;
         Ok(Value::from(std::env::var(env_var).is_ok()))
     }
 }
@@ -1134,6 +1503,44 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_pin_filters() {
+        let mut jinja = Jinja::new(SelectorConfig::default());
+        jinja
+            .context_mut()
+            .insert("version".to_string(), Value::from("1.2.3"));
+
+        let eval = |expr: &str| jinja.eval(expr).unwrap().to_string();
+        assert_eq!(eval("version | min_pin('x.x')"), "1.2");
+        assert_eq!(eval("version | min_pin('x.x.x.x')"), "1.2.3");
+        assert_eq!(eval("version | max_pin('x.x')"), "1.3.0a0");
+        assert_eq!(eval("version | max_pin('x')"), "2.0a0");
+        assert!(jinja.eval("version | max_pin('1.x')").is_err());
+        assert!(jinja.eval("'foo' | min_pin('x.x')").is_err());
+    }
+
+    #[test]
+    fn test_string_methods() {
+        let mut jinja = Jinja::new(SelectorConfig::default());
+        jinja
+            .context_mut()
+            .insert("version".to_string(), Value::from("1.2.3"));
+        jinja
+            .context_mut()
+            .insert("name".to_string(), Value::from(" My-Package "));
+
+        let eval = |expr: &str| jinja.eval(expr).unwrap().to_string();
+        assert_eq!(eval("version.split('.')[0]"), "1");
+        assert_eq!(eval("version.split('.', 1)"), "[\"1\", \"2.3\"]");
+        assert_eq!(eval("name.split()"), "[\"My-Package\"]");
+        assert_eq!(eval("name.strip().replace('-', '_').lower()"), "my_package");
+        assert_eq!(eval("name.upper()"), " MY-PACKAGE ");
+        assert_eq!(eval("version.rstrip('.3')"), "1.2");
+        assert_eq!(eval("version.startswith('1.')"), "true");
+        assert_eq!(eval("version.endswith('.4')"), "false");
+        assert!(jinja.eval("version.foo()").is_err());
+    }
+
     #[test]
     fn eval_env() {
         let options = SelectorConfig {
@@ -1177,6 +1584,41 @@ mod tests {
         });
     }
 
+    #[test]
+    fn eval_env_allow_list() {
+        let options = SelectorConfig {
+            env_access: EnvAccess {
+                allow: ["ALLOWED_JINJA_ENV_VAR".to_string()].into(),
+                hash: ["HASHED_JINJA_ENV_VAR".to_string()].into(),
+                ..EnvAccess::default()
+            },
+            ..Default::default()
+        };
+        let jinja = Jinja::new(options);
+
+        with_env(("ALLOWED_JINJA_ENV_VAR", "allowed"), || {
+            assert_eq!(
+                jinja
+                    .eval("env.get('ALLOWED_JINJA_ENV_VAR')")
+                    .unwrap()
+                    .as_str(),
+                Some("allowed")
+            );
+            assert_eq!(
+                jinja
+                    .eval("env.get('HASHED_JINJA_ENV_VAR', default='none')")
+                    .unwrap()
+                    .as_str(),
+                Some("none")
+            );
+            let err = jinja
+                .eval("env.get('OTHER_JINJA_ENV_VAR', default='none')")
+                .unwrap_err();
+            assert!(err.to_string().contains("not in the allow-list"));
+            assert!(jinja.eval("env.exists('OTHER_JINJA_ENV_VAR')").is_err());
+        });
+    }
+
     #[test]
     fn test_unavailable() {
         let jinja = Jinja::new(Default::default());
@@ -1184,6 +1626,54 @@ mod tests {
         assert!(jinja.eval("${{ \"foo\" | escape }}").is_err());
     }
 
+    #[test]
+    fn test_latest_version() {
+        let jinja = Jinja::new(Default::default());
+        let err = jinja.eval("latest_version('numpy')").unwrap_err();
+        assert!(err.to_string().contains("Experimental feature"));
+
+        let jinja = Jinja::new(SelectorConfig {
+            experimental: true,
+            host_platform: Platform::Linux64,
+            ..Default::default()
+        });
+        assert!(jinja
+            .eval("latest_version('numpy', chanel='conda-forge')")
+            .is_err());
+        // the channels are queried with the gateway of the build
+        let err = jinja.eval("latest_version('numpy')").unwrap_err();
+        assert!(err.to_string().contains("outside of a build"), "{err}");
+
+        // results are only queried once per render
+        let repodata_access = RepodataAccess::new(
+            rattler_repodata_gateway::Gateway::new(),
+            rattler_conda_types::ChannelConfig::default_with_root_dir(
+                std::env::current_dir().unwrap(),
+            ),
+        );
+        repodata_access.latest_versions.lock().unwrap().insert(
+            (
+                "xtensor".to_string(),
+                "my-channel".to_string(),
+                Platform::Linux64,
+            ),
+            "0.25.0".to_string(),
+        );
+        let jinja = Jinja::new(SelectorConfig {
+            experimental: true,
+            host_platform: Platform::Linux64,
+            repodata_access: Some(repodata_access),
+            ..Default::default()
+        });
+        assert_eq!(
+            jinja
+                .eval("latest_version('xtensor', channel='my-channel')")
+                .unwrap()
+                .to_string(),
+            "0.25.0"
+        );
+    }
+
     #[test]
     fn test_default_compiler() {
         let platform = Platform::Linux64;
@@ -1201,4 +1691,58 @@ mod tests {
         assert_eq!("vs2017", default_compiler(platform, "c").unwrap());
         assert_eq!("cuda", default_compiler(platform, "cuda").unwrap());
     }
+
+    #[test]
+    fn test_cmake_meson_args() {
+        let jinja = Jinja::new(SelectorConfig {
+            target_platform: Platform::Linux64,
+            host_platform: Platform::Linux64,
+            build_platform: Platform::Linux64,
+            ..Default::default()
+        });
+        assert_eq!(
+            jinja.eval("cmake_args()").unwrap().to_string(),
+            "-GNinja -DCMAKE_BUILD_TYPE=Release -DCMAKE_INSTALL_PREFIX=$PREFIX -DCMAKE_PREFIX_PATH=$PREFIX -DCMAKE_INSTALL_LIBDIR=lib"
+        );
+        assert_eq!(
+            jinja
+                .eval("cmake_args(generator='Unix Makefiles', build_type='Debug')")
+                .unwrap()
+                .to_string(),
+            "\"-GUnix Makefiles\" -DCMAKE_BUILD_TYPE=Debug -DCMAKE_INSTALL_PREFIX=$PREFIX -DCMAKE_PREFIX_PATH=$PREFIX -DCMAKE_INSTALL_LIBDIR=lib"
+        );
+        assert!(jinja.eval("cmake_args(foo='bar')").is_err());
+        assert_eq!(
+            jinja.eval("meson_args()").unwrap().to_string(),
+            "--backend=ninja --buildtype=release --wrap-mode=nofallback --prefix=$PREFIX --libdir=lib"
+        );
+
+        let jinja = Jinja::new(SelectorConfig {
+            target_platform: Platform::OsxArm64,
+            host_platform: Platform::OsxArm64,
+            build_platform: Platform::Osx64,
+            ..Default::default()
+        });
+        let cmake = jinja.eval("cmake_args()").unwrap().to_string();
+        assert!(cmake.contains("-DCMAKE_SYSTEM_NAME=Darwin"));
+        assert!(cmake.contains("-DCMAKE_OSX_ARCHITECTURES=arm64"));
+        assert!(cmake.contains("-DCMAKE_FIND_ROOT_PATH=$PREFIX"));
+        let meson = jinja.eval("meson_args()").unwrap().to_string();
+        assert!(meson.ends_with("--cross-file=$BUILD_PREFIX/meson_cross_file.txt"));
+
+        let jinja = Jinja::new(SelectorConfig {
+            target_platform: Platform::Win64,
+            host_platform: Platform::Win64,
+            build_platform: Platform::Win64,
+            ..Default::default()
+        });
+        assert_eq!(
+            jinja.eval("cmake_args()").unwrap().to_string(),
+            "-GNinja -DCMAKE_BUILD_TYPE=Release -DCMAKE_INSTALL_PREFIX=%LIBRARY_PREFIX% -DCMAKE_PREFIX_PATH=%LIBRARY_PREFIX%"
+        );
+        assert_eq!(
+            jinja.eval("meson_args()").unwrap().to_string(),
+            "--backend=ninja --buildtype=release --wrap-mode=nofallback --prefix=%LIBRARY_PREFIX%"
+        );
+    }
 }
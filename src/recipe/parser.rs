@@ -24,37 +24,74 @@ mod build;
 mod cache;
 mod glob_vec;
 mod helper;
+mod include;
 mod output;
 mod package;
 mod regex;
 mod requirements;
+mod schema;
 mod script;
 mod skip;
 mod source;
 mod test;
 
 pub use self::{
-    about::About,
-    build::{Build, BuildString, DynamicLinking, PrefixDetection, Python},
+    about::{About, LicenseFileLayout},
+    build::{
+        Build, BuildString, ChannelPriority, Compression, DynamicLinking, FileSize, IncludeRecipe,
+        LinkScripts, PackageFormat, PrefixDetection, Python, SecretScan, SecretScanBehavior,
+        SizeBudget, SizeBudgetBehavior, SymlinkBehavior, SymlinkSettings,
+    },
     cache::Cache,
-    glob_vec::GlobVec,
-    output::find_outputs_from_src,
+    glob_vec::{AllOrGlobVec, GlobVec},
+    include::included_files,
+    output::{find_outputs_from_recipe_file, find_outputs_from_src},
     package::{OutputPackage, Package},
     regex::SerializableRegex,
     requirements::{
         Dependency, IgnoreRunExports, Language, PinCompatible, PinSubpackage, Requirements,
         RunExports,
     },
-    script::{Script, ScriptContent},
-    source::{GitRev, GitSource, GitUrl, PathSource, Source, UrlSource},
+    schema::recipe_schema,
+    script::{CpuLimit, Script, ScriptContent, ScriptLimits, Timeout},
+    skip::SkipReason,
+    source::{
+        GitRev, GitSource, GitUrl, HgSource, PathSource, Source, SourceVerify, SvnSource, UrlSource,
+    },
     test::{
-        CommandsTest, CommandsTestFiles, CommandsTestRequirements, DownstreamTest,
+        CommandsTest, CommandsTestFiles, CommandsTestRequirements, DownstreamTest, ExistsTest,
         PackageContentsTest, PerlTest, PythonTest, PythonVersion, TestType,
     },
 };
 
 use crate::recipe::custom_yaml::Node;
 
+/// The top-level keys of a recipe, in the canonical order of a recipe file.
+/// `recipe` and `outputs` are only allowed in multi-output recipes.
+pub const TOP_LEVEL_KEYS: &[&str] = &[
+    "schema_version",
+    "context",
+    "recipe",
+    "package",
+    "source",
+    "build",
+    "cache",
+    "requirements",
+    "tests",
+    "outputs",
+    "about",
+    "extra",
+];
+
+lazy_static::lazy_static! {
+    /// The top-level keys of a single output (without `recipe` and `outputs`)
+    static ref OUTPUT_KEYS: Vec<&'static str> = TOP_LEVEL_KEYS
+        .iter()
+        .copied()
+        .filter(|key| !matches!(*key, "recipe" | "outputs"))
+        .collect();
+}
+
 /// A recipe that has been parsed and validated.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Recipe {
@@ -247,7 +284,8 @@ impl Recipe {
                         return Err(vec![_partialerror!(
                             *key.span(),
                             ErrorKind::InvalidField(invalid_key.to_string().into()),
-                        )])
+                        )
+                        .with_suggestion(invalid_key, OUTPUT_KEYS.as_slice())])
                     }
                 }
                 Ok(())
@@ -355,6 +393,43 @@ mod tests {
         insta::assert_debug_snapshot!("recipe_windows", win_recipe.unwrap());
     }
 
+    #[test]
+    fn strict_jinja() {
+        let recipe = r#"
+        context:
+            version: "1.0"
+        package:
+            name: test
+            version: ${{ version }}
+        build:
+            string: ${{ hash }}_0
+        requirements:
+            run:
+                - if: cuda_compiler_version
+                  then: cuda-version
+        about:
+            summary: Version ${{ verison }}
+        "#;
+
+        // an undefined variable renders as an empty string by default
+        let lenient = Recipe::from_yaml(recipe, SelectorConfig::default()).unwrap();
+        assert_eq!(lenient.about().summary.as_deref(), Some("Version "));
+
+        let strict = SelectorConfig {
+            strict_jinja: true,
+            ..SelectorConfig::default()
+        };
+        let errs = Recipe::from_yaml(recipe, strict.clone()).unwrap_err();
+        assert_eq!(errs.len(), 1);
+        assert!(matches!(errs[0].kind, ErrorKind::JinjaRendering(_)));
+        assert!(errs[0].to_string().contains("undefined value"));
+
+        // undefined variables in conditions and the hash are fine
+        let recipe = recipe.replace("verison", "version");
+        let parsed = Recipe::from_yaml(&recipe, strict).unwrap();
+        assert!(parsed.requirements().run().is_empty());
+    }
+
     #[test]
     fn bad_skip_single_output() {
         let raw_recipe = include_str!("../../test-data/recipes/test-parsing/recipe_bad_skip.yaml");
@@ -384,6 +459,52 @@ mod tests {
         }
     }
 
+    #[test]
+    fn skip_with_reason() {
+        let raw_recipe = r#"
+        package:
+          name: test
+          version: 0.1.0
+
+        build:
+          skip:
+            - osx
+            - condition: win
+              reason: "not supported upstream"
+            - if: unix
+              then: "linux and aarch64"
+        "#;
+
+        let selector_config = |target_platform| SelectorConfig {
+            target_platform,
+            host_platform: target_platform,
+            ..SelectorConfig::default()
+        };
+
+        let recipe = Recipe::from_yaml(raw_recipe, selector_config(Platform::Win64)).unwrap();
+        let reason = recipe.build().skip_reason().unwrap();
+        assert_eq!(reason.condition, "win");
+        assert_eq!(reason.reason.as_deref(), Some("not supported upstream"));
+        assert_eq!(reason.to_string(), "not supported upstream (`win`)");
+
+        let recipe = Recipe::from_yaml(raw_recipe, selector_config(Platform::Osx64)).unwrap();
+        let reason = recipe.build().skip_reason().unwrap();
+        assert_eq!(reason.to_string(), "skip condition `osx` is true");
+
+        // a conditional entry is still an expression that is evaluated
+        let recipe =
+            Recipe::from_yaml(raw_recipe, selector_config(Platform::LinuxAarch64)).unwrap();
+        let reason = recipe.build().skip_reason().unwrap();
+        assert_eq!(
+            reason.to_string(),
+            "skip condition `linux and aarch64` is true"
+        );
+
+        let recipe = Recipe::from_yaml(raw_recipe, selector_config(Platform::Linux64)).unwrap();
+        assert!(!recipe.build().skip());
+        assert!(recipe.build().skip_reason().is_none());
+    }
+
     #[test]
     fn context_not_mapping() {
         let raw_recipe = r#"
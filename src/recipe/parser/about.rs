@@ -3,6 +3,7 @@ use std::{
     str::FromStr,
 };
 
+use rattler_conda_types::PackageName;
 use serde::{Deserialize, Serialize};
 use serde_with::{DeserializeFromStr, SerializeDisplay};
 use spdx::Expression;
@@ -42,6 +43,13 @@ pub struct About {
     /// The license file(s) of the package.
     #[serde(default, skip_serializing_if = "GlobVec::is_empty")]
     pub license_file: GlobVec,
+    /// How the license files are placed in `info/licenses`.
+    #[serde(default, skip_serializing_if = "LicenseFileLayout::is_default")]
+    pub license_file_layout: LicenseFileLayout,
+    /// Dependencies whose license files are included in the package (e.g.
+    /// because they are statically linked or vendored).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub license_from_dependencies: Vec<PackageName>,
     /// The license URL of the package.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub license_url: Option<Url>,
@@ -84,6 +92,8 @@ impl TryConvertNode<About> for RenderedMappingNode {
             license,
             license_family,
             license_file,
+            license_file_layout,
+            license_from_dependencies,
             license_url,
             summary,
             description,
@@ -94,6 +104,42 @@ impl TryConvertNode<About> for RenderedMappingNode {
     }
 }
 
+/// How the license files are placed in `info/licenses`.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LicenseFileLayout {
+    /// Keep the path of the license files relative to the source or recipe
+    /// directory (e.g. `info/licenses/vendor/foo/LICENSE`)
+    #[default]
+    Preserve,
+    /// Copy all license files directly into `info/licenses` (e.g.
+    /// `info/licenses/LICENSE`). Fails if two license files have the same name.
+    Flatten,
+}
+
+impl LicenseFileLayout {
+    /// Returns true if the directory structure is preserved (the default).
+    pub fn is_default(&self) -> bool {
+        *self == Self::Preserve
+    }
+}
+
+impl TryConvertNode<LicenseFileLayout> for RenderedNode {
+    fn try_convert(&self, name: &str) -> Result<LicenseFileLayout, Vec<PartialParsingError>> {
+        self.as_scalar()
+            .ok_or_else(|| vec![_partialerror!(*self.span(), ErrorKind::ExpectedScalar)])
+            .and_then(|s| match s.as_str() {
+                "preserve" => Ok(LicenseFileLayout::Preserve),
+                "flatten" => Ok(LicenseFileLayout::Flatten),
+                _ => Err(vec![_partialerror!(
+                    *self.span(),
+                    ErrorKind::ExpectedScalar,
+                    help = format!("valid options for {name} are `preserve` or `flatten`")
+                )]),
+            })
+    }
+}
+
 /// A parsed SPDX license
 #[derive(Debug, Clone, SerializeDisplay, DeserializeFromStr)]
 pub struct License {
@@ -185,4 +231,55 @@ mod test {
 
         assert_miette_snapshot!(err);
     }
+
+    #[test]
+    fn license_file_layout() {
+        let recipe = r#"
+        package:
+            name: test
+            version: 0.0.1
+
+        about:
+            license: MIT
+            license_file:
+                - LICENSE
+                - vendor/
+                - "!vendor/**/internal/*"
+            license_file_layout: flatten
+        "#;
+
+        let recipe = Recipe::from_yaml(recipe, SelectorConfig::default()).unwrap();
+        assert_eq!(
+            recipe.about().license_file_layout,
+            super::LicenseFileLayout::Flatten
+        );
+        assert_eq!(recipe.about().license_file.include_globs().len(), 2);
+        assert_eq!(recipe.about().license_file.exclude_globs().len(), 1);
+    }
+
+    #[test]
+    fn license_from_dependencies() {
+        let recipe = r#"
+        package:
+            name: test
+            version: 0.0.1
+
+        about:
+            license: MIT
+            license_from_dependencies:
+                - zlib
+                - libpng
+        "#;
+
+        let recipe = Recipe::from_yaml(recipe, SelectorConfig::default()).unwrap();
+        assert_eq!(
+            recipe
+                .about()
+                .license_from_dependencies
+                .iter()
+                .map(|name| name.as_normalized())
+                .collect::<Vec<_>>(),
+            vec!["zlib", "libpng"]
+        );
+    }
 }
@@ -1,14 +1,19 @@
 use std::borrow::Cow;
+use std::path::PathBuf;
 use std::str::FromStr;
 
-use rattler_conda_types::{package::EntryPoint, NoArchType};
+use indexmap::IndexMap;
+use rattler_conda_types::{
+    package::{ArchiveType, EntryPoint},
+    NoArchType,
+};
 use serde::{Deserialize, Serialize};
 
 use super::glob_vec::{AllOrGlobVec, GlobVec};
 use super::{Dependency, FlattenErrors, SerializableRegex};
 use crate::recipe::custom_yaml::RenderedSequenceNode;
 use crate::recipe::parser::script::Script;
-use crate::recipe::parser::skip::Skip;
+use crate::recipe::parser::skip::{Skip, SkipReason};
 
 use crate::hash::HashInfo;
 use crate::recipe::Jinja;
@@ -77,13 +82,17 @@ pub struct Build {
     /// It's possible to override this by setting it manually, but not recommended.
     #[serde(default, skip_serializing_if = "BuildString::is_derived")]
     pub string: BuildString,
-    /// List of conditions under which to skip the build of the package.
-    #[serde(default, skip)]
+    /// List of conditions under which to skip the build of the package. Only
+    /// serialized (as the reason) if the package is skipped.
+    #[serde(default, skip_serializing_if = "Skip::is_not_skipped")]
     pub skip: Skip,
     /// The build script can be either a list of commands or a path to a script. By
     /// default, the build script is set to `build.sh` or `build.bat` on Unix and Windows respectively.
     #[serde(default, skip_serializing_if = "Script::is_default")]
     pub script: Script,
+    /// Environment variables to set (or unset with `null`) for the build script
+    #[serde(default, skip_serializing_if = "BuildEnv::is_empty")]
+    pub env: BuildEnv,
     /// A noarch package runs on any platform. It can be either a python package or a generic package.
     #[serde(default, skip_serializing_if = "NoArchType::is_none")]
     pub noarch: NoArchType,
@@ -114,6 +123,35 @@ pub struct Build {
     /// Include files in the package
     #[serde(default, skip_serializing_if = "GlobVec::is_empty")]
     pub files: GlobVec,
+    /// The maximum size of the package content
+    #[serde(default, skip_serializing_if = "SizeBudget::is_default")]
+    pub size_budget: SizeBudget,
+    /// How to handle problematic symlinks in the package
+    #[serde(default, skip_serializing_if = "SymlinkSettings::is_default")]
+    pub symlinks: SymlinkSettings,
+    /// Scripts that are executed when the package is installed or removed
+    #[serde(default, skip_serializing_if = "LinkScripts::is_default")]
+    pub link_scripts: LinkScripts,
+    /// Split the debug information of binaries into a `<name>-dbg` package
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub split_debug: bool,
+    /// The channel priority to use when resolving the environments of this
+    /// output, overriding the channel priority of the tool
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel_priority: Option<ChannelPriority>,
+    /// Which parts of the recipe are stored in `info/recipe` of the package
+    #[serde(default, skip_serializing_if = "IncludeRecipe::is_default")]
+    pub include_recipe: IncludeRecipe,
+    /// The package format of this output, overriding `--package-format`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub package_format: Option<PackageFormat>,
+    /// The compression level of this output, overriding the compression
+    /// level of `--package-format`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<Compression>,
+    /// How the files of the package are scanned for secrets before archiving
+    #[serde(default, skip_serializing_if = "SecretScan::is_default")]
+    pub secret_scan: SecretScan,
 }
 
 /// The build string can be either a user specified string, a resolved string or derived from the variant.
@@ -232,11 +270,21 @@ impl Build {
         self.skip.eval()
     }
 
+    /// Get the reason why the package is skipped, if it is skipped.
+    pub fn skip_reason(&self) -> Option<&SkipReason> {
+        self.skip.reason()
+    }
+
     /// Get the build script.
     pub fn script(&self) -> &Script {
         &self.script
     }
 
+    /// Get the environment variables to set or unset for the build script.
+    pub const fn env(&self) -> &BuildEnv {
+        &self.env
+    }
+
     /// Get the noarch type.
     pub const fn noarch(&self) -> &NoArchType {
         &self.noarch
@@ -277,6 +325,51 @@ impl Build {
         &self.post_process
     }
 
+    /// Get the size budget of the package.
+    pub const fn size_budget(&self) -> &SizeBudget {
+        &self.size_budget
+    }
+
+    /// Get the symlink settings of the package.
+    pub const fn symlinks(&self) -> &SymlinkSettings {
+        &self.symlinks
+    }
+
+    /// Get the link scripts of the package.
+    pub const fn link_scripts(&self) -> &LinkScripts {
+        &self.link_scripts
+    }
+
+    /// Get the split debug flag.
+    pub const fn split_debug(&self) -> bool {
+        self.split_debug
+    }
+
+    /// Get the channel priority override of this output.
+    pub const fn channel_priority(&self) -> Option<ChannelPriority> {
+        self.channel_priority
+    }
+
+    /// Get which parts of the recipe are stored in the package.
+    pub const fn include_recipe(&self) -> IncludeRecipe {
+        self.include_recipe
+    }
+
+    /// Get the package format override of this output.
+    pub const fn package_format(&self) -> Option<PackageFormat> {
+        self.package_format
+    }
+
+    /// Get the compression level override of this output.
+    pub const fn compression(&self) -> Option<Compression> {
+        self.compression
+    }
+
+    /// Get the secret scan settings.
+    pub const fn secret_scan(&self) -> &SecretScan {
+        &self.secret_scan
+    }
+
     /// The output is python version independent if the package is
     /// `noarch: python` or the python version independent flag is set
     /// which can also be true for `abi3` packages.
@@ -304,6 +397,7 @@ impl TryConvertNode<Build> for RenderedMappingNode {
             string,
             skip,
             script,
+            env,
             noarch,
             python,
             dynamic_linking,
@@ -313,13 +407,119 @@ impl TryConvertNode<Build> for RenderedMappingNode {
             variant,
             prefix_detection,
             post_process,
-            files
+            files,
+            size_budget,
+            symlinks,
+            link_scripts,
+            split_debug,
+            channel_priority,
+            include_recipe,
+            package_format,
+            compression,
+            secret_scan
         }
 
         Ok(build)
     }
 }
 
+/// Environment variables that are set for the build script. The variables in
+/// the `unset` list are removed from the environment of the build script.
+///
+/// The variables can be given as a mapping or as a list of mappings, which
+/// allows to use `if / then / else` selectors. Later entries take precedence.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildEnv {
+    /// The variables that are set, with their values
+    #[serde(flatten)]
+    set: IndexMap<String, String>,
+    /// The variables that are unset
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    unset: Vec<String>,
+}
+
+impl BuildEnv {
+    /// Returns true if no environment variables are set or unset.
+    pub fn is_empty(&self) -> bool {
+        self.set.is_empty() && self.unset.is_empty()
+    }
+
+    /// The environment variables that are set, with their values.
+    pub fn set_vars(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.set.iter()
+    }
+
+    /// The environment variables that are unset.
+    pub fn unset_vars(&self) -> impl Iterator<Item = &String> {
+        self.unset.iter()
+    }
+
+    fn extend_from_mapping(
+        &mut self,
+        mapping: &RenderedMappingNode,
+    ) -> Result<(), Vec<PartialParsingError>> {
+        for (key, value) in mapping.iter() {
+            if key.as_str() == "unset" {
+                let names: Vec<String> = value.try_convert("unset")?;
+                for name in names {
+                    self.set.shift_remove(&name);
+                    if !self.unset.contains(&name) {
+                        self.unset.push(name);
+                    }
+                }
+                continue;
+            }
+
+            let RenderedNode::Scalar(scalar) = value else {
+                return Err(vec![_partialerror!(
+                    *value.span(),
+                    ErrorKind::ExpectedScalar,
+                    help = format!(
+                        "the value of `{}` must be a string (use `unset: [{}]` to unset it)",
+                        key.as_str(),
+                        key.as_str()
+                    )
+                )]);
+            };
+            self.unset.retain(|name| name != key.as_str());
+            // move the key to the end, so that the last definition wins
+            self.set.shift_remove(key.as_str());
+            self.set
+                .insert(key.as_str().to_string(), scalar.as_str().to_string());
+        }
+        Ok(())
+    }
+}
+
+impl TryConvertNode<BuildEnv> for RenderedNode {
+    fn try_convert(&self, _name: &str) -> Result<BuildEnv, Vec<PartialParsingError>> {
+        let mut env = BuildEnv::default();
+        match self {
+            RenderedNode::Mapping(mapping) => env.extend_from_mapping(mapping)?,
+            RenderedNode::Sequence(sequence) => {
+                for item in sequence.iter() {
+                    let mapping = item.as_mapping().ok_or_else(|| {
+                        vec![_partialerror!(
+                            *item.span(),
+                            ErrorKind::ExpectedMapping,
+                            help = "every entry of `env` must be a mapping of variable names to values"
+                        )]
+                    })?;
+                    env.extend_from_mapping(mapping)?;
+                }
+            }
+            RenderedNode::Null(_) => {}
+            RenderedNode::Scalar(_) => {
+                return Err(vec![_partialerror!(
+                    *self.span(),
+                    ErrorKind::ExpectedMapping,
+                )])
+            }
+        }
+        Ok(env)
+    }
+}
+
 /// Settings for shared libraries and executables.
 #[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct DynamicLinking {
@@ -341,6 +541,10 @@ pub struct DynamicLinking {
     /// What to do when detecting overlinking.
     #[serde(default, skip_serializing_if = "LinkingCheckBehavior::is_default")]
     pub(super) overlinking_behavior: LinkingCheckBehavior,
+    /// Whether to compare the pins of the package to itself in its
+    /// `run_exports` with the SONAMEs of the packaged shared libraries.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(super) check_run_exports: bool,
 }
 
 impl DynamicLinking {
@@ -382,6 +586,11 @@ impl DynamicLinking {
     pub fn error_on_overlinking(&self) -> bool {
         self.overlinking_behavior == LinkingCheckBehavior::Error
     }
+
+    /// Whether to check the `run_exports` against the packaged shared libraries.
+    pub fn check_run_exports(&self) -> bool {
+        self.check_run_exports
+    }
 }
 
 /// What to do during linking checks.
@@ -443,13 +652,536 @@ impl TryConvertNode<DynamicLinking> for RenderedMappingNode {
             missing_dso_allowlist,
             rpath_allowlist,
             overdepending_behavior,
-            overlinking_behavior
+            overlinking_behavior,
+            check_run_exports
         );
 
         Ok(dynamic_linking)
     }
 }
 
+/// A size in bytes that is parsed from a string with an optional unit (e.g.
+/// `100MB`, `1.5 GiB` or `4096`). `KB`, `MB` and `GB` are powers of 1000,
+/// `KiB`, `MiB` and `GiB` are powers of 1024.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct FileSize(pub u64);
+
+impl FromStr for FileSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let split = s
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(s.len());
+        let (number, unit) = s.split_at(split);
+        let number: f64 = number
+            .parse()
+            .map_err(|_| format!("`{s}` is not a valid size (e.g. `100MB`)"))?;
+
+        let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+            "" | "b" => 1,
+            "k" | "kb" => 1000,
+            "m" | "mb" => 1000_u64.pow(2),
+            "g" | "gb" => 1000_u64.pow(3),
+            "kib" => 1024,
+            "mib" => 1024_u64.pow(2),
+            "gib" => 1024_u64.pow(3),
+            unit => return Err(format!("unknown size unit `{unit}` (e.g. `MB` or `MiB`)")),
+        };
+
+        Ok(FileSize((number * multiplier as f64) as u64))
+    }
+}
+
+impl TryConvertNode<FileSize> for RenderedNode {
+    fn try_convert(&self, name: &str) -> Result<FileSize, Vec<PartialParsingError>> {
+        self.as_scalar()
+            .ok_or_else(|| vec![_partialerror!(*self.span(), ErrorKind::ExpectedScalar)])
+            .and_then(|s| s.try_convert(name))
+    }
+}
+
+impl TryConvertNode<FileSize> for RenderedScalarNode {
+    fn try_convert(&self, name: &str) -> Result<FileSize, Vec<PartialParsingError>> {
+        self.as_str().parse().map_err(|err: String| {
+            vec![_partialerror!(
+                *self.span(),
+                ErrorKind::InvalidValue((name.to_string(), err.into())),
+            )]
+        })
+    }
+}
+
+/// The channel priority of an output (`strict` or `disabled`).
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChannelPriority {
+    /// Packages from lower priority channels are not considered if the package
+    /// is available in a higher priority channel
+    Strict,
+    /// Packages from all channels are considered
+    Disabled,
+}
+
+impl From<ChannelPriority> for rattler_solve::ChannelPriority {
+    fn from(value: ChannelPriority) -> Self {
+        match value {
+            ChannelPriority::Strict => rattler_solve::ChannelPriority::Strict,
+            ChannelPriority::Disabled => rattler_solve::ChannelPriority::Disabled,
+        }
+    }
+}
+
+impl FromStr for ChannelPriority {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "strict" => Ok(ChannelPriority::Strict),
+            "disabled" => Ok(ChannelPriority::Disabled),
+            _ => Err(format!(
+                "`{s}` is not a valid channel priority (`strict` or `disabled`)"
+            )),
+        }
+    }
+}
+
+impl TryConvertNode<ChannelPriority> for RenderedNode {
+    fn try_convert(&self, name: &str) -> Result<ChannelPriority, Vec<PartialParsingError>> {
+        self.as_scalar()
+            .ok_or_else(|| vec![_partialerror!(*self.span(), ErrorKind::ExpectedScalar)])
+            .and_then(|s| {
+                s.as_str().parse().map_err(|err: String| {
+                    vec![_partialerror!(
+                        *s.span(),
+                        ErrorKind::InvalidValue((name.to_string(), err.into())),
+                    )]
+                })
+            })
+    }
+}
+
+/// The package format of an output (`conda` or `tar-bz2`).
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum PackageFormat {
+    /// A `.tar.bz2` package
+    #[serde(rename = "tar-bz2")]
+    TarBz2,
+    /// A `.conda` package
+    #[serde(rename = "conda")]
+    Conda,
+}
+
+impl From<PackageFormat> for ArchiveType {
+    fn from(value: PackageFormat) -> Self {
+        match value {
+            PackageFormat::TarBz2 => ArchiveType::TarBz2,
+            PackageFormat::Conda => ArchiveType::Conda,
+        }
+    }
+}
+
+impl TryConvertNode<PackageFormat> for RenderedNode {
+    fn try_convert(&self, name: &str) -> Result<PackageFormat, Vec<PartialParsingError>> {
+        self.as_scalar()
+            .ok_or_else(|| vec![_partialerror!(*self.span(), ErrorKind::ExpectedScalar)])
+            .and_then(|s| match s.as_str() {
+                "tar-bz2" | "tar.bz2" => Ok(PackageFormat::TarBz2),
+                "conda" => Ok(PackageFormat::Conda),
+                _ => Err(vec![_partialerror!(
+                    *self.span(),
+                    ErrorKind::ExpectedScalar,
+                    help = format!("valid options for {name} are `conda` or `tar-bz2`")
+                )]),
+            })
+    }
+}
+
+/// The compression level of an output: `auto`, `fast`, `default`, `max` or
+/// a number (1 to 9 for `tar-bz2`, -7 to 22 for `conda`). The range of a
+/// number is checked against the final package format when the output is
+/// packaged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub enum Compression {
+    /// Select the compression level based on the content of the package
+    Auto,
+    /// The fastest compression
+    Lowest,
+    /// The default compression level of the package format
+    Default,
+    /// The highest compression
+    Highest,
+    /// A numeric compression level
+    Level(i32),
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Compression::Auto),
+            "fast" | "lowest" | "min" => Ok(Compression::Lowest),
+            "default" | "normal" => Ok(Compression::Default),
+            "max" | "highest" => Ok(Compression::Highest),
+            number => number.parse().map(Compression::Level).map_err(|_| {
+                format!("`{s}` is not a valid compression level (`auto`, `fast`, `default`, `max` or a number)")
+            }),
+        }
+    }
+}
+
+impl std::fmt::Display for Compression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Compression::Auto => write!(f, "auto"),
+            Compression::Lowest => write!(f, "fast"),
+            Compression::Default => write!(f, "default"),
+            Compression::Highest => write!(f, "max"),
+            Compression::Level(level) => write!(f, "{level}"),
+        }
+    }
+}
+
+impl From<Compression> for String {
+    fn from(value: Compression) -> Self {
+        value.to_string()
+    }
+}
+
+impl TryFrom<String> for Compression {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl TryConvertNode<Compression> for RenderedNode {
+    fn try_convert(&self, name: &str) -> Result<Compression, Vec<PartialParsingError>> {
+        self.as_scalar()
+            .ok_or_else(|| vec![_partialerror!(*self.span(), ErrorKind::ExpectedScalar)])
+            .and_then(|s| {
+                s.as_str().parse().map_err(|err: String| {
+                    vec![_partialerror!(
+                        *self.span(),
+                        ErrorKind::InvalidValue((name.to_string(), err.into())),
+                    )]
+                })
+            })
+    }
+}
+
+/// Which parts of the recipe are stored in `info/recipe` of the package.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum IncludeRecipe {
+    /// The recipe folder with all files, the variant config and the rendered
+    /// recipe
+    #[default]
+    Full,
+    /// Only `rendered_recipe.yaml` and `variant_config.yaml`, which is enough
+    /// to rebuild the package
+    Rendered,
+    /// Nothing
+    None,
+}
+
+impl IncludeRecipe {
+    /// Returns true if the full recipe is stored (the default).
+    pub fn is_default(&self) -> bool {
+        *self == Self::Full
+    }
+}
+
+impl TryConvertNode<IncludeRecipe> for RenderedNode {
+    fn try_convert(&self, name: &str) -> Result<IncludeRecipe, Vec<PartialParsingError>> {
+        self.as_scalar()
+            .ok_or_else(|| vec![_partialerror!(*self.span(), ErrorKind::ExpectedScalar)])
+            .and_then(|s| match s.as_str() {
+                "full" => Ok(IncludeRecipe::Full),
+                "rendered" => Ok(IncludeRecipe::Rendered),
+                "none" => Ok(IncludeRecipe::None),
+                _ => Err(vec![_partialerror!(
+                    *self.span(),
+                    ErrorKind::ExpectedScalar,
+                    help = format!("valid options for {name} are `full`, `rendered` or `none`")
+                )]),
+            })
+    }
+}
+
+/// What to do when the size budget is exceeded.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SizeBudgetBehavior {
+    /// Fail the build
+    #[default]
+    Error,
+    /// Only print a warning
+    Warn,
+}
+
+impl SizeBudgetBehavior {
+    /// Returns true if this is the default behavior.
+    pub fn is_default(&self) -> bool {
+        self == &SizeBudgetBehavior::default()
+    }
+}
+
+impl TryConvertNode<SizeBudgetBehavior> for RenderedNode {
+    fn try_convert(&self, name: &str) -> Result<SizeBudgetBehavior, Vec<PartialParsingError>> {
+        self.as_scalar()
+            .ok_or_else(|| vec![_partialerror!(*self.span(), ErrorKind::ExpectedScalar)])
+            .and_then(|s| match s.as_str() {
+                "error" => Ok(SizeBudgetBehavior::Error),
+                "warn" => Ok(SizeBudgetBehavior::Warn),
+                _ => Err(vec![_partialerror!(
+                    *self.span(),
+                    ErrorKind::ExpectedScalar,
+                    help = format!("valid options for {name} are `error` or `warn`")
+                )]),
+            })
+    }
+}
+
+/// The size budget of a package. The budget is checked when the package is
+/// created, to catch e.g. accidental static linking or debug symbols.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SizeBudget {
+    /// The maximum total size of all files in the package
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total: Option<FileSize>,
+    /// The maximum size of any single file in the package
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub per_file: Option<FileSize>,
+    /// What to do when the budget is exceeded (`error` or `warn`)
+    #[serde(default, skip_serializing_if = "SizeBudgetBehavior::is_default")]
+    pub behavior: SizeBudgetBehavior,
+}
+
+impl SizeBudget {
+    /// Returns true if no budget is set.
+    pub fn is_default(&self) -> bool {
+        self == &SizeBudget::default()
+    }
+}
+
+impl TryConvertNode<SizeBudget> for RenderedNode {
+    fn try_convert(&self, name: &str) -> Result<SizeBudget, Vec<PartialParsingError>> {
+        self.as_mapping()
+            .ok_or_else(|| vec![_partialerror!(*self.span(), ErrorKind::ExpectedMapping)])
+            .and_then(|m| m.try_convert(name))
+    }
+}
+
+impl TryConvertNode<SizeBudget> for RenderedMappingNode {
+    fn try_convert(&self, _name: &str) -> Result<SizeBudget, Vec<PartialParsingError>> {
+        let mut size_budget = SizeBudget::default();
+        validate_keys!(size_budget, self.iter(), total, per_file, behavior);
+        Ok(size_budget)
+    }
+}
+
+/// What to do when a likely secret is found in the package.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SecretScanBehavior {
+    /// Fail the build
+    #[default]
+    Error,
+    /// Print a warning
+    Warn,
+    /// Do not scan the package
+    Ignore,
+}
+
+impl SecretScanBehavior {
+    /// Returns true if this is the default behavior.
+    pub fn is_default(&self) -> bool {
+        self == &SecretScanBehavior::default()
+    }
+}
+
+impl TryConvertNode<SecretScanBehavior> for RenderedNode {
+    fn try_convert(&self, name: &str) -> Result<SecretScanBehavior, Vec<PartialParsingError>> {
+        self.as_scalar()
+            .ok_or_else(|| vec![_partialerror!(*self.span(), ErrorKind::ExpectedScalar)])
+            .and_then(|s| match s.as_str() {
+                "error" => Ok(SecretScanBehavior::Error),
+                "warn" => Ok(SecretScanBehavior::Warn),
+                "ignore" => Ok(SecretScanBehavior::Ignore),
+                _ => Err(vec![_partialerror!(
+                    *self.span(),
+                    ErrorKind::ExpectedScalar,
+                    help = format!("valid options for {name} are `error`, `warn` or `ignore`")
+                )]),
+            })
+    }
+}
+
+/// The scan of the package for likely secrets (e.g. AWS keys, tokens,
+/// private keys or the values of the script secrets).
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SecretScan {
+    /// What to do when a likely secret is found (`error`, `warn` or `ignore`)
+    #[serde(default, skip_serializing_if = "SecretScanBehavior::is_default")]
+    pub behavior: SecretScanBehavior,
+    /// Files that are not scanned (e.g. test fixtures with example keys)
+    #[serde(default, skip_serializing_if = "GlobVec::is_empty")]
+    pub allow: GlobVec,
+}
+
+impl SecretScan {
+    /// Returns true if the default settings are used.
+    pub fn is_default(&self) -> bool {
+        self == &SecretScan::default()
+    }
+}
+
+impl TryConvertNode<SecretScan> for RenderedNode {
+    fn try_convert(&self, name: &str) -> Result<SecretScan, Vec<PartialParsingError>> {
+        self.as_mapping()
+            .ok_or_else(|| vec![_partialerror!(*self.span(), ErrorKind::ExpectedMapping)])
+            .and_then(|m| m.try_convert(name))
+    }
+}
+
+impl TryConvertNode<SecretScan> for RenderedMappingNode {
+    fn try_convert(&self, _name: &str) -> Result<SecretScan, Vec<PartialParsingError>> {
+        let mut secret_scan = SecretScan::default();
+        validate_keys!(secret_scan, self.iter(), behavior, allow);
+        Ok(secret_scan)
+    }
+}
+
+/// What to do with a problematic symlink in the package.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SymlinkBehavior {
+    /// Fail the build
+    Error,
+    /// Print a warning and package the symlink
+    #[default]
+    Warn,
+    /// Package the symlink silently
+    Ignore,
+}
+
+impl SymlinkBehavior {
+    /// Returns true if this is the default behavior.
+    pub fn is_default(&self) -> bool {
+        self == &SymlinkBehavior::default()
+    }
+}
+
+impl TryConvertNode<SymlinkBehavior> for RenderedNode {
+    fn try_convert(&self, name: &str) -> Result<SymlinkBehavior, Vec<PartialParsingError>> {
+        self.as_scalar()
+            .ok_or_else(|| vec![_partialerror!(*self.span(), ErrorKind::ExpectedScalar)])
+            .and_then(|s| match s.as_str() {
+                "error" => Ok(SymlinkBehavior::Error),
+                "warn" => Ok(SymlinkBehavior::Warn),
+                "ignore" => Ok(SymlinkBehavior::Ignore),
+                _ => Err(vec![_partialerror!(
+                    *self.span(),
+                    ErrorKind::ExpectedScalar,
+                    help = format!("valid options for {name} are `error`, `warn` or `ignore`")
+                )]),
+            })
+    }
+}
+
+/// How symlinks in the package are checked. Absolute symlinks into the prefix
+/// are always made relative.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SymlinkSettings {
+    /// Symlinks that point to a file that does not exist
+    #[serde(default, skip_serializing_if = "SymlinkBehavior::is_default")]
+    pub dangling: SymlinkBehavior,
+    /// Symlinks that point outside of the prefix
+    #[serde(default, skip_serializing_if = "SymlinkBehavior::is_default")]
+    pub outside_prefix: SymlinkBehavior,
+}
+
+impl SymlinkSettings {
+    /// Returns true if the default settings are used.
+    pub fn is_default(&self) -> bool {
+        self == &SymlinkSettings::default()
+    }
+}
+
+impl TryConvertNode<SymlinkSettings> for RenderedNode {
+    fn try_convert(&self, name: &str) -> Result<SymlinkSettings, Vec<PartialParsingError>> {
+        self.as_mapping()
+            .ok_or_else(|| vec![_partialerror!(*self.span(), ErrorKind::ExpectedMapping)])
+            .and_then(|m| m.try_convert(name))
+    }
+}
+
+impl TryConvertNode<SymlinkSettings> for RenderedMappingNode {
+    fn try_convert(&self, _name: &str) -> Result<SymlinkSettings, Vec<PartialParsingError>> {
+        let mut symlinks = SymlinkSettings::default();
+        validate_keys!(symlinks, self.iter(), dangling, outside_prefix);
+        Ok(symlinks)
+    }
+}
+
+/// The scripts that are executed when the package is installed or removed. The
+/// paths are relative to the recipe directory. If a path has no extension,
+/// `.sh` (or `.bat` on Windows) is appended. The scripts are packaged as
+/// `bin/.<name>-<action>.sh` or `Scripts/.<name>-<action>.bat`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LinkScripts {
+    /// The script that is executed before the package is linked (deprecated
+    /// in conda and not supported by all installers)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre_link: Option<PathBuf>,
+    /// The script that is executed after the package is linked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_link: Option<PathBuf>,
+    /// The script that is executed before the package is removed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre_unlink: Option<PathBuf>,
+}
+
+impl LinkScripts {
+    /// Returns true if no link scripts are set.
+    pub fn is_default(&self) -> bool {
+        self == &LinkScripts::default()
+    }
+
+    /// The configured scripts with the name of their action (e.g. `post-link`)
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, &PathBuf)> {
+        [
+            ("pre-link", &self.pre_link),
+            ("post-link", &self.post_link),
+            ("pre-unlink", &self.pre_unlink),
+        ]
+        .into_iter()
+        .filter_map(|(action, path)| path.as_ref().map(|path| (action, path)))
+    }
+}
+
+impl TryConvertNode<LinkScripts> for RenderedNode {
+    fn try_convert(&self, name: &str) -> Result<LinkScripts, Vec<PartialParsingError>> {
+        self.as_mapping()
+            .ok_or_else(|| vec![_partialerror!(*self.span(), ErrorKind::ExpectedMapping)])
+            .and_then(|m| m.try_convert(name))
+    }
+}
+
+impl TryConvertNode<LinkScripts> for RenderedMappingNode {
+    fn try_convert(&self, _name: &str) -> Result<LinkScripts, Vec<PartialParsingError>> {
+        let mut link_scripts = LinkScripts::default();
+        validate_keys!(link_scripts, self.iter(), pre_link, post_link, pre_unlink);
+        Ok(link_scripts)
+    }
+}
+
 impl TryConvertNode<Vec<PostProcess>> for RenderedNode {
     fn try_convert(&self, name: &str) -> Result<Vec<PostProcess>, Vec<PartialParsingError>> {
         self.as_sequence()
@@ -525,12 +1257,18 @@ pub struct Python {
     /// python` packages to use.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub site_packages_path: Option<String>,
+
+    /// Regenerate the `RECORD` files of the packaged `.dist-info` directories
+    /// (hashes and sizes) after the files were modified, and remove the entries
+    /// of files that are not part of the package.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub fix_record: bool,
 }
 
 impl Python {
     /// Returns true if this is the default python configuration.
     pub fn is_default(&self) -> bool {
-        self.entry_points.is_empty() && self.skip_pyc_compilation.is_empty()
+        self.entry_points.is_empty() && self.skip_pyc_compilation.is_empty() && !self.fix_record
     }
 }
 
@@ -552,7 +1290,8 @@ impl TryConvertNode<Python> for RenderedMappingNode {
             skip_pyc_compilation,
             use_python_app_entrypoint,
             site_packages_path,
-            version_independent
+            version_independent,
+            fix_record
         );
         Ok(python)
     }
@@ -741,3 +1480,274 @@ impl TryConvertNode<ForceFileType> for RenderedMappingNode {
         Ok(force_file_type)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rattler_conda_types::Platform;
+
+    use super::*;
+    use crate::{recipe::Recipe, selectors::SelectorConfig};
+
+    #[test]
+    fn test_build_env_parsing() {
+        let recipe = r#"
+        package:
+          name: test
+          version: 1.0.0
+        build:
+          env:
+            - FOO: bar
+              MAKEFLAGS: -j4
+            - FOO: baz
+              CFLAGS: -O2
+            - if: unix
+              then:
+                unset:
+                  - MAKEFLAGS
+                  - LDFLAGS
+              else:
+                WINDOWS: "1"
+        "#;
+
+        let selector_config = SelectorConfig {
+            target_platform: Platform::Linux64,
+            ..SelectorConfig::default()
+        };
+        let recipe = Recipe::from_yaml(recipe, selector_config).unwrap();
+
+        let env = recipe.build().env();
+        assert_eq!(
+            env.set_vars().collect::<Vec<_>>(),
+            vec![
+                (&"FOO".to_string(), &"baz".to_string()),
+                (&"CFLAGS".to_string(), &"-O2".to_string())
+            ]
+        );
+        assert_eq!(
+            env.unset_vars().collect::<Vec<_>>(),
+            vec!["MAKEFLAGS", "LDFLAGS"]
+        );
+
+        // round trip through the serialized recipe
+        let yaml_serde = serde_yaml::to_string(recipe.build()).unwrap();
+        let build: Build = serde_yaml::from_str(&yaml_serde).unwrap();
+        assert_eq!(build.env(), env);
+    }
+
+    #[test]
+    fn test_size_budget_parsing() {
+        assert_eq!("100MB".parse(), Ok(FileSize(100_000_000)));
+        assert_eq!("1.5 GiB".parse(), Ok(FileSize(1_610_612_736)));
+        assert_eq!("4096".parse(), Ok(FileSize(4096)));
+        assert!("10 parsecs".parse::<FileSize>().is_err());
+
+        let build_section = r#"
+        build:
+          size_budget:
+            total: 100MB
+            per_file: 30MiB
+            behavior: warn
+        "#;
+
+        let yaml_root = RenderedNode::parse_yaml(0, build_section)
+            .map_err(|err| vec![err])
+            .unwrap();
+        let build_node = yaml_root.as_mapping().unwrap().get("build").unwrap();
+        let build: Build = build_node.try_convert("build").unwrap();
+
+        let budget = build.size_budget();
+        assert_eq!(budget.total, Some(FileSize(100_000_000)));
+        assert_eq!(budget.per_file, Some(FileSize(30 * 1024 * 1024)));
+        assert_eq!(budget.behavior, SizeBudgetBehavior::Warn);
+
+        // round trip through the serialized recipe
+        let yaml_serde = serde_yaml::to_string(&build).unwrap();
+        let build: Build = serde_yaml::from_str(&yaml_serde).unwrap();
+        assert_eq!(build.size_budget(), budget);
+    }
+
+    #[test]
+    fn test_symlink_settings_parsing() {
+        let build_section = r#"
+        build:
+          symlinks:
+            dangling: error
+            outside_prefix: ignore
+        "#;
+
+        let yaml_root = RenderedNode::parse_yaml(0, build_section)
+            .map_err(|err| vec![err])
+            .unwrap();
+        let build_node = yaml_root.as_mapping().unwrap().get("build").unwrap();
+        let build: Build = build_node.try_convert("build").unwrap();
+        assert_eq!(build.symlinks().dangling, SymlinkBehavior::Error);
+        assert_eq!(build.symlinks().outside_prefix, SymlinkBehavior::Ignore);
+
+        let build_section = r#"
+        build:
+          symlinks:
+            dangling: fail
+        "#;
+        let yaml_root = RenderedNode::parse_yaml(0, build_section)
+            .map_err(|err| vec![err])
+            .unwrap();
+        let build_node = yaml_root.as_mapping().unwrap().get("build").unwrap();
+        let build: Result<Build, _> = build_node.try_convert("build");
+        assert!(build.is_err());
+    }
+
+    #[test]
+    fn test_link_scripts_parsing() {
+        let build_section = r#"
+        build:
+          link_scripts:
+            post_link: scripts/post-link
+            pre_unlink: pre-unlink.sh
+        "#;
+
+        let yaml_root = RenderedNode::parse_yaml(0, build_section)
+            .map_err(|err| vec![err])
+            .unwrap();
+        let build_node = yaml_root.as_mapping().unwrap().get("build").unwrap();
+        let build: Build = build_node.try_convert("build").unwrap();
+        assert_eq!(
+            build.link_scripts().iter().collect::<Vec<_>>(),
+            vec![
+                ("post-link", &PathBuf::from("scripts/post-link")),
+                ("pre-unlink", &PathBuf::from("pre-unlink.sh")),
+            ]
+        );
+
+        let build_section = r#"
+        build:
+          link_scripts:
+            post_install: post-link.sh
+        "#;
+        let yaml_root = RenderedNode::parse_yaml(0, build_section)
+            .map_err(|err| vec![err])
+            .unwrap();
+        let build_node = yaml_root.as_mapping().unwrap().get("build").unwrap();
+        let build: Result<Build, _> = build_node.try_convert("build");
+        assert!(build.is_err());
+    }
+
+    #[test]
+    fn test_channel_priority_parsing() {
+        let build_section = r#"
+        build:
+          channel_priority: disabled
+        "#;
+
+        let yaml_root = RenderedNode::parse_yaml(0, build_section)
+            .map_err(|err| vec![err])
+            .unwrap();
+        let build_node = yaml_root.as_mapping().unwrap().get("build").unwrap();
+        let build: Build = build_node.try_convert("build").unwrap();
+        assert_eq!(build.channel_priority(), Some(ChannelPriority::Disabled));
+
+        // round trip through the serialized recipe
+        let yaml_serde = serde_yaml::to_string(&build).unwrap();
+        assert!(yaml_serde.contains("channel_priority: disabled"));
+        let build: Build = serde_yaml::from_str(&yaml_serde).unwrap();
+        assert_eq!(build.channel_priority(), Some(ChannelPriority::Disabled));
+
+        let build_section = r#"
+        build:
+          channel_priority: flexible
+        "#;
+        let yaml_root = RenderedNode::parse_yaml(0, build_section)
+            .map_err(|err| vec![err])
+            .unwrap();
+        let build_node = yaml_root.as_mapping().unwrap().get("build").unwrap();
+        assert!(TryConvertNode::<Build>::try_convert(build_node, "build").is_err());
+
+        // the `channel_priority` of the variant config is parsed from its value
+        assert_eq!("strict".parse(), Ok(ChannelPriority::Strict));
+        assert!("flexible".parse::<ChannelPriority>().is_err());
+    }
+
+    #[test]
+    fn test_include_recipe_parsing() {
+        let build_section = r#"
+        build:
+          include_recipe: rendered
+        "#;
+
+        let yaml_root = RenderedNode::parse_yaml(0, build_section)
+            .map_err(|err| vec![err])
+            .unwrap();
+        let build_node = yaml_root.as_mapping().unwrap().get("build").unwrap();
+        let build: Build = build_node.try_convert("build").unwrap();
+        assert_eq!(build.include_recipe(), IncludeRecipe::Rendered);
+
+        // round trip through the serialized recipe
+        let yaml_serde = serde_yaml::to_string(&build).unwrap();
+        assert!(yaml_serde.contains("include_recipe: rendered"));
+        let build: Build = serde_yaml::from_str(&yaml_serde).unwrap();
+        assert_eq!(build.include_recipe(), IncludeRecipe::Rendered);
+
+        // the default is not serialized
+        let yaml_serde = serde_yaml::to_string(&Build::default()).unwrap();
+        assert!(!yaml_serde.contains("include_recipe"));
+    }
+
+    #[test]
+    fn test_package_format_parsing() {
+        let build_section = r#"
+        build:
+          package_format: tar-bz2
+          compression: 5
+        "#;
+
+        let yaml_root = RenderedNode::parse_yaml(0, build_section)
+            .map_err(|err| vec![err])
+            .unwrap();
+        let build_node = yaml_root.as_mapping().unwrap().get("build").unwrap();
+        let build: Build = build_node.try_convert("build").unwrap();
+        assert_eq!(build.package_format(), Some(PackageFormat::TarBz2));
+        assert_eq!(build.compression(), Some(Compression::Level(5)));
+
+        // round trip through the serialized recipe
+        let yaml_serde = serde_yaml::to_string(&build).unwrap();
+        assert!(yaml_serde.contains("package_format: tar-bz2"));
+        let build: Build = serde_yaml::from_str(&yaml_serde).unwrap();
+        assert_eq!(build.compression(), Some(Compression::Level(5)));
+
+        assert_eq!("max".parse(), Ok(Compression::Highest));
+        assert_eq!("fast".parse(), Ok(Compression::Lowest));
+        assert!("fastest".parse::<Compression>().is_err());
+    }
+
+    #[test]
+    fn test_secret_scan_parsing() {
+        let build_section = r#"
+        build:
+          secret_scan:
+            behavior: warn
+            allow:
+              - tests/data/**
+        "#;
+
+        let yaml_root = RenderedNode::parse_yaml(0, build_section)
+            .map_err(|err| vec![err])
+            .unwrap();
+        let build_node = yaml_root.as_mapping().unwrap().get("build").unwrap();
+        let build: Build = build_node.try_convert("build").unwrap();
+        assert_eq!(build.secret_scan().behavior, SecretScanBehavior::Warn);
+        assert!(build
+            .secret_scan()
+            .allow
+            .is_match(std::path::Path::new("tests/data/key.pem")));
+
+        let build_section = r#"
+        build:
+          secret_scan:
+            behavior: fail
+        "#;
+        let yaml_root = RenderedNode::parse_yaml(0, build_section)
+            .map_err(|err| vec![err])
+            .unwrap();
+        let build_node = yaml_root.as_mapping().unwrap().get("build").unwrap();
+        assert!(TryConvertNode::<Build>::try_convert(build_node, "build").is_err());
+    }
+}
@@ -233,6 +233,43 @@ fn to_vector_of_globs(
     Ok(vec)
 }
 
+/// The fields whose list of globs may contain negated globs (`!glob`)
+const NEGATABLE_GLOB_FIELDS: &[&str] = &["license_file"];
+
+/// Parses a list of globs where globs that start with `!` are negated (e.g.
+/// `!docs/**/internal/*`). Returns the include and the exclude globs. A list
+/// with negated globs needs at least one glob that is not negated, otherwise it
+/// would match everything that is not excluded.
+fn to_include_and_exclude_globs(
+    sequence: &RenderedSequenceNode,
+    name: &str,
+) -> Result<(Vec<Glob>, Vec<Glob>), Vec<PartialParsingError>> {
+    let mut include = Vec::with_capacity(sequence.len());
+    let mut exclude = Vec::new();
+    for item in sequence.iter() {
+        let str: String = item.try_convert("globs")?;
+        let (target, glob) = match str.strip_prefix('!') {
+            Some(negated) => (&mut exclude, negated),
+            None => (&mut include, str.as_str()),
+        };
+        target.push(
+            to_glob(glob)
+                .map_err(|err| vec![_partialerror!(*item.span(), ErrorKind::GlobParsing(err),)])?,
+        );
+    }
+    if include.is_empty() && !exclude.is_empty() {
+        return Err(vec![_partialerror!(
+            *sequence.span(),
+            ErrorKind::InvalidValue((
+                name.to_string(),
+                "only negated globs are given, add at least one glob to include".into()
+            )),
+            help = "negated globs (`!glob`) only exclude paths that are matched by another glob"
+        )]);
+    }
+    Ok((include, exclude))
+}
+
 impl TryConvertNode<GlobVec> for RenderedScalarNode {
     fn try_convert(&self, _name: &str) -> Result<GlobVec, Vec<PartialParsingError>> {
         let vec = vec![to_glob(self.as_str())
@@ -243,9 +280,13 @@ impl TryConvertNode<GlobVec> for RenderedScalarNode {
 }
 
 impl TryConvertNode<GlobVec> for RenderedSequenceNode {
-    fn try_convert(&self, _name: &str) -> Result<GlobVec, Vec<PartialParsingError>> {
-        let vec = to_vector_of_globs(self)?;
-        GlobVec::new(vec.into(), InnerGlobVec::default())
+    fn try_convert(&self, name: &str) -> Result<GlobVec, Vec<PartialParsingError>> {
+        let (include, exclude) = if NEGATABLE_GLOB_FIELDS.contains(&name) {
+            to_include_and_exclude_globs(self, name)?
+        } else {
+            (to_vector_of_globs(self)?, Vec::new())
+        };
+        GlobVec::new(include.into(), exclude.into())
             .map_err(|err| vec![_partialerror!(*self.span(), ErrorKind::GlobParsing(err),)])
     }
 }
@@ -414,6 +455,45 @@ mod tests {
         assert_eq!(parsed_again.exclude_globset.len(), 2);
     }
 
+    #[test]
+    fn test_parsing_negated_globs() {
+        let yaml = r#"license_file:
+        - LICENSE
+        - docs/
+        - "!docs/**/internal/*"
+        "#;
+
+        let yaml_root = RenderedNode::parse_yaml(0, yaml)
+            .map_err(|err| vec![err])
+            .unwrap();
+        let tests_node = yaml_root.as_mapping().unwrap().get("license_file").unwrap();
+        let globvec: GlobVec = tests_node.try_convert("license_file").unwrap();
+        assert_eq!(globvec.include.len(), 2);
+        assert_eq!(globvec.exclude.len(), 1);
+        assert!(globvec.is_match(Path::new("LICENSE")));
+        assert!(globvec.is_match(Path::new("docs/licenses/MIT.txt")));
+        assert!(!globvec.is_match(Path::new("docs/licenses/internal/NOTES.txt")));
+
+        // the negated globs are serialized as `exclude` and parsed back
+        let as_yaml = serde_yaml::to_string(&globvec).unwrap();
+        let parsed_again: GlobVec = serde_yaml::from_str(&as_yaml).unwrap();
+        assert_eq!(parsed_again, globvec);
+
+        // a list of only negated globs would match everything else
+        let yaml_root =
+            RenderedNode::parse_yaml(0, "license_file: ['!docs/**/internal/*']").unwrap();
+        let tests_node = yaml_root.as_mapping().unwrap().get("license_file").unwrap();
+        let result: Result<GlobVec, _> = tests_node.try_convert("license_file");
+        assert!(result.is_err());
+
+        // other fields do not support negated globs
+        let yaml_root = RenderedNode::parse_yaml(0, "files: [lib/, '!lib/*.a']").unwrap();
+        let tests_node = yaml_root.as_mapping().unwrap().get("files").unwrap();
+        let globvec: GlobVec = tests_node.try_convert("files").unwrap();
+        assert_eq!(globvec.include.len(), 2);
+        assert!(globvec.exclude.is_empty());
+    }
+
     #[test]
     fn test_glob_match_folder() {
         let globvec = GlobVec::from_vec(vec!["foo/"], None);
@@ -27,7 +27,8 @@ macro_rules! validate_keys {
                             *key.span(),
                             ErrorKind::InvalidField(key_str.to_string().into()),
                             help = format!("valid options for {name} are {valid_options}", name = stringify!($name), valid_options = stringify!($($key),*))
-                        )]
+                        )
+                        .with_suggestion(key_str, &[$(stringify!($key)),*])]
                     )
                 }
             }
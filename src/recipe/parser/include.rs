@@ -0,0 +1,281 @@
+//! Recipe composition with the (experimental) `include` key.
+//!
+//! A recipe can include one or more recipe fragments (e.g. shared `about`,
+//! `extra` or `tests` sections that are maintained centrally):
+//!
+//! ```yaml
+//! include:
+//!   - ../common/about.yaml
+//!   - ../common/tests.yaml
+//! ```
+//!
+//! The fragments are merged into the recipe before anything else is parsed,
+//! with the following rules:
+//!
+//! - fragments are applied in order, the recipe itself is applied last
+//! - mappings are merged recursively
+//! - sequences are concatenated (items of earlier fragments come first)
+//! - any other value of a later fragment (or the recipe) replaces the earlier
+//!   one
+//!
+//! Errors in the included content point to the `include` entry in the recipe
+//! that brought it in.
+
+use std::path::{Path, PathBuf};
+
+use marked_yaml::{Node, Span};
+
+use crate::{
+    _partialerror,
+    recipe::{custom_yaml::parse_yaml, error::ErrorKind, ParsingError},
+};
+
+/// The key that is used to include recipe fragments
+const INCLUDE_KEY: &str = "include";
+
+/// Merge `overlay` into `base`. Values of `overlay` take precedence.
+fn merge_into(base: &mut Node, overlay: &Node) {
+    match (base, overlay) {
+        (Node::Mapping(base), Node::Mapping(overlay)) => {
+            for (key, value) in overlay.iter() {
+                match base.get_mut(key) {
+                    Some(existing) => merge_into(existing, value),
+                    None => {
+                        base.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        (Node::Sequence(base), Node::Sequence(overlay)) => {
+            base.extend(overlay.iter().cloned());
+        }
+        (base, overlay) => *base = overlay.clone(),
+    }
+}
+
+/// Point all spans of the node (and its children) to `span`
+fn set_span(node: &mut Node, span: Span) {
+    match node {
+        Node::Scalar(scalar) => *scalar.span_mut() = span,
+        Node::Sequence(sequence) => {
+            *sequence.span_mut() = span;
+            for item in sequence.iter_mut() {
+                set_span(item, span);
+            }
+        }
+        Node::Mapping(mapping) => {
+            *mapping.span_mut() = span;
+            let entries = mapping
+                .iter()
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect::<Vec<_>>();
+            mapping.clear();
+            for (mut key, mut value) in entries {
+                *key.span_mut() = span;
+                set_span(&mut value, span);
+                mapping.insert(key, value);
+            }
+        }
+    }
+}
+
+/// The entries of the `include` key (a single path or a list of paths)
+fn include_entries(include: &Node) -> Vec<&Node> {
+    match include {
+        Node::Sequence(sequence) => sequence.iter().collect(),
+        node => vec![node],
+    }
+}
+
+/// Returns the paths of the recipe fragments that the recipe includes with the
+/// `include` key, as written in the recipe (relative to the recipe directory).
+pub fn included_files(src: &str) -> Result<Vec<PathBuf>, ParsingError> {
+    let root = parse_yaml(0, src)?;
+    let Some(include) = root.as_mapping().and_then(|root| root.get(INCLUDE_KEY)) else {
+        return Ok(Vec::new());
+    };
+    Ok(include_entries(include)
+        .into_iter()
+        .filter_map(Node::as_scalar)
+        .map(|path| PathBuf::from(path.as_str()))
+        .collect())
+}
+
+/// Load a single recipe fragment
+fn load_fragment(src: &str, path_node: &Node, recipe_dir: &Path) -> Result<Node, ParsingError> {
+    let Some(path) = path_node.as_scalar() else {
+        return Err(ParsingError::from_partial(
+            src,
+            _partialerror!(
+                *path_node.span(),
+                ErrorKind::ExpectedScalar,
+                help = "`include` entries must be paths to recipe fragments"
+            ),
+        ));
+    };
+    let span = *path.span();
+
+    let fragment_path = recipe_dir.join(path.as_str());
+    let fragment_src = fs_err::read_to_string(&fragment_path).map_err(|e| {
+        ParsingError::from_partial(
+            src,
+            _partialerror!(
+                span,
+                ErrorKind::InvalidValue((INCLUDE_KEY.to_string(), e.to_string().into())),
+                label = "failed to read this recipe fragment"
+            ),
+        )
+    })?;
+
+    // syntax errors are reported against the fragment itself
+    let mut fragment = parse_yaml(0, &fragment_src)?;
+    let Some(fragment_map) = fragment.as_mapping() else {
+        return Err(ParsingError::from_partial(
+            src,
+            _partialerror!(
+                span,
+                ErrorKind::ExpectedMapping,
+                help = "the root node of an included recipe fragment must be a mapping"
+            ),
+        ));
+    };
+
+    for key in [INCLUDE_KEY, "outputs"] {
+        if fragment_map.contains_key(key) {
+            return Err(ParsingError::from_partial(
+                src,
+                _partialerror!(
+                    span,
+                    ErrorKind::InvalidField(key.to_string().into()),
+                    help = format!("an included recipe fragment cannot contain `{key}`")
+                ),
+            ));
+        }
+    }
+
+    set_span(&mut fragment, span);
+    Ok(fragment)
+}
+
+/// Resolve the `include` key of the recipe root node by merging all included
+/// fragments into it. Fragments are resolved relative to `recipe_dir`. If no
+/// `recipe_dir` is given (e.g. the recipe is parsed from a string), the
+/// `include` key is rejected.
+pub(super) fn resolve_includes(
+    src: &str,
+    root: &mut Node,
+    recipe_dir: Option<&Path>,
+    experimental: bool,
+) -> Result<(), ParsingError> {
+    let Some(root_map) = root.as_mapping_mut() else {
+        return Ok(());
+    };
+    let Some(include) = root_map.remove(INCLUDE_KEY) else {
+        return Ok(());
+    };
+
+    if !experimental {
+        return Err(ParsingError::from_partial(
+            src,
+            _partialerror!(
+                *include.span(),
+                ErrorKind::ExperimentalOnly(INCLUDE_KEY.to_string()),
+                help = "`include` requires the `--experimental` flag to be enabled"
+            ),
+        ));
+    }
+
+    let Some(recipe_dir) = recipe_dir else {
+        return Err(ParsingError::from_partial(
+            src,
+            _partialerror!(
+                *include.span(),
+                ErrorKind::InvalidField(INCLUDE_KEY.to_string().into()),
+                help = "`include` is only supported for recipes that are read from a file"
+            ),
+        ));
+    };
+
+    let mut merged: Option<Node> = None;
+    for path in include_entries(&include) {
+        let fragment = load_fragment(src, path, recipe_dir)?;
+        match merged.as_mut() {
+            Some(merged) => merge_into(merged, &fragment),
+            None => merged = Some(fragment),
+        }
+    }
+
+    if let Some(mut merged) = merged {
+        let span = *root.span();
+        merge_into(&mut merged, root);
+        // the merged root belongs to the recipe
+        if let Node::Mapping(mapping) = &mut merged {
+            *mapping.span_mut() = span;
+        }
+        *root = merged;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_includes() {
+        let dir = tempfile::tempdir().unwrap();
+        fs_err::write(
+            dir.path().join("common.yaml"),
+            "about:\n  license: MIT\n  summary: shared\ntests:\n  - script: [echo shared]\n",
+        )
+        .unwrap();
+
+        let src = "include: common.yaml\nabout:\n  summary: own\ntests:\n  - script: [echo own]\n";
+        let mut root = parse_yaml(0, src).unwrap();
+        resolve_includes(src, &mut root, Some(dir.path()), true).unwrap();
+
+        let root = root.as_mapping().unwrap();
+        assert!(!root.contains_key("include"));
+        let about = root.get("about").unwrap().as_mapping().unwrap();
+        assert_eq!(
+            about.get("license").unwrap().as_scalar().unwrap().as_str(),
+            "MIT"
+        );
+        assert_eq!(
+            about.get("summary").unwrap().as_scalar().unwrap().as_str(),
+            "own"
+        );
+        assert_eq!(root.get("tests").unwrap().as_sequence().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_included_files() {
+        let src = "include:\n  - ../common/about.yaml\n  - tests.yaml\npackage:\n  name: foo\n";
+        assert_eq!(
+            included_files(src).unwrap(),
+            vec![
+                PathBuf::from("../common/about.yaml"),
+                PathBuf::from("tests.yaml")
+            ]
+        );
+
+        let src = "include: common.yaml\n";
+        assert_eq!(
+            included_files(src).unwrap(),
+            vec![PathBuf::from("common.yaml")]
+        );
+
+        assert!(included_files("package:\n  name: foo\n")
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_include_requires_experimental() {
+        let src = "include: common.yaml\n";
+        let mut root = parse_yaml(0, src).unwrap();
+        let err = resolve_includes(src, &mut root, Some(Path::new(".")), false).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::ExperimentalOnly(_)));
+    }
+}
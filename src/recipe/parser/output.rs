@@ -4,8 +4,11 @@
 //! each mapping can have its own `package`, `source`, `build`, `requirements`,
 //! `test`, and `about` fields.
 
+use std::path::Path;
+
 use marked_yaml::types::MarkedMappingNode;
 
+use super::include::resolve_includes;
 use crate::{
     _partialerror,
     recipe::{
@@ -54,7 +57,27 @@ fn check_src_cache(root: &MarkedMappingNode) -> Result<(), ParsingError> {
 
 /// Retrieve all outputs from the recipe source (YAML)
 pub fn find_outputs_from_src(src: &str) -> Result<Vec<Node>, ParsingError> {
-    let root_node = parse_yaml(0, src)?;
+    find_outputs(src, None, false)
+}
+
+/// Retrieve all outputs from the recipe source (YAML) of a recipe file in
+/// `recipe_dir`. Recipe fragments referenced with the (experimental) `include`
+/// key are resolved relative to `recipe_dir`.
+pub fn find_outputs_from_recipe_file(
+    src: &str,
+    recipe_dir: &Path,
+    experimental: bool,
+) -> Result<Vec<Node>, ParsingError> {
+    find_outputs(src, Some(recipe_dir), experimental)
+}
+
+fn find_outputs(
+    src: &str,
+    recipe_dir: Option<&Path>,
+    experimental: bool,
+) -> Result<Vec<Node>, ParsingError> {
+    let mut root_node = parse_yaml(0, src)?;
+    resolve_includes(src, &mut root_node, recipe_dir, experimental)?;
     let root_map = root_node.as_mapping().ok_or_else(|| {
         ParsingError::from_partial(
             src,
@@ -300,4 +323,51 @@ mod tests {
         let src = std::fs::read_to_string(yaml_file).unwrap();
         assert_debug_snapshot!(find_outputs_from_src(&src).unwrap());
     }
+
+    #[test]
+    fn recipe_outputs_about_merging() {
+        let test_data_dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("test-data");
+        let yaml_file =
+            test_data_dir.join("recipes/test-parsing/recipe_outputs_about_merging.yaml");
+        let src = std::fs::read_to_string(yaml_file).unwrap();
+        let recipes = find_outputs_from_src(&src)
+            .unwrap()
+            .iter()
+            .map(|node| Recipe::from_node(node, SelectorConfig::default()).unwrap())
+            .collect::<Vec<_>>();
+
+        let license_files = |recipe: &Recipe| {
+            recipe
+                .about
+                .license_file
+                .include_globs()
+                .iter()
+                .map(|glob| glob.glob().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        // no `about` in the output: everything is inherited
+        let about = &recipes[0].about;
+        assert_eq!(about.summary.as_deref(), Some("The whole project"));
+        assert_eq!(license_files(&recipes[0]), vec!["LICENSE", "COPYING"]);
+
+        // keys of the output take precedence, all other keys are inherited
+        let about = &recipes[1].about;
+        assert_eq!(about.summary.as_deref(), Some("The library part"));
+        assert_eq!(about.description.as_deref(), Some("long description"));
+        assert_eq!(
+            about.homepage.as_ref().map(|url| url.as_str()),
+            Some("https://www.example.com/")
+        );
+        assert_eq!(license_files(&recipes[1]), vec!["LICENSE", "COPYING"]);
+
+        // lists are replaced as a whole, not concatenated
+        let about = &recipes[2].about;
+        assert_eq!(about.summary.as_deref(), Some("The CLI part"));
+        assert_eq!(license_files(&recipes[2]), vec!["cli/LICENSE"]);
+        assert_eq!(
+            about.license.as_ref().map(|l| l.to_string()),
+            Some("BSD-3-Clause".to_string())
+        );
+    }
 }
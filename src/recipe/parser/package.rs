@@ -94,7 +94,7 @@ impl TryConvertNode<Package> for RenderedMappingNode {
 pub struct OutputPackage {
     name: PackageName,
     #[serde(skip_serializing_if = "Option::is_none")]
-    version: Option<String>,
+    version: Option<VersionWithSource>,
 }
 
 impl OutputPackage {
@@ -104,8 +104,8 @@ impl OutputPackage {
     }
 
     /// Get the package version.
-    pub fn version(&self) -> Option<&str> {
-        self.version.as_deref()
+    pub fn version(&self) -> Option<&VersionWithSource> {
+        self.version.as_ref()
     }
 }
 
@@ -174,9 +174,16 @@ impl TryConvertNode<PackageName> for RenderedScalarNode {
 
 #[cfg(test)]
 mod tests {
+    use rattler_conda_types::Version;
+
+    use super::OutputPackage;
     use crate::{
         assert_miette_snapshot,
-        recipe::{jinja::SelectorConfig, Recipe},
+        recipe::{
+            custom_yaml::{RenderedNode, TryConvertNode},
+            jinja::SelectorConfig,
+            Recipe,
+        },
         variant_config::ParseErrors,
     };
 
@@ -205,4 +212,51 @@ mod tests {
         let err: ParseErrors = recipe.unwrap_err().into();
         assert_miette_snapshot!(err);
     }
+
+    #[test]
+    fn invalid_version_and_name() {
+        let raw_recipe = r#"
+        package:
+            name: test
+            version: 1.0-beta
+        "#;
+
+        let err = Recipe::from_yaml(raw_recipe, SelectorConfig::default()).unwrap_err();
+        assert!(err[0].to_string().contains("version"));
+
+        let raw_recipe = r#"
+        package:
+            name: test package
+            version: 0.1.0
+        "#;
+
+        let err = Recipe::from_yaml(raw_recipe, SelectorConfig::default()).unwrap_err();
+        assert_eq!(err.len(), 1);
+    }
+
+    #[test]
+    fn output_package_version() {
+        let parse = |yaml: &str| {
+            let node = RenderedNode::parse_yaml(0, yaml).unwrap();
+            let package = node.as_mapping().unwrap().get("package").unwrap();
+            TryConvertNode::<OutputPackage>::try_convert(package, "package")
+        };
+
+        // the version of an output is optional
+        let package = parse("package:\n  name: test\n").unwrap();
+        assert_eq!(package.name().as_normalized(), "test");
+        assert!(package.version().is_none());
+
+        // the version keeps its source, but is validated
+        let package = parse("package:\n  name: test\n  version: 01.2\n").unwrap();
+        let version = package.version().unwrap();
+        assert_eq!(version.as_str(), "01.2");
+        assert_eq!(version.version(), &"1.2".parse::<Version>().unwrap());
+
+        let err = parse("package:\n  name: test\n  version: 1.0-beta\n").unwrap_err();
+        assert_eq!(err.len(), 1);
+        assert!(err[0].to_string().contains("version"));
+
+        assert!(parse("package:\n  name: test package\n  version: 1.0\n").is_err());
+    }
 }
@@ -0,0 +1,568 @@
+//! A JSON schema of the recipe format, for editors and language servers.
+//!
+//! The keys of the sections are the keys that the parser accepts. The tests
+//! compare them with the valid options reported by the parser, and check the
+//! recipes in `test-data` against the schema, so the schema cannot silently
+//! drift from the parser when a field is added, removed or changes its type.
+use serde_json::{json, Map, Value};
+
+use super::TOP_LEVEL_KEYS;
+
+/// The JSON schema dialect of the generated schema
+const SCHEMA_DIALECT: &str = "http://json-schema.org/draft-07/schema#";
+
+/// A value that is either a plain value of the given schema or an
+/// `if / then / else` selector
+fn selectable(schema: Value) -> Value {
+    json!({
+        "anyOf": [
+            schema,
+            { "$ref": "#/definitions/selector" }
+        ]
+    })
+}
+
+/// A list whose items are strings or selectors
+fn string_list() -> Value {
+    json!({
+        "type": "array",
+        "items": selectable(json!({ "type": "string" }))
+    })
+}
+
+/// A string or a list of strings (or selectors)
+fn string_or_list() -> Value {
+    json!({ "anyOf": [{ "type": "string" }, string_list()] })
+}
+
+/// A list of globs or a mapping with `include` and `exclude` globs
+fn glob_vec() -> Value {
+    json!({
+        "anyOf": [
+            string_or_list(),
+            object(&[("include", string_or_list()), ("exclude", string_or_list())])
+        ]
+    })
+}
+
+/// A boolean (or a Jinja expression that renders to a boolean)
+fn boolean() -> Value {
+    json!({ "anyOf": [{ "type": "boolean" }, { "type": "string" }] })
+}
+
+/// An integer (or a Jinja expression that renders to an integer)
+fn integer() -> Value {
+    json!({ "anyOf": [{ "type": "integer" }, { "type": "string" }] })
+}
+
+/// A string with a fixed set of values
+fn string_enum(values: &[&str]) -> Value {
+    json!({ "type": "string", "enum": values })
+}
+
+/// A mapping that only allows the given keys
+fn object(properties: &[(&str, Value)]) -> Value {
+    let properties = properties
+        .iter()
+        .map(|(key, schema)| (key.to_string(), schema.clone()))
+        .collect::<Map<_, _>>();
+    json!({
+        "type": "object",
+        "properties": properties,
+        "additionalProperties": false
+    })
+}
+
+fn package_schema() -> Value {
+    let mut package = object(&[
+        ("name", json!({ "type": "string" })),
+        ("version", json!({ "type": ["string", "number"] })),
+    ]);
+    package["required"] = json!(["name", "version"]);
+    package
+}
+
+fn run_exports_schema() -> Value {
+    let run_exports = object(&[
+        ("noarch", string_list()),
+        ("strong", string_list()),
+        ("strong_constraints", string_list()),
+        ("weak", string_list()),
+        ("weak_constraints", string_list()),
+    ]);
+    json!({ "anyOf": [string_list(), run_exports] })
+}
+
+fn requirements_schema() -> Value {
+    object(&[
+        ("build", string_list()),
+        ("host", string_list()),
+        ("run", string_list()),
+        ("run_constraints", string_list()),
+        ("run_exports", run_exports_schema()),
+        (
+            "ignore_run_exports",
+            object(&[("by_name", string_list()), ("from_package", string_list())]),
+        ),
+    ])
+}
+
+fn python_schema() -> Value {
+    object(&[
+        ("entry_points", string_list()),
+        ("skip_pyc_compilation", glob_vec()),
+        ("use_python_app_entrypoint", boolean()),
+        ("site_packages_path", json!({ "type": "string" })),
+        ("version_independent", boolean()),
+        ("fix_record", boolean()),
+    ])
+}
+
+fn dynamic_linking_schema() -> Value {
+    let behavior = string_enum(&["ignore", "error"]);
+    object(&[
+        ("rpaths", string_list()),
+        (
+            "binary_relocation",
+            json!({ "anyOf": [{ "type": "boolean" }, glob_vec()] }),
+        ),
+        ("missing_dso_allowlist", glob_vec()),
+        ("rpath_allowlist", glob_vec()),
+        ("overdepending_behavior", behavior.clone()),
+        ("overlinking_behavior", behavior),
+        ("check_run_exports", boolean()),
+    ])
+}
+
+fn variant_schema() -> Value {
+    object(&[
+        ("use_keys", string_list()),
+        ("ignore_keys", string_list()),
+        ("down_prioritize_variant", integer()),
+    ])
+}
+
+fn prefix_detection_schema() -> Value {
+    object(&[
+        (
+            "force_file_type",
+            object(&[("text", glob_vec()), ("binary", glob_vec())]),
+        ),
+        (
+            "ignore",
+            json!({ "anyOf": [{ "type": "boolean" }, glob_vec()] }),
+        ),
+        ("ignore_binary_files", boolean()),
+    ])
+}
+
+fn post_process_schema() -> Value {
+    json!({
+        "type": "array",
+        "items": object(&[
+            ("files", glob_vec()),
+            ("regex", json!({ "type": "string" })),
+            ("replacement", json!({ "type": "string" })),
+        ])
+    })
+}
+
+fn size_budget_schema() -> Value {
+    object(&[
+        ("total", json!({ "type": ["string", "integer"] })),
+        ("per_file", json!({ "type": ["string", "integer"] })),
+        ("behavior", string_enum(&["error", "warn"])),
+    ])
+}
+
+/// A skip condition, a list of conditions or of mappings with a `condition`
+/// and a `reason`
+fn skip_schema() -> Value {
+    let condition = json!({ "type": ["boolean", "string"] });
+    let mut entry = object(&[
+        ("condition", json!({ "type": "string" })),
+        ("reason", json!({ "type": "string" })),
+    ]);
+    entry["required"] = json!(["condition"]);
+    json!({
+        "anyOf": [
+            condition,
+            {
+                "type": "array",
+                "items": selectable(json!({ "anyOf": [condition, entry] }))
+            }
+        ]
+    })
+}
+
+fn symlinks_schema() -> Value {
+    let behavior = string_enum(&["error", "warn", "ignore"]);
+    object(&[("dangling", behavior.clone()), ("outside_prefix", behavior)])
+}
+
+fn link_scripts_schema() -> Value {
+    let script = json!({ "type": "string" });
+    object(&[
+        ("pre_link", script.clone()),
+        ("post_link", script.clone()),
+        ("pre_unlink", script),
+    ])
+}
+
+fn build_schema() -> Value {
+    object(&[
+        ("number", integer()),
+        ("string", json!({ "type": "string" })),
+        ("skip", skip_schema()),
+        ("script", json!({})),
+        (
+            "env",
+            json!({ "anyOf": [{ "type": "object" }, { "type": "array" }] }),
+        ),
+        ("noarch", string_enum(&["python", "generic"])),
+        ("python", python_schema()),
+        ("dynamic_linking", dynamic_linking_schema()),
+        ("always_copy_files", glob_vec()),
+        ("always_include_files", glob_vec()),
+        ("merge_build_and_host_envs", boolean()),
+        ("variant", variant_schema()),
+        ("prefix_detection", prefix_detection_schema()),
+        ("post_process", post_process_schema()),
+        ("files", glob_vec()),
+        ("deduplicate_files", boolean()),
+        ("size_budget", size_budget_schema()),
+        ("symlinks", symlinks_schema()),
+        ("link_scripts", link_scripts_schema()),
+        ("split_debug", boolean()),
+        ("channel_priority", string_enum(&["strict", "disabled"])),
+        ("include_recipe", string_enum(&["full", "rendered", "none"])),
+        ("package_format", string_enum(&["conda", "tar-bz2"])),
+        (
+            "secret_scan",
+            object(&[
+                ("behavior", string_enum(&["error", "warn", "ignore"])),
+                ("allow", glob_vec()),
+            ]),
+        ),
+        (
+            "compression",
+            json!({ "anyOf": [string_enum(&["auto", "fast", "default", "max"]), integer()] }),
+        ),
+    ])
+}
+
+fn about_schema() -> Value {
+    let string = json!({ "type": "string" });
+    object(&[
+        ("homepage", string.clone()),
+        ("repository", string.clone()),
+        ("documentation", string.clone()),
+        ("license", string.clone()),
+        ("license_family", string.clone()),
+        ("license_file", glob_vec()),
+        ("license_file_layout", string_enum(&["preserve", "flatten"])),
+        ("license_from_dependencies", string_list()),
+        ("license_url", string.clone()),
+        ("summary", string.clone()),
+        ("description", string.clone()),
+        ("prelink_message", string),
+    ])
+}
+
+fn cache_schema() -> Value {
+    object(&[
+        ("source", json!({ "type": "array" })),
+        ("build", json!({ "type": "object" })),
+        ("requirements", requirements_schema()),
+    ])
+}
+
+/// The schema of a top-level section of a recipe, with the description that
+/// is shown by editors
+fn section_schema(key: &str) -> Value {
+    let (mut schema, description) = match key {
+        "schema_version" => (
+            json!({ "type": "integer" }),
+            "The version of the recipe format",
+        ),
+        "context" => (
+            json!({ "type": "object" }),
+            "Variables that can be used in Jinja expressions in the recipe",
+        ),
+        "package" => (package_schema(), "The name and version of the package"),
+        "recipe" => (
+            json!({ "type": "object" }),
+            "The name and version of a multi-output recipe",
+        ),
+        "cache" => (
+            cache_schema(),
+            "A build whose files are reused by all outputs (experimental)",
+        ),
+        "source" => (
+            json!({ "type": ["array", "object"] }),
+            "The sources (URLs, git repositories or local paths) of the package",
+        ),
+        "build" => (build_schema(), "How the package is built"),
+        "requirements" => (requirements_schema(), "The dependencies of the package"),
+        "tests" => (
+            json!({ "type": "array" }),
+            "The tests that run after the package is built",
+        ),
+        "about" => (
+            about_schema(),
+            "Metadata about the package, e.g. license and homepage",
+        ),
+        "extra" => (
+            json!({ "type": "object" }),
+            "Extra metadata that is stored in the package as-is",
+        ),
+        "outputs" => (
+            json!({ "type": "array" }),
+            "The outputs of a multi-output recipe",
+        ),
+        _ => unreachable!("`{key}` is not a top-level key of a recipe"),
+    };
+    schema["description"] = json!(description);
+    schema
+}
+
+/// The JSON schema of a recipe file (`recipe.yaml`). Sources and tests are
+/// only checked to be lists, because their shape depends on the kind of source
+/// or test.
+pub fn recipe_schema() -> Value {
+    let sections = TOP_LEVEL_KEYS
+        .iter()
+        .map(|key| (*key, section_schema(key)))
+        .collect::<Vec<_>>();
+    let mut recipe = object(&sections);
+    recipe["$schema"] = json!(SCHEMA_DIALECT);
+    recipe["title"] = json!("rattler-build recipe");
+    recipe["definitions"] = json!({
+        "selector": {
+            "type": "object",
+            "properties": {
+                "if": { "type": "string" },
+                "then": {},
+                "else": {}
+            },
+            "required": ["if", "then"],
+            "additionalProperties": false
+        }
+    });
+    recipe
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+    use crate::recipe::{
+        custom_yaml::{RenderedNode, TryConvertNode},
+        error::PartialParsingError,
+        parser::{About, Build, Requirements},
+    };
+
+    /// The keys of a section of the schema
+    fn schema_keys(section: &Value) -> BTreeSet<String> {
+        section["properties"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect()
+    }
+
+    /// The keys that the parser accepts for a section, taken from the error
+    /// for an invalid key
+    fn parser_keys<T>(yaml: &str) -> BTreeSet<String>
+    where
+        RenderedNode: TryConvertNode<T>,
+    {
+        let node = RenderedNode::parse_yaml(0, yaml).unwrap();
+        let errors: Vec<PartialParsingError> = match node.try_convert("section") {
+            Ok(_) => panic!("expected an invalid key error"),
+            Err(errors) => errors,
+        };
+        errors[0]
+            .options
+            .iter()
+            .map(|key| key.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_schema_matches_parser() {
+        let schema = recipe_schema();
+        let properties = &schema["properties"];
+        let invalid = "__invalid__: 1";
+
+        assert_eq!(
+            schema_keys(&properties["build"]),
+            parser_keys::<Build>(invalid)
+        );
+        assert_eq!(
+            schema_keys(&properties["about"]),
+            parser_keys::<About>(invalid)
+        );
+        assert_eq!(
+            schema_keys(&properties["requirements"]),
+            parser_keys::<Requirements>(invalid)
+        );
+
+        let build = &properties["build"]["properties"];
+        assert_eq!(
+            schema_keys(&build["python"]),
+            parser_keys::<Build>(&format!("python:\n  {invalid}"))
+        );
+        assert_eq!(
+            schema_keys(&build["dynamic_linking"]),
+            parser_keys::<Build>(&format!("dynamic_linking:\n  {invalid}"))
+        );
+        assert_eq!(
+            schema_keys(&build["variant"]),
+            parser_keys::<Build>(&format!("variant:\n  {invalid}"))
+        );
+        assert_eq!(
+            schema_keys(&build["prefix_detection"]),
+            parser_keys::<Build>(&format!("prefix_detection:\n  {invalid}"))
+        );
+        assert_eq!(
+            schema_keys(&build["size_budget"]),
+            parser_keys::<Build>(&format!("size_budget:\n  {invalid}"))
+        );
+        assert_eq!(
+            schema_keys(&build["symlinks"]),
+            parser_keys::<Build>(&format!("symlinks:\n  {invalid}"))
+        );
+        assert_eq!(
+            schema_keys(&build["link_scripts"]),
+            parser_keys::<Build>(&format!("link_scripts:\n  {invalid}"))
+        );
+        let skip_entry = &build["skip"]["anyOf"][1]["items"]["anyOf"][0]["anyOf"][1];
+        assert_eq!(
+            schema_keys(skip_entry),
+            parser_keys::<Build>(&format!("skip:\n  - {invalid}"))
+        );
+    }
+
+    /// Check a value against the subset of JSON schema that the recipe schema
+    /// uses, and return the paths of the values that do not match
+    fn validate(schema: &Value, value: &Value, root: &Value, path: &str) -> Vec<String> {
+        if let Some(reference) = schema["$ref"].as_str() {
+            let name = reference.trim_start_matches("#/definitions/");
+            return validate(&root["definitions"][name], value, root, path);
+        }
+        if let Some(any_of) = schema["anyOf"].as_array() {
+            let matches = any_of
+                .iter()
+                .any(|schema| validate(schema, value, root, path).is_empty());
+            return if matches {
+                vec![]
+            } else {
+                vec![format!(
+                    "{path}: {value} matches none of the allowed schemas"
+                )]
+            };
+        }
+
+        let types = match &schema["type"] {
+            Value::String(kind) => vec![kind.as_str()],
+            Value::Array(kinds) => kinds.iter().filter_map(Value::as_str).collect(),
+            _ => vec![],
+        };
+        let type_matches = |kind: &str| match kind {
+            "string" => value.is_string(),
+            "boolean" => value.is_boolean(),
+            "integer" => value.is_i64() || value.is_u64(),
+            "number" => value.is_number(),
+            "array" => value.is_array(),
+            "object" => value.is_object(),
+            _ => false,
+        };
+        if !types.is_empty() && !types.iter().any(|kind| type_matches(kind)) {
+            return vec![format!("{path}: expected {types:?}, found {value}")];
+        }
+        if let Some(values) = schema["enum"].as_array() {
+            if !values.contains(value) {
+                return vec![format!("{path}: {value} is not one of {values:?}")];
+            }
+        }
+
+        let mut errors = Vec::new();
+        if let (Some(items), Some(array)) = (schema.get("items"), value.as_array()) {
+            for (index, item) in array.iter().enumerate() {
+                errors.extend(validate(items, item, root, &format!("{path}[{index}]")));
+            }
+        }
+        if let (Some(properties), Some(object)) =
+            (schema["properties"].as_object(), value.as_object())
+        {
+            for (key, value) in object {
+                let path = format!("{path}.{key}");
+                match properties.get(key) {
+                    Some(schema) => errors.extend(validate(schema, value, root, &path)),
+                    None if schema["additionalProperties"] == false => {
+                        errors.push(format!("{path}: unknown key"))
+                    }
+                    None => {}
+                }
+            }
+            for key in schema["required"].as_array().into_iter().flatten() {
+                if !object.contains_key(key.as_str().unwrap_or_default()) {
+                    errors.push(format!("{path}: missing key {key}"));
+                }
+            }
+        }
+        errors
+    }
+
+    #[test]
+    fn test_schema_validates_test_recipes() {
+        let schema = recipe_schema();
+        let recipes = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("test-data/recipes");
+
+        let mut checked = 0;
+        for entry in walkdir::WalkDir::new(recipes) {
+            let path = entry.unwrap().into_path();
+            if path.file_name() != Some("recipe.yaml".as_ref()) {
+                continue;
+            }
+            let recipe: Value = serde_yaml::from_str(&fs_err::read_to_string(&path).unwrap())
+                .unwrap_or_else(|err| panic!("{} is not valid YAML: {err}", path.display()));
+            let errors = validate(&schema, &recipe, &schema, "recipe");
+            assert!(errors.is_empty(), "{}: {errors:#?}", path.display());
+            checked += 1;
+        }
+        assert!(checked > 0);
+
+        // the value types are checked, not only the keys
+        let invalid = json!({
+            "package": { "name": "foo", "version": "1.0" },
+            "about": { "license_from_dependencies": true },
+            "build": { "noarch": "rust" }
+        });
+        assert_eq!(validate(&schema, &invalid, &schema, "recipe").len(), 2);
+    }
+
+    #[test]
+    fn test_top_level_sections() {
+        let schema = recipe_schema();
+        assert_eq!(
+            schema_keys(&schema),
+            TOP_LEVEL_KEYS.iter().map(|key| key.to_string()).collect()
+        );
+        for key in TOP_LEVEL_KEYS {
+            assert!(schema["properties"][key]["description"].is_string());
+        }
+    }
+
+    #[test]
+    fn test_schema_is_valid_json() {
+        let schema = recipe_schema();
+        assert_eq!(schema["$schema"], SCHEMA_DIALECT);
+        let serialized = serde_json::to_string(&schema).unwrap();
+        let parsed: Value = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(parsed, schema);
+    }
+}
@@ -5,16 +5,20 @@ use crate::{
         TryConvertNode,
     },
     recipe::error::{ErrorKind, PartialParsingError},
+    recipe::parser::FileSize,
 };
 use indexmap::IndexMap;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
-use std::{borrow::Cow, path::PathBuf};
+use std::{borrow::Cow, fmt, path::PathBuf, str::FromStr, time::Duration};
 
 /// Defines the script to run to build the package.
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct Script {
     /// The interpreter to use for the script.
     pub interpreter: Option<String>,
+    /// Arguments to pass to the interpreter. If empty, the default arguments of the
+    /// interpreter are used.
+    pub interpreter_args: Vec<String>,
     /// Environment variables to set in the build environment.
     pub env: IndexMap<String, String>,
     /// Environment variables to leak into the build environment from the host system that
@@ -26,6 +30,13 @@ pub struct Script {
 
     /// The current working directory for the script.
     pub cwd: Option<PathBuf>,
+
+    /// Render the script file with Jinja and use the variables it references
+    /// for the variant matrix and hash (`track_variables: true`).
+    pub track_variables: bool,
+
+    /// The timeout and the resource limits of the script.
+    pub limits: ScriptLimits,
 }
 
 impl Serialize for Script {
@@ -49,6 +60,8 @@ impl Serialize for Script {
             Object {
                 #[serde(skip_serializing_if = "Option::is_none")]
                 interpreter: Option<&'a String>,
+                #[serde(skip_serializing_if = "Vec::is_empty")]
+                interpreter_args: &'a Vec<String>,
                 #[serde(skip_serializing_if = "IndexMap::is_empty")]
                 env: &'a IndexMap<String, String>,
                 #[serde(skip_serializing_if = "Vec::is_empty")]
@@ -57,13 +70,24 @@ impl Serialize for Script {
                 content: Option<RawScriptContent<'a>>,
                 #[serde(skip_serializing_if = "Option::is_none")]
                 cwd: Option<&'a PathBuf>,
+                #[serde(skip_serializing_if = "std::ops::Not::not")]
+                track_variables: bool,
+                #[serde(skip_serializing_if = "Option::is_none")]
+                timeout: Option<&'a Timeout>,
+                #[serde(skip_serializing_if = "Option::is_none")]
+                memory_limit: Option<&'a FileSize>,
+                #[serde(skip_serializing_if = "Option::is_none")]
+                cpu_limit: Option<&'a CpuLimit>,
             },
         }
 
         let only_content = self.interpreter.is_none()
+            && self.interpreter_args.is_empty()
             && self.env.is_empty()
             && self.secrets.is_empty()
-            && self.cwd.is_none();
+            && self.cwd.is_none()
+            && !self.track_variables
+            && self.limits.is_empty();
 
         let raw_script = match &self.content {
             ScriptContent::CommandOrPath(content) if only_content => {
@@ -72,9 +96,14 @@ impl Serialize for Script {
             ScriptContent::Commands(content) if only_content => RawScript::Commands(content),
             _ => RawScript::Object {
                 interpreter: self.interpreter.as_ref(),
+                interpreter_args: &self.interpreter_args,
                 env: &self.env,
                 secrets: &self.secrets,
                 cwd: self.cwd.as_ref(),
+                track_variables: self.track_variables,
+                timeout: self.limits.timeout.as_ref(),
+                memory_limit: self.limits.memory.as_ref(),
+                cpu_limit: self.limits.cpus.as_ref(),
                 content: match &self.content {
                     ScriptContent::Command(content) => Some(RawScriptContent::Command { content }),
                     ScriptContent::Commands(content) => {
@@ -115,6 +144,8 @@ impl<'de> Deserialize<'de> for Script {
                 #[serde(default)]
                 interpreter: Option<String>,
                 #[serde(default)]
+                interpreter_args: Vec<String>,
+                #[serde(default)]
                 env: IndexMap<String, String>,
                 #[serde(default)]
                 secrets: Vec<String>,
@@ -122,6 +153,14 @@ impl<'de> Deserialize<'de> for Script {
                 content: Option<RawScriptContent>,
                 #[serde(default)]
                 cwd: Option<PathBuf>,
+                #[serde(default)]
+                track_variables: bool,
+                #[serde(default)]
+                timeout: Option<Timeout>,
+                #[serde(default)]
+                memory_limit: Option<FileSize>,
+                #[serde(default)]
+                cpu_limit: Option<CpuLimit>,
             },
         }
 
@@ -131,15 +170,27 @@ impl<'de> Deserialize<'de> for Script {
             RawScript::Commands(commands) => ScriptContent::Commands(commands).into(),
             RawScript::Object {
                 interpreter,
+                interpreter_args,
                 env,
                 secrets,
                 content,
                 cwd,
+                track_variables,
+                timeout,
+                memory_limit,
+                cpu_limit,
             } => Self {
                 interpreter,
+                interpreter_args,
                 env,
                 secrets,
                 cwd: cwd.map(PathBuf::from),
+                track_variables,
+                limits: ScriptLimits {
+                    timeout,
+                    memory: memory_limit,
+                    cpus: cpu_limit,
+                },
                 content: match content {
                     Some(RawScriptContent::Command { content }) => ScriptContent::Command(content),
                     Some(RawScriptContent::Commands { content }) => {
@@ -159,6 +210,11 @@ impl Script {
         self.interpreter.as_deref()
     }
 
+    /// Returns the arguments to pass to the interpreter
+    pub fn interpreter_args(&self) -> &[String] {
+        self.interpreter_args.as_slice()
+    }
+
     /// Returns the script contents
     pub fn contents(&self) -> &ScriptContent {
         &self.content
@@ -180,13 +236,21 @@ impl Script {
         self.secrets.as_slice()
     }
 
+    /// Returns the timeout and the resource limits of the script
+    pub fn limits(&self) -> &ScriptLimits {
+        &self.limits
+    }
+
     /// Returns true if the script references the default build script and has no additional
     /// configuration.
     pub fn is_default(&self) -> bool {
         self.content.is_default()
             && self.interpreter.is_none()
+            && self.interpreter_args.is_empty()
             && self.env.is_empty()
             && self.secrets.is_empty()
+            && !self.track_variables
+            && self.limits.is_empty()
     }
 }
 
@@ -194,10 +258,13 @@ impl From<ScriptContent> for Script {
     fn from(value: ScriptContent) -> Self {
         Self {
             interpreter: None,
+            interpreter_args: Default::default(),
             env: Default::default(),
             secrets: Default::default(),
             content: value,
             cwd: None,
+            track_variables: false,
+            limits: ScriptLimits::default(),
         }
     }
 }
@@ -245,7 +312,16 @@ impl TryConvertNode<Script> for RenderedMappingNode {
         let invalid = self.keys().find(|k| {
             !matches!(
                 k.as_str(),
-                "env" | "secrets" | "interpreter" | "content" | "file"
+                "env"
+                    | "secrets"
+                    | "interpreter"
+                    | "interpreter_args"
+                    | "content"
+                    | "file"
+                    | "track_variables"
+                    | "timeout"
+                    | "memory_limit"
+                    | "cpu_limit"
             )
         });
 
@@ -253,7 +329,7 @@ impl TryConvertNode<Script> for RenderedMappingNode {
             return Err(vec![_partialerror!(
                 *invalid.span(),
                 ErrorKind::InvalidField(invalid.to_string().into()),
-                help = format!("valid keys for {name} are `env`, `secrets`, `interpreter`, `content` or `file`")
+                help = format!("valid keys for {name} are `env`, `secrets`, `interpreter`, `interpreter_args`, `content`, `file`, `track_variables`, `timeout`, `memory_limit` or `cpu_limit`")
             )]);
         }
 
@@ -275,6 +351,33 @@ impl TryConvertNode<Script> for RenderedMappingNode {
             .transpose()?
             .unwrap_or_default();
 
+        let interpreter_args = self
+            .get("interpreter_args")
+            .map(|node| node.try_convert("interpreter_args"))
+            .transpose()?
+            .unwrap_or_default();
+
+        let track_variables = self
+            .get("track_variables")
+            .map(|node| node.try_convert("track_variables"))
+            .transpose()?
+            .unwrap_or_default();
+
+        let limits = ScriptLimits {
+            timeout: self
+                .get("timeout")
+                .map(|node| node.try_convert("timeout"))
+                .transpose()?,
+            memory: self
+                .get("memory_limit")
+                .map(|node| node.try_convert("memory_limit"))
+                .transpose()?,
+            cpus: self
+                .get("cpu_limit")
+                .map(|node| node.try_convert("cpu_limit"))
+                .transpose()?,
+        };
+
         let file = self.get("file");
 
         let content = self.get("content");
@@ -307,8 +410,11 @@ impl TryConvertNode<Script> for RenderedMappingNode {
             env,
             secrets,
             interpreter,
+            interpreter_args,
             content,
             cwd: None,
+            track_variables,
+            limits,
         })
     }
 }
@@ -339,3 +445,235 @@ impl ScriptContent {
         matches!(self, Self::Default)
     }
 }
+
+/// The timeout and the resource limits of a script. The script is killed with
+/// an error when it runs longer than the timeout or uses more memory than
+/// allowed. Memory and CPU limits are enforced with cgroups on Linux and job
+/// objects on Windows.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ScriptLimits {
+    /// The maximum time the script may run (`timeout`, e.g. `2h` or `1h30m`)
+    pub timeout: Option<Timeout>,
+    /// The maximum memory the script may use (`memory_limit`, e.g. `8GiB`)
+    pub memory: Option<FileSize>,
+    /// The maximum number of CPUs the script may use (`cpu_limit`, e.g. `2`
+    /// or `0.5`)
+    pub cpus: Option<CpuLimit>,
+}
+
+impl ScriptLimits {
+    /// Returns true if no limit is set
+    pub fn is_empty(&self) -> bool {
+        self.timeout.is_none() && self.memory.is_none() && self.cpus.is_none()
+    }
+}
+
+/// A duration that is parsed from a string of numbers with units (e.g. `2h`,
+/// `1h30m` or `90s`). A number without a unit is a number of seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Timeout(pub Duration);
+
+impl FromStr for Timeout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("`{s}` is not a valid duration (e.g. `2h` or `1h30m`)");
+
+        let s = s.trim();
+        if let Ok(seconds) = s.parse::<u64>() {
+            return Ok(Timeout(Duration::from_secs(seconds)));
+        }
+
+        let mut seconds = 0;
+        let mut rest = s;
+        while !rest.is_empty() {
+            let split = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .ok_or_else(invalid)?;
+            let (number, tail) = rest.split_at(split);
+            let number: u64 = number.parse().map_err(|_| invalid())?;
+            let split = tail
+                .find(|c: char| c.is_ascii_digit())
+                .unwrap_or(tail.len());
+            let (unit, tail) = tail.split_at(split);
+
+            let multiplier = match unit.trim() {
+                "s" => 1,
+                "m" => 60,
+                "h" => 60 * 60,
+                "d" => 24 * 60 * 60,
+                _ => return Err(invalid()),
+            };
+            seconds += number * multiplier;
+            rest = tail.trim_start();
+        }
+
+        if seconds == 0 {
+            return Err(format!("the duration `{s}` must be greater than zero"));
+        }
+
+        Ok(Timeout(Duration::from_secs(seconds)))
+    }
+}
+
+impl fmt::Display for Timeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut seconds = self.0.as_secs();
+        if seconds == 0 {
+            return write!(f, "0s");
+        }
+        for (unit, size) in [("d", 24 * 60 * 60), ("h", 60 * 60), ("m", 60), ("s", 1)] {
+            if seconds >= size {
+                write!(f, "{}{}", seconds / size, unit)?;
+                seconds %= size;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<String> for Timeout {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Timeout> for String {
+    fn from(value: Timeout) -> Self {
+        value.to_string()
+    }
+}
+
+/// A number of CPUs, stored in thousandths of a CPU (e.g. `2` or `0.5`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "f64", into = "f64")]
+pub struct CpuLimit(pub u32);
+
+impl CpuLimit {
+    /// The number of CPUs
+    pub fn cpus(&self) -> f64 {
+        self.0 as f64 / 1000.0
+    }
+}
+
+impl TryFrom<f64> for CpuLimit {
+    type Error = String;
+
+    fn try_from(value: f64) -> Result<Self, Self::Error> {
+        let millis = (value * 1000.0).round();
+        if !millis.is_finite() || millis < 1.0 || millis > u32::MAX as f64 {
+            return Err(format!(
+                "`{value}` is not a valid number of CPUs (e.g. `2` or `0.5`)"
+            ));
+        }
+        Ok(CpuLimit(millis as u32))
+    }
+}
+
+impl From<CpuLimit> for f64 {
+    fn from(value: CpuLimit) -> Self {
+        value.cpus()
+    }
+}
+
+impl FromStr for CpuLimit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let cpus: f64 = s
+            .trim()
+            .parse()
+            .map_err(|_| format!("`{s}` is not a valid number of CPUs (e.g. `2` or `0.5`)"))?;
+        cpus.try_into()
+    }
+}
+
+impl fmt::Display for CpuLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.cpus())
+    }
+}
+
+impl TryConvertNode<Timeout> for RenderedNode {
+    fn try_convert(&self, name: &str) -> Result<Timeout, Vec<PartialParsingError>> {
+        self.as_scalar()
+            .ok_or_else(|| vec![_partialerror!(*self.span(), ErrorKind::ExpectedScalar)])
+            .and_then(|s| s.try_convert(name))
+    }
+}
+
+impl TryConvertNode<Timeout> for RenderedScalarNode {
+    fn try_convert(&self, name: &str) -> Result<Timeout, Vec<PartialParsingError>> {
+        self.as_str().parse().map_err(|err: String| {
+            vec![_partialerror!(
+                *self.span(),
+                ErrorKind::InvalidValue((name.to_string(), err.into())),
+            )]
+        })
+    }
+}
+
+impl TryConvertNode<CpuLimit> for RenderedNode {
+    fn try_convert(&self, name: &str) -> Result<CpuLimit, Vec<PartialParsingError>> {
+        self.as_scalar()
+            .ok_or_else(|| vec![_partialerror!(*self.span(), ErrorKind::ExpectedScalar)])
+            .and_then(|s| s.try_convert(name))
+    }
+}
+
+impl TryConvertNode<CpuLimit> for RenderedScalarNode {
+    fn try_convert(&self, name: &str) -> Result<CpuLimit, Vec<PartialParsingError>> {
+        self.as_str().parse().map_err(|err: String| {
+            vec![_partialerror!(
+                *self.span(),
+                ErrorKind::InvalidValue((name.to_string(), err.into())),
+            )]
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_timeout() {
+        assert_eq!("2h".parse(), Ok(Timeout(Duration::from_secs(2 * 60 * 60))));
+        assert_eq!("1h30m".parse(), Ok(Timeout(Duration::from_secs(90 * 60))));
+        assert_eq!("90".parse(), Ok(Timeout(Duration::from_secs(90))));
+        assert_eq!("1d 2s".parse(), Ok(Timeout(Duration::from_secs(86402))));
+        assert!("2 hours".parse::<Timeout>().is_err());
+        assert!("h".parse::<Timeout>().is_err());
+        assert!("0s".parse::<Timeout>().is_err());
+
+        assert_eq!(Timeout(Duration::from_secs(90 * 60)).to_string(), "1h30m");
+        assert_eq!(Timeout(Duration::from_secs(7200)).to_string(), "2h");
+    }
+
+    #[test]
+    fn parse_cpu_limit() {
+        assert_eq!("2".parse(), Ok(CpuLimit(2000)));
+        assert_eq!("0.5".parse(), Ok(CpuLimit(500)));
+        assert!("0".parse::<CpuLimit>().is_err());
+        assert!("two".parse::<CpuLimit>().is_err());
+        assert_eq!(CpuLimit(1500).to_string(), "1.5");
+    }
+
+    #[test]
+    fn script_limits_roundtrip() {
+        let yaml = "content: make\ntimeout: 1h30m\nmemory_limit: 1024\ncpu_limit: 0.5\n";
+        let script: Script = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(
+            script.limits,
+            ScriptLimits {
+                timeout: Some(Timeout(Duration::from_secs(90 * 60))),
+                memory: Some(FileSize(1024)),
+                cpus: Some(CpuLimit(500)),
+            }
+        );
+        assert_eq!(serde_yaml::to_string(&script).unwrap(), yaml);
+    }
+}
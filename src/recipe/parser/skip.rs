@@ -1,27 +1,101 @@
 use marked_yaml::Span;
+use serde::{Deserialize, Serialize};
 
 use crate::{
     _partialerror,
     recipe::{
-        custom_yaml::{HasSpan, RenderedNode, RenderedSequenceNode, TryConvertNode},
+        custom_yaml::{
+            HasSpan, RenderedMappingNode, RenderedNode, RenderedSequenceNode, TryConvertNode,
+        },
         error::{ErrorKind, PartialParsingError},
         Jinja,
     },
 };
 
+/// Why an output is skipped
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SkipReason {
+    /// The skip condition that is true (e.g. `win`)
+    pub condition: String,
+    /// The explanation given in the recipe
+    /// (`- condition: win`, `reason: "not supported upstream"`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl std::fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.reason {
+            Some(reason) => write!(f, "{} (`{}`)", reason, self.condition),
+            None => write!(f, "skip condition `{}` is true", self.condition),
+        }
+    }
+}
+
+/// A single skip condition, optionally with an explanation
+#[derive(Debug, Clone)]
+struct SkipCondition {
+    /// The jinja expression that is evaluated
+    condition: String,
+    /// The explanation given in the recipe
+    reason: Option<String>,
+    span: Span,
+}
+
 #[derive(Default, Debug, Clone)]
-pub struct Skip(Vec<(String, Span)>, Option<bool>);
+pub struct Skip {
+    /// The skip conditions (jinja expressions) with their spans
+    conditions: Vec<SkipCondition>,
+    /// The result of the evaluation, `None` if not yet evaluated
+    evaluated: Option<Option<SkipReason>>,
+}
 
-impl TryConvertNode<Vec<(String, Span)>> for RenderedSequenceNode {
-    fn try_convert(&self, name: &str) -> Result<Vec<(String, Span)>, Vec<PartialParsingError>> {
+impl TryConvertNode<SkipCondition> for RenderedMappingNode {
+    fn try_convert(&self, name: &str) -> Result<SkipCondition, Vec<PartialParsingError>> {
+        let mut condition = None;
+        let mut reason = None;
+        for (key, value) in self.iter() {
+            match key.as_str() {
+                "condition" => condition = Some(value.try_convert(name)?),
+                "reason" => reason = Some(value.try_convert(name)?),
+                invalid => {
+                    return Err(vec![_partialerror!(
+                        *key.span(),
+                        ErrorKind::InvalidField(invalid.to_string().into()),
+                        help = "valid fields for a skip entry are `condition` and `reason`"
+                    )
+                    .with_suggestion(invalid, &["condition", "reason"])])
+                }
+            }
+        }
+
+        let Some(condition) = condition else {
+            return Err(vec![_partialerror!(
+                *self.span(),
+                ErrorKind::MissingField("condition".into()),
+            )]);
+        };
+
+        Ok(SkipCondition {
+            condition,
+            reason,
+            span: *self.span(),
+        })
+    }
+}
+
+impl TryConvertNode<Vec<SkipCondition>> for RenderedSequenceNode {
+    fn try_convert(&self, name: &str) -> Result<Vec<SkipCondition>, Vec<PartialParsingError>> {
         let mut conditions = vec![];
 
         for node in self.iter() {
             match node {
-                RenderedNode::Scalar(scalar) => {
-                    let s: String = scalar.try_convert(name)?;
-                    conditions.push((s, *node.span()))
-                }
+                RenderedNode::Scalar(scalar) => conditions.push(SkipCondition {
+                    condition: scalar.try_convert(name)?,
+                    reason: None,
+                    span: *node.span(),
+                }),
+                RenderedNode::Mapping(mapping) => conditions.push(mapping.try_convert(name)?),
                 _ => {
                     return Err(vec![_partialerror!(
                         *node.span(),
@@ -37,7 +111,11 @@ impl TryConvertNode<Vec<(String, Span)>> for RenderedSequenceNode {
 impl TryConvertNode<Skip> for RenderedNode {
     fn try_convert(&self, name: &str) -> Result<Skip, Vec<PartialParsingError>> {
         let conditions = match self {
-            RenderedNode::Scalar(scalar) => vec![(scalar.try_convert(name)?, *self.span())],
+            RenderedNode::Scalar(scalar) => vec![SkipCondition {
+                condition: scalar.try_convert(name)?,
+                reason: None,
+                span: *self.span(),
+            }],
             RenderedNode::Sequence(sequence) => sequence.try_convert(name)?,
             RenderedNode::Mapping(_) => {
                 return Err(vec![_partialerror!(
@@ -48,35 +126,81 @@ impl TryConvertNode<Skip> for RenderedNode {
             RenderedNode::Null(_) => vec![],
         };
 
-        Ok(Skip(conditions, None))
+        Ok(Skip {
+            conditions,
+            evaluated: None,
+        })
+    }
+}
+
+impl Serialize for Skip {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        self.reason().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Skip {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let reason = Option::<SkipReason>::deserialize(deserializer)?;
+        Ok(Skip {
+            conditions: Vec::new(),
+            evaluated: Some(reason),
+        })
     }
 }
 
 impl Skip {
     pub fn is_empty(&self) -> bool {
-        self.0.is_empty()
+        self.conditions.is_empty()
+    }
+
+    /// Returns true if the output is not skipped (nothing to serialize).
+    pub fn is_not_skipped(&self) -> bool {
+        self.reason().is_none()
     }
 
     pub fn with_eval(self, jinja: &Jinja) -> Result<Self, Vec<PartialParsingError>> {
-        for condition in &self.0 {
-            match jinja.eval(&condition.0) {
+        for SkipCondition {
+            condition,
+            reason,
+            span,
+        } in &self.conditions
+        {
+            match jinja.eval(condition) {
                 Ok(res) => {
                     if res.is_true() {
-                        return Ok(Skip(self.0, Some(true)));
+                        let reason = SkipReason {
+                            condition: condition.clone(),
+                            reason: reason.clone(),
+                        };
+                        return Ok(Skip {
+                            conditions: self.conditions,
+                            evaluated: Some(Some(reason)),
+                        });
                     }
                 }
-                Err(e) => {
-                    return Err(vec![_partialerror!(
-                        condition.1,
-                        ErrorKind::JinjaRendering(e),
-                    )])
-                }
+                Err(e) => return Err(vec![_partialerror!(*span, ErrorKind::JinjaRendering(e),)]),
             }
         }
-        Ok(Skip(self.0, Some(false)))
+        Ok(Skip {
+            conditions: self.conditions,
+            evaluated: Some(None),
+        })
     }
 
     pub fn eval(&self) -> bool {
-        self.1.unwrap_or(true)
+        self.evaluated.as_ref().map_or(true, Option::is_some)
+    }
+
+    /// Why the output is skipped, if the conditions were evaluated and one is
+    /// true.
+    pub fn reason(&self) -> Option<&SkipReason> {
+        self.evaluated.as_ref().and_then(Option::as_ref)
     }
 }
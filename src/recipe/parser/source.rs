@@ -4,7 +4,11 @@ use rattler_digest::{serde::SerializableHash, Md5, Md5Hash, Sha256, Sha256Hash};
 use serde::{Deserialize, Serialize};
 use serde_with::{formats::PreferOne, serde_as, OneOrMany};
 use std::fmt::Display;
-use std::{fmt, path::PathBuf, str::FromStr};
+use std::{
+    fmt,
+    path::{Component, PathBuf},
+    str::FromStr,
+};
 use url::Url;
 
 use crate::{
@@ -15,9 +19,10 @@ use crate::{
         },
         error::{ErrorKind, PartialParsingError},
     },
+    validate_keys,
 };
 
-use super::FlattenErrors;
+use super::{FlattenErrors, GlobVec};
 
 /// Source information.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -29,6 +34,10 @@ pub enum Source {
     Url(UrlSource),
     /// Path source pointing to a local file or directory to retrieve the source from
     Path(PathSource),
+    /// Mercurial source pointing to a Mercurial repository to retrieve the source from
+    Hg(HgSource),
+    /// Subversion source pointing to a Subversion repository to retrieve the source from
+    Svn(SvnSource),
 }
 
 impl Source {
@@ -38,6 +47,8 @@ impl Source {
             Self::Git(git) => git.patches(),
             Self::Url(url) => url.patches(),
             Self::Path(path) => path.patches(),
+            Self::Hg(hg) => hg.patches(),
+            Self::Svn(svn) => svn.patches(),
         }
     }
 
@@ -47,10 +58,82 @@ impl Source {
             Self::Git(git) => git.target_directory(),
             Self::Url(url) => url.target_directory(),
             Self::Path(path) => path.target_directory(),
+            Self::Hg(hg) => hg.target_directory(),
+            Self::Svn(svn) => svn.target_directory(),
+        }
+    }
+
+    /// Get the verification of the vendored files.
+    pub fn verify(&self) -> Option<&SourceVerify> {
+        match self {
+            Self::Git(git) => git.verify.as_ref(),
+            Self::Url(url) => url.verify.as_ref(),
+            Self::Path(path) => path.verify.as_ref(),
+            Self::Hg(hg) => hg.verify.as_ref(),
+            Self::Svn(svn) => svn.verify.as_ref(),
         }
     }
 }
 
+/// Verify vendored files of a source (e.g. `vendor/**` of `cargo vendor` or
+/// `go mod vendor`) against a manifest of their hashes. The manifest is
+/// written on the first build and the build fails if the files change later.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceVerify {
+    /// The manifest file, relative to the recipe directory
+    pub manifest: PathBuf,
+    /// The files to verify, relative to the source directory
+    pub files: GlobVec,
+}
+
+impl TryConvertNode<SourceVerify> for RenderedNode {
+    fn try_convert(&self, name: &str) -> Result<SourceVerify, Vec<PartialParsingError>> {
+        self.as_mapping()
+            .ok_or_else(|| vec![_partialerror!(*self.span(), ErrorKind::ExpectedMapping)])
+            .and_then(|m| m.try_convert(name))
+    }
+}
+
+impl TryConvertNode<SourceVerify> for RenderedMappingNode {
+    fn try_convert(&self, _name: &str) -> Result<SourceVerify, Vec<PartialParsingError>> {
+        let mut verify = SourceVerify::default();
+
+        validate_keys!(verify, self.iter(), manifest, files);
+
+        if verify.manifest.as_os_str().is_empty() {
+            return Err(vec![_partialerror!(
+                *self.span(),
+                ErrorKind::MissingField("manifest".into()),
+                help = "`verify` must have a `manifest` file"
+            )]);
+        }
+        let is_relative = verify
+            .manifest
+            .components()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+        if !is_relative {
+            return Err(vec![_partialerror!(
+                *self.span(),
+                ErrorKind::InvalidValue((
+                    "manifest".to_string(),
+                    verify.manifest.display().to_string().into()
+                )),
+                help = "the `manifest` of `verify` must be a path inside the recipe directory \
+                        (relative and without `..`)"
+            )]);
+        }
+        if verify.files.is_empty() {
+            return Err(vec![_partialerror!(
+                *self.span(),
+                ErrorKind::MissingField("files".into()),
+                help = "`verify` must have `files` to verify, e.g. `vendor/**`"
+            )]);
+        }
+
+        Ok(verify)
+    }
+}
+
 impl TryConvertNode<Vec<Source>> for RenderedNode {
     fn try_convert(&self, _name: &str) -> Result<Vec<Source>, Vec<PartialParsingError>> {
         let mut sources = Vec::new();
@@ -67,12 +150,19 @@ impl TryConvertNode<Vec<Source>> for RenderedNode {
                 } else if map.contains_key("path") {
                     let path_src = map.try_convert("source")?;
                     sources.push(Source::Path(path_src));
+                } else if map.contains_key("hg") {
+                    let hg_src = map.try_convert("source")?;
+                    sources.push(Source::Hg(hg_src));
+                } else if map.contains_key("svn") {
+                    let svn_src = map.try_convert("source")?;
+                    sources.push(Source::Svn(svn_src));
                 } else {
                     return Err(vec![_partialerror!(
                         *self.span(),
                         ErrorKind::Other,
-                        label = "unknown source type (no `url`, `path` or `git` found)",
-                        help = "are you missing `url`, `path` or `git`?"
+                        label =
+                            "unknown source type (no `url`, `path`, `git`, `hg` or `svn` found)",
+                        help = "are you missing `url`, `path`, `git`, `hg` or `svn`?"
                     )]);
                 }
             }
@@ -192,6 +282,9 @@ pub struct GitSource {
     /// Optionally request the lfs pull in git source
     #[serde(default, skip_serializing_if = "should_not_serialize_lfs")]
     pub lfs: bool,
+    /// Optionally verify vendored files against a manifest
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verify: Option<SourceVerify>,
 }
 
 /// A helper method to skip serializing the lfs flag if it is false.
@@ -217,6 +310,7 @@ impl GitSource {
             patches,
             target_directory,
             lfs,
+            verify: None,
         }
     }
 
@@ -259,6 +353,7 @@ impl TryConvertNode<GitSource> for RenderedMappingNode {
         let mut patches = Vec::new();
         let mut target_directory = None;
         let mut lfs = false;
+        let mut verify = None;
 
         self.iter().map(|(k, v)| {
             match k.as_str() {
@@ -317,11 +412,14 @@ impl TryConvertNode<GitSource> for RenderedMappingNode {
                 "lfs" => {
                     lfs = v.try_convert("lfs")?;
                 }
+                "verify" => {
+                    verify = Some(v.try_convert("verify")?);
+                }
                 _ => {
                     return Err(vec![_partialerror!(
                         *k.span(),
                         ErrorKind::InvalidField(k.as_str().to_owned().into()),
-                        help = "valid fields for git `source` are `git`, `rev`, `tag`, `branch`, `depth`, `patches`, `lfs` and `target_directory`"
+                        help = "valid fields for git `source` are `git`, `rev`, `tag`, `branch`, `depth`, `patches`, `lfs`, `target_directory` and `verify`"
                     )])
                 }
             }
@@ -354,6 +452,7 @@ impl TryConvertNode<GitSource> for RenderedMappingNode {
             patches,
             target_directory,
             lfs,
+            verify,
         })
     }
 }
@@ -380,6 +479,178 @@ impl fmt::Display for GitUrl {
     }
 }
 
+/// Mercurial source information.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HgSource {
+    /// Url to the Mercurial repository
+    #[serde(rename = "hg")]
+    pub url: Url,
+    /// Optionally a revision (changeset, tag or branch) to update to, defaults to `tip`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rev: Option<String>,
+    /// Optionally patches to apply to the source code
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub patches: Vec<PathBuf>,
+    /// Optionally a folder name under the `work` directory to place the source code
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_directory: Option<PathBuf>,
+    /// Optionally verify vendored files against a manifest
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verify: Option<SourceVerify>,
+}
+
+impl HgSource {
+    /// Get the Mercurial url.
+    pub const fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// Get the revision to update to.
+    pub fn rev(&self) -> Option<&str> {
+        self.rev.as_deref()
+    }
+
+    /// Get the patches.
+    pub fn patches(&self) -> &[PathBuf] {
+        self.patches.as_slice()
+    }
+
+    /// Get the target_directory.
+    pub const fn target_directory(&self) -> Option<&PathBuf> {
+        self.target_directory.as_ref()
+    }
+}
+
+impl TryConvertNode<HgSource> for RenderedMappingNode {
+    fn try_convert(&self, _name: &str) -> Result<HgSource, Vec<PartialParsingError>> {
+        let mut url = None;
+        let mut rev = None;
+        let mut patches = Vec::new();
+        let mut target_directory = None;
+        let mut verify = None;
+
+        self.iter().map(|(key, value)| {
+            match key.as_str() {
+                "hg" => url = Some(value.try_convert("hg")?),
+                "rev" => rev = Some(value.try_convert("rev")?),
+                "patches" => patches = value.try_convert("patches")?,
+                "target_directory" => target_directory = value.try_convert("target_directory")?,
+                "verify" => verify = Some(value.try_convert("verify")?),
+                invalid_key => {
+                    return Err(vec![_partialerror!(
+                        *key.span(),
+                        ErrorKind::InvalidField(invalid_key.to_owned().into()),
+                        help = "valid fields for Mercurial `source` are `hg`, `rev`, `patches`, `target_directory` and `verify`"
+                    )])
+                }
+            }
+            Ok(())
+        }).flatten_errors()?;
+
+        let url = url.ok_or_else(|| {
+            vec![_partialerror!(
+                *self.span(),
+                ErrorKind::MissingField("hg".into()),
+                help = "Mercurial `source` must have a `hg` field"
+            )]
+        })?;
+
+        Ok(HgSource {
+            url,
+            rev,
+            patches,
+            target_directory,
+            verify,
+        })
+    }
+}
+
+/// Subversion source information.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SvnSource {
+    /// Url to the Subversion repository
+    #[serde(rename = "svn")]
+    pub url: Url,
+    /// Optionally a revision to check out, defaults to `HEAD`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rev: Option<String>,
+    /// Optionally patches to apply to the source code
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub patches: Vec<PathBuf>,
+    /// Optionally a folder name under the `work` directory to place the source code
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_directory: Option<PathBuf>,
+    /// Optionally verify vendored files against a manifest
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verify: Option<SourceVerify>,
+}
+
+impl SvnSource {
+    /// Get the Subversion url.
+    pub const fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// Get the revision to check out.
+    pub fn rev(&self) -> Option<&str> {
+        self.rev.as_deref()
+    }
+
+    /// Get the patches.
+    pub fn patches(&self) -> &[PathBuf] {
+        self.patches.as_slice()
+    }
+
+    /// Get the target_directory.
+    pub const fn target_directory(&self) -> Option<&PathBuf> {
+        self.target_directory.as_ref()
+    }
+}
+
+impl TryConvertNode<SvnSource> for RenderedMappingNode {
+    fn try_convert(&self, _name: &str) -> Result<SvnSource, Vec<PartialParsingError>> {
+        let mut url = None;
+        let mut rev = None;
+        let mut patches = Vec::new();
+        let mut target_directory = None;
+        let mut verify = None;
+
+        self.iter().map(|(key, value)| {
+            match key.as_str() {
+                "svn" => url = Some(value.try_convert("svn")?),
+                "rev" => rev = Some(value.try_convert("rev")?),
+                "patches" => patches = value.try_convert("patches")?,
+                "target_directory" => target_directory = value.try_convert("target_directory")?,
+                "verify" => verify = Some(value.try_convert("verify")?),
+                invalid_key => {
+                    return Err(vec![_partialerror!(
+                        *key.span(),
+                        ErrorKind::InvalidField(invalid_key.to_owned().into()),
+                        help = "valid fields for Subversion `source` are `svn`, `rev`, `patches`, `target_directory` and `verify`"
+                    )])
+                }
+            }
+            Ok(())
+        }).flatten_errors()?;
+
+        let url = url.ok_or_else(|| {
+            vec![_partialerror!(
+                *self.span(),
+                ErrorKind::MissingField("svn".into()),
+                help = "Subversion `source` must have a `svn` field"
+            )]
+        })?;
+
+        Ok(SvnSource {
+            url,
+            rev,
+            patches,
+            target_directory,
+            verify,
+        })
+    }
+}
+
 /// A url source (usually a tar.gz or tar.bz2 archive). A compressed file
 /// will be extracted to the `work` (or `work/<folder>` directory).
 #[serde_as]
@@ -410,6 +681,10 @@ pub struct UrlSource {
     /// Optionally a folder name under the `work` directory to place the source code
     #[serde(skip_serializing_if = "Option::is_none")]
     target_directory: Option<PathBuf>,
+
+    /// Optionally verify vendored files against a manifest
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    verify: Option<SourceVerify>,
 }
 
 impl UrlSource {
@@ -452,6 +727,7 @@ impl TryConvertNode<UrlSource> for RenderedMappingNode {
         let mut patches = Vec::new();
         let mut target_directory = None;
         let mut file_name = None;
+        let mut verify = None;
 
         self.iter().map(|(key, value)| {
             match key.as_str() {
@@ -469,11 +745,12 @@ impl TryConvertNode<UrlSource> for RenderedMappingNode {
                 "file_name" => file_name = value.try_convert(key)?,
                 "patches" => patches = value.try_convert(key)?,
                 "target_directory" => target_directory = value.try_convert(key)?,
+                "verify" => verify = Some(value.try_convert(key)?),
                 invalid_key => {
                     return Err(vec![_partialerror!(
                         *key.span(),
                         ErrorKind::InvalidField(invalid_key.to_owned().into()),
-                        help = "valid fields for URL `source` are `url`, `sha256`, `md5`, `patches`, `file_name` and `target_directory`"
+                        help = "valid fields for URL `source` are `url`, `sha256`, `md5`, `patches`, `file_name`, `target_directory` and `verify`"
                     )])
                 }
             }
@@ -503,6 +780,7 @@ impl TryConvertNode<UrlSource> for RenderedMappingNode {
             file_name,
             patches,
             target_directory,
+            verify,
         })
     }
 }
@@ -537,6 +815,9 @@ pub struct PathSource {
         skip_serializing_if = "should_not_serialize_use_gitignore"
     )]
     pub use_gitignore: bool,
+    /// Optionally verify vendored files against a manifest
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verify: Option<SourceVerify>,
 }
 
 fn default_gitignore() -> bool {
@@ -584,6 +865,7 @@ impl TryConvertNode<PathSource> for RenderedMappingNode {
         let mut file_name = None;
         let mut sha256 = None;
         let mut md5 = None;
+        let mut verify = None;
 
         self.iter().map(|(key, value)| {
             match key.as_str() {
@@ -602,11 +884,12 @@ impl TryConvertNode<PathSource> for RenderedMappingNode {
                 "target_directory" => target_directory = value.try_convert("target_directory")?,
                 "file_name" => file_name = value.try_convert("file_name")?,
                 "use_gitignore" => use_gitignore = value.try_convert("use_gitignore")?,
+                "verify" => verify = Some(value.try_convert("verify")?),
                 invalid_key => {
                     return Err(vec![_partialerror!(
                         *key.span(),
                         ErrorKind::InvalidField(invalid_key.to_string().into()),
-                        help = "valid fields for path `source` are `path`, `patches`, `target_directory`, `file_name` and `use_gitignore`"
+                        help = "valid fields for path `source` are `path`, `patches`, `target_directory`, `file_name`, `use_gitignore` and `verify`"
                     )])
                 }
             }
@@ -637,6 +920,7 @@ impl TryConvertNode<PathSource> for RenderedMappingNode {
             target_directory,
             file_name,
             use_gitignore,
+            verify,
         })
     }
 }
@@ -654,6 +938,7 @@ mod tests {
             patches: Vec::new(),
             target_directory: None,
             lfs: false,
+            verify: None,
         };
 
         let yaml = serde_yaml::to_string(&git).unwrap();
@@ -674,6 +959,7 @@ mod tests {
             patches: Vec::new(),
             target_directory: None,
             lfs: false,
+            verify: None,
         };
 
         let yaml = serde_yaml::to_string(&git).unwrap();
@@ -697,9 +983,83 @@ mod tests {
             target_directory: None,
             file_name: None,
             use_gitignore: true,
+            verify: None,
         };
 
         let json = serde_json::to_string(&path_source).unwrap();
         serde_json::from_str::<PathSource>(&json).unwrap();
     }
+
+    #[test]
+    fn test_hg_svn_round_trip() {
+        let hg: Source = serde_yaml::from_str(
+            "hg: https://hg.example.com/foo\nrev: v1.0\ntarget_directory: foo\n",
+        )
+        .unwrap();
+        let Source::Hg(hg_source) = &hg else {
+            panic!("expected a Mercurial source, got {hg:?}");
+        };
+        assert_eq!(hg_source.url().as_str(), "https://hg.example.com/foo");
+        assert_eq!(hg_source.rev(), Some("v1.0"));
+        assert_eq!(hg.target_directory(), Some(&PathBuf::from("foo")));
+
+        let svn: Source = serde_yaml::from_str("svn: svn://svn.example.com/foo/trunk\n").unwrap();
+        let Source::Svn(svn_source) = &svn else {
+            panic!("expected a Subversion source, got {svn:?}");
+        };
+        assert_eq!(svn_source.rev(), None);
+
+        let finalized = Source::Svn(SvnSource {
+            rev: Some("1234".to_string()),
+            ..svn_source.clone()
+        });
+        let yaml = serde_yaml::to_string(&finalized).unwrap();
+        assert_eq!(yaml, "svn: svn://svn.example.com/foo/trunk\nrev: '1234'\n");
+        assert_eq!(serde_yaml::from_str::<Source>(&yaml).unwrap(), finalized);
+    }
+
+    #[test]
+    fn test_source_verify_parsing() {
+        let source = r#"
+        url: https://example.com/foo-1.0.tar.gz
+        sha256: 0000000000000000000000000000000000000000000000000000000000000000
+        verify:
+          manifest: vendor-manifest.json
+          files:
+            - vendor/**
+            - Cargo.lock
+        "#;
+        let node = RenderedNode::parse_yaml(0, source).unwrap();
+        let sources: Vec<Source> = node.try_convert("source").unwrap();
+        let verify = sources[0].verify().unwrap();
+        assert_eq!(verify.manifest, PathBuf::from("vendor-manifest.json"));
+        assert!(verify
+            .files
+            .is_match(std::path::Path::new("vendor/foo/src/lib.rs")));
+        assert!(verify.files.is_match(std::path::Path::new("Cargo.lock")));
+        assert!(!verify.files.is_match(std::path::Path::new("src/main.rs")));
+
+        // round trip through the serialized recipe
+        let yaml = serde_yaml::to_string(&sources[0]).unwrap();
+        assert_eq!(serde_yaml::from_str::<Source>(&yaml).unwrap(), sources[0]);
+
+        let missing_manifest = r#"
+        path: ../foo
+        verify:
+          files: [vendor/**]
+        "#;
+        let node = RenderedNode::parse_yaml(0, missing_manifest).unwrap();
+        assert!(TryConvertNode::<Vec<Source>>::try_convert(&node, "source").is_err());
+
+        // the manifest has to be inside of the recipe directory
+        for manifest in ["../vendor-manifest.json", "/tmp/vendor-manifest.json"] {
+            let source =
+                format!("path: ../foo\nverify:\n  manifest: {manifest}\n  files: [vendor/**]\n");
+            let node = RenderedNode::parse_yaml(0, &source).unwrap();
+            assert!(
+                TryConvertNode::<Vec<Source>>::try_convert(&node, "source").is_err(),
+                "{manifest} should be rejected"
+            );
+        }
+    }
 }
@@ -25,6 +25,19 @@ pub struct CommandsTestRequirements {
     /// Extra build requirements for the test (e.g. emulators, compilers, ...).
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub build: Vec<String>,
+
+    /// Paths to locally built packages (`.conda` or `.tar.bz2`) that are
+    /// installed into the test environment. Relative paths are resolved
+    /// against the recipe directory, and the file name may contain wildcards
+    /// (e.g. `../other-output/*.conda`).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub local: Vec<String>,
+
+    /// Virtual packages (e.g. `__cuda >=12`) that must be present on the
+    /// machine that runs the test. If one is missing, the test is skipped
+    /// instead of failing.
+    #[serde(default, rename = "virtual", skip_serializing_if = "Vec::is_empty")]
+    pub virtual_packages: Vec<String>,
 }
 
 /// The files that should be copied to the test directory (they are stored in the package)
@@ -59,7 +72,10 @@ pub struct CommandsTest {
 impl CommandsTestRequirements {
     /// Check if the requirements are empty
     pub fn is_empty(&self) -> bool {
-        self.run.is_empty() && self.build.is_empty()
+        self.run.is_empty()
+            && self.build.is_empty()
+            && self.local.is_empty()
+            && self.virtual_packages.is_empty()
     }
 }
 
@@ -74,6 +90,10 @@ fn pip_check_true() -> bool {
     true
 }
 
+fn skip_unsolvable_true() -> bool {
+    true
+}
+
 fn is_true(value: &bool) -> bool {
     *value
 }
@@ -128,11 +148,39 @@ pub struct PerlTest {
     pub uses: Vec<String>,
 }
 
+/// A test that checks that commands and libraries are available in the test
+/// environment, without the need for platform specific shell snippets.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExistsTest {
+    /// Commands that should be found in the `PATH` directories of the test
+    /// environment (e.g. `bin/` on Unix, `Library/bin/` or `Scripts/` on Windows)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub commands: Vec<String>,
+    /// Shared libraries that should be found in the library directory of the
+    /// test environment (e.g. `libbar` finds `lib/libbar.so`, `lib/libbar.dylib`
+    /// or `Library/bin/bar.dll`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub libraries: Vec<String>,
+}
+
 /// A test that runs the tests of a downstream package.
-#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct DownstreamTest {
     /// The name of the downstream package
     pub downstream: String,
+    /// Whether to skip the test if the downstream package cannot be resolved
+    /// together with the package under test (default to true)
+    #[serde(default = "skip_unsolvable_true", skip_serializing_if = "is_true")]
+    pub skip_unsolvable: bool,
+}
+
+impl Default for DownstreamTest {
+    fn default() -> Self {
+        Self {
+            downstream: String::new(),
+            skip_unsolvable: true,
+        }
+    }
 }
 
 /// The test type enum
@@ -149,6 +197,11 @@ pub enum TestType {
         /// The modules to test
         perl: PerlTest,
     },
+    /// A test that checks if commands and libraries exist in the test environment
+    Exists {
+        /// The commands and libraries to look for
+        exists: ExistsTest,
+    },
     /// A test that executes multiple commands in a freshly created environment
     Command(CommandsTest),
     /// A test that runs the tests of a downstream package
@@ -251,7 +304,7 @@ impl TryConvertNode<TestType> for RenderedMappingNode {
                     let commands = self.try_convert(key_str)?;
                     test = TestType::Command(commands);
                 }
-                "downstream" => {
+                "downstream" | "skip_unsolvable" => {
                     let downstream = self.try_convert(key_str)?;
                     test = TestType::Downstream(downstream);
                 }
@@ -263,10 +316,14 @@ impl TryConvertNode<TestType> for RenderedMappingNode {
                     let perl = as_mapping(value, key_str)?.try_convert(key_str)?;
                     test = TestType::Perl { perl };
                 }
+                "exists" => {
+                    let exists = as_mapping(value, key_str)?.try_convert(key_str)?;
+                    test = TestType::Exists { exists };
+                }
                 invalid => Err(vec![_partialerror!(
                     *key.span(),
                     ErrorKind::InvalidField(invalid.to_string().into()),
-                    help = format!("expected fields for {name} is one of `python`, `perl`, `script`, `downstream`, `package_contents`")
+                    help = format!("expected fields for {name} is one of `python`, `perl`, `exists`, `script`, `downstream`, `package_contents`")
                 )])?
             }
             Ok(())
@@ -330,9 +387,18 @@ impl TryConvertNode<PythonVersion> for RenderedNode {
 /// Downstream Test     ///
 ///////////////////////////
 impl TryConvertNode<DownstreamTest> for RenderedMappingNode {
-    fn try_convert(&self, _name: &str) -> Result<DownstreamTest, Vec<PartialParsingError>> {
+    fn try_convert(&self, name: &str) -> Result<DownstreamTest, Vec<PartialParsingError>> {
         let mut downstream = DownstreamTest::default();
-        validate_keys!(downstream, self.iter(), downstream);
+        validate_keys!(downstream, self.iter(), downstream, skip_unsolvable);
+
+        if downstream.downstream.is_empty() {
+            return Err(vec![_partialerror!(
+                *self.span(),
+                ErrorKind::MissingField("downstream".into()),
+                help = format!("a downstream test in {name} requires the `downstream` package")
+            )]);
+        }
+
         Ok(downstream)
     }
 }
@@ -357,7 +423,25 @@ impl TryConvertNode<CommandsTestRequirements> for RenderedMappingNode {
         _name: &str,
     ) -> Result<CommandsTestRequirements, Vec<PartialParsingError>> {
         let mut requirements = CommandsTestRequirements::default();
-        validate_keys!(requirements, self.iter(), run, build);
+        // `virtual` is a keyword and can't be used with `validate_keys!`
+        self.iter()
+            .map(|(key, value)| {
+                match key.as_str() {
+                    "run" => requirements.run = value.try_convert("run")?,
+                    "build" => requirements.build = value.try_convert("build")?,
+                    "local" => requirements.local = value.try_convert("local")?,
+                    "virtual" => requirements.virtual_packages = value.try_convert("virtual")?,
+                    _ => {
+                        return Err(vec![_partialerror!(
+                            *key.span(),
+                            ErrorKind::InvalidField(key.as_str().to_owned().into()),
+                            help = "valid fields for test `requirements` are `run`, `build`, `local` and `virtual`"
+                        )])
+                    }
+                }
+                Ok(())
+            })
+            .flatten_errors()?;
         Ok(requirements)
     }
 }
@@ -407,6 +491,26 @@ impl TryConvertNode<PerlTest> for RenderedMappingNode {
     }
 }
 
+///////////////////////////
+/// Exists Test         ///
+///////////////////////////
+impl TryConvertNode<ExistsTest> for RenderedMappingNode {
+    fn try_convert(&self, name: &str) -> Result<ExistsTest, Vec<PartialParsingError>> {
+        let mut exists = ExistsTest::default();
+        validate_keys!(exists, self.iter(), commands, libraries);
+
+        if exists.commands.is_empty() && exists.libraries.is_empty() {
+            Err(vec![_partialerror!(
+                *self.span(),
+                ErrorKind::MissingField("commands".into()),
+                help = format!("an `{name}` test requires `commands` and/or `libraries`")
+            )])?;
+        }
+
+        Ok(exists)
+    }
+}
+
 ///////////////////////////
 /// Package Contents    ///
 ///////////////////////////
@@ -557,4 +661,146 @@ mod test {
             _ => panic!("expected python test"),
         }
     }
+
+    #[test]
+    fn test_downstream_parsing() {
+        let test_section = r#"
+        tests:
+          - downstream: numpy
+          - downstream: scipy
+            skip_unsolvable: false
+        "#;
+
+        // parse the YAML
+        let yaml_root = RenderedNode::parse_yaml(0, test_section)
+            .map_err(|err| vec![err])
+            .unwrap();
+        let tests_node = yaml_root.as_mapping().unwrap().get("tests").unwrap();
+        let tests: Vec<TestType> = tests_node.try_convert("tests").unwrap();
+
+        // round trip through the serialized `tests.yaml`
+        let yaml_serde = serde_yaml::to_string(&tests).unwrap();
+        let tests: Vec<TestType> = serde_yaml::from_str(&yaml_serde).unwrap();
+
+        match tests.as_slice() {
+            [TestType::Downstream(numpy), TestType::Downstream(scipy)] => {
+                assert_eq!(numpy.downstream, "numpy");
+                assert!(numpy.skip_unsolvable);
+                assert_eq!(scipy.downstream, "scipy");
+                assert!(!scipy.skip_unsolvable);
+            }
+            _ => panic!("expected two downstream tests"),
+        }
+
+        // a downstream test without a package is invalid
+        let yaml_root = RenderedNode::parse_yaml(0, "tests:\n  - skip_unsolvable: true\n")
+            .map_err(|err| vec![err])
+            .unwrap();
+        let tests_node = yaml_root.as_mapping().unwrap().get("tests").unwrap();
+        let tests: Result<Vec<TestType>, _> = tests_node.try_convert("tests");
+        assert!(tests.is_err());
+    }
+
+    #[test]
+    fn test_exists_parsing() {
+        let test_section = r#"
+        tests:
+          - exists:
+              commands: [foo, bar]
+              libraries: [libbar]
+        "#;
+
+        let yaml_root = RenderedNode::parse_yaml(0, test_section)
+            .map_err(|err| vec![err])
+            .unwrap();
+        let tests_node = yaml_root.as_mapping().unwrap().get("tests").unwrap();
+        let tests: Vec<TestType> = tests_node.try_convert("tests").unwrap();
+
+        let yaml_serde = serde_yaml::to_string(&tests).unwrap();
+        let tests: Vec<TestType> = serde_yaml::from_str(&yaml_serde).unwrap();
+
+        match tests.as_slice() {
+            [TestType::Exists { exists }] => {
+                assert_eq!(exists.commands, vec!["foo", "bar"]);
+                assert_eq!(exists.libraries, vec!["libbar"]);
+            }
+            _ => panic!("expected exists test"),
+        }
+
+        // an empty exists test is invalid
+        let yaml_root = RenderedNode::parse_yaml(0, "tests:\n  - exists: {}\n")
+            .map_err(|err| vec![err])
+            .unwrap();
+        let tests_node = yaml_root.as_mapping().unwrap().get("tests").unwrap();
+        let tests: Result<Vec<TestType>, _> = tests_node.try_convert("tests");
+        assert!(tests.is_err());
+    }
+
+    #[test]
+    fn test_local_requirements_parsing() {
+        let test_section = r#"
+        tests:
+          - script: [echo test]
+            requirements:
+              local: ["../other-output/*.conda"]
+        "#;
+
+        let yaml_root = RenderedNode::parse_yaml(0, test_section)
+            .map_err(|err| vec![err])
+            .unwrap();
+        let tests_node = yaml_root.as_mapping().unwrap().get("tests").unwrap();
+        let tests: Vec<TestType> = tests_node.try_convert("tests").unwrap();
+
+        let yaml_serde = serde_yaml::to_string(&tests).unwrap();
+        let tests: Vec<TestType> = serde_yaml::from_str(&yaml_serde).unwrap();
+
+        match tests.as_slice() {
+            [TestType::Command(command)] => {
+                assert_eq!(command.requirements.local, vec!["../other-output/*.conda"]);
+                assert!(!command.requirements.is_empty());
+            }
+            _ => panic!("expected script test"),
+        }
+    }
+
+    #[test]
+    fn test_virtual_requirements_parsing() {
+        let test_section = r#"
+        tests:
+          - script: [nvidia-smi]
+            requirements:
+              virtual: ["__cuda >=12"]
+        "#;
+
+        let yaml_root = RenderedNode::parse_yaml(0, test_section)
+            .map_err(|err| vec![err])
+            .unwrap();
+        let tests_node = yaml_root.as_mapping().unwrap().get("tests").unwrap();
+        let tests: Vec<TestType> = tests_node.try_convert("tests").unwrap();
+
+        let yaml_serde = serde_yaml::to_string(&tests).unwrap();
+        assert!(yaml_serde.contains("virtual:"));
+        let tests: Vec<TestType> = serde_yaml::from_str(&yaml_serde).unwrap();
+
+        match tests.as_slice() {
+            [TestType::Command(command)] => {
+                assert_eq!(command.requirements.virtual_packages, vec!["__cuda >=12"]);
+                assert!(!command.requirements.is_empty());
+            }
+            _ => panic!("expected script test"),
+        }
+
+        let test_section = r#"
+        tests:
+          - script: [nvidia-smi]
+            requirements:
+              gpu: ["__cuda >=12"]
+        "#;
+        let yaml_root = RenderedNode::parse_yaml(0, test_section)
+            .map_err(|err| vec![err])
+            .unwrap();
+        let tests_node = yaml_root.as_mapping().unwrap().get("tests").unwrap();
+        let tests: Result<Vec<TestType>, _> = tests_node.try_convert("tests");
+        assert!(tests.is_err());
+    }
 }
@@ -1,12 +1,13 @@
-//! Module for generating recipes for Python (PyPI) or R (CRAN) packages
+//! Module for generating recipes for Python (PyPI), R (CRAN) or Node.js (npm) packages
 use clap::Parser;
 
 mod cran;
-
+mod npm;
 mod pypi;
 mod serialize;
 
 use cran::{generate_r_recipe, CranOpts};
+use npm::{generate_npm_recipe, NpmOpts};
 use pypi::PyPIOpts;
 pub use serialize::write_recipe;
 
@@ -20,6 +21,9 @@ pub enum Source {
 
     /// Generate a recipe for an R package from CRAN
     Cran(CranOpts),
+
+    /// Generate a recipe for a Node.js package from npm
+    Npm(NpmOpts),
 }
 
 /// Options for generating a recipe
@@ -35,6 +39,7 @@ pub async fn generate_recipe(args: GenerateRecipeOpts) -> miette::Result<()> {
     match args.source {
         Source::Pypi(opts) => generate_pypi_recipe(&opts).await?,
         Source::Cran(opts) => generate_r_recipe(&opts).await?,
+        Source::Npm(opts) => generate_npm_recipe(&opts).await?,
     }
 
     Ok(())
@@ -0,0 +1,311 @@
+use clap::Parser;
+use indexmap::IndexMap;
+use miette::{IntoDiagnostic, WrapErr};
+use rattler_conda_types::{ParseStrictness, VersionSpec};
+use serde::Deserialize;
+use std::{collections::HashMap, str::FromStr};
+use url::Url;
+
+use super::{cran::fetch_package_sha256sum, write_recipe};
+use crate::recipe_generator::serialize::{self, ScriptTest, Test};
+
+#[derive(Debug, Clone, Parser)]
+pub struct NpmOpts {
+    /// Name of the package to generate (e.g. `typescript` or `@angular/cli`)
+    pub package: String,
+
+    /// Select a version of the package to generate (defaults to latest)
+    #[arg(long)]
+    pub version: Option<String>,
+
+    /// Whether to write the recipe to a folder
+    #[arg(short, long)]
+    pub write: bool,
+}
+
+/// The `license` field of a `package.json`. Besides a SPDX expression, older
+/// packages use an object (`{ "type": "MIT" }`).
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+enum NpmLicense {
+    Spdx(String),
+    Object {
+        #[serde(rename = "type")]
+        license_type: String,
+    },
+}
+
+/// The `bin` field of a `package.json`, either a single executable (named
+/// like the package) or a map from executable names to scripts.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+enum NpmBin {
+    Single(String),
+    Map(IndexMap<String, String>),
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+enum NpmRepository {
+    Url(String),
+    Object { url: String },
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct NpmDist {
+    tarball: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct NpmPackageVersion {
+    name: String,
+    version: String,
+    description: Option<String>,
+    homepage: Option<String>,
+    license: Option<NpmLicense>,
+    #[serde(default)]
+    licenses: Vec<NpmLicense>,
+    bin: Option<NpmBin>,
+    repository: Option<NpmRepository>,
+    #[serde(default)]
+    engines: HashMap<String, String>,
+    dist: NpmDist,
+}
+
+#[derive(Deserialize)]
+struct NpmPackument {
+    #[serde(rename = "dist-tags")]
+    dist_tags: HashMap<String, String>,
+    versions: HashMap<String, NpmPackageVersion>,
+}
+
+/// Convert a npm package name to a conda package name. Scoped packages
+/// (`@scope/name`) become `scope-name`.
+fn conda_name(npm_name: &str) -> String {
+    npm_name
+        .trim_start_matches('@')
+        .replace('/', "-")
+        .to_lowercase()
+}
+
+/// Translate the npm license field into a SPDX expression and an optional
+/// license file (for `SEE LICENSE IN <file>`).
+fn map_license(licenses: &[NpmLicense]) -> (Option<String>, Option<String>) {
+    let mut expressions = Vec::new();
+    let mut license_file = None;
+    for license in licenses {
+        let license = match license {
+            NpmLicense::Spdx(license) => license.trim(),
+            NpmLicense::Object { license_type } => license_type.trim(),
+        };
+
+        if let Some(file) = license.strip_prefix("SEE LICENSE IN ") {
+            license_file = Some(file.trim().to_string());
+        } else if !license.is_empty() && license != "UNLICENSED" {
+            // strip superfluous parentheses around the whole expression
+            let license = license
+                .strip_prefix('(')
+                .and_then(|l| l.strip_suffix(')'))
+                .unwrap_or(license);
+            expressions.push(license.to_string());
+        }
+    }
+
+    let license = (!expressions.is_empty()).then(|| expressions.join(" OR "));
+    (license, license_file)
+}
+
+/// Turn a npm repository URL (e.g. `git+https://github.com/foo/bar.git`) into
+/// a plain URL.
+fn map_repository(repository: &NpmRepository) -> String {
+    let url = match repository {
+        NpmRepository::Url(url) | NpmRepository::Object { url } => url,
+    };
+    let url = url.trim_start_matches("git+");
+    let url = url.strip_suffix(".git").unwrap_or(url);
+    if let Some(path) = url.strip_prefix("github:") {
+        format!("https://github.com/{path}")
+    } else if let Some(path) = url.strip_prefix("git://") {
+        format!("https://{path}")
+    } else if !url.contains("://") && url.matches('/').count() == 1 {
+        // shorthand for a GitHub repository (`user/repo`)
+        format!("https://github.com/{url}")
+    } else {
+        url.to_string()
+    }
+}
+
+/// Returns the names of all executables that the package provides.
+fn bin_entries(package: &NpmPackageVersion) -> Vec<String> {
+    match &package.bin {
+        Some(NpmBin::Single(_)) => vec![package
+            .name
+            .rsplit('/')
+            .next()
+            .unwrap_or(&package.name)
+            .to_string()],
+        Some(NpmBin::Map(map)) => map.keys().cloned().collect(),
+        None => Vec::new(),
+    }
+}
+
+pub async fn generate_npm_recipe(opts: &NpmOpts) -> miette::Result<()> {
+    eprintln!("Generating recipe for {}", opts.package);
+
+    // scoped packages need an escaped slash in the registry URL
+    let url = format!(
+        "https://registry.npmjs.org/{}",
+        opts.package.replace('/', "%2F")
+    );
+    let packument: NpmPackument = reqwest::get(&url)
+        .await
+        .into_diagnostic()?
+        .error_for_status()
+        .into_diagnostic()
+        .with_context(|| format!("failed to fetch package `{}` from npm", opts.package))?
+        .json()
+        .await
+        .into_diagnostic()
+        .context("failed to parse npm registry response")?;
+
+    let version = match &opts.version {
+        Some(version) => version.clone(),
+        None => packument
+            .dist_tags
+            .get("latest")
+            .cloned()
+            .ok_or_else(|| miette::miette!("No latest version found for {}", opts.package))?,
+    };
+
+    let package = packument
+        .versions
+        .get(&version)
+        .ok_or_else(|| miette::miette!("Version {} of {} not found", version, opts.package))?;
+
+    let mut recipe = serialize::Recipe::default();
+    recipe
+        .context
+        .insert("version".to_string(), package.version.clone());
+    recipe.package.name = conda_name(&package.name);
+    recipe.package.version = "${{ version }}".to_string();
+
+    let tarball = Url::parse(&package.dist.tarball).into_diagnostic()?;
+    let sha256 = fetch_package_sha256sum(&tarball).await?;
+    recipe.source.push(serialize::SourceElement {
+        url: package
+            .dist
+            .tarball
+            .replace(package.version.as_str(), "${{ version }}"),
+        sha256: Some(format!("{:x}", sha256)),
+        md5: None,
+    });
+
+    // The package is installed globally into the prefix, together with all of
+    // its (bundled) npm dependencies. The executables of `bin` are linked into
+    // `$PREFIX/bin` by npm.
+    recipe.build.noarch = Some("generic".to_string());
+    recipe.build.script = [
+        "npm pack --ignore-scripts",
+        "npm install -g --prefix $PREFIX ./*.tgz",
+    ]
+    .join("\n");
+
+    recipe.requirements.host.push("nodejs".to_string());
+    // npm ranges like `^14` have no conda equivalent, only keep valid specs
+    let node_spec = package
+        .engines
+        .get("node")
+        .map(|node| node.split_whitespace().collect::<String>())
+        .filter(|node| VersionSpec::from_str(node, ParseStrictness::Strict).is_ok());
+    recipe.requirements.run.push(match node_spec {
+        Some(node) => format!("nodejs {node}"),
+        None => "nodejs".to_string(),
+    });
+
+    let bins = bin_entries(package);
+    if !bins.is_empty() {
+        recipe.tests.push(Test::Script(ScriptTest {
+            script: bins.iter().map(|bin| format!("command -v {bin}")).collect(),
+        }));
+    }
+
+    let licenses = package
+        .license
+        .iter()
+        .chain(package.licenses.iter())
+        .cloned()
+        .collect::<Vec<_>>();
+    let (license, license_file) = map_license(&licenses);
+
+    recipe.about.summary = package.description.clone();
+    recipe.about.homepage = package.homepage.clone();
+    recipe.about.license = license;
+    recipe.about.license_file = license_file;
+    recipe.about.repository = package.repository.as_ref().map(map_repository);
+
+    let string = format!("{}", recipe);
+    if opts.write {
+        write_recipe(&recipe.package.name, &string).into_diagnostic()?;
+    } else {
+        print!("{}", string);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conda_name() {
+        assert_eq!(conda_name("typescript"), "typescript");
+        assert_eq!(conda_name("@angular/cli"), "angular-cli");
+    }
+
+    #[test]
+    fn test_map_license() {
+        assert_eq!(
+            map_license(&[NpmLicense::Spdx("MIT".into())]),
+            (Some("MIT".into()), None)
+        );
+        assert_eq!(
+            map_license(&[NpmLicense::Spdx("(MIT OR Apache-2.0)".into())]),
+            (Some("MIT OR Apache-2.0".into()), None)
+        );
+        assert_eq!(
+            map_license(&[
+                NpmLicense::Object {
+                    license_type: "MIT".into()
+                },
+                NpmLicense::Object {
+                    license_type: "BSD-3-Clause".into()
+                }
+            ]),
+            (Some("MIT OR BSD-3-Clause".into()), None)
+        );
+        assert_eq!(
+            map_license(&[NpmLicense::Spdx("SEE LICENSE IN LICENSE.md".into())]),
+            (None, Some("LICENSE.md".into()))
+        );
+        assert_eq!(
+            map_license(&[NpmLicense::Spdx("UNLICENSED".into())]),
+            (None, None)
+        );
+    }
+
+    #[test]
+    fn test_map_repository() {
+        for (input, expected) in [
+            (
+                "git+https://github.com/microsoft/TypeScript.git",
+                "https://github.com/microsoft/TypeScript",
+            ),
+            ("github:foo/bar", "https://github.com/foo/bar"),
+            ("foo/bar", "https://github.com/foo/bar"),
+            ("git://github.com/foo/bar.git", "https://github.com/foo/bar"),
+        ] {
+            assert_eq!(map_repository(&NpmRepository::Url(input.into())), expected);
+        }
+    }
+}
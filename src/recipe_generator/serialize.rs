@@ -14,6 +14,8 @@ pub struct SourceElement {
 
 #[derive(Default, Debug, Serialize)]
 pub struct Build {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub noarch: Option<String>,
     pub script: String,
     #[serde(skip_serializing_if = "Python::is_default")]
     pub python: Python,
@@ -0,0 +1,349 @@
+//! A cache of the variants that were found in a recipe, so that rendering the
+//! same recipe again (e.g. repeated `--render-only` calls of bots, editors or
+//! CI matrix planners) does not evaluate the recipe again.
+//!
+//! The key is a hash of everything the render depends on: the recipe, the
+//! recipe fragments it includes, the script files whose variables are tracked,
+//! the variant configuration, the platforms and the render settings. Recipes
+//! that read the environment, git or other files, or that query a channel, are
+//! never cached. Old entries are evicted when a new entry is written (see
+//! [`MAX_AGE`] and [`MAX_SIZE`]).
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use indexmap::IndexSet;
+use rattler_conda_types::{NoArchType, Platform};
+use rattler_digest::{compute_bytes_digest, Sha256};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    hash::HashInfo,
+    normalized_key::NormalizedKey,
+    recipe::{custom_yaml::Node, parser::included_files, Recipe},
+    selectors::SelectorConfig,
+    used_variables::tracked_script_files,
+    variant_config::{DiscoveredOutput, VariantConfig},
+};
+
+/// Jinja features whose result does not only depend on the recipe files
+const UNCACHEABLE: &[&str] = &["env.", "env[", "git.", "load_from_file", "latest_version"];
+
+/// Entries that were written longer ago are removed
+pub const MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// The oldest entries are removed when the cache grows larger than this
+pub const MAX_SIZE: u64 = 100 * 1024 * 1024;
+
+/// A discovered output without its node (which is taken from the recipe)
+#[derive(Serialize, Deserialize)]
+struct CachedOutput {
+    node: usize,
+    name: String,
+    version: String,
+    build_string: String,
+    noarch_type: NoArchType,
+    target_platform: Platform,
+    used_vars: BTreeMap<NormalizedKey, String>,
+    recipe: Recipe,
+    hash: HashInfo,
+}
+
+/// The render cache in a directory, with one file per key
+#[derive(Debug, Clone)]
+pub struct RenderCache {
+    dir: PathBuf,
+}
+
+impl RenderCache {
+    /// Create a render cache in the given directory
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// The render cache in the rattler cache directory
+    pub fn default_cache() -> Option<Self> {
+        let cache_dir = rattler_cache::default_cache_dir().ok()?;
+        Some(Self::new(cache_dir.join("render-cache")))
+    }
+
+    /// The key of a render, or `None` if the recipe cannot be cached
+    pub fn key(
+        recipe: &str,
+        recipe_dir: Option<&Path>,
+        outputs: &[Node],
+        variant_config: &VariantConfig,
+        selector_config: &SelectorConfig,
+    ) -> Option<String> {
+        if UNCACHEABLE.iter().any(|feature| recipe.contains(feature)) {
+            return None;
+        }
+
+        let mut input = vec![
+            env!("CARGO_PKG_VERSION").to_string(),
+            recipe.to_string(),
+            serde_json::to_string(variant_config).ok()?,
+            selector_config.target_platform.to_string(),
+            selector_config.host_platform.to_string(),
+            selector_config.build_platform.to_string(),
+            selector_config.experimental.to_string(),
+            selector_config.strict_jinja.to_string(),
+            serde_json::to_string(&selector_config.hash_config).ok()?,
+            serde_json::to_string(&selector_config.env_access).ok()?,
+        ];
+
+        // the included recipe fragments and the script files that opt in to
+        // variable tracking are read during the render
+        if let Some(recipe_dir) = recipe_dir {
+            for path in included_files(recipe).ok()? {
+                let content = fs_err::read_to_string(recipe_dir.join(&path)).ok()?;
+                if UNCACHEABLE.iter().any(|feature| content.contains(feature)) {
+                    return None;
+                }
+                let digest = compute_bytes_digest::<Sha256>(content);
+                input.push(format!("{}:{:x}", path.display(), digest));
+            }
+            for path in outputs
+                .iter()
+                .flat_map(|output| tracked_script_files(output, recipe_dir))
+            {
+                let digest = compute_bytes_digest::<Sha256>(fs_err::read(&path).ok()?);
+                let relative = path.strip_prefix(recipe_dir).unwrap_or(&path);
+                input.push(format!("{}:{:x}", relative.display(), digest));
+            }
+        }
+
+        Some(format!(
+            "{:x}",
+            compute_bytes_digest::<Sha256>(input.join("\0"))
+        ))
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{key}.json"))
+    }
+
+    /// Returns the cached outputs for the key. The nodes of the outputs are
+    /// taken from the outputs of the recipe.
+    pub fn get(&self, key: &str, outputs: &[Node]) -> Option<IndexSet<DiscoveredOutput>> {
+        let content = fs_err::read_to_string(self.path(key)).ok()?;
+        let cached: Vec<CachedOutput> = match serde_json::from_str(&content) {
+            Ok(cached) => cached,
+            Err(e) => {
+                tracing::debug!("Ignoring invalid render cache entry {}: {}", key, e);
+                return None;
+            }
+        };
+
+        cached
+            .into_iter()
+            .map(|output| {
+                Some(DiscoveredOutput {
+                    name: output.name,
+                    version: output.version,
+                    build_string: output.build_string,
+                    noarch_type: output.noarch_type,
+                    target_platform: output.target_platform,
+                    node: outputs.get(output.node)?.clone(),
+                    used_vars: output.used_vars,
+                    recipe: output.recipe,
+                    hash: output.hash,
+                })
+            })
+            .collect()
+    }
+
+    /// Store the outputs for the key. Failures are only logged, as the cache
+    /// is an optimization.
+    pub fn insert(&self, key: &str, discovered: &IndexSet<DiscoveredOutput>, outputs: &[Node]) {
+        let cached = discovered
+            .iter()
+            .map(|output| {
+                Some(CachedOutput {
+                    node: outputs.iter().position(|node| node == &output.node)?,
+                    name: output.name.clone(),
+                    version: output.version.clone(),
+                    build_string: output.build_string.clone(),
+                    noarch_type: output.noarch_type,
+                    target_platform: output.target_platform,
+                    used_vars: output.used_vars.clone(),
+                    recipe: output.recipe.clone(),
+                    hash: output.hash.clone(),
+                })
+            })
+            .collect::<Option<Vec<_>>>();
+        let Some(cached) = cached else {
+            return;
+        };
+
+        let result = serde_json::to_string(&cached)
+            .map_err(std::io::Error::other)
+            .and_then(|content| {
+                fs_err::create_dir_all(&self.dir)?;
+                fs_err::write(self.path(key), content)
+            });
+        if let Err(e) = result {
+            tracing::debug!("Failed to write the render cache entry {}: {}", key, e);
+        }
+
+        self.evict(MAX_AGE, MAX_SIZE);
+    }
+
+    /// Remove the entries that are older than `max_age`, and the oldest
+    /// entries until the cache is not larger than `max_size`
+    fn evict(&self, max_age: Duration, max_size: u64) {
+        let Ok(entries) = fs_err::read_dir(&self.dir) else {
+            return;
+        };
+        let now = SystemTime::now();
+        let mut entries = entries
+            .filter_map(Result::ok)
+            .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "json"))
+            .filter_map(|entry| {
+                let metadata = entry.metadata().ok()?;
+                Some((metadata.modified().ok()?, metadata.len(), entry.path()))
+            })
+            .collect::<Vec<_>>();
+        // newest first
+        entries.sort_by(|a, b| b.0.cmp(&a.0));
+
+        let mut size = 0;
+        for (modified, len, path) in entries {
+            size += len;
+            let age = now.duration_since(modified).unwrap_or_default();
+            if age > max_age || size > max_size {
+                if let Err(e) = fs_err::remove_file(&path) {
+                    tracing::debug!("Failed to remove the render cache entry: {}", e);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recipe::parser::{find_outputs_from_recipe_file, find_outputs_from_src};
+
+    #[test]
+    fn test_render_cache_key() {
+        let recipe = "package:\n  name: foo\n  version: ${{ version }}\n";
+        let outputs = find_outputs_from_src(recipe).unwrap();
+        let selector_config = SelectorConfig::default();
+        let variant_config = VariantConfig::default();
+        let key = |recipe: &str,
+                   recipe_dir: Option<&Path>,
+                   variant_config: &VariantConfig,
+                   selector_config: &SelectorConfig| {
+            let outputs = match recipe_dir {
+                Some(recipe_dir) => find_outputs_from_recipe_file(recipe, recipe_dir, true),
+                None => find_outputs_from_src(recipe),
+            };
+            RenderCache::key(
+                recipe,
+                recipe_dir,
+                &outputs.unwrap(),
+                variant_config,
+                selector_config,
+            )
+        };
+
+        let base = RenderCache::key(recipe, None, &outputs, &variant_config, &selector_config);
+        assert!(base.is_some());
+        assert_eq!(key(recipe, None, &variant_config, &selector_config), base);
+
+        // every input is part of the key
+        let other_recipe = "package:\n  name: bar\n  version: ${{ version }}\n";
+        assert_ne!(
+            key(other_recipe, None, &variant_config, &selector_config),
+            base
+        );
+        let mut other_variants = variant_config.clone();
+        other_variants
+            .variants
+            .insert("python".into(), vec!["3.12".to_string()]);
+        assert_ne!(key(recipe, None, &other_variants, &selector_config), base);
+        let other_platform = SelectorConfig {
+            target_platform: Platform::LinuxAarch64,
+            ..selector_config.clone()
+        };
+        assert_ne!(key(recipe, None, &variant_config, &other_platform), base);
+
+        // included recipe fragments are part of the key
+        let recipe_dir = tempfile::tempdir().unwrap();
+        let dir = Some(recipe_dir.path());
+        let recipe = "include: about.yaml\npackage:\n  name: foo\n  version: '1.0'\n";
+        fs_err::write(
+            recipe_dir.path().join("about.yaml"),
+            "about:\n  license: MIT\n",
+        )
+        .unwrap();
+        let with_include = key(recipe, dir, &variant_config, &selector_config);
+        assert!(with_include.is_some());
+        fs_err::write(
+            recipe_dir.path().join("about.yaml"),
+            "about:\n  license: BSD\n",
+        )
+        .unwrap();
+        assert_ne!(
+            key(recipe, dir, &variant_config, &selector_config),
+            with_include
+        );
+
+        // other files in the recipe directory are not
+        let with_include = key(recipe, dir, &variant_config, &selector_config);
+        fs_err::write(recipe_dir.path().join("build.sh"), "echo $python").unwrap();
+        assert_eq!(
+            key(recipe, dir, &variant_config, &selector_config),
+            with_include
+        );
+
+        // unless they are scripts whose variables are tracked
+        let recipe = "package:\n  name: foo\n  version: '1.0'\n\
+                      build:\n  script:\n    track_variables: true\n";
+        let with_script = key(recipe, dir, &variant_config, &selector_config);
+        assert!(with_script.is_some());
+        fs_err::write(recipe_dir.path().join("build.sh"), "echo $numpy").unwrap();
+        assert_ne!(
+            key(recipe, dir, &variant_config, &selector_config),
+            with_script
+        );
+
+        // recipes that read the environment are not cached, also if the
+        // environment is read in an included fragment
+        let recipe = "package:\n  name: foo\n  version: ${{ env.get('VERSION') }}\n";
+        assert!(key(recipe, None, &variant_config, &selector_config).is_none());
+        fs_err::write(
+            recipe_dir.path().join("about.yaml"),
+            "about:\n  summary: ${{ env.get('SUMMARY') }}\n",
+        )
+        .unwrap();
+        let recipe = "include: about.yaml\npackage:\n  name: foo\n  version: '1.0'\n";
+        assert!(key(recipe, dir, &variant_config, &selector_config).is_none());
+    }
+
+    #[test]
+    fn test_evict() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = RenderCache::new(dir.path().to_path_buf());
+        for key in ["a", "b", "c"] {
+            fs_err::write(cache.path(key), "x".repeat(10)).unwrap();
+            // make sure the modification times differ
+            std::thread::sleep(Duration::from_millis(20));
+        }
+
+        // the oldest entry is removed to stay within the size
+        cache.evict(MAX_AGE, 25);
+        assert!(!cache.path("a").exists());
+        assert!(cache.path("b").exists());
+        assert!(cache.path("c").exists());
+
+        // all entries are older than zero seconds
+        cache.evict(Duration::ZERO, MAX_SIZE);
+        assert!(!cache.path("b").exists());
+        assert!(!cache.path("c").exists());
+    }
+}
@@ -0,0 +1,237 @@
+//! Explain environments that cannot be solved.
+//!
+//! When a solve fails, it is retried with iteratively relaxed specs (the
+//! version and build constraints of the newest specs are dropped first) until
+//! a solvable set of specs is found. Afterwards, every relaxed spec that is
+//! not required to make the environment solvable is restored again. The
+//! remaining difference (the "closest solvable set") is reported together with
+//! the explanation of the solver. Relaxing a spec never makes an environment
+//! unsolvable, so sets of specs that are at least as strict as a set that
+//! failed to solve are not solved again.
+use std::{fmt, str::FromStr};
+
+use rattler_conda_types::{MatchSpec, ParseStrictness, RepoDataRecord};
+
+/// The maximum number of additional solves that are attempted to find the
+/// closest solvable set of specs
+const MAX_ATTEMPTS: usize = 16;
+
+/// Returns the spec without any version or build constraints, or `None` if
+/// there is nothing to relax
+fn relax(spec: &MatchSpec) -> Option<MatchSpec> {
+    let name = spec.name.as_ref()?;
+    if spec.version.is_none() && spec.build.is_none() && spec.build_number.is_none() {
+        return None;
+    }
+    MatchSpec::from_str(name.as_source(), ParseStrictness::Strict).ok()
+}
+
+/// Find the closest solvable set of specs by relaxing the specs from the last
+/// (newest) to the first, and then restoring all relaxed specs that are not
+/// needed to make the environment solvable. Returns the indices of the
+/// relaxed specs together with the solution, or `None` if no solvable set was
+/// found within [`MAX_ATTEMPTS`] solves.
+fn closest_solvable<R, E>(
+    specs: &[MatchSpec],
+    mut solve: impl FnMut(&[MatchSpec]) -> Result<R, E>,
+) -> Option<(Vec<usize>, R)> {
+    let mut current = specs.to_vec();
+    let mut relaxed = Vec::new();
+    let mut attempts = 0;
+    let mut solution = None;
+    // the indices of the relaxed specs of the sets that failed to solve (the
+    // original specs failed already)
+    let mut failed = vec![Vec::new()];
+
+    for idx in (0..specs.len()).rev() {
+        if attempts >= MAX_ATTEMPTS {
+            break;
+        }
+        let Some(relaxed_spec) = relax(&specs[idx]) else {
+            continue;
+        };
+        current[idx] = relaxed_spec;
+        relaxed.push(idx);
+        attempts += 1;
+        match solve(&current) {
+            Ok(result) => {
+                solution = Some(result);
+                break;
+            }
+            Err(_) => failed.push(relaxed.clone()),
+        }
+    }
+    let mut solution = solution?;
+
+    // restore the specs that were relaxed first (the newest ones) last, as
+    // they are the most likely to be part of the conflict
+    for idx in relaxed.clone().into_iter().rev() {
+        let trial_relaxed = relaxed
+            .iter()
+            .copied()
+            .filter(|i| *i != idx)
+            .collect::<Vec<_>>();
+        if failed
+            .iter()
+            .any(|f| trial_relaxed.iter().all(|i| f.contains(i)))
+        {
+            continue;
+        }
+        if attempts >= MAX_ATTEMPTS {
+            break;
+        }
+        let mut trial = current.clone();
+        trial[idx] = specs[idx].clone();
+        attempts += 1;
+        match solve(&trial) {
+            Ok(result) => {
+                current = trial;
+                solution = result;
+                relaxed = trial_relaxed;
+            }
+            Err(_) => failed.push(trial_relaxed),
+        }
+    }
+
+    relaxed.sort_unstable();
+    Some((relaxed, solution))
+}
+
+/// A spec that has to be relaxed to make the environment solvable
+#[derive(Debug, Clone)]
+pub struct RelaxedSpec {
+    /// The spec as it was requested
+    pub spec: MatchSpec,
+    /// The package that is selected for the relaxed spec in the closest
+    /// solvable set
+    pub selected: Option<RepoDataRecord>,
+}
+
+/// The environment could not be solved
+#[derive(Debug)]
+pub struct SolveConflict {
+    /// The name of the environment (e.g. `host`)
+    pub environment: String,
+    /// The explanation of the solver
+    pub explanation: String,
+    /// The specs that need to be relaxed to make the environment solvable.
+    /// `None` if no solvable set of specs was found.
+    pub relaxed: Option<Vec<RelaxedSpec>>,
+}
+
+impl SolveConflict {
+    /// Retry the solve with relaxed specs to find the closest solvable set of
+    /// specs and create the conflict report
+    pub fn new<E>(
+        environment: &str,
+        specs: &[MatchSpec],
+        explanation: String,
+        solve: impl FnMut(&[MatchSpec]) -> Result<Vec<RepoDataRecord>, E>,
+    ) -> Self {
+        let relaxed = closest_solvable(specs, solve).map(|(indices, solution)| {
+            indices
+                .into_iter()
+                .map(|idx| {
+                    let spec = specs[idx].clone();
+                    let selected = solution
+                        .iter()
+                        .find(|record| Some(&record.package_record.name) == spec.name.as_ref())
+                        .cloned();
+                    RelaxedSpec { spec, selected }
+                })
+                .collect()
+        });
+
+        Self {
+            environment: environment.to_string(),
+            explanation,
+            relaxed,
+        }
+    }
+}
+
+impl fmt::Display for SolveConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Failed to solve the {} environment.", self.environment)?;
+        writeln!(f)?;
+
+        match &self.relaxed {
+            Some(relaxed) => {
+                writeln!(
+                    f,
+                    "The following specs cannot be satisfied together with the rest of the environment:"
+                )?;
+                for RelaxedSpec { spec, selected } in relaxed {
+                    match selected {
+                        Some(record) => writeln!(
+                            f,
+                            "  - {} (the closest solvable environment contains {} {} {})",
+                            spec,
+                            record.package_record.name.as_normalized(),
+                            record.package_record.version,
+                            record.package_record.build
+                        )?,
+                        None => writeln!(f, "  - {}", spec)?,
+                    }
+                }
+            }
+            None => writeln!(
+                f,
+                "No solvable environment was found by relaxing the version constraints of the specs."
+            )?,
+        }
+
+        writeln!(f)?;
+        writeln!(f, "Solver explanation:")?;
+        for line in self.explanation.lines() {
+            writeln!(f, "  {}", line)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for SolveConflict {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(s: &str) -> MatchSpec {
+        MatchSpec::from_str(s, ParseStrictness::Strict).unwrap()
+    }
+
+    #[test]
+    fn test_closest_solvable() {
+        let specs = vec![
+            spec("python 3.12.*"),
+            spec("numpy >=2.1"),
+            spec("zlib 1.3.*"),
+        ];
+
+        // `numpy >=2.1` does not exist for python 3.12 in this fake world
+        let mut solves = 0;
+        let solve = |specs: &[MatchSpec]| -> Result<(), ()> {
+            solves += 1;
+            let strict_numpy = specs[1].version.is_some();
+            let strict_python = specs[0].version.is_some();
+            if strict_numpy && strict_python {
+                Err(())
+            } else {
+                Ok(())
+            }
+        };
+
+        let (relaxed, _) = closest_solvable(&specs, solve).unwrap();
+        assert_eq!(relaxed, vec![1]);
+        // restoring `numpy >=2.1` gives the set that failed before, it is not
+        // solved again
+        assert_eq!(solves, 3);
+
+        // nothing can be solved
+        let solve = |_: &[MatchSpec]| -> Result<(), ()> { Err(()) };
+        assert!(closest_solvable(&specs, solve).is_none());
+
+        assert!(relax(&spec("python")).is_none());
+        assert_eq!(relax(&spec("python >=3.8")).unwrap().to_string(), "python");
+    }
+}
@@ -1,6 +1,8 @@
 #![allow(missing_docs)]
 //! Render the dependencies to a final recipe
 
+pub mod cache;
+pub mod conflict;
 pub mod pin;
 pub mod resolved_dependencies;
 mod run_exports;
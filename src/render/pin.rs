@@ -58,6 +58,34 @@ impl FromStr for PinBound {
     }
 }
 
+impl PinExpression {
+    /// The number of version segments of the pin expression (e.g. 2 for `x.x`)
+    fn segments(&self) -> Result<usize, PinError> {
+        match self.0.chars().filter(|c| *c == 'x').count() {
+            0 => Err(PinError::EmptyPinExpression),
+            segments => Ok(segments),
+        }
+    }
+
+    /// The lower bound of the pin for a version (e.g. `1.2` for `x.x` and
+    /// `1.2.3`)
+    pub fn lower_bound(&self, version: &Version) -> Result<Version, PinError> {
+        let pin_digits = self.segments()?;
+        version
+            .clone()
+            .with_segments(..cmp::min(pin_digits, version.segment_count()))
+            .ok_or_else(|| {
+                PinError::CouldNotPin("Failed to extract min_pin from version".to_string())
+            })
+    }
+
+    /// The (exclusive) upper bound of the pin for a version (e.g. `1.3.0a0`
+    /// for `x.x` and `1.2.3`)
+    pub fn upper_bound(&self, version: &Version) -> Result<Version, PinError> {
+        Ok(increment(version, self.segments()? as i32)?)
+    }
+}
+
 impl Display for PinExpression {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.0)
@@ -165,20 +193,7 @@ impl Pin {
         // extract same amount of digits as the pin expression (in the form of x.x.x) from version str
         match self.args.lower_bound.as_ref() {
             Some(PinBound::Expression(expression)) => {
-                // number of digits in pin expression
-                let pin_digits = expression.0.chars().filter(|c| *c == 'x').count();
-                if pin_digits == 0 {
-                    return Err(PinError::EmptyPinExpression);
-                }
-
-                // get version string up the to pin_digits dot
-                let pin = version
-                    .clone()
-                    .with_segments(..cmp::min(pin_digits, version.segment_count()))
-                    .ok_or_else(|| {
-                        PinError::CouldNotPin("Failed to extract min_pin from version".to_string())
-                    })?;
-                pin_str.push_str(&format!(">={}", pin));
+                pin_str.push_str(&format!(">={}", expression.lower_bound(version)?));
             }
             Some(PinBound::Version(version)) => {
                 pin_str.push_str(&format!(">={}", version));
@@ -188,14 +203,7 @@ impl Pin {
 
         match self.args.upper_bound.as_ref() {
             Some(PinBound::Expression(expression)) => {
-                // number of digits in pin expression
-                let pin_digits = expression.0.chars().filter(|c| *c == 'x').count();
-                if pin_digits == 0 {
-                    return Err(PinError::EmptyPinExpression);
-                }
-
-                // increment last digit
-                let pin = increment(version, pin_digits as i32)?;
+                let pin = expression.upper_bound(version)?;
 
                 if !pin_str.is_empty() {
                     pin_str.push(',')
@@ -1,6 +1,6 @@
 use std::{
     borrow::Cow,
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     fmt::{Display, Formatter},
     str::FromStr,
     sync::Arc,
@@ -11,8 +11,7 @@ use rattler::install::Placement;
 use rattler_cache::package_cache::PackageCache;
 use rattler_conda_types::{
     package::RunExportsJson, version_spec::ParseVersionSpecError, ChannelUrl, MatchSpec,
-    PackageName, PackageRecord, ParseStrictness, Platform, RepoDataRecord, StringMatcher,
-    VersionSpec,
+    PackageName, ParseStrictness, Platform, RepoDataRecord, StringMatcher, VersionSpec,
 };
 use reqwest_middleware::ClientWithMiddleware;
 use serde::{Deserialize, Serialize};
@@ -26,12 +25,13 @@ use crate::{
     package_cache_reporter::PackageCacheReporter,
     recipe::parser::{Dependency, Requirements},
     render::{
-        pin::PinArgs,
+        pin::{Pin, PinArgs, PinBound},
         solver::{install_packages, solve_environment},
     },
     run_exports::{RunExportExtractor, RunExportExtractorError},
     tool_configuration,
     tool_configuration::Configuration,
+    variant_config,
 };
 
 /// A enum to keep track of where a given Dependency comes from
@@ -113,6 +113,11 @@ pub struct PinCompatibleDependency {
 
     #[serde_as(as = "DisplayFromStr")]
     pub spec: MatchSpec,
+
+    /// The channel of the host package that the pin was created from (only
+    /// recorded for `exact=True` pins)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<String>,
 }
 
 impl From<PinCompatibleDependency> for DependencyInfo {
@@ -185,9 +190,13 @@ impl DependencyInfo {
                 DependencyInfo::PinSubpackage(spec) => {
                     format!("{} (from pin subpackage)", &spec.spec)
                 }
-                DependencyInfo::PinCompatible(spec) => {
-                    format!("{} (from pin compatible)", &spec.spec)
-                }
+                DependencyInfo::PinCompatible(spec) => match &spec.channel {
+                    Some(channel) => format!(
+                        "{} (from pin compatible, installed from {})",
+                        &spec.spec, channel
+                    ),
+                    None => format!("{} (from pin compatible)", &spec.spec),
+                },
                 DependencyInfo::RunExport(spec) => format!(
                     "{} (run export by {} in {} env)",
                     &spec.spec, &spec.from, &spec.source_package
@@ -241,6 +250,10 @@ pub struct FinalizedRunDependencies {
     pub constraints: Vec<DependencyInfo>,
     #[serde(default, skip_serializing_if = "RunExportsJson::is_empty")]
     pub run_exports: RunExportsJson,
+    /// The run exports of the build and host environments (and of the cache)
+    /// that were removed by `ignore_run_exports`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ignored_run_exports: Vec<DependencyInfo>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -391,6 +404,20 @@ impl FinalizedRunDependencies {
             });
         }
 
+        if !self.ignored_run_exports.is_empty() {
+            let mut row = comfy_table::Row::new();
+            row.add_cell(
+                comfy_table::Cell::new("Ignored run exports")
+                    .add_attribute(comfy_table::Attribute::Bold),
+            );
+            table.add_row(row);
+
+            self.ignored_run_exports.iter().for_each(|d| {
+                let rendered = d.render(long);
+                table.add_row(rendered.splitn(2, ' ').collect::<Vec<&str>>());
+            });
+        }
+
         table
     }
 }
@@ -453,7 +480,7 @@ pub enum ResolveError {
 pub fn apply_variant(
     raw_specs: &[Dependency],
     build_configuration: &BuildConfiguration,
-    compatibility_specs: &HashMap<PackageName, PackageRecord>,
+    compatibility_specs: &HashMap<PackageName, RepoDataRecord>,
     build_time: bool,
 ) -> Result<Vec<DependencyInfo>, ResolveError> {
     let variant = &build_configuration.variant;
@@ -525,13 +552,23 @@ pub fn apply_variant(
                         .get(name)
                         .ok_or(ResolveError::SubpackageNotFound(name.to_owned()))?;
 
-                    let pinned = pin
+                    let pinned = pin.pin_value().apply(
+                        &pin_package.package_record.version,
+                        &pin_package.package_record.build,
+                    )?;
+                    // an exact pin refers to the very package that was
+                    // installed in the host environment, including its channel
+                    let channel = pin
                         .pin_value()
-                        .apply(&pin_package.version, &pin_package.build)?;
+                        .args
+                        .exact
+                        .then(|| short_channel(pin_package.channel.as_deref()))
+                        .filter(|channel| !channel.is_empty());
                     Ok(PinCompatibleDependency {
                         spec: pinned,
                         name: name.as_normalized().to_string(),
                         args: pin.pin_value().args.clone(),
+                        channel,
                     }
                     .into())
                 }
@@ -540,6 +577,86 @@ pub fn apply_variant(
         .collect()
 }
 
+/// Pin the run dependencies of the packages of `pin_run_as_build` (from the
+/// variant config) that are direct host dependencies to the version that is
+/// installed in the host environment. Only run dependencies without a version
+/// or build constraint are replaced by the pin, no run dependencies are added.
+fn apply_pin_run_as_build(
+    depends: &mut Vec<DependencyInfo>,
+    pin_run_as_build: &BTreeMap<String, variant_config::Pin>,
+    host_env: Option<&ResolvedDependencies>,
+) -> Result<(), ResolveError> {
+    let Some(host_env) = host_env else {
+        return Ok(());
+    };
+
+    let parse_bound = |bound: Option<&str>, default: Option<PinBound>| {
+        bound
+            .map(PinBound::from_str)
+            .transpose()
+            .map(|bound| bound.or(default))
+            .map_err(PinError::from)
+    };
+
+    for (name, pin) in pin_run_as_build {
+        let Ok(name) = PackageName::from_str(name) else {
+            tracing::warn!("Invalid package name in pin_run_as_build: {}", name);
+            continue;
+        };
+
+        let is_host_dependency = host_env
+            .specs
+            .iter()
+            .any(|dep| dep.spec().name.as_ref() == Some(&name));
+        let Some(record) = host_env
+            .resolved
+            .iter()
+            .find(|record| record.package_record.name == name)
+        else {
+            continue;
+        };
+        if !is_host_dependency {
+            continue;
+        }
+
+        let Some(position) = depends.iter().position(|dep| {
+            let spec = dep.spec();
+            spec.name.as_ref() == Some(&name) && spec.version.is_none() && spec.build.is_none()
+        }) else {
+            continue;
+        };
+        let is_constrained = depends.iter().any(|dep| {
+            let spec = dep.spec();
+            spec.name.as_ref() == Some(&name) && (spec.version.is_some() || spec.build.is_some())
+        });
+        if is_constrained {
+            continue;
+        }
+
+        let default_args = PinArgs::default();
+        let args = PinArgs {
+            lower_bound: parse_bound(pin.min_pin.as_deref(), default_args.lower_bound)?,
+            upper_bound: parse_bound(pin.max_pin.as_deref(), default_args.upper_bound)?,
+            ..default_args
+        };
+        let spec = Pin {
+            name: name.clone(),
+            args: args.clone(),
+        }
+        .apply(&record.package_record.version, &record.package_record.build)?;
+
+        depends[position] = PinCompatibleDependency {
+            spec,
+            name: name.as_normalized().to_string(),
+            args,
+            channel: None,
+        }
+        .into();
+    }
+
+    Ok(())
+}
+
 /// Collect run exports from the package cache and add them to the package
 /// records.
 /// TODO: There are many ways that would allow us to optimize this function.
@@ -631,7 +748,7 @@ pub async fn install_environments(
 /// specifications to the run exports.
 fn render_run_exports(
     output: &Output,
-    compatibility_specs: &HashMap<PackageName, PackageRecord>,
+    compatibility_specs: &HashMap<PackageName, RepoDataRecord>,
 ) -> Result<RunExportsJson, ResolveError> {
     let render_run_exports = |run_export: &[Dependency]| -> Result<Vec<String>, ResolveError> {
         let rendered = apply_variant(
@@ -702,6 +819,7 @@ pub(crate) async fn resolve_dependencies(
             tool_configuration,
             output.build_configuration.channel_priority,
             output.build_configuration.solve_strategy,
+            None,
         )
         .await
         .map_err(ResolveError::from)?;
@@ -728,7 +846,7 @@ pub(crate) async fn resolve_dependencies(
             .map_err(ResolveError::CouldNotCollectRunExports)?;
 
         resolved.iter().for_each(|r| {
-            compatibility_specs.insert(r.package_record.name.clone(), r.package_record.clone());
+            compatibility_specs.insert(r.package_record.name.clone(), r.clone());
         });
 
         Some(ResolvedDependencies {
@@ -798,6 +916,7 @@ pub(crate) async fn resolve_dependencies(
             tool_configuration,
             output.build_configuration.channel_priority,
             output.build_configuration.solve_strategy,
+            None,
         )
         .await
         .map_err(ResolveError::from)?;
@@ -824,7 +943,7 @@ pub(crate) async fn resolve_dependencies(
             .map_err(ResolveError::CouldNotCollectRunExports)?;
 
         resolved.iter().for_each(|r| {
-            compatibility_specs.insert(r.package_record.name.clone(), r.package_record.clone());
+            compatibility_specs.insert(r.package_record.name.clone(), r.clone());
         });
 
         Some(ResolvedDependencies {
@@ -842,6 +961,16 @@ pub(crate) async fn resolve_dependencies(
         false,
     )?;
 
+    // `noarch` packages do not depend on the binary compatibility of the
+    // host environment
+    if output.recipe.build().noarch().is_none() {
+        apply_pin_run_as_build(
+            &mut depends,
+            &output.build_configuration.pin_run_as_build,
+            host_env.as_ref(),
+        )?;
+    }
+
     let mut constraints = apply_variant(
         &requirements.run_constraints,
         &output.build_configuration,
@@ -917,34 +1046,19 @@ pub(crate) async fn resolve_dependencies(
         constraints.extend(host_run_exports.weak_constraints.iter().cloned());
     }
 
-    if let Some(cache) = &output.finalized_cache_dependencies {
-        // add in the run exports from the cache
-        // filter run dependencies that came from run exports
-        let ignore_run_exports = requirements.ignore_run_exports(None);
-        // Note: these run exports are already filtered
-        let _cache_run_exports = cache.run.depends.iter().filter(|c| match c {
-            DependencyInfo::RunExport(run_export) => {
-                let source_package: Option<PackageName> = run_export.source_package.parse().ok();
-                let spec_name = &run_export.spec.name;
-
-                let by_name = spec_name
-                    .as_ref()
-                    .map(|n| ignore_run_exports.by_name().contains(n))
-                    .unwrap_or(false);
-                let by_package = source_package
-                    .map(|s| ignore_run_exports.from_package().contains(&s))
-                    .unwrap_or(false);
-
-                !by_name && !by_package
-            }
-            _ => false,
-        });
-    }
+    // the run exports that were ignored are only recorded for the rendered
+    // output
+    let ignored_run_exports = build_run_exports
+        .ignored
+        .into_iter()
+        .chain(host_run_exports.ignored)
+        .collect();
 
     let run_specs = FinalizedRunDependencies {
         depends,
         constraints,
         run_exports: rendered_run_exports,
+        ignored_run_exports,
     };
 
     // log a table of the rendered run dependencies
@@ -998,6 +1112,9 @@ impl Output {
         &self,
         tool_configuration: &Configuration,
     ) -> Result<(), ResolveError> {
+        let span = tracing::info_span!("Installing environments");
+        let _enter = span.enter();
+
         let dependencies = self
             .finalized_dependencies
             .as_ref()
@@ -1044,6 +1161,7 @@ mod tests {
                     exact: true,
                     ..Default::default()
                 },
+                channel: None,
             }
             .into(),
         ];
@@ -1058,4 +1176,100 @@ mod tests {
         assert!(matches!(dep_info[2], DependencyInfo::PinSubpackage(_)));
         assert!(matches!(dep_info[3], DependencyInfo::PinCompatible(_)));
     }
+
+    #[test]
+    fn test_exact_pin_compatible_channel() {
+        let dep: DependencyInfo = PinCompatibleDependency {
+            name: "bat".to_string(),
+            spec: MatchSpec::from_str("bat ==1.2.3 h1234_0", ParseStrictness::Strict).unwrap(),
+            args: PinArgs {
+                exact: true,
+                ..Default::default()
+            },
+            channel: Some(short_channel(Some(
+                "https://conda.anaconda.org/conda-forge/",
+            ))),
+        }
+        .into();
+
+        assert_eq!(
+            dep.render(true),
+            "bat ==1.2.3 h1234_0 (from pin compatible, installed from conda-forge)"
+        );
+
+        let yaml_str = serde_yaml::to_string(&dep).unwrap();
+        assert!(yaml_str.contains("channel: conda-forge"));
+        let dep: DependencyInfo = serde_yaml::from_str(&yaml_str).unwrap();
+        assert_eq!(
+            dep.as_pin_compatible().unwrap().channel.as_deref(),
+            Some("conda-forge")
+        );
+    }
+
+    #[test]
+    fn test_pin_run_as_build() {
+        let spec = |s: &str| MatchSpec::from_str(s, ParseStrictness::Strict).unwrap();
+        let record = |name: &str, version: &str| RepoDataRecord {
+            package_record: rattler_conda_types::PackageRecord::new(
+                PackageName::from_str(name).unwrap(),
+                rattler_conda_types::Version::from_str(version).unwrap(),
+                "h123_0".to_string(),
+            ),
+            file_name: format!("{name}-{version}-h123_0.conda"),
+            url: url::Url::parse("https://example.com/").unwrap(),
+            channel: None,
+        };
+        let host_env = ResolvedDependencies {
+            specs: vec![
+                SourceDependency {
+                    spec: spec("libpng"),
+                }
+                .into(),
+                SourceDependency { spec: spec("zlib") }.into(),
+                SourceDependency {
+                    spec: spec("openssl"),
+                }
+                .into(),
+            ],
+            resolved: vec![
+                record("libpng", "1.6.43"),
+                record("zlib", "1.3.1"),
+                record("openssl", "3.2.1"),
+                record("libzlib", "1.3.1"),
+            ],
+        };
+
+        let pin_run_as_build: BTreeMap<String, variant_config::Pin> = serde_yaml::from_str(
+            r#"
+            libpng:
+              max_pin: x.x
+            zlib:
+              max_pin: x.x
+            openssl:
+              max_pin: x
+            libzlib:
+              max_pin: x
+            "#,
+        )
+        .unwrap();
+
+        let mut depends: Vec<DependencyInfo> = vec![
+            SourceDependency { spec: spec("zlib") }.into(),
+            SourceDependency {
+                spec: spec("openssl >=3.0"),
+            }
+            .into(),
+        ];
+        apply_pin_run_as_build(&mut depends, &pin_run_as_build, Some(&host_env)).unwrap();
+
+        let rendered = depends
+            .iter()
+            .map(|dep| dep.spec().to_string())
+            .collect::<Vec<_>>();
+        // explicit constraints are kept, bare run dependencies are replaced
+        // and host dependencies that are not run dependencies are not added
+        assert_eq!(rendered, vec!["zlib >=1.3.1,<1.4.0a0", "openssl >=3.0"]);
+        assert!(depends[0].as_pin_compatible().is_some());
+        assert!(depends[1].as_pin_compatible().is_none());
+    }
 }
@@ -16,6 +16,8 @@ pub struct FilteredRunExports {
     pub strong_constraints: Vec<DependencyInfo>,
     pub weak: Vec<DependencyInfo>,
     pub weak_constraints: Vec<DependencyInfo>,
+    /// The run exports that were removed by `ignore_run_exports`
+    pub ignored: Vec<DependencyInfo>,
 }
 
 impl FilteredRunExports {
@@ -28,6 +30,7 @@ impl FilteredRunExports {
         self.weak.extend(other.weak.iter().cloned());
         self.weak_constraints
             .extend(other.weak_constraints.iter().cloned());
+        self.ignored.extend(other.ignored.iter().cloned());
     }
 }
 
@@ -37,34 +40,8 @@ impl IgnoreRunExports {
         run_export_map: &HashMap<PackageName, RunExportsJson>,
         from_env: &str,
     ) -> Result<FilteredRunExports, ParseMatchSpecError> {
-        let mut run_export_map = run_export_map.clone();
-        run_export_map.retain(|name, _| !self.from_package().contains(name));
-
         let mut filtered_run_exports = FilteredRunExports::default();
 
-        let to_specs = |strings: &Vec<String>| -> Result<Vec<MatchSpec>, ParseMatchSpecError> {
-            strings
-                .iter()
-                // We have to parse these as lenient as they come from packages
-                .map(|s| MatchSpec::from_str(s, ParseStrictness::Lenient))
-                .filter_map(|result| match result {
-                    Ok(spec) => {
-                        if spec
-                            .name
-                            .as_ref()
-                            .map(|n| !self.by_name().contains(n))
-                            .unwrap_or(false)
-                        {
-                            Some(Ok(spec))
-                        } else {
-                            None
-                        }
-                    }
-                    Err(e) => Some(Err(e)),
-                })
-                .collect()
-        };
-
         let as_dependency = |spec: MatchSpec, name: &PackageName| -> DependencyInfo {
             DependencyInfo::RunExport(RunExportDependency {
                 spec,
@@ -73,38 +50,117 @@ impl IgnoreRunExports {
             })
         };
 
-        for (name, run_export) in run_export_map.iter() {
-            if self.from_package().contains(name) {
-                continue;
+        // Sort the run exports into the kept and the ignored ones (the latter
+        // are only recorded so that they show up in the rendered output)
+        let mut ignored = Vec::new();
+        let mut sort_specs = |strings: &Vec<String>,
+                              name: &PackageName,
+                              ignored_package: bool|
+         -> Result<Vec<DependencyInfo>, ParseMatchSpecError> {
+            let mut kept = Vec::new();
+            for string in strings {
+                // We have to parse these as lenient as they come from packages
+                let spec = match MatchSpec::from_str(string, ParseStrictness::Lenient) {
+                    Ok(spec) => spec,
+                    // the run exports of ignored packages do not have to be valid
+                    Err(_) if ignored_package => continue,
+                    Err(e) => return Err(e),
+                };
+                let ignored_name = spec
+                    .name
+                    .as_ref()
+                    .map(|n| self.by_name().contains(n))
+                    .unwrap_or(true);
+                if ignored_package || ignored_name {
+                    ignored.push(as_dependency(spec, name));
+                } else {
+                    kept.push(as_dependency(spec, name));
+                }
             }
+            Ok(kept)
+        };
+
+        for (name, run_export) in run_export_map.iter() {
+            let ignored_package = self.from_package().contains(name);
 
-            filtered_run_exports.noarch.extend(
-                to_specs(&run_export.noarch)?
-                    .into_iter()
-                    .map(|d| as_dependency(d, name)),
-            );
-            filtered_run_exports.strong.extend(
-                to_specs(&run_export.strong)?
-                    .into_iter()
-                    .map(|d| as_dependency(d, name)),
-            );
-            filtered_run_exports.strong_constraints.extend(
-                to_specs(&run_export.strong_constrains)?
-                    .into_iter()
-                    .map(|d| as_dependency(d, name)),
-            );
-            filtered_run_exports.weak.extend(
-                to_specs(&run_export.weak)?
-                    .into_iter()
-                    .map(|d| as_dependency(d, name)),
-            );
-            filtered_run_exports.weak_constraints.extend(
-                to_specs(&run_export.weak_constrains)?
-                    .into_iter()
-                    .map(|d| as_dependency(d, name)),
-            );
+            filtered_run_exports.noarch.extend(sort_specs(
+                &run_export.noarch,
+                name,
+                ignored_package,
+            )?);
+            filtered_run_exports.strong.extend(sort_specs(
+                &run_export.strong,
+                name,
+                ignored_package,
+            )?);
+            filtered_run_exports.strong_constraints.extend(sort_specs(
+                &run_export.strong_constrains,
+                name,
+                ignored_package,
+            )?);
+            filtered_run_exports
+                .weak
+                .extend(sort_specs(&run_export.weak, name, ignored_package)?);
+            filtered_run_exports.weak_constraints.extend(sort_specs(
+                &run_export.weak_constrains,
+                name,
+                ignored_package,
+            )?);
         }
+        filtered_run_exports.ignored = ignored;
 
         Ok(filtered_run_exports)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn test_filter_records_ignored() {
+        let name = |s: &str| PackageName::from_str(s).unwrap();
+        let run_exports = HashMap::from([
+            (
+                name("libfoo"),
+                RunExportsJson {
+                    weak: vec!["libfoo >=1.0".to_string(), "libfoo-base".to_string()],
+                    ..Default::default()
+                },
+            ),
+            (
+                name("compiler"),
+                RunExportsJson {
+                    strong: vec!["libgcc >=13".to_string()],
+                    ..Default::default()
+                },
+            ),
+        ]);
+
+        let ignore = IgnoreRunExports {
+            by_name: [name("libfoo-base")].into_iter().collect(),
+            from_package: [name("compiler")].into_iter().collect(),
+        };
+        let filtered = ignore.filter(&run_exports, "cache-host").unwrap();
+
+        let render = |deps: &[DependencyInfo]| {
+            let mut rendered = deps.iter().map(|d| d.render(false)).collect::<Vec<_>>();
+            rendered.sort();
+            rendered
+        };
+        assert_eq!(
+            render(&filtered.weak),
+            vec!["libfoo >=1.0 (RE of [cache-host: libfoo])"]
+        );
+        assert!(filtered.strong.is_empty());
+        assert_eq!(
+            render(&filtered.ignored),
+            vec![
+                "libfoo-base (RE of [cache-host: libfoo])",
+                "libgcc >=13 (RE of [cache-host: compiler])",
+            ]
+        );
+    }
+}
@@ -1,21 +1,31 @@
 use std::{
-    future::IntoFuture,
+    future::{Future, IntoFuture},
     path::Path,
     sync::{Arc, Mutex},
 };
 
 use anyhow::Context;
+use chrono::{DateTime, Utc};
 use comfy_table::Table;
 use console::style;
-use futures::FutureExt;
+use futures::{FutureExt, StreamExt, TryStreamExt};
 use indicatif::{HumanBytes, ProgressBar, ProgressStyle};
 use itertools::Itertools;
-use rattler::install::{DefaultProgressFormatter, IndicatifReporter, Installer};
+use rattler::install::{DefaultProgressFormatter, IndicatifReporter, Installer, Placement};
+use rattler_cache::package_cache::CacheKey;
 use rattler_conda_types::{Channel, ChannelUrl, MatchSpec, Platform, PrefixRecord, RepoDataRecord};
-use rattler_solve::{resolvo::Solver, ChannelPriority, SolveStrategy, SolverImpl, SolverTask};
+use rattler_networking::retry_policies::default_retry_policy;
+use rattler_solve::{
+    resolvo::Solver, ChannelPriority, SolveError, SolveStrategy, SolverImpl, SolverTask,
+};
+use tracing::Instrument;
 use url::Url;
 
-use crate::{metadata::PlatformWithVirtualPackages, packaging::Files, tool_configuration};
+use crate::{
+    build_profile::ProfileCategory, metadata::PlatformWithVirtualPackages,
+    package_cache_reporter::PackageCacheReporter, packaging::Files,
+    render::conflict::SolveConflict, tool_configuration,
+};
 
 fn print_as_table(packages: &[RepoDataRecord]) {
     let mut table = Table::new();
@@ -55,6 +65,22 @@ fn print_as_table(packages: &[RepoDataRecord]) {
     tracing::info!("\n{table}");
 }
 
+/// Await the future, unless the build is cancelled (e.g. with Ctrl-C) before it completes.
+async fn cancellable<T>(
+    tool_configuration: &tool_configuration::Configuration,
+    future: impl Future<Output = anyhow::Result<T>>,
+) -> anyhow::Result<T> {
+    tokio::select! {
+        result = future => result,
+        _ = tool_configuration.cancellation_token.cancelled() => {
+            Err(anyhow::anyhow!("the build was cancelled"))
+        }
+    }
+}
+
+/// Solve an environment. Packages that were published after `exclude_newer`
+/// are ignored, which reproduces the environment of an earlier point in time.
+#[allow(clippy::too_many_arguments)]
 pub async fn solve_environment(
     name: &str,
     specs: &[MatchSpec],
@@ -63,6 +89,7 @@ pub async fn solve_environment(
     tool_configuration: &tool_configuration::Configuration,
     channel_priority: ChannelPriority,
     solve_strategy: SolveStrategy,
+    exclude_newer: Option<DateTime<Utc>>,
 ) -> anyhow::Result<Vec<RepoDataRecord>> {
     let vp_string = format!("[{}]", target_platform.virtual_packages.iter().format(", "));
 
@@ -81,6 +108,9 @@ pub async fn solve_environment(
                 .canonical_name(channel.url())
         );
     }
+    if let Some(exclude_newer) = exclude_newer {
+        tracing::info!("  Excluding packages newer than {}", exclude_newer);
+    }
     tracing::info!("  Specs:");
     for spec in specs {
         tracing::info!("   - {}", spec);
@@ -92,26 +122,61 @@ pub async fn solve_environment(
         specs,
         tool_configuration,
     )
+    .instrument(tracing::debug_span!(
+        "Fetching repodata",
+        profile = ProfileCategory::RepodataFetch.as_str()
+    ))
     .await?;
 
+    // the solve itself cannot be interrupted, so check before starting it
+    if tool_configuration.cancellation_token.is_cancelled() {
+        anyhow::bail!("the build was cancelled");
+    }
+
     // Now that we parsed and downloaded all information, construct the packaging
     // problem that we need to solve. We do this by constructing a
     // `SolverProblem`. This encapsulates all the information required to be
     // able to solve the problem.
-    let solver_task = SolverTask {
-        virtual_packages: target_platform.virtual_packages.clone(),
-        specs: specs.to_vec(),
-        channel_priority,
-        strategy: solve_strategy,
-        ..SolverTask::from_iter(&repo_data)
+    let solve = |specs: &[MatchSpec]| {
+        let solver_task = SolverTask {
+            virtual_packages: target_platform.virtual_packages.clone(),
+            specs: specs.to_vec(),
+            channel_priority,
+            strategy: solve_strategy,
+            exclude_newer,
+            ..SolverTask::from_iter(&repo_data)
+        };
+        Solver.solve(solver_task)
     };
 
     // Next, use a solver to solve this specific problem. This provides us with all
     // the operations we need to apply to our environment to bring it up to
     // date.
-    let required_packages = tool_configuration
-        .fancy_log_handler
-        .wrap_in_progress("solving", move || Solver.solve(solver_task))?;
+    let result = tracing::debug_span!(
+        "Solving environment",
+        profile = ProfileCategory::Solve.as_str()
+    )
+    .in_scope(|| {
+        tool_configuration
+            .fancy_log_handler
+            .wrap_in_progress("solving", || solve(specs))
+    });
+
+    let required_packages = match result {
+        Ok(required_packages) => required_packages,
+        Err(SolveError::Unsolvable(explanation)) if tool_configuration.explain_conflicts => {
+            tracing::warn!(
+                "Failed to solve the {name} environment, looking for the closest solvable set of specs"
+            );
+            let conflict = tool_configuration
+                .fancy_log_handler
+                .wrap_in_progress("explaining conflict", || {
+                    SolveConflict::new(name, specs, explanation.join("\n"), solve)
+                });
+            return Err(conflict.into());
+        }
+        Err(e) => return Err(e.into()),
+    };
 
     // Print the result as a table
     print_as_table(&required_packages);
@@ -129,6 +194,7 @@ pub async fn create_environment(
     tool_configuration: &tool_configuration::Configuration,
     channel_priority: ChannelPriority,
     solve_strategy: SolveStrategy,
+    exclude_newer: Option<DateTime<Utc>>,
 ) -> anyhow::Result<Vec<RepoDataRecord>> {
     let required_packages = solve_environment(
         name,
@@ -138,6 +204,7 @@ pub async fn create_environment(
         tool_configuration,
         channel_priority,
         solve_strategy,
+        exclude_newer,
     )
     .await?;
 
@@ -256,7 +323,7 @@ pub async fn load_repodatas(
         .map(|url| Channel::from_url(url.clone()))
         .collect::<Vec<_>>();
 
-    let result = tool_configuration
+    let query = tool_configuration
         .repodata_gateway
         .query(
             channels,
@@ -281,8 +348,8 @@ pub async fn load_repodatas(
         )
         .recursive(true)
         .into_future()
-        .boxed()
-        .await?;
+        .boxed();
+    let result = cancellable(tool_configuration, async { Ok(query.await?) }).await?;
 
     tool_configuration
         .fancy_log_handler
@@ -330,8 +397,46 @@ pub async fn install_packages(
         }
     }
 
+    // download the packages into the package cache before installing them, so
+    // that the time spent downloading is profiled separately
+    let mut progress = PackageCacheReporter::new(
+        tool_configuration
+            .fancy_log_handler
+            .multi_progress()
+            .clone(),
+        Placement::End,
+    )
+    .with_prefix(tool_configuration.fancy_log_handler.with_indent_levels(""));
+    let downloads = required_packages
+        .iter()
+        .filter(|record| {
+            !installed_packages
+                .iter()
+                .any(|installed| installed.repodata_record.url == record.url)
+        })
+        .map(|record| {
+            tool_configuration
+                .package_cache
+                .get_or_fetch_from_url_with_retry(
+                    CacheKey::from(&record.package_record),
+                    record.url.clone(),
+                    tool_configuration.client.clone(),
+                    default_retry_policy(),
+                    Some(Arc::new(progress.add(record))),
+                )
+        })
+        .collect::<Vec<_>>();
+    let download = futures::stream::iter(downloads)
+        .buffer_unordered(50)
+        .try_collect::<Vec<_>>()
+        .instrument(tracing::debug_span!(
+            "Downloading packages",
+            profile = ProfileCategory::Download.as_str()
+        ));
+    cancellable(tool_configuration, async { Ok(download.await?) }).await?;
+
     tracing::info!("\nInstalling {name} environment\n");
-    Installer::new()
+    let installation = Installer::new()
         .with_download_client(tool_configuration.client.clone())
         .with_target_platform(target_platform)
         .with_execute_link_scripts(true)
@@ -352,7 +457,11 @@ pub async fn install_packages(
                 .finish(),
         )
         .install(&target_prefix, required_packages.to_owned())
-        .await?;
+        .instrument(tracing::debug_span!(
+            "Installing packages",
+            profile = ProfileCategory::Install.as_str()
+        ));
+    cancellable(tool_configuration, async { Ok(installation.await?) }).await?;
 
     tracing::info!(
         "{} Successfully updated the {name} environment",
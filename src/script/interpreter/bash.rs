@@ -5,7 +5,7 @@ use rattler_shell::shell;
 
 use crate::script::{interpreter::DEBUG_HELP, run_process_with_replacements, ExecutionArgs};
 
-use super::{find_interpreter, Interpreter};
+use super::{find_interpreter, log_dry_run, Interpreter};
 
 const BASH_PREAMBLE: &str = r#"#!/bin/bash
 ## Start of bash preamble
@@ -33,13 +33,27 @@ impl Interpreter for BashInterpreter {
         tokio::fs::write(&build_script_path, script).await?;
 
         let build_script_path_str = build_script_path.to_string_lossy().to_string();
-        let cmd_args = ["bash", "-e", &build_script_path_str];
+        let mut cmd_args = vec!["bash"];
+        if args.interpreter_args.is_empty() {
+            cmd_args.push("-e");
+        } else {
+            cmd_args.extend(args.interpreter_args.iter().map(String::as_str));
+        }
+        cmd_args.push(&build_script_path_str);
+
+        if args.dry_run {
+            log_dry_run(&cmd_args, &build_script_path);
+            return Ok(());
+        }
 
         let output = run_process_with_replacements(
             &cmd_args,
             &args.work_dir,
+            &args.secrets,
             &args.replacements("$((var))"),
             args.sandbox_config.as_ref(),
+            &args.limits,
+            &args.cancellation_token,
         )
         .await?;
 
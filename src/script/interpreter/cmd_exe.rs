@@ -5,7 +5,7 @@ use rattler_shell::shell;
 
 use crate::script::{interpreter::DEBUG_HELP, run_process_with_replacements, ExecutionArgs};
 
-use super::{find_interpreter, Interpreter};
+use super::{find_interpreter, log_dry_run, Interpreter};
 
 const CMDEXE_PREAMBLE: &str = r#"
 @chcp 65001 > nul
@@ -41,13 +41,27 @@ impl Interpreter for CmdExeInterpreter {
         .await?;
 
         let build_script_path_str = build_script_path.to_string_lossy().to_string();
-        let cmd_args = ["cmd.exe", "/d", "/c", &build_script_path_str];
+        let mut cmd_args = vec!["cmd.exe"];
+        if args.interpreter_args.is_empty() {
+            cmd_args.push("/d");
+        } else {
+            cmd_args.extend(args.interpreter_args.iter().map(String::as_str));
+        }
+        cmd_args.extend(["/c", build_script_path_str.as_str()]);
+
+        if args.dry_run {
+            log_dry_run(&cmd_args, &build_script_path);
+            return Ok(());
+        }
 
         let output = run_process_with_replacements(
             &cmd_args,
             &args.work_dir,
+            &args.secrets,
             &args.replacements("%((var))%"),
             None,
+            &args.limits,
+            &args.cancellation_token,
         )
         .await?;
 
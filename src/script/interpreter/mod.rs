@@ -4,7 +4,7 @@ mod nushell;
 mod perl;
 mod python;
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 pub(crate) use bash::BashInterpreter;
 pub(crate) use cmd_exe::CmdExeInterpreter;
@@ -22,6 +22,18 @@ use super::ExecutionArgs;
 
 const DEBUG_HELP : &str  = "To debug the build, run it manually in the work directory (execute the `./conda_build.sh` or `conda_build.bat` script)";
 
+/// Log the command and the script that would be executed in a dry run.
+fn log_dry_run(cmd_args: &[&str], script_path: &Path) {
+    tracing::info!(
+        "Dry run: not executing `{}`, the build script was written to '{}'",
+        cmd_args.join(" "),
+        script_path.display()
+    );
+    if let Ok(script) = fs_err::read_to_string(script_path) {
+        tracing::debug!("Build script:\n{}", script);
+    }
+}
+
 fn find_interpreter(
     name: &str,
     build_prefix: Option<&PathBuf>,
@@ -53,6 +65,9 @@ pub trait Interpreter {
         for (k, v) in args.env_vars.iter() {
             shell_script.set_env_var(k, v)?;
         }
+        for k in args.unset_env_vars.iter() {
+            shell_script.unset_env_var(k)?;
+        }
         let host_prefix_activator =
             Activator::from_path(&args.run_prefix, shell_type, args.execution_platform)?;
 
@@ -9,7 +9,7 @@ use rattler_shell::{
 
 use crate::script::{interpreter::DEBUG_HELP, run_process_with_replacements, ExecutionArgs};
 
-use super::{find_interpreter, Interpreter};
+use super::{find_interpreter, log_dry_run, Interpreter};
 
 pub(crate) struct NuShellInterpreter;
 
@@ -113,13 +113,23 @@ impl Interpreter for NuShellInterpreter {
             .to_string_lossy()
             .to_string();
 
-        let cmd_args = [nu_path.as_str(), build_script_path_str.as_str()];
+        let mut cmd_args = vec![nu_path.as_str()];
+        cmd_args.extend(args.interpreter_args.iter().map(String::as_str));
+        cmd_args.push(build_script_path_str.as_str());
+
+        if args.dry_run {
+            log_dry_run(&cmd_args, &build_script_path);
+            return Ok(());
+        }
 
         let output = run_process_with_replacements(
             &cmd_args,
             &args.work_dir,
+            &args.secrets,
             &args.replacements("$((var))"),
             None,
+            &args.limits,
+            &args.cancellation_token,
         )
         .await?;
 
@@ -1,5 +1,6 @@
 use std::path::PathBuf;
 
+use itertools::Itertools;
 use rattler_conda_types::Platform;
 
 use crate::script::{ExecutionArgs, ResolvedScriptContents};
@@ -14,8 +15,15 @@ impl Interpreter for PerlInterpreter {
         let perl_script = args.work_dir.join("conda_build_script.pl");
         tokio::fs::write(&perl_script, args.script.script()).await?;
 
+        // the interpreter arguments are passed to perl, not to the activation shell
+        let command = std::iter::once("perl".to_string())
+            .chain(args.interpreter_args.iter().map(|arg| format!("{:?}", arg)))
+            .chain(std::iter::once(format!("{:?}", perl_script)))
+            .join(" ");
+
         let args = ExecutionArgs {
-            script: ResolvedScriptContents::Inline(format!("perl {:?}", perl_script)),
+            script: ResolvedScriptContents::Inline(command),
+            interpreter_args: Vec::new(),
             ..args
         };
 
@@ -1,5 +1,6 @@
 use std::path::PathBuf;
 
+use itertools::Itertools;
 use rattler_conda_types::Platform;
 
 use crate::script::{ExecutionArgs, ResolvedScriptContents};
@@ -14,8 +15,15 @@ impl Interpreter for PythonInterpreter {
         let py_script = args.work_dir.join("conda_build_script.py");
         tokio::fs::write(&py_script, args.script.script()).await?;
 
+        // the interpreter arguments are passed to python, not to the activation shell
+        let command = std::iter::once("python".to_string())
+            .chain(args.interpreter_args.iter().map(|arg| format!("{:?}", arg)))
+            .chain(std::iter::once(format!("{:?}", py_script)))
+            .join(" ");
+
         let args = ExecutionArgs {
-            script: ResolvedScriptContents::Inline(format!("python {:?}", py_script)),
+            script: ResolvedScriptContents::Inline(command),
+            interpreter_args: Vec::new(),
             ..args
         };
 
@@ -0,0 +1,200 @@
+//! Isolate the build script from the home directory of the user.
+//!
+//! With `--isolate-env`, the build script gets a fresh `HOME` and temporary
+//! directory next to the work directory (so that they do not end up in the
+//! sources, e.g. for `git status` or `pip install .`) and the XDG base
+//! directories are cleared, so that configuration files of the user (e.g.
+//! `pip.conf` or the cargo configuration) cannot change the result of the
+//! build. The environment variables that are still inherited from the host are
+//! reported.
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use fs_err as fs;
+
+/// The XDG base directory variables that are cleared, so that they default to
+/// the fresh home directory
+const XDG_VARS: &[&str] = &[
+    "XDG_CONFIG_HOME",
+    "XDG_CONFIG_DIRS",
+    "XDG_CACHE_HOME",
+    "XDG_DATA_HOME",
+    "XDG_DATA_DIRS",
+    "XDG_STATE_HOME",
+    "XDG_RUNTIME_DIR",
+];
+
+/// Variables of the host environment that are expected to be inherited and are
+/// not reported. Names ending with `*` match as prefix.
+const INHERITED_VARS: &[&str] = &[
+    "PATH",
+    "PWD",
+    "OLDPWD",
+    "SHLVL",
+    "_",
+    "SHELL",
+    "TERM",
+    "COLORTERM",
+    "USER",
+    "USERNAME",
+    "LOGNAME",
+    "HOSTNAME",
+    "LANG",
+    "LANGUAGE",
+    "LC_*",
+    "TZ",
+    // Windows system variables that are required to run programs
+    "SYSTEMROOT",
+    "SYSTEMDRIVE",
+    "WINDIR",
+    "COMSPEC",
+    "PATHEXT",
+    "OS",
+    "NUMBER_OF_PROCESSORS",
+    "PROCESSOR_*",
+    "PROGRAMDATA",
+    "PROGRAMFILES*",
+    "COMMONPROGRAMFILES*",
+];
+
+/// A fresh home and temporary directory for the build script
+#[derive(Debug)]
+pub struct IsolatedEnvironment {
+    /// The home directory of the script
+    pub home: PathBuf,
+    /// The temporary directory of the script
+    pub tmp: PathBuf,
+}
+
+impl IsolatedEnvironment {
+    /// Create empty home and temporary directories in the build directory
+    /// (the parent of the work directory)
+    pub fn create(build_dir: &Path) -> Result<Self, std::io::Error> {
+        let home = build_dir.join("home");
+        let tmp = build_dir.join("tmp");
+        for dir in [&home, &tmp] {
+            if dir.exists() {
+                fs::remove_dir_all(dir)?;
+            }
+            fs::create_dir_all(dir)?;
+        }
+        Ok(Self { home, tmp })
+    }
+
+    /// The environment variables that point the script to the fresh
+    /// directories
+    pub fn env_vars(&self) -> HashMap<String, Option<String>> {
+        let home = self.home.to_string_lossy().to_string();
+        let tmp = self.tmp.to_string_lossy().to_string();
+
+        let mut vars = HashMap::from([
+            ("HOME".to_string(), Some(home.clone())),
+            ("TMPDIR".to_string(), Some(tmp.clone())),
+            ("TMP".to_string(), Some(tmp.clone())),
+            ("TEMP".to_string(), Some(tmp)),
+        ]);
+        if cfg!(windows) {
+            vars.insert("USERPROFILE".to_string(), Some(home.clone()));
+            vars.insert(
+                "APPDATA".to_string(),
+                Some(
+                    self.home
+                        .join("AppData/Roaming")
+                        .to_string_lossy()
+                        .to_string(),
+                ),
+            );
+            vars.insert(
+                "LOCALAPPDATA".to_string(),
+                Some(
+                    self.home
+                        .join("AppData/Local")
+                        .to_string_lossy()
+                        .to_string(),
+                ),
+            );
+        }
+        vars
+    }
+
+    /// The variables that are removed from the environment of the script
+    pub fn unset_env_vars() -> impl Iterator<Item = String> {
+        XDG_VARS.iter().map(|var| var.to_string())
+    }
+}
+
+/// Returns true if the variable is expected to be inherited from the host
+fn is_expected(name: &str) -> bool {
+    let name = name.to_uppercase();
+    INHERITED_VARS.iter().any(|expected| {
+        if let Some(prefix) = expected.strip_suffix('*') {
+            name.starts_with(prefix)
+        } else {
+            name == *expected
+        }
+    })
+}
+
+/// Returns the (sorted) names of the host environment variables that leak into
+/// the script, because they are neither set nor unset for the script and are
+/// not expected to be inherited.
+pub fn leaked_env_vars(
+    host_vars: impl IntoIterator<Item = String>,
+    script_vars: &HashMap<String, Option<String>>,
+    unset_vars: &[String],
+) -> Vec<String> {
+    let mut leaked = host_vars
+        .into_iter()
+        .filter(|name| {
+            !script_vars.contains_key(name) && !unset_vars.contains(name) && !is_expected(name)
+        })
+        .collect::<Vec<_>>();
+    leaked.sort();
+    leaked.dedup();
+    leaked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn isolated_environment() {
+        let build_dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(build_dir.path().join("home/.cargo")).unwrap();
+
+        let isolated = IsolatedEnvironment::create(build_dir.path()).unwrap();
+        assert!(isolated.home.is_dir());
+        assert!(isolated.tmp.is_dir());
+        // the home directory is recreated for every build
+        assert!(!isolated.home.join(".cargo").exists());
+
+        let vars = isolated.env_vars();
+        assert_eq!(
+            vars["HOME"].as_deref(),
+            Some(isolated.home.to_string_lossy().as_ref())
+        );
+        assert_eq!(
+            vars["TMPDIR"].as_deref(),
+            Some(isolated.tmp.to_string_lossy().as_ref())
+        );
+
+        let unset = IsolatedEnvironment::unset_env_vars().collect::<Vec<_>>();
+        let leaked = leaked_env_vars(
+            [
+                "PATH",
+                "HOME",
+                "LC_ALL",
+                "XDG_CONFIG_HOME",
+                "PIP_INDEX_URL",
+                "CARGO_HOME",
+            ]
+            .map(String::from),
+            &vars,
+            &unset,
+        );
+        assert_eq!(leaked, vec!["CARGO_HOME", "PIP_INDEX_URL"]);
+    }
+}
@@ -0,0 +1,508 @@
+//! Enforce the timeout and the resource limits ([`ScriptLimits`]) of a script.
+//!
+//! When a timeout is set, the script is started in its own process group (Unix)
+//! or job object (Windows) so that the whole process tree can be killed once
+//! the timeout is reached. Memory and CPU limits are applied with a dedicated
+//! cgroup (v2) on Linux, which the script joins before it is executed, and with
+//! the job object on Windows. If no cgroup can be created,
+//! the memory limit falls back to `RLIMIT_DATA` (per process) and the CPU limit
+//! is ignored with a warning. `RLIMIT_AS` is not used, because it also counts
+//! reserved (but unused) virtual memory.
+use tokio::process::{Child, Command};
+
+use crate::recipe::parser::ScriptLimits;
+
+/// The resource limits applied to a single spawned script process.
+pub(crate) struct ProcessLimits {
+    limits: ScriptLimits,
+    #[cfg(target_os = "linux")]
+    cgroup: Option<cgroup::Cgroup>,
+    #[cfg(windows)]
+    job: Option<job::Job>,
+}
+
+impl ProcessLimits {
+    /// Prepare the command for the given limits. This has to be called before
+    /// the command is spawned.
+    pub(crate) fn new(limits: &ScriptLimits, command: &mut Command) -> Self {
+        #[cfg(target_os = "linux")]
+        let cgroup = if limits.memory.is_some() || limits.cpus.is_some() {
+            match cgroup::Cgroup::create(limits) {
+                Ok(cgroup) => Some(cgroup),
+                Err(e) => {
+                    tracing::warn!(
+                        "Could not create a cgroup for the script resource limits: {}",
+                        e
+                    );
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        #[cfg(target_os = "linux")]
+        if let Some(cgroup) = &cgroup {
+            let procs = cgroup.procs_path();
+            // SAFETY: the closure only calls `open`, `write` and `close`, which
+            // are async-signal-safe, and does not allocate
+            unsafe {
+                command.pre_exec(move || cgroup::join(&procs));
+            }
+        }
+
+        #[cfg(unix)]
+        {
+            // a separate process group allows killing all processes started by
+            // the script when the timeout is reached
+            if limits.timeout.is_some() {
+                command.process_group(0);
+            }
+
+            #[cfg(target_os = "linux")]
+            let needs_rlimit = cgroup.is_none();
+            #[cfg(not(target_os = "linux"))]
+            let needs_rlimit = true;
+
+            if let (Some(memory), true) = (limits.memory, needs_rlimit) {
+                tracing::warn!(
+                    "Enforcing the memory limit of the script as a per-process data limit of {}",
+                    indicatif::HumanBytes(memory.0)
+                );
+                // SAFETY: `setrlimit` is async-signal-safe and does not allocate
+                unsafe {
+                    command.pre_exec(move || {
+                        nix::sys::resource::setrlimit(
+                            nix::sys::resource::Resource::RLIMIT_DATA,
+                            memory.0,
+                            memory.0,
+                        )
+                        .map_err(std::io::Error::from)
+                    });
+                }
+            }
+
+            #[cfg(not(target_os = "linux"))]
+            {
+                if limits.cpus.is_some() {
+                    tracing::warn!("CPU limits for scripts are not supported on this platform");
+                }
+            }
+        }
+
+        #[cfg(windows)]
+        let job = if limits.is_empty() {
+            None
+        } else {
+            match job::Job::create(limits) {
+                Ok(job) => Some(job),
+                Err(e) => {
+                    tracing::warn!(
+                        "Could not create a job object for the script resource limits: {}",
+                        e
+                    );
+                    None
+                }
+            }
+        };
+
+        #[cfg(not(unix))]
+        let _ = command;
+
+        Self {
+            limits: *limits,
+            #[cfg(target_os = "linux")]
+            cgroup,
+            #[cfg(windows)]
+            job,
+        }
+    }
+
+    /// Move the spawned child process into the job object. On Linux the
+    /// process already joined its cgroup before it was executed.
+    pub(crate) fn attach(&self, child: &Child) -> Result<(), std::io::Error> {
+        #[cfg(windows)]
+        {
+            if let (Some(job), Some(handle)) = (&self.job, child.raw_handle()) {
+                job.assign(handle)?;
+            }
+        }
+
+        #[cfg(not(windows))]
+        let _ = child;
+
+        Ok(())
+    }
+
+    /// Kill the child process and every process it started.
+    pub(crate) async fn kill(&self, child: &mut Child) {
+        #[cfg(unix)]
+        {
+            if let (Some(pid), true) = (child.id(), self.limits.timeout.is_some()) {
+                let _ = nix::sys::signal::killpg(
+                    nix::unistd::Pid::from_raw(pid as i32),
+                    nix::sys::signal::Signal::SIGKILL,
+                );
+            }
+        }
+
+        #[cfg(target_os = "linux")]
+        {
+            // this also catches processes that left the process group
+            if let Some(cgroup) = &self.cgroup {
+                cgroup.kill();
+            }
+        }
+
+        #[cfg(windows)]
+        {
+            if let Some(job) = &self.job {
+                job.terminate();
+            }
+        }
+
+        if let Err(e) = child.kill().await {
+            tracing::warn!("Failed to kill the script process: {}", e);
+        }
+    }
+
+    /// Returns true if the script was killed because it exceeded the memory
+    /// limit.
+    pub(crate) fn memory_exceeded(&self) -> bool {
+        #[cfg(target_os = "linux")]
+        {
+            if let Some(cgroup) = &self.cgroup {
+                return cgroup.oom_killed();
+            }
+        }
+
+        false
+    }
+
+    /// Returns the error that is reported when the script exceeded the memory
+    /// limit.
+    pub(crate) fn memory_exceeded_error(&self) -> std::io::Error {
+        let memory = self.limits.memory.map(|m| m.0).unwrap_or_default();
+        std::io::Error::new(
+            std::io::ErrorKind::Other,
+            format!(
+                "the script exceeded its memory limit of {} and was killed (`build.script.memory_limit`)",
+                indicatif::HumanBytes(memory)
+            ),
+        )
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod cgroup {
+    use std::{
+        ffi::{CStr, CString},
+        os::{fd::BorrowedFd, unix::ffi::OsStrExt},
+        path::{Path, PathBuf},
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use fs_err as fs;
+
+    use crate::recipe::parser::ScriptLimits;
+
+    /// The period (in microseconds) that is used for the CPU quota.
+    const CPU_PERIOD: u64 = 100_000;
+
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    /// A dedicated cgroup (v2) for a single script. All processes that are
+    /// left in it are killed and the cgroup is removed when it is dropped.
+    pub(super) struct Cgroup {
+        path: PathBuf,
+    }
+
+    /// Returns the cgroup (v2) of the current process.
+    fn current_cgroup() -> Result<PathBuf, std::io::Error> {
+        let content = fs::read_to_string("/proc/self/cgroup")?;
+        let path = content
+            .lines()
+            .find_map(|line| line.strip_prefix("0::"))
+            .ok_or_else(|| {
+                std::io::Error::new(std::io::ErrorKind::NotFound, "cgroup v2 is not available")
+            })?;
+        Ok(Path::new("/sys/fs/cgroup").join(path.trim_start_matches('/')))
+    }
+
+    impl Cgroup {
+        /// Create a new child cgroup of the current cgroup with the given
+        /// limits. The controllers have to be enabled (delegated) for the
+        /// children of the current cgroup already: they cannot be enabled
+        /// from here, because the current cgroup contains processes (at least
+        /// this one).
+        pub(super) fn create(limits: &ScriptLimits) -> Result<Self, std::io::Error> {
+            let parent = current_cgroup()?;
+
+            let path = parent.join(format!(
+                "rattler-build-{}-{}",
+                std::process::id(),
+                COUNTER.fetch_add(1, Ordering::Relaxed)
+            ));
+            fs::create_dir(&path)?;
+            let cgroup = Cgroup { path };
+
+            let controllers = fs::read_to_string(cgroup.path.join("cgroup.controllers"))?;
+            let required = [
+                ("memory", limits.memory.is_some()),
+                ("cpu", limits.cpus.is_some()),
+            ];
+            for (controller, _) in required.iter().filter(|(_, required)| *required) {
+                if !controllers.split_whitespace().any(|c| c == *controller) {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::Unsupported,
+                        format!(
+                            "the {} controller is not enabled for the children of {}",
+                            controller,
+                            parent.display()
+                        ),
+                    ));
+                }
+            }
+
+            if let Some(memory) = limits.memory {
+                fs::write(cgroup.path.join("memory.max"), memory.0.to_string())?;
+                // swap is not counted in `memory.max`
+                let _ = fs::write(cgroup.path.join("memory.swap.max"), "0");
+            }
+
+            if let Some(cpus) = limits.cpus {
+                let quota = cpus.0 as u64 * CPU_PERIOD / 1000;
+                fs::write(
+                    cgroup.path.join("cpu.max"),
+                    format!("{} {}", quota, CPU_PERIOD),
+                )?;
+            }
+
+            Ok(cgroup)
+        }
+
+        /// Returns the path of the `cgroup.procs` file, which is used to join
+        /// the cgroup with [`join`].
+        pub(super) fn procs_path(&self) -> CString {
+            CString::new(self.path.join("cgroup.procs").as_os_str().as_bytes())
+                .expect("cgroup paths do not contain null bytes")
+        }
+
+        /// Kill all processes in the cgroup.
+        pub(super) fn kill(&self) {
+            // `cgroup.kill` is available since Linux 5.14
+            if fs::write(self.path.join("cgroup.kill"), "1").is_ok() {
+                return;
+            }
+            let Ok(procs) = fs::read_to_string(self.path.join("cgroup.procs")) else {
+                return;
+            };
+            for pid in procs.lines().filter_map(|pid| pid.trim().parse().ok()) {
+                let _ = nix::sys::signal::kill(
+                    nix::unistd::Pid::from_raw(pid),
+                    nix::sys::signal::Signal::SIGKILL,
+                );
+            }
+        }
+
+        /// Returns true if no process is left in the cgroup.
+        fn is_empty(&self) -> bool {
+            fs::read_to_string(self.path.join("cgroup.procs"))
+                .map(|procs| procs.trim().is_empty())
+                .unwrap_or(true)
+        }
+
+        /// Returns true if a process in the cgroup was killed by the OOM killer.
+        pub(super) fn oom_killed(&self) -> bool {
+            fs::read_to_string(self.path.join("memory.events"))
+                .map(|events| {
+                    events.lines().any(|line| {
+                        line.strip_prefix("oom_kill ")
+                            .and_then(|count| count.trim().parse::<u64>().ok())
+                            .is_some_and(|count| count > 0)
+                    })
+                })
+                .unwrap_or(false)
+        }
+    }
+
+    /// Move the calling process into the cgroup with the given `cgroup.procs`
+    /// file. This is called in the forked child before the script is executed
+    /// so that the script is limited from its very first instruction.
+    pub(super) fn join(procs: &CStr) -> Result<(), std::io::Error> {
+        use nix::{fcntl::OFlag, sys::stat::Mode};
+
+        let fd = nix::fcntl::open(procs, OFlag::O_WRONLY | OFlag::O_CLOEXEC, Mode::empty())?;
+        // SAFETY: the file descriptor was just opened and is closed below
+        let result = nix::unistd::write(unsafe { BorrowedFd::borrow_raw(fd) }, b"0");
+        let _ = nix::unistd::close(fd);
+        result.map(|_| ()).map_err(std::io::Error::from)
+    }
+
+    impl Drop for Cgroup {
+        fn drop(&mut self) {
+            // a cgroup can only be removed once all of its processes are gone
+            if !self.is_empty() {
+                self.kill();
+                for _ in 0..50 {
+                    if self.is_empty() {
+                        break;
+                    }
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+            }
+
+            if let Err(e) = std::fs::remove_dir(&self.path) {
+                tracing::debug!("Failed to remove cgroup {}: {}", self.path.display(), e);
+            }
+        }
+    }
+}
+
+#[cfg(windows)]
+mod job {
+    use std::os::windows::io::RawHandle;
+
+    use windows_sys::Win32::{
+        Foundation::{CloseHandle, HANDLE},
+        System::JobObjects::{
+            AssignProcessToJobObject, CreateJobObjectW, JobObjectCpuRateControlInformation,
+            JobObjectExtendedLimitInformation, SetInformationJobObject, TerminateJobObject,
+            JOBOBJECT_CPU_RATE_CONTROL_INFORMATION, JOBOBJECT_EXTENDED_LIMIT_INFORMATION,
+            JOB_OBJECT_CPU_RATE_CONTROL_ENABLE, JOB_OBJECT_CPU_RATE_CONTROL_HARD_CAP,
+            JOB_OBJECT_LIMIT_JOB_MEMORY, JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE,
+        },
+    };
+
+    use crate::recipe::parser::ScriptLimits;
+
+    /// A job object that kills all of its processes when dropped.
+    pub(super) struct Job {
+        handle: HANDLE,
+    }
+
+    // SAFETY: the job handle can be used from any thread
+    unsafe impl Send for Job {}
+    unsafe impl Sync for Job {}
+
+    impl Job {
+        /// Create a new job object with the given limits.
+        pub(super) fn create(limits: &ScriptLimits) -> Result<Self, std::io::Error> {
+            // SAFETY: all pointers passed to the Windows API are valid for the
+            // duration of the calls
+            unsafe {
+                let handle = CreateJobObjectW(std::ptr::null(), std::ptr::null());
+                if handle.is_null() {
+                    return Err(std::io::Error::last_os_error());
+                }
+                let job = Job { handle };
+
+                let mut info: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = std::mem::zeroed();
+                info.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+                if let Some(memory) = limits.memory {
+                    info.BasicLimitInformation.LimitFlags |= JOB_OBJECT_LIMIT_JOB_MEMORY;
+                    info.JobMemoryLimit = memory.0 as usize;
+                }
+                if SetInformationJobObject(
+                    job.handle,
+                    JobObjectExtendedLimitInformation,
+                    &info as *const _ as *const _,
+                    std::mem::size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+                ) == 0
+                {
+                    return Err(std::io::Error::last_os_error());
+                }
+
+                if let Some(cpus) = limits.cpus {
+                    // the rate is the share of all processors in 1/100th of a percent
+                    let rate = (cpus.0 as u64 * 10_000 / 1000 / num_cpus::get() as u64)
+                        .clamp(1, 10_000) as u32;
+                    let mut cpu_info: JOBOBJECT_CPU_RATE_CONTROL_INFORMATION = std::mem::zeroed();
+                    cpu_info.ControlFlags =
+                        JOB_OBJECT_CPU_RATE_CONTROL_ENABLE | JOB_OBJECT_CPU_RATE_CONTROL_HARD_CAP;
+                    cpu_info.Anonymous.CpuRate = rate;
+                    if SetInformationJobObject(
+                        job.handle,
+                        JobObjectCpuRateControlInformation,
+                        &cpu_info as *const _ as *const _,
+                        std::mem::size_of::<JOBOBJECT_CPU_RATE_CONTROL_INFORMATION>() as u32,
+                    ) == 0
+                    {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                }
+
+                Ok(job)
+            }
+        }
+
+        /// Assign a process to the job object.
+        pub(super) fn assign(&self, process: RawHandle) -> Result<(), std::io::Error> {
+            // SAFETY: the process handle is owned by the (still running) child
+            if unsafe { AssignProcessToJobObject(self.handle, process as HANDLE) } == 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        }
+
+        /// Kill all processes in the job object.
+        pub(super) fn terminate(&self) {
+            // SAFETY: the job handle is valid until the job is dropped
+            unsafe { TerminateJobObject(self.handle, 1) };
+        }
+    }
+
+    impl Drop for Job {
+        fn drop(&mut self) {
+            // SAFETY: the job handle is valid and not used after this
+            unsafe { CloseHandle(self.handle) };
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::recipe::parser::{ScriptLimits, Timeout};
+    use crate::script::run_process_with_replacements;
+
+    async fn run_with_timeout(script: &str, timeout: Duration) -> std::io::Result<()> {
+        let dir = tempfile::tempdir().unwrap();
+        let limits = ScriptLimits {
+            timeout: Some(Timeout(timeout)),
+            ..Default::default()
+        };
+        run_process_with_replacements(
+            &["sh", "-c", script],
+            dir.path(),
+            &Default::default(),
+            &Default::default(),
+            None,
+            &limits,
+            &Default::default(),
+        )
+        .await
+        .map(|_| ())
+    }
+
+    #[tokio::test]
+    async fn test_timeout_kills_process_tree() {
+        let start = Instant::now();
+        // the background process keeps stdout open, so the script only
+        // finishes early if the whole process group is killed
+        let err = run_with_timeout("sleep 60 & sleep 60", Duration::from_millis(500))
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+        assert!(start.elapsed() < Duration::from_secs(30));
+    }
+
+    #[tokio::test]
+    async fn test_timeout_not_reached() {
+        run_with_timeout("echo done", Duration::from_secs(60))
+            .await
+            .unwrap();
+    }
+}
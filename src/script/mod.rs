@@ -1,7 +1,11 @@
 //! Module for running scripts in different interpreters.
 mod interpreter;
+mod isolation;
+mod limits;
 mod sandbox;
+mod secrets;
 pub use sandbox::{SandboxArguments, SandboxConfiguration};
+pub use secrets::{SecretError, SecretSource, SecretSpec};
 
 use crate::script::interpreter::Interpreter;
 use indexmap::IndexMap;
@@ -19,12 +23,14 @@ use std::{
     process::Stdio,
 };
 use tokio::io::AsyncBufReadExt as _;
+use tokio_util::sync::CancellationToken;
 
 use crate::{
+    build_profile::ProfileCategory,
     env_vars::{self},
     metadata::Output,
     recipe::{
-        parser::{Script, ScriptContent},
+        parser::{Script, ScriptContent, ScriptLimits},
         Jinja,
     },
 };
@@ -34,8 +40,12 @@ use crate::{
 pub struct ExecutionArgs {
     /// Contents of the script to execute
     pub script: ResolvedScriptContents,
+    /// Arguments to pass to the interpreter, replacing its default arguments if not empty
+    pub interpreter_args: Vec<String>,
     /// Environment variables to set before executing the script
     pub env_vars: IndexMap<String, String>,
+    /// Environment variables to unset before executing the script
+    pub unset_env_vars: Vec<String>,
     /// Secrets to set as env vars and replace in the output
     pub secrets: IndexMap<String, String>,
 
@@ -52,6 +62,15 @@ pub struct ExecutionArgs {
 
     /// The sandbox configuration to use for the script execution
     pub sandbox_config: Option<SandboxConfiguration>,
+
+    /// The timeout and the resource limits of the script execution
+    pub limits: ScriptLimits,
+
+    /// Only write the script files, but do not execute them
+    pub dry_run: bool,
+
+    /// Kills the script (and everything it started) when cancelled, e.g. on Ctrl-C
+    pub cancellation_token: CancellationToken,
 }
 
 impl ExecutionArgs {
@@ -83,8 +102,10 @@ impl ExecutionArgs {
             }
         }
 
-        self.secrets.iter().for_each(|(_, v)| {
-            replacements.insert(v.to_string(), "********".to_string());
+        self.secrets.values().for_each(|v| {
+            for mask in secrets::secret_masks(v) {
+                replacements.insert(mask.to_string(), secrets::SECRET_MASK.to_string());
+            }
         });
 
         replacements
@@ -203,7 +224,8 @@ impl Script {
             }
         };
 
-        // render jinja if it is an inline script
+        // render jinja if it is an inline script (or a script file with
+        // `track_variables`)
         if let Some(jinja_context) = jinja_context {
             match script_content? {
                 ResolvedScriptContents::Inline(script) => {
@@ -215,6 +237,19 @@ impl Script {
                     })?;
                     Ok(ResolvedScriptContents::Inline(rendered))
                 }
+                ResolvedScriptContents::Path(path, script) if self.track_variables => {
+                    let rendered = jinja_context.render_str(&script).map_err(|e| {
+                        std::io::Error::new(
+                            std::io::ErrorKind::Other,
+                            format!(
+                                "Failed to render jinja template in script file {}: {}",
+                                path.display(),
+                                e
+                            ),
+                        )
+                    })?;
+                    Ok(ResolvedScriptContents::Path(path, rendered))
+                }
                 other => Ok(other),
             }
         } else {
@@ -222,18 +257,50 @@ impl Script {
         }
     }
 
-    /// Run the script with the given parameters
+    /// Run the script with the given parameters. The `unset_env_vars` are removed from the
+    /// environment of the script. The script is killed when the `cancellation_token` is
+    /// cancelled.
     #[allow(clippy::too_many_arguments)]
     pub async fn run_script(
         &self,
         env_vars: HashMap<String, Option<String>>,
+        unset_env_vars: &[String],
         work_dir: &Path,
         recipe_dir: &Path,
         run_prefix: &Path,
         build_prefix: Option<&PathBuf>,
-        mut jinja_config: Option<Jinja<'_>>,
+        jinja_config: Option<Jinja<'_>>,
         sandbox_config: Option<&SandboxConfiguration>,
+        cancellation_token: &CancellationToken,
     ) -> Result<(), std::io::Error> {
+        let (interpreter, mut exec_args) = self.execution_args(
+            env_vars,
+            unset_env_vars,
+            work_dir,
+            recipe_dir,
+            run_prefix,
+            build_prefix,
+            jinja_config,
+            sandbox_config,
+        )?;
+        exec_args.cancellation_token = cancellation_token.clone();
+        run_interpreter(interpreter, exec_args).await
+    }
+
+    /// Resolve the script contents, environment and secrets and select the interpreter
+    /// that should execute the script.
+    #[allow(clippy::too_many_arguments)]
+    fn execution_args(
+        &self,
+        env_vars: HashMap<String, Option<String>>,
+        unset_env_vars: &[String],
+        work_dir: &Path,
+        recipe_dir: &Path,
+        run_prefix: &Path,
+        build_prefix: Option<&PathBuf>,
+        mut jinja_config: Option<Jinja<'_>>,
+        sandbox_config: Option<&SandboxConfiguration>,
+    ) -> Result<(&str, ExecutionArgs), std::io::Error> {
         // TODO: This is a bit of an out and about way to determine whether or
         //  not nushell is available. It would be best to run the activation
         //  of the environment and see if nu is on the path, but hat is a
@@ -267,6 +334,7 @@ impl Script {
 
         let env_vars = env_vars
             .into_iter()
+            .filter(|(k, _)| !unset_env_vars.contains(k))
             .filter_map(|(k, v)| v.map(|v| (k, v)))
             .chain(self.env().clone().into_iter())
             .collect::<IndexMap<String, String>>();
@@ -294,20 +362,8 @@ impl Script {
             interpreter = "nushell";
         }
 
-        let secrets = self
-            .secrets()
-            .iter()
-            .filter_map(|k| {
-                let secret = k.to_string();
-
-                if let Ok(value) = std::env::var(&secret) {
-                    Some((secret, value))
-                } else {
-                    tracing::warn!("Secret {} not found in environment", secret);
-                    None
-                }
-            })
-            .collect::<IndexMap<String, String>>();
+        let secrets = secrets::resolve_secrets(self.secrets())
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
 
         let work_dir = if let Some(cwd) = self.cwd.as_ref() {
             run_prefix.join(cwd)
@@ -319,41 +375,54 @@ impl Script {
 
         let exec_args = ExecutionArgs {
             script: contents,
+            interpreter_args: self.interpreter_args().to_vec(),
             env_vars,
+            unset_env_vars: unset_env_vars.to_vec(),
             secrets,
             build_prefix: build_prefix.map(|p| p.to_owned()),
             run_prefix: run_prefix.to_owned(),
             execution_platform: Platform::current(),
             work_dir,
             sandbox_config: sandbox_config.cloned(),
+            limits: *self.limits(),
+            dry_run: false,
+            cancellation_token: CancellationToken::new(),
         };
 
-        match interpreter {
-            "nushell" | "nu" => {
-                if !has_nushell {
-                    return Err(std::io::Error::new(
-                        std::io::ErrorKind::Other,
-                        "Nushell is not installed, did you add `nushell` to the build dependencies?".to_string(),
-                    ));
-                }
-                NuShellInterpreter.run(exec_args).await?
-            }
-            "bash" => BashInterpreter.run(exec_args).await?,
-            "cmd" => CmdExeInterpreter.run(exec_args).await?,
-            "python" => PythonInterpreter.run(exec_args).await?,
-            "perl" => PerlInterpreter.run(exec_args).await?,
-            _ => {
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    format!("Unsupported interpreter: {}", interpreter),
-                ))
-            }
-        };
+        if matches!(interpreter, "nushell" | "nu") && !has_nushell {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "Nushell is not installed, did you add `nushell` to the build dependencies?"
+                    .to_string(),
+            ));
+        }
 
-        Ok(())
+        Ok((interpreter, exec_args))
     }
 }
 
+/// Execute the script with the interpreter of the given name.
+async fn run_interpreter(
+    interpreter: &str,
+    exec_args: ExecutionArgs,
+) -> Result<(), std::io::Error> {
+    match interpreter {
+        "nushell" | "nu" => NuShellInterpreter.run(exec_args).await?,
+        "bash" => BashInterpreter.run(exec_args).await?,
+        "cmd" => CmdExeInterpreter.run(exec_args).await?,
+        "python" => PythonInterpreter.run(exec_args).await?,
+        "perl" => PerlInterpreter.run(exec_args).await?,
+        _ => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!("Unsupported interpreter: {}", interpreter),
+            ))
+        }
+    };
+
+    Ok(())
+}
+
 impl Output {
     /// Add environment variables from the variant to the environment variables.
     fn env_vars_from_variant(&self) -> HashMap<String, Option<String>> {
@@ -371,45 +440,104 @@ impl Output {
             .collect()
     }
 
-    /// Run the build script for the output as defined in the YAML `build.script`.
-    pub async fn run_build_script(&self) -> Result<(), std::io::Error> {
-        let span = tracing::info_span!("Running build script");
+    /// Run the build script for the output as defined in the YAML `build.script`. The
+    /// script is killed when the `cancellation_token` is cancelled.
+    pub async fn run_build_script(
+        &self,
+        cancellation_token: &CancellationToken,
+    ) -> Result<(), std::io::Error> {
+        let span = tracing::info_span!(
+            "Running build script",
+            profile = ProfileCategory::Script.as_str()
+        );
+        let _enter = span.enter();
+
+        let (interpreter, mut exec_args) = self.build_script_execution_args()?;
+        exec_args.cancellation_token = cancellation_token.clone();
+        run_interpreter(interpreter, exec_args).await
+    }
+
+    /// Write the build script for the output to the work directory without executing it.
+    /// This is used for dry runs.
+    pub async fn write_build_script(&self) -> Result<(), std::io::Error> {
+        let span = tracing::info_span!("Writing build script");
         let _enter = span.enter();
 
+        let (interpreter, mut exec_args) = self.build_script_execution_args()?;
+        exec_args.dry_run = true;
+        run_interpreter(interpreter, exec_args).await
+    }
+
+    fn build_script_execution_args(&self) -> Result<(&str, ExecutionArgs), std::io::Error> {
         let host_prefix = self.build_configuration.directories.host_prefix.clone();
         let target_platform = self.build_configuration.target_platform;
         let mut env_vars = env_vars::vars(self, "BUILD");
         env_vars.extend(env_vars::os_vars(&host_prefix, &target_platform));
+        env_vars.extend(env_vars::variant_os_vars(self));
         env_vars.extend(self.env_vars_from_variant());
 
+        // apply the `build.env` overrides of the recipe
+        let build_env = self.recipe.build().env();
+        env_vars.extend(
+            build_env
+                .set_vars()
+                .map(|(k, v)| (k.clone(), Some(v.clone()))),
+        );
+        let mut unset_env_vars = build_env.unset_vars().cloned().collect::<Vec<_>>();
+
+        if self.build_configuration.isolate_env {
+            let isolated = isolation::IsolatedEnvironment::create(
+                &self.build_configuration.directories.build_dir,
+            )?;
+            for (key, value) in isolated.env_vars() {
+                env_vars.entry(key).or_insert(value);
+            }
+            unset_env_vars.extend(isolation::IsolatedEnvironment::unset_env_vars());
+
+            let leaked = isolation::leaked_env_vars(
+                std::env::vars_os().map(|(key, _)| key.to_string_lossy().to_string()),
+                &env_vars,
+                &unset_env_vars,
+            );
+            if !leaked.is_empty() {
+                let warn_str = format!(
+                    "Environment variables inherited from the host by the build script: {}",
+                    leaked.join(", ")
+                );
+                tracing::warn!(warn_str);
+                self.record_warning(&warn_str);
+            }
+        }
+
         let selector_config = self.build_configuration.selector_config();
         let jinja = Jinja::new(selector_config.clone()).with_context(&self.recipe.context);
 
-        self.recipe
-            .build()
-            .script()
-            .run_script(
-                env_vars,
-                &self.build_configuration.directories.work_dir,
-                &self.build_configuration.directories.recipe_dir,
-                &self.build_configuration.directories.host_prefix,
-                Some(&self.build_configuration.directories.build_prefix),
-                Some(jinja),
-                self.build_configuration.sandbox_config(),
-            )
-            .await?;
-
-        Ok(())
+        self.recipe.build().script().execution_args(
+            env_vars,
+            &unset_env_vars,
+            &self.build_configuration.directories.work_dir,
+            &self.build_configuration.directories.recipe_dir,
+            &self.build_configuration.directories.host_prefix,
+            Some(&self.build_configuration.directories.build_prefix),
+            Some(jinja),
+            self.build_configuration.sandbox_config(),
+        )
     }
 }
 
 /// Spawns a process and replaces the given strings in the output with the given replacements.
 /// This is used to replace the host prefix with $PREFIX and the build prefix with $BUILD_PREFIX
+/// and to mask secrets. The secrets are passed to the process as environment variables.
+/// The process (and everything it started) is killed with an error when it exceeds the
+/// timeout or the memory limit in `limits`, or when the `cancellation_token` is cancelled.
 async fn run_process_with_replacements(
     args: &[&str],
     cwd: &Path,
+    secrets: &IndexMap<String, String>,
     replacements: &HashMap<String, String>,
     sandbox_config: Option<&SandboxConfiguration>,
+    limits: &ScriptLimits,
+    cancellation_token: &CancellationToken,
 ) -> Result<std::process::Output, std::io::Error> {
     let mut command = if let Some(sandbox_config) = sandbox_config {
         #[cfg(any(
@@ -444,11 +572,18 @@ async fn run_process_with_replacements(
     command
         .current_dir(cwd)
         .args(&args[1..])
+        .envs(secrets)
         .stdin(Stdio::null())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped());
 
+    let process_limits = limits::ProcessLimits::new(limits, &mut command);
+
     let mut child = command.spawn()?;
+    if let Err(e) = process_limits.attach(&child) {
+        process_limits.kill(&mut child).await;
+        return Err(e);
+    }
 
     let stdout = child.stdout.take().expect("Failed to take stdout");
     let stderr = child.stderr.take().expect("Failed to take stderr");
@@ -460,44 +595,83 @@ async fn run_process_with_replacements(
     let mut stderr_log = String::new();
     let mut closed = (false, false);
 
-    loop {
-        let (line, is_stderr) = tokio::select! {
-            line = stdout_lines.next_line() => (line, false),
-            line = stderr_lines.next_line() => (line, true),
-            else => break,
-        };
-
-        match line {
-            Ok(Some(line)) => {
-                let filtered_line = replacements
-                    .iter()
-                    .fold(line, |acc, (from, to)| acc.replace(from, to));
+    let run = async {
+        loop {
+            let (line, is_stderr) = tokio::select! {
+                line = stdout_lines.next_line() => (line, false),
+                line = stderr_lines.next_line() => (line, true),
+                else => break,
+            };
+
+            match line {
+                Ok(Some(line)) => {
+                    let filtered_line = replacements
+                        .iter()
+                        .fold(line, |acc, (from, to)| acc.replace(from, to));
+
+                    if is_stderr {
+                        stderr_log.push_str(&filtered_line);
+                        stderr_log.push('\n');
+                    } else {
+                        stdout_log.push_str(&filtered_line);
+                        stdout_log.push('\n');
+                    }
 
-                if is_stderr {
-                    stderr_log.push_str(&filtered_line);
-                    stderr_log.push('\n');
-                } else {
-                    stdout_log.push_str(&filtered_line);
-                    stdout_log.push('\n');
+                    tracing::info!("{}", filtered_line);
                 }
-
-                tracing::info!("{}", filtered_line);
-            }
-            Ok(None) if !is_stderr => closed.0 = true,
-            Ok(None) if is_stderr => closed.1 = true,
-            Ok(None) => unreachable!(),
-            Err(e) => {
-                tracing::warn!("Error reading output: {:?}", e);
+                Ok(None) if !is_stderr => closed.0 = true,
+                Ok(None) if is_stderr => closed.1 = true,
+                Ok(None) => unreachable!(),
+                Err(e) => {
+                    tracing::warn!("Error reading output: {:?}", e);
+                    break;
+                }
+            };
+            // make sure we close the loop when both stdout and stderr are closed
+            if closed == (true, true) {
                 break;
             }
-        };
-        // make sure we close the loop when both stdout and stderr are closed
-        if closed == (true, true) {
-            break;
         }
-    }
 
-    let status = child.wait().await?;
+        child.wait().await
+    };
+
+    let timeout = async {
+        match limits.timeout {
+            Some(timeout) => {
+                tokio::time::sleep(timeout.0).await;
+                timeout
+            }
+            None => std::future::pending().await,
+        }
+    };
+
+    let result = tokio::select! {
+        status = run => Ok(status?),
+        timeout = timeout => Err(std::io::Error::new(
+            std::io::ErrorKind::TimedOut,
+            format!(
+                "the script did not finish within {} and was killed (`build.script.timeout`)",
+                timeout
+            ),
+        )),
+        _ = cancellation_token.cancelled() => Err(std::io::Error::new(
+            std::io::ErrorKind::Interrupted,
+            "the script was cancelled",
+        )),
+    };
+
+    let status = match result {
+        Ok(status) => status,
+        Err(e) => {
+            process_limits.kill(&mut child).await;
+            return Err(e);
+        }
+    };
+
+    if !status.success() && process_limits.memory_exceeded() {
+        return Err(process_limits.memory_exceeded_error());
+    }
 
     Ok(std::process::Output {
         status,
@@ -505,3 +679,38 @@ async fn run_process_with_replacements(
         stderr: stderr_log.into_bytes(),
     })
 }
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use tokio_util::sync::CancellationToken;
+
+    use super::run_process_with_replacements;
+
+    #[tokio::test]
+    async fn test_cancel_kills_script() {
+        let dir = tempfile::tempdir().unwrap();
+        let cancellation_token = CancellationToken::new();
+        let cancel = cancellation_token.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            cancel.cancel();
+        });
+
+        let start = Instant::now();
+        let err = run_process_with_replacements(
+            &["sh", "-c", "sleep 60"],
+            dir.path(),
+            &Default::default(),
+            &Default::default(),
+            None,
+            &Default::default(),
+            &cancellation_token,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::Interrupted);
+        assert!(start.elapsed() < Duration::from_secs(30));
+    }
+}
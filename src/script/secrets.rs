@@ -0,0 +1,289 @@
+//! Resolution of the `secrets` of a build script.
+//!
+//! A secret is either the plain name of an environment variable that is forwarded from the
+//! host environment, or a provider URI that is resolved right before the script is executed:
+//!
+//! - `env:VAR` reads the value from the environment variable `VAR`
+//! - `file:/run/secrets/token` reads the (trimmed) contents of a file
+//! - `cmd:vault read -field=token secret/foo` runs a command and uses its (trimmed) stdout. The
+//!   command line is split into arguments like a POSIX shell would (`cmd:tool --name 'a b'`),
+//!   but it is not run in a shell, so pipes, redirections and variables are not supported.
+//!
+//! The name of the environment variable that receives the value can be given explicitly with
+//! `NAME=<provider>:<argument>`. Without it the name is derived from the variable name (`env:`)
+//! or the file name (`file:`). Commands always require an explicit name.
+
+use std::path::{Path, PathBuf};
+
+use indexmap::IndexMap;
+
+/// The string that is used to mask secret values in the logs.
+pub const SECRET_MASK: &str = "********";
+
+/// Where the value of a secret comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SecretSource {
+    /// An environment variable of the host environment.
+    Env(String),
+    /// The contents of a file.
+    File(PathBuf),
+    /// The standard output of a command (the program and its arguments).
+    Command(Vec<String>),
+}
+
+/// A secret as declared in `script.secrets`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecretSpec {
+    /// The name of the environment variable that receives the secret value.
+    pub name: String,
+    /// The source of the secret value.
+    pub source: SecretSource,
+}
+
+/// An error that can occur while parsing or resolving a secret.
+#[derive(Debug, thiserror::Error)]
+pub enum SecretError {
+    /// The secret declaration could not be parsed.
+    #[error("invalid secret `{0}`: {1}")]
+    InvalidSpec(String, String),
+
+    /// The environment variable holding the secret is not set.
+    #[error("secret environment variable `{0}` is not set")]
+    MissingEnv(String),
+
+    /// The file holding the secret could not be read.
+    #[error("failed to read secret `{0}` from file")]
+    File(String, #[source] std::io::Error),
+
+    /// The command providing the secret could not be started.
+    #[error("failed to run command for secret `{0}`")]
+    RunCommand(String, #[source] std::io::Error),
+
+    /// The command providing the secret failed.
+    #[error("command for secret `{0}` failed: {1}")]
+    Command(String, String),
+}
+
+fn is_valid_env_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Derive an environment variable name from the file name of a secret file, e.g.
+/// `/run/secrets/api-token` becomes `API_TOKEN`.
+fn name_from_path(path: &Path) -> Option<String> {
+    let name = path
+        .file_stem()?
+        .to_string_lossy()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    is_valid_env_name(&name).then_some(name)
+}
+
+impl SecretSpec {
+    /// Parse a secret declaration from the recipe.
+    pub fn parse(spec: &str) -> Result<Self, SecretError> {
+        let invalid = |msg: &str| SecretError::InvalidSpec(spec.to_string(), msg.to_string());
+
+        // An explicit name is only recognized when it precedes the provider prefix.
+        let (name, source) = match (spec.find('='), spec.find(':')) {
+            (Some(eq), colon) if colon.map_or(true, |colon| eq < colon) => {
+                (Some(spec[..eq].trim()), spec[eq + 1..].trim())
+            }
+            _ => (None, spec.trim()),
+        };
+
+        let source = match source.split_once(':') {
+            Some(("env", var)) => SecretSource::Env(var.trim().to_string()),
+            Some(("file", path)) => SecretSource::File(PathBuf::from(path.trim())),
+            Some(("cmd", command)) => SecretSource::Command(
+                shlex::split(command).ok_or_else(|| invalid("unbalanced quotes in command"))?,
+            ),
+            Some((provider, _)) => {
+                return Err(invalid(&format!(
+                    "unknown secret provider `{provider}`, expected one of `env`, `file` or `cmd`"
+                )))
+            }
+            None => SecretSource::Env(source.to_string()),
+        };
+
+        let name = match (name, &source) {
+            (Some(name), _) => name.to_string(),
+            (None, SecretSource::Env(var)) => var.clone(),
+            (None, SecretSource::File(path)) => name_from_path(path).ok_or_else(|| {
+                invalid("cannot derive a variable name from the file name, use `NAME=file:...`")
+            })?,
+            (None, SecretSource::Command(_)) => {
+                return Err(invalid(
+                    "command secrets require an explicit name, use `NAME=cmd:...`",
+                ))
+            }
+        };
+
+        if !is_valid_env_name(&name) {
+            return Err(invalid(&format!("`{name}` is not a valid variable name")));
+        }
+
+        match &source {
+            SecretSource::Env(var) if var.is_empty() => Err(invalid("missing variable name")),
+            SecretSource::File(path) if path.as_os_str().is_empty() => {
+                Err(invalid("missing file path"))
+            }
+            SecretSource::Command(args) if args.is_empty() => Err(invalid("missing command")),
+            _ => Ok(Self { name, source }),
+        }
+    }
+
+    /// Resolve the value of the secret.
+    pub fn resolve(&self) -> Result<String, SecretError> {
+        match &self.source {
+            SecretSource::Env(var) => {
+                std::env::var(var).map_err(|_| SecretError::MissingEnv(var.clone()))
+            }
+            SecretSource::File(path) => fs_err::read_to_string(path)
+                .map(|value| value.trim_end_matches(['\r', '\n']).to_string())
+                .map_err(|e| SecretError::File(self.name.clone(), e)),
+            SecretSource::Command(args) => {
+                let output = std::process::Command::new(&args[0])
+                    .args(&args[1..])
+                    .stdin(std::process::Stdio::null())
+                    .output()
+                    .map_err(|e| SecretError::RunCommand(self.name.clone(), e))?;
+                if !output.status.success() {
+                    // Never include the output of the command, it might contain the secret.
+                    return Err(SecretError::Command(
+                        self.name.clone(),
+                        format!("command exited with {}", output.status),
+                    ));
+                }
+                Ok(String::from_utf8_lossy(&output.stdout)
+                    .trim_end_matches(['\r', '\n'])
+                    .to_string())
+            }
+        }
+    }
+}
+
+/// Resolve all secrets of a script into a map of environment variable names to values.
+///
+/// Secrets that are plain environment variable names and that are not set only emit a warning,
+/// while all other resolution errors are returned.
+pub fn resolve_secrets(secrets: &[String]) -> Result<IndexMap<String, String>, SecretError> {
+    let mut resolved = IndexMap::new();
+    for secret in secrets {
+        let spec = SecretSpec::parse(secret)?;
+        match spec.resolve() {
+            Ok(value) => {
+                resolved.insert(spec.name, value);
+            }
+            Err(SecretError::MissingEnv(var)) if !secret.contains(':') => {
+                tracing::warn!("Secret {} not found in environment", var);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(resolved)
+}
+
+/// Returns all strings that should be masked for the given secret value. Output is filtered
+/// line by line, so every line of a multi-line secret is masked on its own.
+pub fn secret_masks(value: &str) -> impl Iterator<Item = &str> {
+    std::iter::once(value)
+        .chain(value.lines())
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_secret_spec() {
+        assert_eq!(
+            SecretSpec::parse("FOO").unwrap(),
+            SecretSpec {
+                name: "FOO".into(),
+                source: SecretSource::Env("FOO".into())
+            }
+        );
+        assert_eq!(
+            SecretSpec::parse("env:BAR").unwrap(),
+            SecretSpec {
+                name: "BAR".into(),
+                source: SecretSource::Env("BAR".into())
+            }
+        );
+        assert_eq!(
+            SecretSpec::parse("TOKEN=env:GITHUB_TOKEN").unwrap(),
+            SecretSpec {
+                name: "TOKEN".into(),
+                source: SecretSource::Env("GITHUB_TOKEN".into())
+            }
+        );
+        assert_eq!(
+            SecretSpec::parse("file:/run/secrets/api-token").unwrap(),
+            SecretSpec {
+                name: "API_TOKEN".into(),
+                source: SecretSource::File("/run/secrets/api-token".into())
+            }
+        );
+        assert_eq!(
+            SecretSpec::parse("KEY=cmd:vault read -field=key secret/foo").unwrap(),
+            SecretSpec {
+                name: "KEY".into(),
+                source: SecretSource::Command(vec![
+                    "vault".into(),
+                    "read".into(),
+                    "-field=key".into(),
+                    "secret/foo".into()
+                ])
+            }
+        );
+        assert_eq!(
+            SecretSpec::parse(r#"KEY=cmd:op read "op://My Vault/api token/credential""#)
+                .unwrap()
+                .source,
+            SecretSource::Command(vec![
+                "op".into(),
+                "read".into(),
+                "op://My Vault/api token/credential".into()
+            ])
+        );
+
+        assert!(SecretSpec::parse("cmd:vault read secret/foo").is_err());
+        assert!(SecretSpec::parse("vault:foo").is_err());
+        assert!(SecretSpec::parse("1FOO=env:BAR").is_err());
+        assert!(SecretSpec::parse("env:").is_err());
+        assert!(SecretSpec::parse("KEY=cmd:").is_err());
+        assert!(SecretSpec::parse("KEY=cmd:vault read 'secret/foo").is_err());
+    }
+
+    #[test]
+    fn test_resolve_file_secret() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("token");
+        fs_err::write(&path, "s3cr3t\n").unwrap();
+
+        let spec = SecretSpec::parse(&format!("TOKEN=file:{}", path.display())).unwrap();
+        assert_eq!(spec.resolve().unwrap(), "s3cr3t");
+    }
+
+    #[test]
+    fn test_secret_masks() {
+        assert_eq!(secret_masks("abc").collect::<Vec<_>>(), vec!["abc", "abc"]);
+        assert_eq!(
+            secret_masks("line1\nline2\n").collect::<Vec<_>>(),
+            vec!["line1\nline2", "line1", "line2"]
+        );
+        assert_eq!(secret_masks("").count(), 0);
+    }
+}
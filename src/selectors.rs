@@ -1,15 +1,167 @@
 //! Contains the selector config, which is used to render the recipe.
 
-use std::collections::BTreeMap;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
 
 use crate::{
-    hash::HashInfo,
+    hash::{HashConfig, HashInfo},
     normalized_key::NormalizedKey,
     recipe::jinja::{Env, Git},
 };
 
 use minijinja::value::Value;
-use rattler_conda_types::Platform;
+use rattler_conda_types::{ChannelConfig, Platform};
+use rattler_repodata_gateway::Gateway;
+use serde::{Deserialize, Serialize};
+
+/// The variant key that selects the platform (family) that a `noarch` package is
+/// built for
+pub const NOARCH_PLATFORM_KEY: &str = "noarch_platform";
+
+/// The platform (family) of a `noarch` package that has platform specific
+/// dependencies (e.g. `noarch_platform: [unix, win]`). Platform selectors in the
+/// recipe are evaluated for this platform and the package depends on the
+/// matching virtual packages (e.g. `__unix` or `__win`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoArchPlatform {
+    /// Any unix platform (`__unix`)
+    Unix,
+    /// Windows (`__win`)
+    Win,
+    /// A specific platform (e.g. `linux-64`)
+    Platform(Platform),
+}
+
+impl FromStr for NoArchPlatform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "unix" => Ok(Self::Unix),
+            "win" => Ok(Self::Win),
+            platform => Platform::from_str(platform)
+                .map(Self::Platform)
+                .map_err(|_| format!("invalid noarch platform: {s}")),
+        }
+    }
+}
+
+impl NoArchPlatform {
+    /// The virtual packages that a package built for this platform depends on
+    pub fn virtual_packages(&self) -> Vec<&'static str> {
+        match self {
+            Self::Unix => vec!["__unix"],
+            Self::Win => vec!["__win"],
+            Self::Platform(platform) if platform.is_windows() => vec!["__win"],
+            Self::Platform(platform) if platform.is_linux() => vec!["__unix", "__linux"],
+            Self::Platform(platform) if platform.is_osx() => vec!["__unix", "__osx"],
+            Self::Platform(platform) if platform.is_unix() => vec!["__unix"],
+            Self::Platform(_) => vec![],
+        }
+    }
+
+    /// Whether a package built for this platform can be installed on `platform`
+    pub fn matches(&self, platform: Platform) -> bool {
+        match self {
+            Self::Unix => platform.is_unix(),
+            Self::Win => platform.is_windows(),
+            Self::Platform(p) => p.only_platform() == platform.only_platform(),
+        }
+    }
+}
+
+/// The prefix of variant keys that hold the value of an environment variable
+/// (e.g. `env.CI_COMMIT_SHA`)
+pub const ENV_VARIANT_PREFIX: &str = "env.";
+
+/// Controls which environment variables a recipe can read with `env.get` and
+/// `env.exists`, and which of them are part of the variant (and its hash).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EnvAccess {
+    /// The environment variables that can be read. If empty, all environment
+    /// variables can be read.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub allow: BTreeSet<String>,
+
+    /// Environment variables that are added to the variant when the recipe
+    /// reads them, so that their value changes the hash of the package.
+    /// These variables can always be read.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub hash: BTreeSet<String>,
+
+    /// Do not allow the recipe to read any environment variable (e.g. when
+    /// rendering recipes of untrusted clients)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub deny_all: bool,
+}
+
+impl EnvAccess {
+    /// Returns true if this is the default (unrestricted) configuration
+    pub fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// A configuration that does not allow reading any environment variable
+    pub fn deny_all() -> Self {
+        Self {
+            deny_all: true,
+            ..Self::default()
+        }
+    }
+
+    /// Returns true if the recipe is allowed to read the environment variable
+    pub fn is_allowed(&self, name: &str) -> bool {
+        if self.deny_all {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.contains(name) || self.hash.contains(name)
+    }
+
+    /// The value of the environment variable for the variant, if it is part of
+    /// the hash and set
+    pub fn hashed_value(&self, name: &str) -> Option<String> {
+        if self.deny_all || !self.hash.contains(name) {
+            return None;
+        }
+        std::env::var(name).ok()
+    }
+}
+
+/// The repodata that recipes can query with the `latest_version` Jinja
+/// function (the gateway and channel configuration of the build)
+#[derive(Clone)]
+pub struct RepodataAccess {
+    /// The gateway to query the channels
+    pub gateway: Gateway,
+    /// The channel configuration to resolve channel names
+    pub channel_config: ChannelConfig,
+    /// The results of `latest_version` by spec, channel and platform. A recipe
+    /// is rendered many times (for every variant), but the channel is only
+    /// queried once per render of the recipe.
+    pub(crate) latest_versions: Arc<Mutex<HashMap<(String, String, Platform), String>>>,
+}
+
+impl RepodataAccess {
+    /// Query the channels with the given gateway and channel configuration
+    pub fn new(gateway: Gateway, channel_config: ChannelConfig) -> Self {
+        Self {
+            gateway,
+            channel_config,
+            latest_versions: Arc::default(),
+        }
+    }
+}
+
+impl std::fmt::Debug for RepodataAccess {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RepodataAccess")
+            .field("channel_config", &self.channel_config)
+            .finish_non_exhaustive()
+    }
+}
 
 /// The selector config is used to render the recipe.
 #[derive(Clone, Debug)]
@@ -28,6 +180,15 @@ pub struct SelectorConfig {
     pub experimental: bool,
     /// Allow undefined variables
     pub allow_undefined: bool,
+    /// Fail when an undefined variable is rendered or used in an expression
+    /// (instead of rendering it as an empty string)
+    pub strict_jinja: bool,
+    /// The configuration used to compute the hash of a variant
+    pub hash_config: HashConfig,
+    /// The environment variables that the recipe can read
+    pub env_access: EnvAccess,
+    /// The repodata that `latest_version` queries, if available
+    pub repodata_access: Option<RepodataAccess>,
 }
 
 impl SelectorConfig {
@@ -45,22 +206,44 @@ impl SelectorConfig {
             Value::from_safe_string(self.host_platform.to_string()),
         );
 
-        if let Some(platform) = self.host_platform.only_platform() {
-            context.insert(
-                platform.to_string(),
-                Value::from_safe_string(platform.to_string()),
-            );
+        // platform selectors of `noarch` packages that are built for a specific
+        // platform (family) are evaluated for that platform
+        match self.noarch_platform() {
+            Some(NoArchPlatform::Unix) => {
+                context.insert("unix".to_string(), Value::from(true));
+            }
+            Some(NoArchPlatform::Win) => {
+                context.insert("win".to_string(), Value::from_safe_string("win".into()));
+                context.insert("unix".to_string(), Value::from(false));
+            }
+            Some(NoArchPlatform::Platform(platform)) => {
+                if let Some(only_platform) = platform.only_platform() {
+                    context.insert(
+                        only_platform.to_string(),
+                        Value::from_safe_string(only_platform.to_string()),
+                    );
+                }
+                context.insert("unix".to_string(), Value::from(platform.is_unix()));
+            }
+            None => {
+                if let Some(platform) = self.host_platform.only_platform() {
+                    context.insert(
+                        platform.to_string(),
+                        Value::from_safe_string(platform.to_string()),
+                    );
+                }
+
+                context.insert(
+                    "unix".to_string(),
+                    Value::from(self.host_platform.is_unix()),
+                );
+            }
         }
 
         if let Some(arch) = self.target_platform.arch() {
             context.insert(arch.to_string(), Value::from(true));
         }
 
-        context.insert(
-            "unix".to_string(),
-            Value::from(self.host_platform.is_unix()),
-        );
-
         context.insert(
             "build_platform".to_string(),
             Value::from_safe_string(self.build_platform.to_string()),
@@ -68,9 +251,18 @@ impl SelectorConfig {
 
         if let Some(hash) = self.hash {
             context.insert("hash".to_string(), Value::from_safe_string(hash.hash));
+        } else if self.strict_jinja {
+            // the hash is only known after the variants are resolved (e.g. for
+            // `build.string`), so it is not an undefined variable
+            context.insert("hash".to_string(), Value::from_safe_string(String::new()));
         }
 
-        context.insert("env".to_string(), Value::from_object(Env));
+        context.insert(
+            "env".to_string(),
+            Value::from_object(Env {
+                access: self.env_access.clone(),
+            }),
+        );
         context.insert(
             "git".to_string(),
             Value::from_object(Git {
@@ -86,6 +278,14 @@ impl SelectorConfig {
         context
     }
 
+    /// The platform (family) that a `noarch` package is built for, as defined
+    /// by the `noarch_platform` variant
+    pub fn noarch_platform(&self) -> Option<NoArchPlatform> {
+        self.variant
+            .get(&NormalizedKey::from(NOARCH_PLATFORM_KEY))
+            .and_then(|value| value.parse().ok())
+    }
+
     /// Create a new selector config from an existing one, replacing the variant
     pub fn with_variant(
         &self,
@@ -110,6 +310,53 @@ impl Default for SelectorConfig {
             variant: Default::default(),
             experimental: false,
             allow_undefined: false,
+            strict_jinja: false,
+            hash_config: HashConfig::default(),
+            env_access: EnvAccess::default(),
+            repodata_access: None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_noarch_platform_selectors() {
+        let mut config = SelectorConfig {
+            host_platform: Platform::Linux64,
+            ..Default::default()
+        };
+        config
+            .variant
+            .insert(NOARCH_PLATFORM_KEY.into(), "win".to_string());
+
+        let context = config.clone().into_context();
+        assert!(context.contains_key("win"));
+        assert!(!context.contains_key("linux"));
+        assert_eq!(context.get("unix"), Some(&Value::from(false)));
+
+        config
+            .variant
+            .insert(NOARCH_PLATFORM_KEY.into(), "unix".to_string());
+        let context = config.into_context();
+        assert!(!context.contains_key("win"));
+        assert!(!context.contains_key("linux"));
+        assert_eq!(context.get("unix"), Some(&Value::from(true)));
+    }
+
+    #[test]
+    fn test_noarch_platform() {
+        let win: NoArchPlatform = "win".parse().unwrap();
+        assert_eq!(win.virtual_packages(), vec!["__win"]);
+        assert!(win.matches(Platform::Win64));
+        assert!(!win.matches(Platform::Linux64));
+
+        let osx: NoArchPlatform = "osx-arm64".parse().unwrap();
+        assert_eq!(osx.virtual_packages(), vec!["__unix", "__osx"]);
+        assert!(osx.matches(Platform::Osx64));
+
+        assert!("foo".parse::<NoArchPlatform>().is_err());
+    }
+}
@@ -0,0 +1,270 @@
+//! Compare rendered recipes against checked-in golden snapshots.
+//!
+//! Every output of a recipe is rendered for a set of platforms and written as
+//! a YAML document (the variant and the final rendered recipe) to
+//! `<snapshot dir>/<platform>/<name>-<version>-<build string>.yaml`. Comparing
+//! these files with freshly rendered ones makes the effect of a recipe
+//! refactor reviewable: any change in the rendered recipe shows up as a
+//! unified diff, and any change in the variants shows up as a new or stale
+//! snapshot. Only the snapshots of the rendered platforms (and `noarch`) are
+//! compared, so rendering a subset of the platforms does not report (or
+//! remove) the snapshots of the other platforms.
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display, Formatter},
+    path::{Path, PathBuf},
+};
+
+use fs_err as fs;
+use rattler_conda_types::Platform;
+use serde::Serialize;
+
+use crate::{metadata::Output, normalized_key::NormalizedKey, recipe::Recipe};
+
+/// The default name of the directory (next to the recipe) with the snapshots
+pub const DEFAULT_SNAPSHOT_DIR: &str = "snapshots";
+
+/// Errors that can occur while comparing or updating snapshots
+#[derive(Debug, thiserror::Error)]
+pub enum SnapshotError {
+    /// An IO error occurred
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    /// Failed to walk the snapshot directory
+    #[error(transparent)]
+    WalkDir(#[from] walkdir::Error),
+
+    /// Failed to serialize a rendered output
+    #[error("failed to serialize rendered output: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+}
+
+/// The content of a single snapshot file
+#[derive(Serialize)]
+struct GoldenRender<'a> {
+    variant: &'a BTreeMap<NormalizedKey, String>,
+    recipe: &'a Recipe,
+}
+
+/// The path of the snapshot of an output, relative to the snapshot directory
+pub fn snapshot_path(output: &Output) -> PathBuf {
+    PathBuf::from(output.target_platform().as_str()).join(format!("{}.yaml", output.identifier()))
+}
+
+/// Render the snapshot content of an output
+pub fn render_snapshot(output: &Output) -> Result<String, SnapshotError> {
+    Ok(serde_yaml::to_string(&GoldenRender {
+        variant: &output.build_configuration.variant,
+        recipe: &output.recipe,
+    })?)
+}
+
+/// A difference between the rendered outputs and the checked-in snapshots
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SnapshotMismatch {
+    /// An output was rendered for which no snapshot exists
+    Missing(PathBuf),
+    /// The snapshot differs from the rendered output
+    Changed {
+        /// The path of the snapshot relative to the snapshot directory
+        path: PathBuf,
+        /// A unified diff from the snapshot to the rendered output
+        diff: String,
+    },
+    /// A snapshot exists for which no output was rendered
+    Stale(PathBuf),
+}
+
+impl Display for SnapshotMismatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotMismatch::Missing(path) => write!(f, "+ {} (new output)", path.display()),
+            SnapshotMismatch::Stale(path) => {
+                write!(f, "- {} (output no longer rendered)", path.display())
+            }
+            SnapshotMismatch::Changed { path, diff } => {
+                writeln!(f, "~ {} (changed)", path.display())?;
+                write!(f, "{}", diff.trim_end())
+            }
+        }
+    }
+}
+
+/// Collect the snapshot files of the platforms (and `noarch`) in a directory,
+/// relative to the directory
+fn existing_snapshots(
+    snapshot_dir: &Path,
+    platforms: &[Platform],
+) -> Result<Vec<PathBuf>, SnapshotError> {
+    let mut subdirs = platforms
+        .iter()
+        .chain([&Platform::NoArch])
+        .map(|platform| snapshot_dir.join(platform.as_str()))
+        .collect::<Vec<_>>();
+    subdirs.sort();
+    subdirs.dedup();
+
+    let mut snapshots = Vec::new();
+    for entry in subdirs
+        .iter()
+        .filter(|subdir| subdir.exists())
+        .flat_map(|subdir| walkdir::WalkDir::new(subdir).sort_by_file_name())
+    {
+        let entry = entry?;
+        if entry.file_type().is_file() && entry.path().extension().is_some_and(|ext| ext == "yaml")
+        {
+            let relative = entry
+                .path()
+                .strip_prefix(snapshot_dir)
+                .expect("walkdir entries are inside the root")
+                .to_path_buf();
+            snapshots.push(relative);
+        }
+    }
+    Ok(snapshots)
+}
+
+/// Compare rendered snapshots (keyed by their relative path) with the files of
+/// the rendered platforms in the snapshot directory
+pub fn compare_snapshots(
+    snapshot_dir: &Path,
+    platforms: &[Platform],
+    rendered: &BTreeMap<PathBuf, String>,
+) -> Result<Vec<SnapshotMismatch>, SnapshotError> {
+    let mut mismatches = Vec::new();
+
+    for (path, content) in rendered {
+        let file = snapshot_dir.join(path);
+        if !file.exists() {
+            mismatches.push(SnapshotMismatch::Missing(path.clone()));
+            continue;
+        }
+
+        let expected = fs::read_to_string(&file)?;
+        if &expected != content {
+            let name = path.to_string_lossy().replace('\\', "/");
+            let diff = diffy::create_patch(&expected, content)
+                .to_string()
+                .replacen(
+                    "--- original\n+++ modified\n",
+                    &format!("--- a/{name}\n+++ b/{name}\n"),
+                    1,
+                );
+            mismatches.push(SnapshotMismatch::Changed {
+                path: path.clone(),
+                diff,
+            });
+        }
+    }
+
+    for path in existing_snapshots(snapshot_dir, platforms)? {
+        if !rendered.contains_key(&path) {
+            mismatches.push(SnapshotMismatch::Stale(path));
+        }
+    }
+
+    Ok(mismatches)
+}
+
+/// Write the rendered snapshots to the snapshot directory and remove the stale
+/// snapshots of the rendered platforms
+pub fn update_snapshots(
+    snapshot_dir: &Path,
+    platforms: &[Platform],
+    rendered: &BTreeMap<PathBuf, String>,
+) -> Result<(), SnapshotError> {
+    for path in existing_snapshots(snapshot_dir, platforms)? {
+        if !rendered.contains_key(&path) {
+            fs::remove_file(snapshot_dir.join(path))?;
+        }
+    }
+
+    for (path, content) in rendered {
+        let file = snapshot_dir.join(path);
+        if let Some(parent) = file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(file, content)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compare_and_update_snapshots() {
+        let dir = tempfile::tempdir().unwrap();
+        let snapshot_dir = dir.path().join("snapshots");
+        let platforms = [Platform::Linux64, Platform::Osx64];
+
+        let mut rendered = BTreeMap::new();
+        rendered.insert(
+            PathBuf::from("linux-64/foo-1.0-h1234_0.yaml"),
+            "recipe: 1\n".to_string(),
+        );
+        rendered.insert(
+            PathBuf::from("osx-64/foo-1.0-h5678_0.yaml"),
+            "recipe: 2\n".to_string(),
+        );
+
+        let mismatches = compare_snapshots(&snapshot_dir, &platforms, &rendered).unwrap();
+        assert_eq!(mismatches.len(), 2);
+        assert!(matches!(mismatches[0], SnapshotMismatch::Missing(_)));
+
+        update_snapshots(&snapshot_dir, &platforms, &rendered).unwrap();
+        assert!(compare_snapshots(&snapshot_dir, &platforms, &rendered)
+            .unwrap()
+            .is_empty());
+
+        rendered.remove(&PathBuf::from("osx-64/foo-1.0-h5678_0.yaml"));
+        rendered.insert(
+            PathBuf::from("linux-64/foo-1.0-h1234_0.yaml"),
+            "recipe: 3\n".to_string(),
+        );
+        let mismatches = compare_snapshots(&snapshot_dir, &platforms, &rendered).unwrap();
+        assert_eq!(mismatches.len(), 2);
+        match &mismatches[0] {
+            SnapshotMismatch::Changed { diff, .. } => {
+                assert!(diff.contains("--- a/linux-64/foo-1.0-h1234_0.yaml"));
+                assert!(diff.contains("-recipe: 1"));
+                assert!(diff.contains("+recipe: 3"));
+            }
+            other => panic!("unexpected mismatch: {other:?}"),
+        }
+        assert_eq!(
+            mismatches[1],
+            SnapshotMismatch::Stale(PathBuf::from("osx-64/foo-1.0-h5678_0.yaml"))
+        );
+
+        update_snapshots(&snapshot_dir, &platforms, &rendered).unwrap();
+        assert!(compare_snapshots(&snapshot_dir, &platforms, &rendered)
+            .unwrap()
+            .is_empty());
+        assert!(!snapshot_dir.join("osx-64/foo-1.0-h5678_0.yaml").exists());
+
+        // the snapshots of platforms that are not rendered are neither stale
+        // nor removed
+        let linux = [Platform::Linux64];
+        fs::create_dir_all(snapshot_dir.join("win-64")).unwrap();
+        fs::write(
+            snapshot_dir.join("win-64/foo-1.0-h9999_0.yaml"),
+            "recipe: 4\n",
+        )
+        .unwrap();
+        assert!(compare_snapshots(&snapshot_dir, &linux, &rendered)
+            .unwrap()
+            .is_empty());
+        update_snapshots(&snapshot_dir, &linux, &rendered).unwrap();
+        assert!(snapshot_dir.join("win-64/foo-1.0-h9999_0.yaml").exists());
+        assert_eq!(
+            compare_snapshots(&snapshot_dir, &[Platform::Win64], &BTreeMap::new()).unwrap(),
+            vec![SnapshotMismatch::Stale(PathBuf::from(
+                "win-64/foo-1.0-h9999_0.yaml"
+            ))]
+        );
+    }
+}
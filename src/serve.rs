@@ -0,0 +1,709 @@
+//! An experimental HTTP API for recipes (`rattler-build serve`).
+//!
+//! The server answers JSON requests with the same library functions that the
+//! CLI uses, so that tools do not have to start a `rattler-build` process for
+//! every recipe. It provides the following endpoints:
+//!
+//! - `GET /health`: the version of rattler-build
+//! - `POST /lint`: the diagnostics of a recipe (the same as `rattler-build lsp`)
+//! - `POST /variants`: the variants and the hash input of every output of a
+//!   recipe
+//! - `POST /render`: the same as `/variants`, with the rendered recipe of every
+//!   output and the requirements that it inherits from the top-level `cache`
+//!
+//! The `render` and `variants` endpoints take the recipe, the variant
+//! configuration (in the format of `variants.yaml`) and the target platform:
+//!
+//! ```json
+//! {
+//!   "recipe": "package: ...",
+//!   "variants": { "python": ["3.11", "3.12"] },
+//!   "target_platform": "linux-64"
+//! }
+//! ```
+//!
+//! All endpoints accept the `recipe_path` of the recipe file on the server
+//! (optional), the `include`s of the recipe are resolved relative to it.
+//!
+//! Recipes are rendered without access to the environment variables of the
+//! server. Requests with a `Host` header other than an IP address, `localhost`
+//! or one of the allowed hosts are rejected, so that websites cannot reach the
+//! server through DNS rebinding. The connections are handled by a fixed
+//! number of threads and closed after the response.
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{IpAddr, TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc, Mutex},
+    time::Duration,
+};
+
+use miette::Diagnostic;
+use rattler_conda_types::Platform;
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value};
+
+use crate::{
+    hash::HashInput,
+    lsp,
+    recipe::parser::{find_outputs_from_recipe_file, find_outputs_from_src},
+    selectors::{EnvAccess, SelectorConfig},
+    variant_config::VariantConfig,
+};
+
+/// The largest request body that is accepted (10 MiB)
+const MAX_BODY_SIZE: usize = 10 * 1024 * 1024;
+
+/// How long reading a request or writing a response may take
+const IO_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The number of connections that wait for a free thread before new
+/// connections are rejected
+const MAX_PENDING_CONNECTIONS: usize = 64;
+
+/// The configuration of the server
+#[derive(Debug, Clone, Default)]
+pub struct ServeConfig {
+    /// Host names that are accepted in the `Host` header, in addition to IP
+    /// addresses and `localhost`
+    pub allowed_hosts: Vec<String>,
+    /// Enable experimental features when rendering recipes
+    pub experimental: bool,
+    /// The number of threads that handle connections
+    pub threads: usize,
+}
+
+impl ServeConfig {
+    /// Returns true if the `Host` header of a request is accepted
+    fn is_allowed_host(&self, host: &str) -> bool {
+        // strip the port (`[::1]:8080`, `localhost:8080`)
+        let name = match host.rsplit_once(':') {
+            Some((name, port)) if !name.is_empty() && port.parse::<u16>().is_ok() => {
+                if name.starts_with('[') || !name.contains(':') {
+                    name
+                } else {
+                    // an IPv6 address without brackets and port
+                    host
+                }
+            }
+            _ => host,
+        };
+        let address = name.trim_start_matches('[').trim_end_matches(']');
+        address.parse::<IpAddr>().is_ok()
+            || name.eq_ignore_ascii_case("localhost")
+            || self
+                .allowed_hosts
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(name))
+    }
+}
+
+/// Errors of the HTTP server
+#[derive(Debug, thiserror::Error)]
+pub enum ServeError {
+    /// An IO error occurred
+    #[error(transparent)]
+    Io(#[from] io::Error),
+
+    /// A request of the client could not be read
+    #[error("invalid request: {0}")]
+    InvalidRequest(String),
+
+    /// The body of a request is larger than the limit
+    #[error("the body is larger than {MAX_BODY_SIZE} bytes")]
+    BodyTooLarge,
+}
+
+/// An HTTP request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    /// The method (e.g. `POST`)
+    pub method: String,
+    /// The path without the query string
+    pub path: String,
+    /// The value of the `Host` header
+    pub host: Option<String>,
+    /// The body of the request
+    pub body: Vec<u8>,
+}
+
+/// An HTTP response with a JSON body
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    /// The status code
+    pub status: u16,
+    /// The body of the response
+    pub body: Value,
+}
+
+impl Response {
+    fn ok(body: Value) -> Self {
+        Self { status: 200, body }
+    }
+
+    fn error(status: u16, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            body: json!({ "error": message.into() }),
+        }
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            403 => "Forbidden",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            413 => "Payload Too Large",
+            422 => "Unprocessable Entity",
+            503 => "Service Unavailable",
+            _ => "Internal Server Error",
+        }
+    }
+}
+
+/// The request of the `render` and `variants` endpoints
+#[derive(Debug, Deserialize)]
+struct RenderRequest {
+    /// The text of the recipe
+    recipe: String,
+    /// The path of the recipe file on the server, to resolve `include`s
+    #[serde(default)]
+    recipe_path: Option<PathBuf>,
+    /// The variant configuration
+    #[serde(default)]
+    variants: VariantConfig,
+    /// The platform to render the recipe for (defaults to the current platform)
+    #[serde(default)]
+    target_platform: Option<Platform>,
+}
+
+/// The request of the `lint` endpoint
+#[derive(Debug, Deserialize)]
+struct LintRequest {
+    /// The text of the recipe
+    recipe: String,
+    /// The path of the recipe file on the server, to resolve `include`s
+    #[serde(default)]
+    recipe_path: Option<PathBuf>,
+}
+
+/// The directory that the `include`s of a recipe are resolved against
+fn recipe_dir(recipe_path: Option<&Path>) -> Option<&Path> {
+    recipe_path.and_then(Path::parent)
+}
+
+/// Read a request (request line, headers and a body with `Content-Length`)
+pub fn read_request(reader: &mut impl BufRead) -> Result<Request, ServeError> {
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(ServeError::InvalidRequest(format!(
+            "invalid request line `{}`",
+            request_line.trim_end()
+        )));
+    };
+    let path = target.split_once('?').map_or(target, |(path, _)| path);
+
+    let mut content_length = 0;
+    let mut host = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value
+                    .trim()
+                    .parse::<usize>()
+                    .map_err(|e| ServeError::InvalidRequest(e.to_string()))?;
+            } else if name.eq_ignore_ascii_case("host") {
+                host = Some(value.trim().to_string());
+            }
+        }
+    }
+
+    if content_length > MAX_BODY_SIZE {
+        return Err(ServeError::BodyTooLarge);
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+
+    Ok(Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        host,
+        body,
+    })
+}
+
+/// Write a response to the client
+pub fn write_response(writer: &mut impl Write, response: &Response) -> Result<(), ServeError> {
+    let body = response.body.to_string();
+    write!(
+        writer,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        response.status,
+        response.reason(),
+        body.len(),
+        body
+    )?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Serve the API on the listener until the process is stopped
+pub fn run(listener: TcpListener, config: ServeConfig) -> Result<(), ServeError> {
+    let config = Arc::new(config);
+    let (sender, receiver) = mpsc::sync_channel::<TcpStream>(MAX_PENDING_CONNECTIONS);
+    let receiver = Arc::new(Mutex::new(receiver));
+
+    for _ in 0..config.threads.max(1) {
+        let receiver = receiver.clone();
+        let config = config.clone();
+        std::thread::spawn(move || loop {
+            let stream = match receiver.lock() {
+                Ok(receiver) => receiver.recv(),
+                Err(_) => return,
+            };
+            let Ok(stream) = stream else {
+                return;
+            };
+            if let Err(e) = handle_connection(&stream, &config) {
+                tracing::debug!("Failed to answer a request: {}", e);
+            }
+        });
+    }
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                tracing::warn!("Failed to accept a connection: {}", e);
+                continue;
+            }
+        };
+        if let Err(e) = configure_stream(&stream) {
+            tracing::debug!("Failed to configure a connection: {}", e);
+            continue;
+        }
+        match sender.try_send(stream) {
+            Ok(()) => {}
+            Err(mpsc::TrySendError::Full(stream)) => {
+                let response = Response::error(503, "too many pending requests");
+                if let Err(e) = write_response(&mut &stream, &response) {
+                    tracing::debug!("Failed to answer a request: {}", e);
+                }
+            }
+            Err(mpsc::TrySendError::Disconnected(_)) => break,
+        }
+    }
+    Ok(())
+}
+
+fn configure_stream(stream: &TcpStream) -> io::Result<()> {
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))
+}
+
+fn handle_connection(stream: &TcpStream, config: &ServeConfig) -> Result<(), ServeError> {
+    let mut reader = BufReader::new(stream);
+    let response = match read_request(&mut reader) {
+        Ok(request) => {
+            let response = handle(&request, config);
+            tracing::info!("{} {} {}", request.method, request.path, response.status);
+            response
+        }
+        Err(e @ ServeError::BodyTooLarge) => Response::error(413, e.to_string()),
+        Err(e @ ServeError::InvalidRequest(_)) => Response::error(400, e.to_string()),
+        Err(e) => return Err(e),
+    };
+    write_response(&mut &*stream, &response)
+}
+
+/// Answer a request
+pub fn handle(request: &Request, config: &ServeConfig) -> Response {
+    match request.host.as_deref() {
+        Some(host) if config.is_allowed_host(host) => {}
+        Some(host) => return Response::error(403, format!("host `{host}` is not allowed")),
+        None => return Response::error(400, "the `Host` header is missing"),
+    }
+
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/health") => Response::ok(json!({ "version": env!("CARGO_PKG_VERSION") })),
+        ("POST", "/lint") => match parse_body::<LintRequest>(request) {
+            Ok(lint) => {
+                let recipe_dir = recipe_dir(lint.recipe_path.as_deref());
+                Response::ok(json!({ "diagnostics": lsp::diagnostics(recipe_dir, &lint.recipe) }))
+            }
+            Err(response) => response,
+        },
+        ("POST", "/variants") => match parse_body(request) {
+            Ok(render_request) => render(&render_request, config, false),
+            Err(response) => response,
+        },
+        ("POST", "/render") => match parse_body(request) {
+            Ok(render_request) => render(&render_request, config, true),
+            Err(response) => response,
+        },
+        (_, "/health" | "/lint" | "/variants" | "/render") => Response::error(
+            405,
+            format!("`{}` is not allowed for {}", request.method, request.path),
+        ),
+        (_, path) => Response::error(404, format!("unknown endpoint `{path}`")),
+    }
+}
+
+fn parse_body<T: DeserializeOwned>(request: &Request) -> Result<T, Response> {
+    serde_json::from_slice(&request.body)
+        .map_err(|e| Response::error(400, format!("invalid request body: {e}")))
+}
+
+/// An error of a recipe, with the diagnostics of the recipe
+fn recipe_error(recipe: &str, recipe_dir: Option<&Path>, error: &dyn Diagnostic) -> Response {
+    let mut message = error.to_string();
+    for related in error.related().into_iter().flatten() {
+        message.push('\n');
+        message.push_str(&related.to_string());
+    }
+    Response {
+        status: 422,
+        body: json!({ "error": message, "diagnostics": lsp::diagnostics(recipe_dir, recipe) }),
+    }
+}
+
+/// Find the variants of the recipe and return them (with the rendered recipe
+/// if `with_recipe` is set)
+fn render(request: &RenderRequest, config: &ServeConfig, with_recipe: bool) -> Response {
+    let target_platform = request.target_platform.unwrap_or_else(Platform::current);
+    let selector_config = SelectorConfig {
+        target_platform,
+        host_platform: target_platform,
+        experimental: config.experimental,
+        // the recipes come from the clients, they must not read the
+        // environment of the server
+        env_access: EnvAccess::deny_all(),
+        // allow undefined while finding the variants
+        allow_undefined: true,
+        ..SelectorConfig::default()
+    };
+
+    // the same keys that `VariantConfig::from_files` inserts
+    let mut variant_config = request.variants.clone();
+    variant_config
+        .variants
+        .insert("target_platform".into(), vec![target_platform.to_string()]);
+    variant_config.variants.insert(
+        "build_platform".into(),
+        vec![selector_config.build_platform.to_string()],
+    );
+
+    let recipe_dir = recipe_dir(request.recipe_path.as_deref());
+    let outputs = match recipe_dir {
+        Some(recipe_dir) => {
+            find_outputs_from_recipe_file(&request.recipe, recipe_dir, config.experimental)
+        }
+        None => find_outputs_from_src(&request.recipe),
+    };
+    let outputs = match outputs {
+        Ok(outputs) => outputs,
+        Err(err) => return recipe_error(&request.recipe, recipe_dir, &err),
+    };
+    let discovered =
+        match variant_config.find_variants(&outputs, &request.recipe, recipe_dir, &selector_config)
+        {
+            Ok(discovered) => discovered,
+            Err(err) => return recipe_error(&request.recipe, recipe_dir, &err),
+        };
+
+    let hash_config = &selector_config.hash_config;
+    let mut rendered = Vec::new();
+    for output in discovered {
+        let recipe = &output.recipe;
+        let excluded_keys = output
+            .used_vars
+            .keys()
+            .filter(|key| hash_config.exclude_keys.contains(key))
+            .map(|key| key.normalize())
+            .collect::<Vec<_>>();
+        let mut value = json!({
+            "name": output.name,
+            "version": output.version,
+            "build_string": output.build_string,
+            "target_platform": output.target_platform.to_string(),
+            "variant": output.used_vars,
+            "skip_reason": recipe.build().skip_reason().map(ToString::to_string),
+            "hash": {
+                "input": HashInput::from_variant_with_config(&output.used_vars, hash_config)
+                    .as_str(),
+                "excluded_keys": excluded_keys,
+                "hash": output.hash.to_string(),
+            },
+        });
+        if with_recipe {
+            // the outputs inherit the files of the cache and the run exports
+            // of its requirements, without the run exports that the cache or
+            // the output ignore
+            value["cache"] = match &recipe.cache {
+                Some(cache) => json!({
+                    "requirements": cache.requirements,
+                    "ignore_run_exports": cache
+                        .requirements
+                        .ignore_run_exports(Some(&recipe.requirements().ignore_run_exports(None))),
+                }),
+                None => Value::Null,
+            };
+            match serde_json::to_value(recipe) {
+                Ok(recipe) => value["recipe"] = recipe,
+                Err(e) => return Response::error(500, e.to_string()),
+            }
+        }
+        rendered.push(value);
+    }
+    Response::ok(json!({ "outputs": rendered }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RECIPE: &str =
+        "package:\n  name: test\n  version: 1.0\nrequirements:\n  host:\n    - python\n";
+
+    fn config() -> ServeConfig {
+        ServeConfig {
+            experimental: true,
+            ..ServeConfig::default()
+        }
+    }
+
+    fn post(path: &str, body: Value) -> Response {
+        handle(
+            &Request {
+                method: "POST".to_string(),
+                path: path.to_string(),
+                host: Some("localhost:8080".to_string()),
+                body: body.to_string().into_bytes(),
+            },
+            &config(),
+        )
+    }
+
+    #[test]
+    fn test_request_roundtrip() {
+        let mut reader = io::Cursor::new(
+            "POST /render?pretty=1 HTTP/1.1\r\nHost: localhost\r\ncontent-length: 2\r\n\r\n{}",
+        );
+        let request = read_request(&mut reader).unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/render");
+        assert_eq!(request.host.as_deref(), Some("localhost"));
+        assert_eq!(request.body, b"{}");
+
+        let mut buffer = Vec::new();
+        write_response(&mut buffer, &Response::error(404, "unknown")).unwrap();
+        let response = String::from_utf8(buffer).unwrap();
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        assert!(response.ends_with("\r\n\r\n{\"error\":\"unknown\"}"));
+    }
+
+    #[test]
+    fn test_variants() {
+        let response = post(
+            "/variants",
+            json!({
+                "recipe": RECIPE,
+                "variants": { "python": ["3.11", "3.12"] },
+                "target_platform": "linux-64"
+            }),
+        );
+        assert_eq!(response.status, 200, "{}", response.body);
+        let outputs = response.body["outputs"].as_array().unwrap();
+        assert_eq!(outputs.len(), 2);
+        assert_eq!(outputs[0]["name"], "test");
+        assert_eq!(outputs[0]["target_platform"], "linux-64");
+        let pythons = outputs
+            .iter()
+            .map(|output| output["variant"]["python"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(pythons, vec!["3.11", "3.12"]);
+        assert!(outputs[0].get("recipe").is_none());
+
+        // the hash of the build string is computed from the hash input
+        let hash = &outputs[0]["hash"];
+        assert!(hash["input"].as_str().unwrap().contains("3.11"), "{hash}");
+        let build_string = outputs[0]["build_string"].as_str().unwrap();
+        assert!(build_string.starts_with(hash["hash"].as_str().unwrap()));
+
+        let response = post("/render", json!({ "recipe": RECIPE }));
+        assert_eq!(response.status, 200, "{}", response.body);
+        let recipe = &response.body["outputs"][0]["recipe"];
+        assert_eq!(recipe["package"]["name"], "test");
+    }
+
+    #[test]
+    fn test_render_cache() {
+        let recipe = r#"
+recipe:
+  name: calculator
+  version: 1.0.0
+
+cache:
+  requirements:
+    build:
+      - cmake
+    ignore_run_exports:
+      from_package:
+        - cmake
+
+outputs:
+  - package:
+      name: libcalculator
+  - package:
+      name: py-calculator
+    requirements:
+      ignore_run_exports:
+        by_name:
+          - libzlib
+"#;
+        let response = post("/render", json!({ "recipe": recipe }));
+        assert_eq!(response.status, 200, "{}", response.body);
+        let outputs = response.body["outputs"].as_array().unwrap();
+        assert_eq!(outputs.len(), 2);
+
+        // every output inherits the requirements of the cache
+        for output in outputs {
+            assert_eq!(output["cache"]["requirements"]["build"], json!(["cmake"]));
+        }
+        assert_eq!(
+            outputs[0]["cache"]["ignore_run_exports"],
+            json!({ "from_package": ["cmake"] })
+        );
+        assert_eq!(
+            outputs[1]["cache"]["ignore_run_exports"],
+            json!({ "by_name": ["libzlib"], "from_package": ["cmake"] })
+        );
+
+        // without a cache
+        let response = post("/render", json!({ "recipe": RECIPE }));
+        assert_eq!(response.body["outputs"][0]["cache"], Value::Null);
+    }
+
+    #[test]
+    fn test_errors() {
+        let response = post("/lint", json!({ "recipe": RECIPE }));
+        assert_eq!(response.body, json!({ "diagnostics": [] }));
+
+        let invalid = RECIPE.replace("requirements", "requirement");
+        let response = post("/lint", json!({ "recipe": invalid }));
+        assert_eq!(response.body["diagnostics"].as_array().unwrap().len(), 1);
+        let response = post("/render", json!({ "recipe": invalid }));
+        assert_eq!(response.status, 422);
+        assert_eq!(response.body["diagnostics"].as_array().unwrap().len(), 1);
+
+        // broken links are reported as warnings
+        let broken_link = format!("{RECIPE}about:\n  homepage: htps://example.com\n");
+        let response = post("/lint", json!({ "recipe": broken_link }));
+        assert_eq!(response.body["diagnostics"][0]["severity"], 2);
+
+        assert_eq!(post("/render", json!({})).status, 400);
+        assert_eq!(post("/unknown", json!({})).status, 404);
+        let get = Request {
+            method: "GET".to_string(),
+            path: "/render".to_string(),
+            host: Some("127.0.0.1:8080".to_string()),
+            body: Vec::new(),
+        };
+        assert_eq!(handle(&get, &config()).status, 405);
+    }
+
+    #[test]
+    fn test_include() {
+        let dir = tempfile::tempdir().unwrap();
+        fs_err::write(
+            dir.path().join("about.yaml"),
+            "about:\n  summary: A shared summary\n",
+        )
+        .unwrap();
+        let recipe = format!("include:\n  - about.yaml\n{RECIPE}");
+        let recipe_path = dir.path().join("recipe.yaml");
+
+        let response = post(
+            "/render",
+            json!({ "recipe": recipe, "recipe_path": recipe_path }),
+        );
+        assert_eq!(response.status, 200, "{}", response.body);
+        assert_eq!(
+            response.body["outputs"][0]["recipe"]["about"]["summary"],
+            "A shared summary"
+        );
+        let response = post(
+            "/lint",
+            json!({ "recipe": recipe, "recipe_path": recipe_path }),
+        );
+        assert_eq!(response.body, json!({ "diagnostics": [] }));
+
+        // without the path of the recipe, includes can not be resolved
+        let response = post("/render", json!({ "recipe": recipe }));
+        assert_eq!(response.status, 422);
+        let response = post("/lint", json!({ "recipe": recipe }));
+        assert_eq!(response.body["diagnostics"].as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_host() {
+        let config = ServeConfig {
+            allowed_hosts: vec!["build.example.com".to_string()],
+            ..ServeConfig::default()
+        };
+        for host in [
+            "localhost",
+            "LOCALHOST:8080",
+            "127.0.0.1:8080",
+            "[::1]:8080",
+            "::1",
+            "build.example.com:8080",
+        ] {
+            assert!(config.is_allowed_host(host), "{host}");
+        }
+        for host in [
+            "example.com",
+            "localhost.example.com:8080",
+            "127.0.0.1.nip.io",
+        ] {
+            assert!(!config.is_allowed_host(host), "{host}");
+        }
+
+        let mut request = Request {
+            method: "GET".to_string(),
+            path: "/health".to_string(),
+            host: Some("rebind.example.com:8080".to_string()),
+            body: Vec::new(),
+        };
+        assert_eq!(handle(&request, &config).status, 403);
+        request.host = None;
+        assert_eq!(handle(&request, &config).status, 400);
+        request.host = Some("localhost:8080".to_string());
+        assert_eq!(handle(&request, &config).status, 200);
+    }
+
+    #[test]
+    fn test_no_env_access() {
+        std::env::set_var("RATTLER_BUILD_SERVE_SECRET", "secret");
+        let recipe = RECIPE.replace(
+            "version: 1.0",
+            "version: ${{ env.get(\"RATTLER_BUILD_SERVE_SECRET\") }}",
+        );
+        let response = post("/render", json!({ "recipe": recipe }));
+        assert_ne!(response.status, 200, "{}", response.body);
+        assert!(!response.body.to_string().contains("\"secret\""));
+    }
+}
@@ -0,0 +1,507 @@
+//! Signing of packages for conda content trust.
+//!
+//! conda content trust signs the repodata record of every package with an
+//! ed25519 key. The signatures are stored next to the records in the
+//! `signatures` section of `repodata.json`:
+//!
+//! ```json
+//! "signatures": {
+//!   "foo-1.0-h1234_0.conda": {
+//!     "<public key (hex)>": { "signature": "<signature (hex)>" }
+//!   }
+//! }
+//! ```
+//!
+//! The signed data is the canonical JSON serialization of the record (sorted
+//! keys, indented by two spaces and ASCII only), which is what conda and mamba
+//! use to verify the signatures.
+//!
+//! The signatures of a local channel are also stored in `signatures.json` in
+//! every subdirectory, so that they can be restored after the subdirectory is
+//! reindexed (which rewrites `repodata.json`), see [`restore_signatures`].
+use std::{
+    fmt::Write as _,
+    io::Write as _,
+    path::{Path, PathBuf},
+};
+
+use ed25519_dalek::{
+    pkcs8::{DecodePrivateKey, EncodePrivateKey, LineEnding},
+    Signature, Signer, Verifier, VerifyingKey,
+};
+use fs_err as fs;
+use serde_json::{Map, Value};
+
+/// The sections of `repodata.json` that contain package records
+const PACKAGE_SECTIONS: [&str; 2] = ["packages", "packages.conda"];
+
+/// The file in every subdirectory of a local channel that keeps the signatures
+/// across reindexing
+const SIGNATURES_FILE: &str = "signatures.json";
+
+/// Errors that can occur while signing packages
+#[derive(Debug, thiserror::Error)]
+pub enum SigningError {
+    /// An IO error occurred
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    /// The key file could not be parsed
+    #[error("failed to read signing key from {0}: expected an ed25519 private key in PKCS#8 PEM format or as 64 hex characters")]
+    InvalidKey(PathBuf),
+
+    /// The key could not be encoded
+    #[error("failed to encode signing key: {0}")]
+    EncodeKey(String),
+
+    /// The repodata could not be parsed or serialized
+    #[error("failed to parse {0}: {1}")]
+    Repodata(PathBuf, serde_json::Error),
+}
+
+/// An ed25519 key to sign packages with
+pub struct SigningKey(ed25519_dalek::SigningKey);
+
+impl std::fmt::Debug for SigningKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SigningKey")
+            .field("public_key", &self.public_key())
+            .finish_non_exhaustive()
+    }
+}
+
+impl SigningKey {
+    /// Generate a new random signing key
+    pub fn generate() -> Self {
+        SigningKey(ed25519_dalek::SigningKey::generate(&mut rand::rngs::OsRng))
+    }
+
+    /// Read a signing key from a file. The key can either be a PKCS#8 PEM file
+    /// (e.g. created with `openssl genpkey -algorithm ed25519`) or the hex
+    /// encoded private key as used by `conda-content-trust`.
+    pub fn from_file(path: &Path) -> Result<Self, SigningError> {
+        let content = fs::read_to_string(path)?;
+        let content = content.trim();
+
+        if let Ok(key) = ed25519_dalek::SigningKey::from_pkcs8_pem(content) {
+            return Ok(SigningKey(key));
+        }
+
+        let bytes = hex::decode(content).map_err(|_| SigningError::InvalidKey(path.into()))?;
+        let bytes: [u8; 32] = bytes
+            .try_into()
+            .map_err(|_| SigningError::InvalidKey(path.into()))?;
+        Ok(SigningKey(ed25519_dalek::SigningKey::from_bytes(&bytes)))
+    }
+
+    /// Write the key to a new file in PKCS#8 PEM format. On unix, the file is
+    /// only readable by the owner (mode `0600`).
+    pub fn write_pem(&self, path: &Path) -> Result<(), SigningError> {
+        let pem = self
+            .0
+            .to_pkcs8_pem(LineEnding::LF)
+            .map_err(|e| SigningError::EncodeKey(e.to_string()))?;
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use fs_err::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        options.open(path)?.write_all(pem.as_bytes())?;
+        Ok(())
+    }
+
+    /// The hex encoded public key, which clients use to verify the signatures
+    pub fn public_key(&self) -> String {
+        hex::encode(self.0.verifying_key().as_bytes())
+    }
+
+    /// Sign a package record and return the hex encoded signature
+    pub fn sign_record(&self, record: &Value) -> String {
+        hex::encode(self.0.sign(canonical_json(record).as_bytes()).to_bytes())
+    }
+}
+
+/// Verify the hex encoded signature of a package record with a hex encoded
+/// public key
+pub fn verify_record(record: &Value, public_key: &str, signature: &str) -> bool {
+    let Some(public_key) = hex::decode(public_key)
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .and_then(|bytes| VerifyingKey::from_bytes(&bytes).ok())
+    else {
+        return false;
+    };
+    let Some(signature) = hex::decode(signature)
+        .ok()
+        .and_then(|bytes| <[u8; 64]>::try_from(bytes).ok())
+        .map(|bytes| Signature::from_bytes(&bytes))
+    else {
+        return false;
+    };
+
+    public_key
+        .verify(canonical_json(record).as_bytes(), &signature)
+        .is_ok()
+}
+
+/// Serialize a JSON value the way `conda-content-trust` does before signing
+/// (`json.dumps(value, indent=2, sort_keys=True, separators=(",", ": "))`)
+pub fn canonical_json(value: &Value) -> String {
+    let mut out = String::new();
+    write_canonical(value, 0, &mut out);
+    out
+}
+
+fn write_canonical(value: &Value, indent: usize, out: &mut String) {
+    match value {
+        Value::Null | Value::Bool(_) | Value::Number(_) => out.push_str(&value.to_string()),
+        Value::String(s) => write_ascii_string(s, out),
+        Value::Array(items) if items.is_empty() => out.push_str("[]"),
+        Value::Array(items) => {
+            out.push_str("[\n");
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(",\n");
+                }
+                out.push_str(&" ".repeat(indent + 2));
+                write_canonical(item, indent + 2, out);
+            }
+            out.push('\n');
+            out.push_str(&" ".repeat(indent));
+            out.push(']');
+        }
+        Value::Object(map) if map.is_empty() => out.push_str("{}"),
+        Value::Object(map) => {
+            let mut entries = map.iter().collect::<Vec<_>>();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+            out.push_str("{\n");
+            for (i, (key, value)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push_str(",\n");
+                }
+                out.push_str(&" ".repeat(indent + 2));
+                write_ascii_string(key, out);
+                out.push_str(": ");
+                write_canonical(value, indent + 2, out);
+            }
+            out.push('\n');
+            out.push_str(&" ".repeat(indent));
+            out.push('}');
+        }
+    }
+}
+
+/// Write a JSON string with all non-ASCII characters escaped (like Python's
+/// `ensure_ascii`)
+fn write_ascii_string(s: &str, out: &mut String) {
+    let escaped = Value::String(s.to_string()).to_string();
+    for c in escaped.chars() {
+        if c.is_ascii() {
+            out.push(c);
+        } else {
+            let mut buf = [0u16; 2];
+            for unit in c.encode_utf16(&mut buf) {
+                write!(out, "\\u{unit:04x}").expect("writing to a string cannot fail");
+            }
+        }
+    }
+}
+
+/// Add the signature of `public_key` for the package `filename` to a parsed
+/// `repodata.json`
+fn insert_signature(repodata: &mut Value, filename: &str, public_key: &str, signature: Value) {
+    let Some(root) = repodata.as_object_mut() else {
+        return;
+    };
+    let signature_section = root
+        .entry("signatures")
+        .or_insert_with(|| Value::Object(Map::new()));
+    if !signature_section.is_object() {
+        *signature_section = Value::Object(Map::new());
+    }
+    let entry = signature_section
+        .as_object_mut()
+        .expect("the signatures section is an object")
+        .entry(filename)
+        .or_insert_with(|| Value::Object(Map::new()));
+    if let Value::Object(entry) = entry {
+        entry.insert(public_key.to_string(), signature);
+    }
+}
+
+/// Sign the record of the package `filename` and add the signature to a
+/// parsed `repodata.json`
+pub fn add_signature(repodata: &mut Value, filename: &str, record: &Value, key: &SigningKey) {
+    let signature = serde_json::json!({ "signature": key.sign_record(record) });
+    insert_signature(repodata, filename, &key.public_key(), signature);
+}
+
+/// Add the signatures of all package records to a parsed `repodata.json`.
+/// Returns the number of signed packages.
+pub fn sign_repodata(repodata: &mut Value, key: &SigningKey) -> usize {
+    let mut records = Vec::new();
+    for section in PACKAGE_SECTIONS {
+        if let Some(Value::Object(section)) = repodata.get(section) {
+            records.extend(
+                section
+                    .iter()
+                    .map(|(filename, record)| (filename.clone(), record.clone())),
+            );
+        }
+    }
+
+    for (filename, record) in &records {
+        add_signature(repodata, filename, record, key);
+    }
+    records.len()
+}
+
+/// The record of the package `filename` in a parsed `repodata.json`
+fn find_record<'a>(repodata: &'a Value, filename: &str) -> Option<&'a Value> {
+    PACKAGE_SECTIONS
+        .iter()
+        .find_map(|section| repodata.get(section)?.get(filename))
+}
+
+/// Add the signatures from `signatures.json` to the `repodata.json` of a
+/// subdirectory of a local channel, e.g. after it was reindexed. Signatures of
+/// packages that were removed or rebuilt (and no longer verify) are dropped.
+/// Returns the number of restored signatures.
+pub fn restore_signatures(subdir: &Path) -> Result<usize, SigningError> {
+    let signatures_path = subdir.join(SIGNATURES_FILE);
+    let repodata_path = subdir.join("repodata.json");
+    if !signatures_path.is_file() || !repodata_path.is_file() {
+        return Ok(0);
+    }
+
+    let signatures: Map<String, Value> =
+        serde_json::from_str(&fs::read_to_string(&signatures_path)?)
+            .map_err(|e| SigningError::Repodata(signatures_path.clone(), e))?;
+    let mut repodata: Value = serde_json::from_str(&fs::read_to_string(&repodata_path)?)
+        .map_err(|e| SigningError::Repodata(repodata_path.clone(), e))?;
+
+    let mut restored = Vec::new();
+    for (filename, keys) in &signatures {
+        let Some(record) = find_record(&repodata, filename) else {
+            continue;
+        };
+        for (public_key, signature) in keys.as_object().into_iter().flatten() {
+            let verified = signature
+                .get("signature")
+                .and_then(Value::as_str)
+                .is_some_and(|signature| verify_record(record, public_key, signature));
+            if verified {
+                restored.push((filename, public_key, signature.clone()));
+            }
+        }
+    }
+
+    let count = restored.len();
+    for (filename, public_key, signature) in restored {
+        insert_signature(&mut repodata, filename, public_key, signature);
+    }
+    let content = serde_json::to_string_pretty(&repodata)
+        .map_err(|e| SigningError::Repodata(repodata_path.clone(), e))?;
+    fs::write(&repodata_path, content)?;
+    Ok(count)
+}
+
+/// Write a signed `repodata.json` and the `signatures.json` next to it
+fn write_signed_repodata(repodata_path: &Path, repodata: &Value) -> Result<(), SigningError> {
+    let content = serde_json::to_string_pretty(repodata)
+        .map_err(|e| SigningError::Repodata(repodata_path.to_path_buf(), e))?;
+    fs::write(repodata_path, content)?;
+
+    let signatures_path = repodata_path.with_file_name(SIGNATURES_FILE);
+    let content = serde_json::to_string_pretty(&repodata["signatures"])
+        .map_err(|e| SigningError::Repodata(signatures_path.clone(), e))?;
+    fs::write(&signatures_path, content)?;
+    Ok(())
+}
+
+/// Sign all packages of a local channel by adding the signatures to the
+/// `repodata.json` file (and `signatures.json`) of every subdirectory.
+/// Returns the number of signed packages.
+pub fn sign_channel(channel_dir: &Path, key: &SigningKey) -> Result<usize, SigningError> {
+    let mut count = 0;
+    for entry in fs::read_dir(channel_dir)? {
+        let repodata_path = entry?.path().join("repodata.json");
+        if !repodata_path.is_file() {
+            continue;
+        }
+
+        let mut repodata: Value = serde_json::from_str(&fs::read_to_string(&repodata_path)?)
+            .map_err(|e| SigningError::Repodata(repodata_path.clone(), e))?;
+        let signed = sign_repodata(&mut repodata, key);
+        write_signed_repodata(&repodata_path, &repodata)?;
+
+        tracing::info!(
+            "Signed {} package(s) in {}",
+            signed,
+            repodata_path.display()
+        );
+        count += signed;
+    }
+    Ok(count)
+}
+
+/// Sign the given packages in the `repodata.json` file (and
+/// `signatures.json`) of a subdirectory of a local channel. Packages that are
+/// not in the repodata are skipped. Returns the number of signed packages.
+pub fn sign_packages<'a>(
+    subdir: &Path,
+    filenames: impl IntoIterator<Item = &'a str>,
+    key: &SigningKey,
+) -> Result<usize, SigningError> {
+    let repodata_path = subdir.join("repodata.json");
+    let mut repodata: Value = serde_json::from_str(&fs::read_to_string(&repodata_path)?)
+        .map_err(|e| SigningError::Repodata(repodata_path.clone(), e))?;
+
+    let mut count = 0;
+    for filename in filenames {
+        if let Some(record) = find_record(&repodata, filename).cloned() {
+            add_signature(&mut repodata, filename, &record, key);
+            count += 1;
+        }
+    }
+    write_signed_repodata(&repodata_path, &repodata)?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn canonical_serialization() {
+        let value = json!({
+            "name": "föö",
+            "depends": [],
+            "build_number": 0,
+            "constrains": ["a", "b"],
+            "noarch": null,
+            "info": {"z": true, "a": {}}
+        });
+        assert_eq!(
+            canonical_json(&value),
+            r#"{
+  "build_number": 0,
+  "constrains": [
+    "a",
+    "b"
+  ],
+  "depends": [],
+  "info": {
+    "a": {},
+    "z": true
+  },
+  "name": "f\u00f6\u00f6",
+  "noarch": null
+}"#
+        );
+    }
+
+    #[test]
+    fn sign_and_verify_channel() {
+        let dir = tempfile::tempdir().unwrap();
+        let key_path = dir.path().join("key.pem");
+        let key = SigningKey::generate();
+        key.write_pem(&key_path).unwrap();
+
+        let key = SigningKey::from_file(&key_path).unwrap();
+        assert_eq!(key.public_key().len(), 64);
+
+        let record = json!({"name": "foo", "version": "1.0", "depends": ["bar"]});
+        fs::create_dir(dir.path().join("noarch")).unwrap();
+        fs::write(
+            dir.path().join("noarch/repodata.json"),
+            json!({
+                "info": {"subdir": "noarch"},
+                "packages": {},
+                "packages.conda": {"foo-1.0-h1234_0.conda": record.clone()}
+            })
+            .to_string(),
+        )
+        .unwrap();
+
+        assert_eq!(sign_channel(dir.path(), &key).unwrap(), 1);
+
+        let repodata: Value = serde_json::from_str(
+            &fs::read_to_string(dir.path().join("noarch/repodata.json")).unwrap(),
+        )
+        .unwrap();
+        let signature = repodata["signatures"]["foo-1.0-h1234_0.conda"][key.public_key()]
+            ["signature"]
+            .as_str()
+            .unwrap();
+        assert!(verify_record(&record, &key.public_key(), signature));
+        assert!(!verify_record(
+            &json!({"name": "foo", "version": "1.1", "depends": ["bar"]}),
+            &key.public_key(),
+            signature
+        ));
+
+        // the key file is only readable by the owner, and is never overwritten
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&key_path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        assert!(key.write_pem(&key_path).is_err());
+
+        // reindexing rewrites the repodata, the signatures are restored from
+        // `signatures.json` (only for records that still verify)
+        let rebuilt = json!({"name": "bar", "version": "2.0", "depends": []});
+        fs::write(
+            dir.path().join("noarch/repodata.json"),
+            json!({
+                "info": {"subdir": "noarch"},
+                "packages": {},
+                "packages.conda": {
+                    "foo-1.0-h1234_0.conda": record.clone(),
+                    "bar-2.0-h1234_0.conda": rebuilt,
+                }
+            })
+            .to_string(),
+        )
+        .unwrap();
+        let mut signatures: Value = serde_json::from_str(
+            &fs::read_to_string(dir.path().join("noarch/signatures.json")).unwrap(),
+        )
+        .unwrap();
+        signatures["bar-2.0-h1234_0.conda"] = signatures["foo-1.0-h1234_0.conda"].clone();
+        fs::write(
+            dir.path().join("noarch/signatures.json"),
+            signatures.to_string(),
+        )
+        .unwrap();
+        assert_eq!(restore_signatures(&dir.path().join("noarch")).unwrap(), 1);
+        let repodata: Value = serde_json::from_str(
+            &fs::read_to_string(dir.path().join("noarch/repodata.json")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            repodata["signatures"]["foo-1.0-h1234_0.conda"][key.public_key()]["signature"],
+            signature
+        );
+        assert!(repodata["signatures"]
+            .get("bar-2.0-h1234_0.conda")
+            .is_none());
+
+        // the hex format of conda-content-trust is accepted as well
+        let hex_path = dir.path().join("key.hex");
+        fs::write(&hex_path, hex::encode(key.0.to_bytes())).unwrap();
+        assert_eq!(
+            SigningKey::from_file(&hex_path).unwrap().public_key(),
+            key.public_key()
+        );
+        fs::write(&hex_path, "not a key").unwrap();
+        assert!(SigningKey::from_file(&hex_path).is_err());
+    }
+}
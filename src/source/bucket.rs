@@ -0,0 +1,346 @@
+//! Fetching of `s3://` and `gs://` source urls.
+//!
+//! Objects in Google Cloud Storage buckets are downloaded from the
+//! `storage.googleapis.com` endpoint with the authenticated download client,
+//! so a bearer token for `storage.googleapis.com` in the keychain / auth file
+//! is used for private buckets.
+//!
+//! Requests to S3 buckets are signed (AWS signature version 4) with the
+//! standard `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and
+//! `AWS_SESSION_TOKEN` environment variables. The region is read from
+//! `AWS_REGION` or `AWS_DEFAULT_REGION`, and S3 compatible servers (e.g.
+//! MinIO) can be used by setting `AWS_ENDPOINT_URL`.
+
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use super::SourceError;
+use crate::tool_configuration;
+
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+const DEFAULT_S3_REGION: &str = "us-east-1";
+
+/// Returns true if the url points to an object in an S3 or GCS bucket
+pub(crate) fn is_bucket_url(url: &Url) -> bool {
+    matches!(url.scheme(), "s3" | "gs")
+}
+
+/// Split a bucket url (`s3://bucket/key`) into the bucket and the key
+fn bucket_and_key(url: &Url) -> Result<(&str, &str), SourceError> {
+    let bucket = url
+        .host_str()
+        .filter(|bucket| !bucket.is_empty())
+        .ok_or_else(|| SourceError::UnknownError(format!("No bucket name in url: {url}")))?;
+    let key = url.path().trim_start_matches('/');
+    if key.is_empty() {
+        return Err(SourceError::UrlNotFile(url.clone()));
+    }
+    Ok((bucket, key))
+}
+
+/// The settings that are used to access S3 buckets
+#[derive(Debug, Clone, Default)]
+struct S3Config {
+    region: String,
+    endpoint: Option<Url>,
+    credentials: Option<S3Credentials>,
+}
+
+#[derive(Debug, Clone)]
+struct S3Credentials {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+}
+
+impl S3Config {
+    fn from_env() -> Self {
+        let var = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+
+        let credentials = match (var("AWS_ACCESS_KEY_ID"), var("AWS_SECRET_ACCESS_KEY")) {
+            (Some(access_key_id), Some(secret_access_key)) => Some(S3Credentials {
+                access_key_id,
+                secret_access_key,
+                session_token: var("AWS_SESSION_TOKEN"),
+            }),
+            _ => None,
+        };
+
+        Self {
+            region: var("AWS_REGION")
+                .or_else(|| var("AWS_DEFAULT_REGION"))
+                .unwrap_or_else(|| DEFAULT_S3_REGION.to_string()),
+            endpoint: var("AWS_ENDPOINT_URL").and_then(|endpoint| Url::parse(&endpoint).ok()),
+            credentials,
+        }
+    }
+
+    /// The https url of the object. A custom endpoint is addressed path-style
+    /// (`<endpoint>/<bucket>/<key>`), AWS virtual-hosted-style.
+    fn object_url(&self, bucket: &str, key: &str) -> Result<Url, SourceError> {
+        let url = match &self.endpoint {
+            Some(endpoint) => format!(
+                "{}/{}/{}",
+                endpoint.as_str().trim_end_matches('/'),
+                bucket,
+                key
+            ),
+            None => format!(
+                "https://{}.s3.{}.amazonaws.com/{}",
+                bucket, self.region, key
+            ),
+        };
+        Url::parse(&url).map_err(|e| SourceError::UnknownError(format!("Invalid S3 url: {e}")))
+    }
+}
+
+/// The https url of an object in a GCS bucket
+fn gcs_object_url(bucket: &str, key: &str) -> Result<Url, SourceError> {
+    Url::parse(&format!("https://storage.googleapis.com/{bucket}/{key}"))
+        .map_err(|e| SourceError::UnknownError(format!("Invalid GCS url: {e}")))
+}
+
+/// HMAC-SHA256 (RFC 2104) of `data` with `key`
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Encode a path for the canonical request: every byte except the unreserved
+/// characters and `/` is percent encoded
+fn uri_encode_path(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Decode the percent encoded path of a url
+fn decode_path(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            if let Some(byte) = path
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Compute the headers of an AWS signature version 4 signed `GET` request
+fn sign_s3_get(
+    url: &Url,
+    region: &str,
+    credentials: &S3Credentials,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Vec<(&'static str, String)> {
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date_stamp = now.format("%Y%m%d").to_string();
+
+    let host = match url.port() {
+        Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+        None => url.host_str().unwrap_or_default().to_string(),
+    };
+
+    let mut headers = vec![
+        ("host", host),
+        ("x-amz-content-sha256", UNSIGNED_PAYLOAD.to_string()),
+        ("x-amz-date", amz_date.clone()),
+    ];
+    if let Some(token) = &credentials.session_token {
+        headers.push(("x-amz-security-token", token.clone()));
+    }
+
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+        .collect();
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(";");
+
+    let canonical_request = format!(
+        "GET\n{}\n\n{}\n{}\n{}",
+        uri_encode_path(&decode_path(url.path())),
+        canonical_headers,
+        signed_headers,
+        UNSIGNED_PAYLOAD
+    );
+
+    let scope = format!("{}/{}/s3/aws4_request", date_stamp, region);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{:x}",
+        amz_date,
+        scope,
+        Sha256::digest(canonical_request.as_bytes())
+    );
+
+    let key = hmac_sha256(
+        format!("AWS4{}", credentials.secret_access_key).as_bytes(),
+        date_stamp.as_bytes(),
+    );
+    let key = hmac_sha256(&key, region.as_bytes());
+    let key = hmac_sha256(&key, b"s3");
+    let key = hmac_sha256(&key, b"aws4_request");
+    let signature = hex::encode(hmac_sha256(&key, string_to_sign.as_bytes()));
+
+    // the host header is set by the http client
+    headers.remove(0);
+    headers.push((
+        "authorization",
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            credentials.access_key_id, scope, signed_headers, signature
+        ),
+    ));
+    headers
+}
+
+/// Send a `GET` request for an object in an S3 or GCS bucket
+pub(crate) async fn get_object(
+    url: &Url,
+    client: &reqwest::Client,
+    tool_configuration: &tool_configuration::Configuration,
+) -> Result<reqwest::Response, SourceError> {
+    let (bucket, key) = bucket_and_key(url)?;
+
+    match url.scheme() {
+        "s3" => {
+            let config = S3Config::from_env();
+            let object_url = config.object_url(bucket, key)?;
+            let mut request = client.get(object_url.clone());
+            if let Some(credentials) = &config.credentials {
+                for (name, value) in
+                    sign_s3_get(&object_url, &config.region, credentials, chrono::Utc::now())
+                {
+                    request = request.header(name, value);
+                }
+            } else {
+                tracing::debug!("No AWS credentials found, fetching {} anonymously", url);
+            }
+            Ok(request.send().await?)
+        }
+        "gs" => {
+            let object_url = gcs_object_url(bucket, key)?;
+            tool_configuration
+                .client
+                .get(object_url)
+                .send()
+                .await
+                .map_err(|e| match e {
+                    reqwest_middleware::Error::Reqwest(e) => SourceError::Url(e),
+                    reqwest_middleware::Error::Middleware(e) => {
+                        SourceError::UnknownError(e.to_string())
+                    }
+                })
+        }
+        scheme => Err(SourceError::UnknownError(format!(
+            "Unsupported bucket url scheme: {scheme}"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn test_bucket_urls() {
+        let url = Url::parse("s3://my-bucket/releases/foo-1.0.tar.gz").unwrap();
+        assert!(is_bucket_url(&url));
+        let (bucket, key) = bucket_and_key(&url).unwrap();
+        assert_eq!((bucket, key), ("my-bucket", "releases/foo-1.0.tar.gz"));
+
+        let config = S3Config {
+            region: "eu-central-1".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            config.object_url(bucket, key).unwrap().as_str(),
+            "https://my-bucket.s3.eu-central-1.amazonaws.com/releases/foo-1.0.tar.gz"
+        );
+
+        let config = S3Config {
+            endpoint: Some(Url::parse("http://localhost:9000/").unwrap()),
+            ..config
+        };
+        assert_eq!(
+            config.object_url(bucket, key).unwrap().as_str(),
+            "http://localhost:9000/my-bucket/releases/foo-1.0.tar.gz"
+        );
+
+        assert_eq!(
+            gcs_object_url("my-bucket", "foo.tar.gz").unwrap().as_str(),
+            "https://storage.googleapis.com/my-bucket/foo.tar.gz"
+        );
+
+        assert!(bucket_and_key(&Url::parse("gs://my-bucket/").unwrap()).is_err());
+        assert!(!is_bucket_url(
+            &Url::parse("https://example.com/foo.tar.gz").unwrap()
+        ));
+    }
+
+    #[test]
+    fn test_hmac_sha256() {
+        // RFC 4231, test case 2
+        assert_eq!(
+            hex::encode(hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn test_sign_s3_get() {
+        let url =
+            Url::parse("https://my-bucket.s3.us-east-1.amazonaws.com/foo%20bar.tar.gz").unwrap();
+        let credentials = S3Credentials {
+            access_key_id: "AKIDEXAMPLE".to_string(),
+            secret_access_key: "secret".to_string(),
+            session_token: Some("token".to_string()),
+        };
+        let now = chrono::Utc.with_ymd_and_hms(2025, 1, 2, 3, 4, 5).unwrap();
+        let headers = sign_s3_get(&url, "us-east-1", &credentials, now);
+
+        let header = |name: &str| {
+            headers
+                .iter()
+                .find(|(n, _)| *n == name)
+                .map(|(_, v)| v.as_str())
+                .unwrap()
+        };
+        assert_eq!(header("x-amz-date"), "20250102T030405Z");
+        assert_eq!(header("x-amz-security-token"), "token");
+        let authorization = header("authorization");
+        assert!(authorization.starts_with(
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20250102/us-east-1/s3/aws4_request, SignedHeaders=host;x-amz-content-sha256;x-amz-date;x-amz-security-token, Signature="
+        ));
+        assert!(!headers.iter().any(|(name, _)| *name == "host"));
+        assert_eq!(decode_path("/foo%20bar.tar.gz"), "/foo bar.tar.gz");
+        assert_eq!(
+            uri_encode_path("/foo bar+1.tar.gz"),
+            "/foo%20bar%2B1.tar.gz"
+        );
+    }
+}
@@ -0,0 +1,129 @@
+//! Inspect the source cache (`<output_dir>/src_cache`). The cache contains the
+//! downloaded archives, their extracted contents and the checkouts of
+//! repositories, so that sources do not need to be fetched again.
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use fs_err as fs;
+use walkdir::WalkDir;
+
+/// The source cache of an output directory
+#[derive(Debug, Clone)]
+pub struct SourceCache {
+    path: PathBuf,
+}
+
+/// An entry (file or directory) in the source cache
+#[derive(Debug, Clone)]
+pub struct SourceCacheEntry {
+    /// The name of the entry (the cache key)
+    pub name: String,
+    /// The path of the entry
+    pub path: PathBuf,
+    /// The size of the entry in bytes (for directories, the size of all files
+    /// in the directory)
+    pub size: u64,
+    /// The time the entry was last modified
+    pub modified: Option<SystemTime>,
+    /// Whether the entry is a directory (an extracted archive or a checkout)
+    pub is_dir: bool,
+}
+
+impl SourceCacheEntry {
+    /// The time since the entry was last modified
+    pub fn age(&self) -> Option<Duration> {
+        self.modified?.elapsed().ok()
+    }
+}
+
+impl SourceCache {
+    /// The source cache of the given output directory
+    pub fn new(output_dir: &Path) -> Self {
+        Self {
+            path: output_dir.join("src_cache"),
+        }
+    }
+
+    /// The path of the source cache
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// All entries of the cache, sorted by name. Returns no entries if the cache
+    /// does not exist yet.
+    pub fn entries(&self) -> Result<Vec<SourceCacheEntry>, std::io::Error> {
+        if !self.path.is_dir() {
+            return Ok(Vec::new());
+        }
+
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(&self.path)? {
+            let entry = entry?;
+            let path = entry.path();
+            let metadata = fs::symlink_metadata(&path)?;
+            let size = if metadata.is_dir() {
+                directory_size(&path)
+            } else {
+                metadata.len()
+            };
+            entries.push(SourceCacheEntry {
+                name: entry.file_name().to_string_lossy().to_string(),
+                path,
+                size,
+                modified: metadata.modified().ok(),
+                is_dir: metadata.is_dir(),
+            });
+        }
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(entries)
+    }
+
+    /// The total size of the cache in bytes
+    pub fn size(&self) -> Result<u64, std::io::Error> {
+        Ok(self.entries()?.iter().map(|entry| entry.size).sum())
+    }
+}
+
+/// The size of all files in a directory (symlinks are not followed)
+fn directory_size(path: &Path) -> u64 {
+    WalkDir::new(path)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter_map(|entry| entry.metadata().ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_source_cache_entries() {
+        let output_dir = tempfile::tempdir().unwrap();
+        let cache = SourceCache::new(output_dir.path());
+        assert!(cache.entries().unwrap().is_empty());
+
+        fs::create_dir_all(cache.path().join("foo-1.0_abcdef12/src")).unwrap();
+        fs::write(
+            cache.path().join("foo-1.0_abcdef12/src/main.c"),
+            "int main;",
+        )
+        .unwrap();
+        fs::write(cache.path().join("foo-1.0_abcdef12.tar.gz"), "archive").unwrap();
+
+        let entries = cache.entries().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].name, "foo-1.0_abcdef12");
+        assert!(entries[0].is_dir);
+        assert_eq!(entries[0].size, 9);
+        assert_eq!(entries[1].name, "foo-1.0_abcdef12.tar.gz");
+        assert!(!entries[1].is_dir);
+        assert_eq!(entries[1].size, 7);
+        assert!(entries[1].age().is_some());
+        assert_eq!(cache.size().unwrap(), 16);
+    }
+}
@@ -5,6 +5,7 @@ use std::path::Path;
 use rattler_digest::{compute_file_digest, serde::SerializableHash, Md5, Md5Hash, Sha256Hash};
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
+use sha2::Digest as _;
 
 use crate::recipe::parser::{PathSource, UrlSource};
 
@@ -45,6 +46,14 @@ impl Checksum {
         }
     }
 
+    /// Create an incremental hasher of the same kind as this checksum.
+    pub(crate) fn hasher(&self) -> ChecksumHasher {
+        match self {
+            Checksum::Sha256(_) => ChecksumHasher::Sha256(sha2::Sha256::new()),
+            Checksum::Md5(_) => ChecksumHasher::Md5(Md5::new()),
+        }
+    }
+
     /// Validate the checksum of a file.
     pub fn validate(&self, path: &Path) -> bool {
         match self {
@@ -84,3 +93,70 @@ impl Checksum {
         }
     }
 }
+
+/// Computes a checksum incrementally, e.g. while a file is being downloaded.
+pub(crate) enum ChecksumHasher {
+    Sha256(sha2::Sha256),
+    Md5(Md5),
+}
+
+impl ChecksumHasher {
+    /// Feed more data into the hasher.
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            ChecksumHasher::Sha256(hasher) => hasher.update(data),
+            ChecksumHasher::Md5(hasher) => hasher.update(data),
+        }
+    }
+
+    /// Finish hashing and compare the result with the expected checksum.
+    pub fn matches(self, expected: &Checksum) -> bool {
+        let (computed, expected) = match (self, expected) {
+            (ChecksumHasher::Sha256(hasher), Checksum::Sha256(value)) => {
+                (hex::encode(hasher.finalize()), hex::encode(value))
+            }
+            (ChecksumHasher::Md5(hasher), Checksum::Md5(value)) => {
+                (hex::encode(hasher.finalize()), hex::encode(value))
+            }
+            _ => return false,
+        };
+
+        if computed != expected {
+            tracing::error!(
+                "Checksum of downloaded file not matching!\nDownloaded = {}, should be {}",
+                computed,
+                expected
+            );
+            false
+        } else {
+            tracing::info!("Validated checksum of the downloaded file!");
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn incremental_hasher_matches_file_digest() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.txt");
+        fs_err::write(&path, b"hello streaming world").unwrap();
+
+        let sha256 = Checksum::Sha256(compute_file_digest::<sha2::Sha256>(&path).unwrap());
+        let md5 = Checksum::Md5(compute_file_digest::<Md5>(&path).unwrap());
+
+        for checksum in [sha256, md5] {
+            let mut hasher = checksum.hasher();
+            hasher.update(b"hello ");
+            hasher.update(b"streaming world");
+            assert!(hasher.matches(&checksum));
+
+            let mut hasher = checksum.hasher();
+            hasher.update(b"something else");
+            assert!(!hasher.matches(&checksum));
+        }
+    }
+}
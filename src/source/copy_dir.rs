@@ -10,7 +10,7 @@ use globset::Glob;
 use ignore::WalkBuilder;
 use rayon::iter::{ParallelBridge, ParallelIterator};
 
-use crate::recipe::parser::GlobVec;
+use crate::{recipe::parser::GlobVec, utils::make_writable};
 
 use super::SourceError;
 
@@ -119,6 +119,7 @@ pub(crate) struct CopyDir<'a> {
     use_gitignore: bool,
     use_git_global: bool,
     hidden: bool,
+    writable: bool,
     copy_options: CopyOptions,
 }
 
@@ -134,6 +135,8 @@ impl<'a> CopyDir<'a> {
             use_git_global: false,
             // include hidden files by default
             hidden: false,
+            // keep the permissions of the copied files by default
+            writable: false,
             copy_options: CopyOptions::default(),
         }
     }
@@ -160,6 +163,13 @@ impl<'a> CopyDir<'a> {
         self
     }
 
+    /// Make the copied files writable for the user. Use this when copying from
+    /// the recipe directory, which may be mounted read-only.
+    pub fn make_writable(mut self, b: bool) -> Self {
+        self.writable = b;
+        self
+    }
+
     /// Setup copy options, overwrite if needed, only copy the contents as we want to specify the
     /// dir name manually
     #[allow(unused)]
@@ -291,6 +301,9 @@ impl<'a> CopyDir<'a> {
                             }
                             reflink_or_copy(path, &dest_path, &self.copy_options)
                                 .map_err(SourceError::FileSystemError)?;
+                            if self.writable {
+                                make_writable(&dest_path)?;
+                            }
                         }
 
                         Ok(Some(dest_path))
@@ -419,6 +432,22 @@ impl CopyDirResult {
         self.include_globs.values().any(|m| m.get_matched())
     }
 
+    /// The include globs that matched at least one path
+    pub fn matched_include_globs(&self) -> impl Iterator<Item = &Glob> {
+        self.include_globs
+            .iter()
+            .filter(|(_, m)| m.get_matched())
+            .map(|(glob, _)| glob)
+    }
+
+    /// The exclude globs that matched (and excluded) at least one path
+    pub fn matched_exclude_globs(&self) -> impl Iterator<Item = &Glob> {
+        self.exclude_globs
+            .iter()
+            .filter(|(_, m)| m.get_matched())
+            .map(|(glob, _)| glob)
+    }
+
     #[allow(unused)]
     pub fn exclude_globs(&self) -> &HashMap<Glob, Match> {
         &self.exclude_globs
@@ -626,4 +655,31 @@ mod test {
             std::path::PathBuf::from("/does/not/exist")
         );
     }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_copy_dir_make_writable() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let src = tmp_dir.path().join("src");
+        fs_err::create_dir_all(&src).unwrap();
+        fs_err::write(src.join("patch.diff"), "").unwrap();
+        fs_err::set_permissions(
+            src.join("patch.diff"),
+            std::fs::Permissions::from_mode(0o444),
+        )
+        .unwrap();
+
+        let dest = tmp_dir.path().join("keep");
+        super::CopyDir::new(&src, &dest).run().unwrap();
+        assert!(crate::utils::is_read_only(&dest.join("patch.diff")));
+
+        let dest = tmp_dir.path().join("writable");
+        super::CopyDir::new(&src, &dest)
+            .make_writable(true)
+            .run()
+            .unwrap();
+        assert!(!crate::utils::is_read_only(&dest.join("patch.diff")));
+    }
 }
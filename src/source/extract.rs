@@ -130,17 +130,32 @@ pub(crate) fn extract_tar(
     let buf_reader = std::io::BufReader::with_capacity(1024 * 1024, file);
     let wrapped = progress_bar.wrap_read(buf_reader);
 
-    let mut archive = tar::Archive::new(ext_to_compression(archive.file_name(), Box::new(wrapped)));
+    unpack_tar(wrapped, archive.file_name(), target_directory)?;
+    progress_bar.finish_with_message("Extracted...");
+
+    Ok(())
+}
+
+/// Extracts a tar archive from a reader to the specified target directory. The
+/// compression is derived from the file name of the archive.
+///
+/// This does not read the reader to the end: any padding after the end of the
+/// tar archive is left unread.
+pub(crate) fn unpack_tar<'a>(
+    reader: impl BufRead + 'a,
+    file_name: Option<&OsStr>,
+    target_directory: &Path,
+) -> Result<(), SourceError> {
+    fs::create_dir_all(target_directory)?;
+
+    let mut archive = tar::Archive::new(ext_to_compression(file_name, Box::new(reader)));
 
     let tmp_extraction_dir = tempfile::Builder::new().tempdir_in(target_directory)?;
     archive
         .unpack(&tmp_extraction_dir)
         .map_err(|e| SourceError::TarExtractionError(e.to_string()))?;
 
-    move_extracted_dir(tmp_extraction_dir.path(), target_directory)?;
-    progress_bar.finish_with_message("Extracted...");
-
-    Ok(())
+    move_extracted_dir(tmp_extraction_dir.path(), target_directory)
 }
 
 /// Extracts a zip archive to the specified target directory
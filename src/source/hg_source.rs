@@ -0,0 +1,85 @@
+//! This module contains the implementation of the fetching of `HgSource` struct.
+
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{
+    recipe::parser::HgSource,
+    system_tools::{SystemTools, Tool},
+};
+
+use super::{repository_cache_name, SourceError};
+
+/// Run a `hg` command and return its (trimmed) standard output.
+fn run_hg(mut command: Command, description: &str) -> Result<String, SourceError> {
+    let output = command
+        .output()
+        .map_err(|e| SourceError::HgError(format!("failed to execute `hg {description}`: {e}")))?;
+
+    if !output.status.success() {
+        return Err(SourceError::HgError(format!(
+            "`hg {description}` failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        )));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Fetch the Mercurial repository specified by the given source and place it in the cache
+/// directory. Returns the path to the repository and the resolved changeset id.
+pub fn hg_src(
+    system_tools: &SystemTools,
+    source: &HgSource,
+    cache_dir: &Path,
+) -> Result<(PathBuf, String), SourceError> {
+    let cache_name = repository_cache_name(source.url())
+        .ok_or_else(|| SourceError::HgError("failed to get name from url".to_string()))?;
+    let cache_path = cache_dir.join(cache_name);
+    let url = source.url().to_string();
+
+    if cache_path.join(".hg").is_dir() {
+        tracing::info!(
+            "Pulling repository from {} into {}",
+            url,
+            cache_path.display()
+        );
+        let mut command = system_tools.call(Tool::Hg)?;
+        command.args(["pull", &url]).current_dir(&cache_path);
+        run_hg(command, "pull")?;
+    } else {
+        if cache_path.exists() {
+            fs_err::remove_dir_all(&cache_path)?;
+        }
+        tracing::info!(
+            "Cloning repository from {} into {}",
+            url,
+            cache_path.display()
+        );
+        let mut command = system_tools.call(Tool::Hg)?;
+        command
+            .args(["clone", "--noupdate", &url])
+            .arg(cache_path.as_os_str());
+        run_hg(command, "clone")?;
+    }
+
+    // `tip` is the most recent changeset of the repository
+    let rev = source.rev().unwrap_or("tip");
+    let mut command = system_tools.call(Tool::Hg)?;
+    command
+        .args(["update", "--clean", "--rev", rev])
+        .current_dir(&cache_path);
+    run_hg(command, "update")?;
+
+    let mut command = system_tools.call(Tool::Hg)?;
+    command
+        .args(["log", "--rev", ".", "--template", "{node}"])
+        .current_dir(&cache_path);
+    let node = run_hg(command, "log")?;
+
+    tracing::info!("Checked out revision: '{}' at '{}'", rev, node);
+
+    Ok((cache_path, node))
+}
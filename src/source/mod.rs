@@ -2,29 +2,37 @@
 
 use std::{
     ffi::OsStr,
-    path::{PathBuf, StripPrefixError},
+    path::{Path, PathBuf, StripPrefixError},
 };
 
 use crate::{
+    build_profile::ProfileCategory,
     metadata::{Directories, Output},
-    recipe::parser::{GitRev, GitSource, Source},
+    recipe::parser::{GitRev, GitSource, HgSource, Source, SvnSource},
     source::{
         checksum::Checksum,
         extract::{extract_tar, extract_zip, is_tarball},
     },
     system_tools::ToolError,
     tool_configuration,
+    utils::make_writable,
 };
 
 use fs_err as fs;
+use rattler_digest::{compute_bytes_digest, Sha256};
 
 use crate::system_tools::SystemTools;
+pub(crate) mod bucket;
+pub mod cache;
 pub mod checksum;
 pub mod copy_dir;
 pub mod extract;
 pub mod git_source;
+pub mod hg_source;
 pub mod patch;
+pub mod svn_source;
 pub mod url_source;
+pub mod verify;
 
 #[allow(missing_docs)]
 #[derive(Debug, thiserror::Error)]
@@ -92,8 +100,65 @@ pub enum SourceError {
     #[error("No checksum found for url: {0}")]
     NoChecksum(String),
 
-    #[error("Failed to find git executable: {0}")]
+    #[error("Failed to run hg command: {0}")]
+    HgError(String),
+
+    #[error("Failed to run svn command: {0}")]
+    SvnError(String),
+
+    // the name is kept for compatibility, the tool can also be `hg` or `svn`
+    #[error("Failed to find executable: {0}")]
     GitNotFound(#[from] ToolError),
+
+    #[error("Download was cancelled")]
+    Cancelled,
+
+    #[error("The vendored files do not match the manifest {0}:\n{1}\nDelete the manifest to record the new files.")]
+    VendoredFilesChanged(PathBuf, String),
+}
+
+/// The name of the cache directory of a Mercurial or Subversion repository: the
+/// last segment of the url and a short hash of the full url (as multiple
+/// repositories or branches can end with the same segment, e.g. `trunk`).
+pub(crate) fn repository_cache_name(url: &url::Url) -> Option<String> {
+    let name = url.path_segments()?.filter(|x| !x.is_empty()).last()?;
+    let hash = format!("{:x}", compute_bytes_digest::<Sha256>(url.as_str()));
+    Some(format!("{}_{}", name, &hash[..8]))
+}
+
+/// Copy a checked out repository from the cache into the work directory and
+/// apply the patches
+fn copy_checkout(
+    checkout: &Path,
+    target_directory: Option<&PathBuf>,
+    patches: &[PathBuf],
+    directories: &Directories,
+    system_tools: &SystemTools,
+    tool_configuration: &tool_configuration::Configuration,
+) -> Result<(), SourceError> {
+    let dest_dir = if let Some(target_directory) = target_directory {
+        directories.work_dir.join(target_directory)
+    } else {
+        directories.work_dir.to_path_buf()
+    };
+
+    let copy_result = tool_configuration.fancy_log_handler.wrap_in_progress(
+        "copying source into isolated environment",
+        || {
+            copy_dir::CopyDir::new(checkout, &dest_dir)
+                .use_gitignore(false)
+                .run()
+        },
+    )?;
+    tracing::info!(
+        "Copied {} files into isolated environment",
+        copy_result.copied_paths().len()
+    );
+
+    if !patches.is_empty() {
+        patch::apply_patches(system_tools, patches, &dest_dir, &directories.recipe_dir)?;
+    }
+    Ok(())
 }
 
 /// Fetches all sources in a list of sources and applies specified patches
@@ -111,7 +176,9 @@ pub async fn fetch_sources(
     // Figure out the directories we need
     let work_dir = &directories.work_dir;
     let recipe_dir = &directories.recipe_dir;
-    let cache_src = directories.output_dir.join("src_cache");
+    let cache_src = cache::SourceCache::new(&directories.output_dir)
+        .path()
+        .to_path_buf();
     fs::create_dir_all(&cache_src)?;
 
     let mut rendered_sources = Vec::new();
@@ -121,33 +188,54 @@ pub async fn fetch_sources(
             Source::Git(src) => {
                 tracing::info!("Fetching source from git repo: {}", src.url());
                 let result = git_source::git_src(system_tools, src, &cache_src, recipe_dir)?;
-                let dest_dir = if let Some(target_directory) = src.target_directory() {
-                    work_dir.join(target_directory)
-                } else {
-                    work_dir.to_path_buf()
-                };
 
                 rendered_sources.push(Source::Git(GitSource {
                     rev: GitRev::Commit(result.1),
                     ..src.clone()
                 }));
 
-                let copy_result = tool_configuration.fancy_log_handler.wrap_in_progress(
-                    "copying source into isolated environment",
-                    || {
-                        copy_dir::CopyDir::new(&result.0, &dest_dir)
-                            .use_gitignore(false)
-                            .run()
-                    },
+                copy_checkout(
+                    &result.0,
+                    src.target_directory(),
+                    src.patches(),
+                    directories,
+                    system_tools,
+                    tool_configuration,
+                )?;
+            }
+            Source::Hg(src) => {
+                tracing::info!("Fetching source from Mercurial repo: {}", src.url());
+                let (checkout, node) = hg_source::hg_src(system_tools, src, &cache_src)?;
+                copy_checkout(
+                    &checkout,
+                    src.target_directory(),
+                    src.patches(),
+                    directories,
+                    system_tools,
+                    tool_configuration,
+                )?;
+
+                rendered_sources.push(Source::Hg(HgSource {
+                    rev: Some(node),
+                    ..src.clone()
+                }));
+            }
+            Source::Svn(src) => {
+                tracing::info!("Fetching source from Subversion repo: {}", src.url());
+                let (checkout, revision) = svn_source::svn_src(system_tools, src, &cache_src)?;
+                copy_checkout(
+                    &checkout,
+                    src.target_directory(),
+                    src.patches(),
+                    directories,
+                    system_tools,
+                    tool_configuration,
                 )?;
-                tracing::info!(
-                    "Copied {} files into isolated environment",
-                    copy_result.copied_paths().len()
-                );
 
-                if !src.patches().is_empty() {
-                    patch::apply_patches(system_tools, src.patches(), &dest_dir, recipe_dir)?;
-                }
+                rendered_sources.push(Source::Svn(SvnSource {
+                    rev: Some(revision),
+                    ..src.clone()
+                }));
             }
             Source::Url(src) => {
                 let first_url = src.urls().first().expect("we should have at least one URL");
@@ -228,6 +316,7 @@ pub async fn fetch_sources(
                         || {
                             copy_dir::CopyDir::new(&src_path, &dest_dir)
                                 .use_gitignore(src.use_gitignore())
+                                .make_writable(true)
                                 .run()
                         },
                     )?;
@@ -263,7 +352,8 @@ pub async fn fetch_sources(
                             return Err(SourceError::ValidationFailed);
                         }
                     }
-                    fs::copy(&src_path, dest)?;
+                    fs::copy(&src_path, &dest)?;
+                    make_writable(&dest)?;
                 } else {
                     return Err(SourceError::FileNotFound(src_path));
                 }
@@ -275,6 +365,20 @@ pub async fn fetch_sources(
                 rendered_sources.push(Source::Path(src.clone()));
             }
         }
+
+        if let Some(verify) = src.verify() {
+            let source_dir = match src.target_directory() {
+                Some(target_directory) => work_dir.join(target_directory),
+                None => work_dir.to_path_buf(),
+            };
+            // manifests that do not exist yet are recorded next to the logs
+            // (`<output_dir>/vendor-manifests/<build dir>/<manifest>`)
+            let record_dir = directories
+                .output_dir
+                .join("vendor-manifests")
+                .join(directories.build_dir.file_name().unwrap_or_default());
+            verify::verify_vendored_files(verify, &source_dir, recipe_dir, &record_dir)?;
+        }
     }
     Ok(rendered_sources)
 }
@@ -285,7 +389,10 @@ impl Output {
         self,
         tool_configuration: &tool_configuration::Configuration,
     ) -> Result<Self, SourceError> {
-        let span = tracing::info_span!("Fetching source code");
+        let span = tracing::info_span!(
+            "Fetching source code",
+            profile = ProfileCategory::SourceFetch.as_str()
+        );
         let _enter = span.enter();
 
         let rendered_sources = fetch_sources(
@@ -304,3 +411,20 @@ impl Output {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repository_cache_name() {
+        let trunk = url::Url::parse("svn://svn.example.com/foo/trunk").unwrap();
+        let other_trunk = url::Url::parse("svn://svn.example.com/bar/trunk/").unwrap();
+
+        let name = repository_cache_name(&trunk).unwrap();
+        assert!(name.starts_with("trunk_"));
+        assert_eq!(name.len(), "trunk_".len() + 8);
+        assert_eq!(repository_cache_name(&trunk).unwrap(), name);
+        assert_ne!(repository_cache_name(&other_trunk).unwrap(), name);
+    }
+}
@@ -0,0 +1,88 @@
+//! This module contains the implementation of the fetching of `SvnSource` struct.
+
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{
+    recipe::parser::SvnSource,
+    system_tools::{SystemTools, Tool},
+};
+
+use super::{repository_cache_name, SourceError};
+
+/// Run a `svn` command and return its (trimmed) standard output.
+fn run_svn(mut command: Command, description: &str) -> Result<String, SourceError> {
+    let output = command.output().map_err(|e| {
+        SourceError::SvnError(format!("failed to execute `svn {description}`: {e}"))
+    })?;
+
+    if !output.status.success() {
+        return Err(SourceError::SvnError(format!(
+            "`svn {description}` failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        )));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Check out the Subversion repository specified by the given source into the cache
+/// directory. Returns the path to the working copy and the resolved revision number.
+pub fn svn_src(
+    system_tools: &SystemTools,
+    source: &SvnSource,
+    cache_dir: &Path,
+) -> Result<(PathBuf, String), SourceError> {
+    let cache_name = repository_cache_name(source.url())
+        .ok_or_else(|| SourceError::SvnError("failed to get name from url".to_string()))?;
+    let cache_path = cache_dir.join(cache_name);
+    let url = source.url().to_string();
+    let rev = source.rev().unwrap_or("HEAD");
+
+    if cache_path.join(".svn").is_dir() {
+        tracing::info!(
+            "Updating working copy of {} at {} in {}",
+            url,
+            rev,
+            cache_path.display()
+        );
+        let mut command = system_tools.call(Tool::Svn)?;
+        command
+            .args(["revert", "--recursive", "--non-interactive", "."])
+            .current_dir(&cache_path);
+        run_svn(command, "revert")?;
+
+        let mut command = system_tools.call(Tool::Svn)?;
+        command
+            .args(["update", "--non-interactive", "--revision", rev])
+            .current_dir(&cache_path);
+        run_svn(command, "update")?;
+    } else {
+        if cache_path.exists() {
+            fs_err::remove_dir_all(&cache_path)?;
+        }
+        tracing::info!(
+            "Checking out {} at {} into {}",
+            url,
+            rev,
+            cache_path.display()
+        );
+        let mut command = system_tools.call(Tool::Svn)?;
+        command
+            .args(["checkout", "--non-interactive", "--revision", rev, &url])
+            .arg(cache_path.as_os_str());
+        run_svn(command, "checkout")?;
+    }
+
+    let mut command = system_tools.call(Tool::Svn)?;
+    command
+        .args(["info", "--show-item", "revision"])
+        .current_dir(&cache_path);
+    let revision = run_svn(command, "info")?;
+
+    tracing::info!("Checked out revision: '{}' at '{}'", rev, revision);
+
+    Ok((cache_path, revision))
+}
@@ -3,19 +3,24 @@
 use std::{
     ffi::OsStr,
     fs,
-    io::{Read as _, Write as _},
+    io::{Read, Write as _},
     path::{Path, PathBuf},
 };
 
 use crate::{
     console_utils::LoggingOutputHandler,
     recipe::parser::UrlSource,
-    source::extract::{extract_tar, extract_zip},
+    source::extract::{extract_tar, extract_zip, unpack_tar},
     tool_configuration::{self, APP_USER_AGENT},
 };
 use tokio::io::AsyncWriteExt;
 
-use super::{checksum::Checksum, extract::is_tarball, SourceError};
+use super::{
+    bucket,
+    checksum::{Checksum, ChecksumHasher},
+    extract::is_tarball,
+    SourceError,
+};
 
 /// Splits a path into stem and extension, handling special cases like .tar.gz
 fn split_path(path: &Path) -> std::io::Result<(String, String)> {
@@ -61,18 +66,23 @@ fn cache_name_from_url(
     })
 }
 
-async fn fetch_remote(
+/// Sends the request for a remote source and returns the response together
+/// with the size of the download (or 0 if unknown).
+async fn request_remote(
     url: &url::Url,
-    target: &Path,
     tool_configuration: &tool_configuration::Configuration,
-) -> Result<(), SourceError> {
+) -> Result<(reqwest::Response, u64), SourceError> {
     let client = reqwest::Client::builder()
         .user_agent(APP_USER_AGENT)
         .redirect(reqwest::redirect::Policy::limited(50))
         .build()?;
 
     let (mut response, download_size) = {
-        let resp = client.get(url.as_str()).send().await?;
+        let resp = if bucket::is_bucket_url(url) {
+            bucket::get_object(url, &client, tool_configuration).await?
+        } else {
+            client.get(url.as_str()).send().await?
+        };
 
         match resp.error_for_status() {
             Ok(resp) => {
@@ -90,9 +100,18 @@ async fn fetch_remote(
         }
     };
 
+    Ok((response, download_size))
+}
+
+fn download_progress_bar(
+    url: &url::Url,
+    download_size: u64,
+    prefix: &'static str,
+    tool_configuration: &tool_configuration::Configuration,
+) -> indicatif::ProgressBar {
     let progress_bar = tool_configuration.fancy_log_handler.add_progress_bar(
         indicatif::ProgressBar::new(download_size)
-            .with_prefix("Downloading")
+            .with_prefix(prefix)
             .with_style(tool_configuration.fancy_log_handler.default_bytes_style()),
     );
 
@@ -103,15 +122,182 @@ async fn fetch_remote(
             .unwrap_or_else(|| "Unknown File".to_string()),
     );
 
-    let mut file = tokio::fs::File::create(&target).await?;
-    while let Some(chunk) = response.chunk().await? {
-        progress_bar.inc(chunk.len() as u64);
-        file.write_all(&chunk).await?;
+    progress_bar
+}
+
+/// The directory in which partial downloads and extractions for `target` are
+/// staged.
+fn staging_dir(target: &Path) -> &Path {
+    target
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."))
+}
+
+async fn fetch_remote(
+    url: &url::Url,
+    target: &Path,
+    tool_configuration: &tool_configuration::Configuration,
+) -> Result<(), SourceError> {
+    let cancellation = &tool_configuration.cancellation_token;
+
+    let (mut response, download_size) = request_remote(url, tool_configuration).await?;
+    let progress_bar = download_progress_bar(url, download_size, "Downloading", tool_configuration);
+
+    // Download to a temporary file so that no partial file is left behind in
+    // the cache when the download fails or is cancelled
+    let partial = tempfile::NamedTempFile::new_in(staging_dir(target))?;
+    let mut file = tokio::fs::File::from_std(partial.reopen()?);
+
+    let download = async {
+        while let Some(chunk) = response.chunk().await? {
+            progress_bar.inc(chunk.len() as u64);
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+        Ok::<_, SourceError>(())
+    };
+
+    tokio::select! {
+        result = download => result?,
+        _ = cancellation.cancelled() => {
+            progress_bar.abandon_with_message("Cancelled");
+            tracing::warn!("Download of {} was cancelled", url);
+            return Err(SourceError::Cancelled);
+        }
     }
 
     progress_bar.finish();
+    drop(file);
+    partial.persist(target).map_err(|e| e.error)?;
+    Ok(())
+}
+
+/// Reads the chunks of a download from a channel, while hashing them and
+/// writing them to the archive file.
+struct DownloadReader {
+    receiver: tokio::sync::mpsc::Receiver<Vec<u8>>,
+    chunk: Vec<u8>,
+    position: usize,
+    hasher: ChecksumHasher,
+    archive: fs::File,
+}
+
+impl Read for DownloadReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.position == self.chunk.len() {
+            match self.receiver.blocking_recv() {
+                Some(chunk) => {
+                    self.hasher.update(&chunk);
+                    self.archive.write_all(&chunk)?;
+                    self.chunk = chunk;
+                    self.position = 0;
+                }
+                None => return Ok(0),
+            }
+        }
+
+        let n = buf.len().min(self.chunk.len() - self.position);
+        buf[..n].copy_from_slice(&self.chunk[self.position..self.position + n]);
+        self.position += n;
+        Ok(n)
+    }
+}
+
+/// Downloads a tarball and extracts it while it is being downloaded, instead
+/// of extracting it after the download finished.
+///
+/// The archive is stored at `target` and the extracted sources at
+/// `extract_target`, but only once the download completed and the checksum
+/// matches. If the download fails, is cancelled or does not match the
+/// checksum, all partial files are removed.
+async fn fetch_and_extract_remote(
+    url: &url::Url,
+    target: &Path,
+    extract_target: &Path,
+    checksum: &Checksum,
+    tool_configuration: &tool_configuration::Configuration,
+) -> Result<(), SourceError> {
+    let cancellation = &tool_configuration.cancellation_token;
+
+    let (mut response, download_size) = request_remote(url, tool_configuration).await?;
+    let progress_bar = download_progress_bar(
+        url,
+        download_size,
+        "Downloading and extracting",
+        tool_configuration,
+    );
+
+    let partial_archive = tempfile::NamedTempFile::new_in(staging_dir(target))?;
+    let partial_extraction = tempfile::Builder::new()
+        .prefix(".extracting-")
+        .tempdir_in(staging_dir(extract_target))?;
+
+    let (sender, receiver) = tokio::sync::mpsc::channel::<Vec<u8>>(16);
+    let mut reader = DownloadReader {
+        receiver,
+        chunk: Vec::new(),
+        position: 0,
+        hasher: checksum.hasher(),
+        archive: partial_archive.reopen()?,
+    };
+    let file_name = target.file_name().map(OsStr::to_os_string);
+    let extraction_dir = partial_extraction.path().to_path_buf();
+    let extraction = tokio::task::spawn_blocking(move || {
+        unpack_tar(
+            std::io::BufReader::new(&mut reader),
+            file_name.as_deref(),
+            &extraction_dir,
+        )?;
+        // Read the remainder of the download (e.g. the padding after the end
+        // of the tar archive) so that the checksum covers all of it
+        std::io::copy(&mut reader, &mut std::io::sink())?;
+        reader.archive.flush()?;
+        Ok::<_, SourceError>(reader.hasher)
+    });
+
+    let download_progress = progress_bar.clone();
+    let download = async move {
+        while let Some(chunk) = response.chunk().await? {
+            download_progress.inc(chunk.len() as u64);
+            if sender.send(chunk.to_vec()).await.is_err() {
+                // The extraction stopped early, its error is reported below
+                break;
+            }
+        }
+        Ok::<_, SourceError>(())
+    };
 
-    file.flush().await?;
+    let downloaded = tokio::select! {
+        result = download => result,
+        _ = cancellation.cancelled() => Err(SourceError::Cancelled),
+    };
+
+    // Dropping the download closes the channel, so the extraction always
+    // finishes and we never remove files while they are being written
+    let extracted = extraction
+        .await
+        .map_err(|e| SourceError::UnknownError(format!("Extraction task failed: {e}")))?;
+
+    match downloaded {
+        Ok(()) => progress_bar.finish(),
+        Err(SourceError::Cancelled) => {
+            progress_bar.abandon_with_message("Cancelled");
+            tracing::warn!("Download of {} was cancelled", url);
+            return Err(SourceError::Cancelled);
+        }
+        Err(e) => {
+            progress_bar.abandon();
+            return Err(e);
+        }
+    }
+
+    if !extracted?.matches(checksum) {
+        return Err(SourceError::ValidationFailed);
+    }
+
+    partial_archive.persist(target).map_err(|e| e.error)?;
+    fs::rename(partial_extraction.into_path(), extract_target)?;
     Ok(())
 }
 
@@ -133,22 +319,40 @@ fn extract_to_cache(
         return Ok(target);
     }
 
-    if is_tarball(
+    let is_zip = path.extension() == Some(OsStr::new("zip"));
+    if !is_tarball(
         path.file_name()
             .unwrap_or_default()
             .to_string_lossy()
             .as_ref(),
-    ) {
-        tracing::info!("Extracting tar file to cache: {}", path.display());
-        extract_tar(path, &target, &tool_configuration.fancy_log_handler)?;
-        return Ok(target);
-    } else if path.extension() == Some(OsStr::new("zip")) {
+    ) && !is_zip
+    {
+        return Ok(path.to_path_buf());
+    }
+
+    // Extract to a temporary directory first so that a failed extraction does
+    // not leave a partially extracted directory in the cache
+    let partial_extraction = tempfile::Builder::new()
+        .prefix(".extracting-")
+        .tempdir_in(staging_dir(&target))?;
+    if is_zip {
         tracing::info!("Extracting zip file to cache: {}", path.display());
-        extract_zip(path, &target, &tool_configuration.fancy_log_handler)?;
-        return Ok(target);
+        extract_zip(
+            path,
+            partial_extraction.path(),
+            &tool_configuration.fancy_log_handler,
+        )?;
+    } else {
+        tracing::info!("Extracting tar file to cache: {}", path.display());
+        extract_tar(
+            path,
+            partial_extraction.path(),
+            &tool_configuration.fancy_log_handler,
+        )?;
     }
+    fs::rename(partial_extraction.into_path(), &target)?;
 
-    Ok(path.to_path_buf())
+    Ok(target)
 }
 
 fn copy_with_progress(
@@ -225,9 +429,54 @@ pub(crate) async fn url_src(
 
             tracing::info!("Using local source file.");
         } else {
-            let metadata = fs::metadata(&cache_name);
-            if metadata.is_ok() && metadata?.is_file() && checksum.validate(&cache_name) {
+            let cached = fs::metadata(&cache_name).is_ok_and(|m| m.is_file());
+            let extract_target = extracted_folder(&cache_name);
+            let cache_key = cache_name
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let valid = cached && checksum.validate(&cache_name);
+            tool_configuration.cache_statistics.record_source(
+                valid,
+                url.as_str(),
+                &cache_key,
+                if valid {
+                    "the cached file has a valid checksum"
+                } else if cached {
+                    "the checksum of the cached file does not match"
+                } else {
+                    "the file is not in the source cache"
+                },
+            );
+            if valid {
                 tracing::info!("Found valid source cache file.");
+            } else if source.file_name().is_none()
+                && is_tarball(&cache_name.to_string_lossy())
+                && !extract_target.is_dir()
+            {
+                // Stream large tarballs through the extraction while
+                // downloading them
+                match fetch_and_extract_remote(
+                    url,
+                    &cache_name,
+                    &extract_target,
+                    &checksum,
+                    tool_configuration,
+                )
+                .await
+                {
+                    Ok(()) => {
+                        tracing::info!("Downloaded and extracted file from {}", url);
+                        return Ok(extract_target);
+                    }
+                    Err(e @ (SourceError::Cancelled | SourceError::ValidationFailed)) => {
+                        return Err(e)
+                    }
+                    Err(e) => {
+                        last_error = Some(e);
+                        continue;
+                    }
+                }
             } else {
                 match fetch_remote(url, &cache_name, tool_configuration).await {
                     Ok(_) => {
@@ -239,6 +488,7 @@ pub(crate) async fn url_src(
                             return Err(SourceError::ValidationFailed);
                         }
                     }
+                    Err(SourceError::Cancelled) => return Err(SourceError::Cancelled),
                     Err(e) => {
                         last_error = Some(e);
                         continue;
@@ -326,4 +576,59 @@ mod tests {
             assert_eq!(name, expected);
         }
     }
+
+    #[test]
+    fn test_extract_while_downloading() {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        {
+            let mut builder = tar::Builder::new(&mut encoder);
+            let content = b"fn main() {}\n";
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, "project-1.0/src/main.rs", &content[..])
+                .unwrap();
+            builder.finish().unwrap();
+        }
+        let tarball = encoder.finish().unwrap();
+
+        let checksum = Checksum::Sha256(rattler_digest::compute_bytes_digest::<Sha256>(&tarball));
+        let dir = tempfile::tempdir().unwrap();
+        let archive_path = dir.path().join("project_12345678.tar.gz");
+
+        let (sender, receiver) = tokio::sync::mpsc::channel(4);
+        let mut reader = DownloadReader {
+            receiver,
+            chunk: Vec::new(),
+            position: 0,
+            hasher: checksum.hasher(),
+            archive: fs::File::create(&archive_path).unwrap(),
+        };
+
+        let chunks: Vec<Vec<u8>> = tarball.chunks(7).map(<[u8]>::to_vec).collect();
+        let producer = std::thread::spawn(move || {
+            for chunk in chunks {
+                sender.blocking_send(chunk).unwrap();
+            }
+        });
+
+        let target = dir.path().join("extracted");
+        unpack_tar(
+            std::io::BufReader::new(&mut reader),
+            archive_path.file_name(),
+            &target,
+        )
+        .unwrap();
+        std::io::copy(&mut reader, &mut std::io::sink()).unwrap();
+        producer.join().unwrap();
+
+        assert!(reader.hasher.matches(&checksum));
+        assert_eq!(fs::read(&archive_path).unwrap(), tarball);
+        assert_eq!(
+            fs::read_to_string(target.join("src/main.rs")).unwrap(),
+            "fn main() {}\n"
+        );
+    }
 }
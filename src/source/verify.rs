@@ -0,0 +1,210 @@
+//! Verify vendored files of a source (`source.verify`) against a manifest of
+//! their hashes. The manifest is read from the recipe directory. If it does not
+//! exist yet, it is recorded in the output directory (and should be copied
+//! next to the recipe and committed); builds with a manifest fail if a vendored
+//! file was added, removed or changed.
+use std::{
+    collections::BTreeMap,
+    path::{Component, Path},
+};
+
+use fs_err as fs;
+use rattler_digest::{compute_bytes_digest, compute_file_digest, Sha256};
+use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
+
+use crate::recipe::parser::SourceVerify;
+
+use super::SourceError;
+
+/// The manifest of the vendored files of a source
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VendorManifest {
+    /// The SHA256 hash of every file, by path relative to the source directory
+    /// (with `/` as separator)
+    pub files: BTreeMap<String, String>,
+}
+
+impl VendorManifest {
+    /// Hash all files in `source_dir` that match the globs of `verify`.
+    /// Symlinks are not followed, the hash of their target path is recorded
+    /// instead.
+    pub fn from_directory(verify: &SourceVerify, source_dir: &Path) -> Result<Self, SourceError> {
+        let mut files = BTreeMap::new();
+        for entry in WalkDir::new(source_dir) {
+            let entry = entry?;
+            if entry.file_type().is_dir() {
+                continue;
+            }
+            let relative = entry.path().strip_prefix(source_dir)?;
+            if !verify.files.is_match(relative) {
+                continue;
+            }
+
+            let hash = if entry.path_is_symlink() {
+                let target = fs::read_link(entry.path())?;
+                compute_bytes_digest::<Sha256>(target.to_string_lossy().as_bytes())
+            } else {
+                compute_file_digest::<Sha256>(entry.path())?
+            };
+            files.insert(manifest_path(relative), format!("{:x}", hash));
+        }
+        Ok(Self { files })
+    }
+
+    /// The differences to another manifest (e.g. the current files), sorted by
+    /// path
+    pub fn diff(&self, current: &Self) -> Vec<String> {
+        let mut changes = BTreeMap::new();
+        for (path, hash) in &self.files {
+            match current.files.get(path) {
+                None => {
+                    changes.insert(path, "removed");
+                }
+                Some(current_hash) if current_hash != hash => {
+                    changes.insert(path, "changed");
+                }
+                Some(_) => {}
+            }
+        }
+        for path in current.files.keys() {
+            if !self.files.contains_key(path) {
+                changes.insert(path, "added");
+            }
+        }
+        changes
+            .into_iter()
+            .map(|(path, change)| format!("{}: {}", change, path))
+            .collect()
+    }
+}
+
+/// A path relative to the source directory with `/` as separator, so that the
+/// manifest is the same on all platforms
+fn manifest_path(relative: &Path) -> String {
+    relative
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Verify the vendored files in `source_dir` against the manifest of
+/// `verify` in `recipe_dir`. If the manifest does not exist yet, the hashes of
+/// the current files are recorded in `record_dir` instead (the recipe directory
+/// is never written to).
+pub fn verify_vendored_files(
+    verify: &SourceVerify,
+    source_dir: &Path,
+    recipe_dir: &Path,
+    record_dir: &Path,
+) -> Result<(), SourceError> {
+    let manifest_path = recipe_dir.join(&verify.manifest);
+    let current = VendorManifest::from_directory(verify, source_dir)?;
+
+    if !manifest_path.exists() {
+        if current.files.is_empty() {
+            tracing::warn!(
+                "No vendored files match the globs of `verify` in {}",
+                source_dir.display()
+            );
+        }
+        let recorded_path = record_dir.join(&verify.manifest);
+        if let Some(parent) = recorded_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let content = serde_json::to_string_pretty(&current)
+            .map_err(|e| SourceError::UnknownError(e.to_string()))?;
+        fs::write(&recorded_path, content + "\n")?;
+        tracing::warn!(
+            "The vendor manifest {} does not exist, recorded the hashes of {} vendored files in {} \
+             (copy it to the recipe directory and commit it with the recipe)",
+            manifest_path.display(),
+            current.files.len(),
+            recorded_path.display()
+        );
+        return Ok(());
+    }
+
+    let expected: VendorManifest = serde_json::from_str(&fs::read_to_string(&manifest_path)?)
+        .map_err(|e| {
+            SourceError::UnknownError(format!(
+                "failed to read the vendor manifest {}: {}",
+                manifest_path.display(),
+                e
+            ))
+        })?;
+
+    let changes = expected.diff(&current);
+    if !changes.is_empty() {
+        return Err(SourceError::VendoredFilesChanged(
+            manifest_path,
+            changes.join("\n"),
+        ));
+    }
+
+    tracing::info!(
+        "Verified {} vendored files against {}",
+        current.files.len(),
+        manifest_path.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recipe::parser::GlobVec;
+
+    #[test]
+    fn test_verify_vendored_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let recipe_dir = dir.path().join("recipe");
+        let source_dir = dir.path().join("work");
+        let record_dir = dir.path().join("output/vendor-manifests");
+        fs::create_dir_all(&recipe_dir).unwrap();
+        fs::create_dir_all(source_dir.join("vendor/foo")).unwrap();
+        fs::write(source_dir.join("vendor/foo/lib.rs"), "fn foo() {}").unwrap();
+        fs::write(source_dir.join("Cargo.lock"), "lock").unwrap();
+        fs::write(source_dir.join("main.rs"), "fn main() {}").unwrap();
+
+        let verify = SourceVerify {
+            manifest: "vendor.json".into(),
+            files: GlobVec::from_vec(vec!["vendor/**", "Cargo.lock"], None),
+        };
+
+        // the first build records the manifest, outside of the recipe directory
+        verify_vendored_files(&verify, &source_dir, &recipe_dir, &record_dir).unwrap();
+        assert!(!recipe_dir.join("vendor.json").exists());
+        let recorded = fs::read_to_string(record_dir.join("vendor.json")).unwrap();
+        let manifest: VendorManifest = serde_json::from_str(&recorded).unwrap();
+        assert_eq!(
+            manifest.files.keys().collect::<Vec<_>>(),
+            vec!["Cargo.lock", "vendor/foo/lib.rs"]
+        );
+
+        // the recorded manifest is committed with the recipe
+        fs::write(recipe_dir.join("vendor.json"), recorded).unwrap();
+        verify_vendored_files(&verify, &source_dir, &recipe_dir, &record_dir).unwrap();
+
+        // files that are not vendored can change
+        fs::write(source_dir.join("main.rs"), "fn main() { foo() }").unwrap();
+        verify_vendored_files(&verify, &source_dir, &recipe_dir, &record_dir).unwrap();
+
+        // vendored files can not
+        fs::write(source_dir.join("vendor/foo/lib.rs"), "fn foo() { evil() }").unwrap();
+        fs::write(source_dir.join("vendor/foo/build.rs"), "fn main() {}").unwrap();
+        let err =
+            verify_vendored_files(&verify, &source_dir, &recipe_dir, &record_dir).unwrap_err();
+        let SourceError::VendoredFilesChanged(_, changes) = &err else {
+            panic!("expected the vendored files to have changed, got {err:?}");
+        };
+        assert_eq!(
+            changes,
+            "added: vendor/foo/build.rs\nchanged: vendor/foo/lib.rs"
+        );
+    }
+}
@@ -3,10 +3,12 @@
 use rattler_conda_types::Platform;
 use rattler_shell::{activation::Activator, shell};
 use serde::{Deserialize, Serialize, Serializer};
+use serde_with::{DeserializeFromStr, SerializeDisplay};
 use std::{
     collections::{BTreeMap, HashMap},
     path::{Path, PathBuf},
     process::Command,
+    str::FromStr,
     sync::{Arc, Mutex},
 };
 use thiserror::Error;
@@ -15,17 +17,34 @@ use thiserror::Error;
 #[derive(Error, Debug)]
 pub enum ToolError {
     /// The tool was not found on the system
-    #[error("failed to find `{0}` ({1})")]
+    #[error(
+        "failed to find `{0}` ({1}). Install `{0}` or set its path with `--system-tool {0}=<path>` (or `system-tools` in `rattler-build.toml`)"
+    )]
     ToolNotFound(Tool, which::Error),
+
+    /// The path that the tool was pinned to does not exist
+    #[error(
+        "`{0}` is set to `{1}`, but that file does not exist. Fix the path given with `--system-tool` or `system-tools` in `rattler-build.toml`"
+    )]
+    OverrideNotFound(Tool, PathBuf),
 }
 
+/// The tool name is not known
+#[derive(Error, Debug)]
+#[error(
+    "unknown system tool `{0}` (expected one of: rattler-build, patch, patchelf, codesign, install_name_tool, git, hg, svn, objcopy, strip, dsymutil)"
+)]
+pub struct UnknownToolError(String);
+
 /// Any third party tool that is used by rattler build should be added here
-/// and the tool should be invoked through the system tools object.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
-#[serde(rename_all = "snake_case")]
+/// and the tool should be invoked through the system tools object. The tools
+/// are (de)serialized with their [`std::fmt::Display`] and [`FromStr`]
+/// implementations.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, SerializeDisplay, DeserializeFromStr,
+)]
 pub enum Tool {
     /// The rattler build tool itself
-    #[serde(rename = "rattler-build")]
     RattlerBuild,
     /// The patch tool
     Patch,
@@ -37,6 +56,16 @@ pub enum Tool {
     InstallNameTool,
     /// The git tool
     Git,
+    /// The Mercurial tool
+    Hg,
+    /// The Subversion tool
+    Svn,
+    /// The objcopy tool (for splitting debug information of ELF files)
+    Objcopy,
+    /// The strip tool (for stripping MachO files)
+    Strip,
+    /// The dsymutil tool (for splitting debug information of MachO files)
+    Dsymutil,
 }
 
 impl std::fmt::Display for Tool {
@@ -51,11 +80,37 @@ impl std::fmt::Display for Tool {
                 Tool::Patchelf => "patchelf".to_string(),
                 Tool::InstallNameTool => "install_name_tool".to_string(),
                 Tool::Git => "git".to_string(),
+                Tool::Hg => "hg".to_string(),
+                Tool::Svn => "svn".to_string(),
+                Tool::Objcopy => "objcopy".to_string(),
+                Tool::Strip => "strip".to_string(),
+                Tool::Dsymutil => "dsymutil".to_string(),
             }
         )
     }
 }
 
+impl FromStr for Tool {
+    type Err = UnknownToolError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rattler-build" => Ok(Tool::RattlerBuild),
+            "patch" => Ok(Tool::Patch),
+            "patchelf" => Ok(Tool::Patchelf),
+            "codesign" => Ok(Tool::Codesign),
+            "install_name_tool" => Ok(Tool::InstallNameTool),
+            "git" => Ok(Tool::Git),
+            "hg" => Ok(Tool::Hg),
+            "svn" => Ok(Tool::Svn),
+            "objcopy" => Ok(Tool::Objcopy),
+            "strip" => Ok(Tool::Strip),
+            "dsymutil" => Ok(Tool::Dsymutil),
+            _ => Err(UnknownToolError(s.to_string())),
+        }
+    }
+}
+
 /// The system tools object is used to find and call system tools. It also keeps track of the
 /// versions of the tools that are used.
 #[derive(Debug, Clone)]
@@ -64,6 +119,8 @@ pub struct SystemTools {
     used_tools: Arc<Mutex<HashMap<Tool, String>>>,
     found_tools: Arc<Mutex<HashMap<Tool, PathBuf>>>,
     build_prefix: Option<PathBuf>,
+    host_triple: Option<String>,
+    overrides: Arc<BTreeMap<Tool, PathBuf>>,
 }
 
 impl Default for SystemTools {
@@ -73,6 +130,8 @@ impl Default for SystemTools {
             used_tools: Arc::new(Mutex::new(HashMap::new())),
             found_tools: Arc::new(Mutex::new(HashMap::new())),
             build_prefix: None,
+            host_triple: None,
+            overrides: Arc::new(BTreeMap::new()),
         }
     }
 }
@@ -92,6 +151,42 @@ impl SystemTools {
         }
     }
 
+    /// Create a copy of the system tools object that looks up the binutils
+    /// (`objcopy`, `strip` and `dsymutil`) prefixed with the host triple of
+    /// the cross compilers first (e.g. `aarch64-conda-linux-gnu-objcopy`).
+    pub fn with_host_triple(&self, host_triple: Option<&str>) -> Self {
+        Self {
+            host_triple: host_triple.map(ToString::to_string),
+            ..self.clone()
+        }
+    }
+
+    /// Create a copy of the system tools object that uses the given paths for
+    /// the tools instead of searching them (in the build prefix or `PATH`).
+    pub fn with_overrides(&self, overrides: BTreeMap<Tool, PathBuf>) -> Self {
+        Self {
+            overrides: Arc::new(overrides),
+            ..self.clone()
+        }
+    }
+
+    /// The versions of rattler-build and of all tools that were used so far,
+    /// by tool name (e.g. to record them in `about.json`)
+    pub fn versions(&self) -> BTreeMap<String, String> {
+        let mut versions = self
+            .used_tools
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(tool, version)| (tool.to_string(), version.clone()))
+            .collect::<BTreeMap<_, _>>();
+        versions.insert(
+            Tool::RattlerBuild.to_string(),
+            self.rattler_build_version.clone(),
+        );
+        versions
+    }
+
     /// Create a new system tools object from a previous run so that we can warn if the versions
     /// of the tools have changed
     pub fn from_previous_run(
@@ -111,25 +206,38 @@ impl SystemTools {
             used_tools: Arc::new(Mutex::new(used_tools)),
             found_tools: Arc::new(Mutex::new(HashMap::new())),
             build_prefix: None,
+            host_triple: None,
+            overrides: Arc::new(BTreeMap::new()),
         }
     }
 
     /// Find the tool in the system and return the path to the tool
-    fn find_tool(&self, tool: Tool) -> Result<PathBuf, which::Error> {
-        let which = |tool: &str| -> Result<PathBuf, which::Error> {
+    fn find_tool(&self, tool: Tool) -> Result<PathBuf, ToolError> {
+        if let Some(path) = self.overrides.get(&tool) {
+            if !path.is_file() {
+                return Err(ToolError::OverrideNotFound(tool, path.clone()));
+            }
+        }
+
+        let which = |name: &str| -> Result<PathBuf, ToolError> {
+            if let Some(path) = self.overrides.get(&tool) {
+                return Ok(path.clone());
+            }
+
             if let Some(build_prefix) = &self.build_prefix {
                 let build_prefix_activator =
                     Activator::from_path(build_prefix, shell::Bash, Platform::current()).unwrap();
 
                 let paths = std::env::join_paths(build_prefix_activator.paths).ok();
-                let mut found_tool = which::which_in_global(&tool, paths)?;
+                let mut found_tool = which::which_in_global(name, paths)
+                    .map_err(|e| ToolError::ToolNotFound(tool, e))?;
 
                 // if the tool is found in the build prefix, return it
                 if let Some(found_tool) = found_tool.next() {
                     return Ok(found_tool);
                 }
             }
-            which::which(tool)
+            which::which(name).map_err(|e| ToolError::ToolNotFound(tool, e))
         };
 
         let (tool_path, found_version) = match tool {
@@ -152,6 +260,19 @@ impl SystemTools {
                 let path = which("codesign")?;
                 (path, "".to_string())
             }
+            Tool::Objcopy | Tool::Strip | Tool::Dsymutil => {
+                // the binutils of the (cross) compilers are prefixed with the
+                // host triple and work on binaries of the target platform
+                let prefixed = self
+                    .host_triple
+                    .as_ref()
+                    .and_then(|host| which(&format!("{}-{}", host, tool)).ok());
+                let path = match prefixed {
+                    Some(path) => path,
+                    None => which(&tool.to_string())?,
+                };
+                (path, "".to_string())
+            }
             Tool::Git => {
                 let path = which("git")?;
                 let output = std::process::Command::new(&path)
@@ -162,6 +283,26 @@ impl SystemTools {
 
                 (path, found_version.to_string())
             }
+            Tool::Hg => {
+                let path = which("hg")?;
+                let output = std::process::Command::new(&path)
+                    .args(["--version", "--quiet"])
+                    .output()
+                    .expect("Failed to execute `hg` command");
+                let found_version = String::from_utf8_lossy(&output.stdout);
+
+                (path, found_version.to_string())
+            }
+            Tool::Svn => {
+                let path = which("svn")?;
+                let output = std::process::Command::new(&path)
+                    .args(["--version", "--quiet"])
+                    .output()
+                    .expect("Failed to execute `svn` command");
+                let found_version = String::from_utf8_lossy(&output.stdout);
+
+                (path, found_version.to_string())
+            }
             Tool::Patch => {
                 let path = which("patch")?;
                 let version = std::process::Command::new(&path)
@@ -210,23 +351,14 @@ impl SystemTools {
     /// Create a new `std::process::Command` for the given tool. The command is created with the
     /// path to the tool and can be further configured with arguments and environment variables.
     pub fn call(&self, tool: Tool) -> Result<Command, ToolError> {
-        let tool_path = self
-            .find_tool(tool)
-            .map_err(|e| ToolError::ToolNotFound(tool, e))?;
+        let tool_path = self.find_tool(tool)?;
         Ok(std::process::Command::new(tool_path))
     }
 }
 
 impl Serialize for SystemTools {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        let mut ordered_map = BTreeMap::new();
-        let used_tools = self.used_tools.lock().unwrap();
-        for (tool, version) in used_tools.iter() {
-            ordered_map.insert(tool.to_string(), version);
-        }
-        ordered_map.insert(Tool::RattlerBuild.to_string(), &self.rattler_build_version);
-
-        ordered_map.serialize(serializer)
+        self.versions().serialize(serializer)
     }
 }
 
@@ -279,6 +411,8 @@ mod tests {
             used_tools: Arc::new(Mutex::new(used_tools)),
             found_tools: Arc::new(Mutex::new(HashMap::new())),
             build_prefix: None,
+            host_triple: None,
+            overrides: Arc::new(BTreeMap::new()),
         };
 
         let json = serde_json::to_string_pretty(&system_tool).unwrap();
@@ -291,4 +425,46 @@ mod tests {
             .unwrap()
             .contains_key(&Tool::Patchelf));
     }
+
+    #[test]
+    fn test_tool_names() {
+        for tool in [
+            Tool::RattlerBuild,
+            Tool::Patch,
+            Tool::Patchelf,
+            Tool::Codesign,
+            Tool::InstallNameTool,
+            Tool::Git,
+            Tool::Hg,
+            Tool::Svn,
+            Tool::Objcopy,
+            Tool::Strip,
+            Tool::Dsymutil,
+        ] {
+            let name = tool.to_string();
+            assert_eq!(name.parse::<Tool>().unwrap(), tool);
+            let json = serde_json::to_value(tool).unwrap();
+            assert_eq!(json, serde_json::Value::String(name));
+            assert_eq!(serde_json::from_value::<Tool>(json).unwrap(), tool);
+        }
+        assert!("install-name-tool".parse::<Tool>().is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_prefixed_binutils() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let prefix = tempfile::tempdir().unwrap();
+        let bin = prefix.path().join("bin");
+        fs_err::create_dir_all(&bin).unwrap();
+        let objcopy = bin.join("aarch64-conda-linux-gnu-objcopy");
+        fs_err::write(&objcopy, "#!/bin/sh\n").unwrap();
+        fs_err::set_permissions(&objcopy, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let system_tools = SystemTools::new()
+            .with_build_prefix(prefix.path())
+            .with_host_triple(Some("aarch64-conda-linux-gnu"));
+        assert_eq!(system_tools.find_tool(Tool::Objcopy).unwrap(), objcopy);
+    }
 }
@@ -14,8 +14,12 @@ use rattler_repodata_gateway::Gateway;
 use rattler_solve::ChannelPriority;
 use reqwest_middleware::ClientWithMiddleware;
 use reqwest_retry::{policies::ExponentialBackoff, RetryTransientMiddleware};
+use tokio_util::sync::CancellationToken;
 
-use crate::console_utils::LoggingOutputHandler;
+use crate::{
+    cache_statistics::CacheStatistics, clobber::PackagedFiles, console_utils::LoggingOutputHandler,
+    hash::HashConfig, metadata::FilenameTemplate, selectors::EnvAccess,
+};
 
 /// The user agent to use for the reqwest client
 pub const APP_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"),);
@@ -40,11 +44,25 @@ pub enum TestStrategy {
     /// Otherwise, skip the tests. If the target platform is noarch,
     /// the tests are always executed.
     Native,
-    /// Always run the tests
+    /// Run the tests natively or through emulation (QEMU registered with
+    /// binfmt_misc, Rosetta 2). The tests are skipped if the host platform
+    /// cannot be emulated on the build platform.
     #[default]
     NativeAndEmulated,
 }
 
+/// What to do when files are packaged by more than one output of a recipe
+#[derive(Debug, Clone, Copy, ValueEnum, Default, PartialEq, Eq)]
+pub enum ClobberCheck {
+    /// Do not check the outputs
+    Ignore,
+    /// Print a warning
+    #[default]
+    Warn,
+    /// Fail the build
+    Error,
+}
+
 /// Global configuration for the build
 #[derive(Clone)]
 pub struct Configuration {
@@ -58,6 +76,9 @@ pub struct Configuration {
     /// is done
     pub no_clean: bool,
 
+    /// Resume an interrupted build from the last completed phase
+    pub resume: bool,
+
     /// The strategy to use for running tests
     pub test_strategy: TestStrategy,
 
@@ -81,6 +102,10 @@ pub struct Configuration {
     /// threads does not matter for the final result.
     pub compression_threads: Option<u32>,
 
+    /// The template for an additional, content-addressed file name of every
+    /// package archive (`--filename-template`)
+    pub filename_template: Option<FilenameTemplate>,
+
     /// The package cache to use to store packages in.
     pub package_cache: PackageCache,
 
@@ -89,6 +114,36 @@ pub struct Configuration {
 
     /// What channel priority to use in solving
     pub channel_priority: ChannelPriority,
+
+    /// Whether to stop the build right before running the build script
+    pub dry_run: bool,
+
+    /// Whether to continue building the remaining outputs when an output fails
+    pub continue_on_failure: bool,
+
+    /// What to do when files are packaged by more than one output of a recipe
+    pub clobber_check: ClobberCheck,
+
+    /// The files of the outputs that were packaged, to find files that are
+    /// packaged by more than one output of a recipe
+    pub packaged_files: Arc<PackagedFiles>,
+
+    /// How to compute the hash of a variant (length and excluded keys)
+    pub hash_config: HashConfig,
+
+    /// Whether to retry failed solves with relaxed specs to explain the
+    /// conflict
+    pub explain_conflicts: bool,
+
+    /// The environment variables that recipes can read with `env.get`
+    pub env_access: EnvAccess,
+
+    /// Statistics about the source cache and skipped existing packages
+    pub cache_statistics: Arc<CacheStatistics>,
+
+    /// Cancels the running downloads and the remaining builds. The caller
+    /// decides when to cancel (e.g. the CLI cancels it on Ctrl-C).
+    pub cancellation_token: CancellationToken,
 }
 
 /// Get the authentication storage from the given file
@@ -136,6 +191,7 @@ pub struct ConfigurationBuilder {
     fancy_log_handler: Option<LoggingOutputHandler>,
     client: Option<ClientWithMiddleware>,
     no_clean: bool,
+    resume: bool,
     no_test: bool,
     test_strategy: TestStrategy,
     use_zstd: bool,
@@ -144,7 +200,16 @@ pub struct ConfigurationBuilder {
     noarch_build_platform: Option<Platform>,
     channel_config: Option<ChannelConfig>,
     compression_threads: Option<u32>,
+    filename_template: Option<FilenameTemplate>,
     channel_priority: ChannelPriority,
+    dry_run: bool,
+    continue_on_failure: bool,
+    clobber_check: ClobberCheck,
+    hash_config: HashConfig,
+    explain_conflicts: bool,
+    env_access: EnvAccess,
+    explain_cache: bool,
+    cancellation_token: CancellationToken,
 }
 
 impl Configuration {
@@ -162,6 +227,7 @@ impl ConfigurationBuilder {
             fancy_log_handler: None,
             client: None,
             no_clean: false,
+            resume: false,
             no_test: false,
             test_strategy: TestStrategy::default(),
             use_zstd: true,
@@ -170,7 +236,16 @@ impl ConfigurationBuilder {
             noarch_build_platform: None,
             channel_config: None,
             compression_threads: None,
+            filename_template: None,
             channel_priority: ChannelPriority::Strict,
+            dry_run: false,
+            continue_on_failure: false,
+            clobber_check: ClobberCheck::default(),
+            hash_config: HashConfig::default(),
+            explain_conflicts: false,
+            env_access: EnvAccess::default(),
+            explain_cache: false,
+            cancellation_token: CancellationToken::new(),
         }
     }
 
@@ -205,6 +280,70 @@ impl ConfigurationBuilder {
         }
     }
 
+    /// Set whether to explain why the source cache is (not) used and why
+    /// existing packages are (not) skipped.
+    pub fn with_explain_cache(self, explain_cache: bool) -> Self {
+        Self {
+            explain_cache,
+            ..self
+        }
+    }
+
+    /// Set whether to stop the build right before running the build script.
+    pub fn with_dry_run(self, dry_run: bool) -> Self {
+        Self { dry_run, ..self }
+    }
+
+    /// Set whether to retry failed solves with relaxed specs to explain the
+    /// conflict.
+    pub fn with_explain_conflicts(self, explain_conflicts: bool) -> Self {
+        Self {
+            explain_conflicts,
+            ..self
+        }
+    }
+
+    /// Set whether to continue building the remaining outputs when an output
+    /// fails. Outputs that depend on a failed output are skipped.
+    pub fn with_continue_on_failure(self, continue_on_failure: bool) -> Self {
+        Self {
+            continue_on_failure,
+            ..self
+        }
+    }
+
+    /// Set what to do when files are packaged by more than one output of a
+    /// recipe.
+    pub fn with_clobber_check(self, clobber_check: ClobberCheck) -> Self {
+        Self {
+            clobber_check,
+            ..self
+        }
+    }
+
+    /// Set the configuration that is used to compute the hash of a variant.
+    pub fn with_hash_config(self, hash_config: HashConfig) -> Self {
+        Self {
+            hash_config,
+            ..self
+        }
+    }
+
+    /// Set the environment variables that recipes can read and that are part
+    /// of the variant hash.
+    pub fn with_env_access(self, env_access: EnvAccess) -> Self {
+        Self { env_access, ..self }
+    }
+
+    /// Set the token that cancels the running downloads and the remaining
+    /// builds.
+    pub fn with_cancellation_token(self, cancellation_token: CancellationToken) -> Self {
+        Self {
+            cancellation_token,
+            ..self
+        }
+    }
+
     /// Set the channel configuration to use.
     pub fn with_channel_config(self, channel_config: ChannelConfig) -> Self {
         Self {
@@ -222,6 +361,15 @@ impl ConfigurationBuilder {
         }
     }
 
+    /// Set the template for an additional file name of every package archive
+    /// (in `<output_dir>/artifacts/`).
+    pub fn with_filename_template(self, filename_template: Option<FilenameTemplate>) -> Self {
+        Self {
+            filename_template,
+            ..self
+        }
+    }
+
     /// Sets whether to keep the build output or delete it after the build is
     /// done.
     pub fn with_keep_build(self, keep_build: bool) -> Self {
@@ -231,6 +379,12 @@ impl ConfigurationBuilder {
         }
     }
 
+    /// Sets whether to resume an interrupted build from the last completed
+    /// phase.
+    pub fn with_resume(self, resume: bool) -> Self {
+        Self { resume, ..self }
+    }
+
     /// Sets the request client to use for network requests.
     pub fn with_reqwest_client(self, client: ClientWithMiddleware) -> Self {
         Self {
@@ -326,6 +480,7 @@ impl ConfigurationBuilder {
             fancy_log_handler: self.fancy_log_handler.unwrap_or_default(),
             client,
             no_clean: self.no_clean,
+            resume: self.resume,
             test_strategy,
             use_zstd: self.use_zstd,
             use_bz2: self.use_bz2,
@@ -333,9 +488,19 @@ impl ConfigurationBuilder {
             noarch_build_platform: self.noarch_build_platform,
             channel_config,
             compression_threads: self.compression_threads,
+            filename_template: self.filename_template,
             package_cache,
             repodata_gateway,
             channel_priority: self.channel_priority,
+            dry_run: self.dry_run,
+            continue_on_failure: self.continue_on_failure,
+            clobber_check: self.clobber_check,
+            packaged_files: Arc::new(PackagedFiles::default()),
+            hash_config: self.hash_config,
+            explain_conflicts: self.explain_conflicts,
+            env_access: self.env_access,
+            cache_statistics: Arc::new(CacheStatistics::new(self.explain_cache)),
+            cancellation_token: self.cancellation_token,
         }
     }
 }
@@ -24,6 +24,8 @@ pub enum Event {
     GetBuildOutputs(Vec<PathBuf>),
     /// Processes the build outputs.
     ProcessBuildOutputs(Vec<Output>),
+    /// Confirm the variant selection of the pre-flight screen.
+    ConfirmVariants,
     /// Start building.
     StartBuild(usize),
     /// Build all packages.
@@ -180,27 +180,52 @@ pub async fn run<B: Backend>(
             }
             Event::ProcessBuildOutputs(mut outputs) => {
                 sort_build_outputs_topologically(&mut outputs, state.build_data.up_to.as_deref())?;
-                let packages: Vec<Package> = outputs
+                state.variant_matrix = Some(VariantMatrix::new(outputs));
+            }
+            Event::ConfirmVariants => {
+                if state
+                    .variant_matrix
+                    .as_ref()
+                    .is_some_and(|variant_matrix| variant_matrix.enabled_count() == 0)
+                {
+                    tracing::warn!("No variants were selected for building");
+                    continue;
+                }
+                let Some(variant_matrix) = state.variant_matrix.take() else {
+                    continue;
+                };
+                let packages: Vec<Package> = variant_matrix
+                    .into_enabled_outputs()
                     .into_iter()
                     .map(|output| Package::from_output(output, &state.tool_config))
                     .collect();
+                // Drop the packages of re-rendered recipes that were deselected.
                 state.packages.retain(|package| {
-                    packages
+                    !packages
                         .iter()
-                        .any(|p| p.recipe_path != package.recipe_path)
+                        .any(|p| p.recipe_path == package.recipe_path)
+                        || packages.iter().any(|p| {
+                            p.name == package.name && p.build_string == package.build_string
+                        })
                 });
                 for new_package in packages {
-                    match state
-                        .packages
-                        .iter_mut()
-                        .find(|p| new_package.name == p.name)
-                    {
+                    match state.packages.iter_mut().find(|p| {
+                        new_package.name == p.name && new_package.build_string == p.build_string
+                    }) {
                         Some(package) => {
                             *package = new_package;
                         }
                         None => state.packages.push(new_package),
                     }
                 }
+                state.selected_package = 0;
+                if !state.is_building_package() {
+                    state.build_queue = None;
+                    tui.event_handler
+                        .sender
+                        .send(Event::StartBuildQueue)
+                        .into_diagnostic()?;
+                }
             }
             Event::StartBuildQueue => match state.build_queue {
                 Some(mut build_index) => {
@@ -8,13 +8,19 @@ use ratatui::{
     layout::{Alignment, Position},
     prelude::*,
     style::{Color, Style, Stylize},
-    widgets::{Block, BorderType, Paragraph, Scrollbar, ScrollbarOrientation, ScrollbarState},
+    widgets::{
+        Block, BorderType, Cell, Paragraph, Row, Scrollbar, ScrollbarOrientation, ScrollbarState,
+        Table, TableState,
+    },
     Frame,
 };
 use tokio::sync::mpsc;
 use tui_input::backend::crossterm::EventHandler;
 
-use super::{event::Event, state::TuiState};
+use super::{
+    event::Event,
+    state::{TuiState, VariantMatrix},
+};
 
 /// Key bindings.
 const KEY_BINDINGS: &[(&str, &str)] = &[
@@ -28,12 +34,61 @@ const KEY_BINDINGS: &[(&str, &str)] = &[
     ("q", "Quit"),
 ];
 
+/// Key bindings of the variant pre-flight screen.
+const VARIANT_KEY_BINDINGS: &[(&str, &str)] = &[
+    ("⏎ ", "Build Selected"),
+    ("␣ ", "Toggle"),
+    ("a", "Toggle All"),
+    ("j", "Next"),
+    ("k", "Prev"),
+    ("J", "Move Down"),
+    ("K", "Move Up"),
+    ("q", "Quit"),
+];
+
+/// Handles the key events of the variant pre-flight screen.
+fn handle_variant_key_events(
+    key_event: KeyEvent,
+    sender: mpsc::UnboundedSender<Event>,
+    variant_matrix: &mut VariantMatrix,
+) -> miette::Result<bool> {
+    match key_event.code {
+        KeyCode::Esc | KeyCode::Char('q') => return Ok(false),
+        KeyCode::Char('c') | KeyCode::Char('C') if key_event.modifiers == KeyModifiers::CONTROL => {
+            return Ok(false)
+        }
+        KeyCode::Char('j') | KeyCode::Down => variant_matrix.select_next(),
+        KeyCode::Char('k') | KeyCode::Up => variant_matrix.select_previous(),
+        KeyCode::Char('J') => {
+            if !variant_matrix.move_selected_down() {
+                tracing::warn!("The next variant depends on the highlighted variant");
+            }
+        }
+        KeyCode::Char('K') => {
+            if !variant_matrix.move_selected_up() {
+                tracing::warn!("The highlighted variant depends on the previous variant");
+            }
+        }
+        KeyCode::Char(' ') => variant_matrix.toggle_selected(),
+        KeyCode::Char('a') => variant_matrix.toggle_all(),
+        KeyCode::Enter => sender.send(Event::ConfirmVariants).into_diagnostic()?,
+        _ => {}
+    }
+    Ok(true)
+}
+
 /// Handles the key events and updates the state.
 pub(crate) fn handle_key_events(
     key_event: KeyEvent,
     sender: mpsc::UnboundedSender<Event>,
     state: &mut TuiState,
 ) -> miette::Result<()> {
+    if let Some(variant_matrix) = state.variant_matrix.as_mut() {
+        if !handle_variant_key_events(key_event, sender, variant_matrix)? {
+            state.quit();
+        }
+        return Ok(());
+    }
     if state.input_mode {
         match key_event.code {
             KeyCode::Enter => sender.send(Event::HandleInput).into_diagnostic()?,
@@ -151,22 +206,13 @@ pub(crate) fn handle_mouse_events(
     Ok(())
 }
 
-/// Renders the user interface widgets.
-pub(crate) fn render_widgets(state: &mut TuiState, frame: &mut Frame) {
-    frame.render_widget(
-        Block::new()
-            .title_top(Line::from("rattler-build-tui").style(Style::default().bold()))
-            .title_alignment(Alignment::Center),
-        frame.area(),
-    );
-    let rects = Layout::vertical([Constraint::Percentage(100), Constraint::Min(3)])
-        .margin(1)
-        .split(frame.area());
+/// Renders the key bindings bar.
+fn render_key_bindings(frame: &mut Frame, key_bindings: &[(&str, &str)], area: Rect) {
     frame.render_widget(
         Paragraph::new(
             Line::default()
                 .spans(
-                    KEY_BINDINGS
+                    key_bindings
                         .iter()
                         .flat_map(|(key, desc)| {
                             vec![
@@ -188,8 +234,87 @@ pub(crate) fn render_widgets(state: &mut TuiState, frame: &mut Frame) {
                 .border_type(BorderType::Rounded)
                 .border_style(Style::default().fg(Color::Rgb(100, 100, 100))),
         ),
-        rects[1],
+        area,
+    );
+}
+
+/// Renders the variant pre-flight screen.
+fn render_variant_matrix(frame: &mut Frame, variant_matrix: &VariantMatrix, area: Rect) {
+    let rows = variant_matrix
+        .entries
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            let checkbox = if entry.enabled { "[x]" } else { "[ ]" };
+            let mut style = if entry.enabled {
+                Style::default()
+            } else {
+                Style::default().fg(Color::Rgb(100, 100, 100))
+            };
+            if i == variant_matrix.selected {
+                style = style.yellow().bold();
+            }
+            Row::new(vec![
+                Cell::from(format!("{:>3}", i + 1)),
+                Cell::from(checkbox),
+                Cell::from(entry.identifier()),
+                Cell::from(entry.variant_description()),
+            ])
+            .style(style)
+        })
+        .collect::<Vec<Row>>();
+
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(4),
+            Constraint::Length(3),
+            Constraint::Percentage(40),
+            Constraint::Fill(1),
+        ],
+    )
+    .header(
+        Row::new(vec!["#", "", "Package", "Variant"])
+            .style(Style::default().bold())
+            .bottom_margin(1),
+    )
+    .column_spacing(2)
+    .block(
+        Block::bordered()
+            .title_top(
+                format!(
+                    "|Variants ({}/{} selected)|",
+                    variant_matrix.enabled_count(),
+                    variant_matrix.entries.len()
+                )
+                .yellow(),
+            )
+            .title_alignment(Alignment::Center)
+            .border_type(BorderType::Rounded)
+            .border_style(Style::default().fg(Color::Rgb(100, 100, 100))),
+    );
+
+    let mut table_state = TableState::default().with_selected(Some(variant_matrix.selected));
+    frame.render_stateful_widget(table, area, &mut table_state);
+}
+
+/// Renders the user interface widgets.
+pub(crate) fn render_widgets(state: &mut TuiState, frame: &mut Frame) {
+    frame.render_widget(
+        Block::new()
+            .title_top(Line::from("rattler-build-tui").style(Style::default().bold()))
+            .title_alignment(Alignment::Center),
+        frame.area(),
     );
+    let rects = Layout::vertical([Constraint::Percentage(100), Constraint::Min(3)])
+        .margin(1)
+        .split(frame.area());
+    if let Some(variant_matrix) = &state.variant_matrix {
+        render_key_bindings(frame, VARIANT_KEY_BINDINGS, rects[1]);
+        render_variant_matrix(frame, variant_matrix, rects[0]);
+        return;
+    }
+    render_key_bindings(frame, KEY_BINDINGS, rects[1]);
     frame.render_widget(
         Block::new()
             .title_top(Line::from("rattler-build-tui").style(Style::default().bold()))
@@ -6,7 +6,7 @@ use tui_input::Input;
 
 use crate::{
     console_utils::LoggingOutputHandler, get_tool_config, metadata::Output,
-    tool_configuration::Configuration, BuildData,
+    recipe::parser::Dependency, tool_configuration::Configuration, BuildData,
 };
 
 /// Representation of a package.
@@ -80,6 +80,151 @@ impl BuildProgress {
     }
 }
 
+/// A discovered variant in the pre-flight screen.
+#[derive(Clone)]
+pub struct VariantEntry {
+    pub output: Output,
+    pub enabled: bool,
+}
+
+impl VariantEntry {
+    /// Returns the package identifier of the variant.
+    pub fn identifier(&self) -> String {
+        self.output.identifier()
+    }
+
+    /// Returns the used variables of the variant as `key=value` pairs.
+    pub fn variant_description(&self) -> String {
+        self.output
+            .variant()
+            .iter()
+            .map(|(key, value)| format!("{}={}", key.normalize(), value))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Returns true if the variant depends on the package of `other` in its
+    /// build, host or run requirements, i.e. it has to be built after `other`.
+    pub fn depends_on(&self, other: &VariantEntry) -> bool {
+        let name = other.output.name();
+        if self.output.name() == name {
+            return false;
+        }
+        self.output
+            .recipe
+            .requirements()
+            .run_build_host()
+            .any(|dep| match dep {
+                Dependency::Spec(spec) => spec.name.as_ref() == Some(name),
+                Dependency::PinSubpackage(pin) => &pin.pin_value().name == name,
+                Dependency::PinCompatible(pin) => &pin.pin_value().name == name,
+            })
+    }
+}
+
+/// Pre-flight selection of the discovered variants.
+#[derive(Clone, Default)]
+pub struct VariantMatrix {
+    /// All discovered variants in build order.
+    pub entries: Vec<VariantEntry>,
+    /// Index of the highlighted variant.
+    pub selected: usize,
+}
+
+impl VariantMatrix {
+    /// Constructs a new matrix with all variants enabled.
+    pub fn new(outputs: Vec<Output>) -> Self {
+        Self {
+            entries: outputs
+                .into_iter()
+                .map(|output| VariantEntry {
+                    output,
+                    enabled: true,
+                })
+                .collect(),
+            selected: 0,
+        }
+    }
+
+    /// Highlights the next variant.
+    pub fn select_next(&mut self) {
+        if !self.entries.is_empty() {
+            self.selected = (self.selected + 1) % self.entries.len();
+        }
+    }
+
+    /// Highlights the previous variant.
+    pub fn select_previous(&mut self) {
+        if !self.entries.is_empty() {
+            self.selected = self
+                .selected
+                .checked_sub(1)
+                .unwrap_or(self.entries.len() - 1);
+        }
+    }
+
+    /// Toggles the highlighted variant on/off.
+    pub fn toggle_selected(&mut self) {
+        if let Some(entry) = self.entries.get_mut(self.selected) {
+            entry.enabled = !entry.enabled;
+        }
+    }
+
+    /// Enables all variants, or disables all of them if they are all enabled.
+    pub fn toggle_all(&mut self) {
+        let enable = !self.entries.iter().all(|entry| entry.enabled);
+        self.entries
+            .iter_mut()
+            .for_each(|entry| entry.enabled = enable);
+    }
+
+    /// Moves the highlighted variant one position up in the build order.
+    /// Returns false (and does not move it) if the variant depends on the
+    /// variant above it.
+    pub fn move_selected_up(&mut self) -> bool {
+        if self.selected == 0 {
+            return true;
+        }
+        let (above, selected) = (self.selected - 1, self.selected);
+        if self.entries[selected].depends_on(&self.entries[above]) {
+            return false;
+        }
+        self.entries.swap(selected, above);
+        self.selected = above;
+        true
+    }
+
+    /// Moves the highlighted variant one position down in the build order.
+    /// Returns false (and does not move it) if the variant below depends on
+    /// it.
+    pub fn move_selected_down(&mut self) -> bool {
+        if self.selected + 1 >= self.entries.len() {
+            return true;
+        }
+        let (selected, below) = (self.selected, self.selected + 1);
+        if self.entries[below].depends_on(&self.entries[selected]) {
+            return false;
+        }
+        self.entries.swap(selected, below);
+        self.selected = below;
+        true
+    }
+
+    /// Returns the number of enabled variants.
+    pub fn enabled_count(&self) -> usize {
+        self.entries.iter().filter(|entry| entry.enabled).count()
+    }
+
+    /// Consumes the matrix and returns the enabled outputs in the selected order.
+    pub fn into_enabled_outputs(self) -> Vec<Output> {
+        self.entries
+            .into_iter()
+            .filter(|entry| entry.enabled)
+            .map(|entry| entry.output)
+            .collect()
+    }
+}
+
 /// Application state.
 #[derive(Clone)]
 pub(crate) struct TuiState {
@@ -105,6 +250,8 @@ pub(crate) struct TuiState {
     pub input_mode: bool,
     /// Current value of the prompt input.
     pub input: Input,
+    /// Discovered variants awaiting confirmation before building.
+    pub variant_matrix: Option<VariantMatrix>,
 }
 
 impl TuiState {
@@ -123,6 +270,7 @@ impl TuiState {
             input_mode: false,
             build_queue: None,
             input: Input::default(),
+            variant_matrix: None,
         }
     }
 
@@ -145,3 +293,53 @@ impl TuiState {
         self.packages.iter().any(|p| p.build_progress.is_building())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An output of the package `name` that depends on `run` at runtime
+    fn output(name: &str, run: &[&str]) -> Output {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("test-data/rendered_recipes/curl_recipe.yaml");
+        let mut output: serde_yaml::Value =
+            serde_yaml::from_str(&fs_err::read_to_string(path).unwrap()).unwrap();
+        output["recipe"]["package"]["name"] = name.into();
+        output["recipe"]["requirements"] = serde_yaml::from_str(&format!("run: {run:?}")).unwrap();
+        serde_yaml::from_value(output).unwrap()
+    }
+
+    fn names(variant_matrix: &VariantMatrix) -> Vec<String> {
+        variant_matrix
+            .entries
+            .iter()
+            .map(|entry| entry.output.name().as_normalized().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_move_respects_dependencies() {
+        let mut variant_matrix = VariantMatrix::new(vec![
+            output("libfoo", &[]),
+            output("foo", &["libfoo >=1"]),
+            output("bar", &[]),
+        ]);
+
+        // `foo` cannot be built before `libfoo`
+        variant_matrix.selected = 1;
+        assert!(!variant_matrix.move_selected_up());
+        assert_eq!(variant_matrix.selected, 1);
+        variant_matrix.selected = 0;
+        assert!(!variant_matrix.move_selected_down());
+        assert_eq!(names(&variant_matrix), ["libfoo", "foo", "bar"]);
+
+        // `bar` is independent of the other variants
+        variant_matrix.selected = 2;
+        assert!(variant_matrix.move_selected_up());
+        assert!(variant_matrix.move_selected_up());
+        assert_eq!(variant_matrix.selected, 0);
+        assert_eq!(names(&variant_matrix), ["bar", "libfoo", "foo"]);
+        assert!(variant_matrix.move_selected_down());
+        assert_eq!(names(&variant_matrix), ["libfoo", "bar", "foo"]);
+    }
+}
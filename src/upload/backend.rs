@@ -0,0 +1,614 @@
+//! Pluggable upload backends.
+//!
+//! Every server type implements the [`UploadBackend`] trait. Additional
+//! backends can be registered from library code with
+//! [`register_upload_backend`] and are then available on the command line with
+//! `rattler-build upload --to <URL> --backend <NAME>`. Channels in GCS
+//! (`gs://`) and Azure (`az://`) buckets use the `gcs` and `azure` backends,
+//! channels in a local directory (`file://`) the `file` backend.
+
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+};
+
+use futures::future::BoxFuture;
+use miette::IntoDiagnostic;
+use rattler_networking::{Authentication, AuthenticationStorage};
+use reqwest::Method;
+use tracing::{info, warn};
+use url::Url;
+
+use super::{
+    bucket_channel::{BucketChannelBackend, ObjectStore, AZURE_BACKEND, GCS_BACKEND},
+    file_channel::{FileChannelBackend, FILE_BACKEND},
+    get_client_with_retry,
+    package::ExtractedPackage,
+    plan::UploadDestination,
+    send_request,
+};
+use crate::{
+    opt::{CondaForgeOpts, ServerType},
+    signing::SigningKey,
+    url_with_trailing_slash::UrlWithTrailingSlash,
+};
+
+/// A server that packages can be uploaded to.
+pub trait UploadBackend: Send + Sync {
+    /// The name of the backend (e.g. `quetz`).
+    fn name(&self) -> &str;
+
+    /// Upload the package files. Credentials that are not part of the backend
+    /// configuration are read from the authentication storage.
+    fn upload<'a>(
+        &'a self,
+        storage: &'a AuthenticationStorage,
+        package_files: &'a [PathBuf],
+    ) -> BoxFuture<'a, miette::Result<()>>;
+
+    /// Sign the records of the uploaded packages with the key (conda content
+    /// trust). Only backends that write the repodata of the channel
+    /// themselves can sign, servers that index the packages compute the
+    /// repodata (and its signatures) on their own.
+    fn set_signing_key(&mut self, key: SigningKey) -> miette::Result<()> {
+        let _ = key;
+        Err(miette::miette!(
+            "The `{}` backend does not support `--sign-key`, the server computes the repodata (and its signatures) itself",
+            self.name()
+        ))
+    }
+
+    /// Where the packages are uploaded to. Used by `upload --dry-run` to show
+    /// the upload plan and to check for packages that already exist.
+    fn destination(&self) -> UploadDestination {
+        UploadDestination::new(format!("{} backend", self.name()))
+    }
+}
+
+/// Creates an upload backend for the URL that is passed with `--to`.
+pub type UploadBackendFactory =
+    Arc<dyn Fn(Url) -> miette::Result<Box<dyn UploadBackend>> + Send + Sync>;
+
+/// The name of the built-in backend that uploads with plain HTTP `PUT`
+/// requests.
+pub const GENERIC_PUT_BACKEND: &str = "generic-put";
+
+lazy_static::lazy_static! {
+    static ref UPLOAD_BACKENDS: RwLock<BTreeMap<String, UploadBackendFactory>> = {
+        let mut backends = BTreeMap::new();
+        let generic_put: UploadBackendFactory = Arc::new(|url: Url| {
+            Ok(Box::new(GenericPutBackend { url: url.into() }) as Box<dyn UploadBackend>)
+        });
+        backends.insert(GENERIC_PUT_BACKEND.to_string(), generic_put);
+        let gcs: UploadBackendFactory = Arc::new(|url: Url| {
+            Ok(Box::new(BucketChannelBackend::from_url(ObjectStore::Gcs, &url)?)
+                as Box<dyn UploadBackend>)
+        });
+        backends.insert(GCS_BACKEND.to_string(), gcs);
+        let azure: UploadBackendFactory = Arc::new(|url: Url| {
+            Ok(Box::new(BucketChannelBackend::from_url(ObjectStore::Azure, &url)?)
+                as Box<dyn UploadBackend>)
+        });
+        backends.insert(AZURE_BACKEND.to_string(), azure);
+        let file: UploadBackendFactory = Arc::new(|url: Url| {
+            Ok(Box::new(FileChannelBackend::from_url(&url)?) as Box<dyn UploadBackend>)
+        });
+        backends.insert(FILE_BACKEND.to_string(), file);
+        RwLock::new(backends)
+    };
+}
+
+/// Register a custom upload backend under the given name. An existing backend
+/// with the same name is replaced.
+pub fn register_upload_backend(
+    name: impl Into<String>,
+    factory: impl Fn(Url) -> miette::Result<Box<dyn UploadBackend>> + Send + Sync + 'static,
+) {
+    UPLOAD_BACKENDS
+        .write()
+        .expect("upload backend registry is poisoned")
+        .insert(name.into(), Arc::new(factory));
+}
+
+/// The names of all registered upload backends.
+pub fn upload_backend_names() -> Vec<String> {
+    UPLOAD_BACKENDS
+        .read()
+        .expect("upload backend registry is poisoned")
+        .keys()
+        .cloned()
+        .collect()
+}
+
+/// The backend that is used for a `--to` URL when no `--backend` is given:
+/// `gcs` for `gs://`, `azure` for `az://`, `file` for `file://` and
+/// `generic-put` otherwise.
+pub fn default_backend_for_url(url: &Url) -> &'static str {
+    match url.scheme() {
+        "gs" => GCS_BACKEND,
+        "az" => AZURE_BACKEND,
+        "file" => FILE_BACKEND,
+        _ => GENERIC_PUT_BACKEND,
+    }
+}
+
+/// Create the registered upload backend with the given name for the URL.
+pub fn create_upload_backend(name: &str, url: Url) -> miette::Result<Box<dyn UploadBackend>> {
+    let factory = UPLOAD_BACKENDS
+        .read()
+        .expect("upload backend registry is poisoned")
+        .get(name)
+        .cloned();
+    match factory {
+        Some(factory) => factory(url),
+        None => Err(miette::miette!(
+            "Unknown upload backend `{}` (available backends: {})",
+            name,
+            upload_backend_names().join(", ")
+        )),
+    }
+}
+
+/// Create the backend for a server type given on the command line.
+pub fn backend_from_server_type(server_type: ServerType) -> miette::Result<Box<dyn UploadBackend>> {
+    Ok(match server_type {
+        ServerType::Quetz(opts) => Box::new(QuetzBackend {
+            url: opts.url.into(),
+            channel: opts.channel,
+            api_key: opts.api_key,
+        }),
+        ServerType::Artifactory(opts) => {
+            let token = match (opts.username, opts.password, opts.token) {
+                (_, _, Some(token)) => Some(token),
+                (Some(_), Some(password), _) => {
+                    warn!("Using username and password for Artifactory authentication is deprecated, using password as token. Please use an API token instead.");
+                    Some(password)
+                }
+                (Some(_), None, _) => {
+                    return Err(miette::miette!(
+                        "Artifactory username provided without a password"
+                    ));
+                }
+                (None, Some(_), _) => {
+                    return Err(miette::miette!(
+                        "Artifactory password provided without a username"
+                    ));
+                }
+                _ => None,
+            };
+            Box::new(ArtifactoryBackend {
+                url: opts.url.into(),
+                channel: opts.channel,
+                token,
+            })
+        }
+        ServerType::Prefix(opts) => Box::new(PrefixBackend {
+            url: opts.url.into(),
+            channel: opts.channel,
+            api_key: opts.api_key,
+        }),
+        ServerType::Anaconda(opts) => Box::new(AnacondaBackend {
+            url: opts.url.into(),
+            owner: opts.owner,
+            channels: opts.channel,
+            api_key: opts.api_key,
+            force: opts.force,
+        }),
+        ServerType::CondaForge(opts) => Box::new(CondaForgeBackend { opts }),
+    })
+}
+
+/// Uploads to a Quetz server.
+#[derive(Debug, Clone)]
+pub struct QuetzBackend {
+    /// The URL of the Quetz server
+    pub url: UrlWithTrailingSlash,
+    /// The channel to upload to
+    pub channel: String,
+    /// The API key (read from the authentication storage if not set)
+    pub api_key: Option<String>,
+}
+
+impl UploadBackend for QuetzBackend {
+    fn name(&self) -> &str {
+        "quetz"
+    }
+
+    fn destination(&self) -> UploadDestination {
+        UploadDestination {
+            description: format!("Quetz server {} (channel `{}`)", self.url, self.channel),
+            channel: self.url.join(&format!("get/{}/", self.channel)).ok(),
+            labels: Vec::new(),
+            rejects_duplicates: true,
+        }
+    }
+
+    fn upload<'a>(
+        &'a self,
+        storage: &'a AuthenticationStorage,
+        package_files: &'a [PathBuf],
+    ) -> BoxFuture<'a, miette::Result<()>> {
+        Box::pin(super::upload_package_to_quetz(
+            storage,
+            self.api_key.clone(),
+            package_files,
+            self.url.clone(),
+            self.channel.clone(),
+        ))
+    }
+}
+
+/// Uploads to an Artifactory server.
+#[derive(Debug, Clone)]
+pub struct ArtifactoryBackend {
+    /// The URL of the Artifactory server
+    pub url: UrlWithTrailingSlash,
+    /// The channel to upload to
+    pub channel: String,
+    /// The bearer token (read from the authentication storage if not set)
+    pub token: Option<String>,
+}
+
+impl UploadBackend for ArtifactoryBackend {
+    fn name(&self) -> &str {
+        "artifactory"
+    }
+
+    fn destination(&self) -> UploadDestination {
+        UploadDestination {
+            description: format!(
+                "Artifactory server {} (channel `{}`)",
+                self.url, self.channel
+            ),
+            channel: self.url.join(&format!("{}/", self.channel)).ok(),
+            labels: Vec::new(),
+            // Artifactory overwrites existing packages
+            rejects_duplicates: false,
+        }
+    }
+
+    fn upload<'a>(
+        &'a self,
+        storage: &'a AuthenticationStorage,
+        package_files: &'a [PathBuf],
+    ) -> BoxFuture<'a, miette::Result<()>> {
+        Box::pin(super::upload_package_to_artifactory(
+            storage,
+            self.token.clone(),
+            package_files,
+            self.url.clone(),
+            self.channel.clone(),
+        ))
+    }
+}
+
+/// Uploads to a prefix.dev server.
+#[derive(Debug, Clone)]
+pub struct PrefixBackend {
+    /// The URL of the prefix.dev server
+    pub url: UrlWithTrailingSlash,
+    /// The channel to upload to
+    pub channel: String,
+    /// The API key (trusted publishing or the authentication storage is used
+    /// if not set)
+    pub api_key: Option<String>,
+}
+
+impl UploadBackend for PrefixBackend {
+    fn name(&self) -> &str {
+        "prefix"
+    }
+
+    fn destination(&self) -> UploadDestination {
+        UploadDestination {
+            description: format!(
+                "prefix.dev server {} (channel `{}`)",
+                self.url, self.channel
+            ),
+            channel: self.url.join(&format!("{}/", self.channel)).ok(),
+            labels: Vec::new(),
+            rejects_duplicates: true,
+        }
+    }
+
+    fn upload<'a>(
+        &'a self,
+        storage: &'a AuthenticationStorage,
+        package_files: &'a [PathBuf],
+    ) -> BoxFuture<'a, miette::Result<()>> {
+        Box::pin(super::upload_package_to_prefix(
+            storage,
+            self.api_key.clone(),
+            package_files,
+            self.url.clone(),
+            self.channel.clone(),
+        ))
+    }
+}
+
+/// Uploads to an Anaconda.org server.
+#[derive(Debug, Clone)]
+pub struct AnacondaBackend {
+    /// The URL of the Anaconda API
+    pub url: UrlWithTrailingSlash,
+    /// The owner of the distribution
+    pub owner: String,
+    /// The labels to upload to (the `channel_targets` label if empty)
+    pub channels: Vec<String>,
+    /// The API key (read from the authentication storage if not set)
+    pub api_key: Option<String>,
+    /// Replace files on conflict
+    pub force: bool,
+}
+
+impl UploadBackend for AnacondaBackend {
+    fn name(&self) -> &str {
+        "anaconda"
+    }
+
+    fn destination(&self) -> UploadDestination {
+        // Only the channels of anaconda.org itself can be queried, for other
+        // servers the URL of the channel is unknown
+        let channel = if self.url.host_str() == Some("api.anaconda.org") {
+            // packages are checked against the first label
+            let label = match self.channels.first() {
+                Some(label) if label != "main" => format!("/label/{label}"),
+                _ => String::new(),
+            };
+            Url::parse(&format!(
+                "https://conda.anaconda.org/{}{}/",
+                self.owner, label
+            ))
+            .ok()
+        } else {
+            None
+        };
+        UploadDestination {
+            description: format!("Anaconda server {} (owner `{}`)", self.url, self.owner),
+            channel,
+            labels: self.channels.clone(),
+            rejects_duplicates: !self.force,
+        }
+    }
+
+    fn upload<'a>(
+        &'a self,
+        storage: &'a AuthenticationStorage,
+        package_files: &'a [PathBuf],
+    ) -> BoxFuture<'a, miette::Result<()>> {
+        Box::pin(super::upload_package_to_anaconda(
+            storage,
+            self.api_key.clone(),
+            package_files,
+            self.url.clone(),
+            self.owner.clone(),
+            self.channels.clone(),
+            self.force,
+        ))
+    }
+}
+
+/// Uploads to the conda-forge staging channel.
+#[derive(Debug, Clone)]
+pub struct CondaForgeBackend {
+    /// The conda-forge upload options
+    pub opts: CondaForgeOpts,
+}
+
+impl UploadBackend for CondaForgeBackend {
+    fn name(&self) -> &str {
+        "conda-forge"
+    }
+
+    fn destination(&self) -> UploadDestination {
+        UploadDestination {
+            description: format!(
+                "conda-forge staging channel `{}` on {}",
+                self.opts.staging_channel, self.opts.anaconda_url
+            ),
+            channel: Url::parse(&format!(
+                "https://conda.anaconda.org/{}/",
+                self.opts.staging_channel
+            ))
+            .ok(),
+            labels: Vec::new(),
+            rejects_duplicates: true,
+        }
+    }
+
+    fn upload<'a>(
+        &'a self,
+        _storage: &'a AuthenticationStorage,
+        package_files: &'a [PathBuf],
+    ) -> BoxFuture<'a, miette::Result<()>> {
+        Box::pin(super::conda_forge::upload_packages_to_conda_forge(
+            self.opts.clone(),
+            package_files,
+        ))
+    }
+}
+
+/// Uploads every package with a `PUT` request to `<url>/<subdir>/<filename>`.
+/// A bearer token or basic HTTP credentials are read from the authentication
+/// storage.
+#[derive(Debug, Clone)]
+pub struct GenericPutBackend {
+    /// The base URL of the channel
+    pub url: UrlWithTrailingSlash,
+}
+
+impl GenericPutBackend {
+    async fn upload_packages(
+        &self,
+        storage: &AuthenticationStorage,
+        package_files: &[PathBuf],
+    ) -> miette::Result<()> {
+        let authentication = match storage.get_by_url(Url::from(self.url.clone())) {
+            Ok((_, authentication)) => authentication,
+            Err(e) => {
+                return Err(miette::miette!(
+                    "Failed to get authentication information from keychain: {e}"
+                ));
+            }
+        };
+
+        let client = get_client_with_retry().into_diagnostic()?;
+
+        for package_file in package_files {
+            let package = ExtractedPackage::from_package_file(package_file)?;
+
+            let subdir = package.subdir().ok_or_else(|| {
+                miette::miette!(
+                    "index.json of package {} has no subdirectory. Cannot determine which directory to upload to",
+                    package_file.display()
+                )
+            })?;
+
+            let package_name = package.filename().ok_or(miette::miette!(
+                "Package file {} has no filename",
+                package_file.display()
+            ))?;
+
+            let upload_url = self
+                .url
+                .join(&format!("{}/{}", subdir, package_name))
+                .into_diagnostic()?;
+
+            let prepared_request = client.request(Method::PUT, upload_url);
+            let prepared_request = match &authentication {
+                Some(Authentication::BearerToken(token)) => prepared_request.bearer_auth(token),
+                Some(Authentication::BasicHTTP { username, password }) => {
+                    prepared_request.basic_auth(username, Some(password))
+                }
+                Some(_) => {
+                    warn!("Only bearer tokens and basic HTTP credentials are supported by the generic PUT backend, uploading without authentication");
+                    prepared_request
+                }
+                None => prepared_request,
+            };
+
+            send_request(prepared_request, package_file).await?;
+        }
+
+        info!("Packages successfully uploaded to {}", self.url);
+
+        Ok(())
+    }
+}
+
+impl UploadBackend for GenericPutBackend {
+    fn name(&self) -> &str {
+        GENERIC_PUT_BACKEND
+    }
+
+    fn destination(&self) -> UploadDestination {
+        UploadDestination {
+            description: format!("{} (HTTP PUT)", self.url),
+            channel: Some(self.url.clone().into()),
+            labels: Vec::new(),
+            rejects_duplicates: false,
+        }
+    }
+
+    fn upload<'a>(
+        &'a self,
+        storage: &'a AuthenticationStorage,
+        package_files: &'a [PathBuf],
+    ) -> BoxFuture<'a, miette::Result<()>> {
+        Box::pin(self.upload_packages(storage, package_files))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct NoopBackend;
+
+    impl UploadBackend for NoopBackend {
+        fn name(&self) -> &str {
+            "noop"
+        }
+
+        fn upload<'a>(
+            &'a self,
+            _storage: &'a AuthenticationStorage,
+            _package_files: &'a [PathBuf],
+        ) -> BoxFuture<'a, miette::Result<()>> {
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    #[test]
+    fn test_upload_backend_registry() {
+        let url = Url::parse("https://example.com/channel").unwrap();
+
+        let backend = create_upload_backend(GENERIC_PUT_BACKEND, url.clone()).unwrap();
+        assert_eq!(backend.name(), GENERIC_PUT_BACKEND);
+        assert_eq!(default_backend_for_url(&url), GENERIC_PUT_BACKEND);
+
+        let bucket = Url::parse("gs://my-bucket/channel").unwrap();
+        assert_eq!(default_backend_for_url(&bucket), GCS_BACKEND);
+        let backend = create_upload_backend(default_backend_for_url(&bucket), bucket).unwrap();
+        assert_eq!(backend.name(), GCS_BACKEND);
+
+        let dir = tempfile::tempdir().unwrap();
+        let local = Url::from_directory_path(dir.path()).unwrap();
+        assert_eq!(default_backend_for_url(&local), FILE_BACKEND);
+        let backend = create_upload_backend(default_backend_for_url(&local), local).unwrap();
+        assert_eq!(backend.name(), FILE_BACKEND);
+
+        assert!(create_upload_backend("noop", url.clone()).is_err());
+        register_upload_backend("noop", |_| Ok(Box::new(NoopBackend)));
+        let backend = create_upload_backend("noop", url).unwrap();
+        assert_eq!(backend.name(), "noop");
+        assert!(upload_backend_names().contains(&"noop".to_string()));
+    }
+
+    #[test]
+    fn test_upload_destination() {
+        assert_eq!(NoopBackend.destination().channel, None);
+
+        let quetz = QuetzBackend {
+            url: Url::parse("https://quetz.example.com").unwrap().into(),
+            channel: "my-channel".to_string(),
+            api_key: None,
+        };
+        let destination = quetz.destination();
+        assert_eq!(
+            destination.channel.unwrap().as_str(),
+            "https://quetz.example.com/get/my-channel/"
+        );
+        assert!(destination.rejects_duplicates);
+
+        let anaconda = AnacondaBackend {
+            url: Url::parse("https://api.anaconda.org").unwrap().into(),
+            owner: "me".to_string(),
+            channels: vec!["dev".to_string()],
+            api_key: None,
+            force: true,
+        };
+        let destination = anaconda.destination();
+        assert_eq!(
+            destination.channel.unwrap().as_str(),
+            "https://conda.anaconda.org/me/label/dev/"
+        );
+        assert_eq!(destination.labels, vec!["dev".to_string()]);
+        assert!(!destination.rejects_duplicates);
+    }
+
+    #[test]
+    fn test_signing_capability() {
+        let mut backend: Box<dyn UploadBackend> = Box::new(NoopBackend);
+        assert!(backend.set_signing_key(SigningKey::generate()).is_err());
+
+        let url = Url::parse("https://example.com/channel").unwrap();
+        let mut backend = create_upload_backend(GENERIC_PUT_BACKEND, url).unwrap();
+        assert!(backend.set_signing_key(SigningKey::generate()).is_err());
+
+        let bucket = Url::parse("gs://my-bucket/channel").unwrap();
+        let mut backend = create_upload_backend(GCS_BACKEND, bucket).unwrap();
+        assert!(backend.set_signing_key(SigningKey::generate()).is_ok());
+    }
+}
@@ -0,0 +1,900 @@
+//! Publishing to channels in Google Cloud Storage (`gs://bucket/channel`) and
+//! Azure Blob Storage (`az://account/container/channel`) buckets.
+//!
+//! The packages are uploaded to `<channel>/<subdir>/<filename>` and the
+//! `repodata.json` of every touched subdirectory is updated in place with
+//! the object store APIs (conditional writes make concurrent uploads safe). A
+//! new channel is initialized with an empty `noarch/repodata.json`. Uploaded
+//! and indexed packages are recorded in an [`UploadJournal`], so that a failed
+//! upload can be resumed. With a signing key, the records of the uploaded
+//! packages are signed for conda content trust.
+//!
+//! Credentials are resolved in the following order:
+//!
+//! * GCS: the `GOOGLE_OAUTH_ACCESS_TOKEN` environment variable, a bearer token
+//!   for `storage.googleapis.com` in the keychain / auth file, or
+//!   `gcloud auth print-access-token`. `STORAGE_EMULATOR_HOST` selects a
+//!   different endpoint.
+//! * Azure: the `AZURE_STORAGE_CONNECTION_STRING`, `AZURE_STORAGE_SAS_TOKEN`
+//!   or `AZURE_STORAGE_KEY` environment variables, a bearer token for the
+//!   blob endpoint in the keychain / auth file, or
+//!   `az account get-access-token`.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use futures::future::BoxFuture;
+use hmac::{Hmac, Mac};
+use miette::{Context, IntoDiagnostic};
+use rattler_conda_types::{package::ArchiveType, PackageRecord};
+use rattler_digest::{compute_file_digest, Md5, Sha256};
+use rattler_networking::{Authentication, AuthenticationStorage};
+use rattler_redaction::Redact;
+use reqwest::{header, Method, StatusCode};
+use reqwest_middleware::{ClientWithMiddleware, RequestBuilder};
+use tracing::{debug, info};
+use url::Url;
+
+use super::{
+    backend::UploadBackend, get_client_with_retry, journal::UploadJournal,
+    package::ExtractedPackage, plan::UploadDestination, send_request,
+};
+use crate::signing::{self, SigningKey};
+
+/// The name of the backend that uploads to `gs://` channels.
+pub const GCS_BACKEND: &str = "gcs";
+
+/// The name of the backend that uploads to `az://` channels.
+pub const AZURE_BACKEND: &str = "azure";
+
+const AZURE_API_VERSION: &str = "2021-08-06";
+const GCS_ENDPOINT: &str = "https://storage.googleapis.com";
+
+/// How often the read-modify-write of a `repodata.json` is retried when
+/// another upload changed it concurrently.
+const MAX_REPODATA_ATTEMPTS: usize = 5;
+
+/// The object store that hosts a bucket channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectStore {
+    /// Google Cloud Storage (`gs://`)
+    Gcs,
+    /// Azure Blob Storage (`az://`)
+    Azure,
+}
+
+/// Credentials for requests to the object store.
+#[derive(Clone)]
+enum Credentials {
+    Bearer(String),
+    AzureSharedKey { account: String, key: Vec<u8> },
+    AzureSas(String),
+    Anonymous,
+}
+
+/// Settings from an `AZURE_STORAGE_CONNECTION_STRING`.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct AzureConnectionString {
+    account_name: Option<String>,
+    account_key: Option<String>,
+    blob_endpoint: Option<String>,
+    sas: Option<String>,
+}
+
+impl AzureConnectionString {
+    fn parse(s: &str) -> Self {
+        let mut result = AzureConnectionString::default();
+        for part in s.split(';').filter(|part| !part.trim().is_empty()) {
+            let Some((key, value)) = part.split_once('=') else {
+                continue;
+            };
+            let value = Some(value.trim().to_string());
+            match key.trim() {
+                "AccountName" => result.account_name = value,
+                "AccountKey" => result.account_key = value,
+                "BlobEndpoint" => result.blob_endpoint = value,
+                "SharedAccessSignature" => result.sas = value,
+                _ => {}
+            }
+        }
+        result
+    }
+
+    fn from_env() -> Option<Self> {
+        std::env::var("AZURE_STORAGE_CONNECTION_STRING")
+            .ok()
+            .map(|s| Self::parse(&s))
+    }
+}
+
+/// Uploads packages to a channel in a GCS or Azure bucket and updates its
+/// repodata.
+#[derive(Debug, Clone)]
+pub struct BucketChannelBackend {
+    store: ObjectStore,
+    /// The storage account (Azure only)
+    account: Option<String>,
+    /// The https url of the channel root (with a trailing slash)
+    base: Url,
+    /// The key to sign the records of the uploaded packages with
+    signing_key: Option<Arc<SigningKey>>,
+}
+
+/// The precondition for writing an object that was read before.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Precondition {
+    /// The object must not exist yet
+    Missing,
+    /// The GCS generation of the object
+    Generation(String),
+    /// The Azure ETag of the object
+    ETag(String),
+}
+
+/// A new package in the repodata of a subdirectory.
+struct UploadedPackage {
+    filename: String,
+    archive_type: ArchiveType,
+    record: PackageRecord,
+}
+
+impl BucketChannelBackend {
+    /// Create the backend for a `gs://bucket/channel` or
+    /// `az://account/container/channel` url.
+    pub fn from_url(store: ObjectStore, url: &Url) -> miette::Result<Self> {
+        let expected_scheme = match store {
+            ObjectStore::Gcs => "gs",
+            ObjectStore::Azure => "az",
+        };
+        if url.scheme() != expected_scheme {
+            return Err(miette::miette!(
+                "The {} backend requires a `{}://` url, got {}",
+                backend_name(store),
+                expected_scheme,
+                url
+            ));
+        }
+
+        let host = url
+            .host_str()
+            .filter(|host| !host.is_empty())
+            .ok_or_else(|| miette::miette!("No bucket or account name in url: {url}"))?;
+        let path = url.path().trim_matches('/');
+
+        let (account, base) = match store {
+            ObjectStore::Gcs => {
+                let endpoint = match std::env::var("STORAGE_EMULATOR_HOST") {
+                    Ok(host) if host.contains("://") => host,
+                    Ok(host) => format!("http://{host}"),
+                    Err(_) => GCS_ENDPOINT.to_string(),
+                };
+                (
+                    None,
+                    format!("{}/{host}/{path}", endpoint.trim_end_matches('/')),
+                )
+            }
+            ObjectStore::Azure => {
+                if path.is_empty() {
+                    return Err(miette::miette!(
+                        "No container in url: {url} (expected `az://account/container/channel`)"
+                    ));
+                }
+                let endpoint = AzureConnectionString::from_env()
+                    .and_then(|c| c.blob_endpoint)
+                    .unwrap_or_else(|| format!("https://{host}.blob.core.windows.net"));
+                (
+                    Some(host.to_string()),
+                    format!("{}/{path}", endpoint.trim_end_matches('/')),
+                )
+            }
+        };
+
+        let base = Url::parse(&format!("{}/", base.trim_end_matches('/')))
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Invalid bucket url: {url}"))?;
+
+        Ok(Self {
+            store,
+            account,
+            base,
+            signing_key: None,
+        })
+    }
+
+    fn object_url(&self, key: &str) -> miette::Result<Url> {
+        self.base.join(key).into_diagnostic()
+    }
+
+    /// Resolve the credentials from the environment, the authentication storage
+    /// or the CLI of the cloud provider.
+    async fn credentials(&self, storage: &AuthenticationStorage) -> miette::Result<Credentials> {
+        let stored_bearer = || match storage.get_by_url(self.base.clone()) {
+            Ok((_, Some(Authentication::BearerToken(token)))) => Some(token),
+            _ => None,
+        };
+
+        match self.store {
+            ObjectStore::Gcs => {
+                if let Ok(token) = std::env::var("GOOGLE_OAUTH_ACCESS_TOKEN") {
+                    return Ok(Credentials::Bearer(token));
+                }
+                if let Some(token) = stored_bearer() {
+                    return Ok(Credentials::Bearer(token));
+                }
+                if let Some(token) = cli_token("gcloud", &["auth", "print-access-token"]).await {
+                    return Ok(Credentials::Bearer(token));
+                }
+                if std::env::var("STORAGE_EMULATOR_HOST").is_ok() {
+                    return Ok(Credentials::Anonymous);
+                }
+                Err(miette::miette!(
+                    "No credentials for Google Cloud Storage found. Set `GOOGLE_OAUTH_ACCESS_TOKEN`, add a token for storage.googleapis.com with `rattler-build auth login` or log in with `gcloud auth login`"
+                ))
+            }
+            ObjectStore::Azure => {
+                let account = self.account.clone().unwrap_or_default();
+                if let Some(connection_string) = AzureConnectionString::from_env() {
+                    if let Some(sas) = connection_string.sas {
+                        return Ok(Credentials::AzureSas(sas));
+                    }
+                    if let Some(key) = connection_string.account_key {
+                        return Ok(Credentials::AzureSharedKey {
+                            account: connection_string.account_name.unwrap_or(account),
+                            key: STANDARD.decode(key).into_diagnostic().wrap_err(
+                                "The AccountKey in AZURE_STORAGE_CONNECTION_STRING is not valid base64",
+                            )?,
+                        });
+                    }
+                }
+                if let Ok(sas) = std::env::var("AZURE_STORAGE_SAS_TOKEN") {
+                    return Ok(Credentials::AzureSas(sas));
+                }
+                if let Ok(key) = std::env::var("AZURE_STORAGE_KEY") {
+                    return Ok(Credentials::AzureSharedKey {
+                        account,
+                        key: STANDARD
+                            .decode(key)
+                            .into_diagnostic()
+                            .wrap_err("AZURE_STORAGE_KEY is not valid base64")?,
+                    });
+                }
+                if let Some(token) = stored_bearer() {
+                    return Ok(Credentials::Bearer(token));
+                }
+                if let Some(token) = cli_token(
+                    "az",
+                    &[
+                        "account",
+                        "get-access-token",
+                        "--resource",
+                        "https://storage.azure.com/",
+                        "--query",
+                        "accessToken",
+                        "--output",
+                        "tsv",
+                    ],
+                )
+                .await
+                {
+                    return Ok(Credentials::Bearer(token));
+                }
+                Err(miette::miette!(
+                    "No credentials for Azure Blob Storage found. Set `AZURE_STORAGE_CONNECTION_STRING`, `AZURE_STORAGE_SAS_TOKEN` or `AZURE_STORAGE_KEY`, add a token for the blob endpoint with `rattler-build auth login` or log in with `az login`"
+                ))
+            }
+        }
+    }
+
+    /// Build an authenticated request. `headers` must use lowercase names.
+    fn request(
+        &self,
+        client: &ClientWithMiddleware,
+        credentials: &Credentials,
+        method: Method,
+        mut url: Url,
+        mut headers: BTreeMap<String, String>,
+    ) -> RequestBuilder {
+        if self.store == ObjectStore::Azure {
+            headers.insert(
+                "x-ms-date".to_string(),
+                chrono::Utc::now()
+                    .format("%a, %d %b %Y %H:%M:%S GMT")
+                    .to_string(),
+            );
+            headers.insert("x-ms-version".to_string(), AZURE_API_VERSION.to_string());
+        }
+
+        let authorization = match credentials {
+            Credentials::Bearer(token) => Some(format!("Bearer {token}")),
+            Credentials::AzureSharedKey { account, key } => {
+                let string_to_sign = azure_string_to_sign(method.as_str(), &url, account, &headers);
+                let mut mac =
+                    Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+                mac.update(string_to_sign.as_bytes());
+                let signature = STANDARD.encode(mac.finalize().into_bytes());
+                Some(format!("SharedKey {account}:{signature}"))
+            }
+            Credentials::AzureSas(sas) => {
+                url.set_query(Some(sas.trim_start_matches('?')));
+                None
+            }
+            Credentials::Anonymous => None,
+        };
+
+        let mut request = client.request(method, url);
+        for (name, value) in headers {
+            request = request.header(name, value);
+        }
+        if let Some(authorization) = authorization {
+            request = request.header(header::AUTHORIZATION, authorization);
+        }
+        request
+    }
+
+    /// Read an object. Returns `None` if the object does not exist.
+    async fn get_object(
+        &self,
+        client: &ClientWithMiddleware,
+        credentials: &Credentials,
+        key: &str,
+    ) -> miette::Result<Option<(Vec<u8>, Precondition)>> {
+        let url = self.object_url(key)?;
+        let response = self
+            .request(client, credentials, Method::GET, url, BTreeMap::new())
+            .send()
+            .await
+            .map_err(|e| e.redact())
+            .into_diagnostic()?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let response = response
+            .error_for_status()
+            .map_err(|e| e.redact())
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Failed to read {key} from the bucket"))?;
+
+        let header_value = |name: &str| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        let precondition = match self.store {
+            ObjectStore::Gcs => header_value("x-goog-generation").map(Precondition::Generation),
+            ObjectStore::Azure => header_value("etag").map(Precondition::ETag),
+        }
+        .ok_or_else(|| miette::miette!("The object store did not return a version for {key}"))?;
+
+        let body = response.bytes().await.into_diagnostic()?;
+        Ok(Some((body.to_vec(), precondition)))
+    }
+
+    /// Returns true if the object exists.
+    async fn object_exists(
+        &self,
+        client: &ClientWithMiddleware,
+        credentials: &Credentials,
+        key: &str,
+    ) -> miette::Result<bool> {
+        let url = self.object_url(key)?;
+        let response = self
+            .request(client, credentials, Method::HEAD, url, BTreeMap::new())
+            .send()
+            .await
+            .map_err(|e| e.redact())
+            .into_diagnostic()?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(false);
+        }
+        response
+            .error_for_status()
+            .map_err(|e| e.redact())
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Failed to check for {key} in the bucket"))?;
+        Ok(true)
+    }
+
+    /// Write an object if the precondition still holds. Returns `false` if the
+    /// object was changed concurrently.
+    async fn put_object_if(
+        &self,
+        client: &ClientWithMiddleware,
+        credentials: &Credentials,
+        key: &str,
+        content: Vec<u8>,
+        precondition: &Precondition,
+    ) -> miette::Result<bool> {
+        let mut headers = self.put_headers(content.len() as u64, "application/json");
+        match (self.store, precondition) {
+            (ObjectStore::Gcs, Precondition::Missing) => {
+                headers.insert("x-goog-if-generation-match".to_string(), "0".to_string());
+            }
+            (ObjectStore::Gcs, Precondition::Generation(generation)) => {
+                headers.insert("x-goog-if-generation-match".to_string(), generation.clone());
+            }
+            (ObjectStore::Azure, Precondition::Missing) => {
+                headers.insert("if-none-match".to_string(), "*".to_string());
+            }
+            (ObjectStore::Azure, Precondition::ETag(etag)) => {
+                headers.insert("if-match".to_string(), etag.clone());
+            }
+            (store, precondition) => {
+                unreachable!("precondition {precondition:?} does not belong to {store:?}")
+            }
+        }
+
+        let url = self.object_url(key)?;
+        let response = self
+            .request(client, credentials, Method::PUT, url, headers)
+            .body(content)
+            .send()
+            .await
+            .map_err(|e| e.redact())
+            .into_diagnostic()?;
+
+        if matches!(
+            response.status(),
+            StatusCode::PRECONDITION_FAILED | StatusCode::CONFLICT
+        ) {
+            return Ok(false);
+        }
+        response
+            .error_for_status()
+            .map_err(|e| e.redact())
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Failed to write {key} to the bucket"))?;
+        Ok(true)
+    }
+
+    fn put_headers(&self, content_length: u64, content_type: &str) -> BTreeMap<String, String> {
+        let mut headers = BTreeMap::from([
+            ("content-length".to_string(), content_length.to_string()),
+            ("content-type".to_string(), content_type.to_string()),
+        ]);
+        if self.store == ObjectStore::Azure {
+            headers.insert("x-ms-blob-type".to_string(), "BlockBlob".to_string());
+        }
+        headers
+    }
+
+    /// Add the packages to the `repodata.json` of the subdirectory (which is
+    /// created if it does not exist yet).
+    async fn update_repodata(
+        &self,
+        client: &ClientWithMiddleware,
+        credentials: &Credentials,
+        subdir: &str,
+        packages: &[UploadedPackage],
+    ) -> miette::Result<()> {
+        let key = format!("{subdir}/repodata.json");
+
+        for attempt in 1..=MAX_REPODATA_ATTEMPTS {
+            let (mut repodata, precondition) =
+                match self.get_object(client, credentials, &key).await? {
+                    Some((content, precondition)) => (
+                        serde_json::from_slice(&content)
+                            .into_diagnostic()
+                            .wrap_err_with(|| format!("Failed to parse {key}"))?,
+                        precondition,
+                    ),
+                    None => (empty_repodata(subdir), Precondition::Missing),
+                };
+
+            for package in packages {
+                add_to_repodata(&mut repodata, package, self.signing_key.as_deref())?;
+            }
+
+            let content = serde_json::to_vec(&repodata).into_diagnostic()?;
+            if self
+                .put_object_if(client, credentials, &key, content, &precondition)
+                .await?
+            {
+                debug!("Updated {}", key);
+                return Ok(());
+            }
+            debug!(
+                "{} was changed concurrently, retrying ({}/{})",
+                key, attempt, MAX_REPODATA_ATTEMPTS
+            );
+        }
+
+        Err(miette::miette!(
+            "Failed to update {key}: it was changed concurrently {MAX_REPODATA_ATTEMPTS} times"
+        ))
+    }
+
+    async fn upload_packages(
+        &self,
+        storage: &AuthenticationStorage,
+        package_files: &[PathBuf],
+    ) -> miette::Result<()> {
+        let credentials = self.credentials(storage).await?;
+        let client = get_client_with_retry().into_diagnostic()?;
+
+        // packages that were uploaded by an earlier (failed) run are not
+        // uploaded again, and subdirectories whose repodata already contains
+        // them are not updated again
+        let mut journal = UploadJournal::load(&UploadJournal::default_folder()?, &self.base)?;
+
+        let mut subdirs: BTreeMap<String, Vec<UploadedPackage>> = BTreeMap::new();
+        for package_file in package_files {
+            let (subdir, package) = uploaded_package(package_file)?;
+            let sha256 = package
+                .record
+                .sha256
+                .map(|hash| format!("{hash:x}"))
+                .unwrap_or_default();
+
+            // the journal is only trusted if the package is still in the
+            // bucket (e.g. it was not removed by a lifecycle rule)
+            let key = format!("{subdir}/{}", package.filename);
+            let indexed = match journal.get(&package.filename, &sha256) {
+                Some(entry) => {
+                    let indexed = entry.indexed;
+                    if self.object_exists(&client, &credentials, &key).await? {
+                        Some(indexed)
+                    } else {
+                        debug!("{} is in the upload journal, but not in the bucket", key);
+                        None
+                    }
+                }
+                None => None,
+            };
+
+            match indexed {
+                Some(true) => {
+                    info!(
+                        "Skipping {}: it was already uploaded and indexed",
+                        package.filename
+                    );
+                    continue;
+                }
+                Some(false) => {
+                    info!(
+                        "Skipping upload of {}: it was already uploaded",
+                        package.filename
+                    );
+                }
+                None => {
+                    let size = fs_err::metadata(package_file).into_diagnostic()?.len();
+                    let url = self.object_url(&key)?;
+                    let request = self.request(
+                        &client,
+                        &credentials,
+                        Method::PUT,
+                        url,
+                        self.put_headers(size, "application/octet-stream"),
+                    );
+                    send_request(request, package_file).await?;
+                    journal.record_upload(&package.filename, &subdir, &sha256)?;
+                }
+            }
+
+            subdirs.entry(subdir).or_default().push(package);
+        }
+
+        // a channel needs a `noarch/repodata.json` to be usable
+        subdirs.entry("noarch".to_string()).or_default();
+
+        for (subdir, packages) in &subdirs {
+            self.update_repodata(&client, &credentials, subdir, packages)
+                .await
+                .wrap_err_with(|| {
+                    format!(
+                        "Failed to index {subdir}. Run the upload again to resume without uploading the packages again"
+                    )
+                })?;
+            journal.record_indexed(packages.iter().map(|p| p.filename.as_str()))?;
+        }
+        journal.finish()?;
+
+        info!("Packages successfully uploaded to {}", self.base);
+        Ok(())
+    }
+}
+
+impl UploadBackend for BucketChannelBackend {
+    fn name(&self) -> &str {
+        backend_name(self.store)
+    }
+
+    fn destination(&self) -> UploadDestination {
+        UploadDestination {
+            description: format!("{} bucket channel {}", self.name(), self.base),
+            channel: Some(self.base.clone()),
+            labels: Vec::new(),
+            rejects_duplicates: false,
+        }
+    }
+
+    fn upload<'a>(
+        &'a self,
+        storage: &'a AuthenticationStorage,
+        package_files: &'a [PathBuf],
+    ) -> BoxFuture<'a, miette::Result<()>> {
+        Box::pin(self.upload_packages(storage, package_files))
+    }
+
+    /// The signatures are added to the `repodata.json` of the channel.
+    fn set_signing_key(&mut self, key: SigningKey) -> miette::Result<()> {
+        self.signing_key = Some(Arc::new(key));
+        Ok(())
+    }
+}
+
+fn backend_name(store: ObjectStore) -> &'static str {
+    match store {
+        ObjectStore::Gcs => GCS_BACKEND,
+        ObjectStore::Azure => AZURE_BACKEND,
+    }
+}
+
+/// Read an access token from the CLI of a cloud provider.
+async fn cli_token(program: &str, args: &[&str]) -> Option<String> {
+    let output = tokio::process::Command::new(program)
+        .args(args)
+        .stdin(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .output()
+        .await
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let token = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    (!token.is_empty()).then_some(token)
+}
+
+/// Compute the repodata record of a package file.
+fn uploaded_package(package_file: &Path) -> miette::Result<(String, UploadedPackage)> {
+    let package = ExtractedPackage::from_package_file(package_file)?;
+
+    let subdir = package.subdir().cloned().ok_or_else(|| {
+        miette::miette!(
+            "index.json of package {} has no subdirectory. Cannot determine which directory to upload to",
+            package_file.display()
+        )
+    })?;
+    let filename = package
+        .filename()
+        .ok_or_else(|| miette::miette!("Package file {} has no filename", package_file.display()))?
+        .to_string();
+    let archive_type = ArchiveType::try_from(package_file)
+        .ok_or_else(|| miette::miette!("{} is not a conda package", package_file.display()))?;
+
+    let record = PackageRecord::from_index_json(
+        package.index_json().clone(),
+        Some(package.file_size().into_diagnostic()?),
+        Some(compute_file_digest::<Sha256>(&package_file).into_diagnostic()?),
+        Some(compute_file_digest::<Md5>(&package_file).into_diagnostic()?),
+    )
+    .into_diagnostic()?;
+
+    Ok((
+        subdir,
+        UploadedPackage {
+            filename,
+            archive_type,
+            record,
+        },
+    ))
+}
+
+fn empty_repodata(subdir: &str) -> serde_json::Value {
+    serde_json::json!({
+        "info": { "subdir": subdir },
+        "packages": {},
+        "packages.conda": {},
+        "removed": [],
+        "repodata_version": 1,
+    })
+}
+
+/// Add (or replace) a package in a `repodata.json` document, and sign its
+/// record if a key is given.
+fn add_to_repodata(
+    repodata: &mut serde_json::Value,
+    package: &UploadedPackage,
+    signing_key: Option<&SigningKey>,
+) -> miette::Result<()> {
+    let section = match package.archive_type {
+        ArchiveType::Conda => "packages.conda",
+        ArchiveType::TarBz2 => "packages",
+    };
+    let record = serde_json::to_value(&package.record).into_diagnostic()?;
+    if let Some(key) = signing_key {
+        signing::add_signature(repodata, &package.filename, &record, key);
+    }
+
+    let repodata = repodata
+        .as_object_mut()
+        .ok_or_else(|| miette::miette!("repodata.json is not a JSON object"))?;
+    let packages = repodata
+        .entry(section)
+        .or_insert_with(|| serde_json::json!({}))
+        .as_object_mut()
+        .ok_or_else(|| miette::miette!("`{section}` in repodata.json is not a JSON object"))?;
+    packages.insert(package.filename.clone(), record);
+    Ok(())
+}
+
+/// The string to sign for a request authorized with an Azure storage account
+/// key (`headers` use lowercase names).
+fn azure_string_to_sign(
+    method: &str,
+    url: &Url,
+    account: &str,
+    headers: &BTreeMap<String, String>,
+) -> String {
+    let header = |name: &str| headers.get(name).map(String::as_str).unwrap_or_default();
+    // a content length of zero is signed as an empty string
+    let content_length = match header("content-length") {
+        "0" => "",
+        length => length,
+    };
+
+    let mut string_to_sign = [
+        method,
+        header("content-encoding"),
+        header("content-language"),
+        content_length,
+        header("content-md5"),
+        header("content-type"),
+        header("date"),
+        header("if-modified-since"),
+        header("if-match"),
+        header("if-none-match"),
+        header("if-unmodified-since"),
+        header("range"),
+    ]
+    .join("\n");
+    string_to_sign.push('\n');
+
+    for (name, value) in headers.iter().filter(|(name, _)| name.starts_with("x-ms-")) {
+        string_to_sign.push_str(&format!("{}:{}\n", name, value.trim()));
+    }
+
+    string_to_sign.push_str(&format!("/{}{}", account, url.path()));
+    let mut query = url.query_pairs().collect::<Vec<_>>();
+    query.sort();
+    for (name, value) in query {
+        string_to_sign.push_str(&format!("\n{}:{}", name.to_lowercase(), value));
+    }
+
+    string_to_sign
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_channel_urls() {
+        let backend = BucketChannelBackend::from_url(
+            ObjectStore::Gcs,
+            &Url::parse("gs://my-bucket/conda/channel").unwrap(),
+        )
+        .unwrap();
+        assert_eq!(backend.name(), GCS_BACKEND);
+        assert_eq!(
+            backend
+                .object_url("linux-64/foo-1.0-h123_0.conda")
+                .unwrap()
+                .as_str(),
+            "https://storage.googleapis.com/my-bucket/conda/channel/linux-64/foo-1.0-h123_0.conda"
+        );
+
+        let backend = BucketChannelBackend::from_url(
+            ObjectStore::Azure,
+            &Url::parse("az://myaccount/container").unwrap(),
+        )
+        .unwrap();
+        assert_eq!(backend.name(), AZURE_BACKEND);
+        assert_eq!(
+            backend.object_url("noarch/repodata.json").unwrap().as_str(),
+            "https://myaccount.blob.core.windows.net/container/noarch/repodata.json"
+        );
+
+        assert!(BucketChannelBackend::from_url(
+            ObjectStore::Azure,
+            &Url::parse("az://myaccount").unwrap()
+        )
+        .is_err());
+        assert!(BucketChannelBackend::from_url(
+            ObjectStore::Gcs,
+            &Url::parse("az://myaccount/container").unwrap()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_azure_connection_string() {
+        let connection_string = AzureConnectionString::parse(
+            "DefaultEndpointsProtocol=http;AccountName=devstoreaccount1;AccountKey=a2V5;BlobEndpoint=http://127.0.0.1:10000/devstoreaccount1;",
+        );
+        assert_eq!(
+            connection_string,
+            AzureConnectionString {
+                account_name: Some("devstoreaccount1".to_string()),
+                account_key: Some("a2V5".to_string()),
+                blob_endpoint: Some("http://127.0.0.1:10000/devstoreaccount1".to_string()),
+                sas: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_azure_string_to_sign() {
+        let url = Url::parse(
+            "https://myaccount.blob.core.windows.net/container/noarch/repodata.json?comp=block&blockid=1",
+        )
+        .unwrap();
+        let headers = BTreeMap::from([
+            ("content-length".to_string(), "42".to_string()),
+            ("content-type".to_string(), "application/json".to_string()),
+            ("if-match".to_string(), "\"0x8D\"".to_string()),
+            ("x-ms-blob-type".to_string(), "BlockBlob".to_string()),
+            (
+                "x-ms-date".to_string(),
+                "Fri, 26 Jun 2015 23:39:12 GMT".to_string(),
+            ),
+            ("x-ms-version".to_string(), AZURE_API_VERSION.to_string()),
+        ]);
+
+        assert_eq!(
+            azure_string_to_sign("PUT", &url, "myaccount", &headers),
+            "PUT\n\n\n42\n\napplication/json\n\n\n\"0x8D\"\n\n\n\n\
+             x-ms-blob-type:BlockBlob\n\
+             x-ms-date:Fri, 26 Jun 2015 23:39:12 GMT\n\
+             x-ms-version:2021-08-06\n\
+             /myaccount/container/noarch/repodata.json\n\
+             blockid:1\n\
+             comp:block"
+        );
+    }
+
+    #[test]
+    fn test_add_to_repodata() {
+        let mut repodata = empty_repodata("linux-64");
+        let record: PackageRecord = serde_json::from_value(serde_json::json!({
+            "name": "foo",
+            "version": "1.0",
+            "build": "h123_0",
+            "build_number": 0,
+            "subdir": "linux-64",
+            "depends": [],
+        }))
+        .unwrap();
+
+        let key = SigningKey::generate();
+        add_to_repodata(
+            &mut repodata,
+            &UploadedPackage {
+                filename: "foo-1.0-h123_0.conda".to_string(),
+                archive_type: ArchiveType::Conda,
+                record,
+            },
+            Some(&key),
+        )
+        .unwrap();
+
+        let record = &repodata["packages.conda"]["foo-1.0-h123_0.conda"];
+        assert_eq!(record["name"], "foo");
+        assert!(repodata["packages"].as_object().unwrap().is_empty());
+        assert_eq!(repodata["info"]["subdir"], "linux-64");
+
+        let signature = repodata["signatures"]["foo-1.0-h123_0.conda"][key.public_key()]
+            ["signature"]
+            .as_str()
+            .unwrap();
+        assert!(signing::verify_record(record, &key.public_key(), signature));
+    }
+}
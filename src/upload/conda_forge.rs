@@ -1,9 +1,6 @@
 //! Conda-forge package uploader.
 
-use std::{
-    collections::HashMap,
-    path::{Path, PathBuf},
-};
+use std::{collections::HashMap, path::PathBuf};
 
 use miette::{miette, IntoDiagnostic};
 use tracing::{debug, info};
@@ -15,38 +12,10 @@ use super::{
     package::{self},
 };
 
-async fn get_channel_target_from_variant_config(
-    variant_config_path: &Path,
-) -> miette::Result<String> {
-    let variant_config = tokio::fs::read_to_string(variant_config_path)
-        .await
-        .into_diagnostic()?;
-
-    let variant_config: serde_yaml::Value =
-        serde_yaml::from_str(&variant_config).into_diagnostic()?;
-
-    let channel_target = variant_config
-        .get("channel_targets")
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| {
-            miette!("\"channel_targets\" not found or invalid format in variant_config")
-        })?;
-
-    let (channel, label) = channel_target
-        .split_once(' ')
-        .ok_or_else(|| miette!("Invalid channel_target format"))?;
-
-    if channel != "conda-forge" {
-        return Err(miette!("channel_target is not a conda-forge channel"));
-    }
-
-    Ok(label.to_string())
-}
-
 /// Uploads the package conda forge.
 pub async fn upload_packages_to_conda_forge(
     opts: CondaForgeOpts,
-    package_files: &Vec<PathBuf>,
+    package_files: &[PathBuf],
 ) -> miette::Result<()> {
     let anaconda = anaconda::Anaconda::new(opts.staging_token, opts.anaconda_url.into());
 
@@ -55,21 +24,20 @@ pub async fn upload_packages_to_conda_forge(
     for package_file in package_files {
         let package = package::ExtractedPackage::from_package_file(package_file)?;
 
-        let variant_config_path = package
-            .extraction_dir()
-            .join("info")
-            .join("recipe")
-            .join("variant_config.yaml");
+        let (channel, label) = package.channel_target().ok_or_else(|| {
+            miette!(
+                "Failed to get channel_targets from {}: \"channel_targets\" not found or invalid",
+                package.path().display()
+            )
+        })?;
 
-        let channel = get_channel_target_from_variant_config(&variant_config_path)
-            .await
-            .map_err(|e| {
-                miette!(
-                    "Failed to get channel_targets from variant config for {}: {}",
-                    package.path().display(),
-                    e
-                )
-            })?;
+        if channel != "conda-forge" {
+            return Err(miette!(
+                "channel_target of {} is not a conda-forge channel",
+                package.path().display()
+            ));
+        }
+        let channel = label;
 
         if !opts.dry_run {
             anaconda
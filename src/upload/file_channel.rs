@@ -0,0 +1,325 @@
+//! Publishing to channels in a local (or mounted) directory (`file:///path`).
+//!
+//! The packages are copied to `<channel>/<subdir>/<filename>` and every
+//! touched subdirectory is reindexed afterwards. Like for bucket channels,
+//! copied and indexed packages are recorded in an [`UploadJournal`], so that a
+//! failed upload can be resumed without copying the packages again. With a
+//! signing key, the records of the uploaded packages are signed for conda
+//! content trust.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
+
+use futures::future::BoxFuture;
+use miette::{Context, IntoDiagnostic};
+use rattler_conda_types::Platform;
+use rattler_index::index;
+use rattler_networking::AuthenticationStorage;
+use tracing::{debug, info};
+use url::Url;
+
+use super::{
+    backend::UploadBackend,
+    journal::UploadJournal,
+    package::{sha256_sum, ExtractedPackage},
+    plan::UploadDestination,
+};
+use crate::signing::{self, SigningKey};
+
+/// The name of the backend that uploads to `file://` channels.
+pub const FILE_BACKEND: &str = "file";
+
+/// Copies packages to a channel directory and reindexes it.
+#[derive(Debug, Clone)]
+pub struct FileChannelBackend {
+    /// The url of the channel (with a trailing slash)
+    base: Url,
+    /// The directory of the channel
+    dir: PathBuf,
+    /// The key to sign the records of the uploaded packages with
+    signing_key: Option<Arc<SigningKey>>,
+}
+
+impl FileChannelBackend {
+    /// Create the backend for a `file:///path/to/channel` url.
+    pub fn from_url(url: &Url) -> miette::Result<Self> {
+        let dir = url.to_file_path().map_err(|_| {
+            miette::miette!("The {FILE_BACKEND} backend requires a `file://` url, got {url}")
+        })?;
+        let base = Url::from_directory_path(&dir)
+            .map_err(|_| miette::miette!("Invalid channel directory: {}", dir.display()))?;
+        Ok(Self {
+            base,
+            dir,
+            signing_key: None,
+        })
+    }
+
+    /// Copy a package to the channel, unless the journal shows that the same
+    /// file was copied before and it is still there. Returns `None` if the
+    /// package is also indexed already.
+    fn copy_package(
+        &self,
+        journal: &mut UploadJournal,
+        package_file: &Path,
+    ) -> miette::Result<Option<(String, String)>> {
+        let package = ExtractedPackage::from_package_file(package_file)?;
+        let subdir = package.subdir().cloned().ok_or_else(|| {
+            miette::miette!(
+                "index.json of package {} has no subdirectory. Cannot determine which directory to upload to",
+                package_file.display()
+            )
+        })?;
+        let filename = package
+            .filename()
+            .ok_or_else(|| {
+                miette::miette!("Package file {} has no filename", package_file.display())
+            })?
+            .to_string();
+        let sha256 = sha256_sum(package_file).into_diagnostic()?;
+
+        let target = self.dir.join(&subdir).join(&filename);
+        let indexed = match journal.get(&filename, &sha256) {
+            Some(entry) if target.is_file() => Some(entry.indexed),
+            Some(_) => {
+                debug!(
+                    "{} is in the upload journal, but not in the channel",
+                    target.display()
+                );
+                None
+            }
+            None => None,
+        };
+
+        match indexed {
+            Some(true) => {
+                info!("Skipping {}: it was already uploaded and indexed", filename);
+                return Ok(None);
+            }
+            Some(false) => {
+                info!("Skipping upload of {}: it was already uploaded", filename);
+            }
+            None => {
+                fs_err::create_dir_all(self.dir.join(&subdir)).into_diagnostic()?;
+                fs_err::copy(package_file, &target).into_diagnostic()?;
+                journal.record_upload(&filename, &subdir, &sha256)?;
+            }
+        }
+        Ok(Some((subdir, filename)))
+    }
+
+    /// Copy the packages to the channel and reindex the touched
+    /// subdirectories. The upload journal is kept in `journal_folder`.
+    fn upload_packages(
+        &self,
+        journal_folder: &Path,
+        package_files: &[PathBuf],
+    ) -> miette::Result<()> {
+        // packages that were copied by an earlier (failed) run are not copied
+        // again, and subdirectories that already contain them are not
+        // reindexed again
+        let mut journal = UploadJournal::load(journal_folder, &self.base)?;
+
+        let mut subdirs: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for package_file in package_files {
+            if let Some((subdir, filename)) = self.copy_package(&mut journal, package_file)? {
+                subdirs.entry(subdir).or_default().push(filename);
+            }
+        }
+
+        for (subdir, filenames) in &subdirs {
+            let platform = Platform::from_str(subdir).into_diagnostic()?;
+            index(&self.dir, Some(&platform))
+                .into_diagnostic()
+                .wrap_err_with(|| {
+                    format!(
+                        "Failed to index {subdir}. Run the upload again to resume without uploading the packages again"
+                    )
+                })?;
+            // reindexing rewrites the repodata, keep the signatures of the
+            // packages
+            for subdir in [platform, Platform::NoArch] {
+                if let Err(e) = signing::restore_signatures(&self.dir.join(subdir.as_str())) {
+                    tracing::warn!("Failed to restore the package signatures: {}", e);
+                }
+            }
+            if let Some(key) = &self.signing_key {
+                let signed = signing::sign_packages(
+                    &self.dir.join(subdir),
+                    filenames.iter().map(String::as_str),
+                    key,
+                )
+                .into_diagnostic()?;
+                info!("Signed {} package(s) in {}", signed, subdir);
+            }
+            journal.record_indexed(filenames.iter().map(String::as_str))?;
+        }
+        journal.finish()?;
+
+        info!("Packages successfully uploaded to {}", self.base);
+        Ok(())
+    }
+}
+
+impl UploadBackend for FileChannelBackend {
+    fn name(&self) -> &str {
+        FILE_BACKEND
+    }
+
+    fn destination(&self) -> UploadDestination {
+        UploadDestination {
+            description: format!("local channel {}", self.dir.display()),
+            channel: Some(self.base.clone()),
+            labels: Vec::new(),
+            rejects_duplicates: false,
+        }
+    }
+
+    fn upload<'a>(
+        &'a self,
+        _storage: &'a AuthenticationStorage,
+        package_files: &'a [PathBuf],
+    ) -> BoxFuture<'a, miette::Result<()>> {
+        Box::pin(
+            async move { self.upload_packages(&UploadJournal::default_folder()?, package_files) },
+        )
+    }
+
+    /// The signatures are added to the `repodata.json` (and `signatures.json`)
+    /// of the subdirectories.
+    fn set_signing_key(&mut self, key: SigningKey) -> miette::Result<()> {
+        self.signing_key = Some(Arc::new(key));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rattler_package_streaming::write::{write_tar_bz2_package, CompressionLevel};
+    use serde_json::Value;
+
+    use super::*;
+
+    /// Write a minimal `noarch` package and return its path
+    fn write_package(dir: &Path, name: &str) -> PathBuf {
+        let contents = dir.join(format!("{name}-contents"));
+        fs_err::create_dir_all(contents.join("info")).unwrap();
+        fs_err::write(
+            contents.join("info/index.json"),
+            format!(
+                r#"{{"name": "{name}", "version": "1.0", "build": "h123_0", "build_number": 0, "subdir": "noarch", "depends": []}}"#
+            ),
+        )
+        .unwrap();
+        fs_err::write(contents.join("info/about.json"), "{}").unwrap();
+
+        let path = dir.join(format!("{name}-1.0-h123_0.tar.bz2"));
+        write_tar_bz2_package(
+            fs_err::File::create(&path).unwrap(),
+            &contents,
+            &[
+                contents.join("info/index.json"),
+                contents.join("info/about.json"),
+            ],
+            CompressionLevel::Default,
+            None,
+            None,
+        )
+        .unwrap();
+        path
+    }
+
+    #[test]
+    fn test_file_channel_urls() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = Url::from_directory_path(dir.path().join("channel")).unwrap();
+        let backend = FileChannelBackend::from_url(&url).unwrap();
+        assert_eq!(backend.dir, dir.path().join("channel"));
+        assert!(backend.base.as_str().ends_with("/channel/"));
+
+        let url = Url::from_file_path(dir.path().join("channel")).unwrap();
+        assert!(FileChannelBackend::from_url(&url)
+            .unwrap()
+            .base
+            .as_str()
+            .ends_with("/channel/"));
+
+        let url = Url::parse("https://example.com/channel").unwrap();
+        assert!(FileChannelBackend::from_url(&url).is_err());
+    }
+
+    #[test]
+    fn test_upload_to_file_channel() {
+        let tmp = tempfile::tempdir().unwrap();
+        let journals = tmp.path().join("journals");
+        let package = write_package(tmp.path(), "foo");
+
+        let url = Url::from_directory_path(tmp.path().join("channel")).unwrap();
+        let mut backend = FileChannelBackend::from_url(&url).unwrap();
+        let key = SigningKey::generate();
+        let public_key = key.public_key();
+        backend.set_signing_key(key).unwrap();
+        backend
+            .upload_packages(&journals, &[package.clone()])
+            .unwrap();
+
+        let subdir = tmp.path().join("channel/noarch");
+        assert!(subdir.join("foo-1.0-h123_0.tar.bz2").is_file());
+        let repodata: Value =
+            serde_json::from_str(&fs_err::read_to_string(subdir.join("repodata.json")).unwrap())
+                .unwrap();
+        let record = &repodata["packages"]["foo-1.0-h123_0.tar.bz2"];
+        assert_eq!(record["name"], "foo");
+        let signature = repodata["signatures"]["foo-1.0-h123_0.tar.bz2"][&public_key]["signature"]
+            .as_str()
+            .unwrap();
+        assert!(signing::verify_record(record, &public_key, signature));
+
+        // everything was indexed, so the journal is removed
+        assert!(!UploadJournal::path_for(&journals, &backend.base).exists());
+    }
+
+    #[test]
+    fn test_resume_upload() {
+        let tmp = tempfile::tempdir().unwrap();
+        let journals = tmp.path().join("journals");
+        let package = write_package(tmp.path(), "foo");
+
+        let url = Url::from_directory_path(tmp.path().join("channel")).unwrap();
+        let backend = FileChannelBackend::from_url(&url).unwrap();
+
+        // a package that was copied, but not indexed, is not copied again
+        let mut journal = UploadJournal::load(&journals, &backend.base).unwrap();
+        assert_eq!(
+            backend.copy_package(&mut journal, &package).unwrap(),
+            Some(("noarch".to_string(), "foo-1.0-h123_0.tar.bz2".to_string()))
+        );
+        let target = tmp.path().join("channel/noarch/foo-1.0-h123_0.tar.bz2");
+        fs_err::write(&target, "copied before").unwrap();
+        let mut journal = UploadJournal::load(&journals, &backend.base).unwrap();
+        assert!(backend
+            .copy_package(&mut journal, &package)
+            .unwrap()
+            .is_some());
+        assert_eq!(fs_err::read_to_string(&target).unwrap(), "copied before");
+
+        // a package that was also indexed is skipped
+        journal.record_indexed(["foo-1.0-h123_0.tar.bz2"]).unwrap();
+        assert!(backend
+            .copy_package(&mut journal, &package)
+            .unwrap()
+            .is_none());
+
+        // a package that is missing from the channel is copied again
+        fs_err::remove_file(&target).unwrap();
+        assert!(backend
+            .copy_package(&mut journal, &package)
+            .unwrap()
+            .is_some());
+        assert!(target.is_file());
+    }
+}
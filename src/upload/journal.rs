@@ -0,0 +1,191 @@
+//! A local journal of the packages that were uploaded to a channel and of the
+//! subdirectories whose repodata was updated afterwards.
+//!
+//! When updating the repodata fails after some packages were already uploaded,
+//! re-running the same upload resumes from the journal: packages that are
+//! already in the channel are not uploaded again and only the subdirectories
+//! that still miss packages are reindexed. The journal is kept in the
+//! rattler cache directory (one file per channel) and removed once all its
+//! packages are indexed.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use miette::{Context, IntoDiagnostic};
+use rattler_digest::{compute_bytes_digest, Sha256};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// The state of a package in the journal.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// The subdirectory the package was uploaded to
+    pub subdir: String,
+    /// The SHA256 hash of the uploaded file
+    pub sha256: String,
+    /// Whether the package was added to the repodata of the subdirectory
+    pub indexed: bool,
+}
+
+/// The upload journal of a single channel.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UploadJournal {
+    /// The channel the packages are uploaded to
+    pub channel: String,
+    /// The uploaded packages by filename
+    pub packages: BTreeMap<String, JournalEntry>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl UploadJournal {
+    /// The path of the journal of a channel in the given folder.
+    pub fn path_for(folder: &Path, channel: &Url) -> PathBuf {
+        let hash = format!("{:x}", compute_bytes_digest::<Sha256>(channel.as_str()));
+        folder.join(format!("{}.json", &hash[..16]))
+    }
+
+    /// The folder in the rattler cache directory that contains the journals.
+    pub fn default_folder() -> miette::Result<PathBuf> {
+        let cache_dir = rattler_cache::default_cache_dir()
+            .map_err(|e| miette::miette!("Failed to determine the cache directory: {e}"))?;
+        Ok(cache_dir.join("upload-journals"))
+    }
+
+    /// Load the journal of a channel, or start a new one if there is none (or
+    /// it belongs to another channel).
+    pub fn load(folder: &Path, channel: &Url) -> miette::Result<Self> {
+        let path = Self::path_for(folder, channel);
+        let journal = if path.is_file() {
+            let content = fs_err::read_to_string(&path).into_diagnostic()?;
+            serde_json::from_str::<Self>(&content)
+                .into_diagnostic()
+                .wrap_err_with(|| format!("Failed to parse upload journal {}", path.display()))?
+        } else {
+            Self::default()
+        };
+
+        if journal.channel == channel.as_str() {
+            Ok(Self { path, ..journal })
+        } else {
+            Ok(Self {
+                channel: channel.to_string(),
+                packages: BTreeMap::new(),
+                path,
+            })
+        }
+    }
+
+    /// Returns the entry of a package if the same file was uploaded before.
+    pub fn get(&self, filename: &str, sha256: &str) -> Option<&JournalEntry> {
+        self.packages
+            .get(filename)
+            .filter(|entry| entry.sha256 == sha256)
+    }
+
+    /// Record that a package was uploaded and write the journal.
+    pub fn record_upload(
+        &mut self,
+        filename: &str,
+        subdir: &str,
+        sha256: &str,
+    ) -> miette::Result<()> {
+        self.packages.insert(
+            filename.to_string(),
+            JournalEntry {
+                subdir: subdir.to_string(),
+                sha256: sha256.to_string(),
+                indexed: false,
+            },
+        );
+        self.save()
+    }
+
+    /// Record that the packages were added to the repodata of their
+    /// subdirectory and write the journal.
+    pub fn record_indexed<'a>(
+        &mut self,
+        filenames: impl IntoIterator<Item = &'a str>,
+    ) -> miette::Result<()> {
+        for filename in filenames {
+            if let Some(entry) = self.packages.get_mut(filename) {
+                entry.indexed = true;
+            }
+        }
+        self.save()
+    }
+
+    /// Drop the packages that are indexed. The journal file is removed when
+    /// no packages are left.
+    pub fn finish(mut self) -> miette::Result<()> {
+        self.packages.retain(|_, entry| !entry.indexed);
+        if self.packages.is_empty() {
+            if self.path.exists() {
+                fs_err::remove_file(&self.path).into_diagnostic()?;
+            }
+            Ok(())
+        } else {
+            self.save()
+        }
+    }
+
+    fn save(&self) -> miette::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs_err::create_dir_all(parent).into_diagnostic()?;
+        }
+        let content = serde_json::to_string_pretty(self).into_diagnostic()?;
+        fs_err::write(&self.path, content).into_diagnostic()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upload_journal() {
+        let tmp = tempfile::tempdir().unwrap();
+        let channel = Url::parse("https://storage.googleapis.com/bucket/channel/").unwrap();
+
+        let mut journal = UploadJournal::load(tmp.path(), &channel).unwrap();
+        assert!(journal.packages.is_empty());
+        journal
+            .record_upload("foo-1.0-h123_0.conda", "linux-64", "abc")
+            .unwrap();
+        journal
+            .record_upload("bar-1.0-h123_0.conda", "noarch", "def")
+            .unwrap();
+        journal.record_indexed(["bar-1.0-h123_0.conda"]).unwrap();
+
+        // the journal is resumed
+        let journal = UploadJournal::load(tmp.path(), &channel).unwrap();
+        let entry = journal.get("foo-1.0-h123_0.conda", "abc").unwrap();
+        assert_eq!(entry.subdir, "linux-64");
+        assert!(!entry.indexed);
+        assert!(journal.get("bar-1.0-h123_0.conda", "def").unwrap().indexed);
+        // a changed file is uploaded again
+        assert!(journal.get("foo-1.0-h123_0.conda", "xyz").is_none());
+
+        // other channels have their own journal
+        let other = Url::parse("https://storage.googleapis.com/bucket/other/").unwrap();
+        assert!(UploadJournal::load(tmp.path(), &other)
+            .unwrap()
+            .packages
+            .is_empty());
+
+        // indexed packages are dropped when finishing
+        journal.clone().finish().unwrap();
+        let mut journal = UploadJournal::load(tmp.path(), &channel).unwrap();
+        assert_eq!(
+            journal.packages.keys().collect::<Vec<_>>(),
+            vec!["foo-1.0-h123_0.conda"]
+        );
+
+        // and the file is removed once everything is indexed
+        journal.record_indexed(["foo-1.0-h123_0.conda"]).unwrap();
+        journal.finish().unwrap();
+        assert!(!UploadJournal::path_for(tmp.path(), &channel).exists());
+    }
+}
@@ -20,9 +20,19 @@ use url::Url;
 
 use crate::upload::package::{sha256_sum, ExtractedPackage};
 
+pub use backend::{
+    create_upload_backend, default_backend_for_url, register_upload_backend, upload_backend_names,
+    UploadBackend,
+};
+
 mod anaconda;
+pub mod backend;
+pub mod bucket_channel;
 pub mod conda_forge;
+pub mod file_channel;
+pub mod journal;
 mod package;
+pub mod plan;
 mod trusted_publishing;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -71,7 +81,7 @@ fn get_client_with_retry() -> Result<reqwest_middleware::ClientWithMiddleware, r
 pub async fn upload_package_to_quetz(
     storage: &AuthenticationStorage,
     api_key: Option<String>,
-    package_files: &Vec<PathBuf>,
+    package_files: &[PathBuf],
     url: UrlWithTrailingSlash,
     channel: String,
 ) -> miette::Result<()> {
@@ -126,7 +136,7 @@ pub async fn upload_package_to_quetz(
 pub async fn upload_package_to_artifactory(
     storage: &AuthenticationStorage,
     token: Option<String>,
-    package_files: &Vec<PathBuf>,
+    package_files: &[PathBuf],
     url: UrlWithTrailingSlash,
     channel: String,
 ) -> miette::Result<()> {
@@ -198,7 +208,7 @@ pub async fn upload_package_to_artifactory(
 pub async fn upload_package_to_prefix(
     storage: &AuthenticationStorage,
     api_key: Option<String>,
-    package_files: &Vec<PathBuf>,
+    package_files: &[PathBuf],
     url: UrlWithTrailingSlash,
     channel: String,
 ) -> miette::Result<()> {
@@ -271,7 +281,7 @@ pub async fn upload_package_to_prefix(
 pub async fn upload_package_to_anaconda(
     storage: &AuthenticationStorage,
     token: Option<String>,
-    package_files: &Vec<PathBuf>,
+    package_files: &[PathBuf],
     url: UrlWithTrailingSlash,
     owner: String,
     channels: Vec<String>,
@@ -311,6 +321,28 @@ pub async fn upload_package_to_anaconda(
 
             anaconda.create_or_update_release(&owner, &package).await?;
 
+            // without explicit channels, the label of the `channel_targets`
+            // variant is used (`main` by default)
+            let channels = if channels.is_empty() {
+                let label = match package.channel_target() {
+                    Some((channel, label)) => {
+                        if channel != owner {
+                            tracing::warn!(
+                                "The channel target of {} is `{}`, but uploading to `{}`",
+                                package.path().display(),
+                                channel,
+                                owner
+                            );
+                        }
+                        label
+                    }
+                    None => "main".to_string(),
+                };
+                vec![label]
+            } else {
+                channels.clone()
+            };
+
             let successful = anaconda
                 .upload_file(&owner, &channels, force, &package)
                 .await?;
@@ -16,6 +16,18 @@ pub fn sha256_sum(package_file: &Path) -> Result<String, std::io::Error> {
     ))
 }
 
+/// Split a `channel_targets` value (e.g. `conda-forge main`) into the channel
+/// and the label. The label defaults to `main`.
+pub fn parse_channel_target(channel_target: &str) -> Option<(String, String)> {
+    let mut parts = channel_target.split_whitespace();
+    let channel = parts.next()?;
+    let label = parts.next().unwrap_or("main");
+    if parts.next().is_some() {
+        return None;
+    }
+    Some((channel.to_string(), label.to_string()))
+}
+
 pub struct ExtractedPackage<'a> {
     file: &'a Path,
     about_json: AboutJson,
@@ -87,4 +99,50 @@ impl<'a> ExtractedPackage<'a> {
     pub fn extraction_dir(&self) -> &Path {
         self.extraction_dir.path()
     }
+
+    /// The destination channel and label of the package as defined by the
+    /// `channel_targets` variant. It is read from the `extra` section of the
+    /// `about.json` and falls back to the rendered variant config of the
+    /// package (for packages built by older versions).
+    pub fn channel_target(&self) -> Option<(String, String)> {
+        if let Some(channel_target) = self
+            .about_json
+            .extra
+            .get("channel_targets")
+            .and_then(|v| v.as_str())
+        {
+            return parse_channel_target(channel_target);
+        }
+
+        let variant_config = fs_err::read_to_string(
+            self.extraction_dir()
+                .join("info/recipe/variant_config.yaml"),
+        )
+        .ok()?;
+        let variant_config: serde_yaml::Value = serde_yaml::from_str(&variant_config).ok()?;
+        parse_channel_target(variant_config.get("channel_targets")?.as_str()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_channel_target;
+
+    #[test]
+    fn test_parse_channel_target() {
+        assert_eq!(
+            parse_channel_target("conda-forge main"),
+            Some(("conda-forge".to_string(), "main".to_string()))
+        );
+        assert_eq!(
+            parse_channel_target("conda-forge  rc"),
+            Some(("conda-forge".to_string(), "rc".to_string()))
+        );
+        assert_eq!(
+            parse_channel_target("my-channel"),
+            Some(("my-channel".to_string(), "main".to_string()))
+        );
+        assert_eq!(parse_channel_target(""), None);
+        assert_eq!(parse_channel_target("a b c"), None);
+    }
 }
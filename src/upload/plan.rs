@@ -0,0 +1,224 @@
+//! The upload plan that `rattler-build upload --dry-run` prints: which
+//! packages are uploaded where, and whether they already exist at the
+//! destination. Nothing is uploaded.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use indicatif::HumanBytes;
+use miette::IntoDiagnostic;
+use rattler_conda_types::{ChannelUrl, MatchSpec, PackageName, Platform};
+use url::Url;
+
+use super::{backend::UploadBackend, package::ExtractedPackage};
+use crate::{render::solver::load_repodatas, tool_configuration};
+
+/// Where a backend uploads the packages to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UploadDestination {
+    /// A description of the destination (e.g. the server and the channel)
+    pub description: String,
+    /// The URL of the channel that is queried for existing packages, if the
+    /// destination can be queried
+    pub channel: Option<Url>,
+    /// The labels that the packages are uploaded to
+    pub labels: Vec<String>,
+    /// Whether the server rejects a package that already exists
+    pub rejects_duplicates: bool,
+}
+
+impl UploadDestination {
+    /// A destination that cannot be queried for existing packages
+    pub fn new(description: impl Into<String>) -> Self {
+        Self {
+            description: description.into(),
+            channel: None,
+            labels: Vec::new(),
+            rejects_duplicates: false,
+        }
+    }
+}
+
+/// A package that would be uploaded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedUpload {
+    /// The package file
+    pub path: PathBuf,
+    /// The file name of the package
+    pub filename: String,
+    /// The name of the package
+    pub name: PackageName,
+    /// The subdirectory of the package (e.g. `linux-64`)
+    pub subdir: String,
+    /// The size of the package file in bytes
+    pub size: u64,
+    /// Whether the package already exists at the destination (`None` if this
+    /// could not be checked)
+    pub exists: Option<bool>,
+}
+
+impl PlannedUpload {
+    fn from_package_file(path: &Path) -> miette::Result<Self> {
+        let package = ExtractedPackage::from_package_file(path)?;
+        let filename = package
+            .filename()
+            .ok_or_else(|| miette::miette!("Package file {} has no filename", path.display()))?
+            .to_string();
+        Ok(Self {
+            path: path.to_path_buf(),
+            filename,
+            name: package.package_name().clone(),
+            subdir: package
+                .subdir()
+                .cloned()
+                .unwrap_or_else(|| Platform::NoArch.to_string()),
+            size: package.file_size().into_diagnostic()?,
+            exists: None,
+        })
+    }
+}
+
+/// Query the channel for the packages and mark the ones whose file already
+/// exists in the repodata.
+async fn check_existing(
+    channel: &Url,
+    uploads: &mut [PlannedUpload],
+    tool_configuration: &tool_configuration::Configuration,
+) -> miette::Result<()> {
+    let mut by_subdir = BTreeMap::<&str, Vec<MatchSpec>>::new();
+    for upload in uploads.iter() {
+        by_subdir
+            .entry(upload.subdir.as_str())
+            .or_default()
+            .push(upload.name.clone().into());
+    }
+
+    let channels = [ChannelUrl::from(channel.clone())];
+    let mut existing = HashSet::new();
+    for (subdir, specs) in by_subdir {
+        let platform = Platform::from_str(subdir).into_diagnostic()?;
+        let repodata = load_repodatas(&channels, platform, &specs, tool_configuration)
+            .await
+            .map_err(|e| miette::miette!("Failed to load repodata of {}: {e}", channel))?;
+        existing.extend(
+            repodata
+                .iter()
+                .flatten()
+                .map(|record| record.file_name.clone()),
+        );
+    }
+
+    for upload in uploads.iter_mut() {
+        upload.exists = Some(existing.contains(&upload.filename));
+    }
+    Ok(())
+}
+
+fn print_plan(destination: &UploadDestination, uploads: &[PlannedUpload]) {
+    let mut table = comfy_table::Table::new();
+    table
+        .load_preset(comfy_table::presets::UTF8_FULL_CONDENSED)
+        .apply_modifier(comfy_table::modifiers::UTF8_ROUND_CORNERS)
+        .set_header(vec!["Package", "Subdir", "Size", "Exists"]);
+    for upload in uploads {
+        table.add_row(vec![
+            upload.filename.clone(),
+            upload.subdir.clone(),
+            HumanBytes(upload.size).to_string(),
+            match upload.exists {
+                Some(true) => "yes".to_string(),
+                Some(false) => "no".to_string(),
+                None => "unknown".to_string(),
+            },
+        ]);
+    }
+
+    tracing::info!("Upload plan (dry run, nothing is uploaded):");
+    tracing::info!("  Destination: {}", destination.description);
+    if let Some(channel) = &destination.channel {
+        tracing::info!("  Channel: {}", channel);
+    }
+    if !destination.labels.is_empty() {
+        tracing::info!("  Labels: {}", destination.labels.join(", "));
+    }
+    tracing::info!("\n{table}");
+}
+
+/// Print the upload plan of the backend for the package files. Fails if
+/// packages already exist at a destination that rejects duplicates.
+pub async fn dry_run(
+    backend: &dyn UploadBackend,
+    package_files: &[PathBuf],
+    tool_configuration: &tool_configuration::Configuration,
+) -> miette::Result<()> {
+    let destination = backend.destination();
+    let mut uploads = package_files
+        .iter()
+        .map(|path| PlannedUpload::from_package_file(path))
+        .collect::<miette::Result<Vec<_>>>()?;
+
+    if let Some(channel) = &destination.channel {
+        if let Err(e) = check_existing(channel, &mut uploads, tool_configuration).await {
+            tracing::warn!("Could not check for existing packages: {e}");
+        }
+    }
+
+    print_plan(&destination, &uploads);
+    duplicates_error(&destination, &uploads)
+}
+
+/// Returns an error if packages already exist at a destination that rejects
+/// duplicates.
+fn duplicates_error(
+    destination: &UploadDestination,
+    uploads: &[PlannedUpload],
+) -> miette::Result<()> {
+    let duplicates = uploads
+        .iter()
+        .filter(|upload| upload.exists == Some(true))
+        .map(|upload| upload.filename.as_str())
+        .collect::<Vec<_>>();
+    if destination.rejects_duplicates && !duplicates.is_empty() {
+        return Err(miette::miette!(
+            "{} package(s) already exist at the destination and would be rejected: {}",
+            duplicates.len(),
+            duplicates.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_duplicates_error() {
+        let upload = |filename: &str, exists| PlannedUpload {
+            path: PathBuf::from(filename),
+            filename: filename.to_string(),
+            name: PackageName::from_str("foo").unwrap(),
+            subdir: "linux-64".to_string(),
+            size: 1024,
+            exists,
+        };
+        let uploads = vec![
+            upload("foo-1.0-h123_0.conda", Some(true)),
+            upload("foo-1.0-h456_0.conda", Some(false)),
+            upload("foo-1.0-h789_0.conda", None),
+        ];
+
+        let mut destination = UploadDestination::new("test");
+        assert!(duplicates_error(&destination, &uploads).is_ok());
+
+        destination.rejects_duplicates = true;
+        let err = duplicates_error(&destination, &uploads).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "1 package(s) already exist at the destination and would be rejected: foo-1.0-h123_0.conda"
+        );
+    }
+}
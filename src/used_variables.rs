@@ -9,7 +9,12 @@
 //!    - extract all `if ... then ... else ` and `jinja` statements and find used variables
 //!    - retrieve used variables from configuration and flatten selectors
 //!    - extract all dependencies and add them to used variables to build full variant
-use std::collections::{HashSet, VecDeque};
+//!    - with `build.script.track_variables`, also extract the jinja variables of the script file
+//!    - environment variables read with `env.get("VAR")` are tracked as `env.VAR`
+use std::{
+    collections::{HashSet, VecDeque},
+    path::{Path, PathBuf},
+};
 
 use marked_yaml::Span;
 use minijinja::machinery::{
@@ -17,11 +22,14 @@ use minijinja::machinery::{
     parse_expr, WhitespaceConfig,
 };
 
-use crate::recipe::{
-    custom_yaml::{self, HasSpan, Node, ScalarNode, SequenceNodeInternal},
-    jinja::SYNTAX_CONFIG,
-    parser::CollectErrors,
-    ParsingError,
+use crate::{
+    recipe::{
+        custom_yaml::{self, HasSpan, Node, ScalarNode, SequenceNodeInternal},
+        jinja::SYNTAX_CONFIG,
+        parser::CollectErrors,
+        ParsingError,
+    },
+    selectors::ENV_VARIANT_PREFIX,
 };
 
 /// Extract all variables from a jinja statement
@@ -72,8 +80,8 @@ fn extract_variable_from_expression(expr: &Expr, variables: &mut HashSet<String>
                 extract_variable_from_expression(arg, variables);
             }
         }
-        Expr::Call(call) => {
-            if let ast::CallType::Function(function) = call.identify_call() {
+        Expr::Call(call) => match call.identify_call() {
+            ast::CallType::Function(function) => {
                 if function == "compiler" {
                     if let Expr::Const(constant) = &call.args[0] {
                         variables.insert(format!("{}_compiler", &constant.value));
@@ -95,6 +103,25 @@ fn extract_variable_from_expression(expr: &Expr, variables: &mut HashSet<String>
                     extract_variable_from_expression(&call.args[0], variables);
                 }
             }
+            ast::CallType::Method(Expr::Var(object), "get" | "exists") if object.id == "env" => {
+                if let Some(Expr::Const(constant)) = call.args.first() {
+                    if let Some(name) = constant.value.as_str() {
+                        variables.insert(format!("{ENV_VARIANT_PREFIX}{name}"));
+                    }
+                }
+            }
+            // string methods such as `version.split('.')`
+            ast::CallType::Method(object, _) => {
+                extract_variable_from_expression(object, variables);
+                for arg in &call.args {
+                    extract_variable_from_expression(arg, variables);
+                }
+            }
+            _ => {}
+        },
+        Expr::GetItem(getitem) => {
+            extract_variable_from_expression(&getitem.expr, variables);
+            extract_variable_from_expression(&getitem.subscript_expr, variables);
         }
         Expr::IfExpr(ifexpr) => {
             extract_variable_from_expression(&ifexpr.test_expr, variables);
@@ -268,10 +295,114 @@ fn variables_from_skip(
     Ok(())
 }
 
-/// This finds all variables used in jinja or `if/then/else` expressions
+/// The extensions that are tried for a script file that is given without one
+const SCRIPT_EXTENSIONS: [&str; 3] = ["sh", "bat", "nu"];
+
+/// The candidates for the script file(s) of the recipe, if the script opts in
+/// with `track_variables: true`, and the span that errors point to. Script
+/// files without an extension are looked up with all known script extensions
+/// (e.g. `build.sh` and `build.bat`).
+fn tracked_script_candidates(root: &Node, recipe_dir: &Path) -> Option<(Vec<PathBuf>, Span)> {
+    let script_node = root
+        .as_mapping()
+        .and_then(|m| m.get("build"))
+        .and_then(|m| m.as_mapping())
+        .and_then(|m| m.get("script"))?;
+    let script = script_node.as_mapping()?;
+
+    let track_variables = script
+        .get("track_variables")
+        .and_then(|node| node.as_scalar())
+        .is_some_and(|scalar| scalar.as_str() == "true");
+    // inline `content` is already part of the recipe
+    if !track_variables || script.contains_key("content") {
+        return None;
+    }
+
+    let (file, span) = match script.get("file").and_then(|node| node.as_scalar()) {
+        Some(file) => (file.as_str(), *file.span()),
+        None => ("build", *script_node.span()),
+    };
+
+    let path = recipe_dir.join(file);
+    let candidates = if path.extension().is_none() {
+        SCRIPT_EXTENSIONS
+            .iter()
+            .map(|ext| path.with_extension(ext))
+            .collect()
+    } else {
+        vec![path]
+    };
+    Some((candidates, span))
+}
+
+/// The existing script files of the recipe whose variables are tracked with
+/// `track_variables: true`
+pub(crate) fn tracked_script_files(root: &Node, recipe_dir: &Path) -> Vec<PathBuf> {
+    tracked_script_candidates(root, recipe_dir)
+        .map(|(candidates, _)| candidates.into_iter().filter(|p| p.is_file()).collect())
+        .unwrap_or_default()
+}
+
+/// Find all variables used in the script file(s) of the recipe, if the script
+/// opts in with `track_variables: true`.
+fn variables_from_script_files(
+    root: &Node,
+    src: &str,
+    recipe_dir: Option<&Path>,
+    variables: &mut HashSet<String>,
+) -> Result<(), Vec<ParsingError>> {
+    let Some((candidates, span)) =
+        recipe_dir.and_then(|recipe_dir| tracked_script_candidates(root, recipe_dir))
+    else {
+        return Ok(());
+    };
+
+    let mut errs = Vec::new();
+    for candidate in candidates.iter().filter(|p| p.is_file()) {
+        let content = match fs_err::read_to_string(candidate) {
+            Ok(content) => content,
+            Err(err) => {
+                errs.push(ParsingError::from_partial(
+                    src,
+                    crate::_partialerror!(
+                        span,
+                        crate::recipe::error::ErrorKind::InvalidValue((
+                            "file".to_string(),
+                            err.to_string().into()
+                        )),
+                        label = "failed to read this script file"
+                    ),
+                ));
+                continue;
+            }
+        };
+        match parse(&content, &candidate.to_string_lossy()) {
+            Ok(ast) => extract_variables(&ast, variables),
+            Err(err) => errs.push(ParsingError::from_partial(
+                src,
+                crate::_partialerror!(
+                    span,
+                    crate::recipe::error::ErrorKind::from(err),
+                    label = "failed to parse the script file as jinja template"
+                ),
+            )),
+        }
+    }
+
+    if !errs.is_empty() {
+        return Err(errs);
+    }
+    Ok(())
+}
+
+/// This finds all variables used in jinja or `if/then/else` expressions. If a
+/// `recipe_dir` is given, script files that opt in with `track_variables` are
+/// scanned as well.
 pub(crate) fn used_vars_from_expressions(
     yaml_node: &Node,
     src: &str,
+    recipe_dir: Option<&Path>,
 ) -> Result<HashSet<String>, Vec<ParsingError>> {
     let mut selectors = HashSet::new();
 
@@ -294,6 +425,9 @@ pub(crate) fn used_vars_from_expressions(
     // parse recipe into AST
     find_jinja(yaml_node, src, &mut variables)?;
 
+    // find all variables in script files on disk
+    variables_from_script_files(yaml_node, src, recipe_dir, &mut variables)?;
+
     Ok(variables)
 }
 
@@ -318,10 +452,13 @@ mod test {
             - ${{ pin_compatible(abc ~ def) }}
             - if: match(xpython, ">=3.7")
               then: numpy 100
+            - ${{ "mkl" if blas_impl.startswith("mkl") }}
+            - ${{ "qt " ~ qt_version.split(".")[0] }}
+            - ${{ "libfoo " ~ foo_version | max_pin("x.x") }}
         "#;
 
         let recipe_node = crate::recipe::custom_yaml::Node::parse_yaml(0, recipe).unwrap();
-        let used_vars = used_vars_from_expressions(&recipe_node, recipe).unwrap();
+        let used_vars = used_vars_from_expressions(&recipe_node, recipe, None).unwrap();
         assert!(used_vars.contains("llvm_variant"));
         assert!(used_vars.contains("linux"));
         assert!(used_vars.contains("osx"));
@@ -335,6 +472,23 @@ mod test {
         assert!(used_vars.contains("abc"));
         assert!(used_vars.contains("def"));
         assert!(used_vars.contains("xpython"));
+        assert!(used_vars.contains("blas_impl"));
+        assert!(used_vars.contains("qt_version"));
+        assert!(used_vars.contains("foo_version"));
+    }
+
+    #[test]
+    fn test_used_env_vars() {
+        let recipe = r#"build:
+            string: ${{ env.get("CI_COMMIT", default="none") }}_${{ hash }}
+            script:
+              - ${{ "foo" if env.exists("FOO") }}
+        "#;
+
+        let recipe_node = crate::recipe::custom_yaml::Node::parse_yaml(0, recipe).unwrap();
+        let used_vars = used_vars_from_expressions(&recipe_node, recipe, None).unwrap();
+        assert!(used_vars.contains("env.CI_COMMIT"));
+        assert!(used_vars.contains("env.FOO"));
     }
 
     #[test]
@@ -345,7 +499,7 @@ mod test {
         "#;
 
         let recipe_node = crate::recipe::custom_yaml::Node::parse_yaml(0, recipe).unwrap();
-        let used_vars = used_vars_from_expressions(&recipe_node, recipe).unwrap();
+        let used_vars = used_vars_from_expressions(&recipe_node, recipe, None).unwrap();
         assert!(used_vars.contains("c_compiler"));
         assert!(used_vars.contains("c_compiler_version"));
         assert!(used_vars.contains("bla"));
@@ -362,10 +516,41 @@ mod test {
         "#;
 
         let recipe_node = crate::recipe::custom_yaml::Node::parse_yaml(0, recipe).unwrap();
-        let used_vars = used_vars_from_expressions(&recipe_node, recipe).unwrap();
+        let used_vars = used_vars_from_expressions(&recipe_node, recipe, None).unwrap();
         assert!(used_vars.contains("llvm_variant"));
         assert!(used_vars.contains("cuda"));
         assert!(used_vars.contains("linux"));
         assert!(!used_vars.contains("osx"));
     }
+
+    #[test]
+    fn test_used_vars_from_script_file() {
+        let dir = tempfile::tempdir().unwrap();
+        fs_err::write(
+            dir.path().join("build.sh"),
+            "cmake -DUSE_CUDA=${{ cuda_enabled }} .\n[[ -n \"${PREFIX}\" ]] && make\n",
+        )
+        .unwrap();
+        fs_err::write(dir.path().join("build.bat"), "echo ${{ vc_version }}\n").unwrap();
+
+        let recipe = r#"build:
+            script:
+              file: build
+              track_variables: true
+        "#;
+        let recipe_node = crate::recipe::custom_yaml::Node::parse_yaml(0, recipe).unwrap();
+        let used_vars = used_vars_from_expressions(&recipe_node, recipe, Some(dir.path())).unwrap();
+        assert!(used_vars.contains("cuda_enabled"));
+        assert!(used_vars.contains("vc_version"));
+        assert!(!used_vars.contains("PREFIX"));
+
+        // without opting in, the script files are not scanned
+        let recipe = r#"build:
+            script:
+              file: build
+        "#;
+        let recipe_node = crate::recipe::custom_yaml::Node::parse_yaml(0, recipe).unwrap();
+        let used_vars = used_vars_from_expressions(&recipe_node, recipe, Some(dir.path())).unwrap();
+        assert!(!used_vars.contains("cuda_enabled"));
+    }
 }
@@ -89,19 +89,7 @@ pub fn remove_dir_all_force(path: &Path) -> std::io::Result<()> {
                 "Adjusting permissions to remove read-only files in the build directory."
             );
             for entry in WalkDir::new(path).into_iter().filter_map(|e| e.ok()) {
-                let file_path = entry.path();
-                let metadata = fs::metadata(file_path)?;
-                let mut permissions = metadata.permissions();
-
-                if permissions.readonly() {
-                    // Set only the user write bit
-                    #[cfg(unix)]
-                    permissions.set_mode(permissions.mode() | 0o200);
-                    #[cfg(windows)]
-                    #[allow(clippy::permissions_set_readonly_false)]
-                    permissions.set_readonly(false);
-                    fs::set_permissions(file_path, permissions)?;
-                }
+                make_writable(entry.path())?;
             }
             fs::remove_dir_all(path)
         }
@@ -109,6 +97,76 @@ pub fn remove_dir_all_force(path: &Path) -> std::io::Result<()> {
     }
 }
 
+/// Makes a read-only file or directory writable for the user. This is needed
+/// for files that are copied from a read-only location (e.g. a recipe directory
+/// that is mounted read-only), because copies keep the permissions.
+pub fn make_writable(path: &Path) -> std::io::Result<()> {
+    let mut permissions = fs::metadata(path)?.permissions();
+    if permissions.readonly() {
+        // Set only the user write bit
+        #[cfg(unix)]
+        permissions.set_mode(permissions.mode() | 0o200);
+        #[cfg(windows)]
+        #[allow(clippy::permissions_set_readonly_false)]
+        permissions.set_readonly(false);
+        fs::set_permissions(path, permissions)?;
+    }
+    Ok(())
+}
+
+/// Returns true if the path is read-only: its permissions do not allow writing
+/// or (on unix) it is on a file system that is mounted read-only. This does not
+/// try to write to the path.
+pub fn is_read_only(path: &Path) -> bool {
+    if fs::metadata(path).is_ok_and(|metadata| metadata.permissions().readonly()) {
+        return true;
+    }
+    #[cfg(unix)]
+    {
+        use nix::sys::statvfs::{statvfs, FsFlags};
+        if statvfs(path).is_ok_and(|stat| stat.flags().contains(FsFlags::ST_RDONLY)) {
+            return true;
+        }
+    }
+    false
+}
+
+/// Splits a multi-document YAML stream at the `---` document markers. A
+/// marker has to start at the beginning of a line, so a `---` in a block
+/// scalar (whose lines are always indented) or in a value does not start a new
+/// document. Content after the marker (e.g. `--- |` or `--- # comment`)
+/// belongs to the new document, and a `...` line ends the current document.
+/// Empty documents (e.g. before a leading `---`) are dropped.
+pub fn split_yaml_documents(content: &str) -> Vec<String> {
+    let mut documents = vec![String::new()];
+    for line in content.lines() {
+        let marker = line
+            .strip_prefix("---")
+            .filter(|rest| rest.is_empty() || rest.starts_with([' ', '\t']));
+        if let Some(rest) = marker {
+            let mut document = String::new();
+            let rest = rest.trim();
+            if !rest.is_empty() && !rest.starts_with('#') {
+                document.push_str(rest);
+                document.push('\n');
+            }
+            documents.push(document);
+        } else if line.trim_end() == "..." {
+            documents.push(String::new());
+        } else {
+            let document = documents.last_mut().expect("there is always a document");
+            document.push_str(line);
+            document.push('\n');
+        }
+    }
+    documents.retain(|document| {
+        document
+            .lines()
+            .any(|line| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+    });
+    documents
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -136,4 +194,40 @@ mod tests {
             assert_eq!(forward_slash, "/foo/bar/baz");
         }
     }
+
+    #[test]
+    fn test_split_yaml_documents() {
+        let stream = "---\npackage:\n  name: foo\n--- # variants\npython:\n  - 3.12\n---\n";
+        assert_eq!(
+            split_yaml_documents(stream),
+            vec!["package:\n  name: foo\n", "python:\n  - 3.12\n"]
+        );
+        assert_eq!(split_yaml_documents("a: b"), vec!["a: b\n"]);
+        assert!(split_yaml_documents("").is_empty());
+
+        // `---` in block scalars, values or indented lines is not a marker
+        let stream = "script: |\n  echo foo\n  ---\n  echo bar\nabout: ---\n ---\n---x: y\n";
+        assert_eq!(split_yaml_documents(stream), vec![stream]);
+
+        // content after the marker and `...` document end markers
+        let stream = "a: b\n...\n# comment\n--- |\n  text\n---\t# comment\n";
+        assert_eq!(split_yaml_documents(stream), vec!["a: b\n", "|\n  text\n"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_make_writable() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("file.txt");
+        fs::write(&file, "content").unwrap();
+        fs::set_permissions(&file, std::fs::Permissions::from_mode(0o444)).unwrap();
+        assert!(is_read_only(&file));
+
+        make_writable(&file).unwrap();
+        assert!(!is_read_only(&file));
+        assert_eq!(
+            fs::metadata(&file).unwrap().permissions().mode() & 0o777,
+            0o644
+        );
+    }
 }
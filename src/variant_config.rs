@@ -1,7 +1,7 @@
 //! Functions to read and parse variant configuration files.
 
 use std::{
-    collections::{BTreeMap, HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
     path::{Path, PathBuf},
 };
 
@@ -15,7 +15,7 @@ use thiserror::Error;
 use crate::{
     _partialerror,
     conda_build_config::load_conda_build_config,
-    consts::CONDA_BUILD_CONFIG_FILE,
+    consts::{CONDA_BUILD_CONFIG_FILE, VARIANTS_CONFIG_FILE},
     normalized_key::NormalizedKey,
     recipe::{
         custom_yaml::{HasSpan, Node, RenderedMappingNode, RenderedNode, TryConvertNode},
@@ -165,17 +165,33 @@ impl TryConvertNode<Pin> for RenderedMappingNode {
 /// [python=3.8, compiler=clang]
 /// ```
 ///
-/// It's also possible to specify additional pins in the variant configuration. These pins are
-/// currently ignored.
+/// It's also possible to specify additional pins in the variant configuration (`pin_run_as_build`).
+/// These pins are applied to the run dependencies when the dependencies are finalized.
+///
+/// The order in which the variants are built can be changed with `prioritize_variants`, a list of
+/// (partial) variant combinations. Variants that match an earlier entry are built first:
+///
+/// ```yaml
+/// prioritize_variants:
+/// - python: "3.12"
+/// - python: "3.11"
+/// ```
 #[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct VariantConfig {
-    /// Pin run dependencies by using the versions from the build dependencies (and applying the pin).
-    /// This is currently ignored (TODO)
+    /// Pin run dependencies by using the versions from the host dependencies (and applying the
+    /// pin). Run dependencies without constraints on packages that are listed here and are
+    /// direct host dependencies are pinned with the `min_pin` and `max_pin` of the entry.
     pub pin_run_as_build: Option<BTreeMap<String, Pin>>,
 
     /// The zip keys are used to "zip" together variants to create specific combinations.
     pub zip_keys: Option<Vec<Vec<NormalizedKey>>>,
 
+    /// Variant combinations that are built first. The variants of an output are ordered by the
+    /// first entry that they match (an entry matches if all keys that are used by the variant
+    /// have the same value), variants that match no entry come last.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prioritize_variants: Option<Vec<BTreeMap<NormalizedKey, String>>>,
+
     /// The variants are a mapping of package names to a list of versions. Each version represents
     /// a variant for the build matrix.
     #[serde(flatten)]
@@ -198,9 +214,46 @@ pub enum VariantConfigError {
     #[error(transparent)]
     #[diagnostic(transparent)]
     NewParseError(#[from] ParsingError),
+
+    #[error("`channel_sources` must have a single value, found {0}")]
+    InvalidChannelSources(usize),
+
+    #[error("Could not parse variant config file ({0})")]
+    InvalidConfigFile(PathBuf, #[related] Vec<ParsingError>),
+
+    #[error("Invalid migration file ({0}): {1}")]
+    InvalidMigration(PathBuf, String),
+
+    #[error("Invalid selector `{selector}` on line {line} of {path}: {source}")]
+    InvalidSelector {
+        path: PathBuf,
+        line: usize,
+        selector: String,
+        source: minijinja::Error,
+    },
 }
 
 impl VariantConfig {
+    /// Returns the channels of the `channel_sources` key (e.g.
+    /// `conda-forge/label/rc,conda-forge`), like on conda-forge. The key is not
+    /// part of the build matrix, so it can only have a single value.
+    pub fn channel_sources(&self) -> Result<Vec<String>, VariantConfigError> {
+        match self
+            .variants
+            .get(&NormalizedKey::from("channel_sources"))
+            .map(Vec::as_slice)
+        {
+            None | Some([]) => Ok(Vec::new()),
+            Some([channel_sources]) => Ok(channel_sources
+                .split(',')
+                .map(str::trim)
+                .filter(|channel| !channel.is_empty())
+                .map(ToString::to_string)
+                .collect()),
+            Some(values) => Err(VariantConfigError::InvalidChannelSources(values.len())),
+        }
+    }
+
     /// This function loads a single variant configuration file and returns the configuration.
     fn load_file(
         path: &Path,
@@ -221,18 +274,29 @@ impl VariantConfig {
             .map_err(|e| VariantConfigError::IOError(path.to_path_buf(), e))?;
         let yaml_node = Node::parse_yaml(0, &file)?;
         let jinja = Jinja::new(selector_config.clone());
+        let invalid_file = |e| {
+            VariantConfigError::InvalidConfigFile(
+                path.to_path_buf(),
+                ParsingError::from_partial_vec(&file, e),
+            )
+        };
         let rendered_node: RenderedNode = yaml_node
             .render(&jinja, path.to_string_lossy().as_ref())
-            .map_err(|e| ParseErrors::from_partial_vec(&file, e))?;
+            .map_err(invalid_file)?;
         let config: VariantConfig = rendered_node
             .try_convert(path.to_string_lossy().as_ref())
-            .map_err(|e| {
-                let parse_errors: ParseErrors = ParsingError::from_partial_vec(&file, e).into();
-                parse_errors
-            })?;
+            .map_err(invalid_file)?;
         Ok(config)
     }
 
+    /// Returns the platform specific variant configuration file that is loaded
+    /// on top of a `variants.yaml` file: `variants-<platform>.yaml` in the same
+    /// folder (e.g. `variants-linux-64.yaml`).
+    pub fn platform_file(path: &Path, platform: Platform) -> Option<PathBuf> {
+        (path.file_name()? == VARIANTS_CONFIG_FILE)
+            .then(|| path.with_file_name(format!("variants-{platform}.yaml")))
+    }
+
     /// This function loads an old-style variant configuration file and returns the configuration.
     fn load_conda_build_config(
         path: &Path,
@@ -283,6 +347,11 @@ impl VariantConfig {
     /// loaded in the order they are provided in the `files` argument. The keys of a later file
     /// replace keys from an earlier file (values are _not_ merged).
     ///
+    /// A `variants.yaml` file is directly followed by the `variants-<platform>.yaml` file for the
+    /// target platform in the same folder, if it exists (see [`VariantConfig::platform_file`]).
+    /// Its keys take precedence over the `variants.yaml` file, but not over the files that come
+    /// later in `files`.
+    ///
     /// A special key, the `zip_keys` is used to "zip" the values of two keys. For example, if the
     /// following configuration file is loaded:
     ///
@@ -314,6 +383,15 @@ impl VariantConfig {
             tracing::info!("Loading variant config file: {:?}", filename);
             let config = Self::load_file(filename, selector_config)?;
             variant_configs.push(config);
+
+            if let Some(platform_file) =
+                Self::platform_file(filename, selector_config.target_platform)
+            {
+                if platform_file.is_file() && !files.contains(&platform_file) {
+                    tracing::info!("Loading variant config file: {:?}", platform_file);
+                    variant_configs.push(Self::load_file(&platform_file, selector_config)?);
+                }
+            }
         }
 
         let mut final_config = VariantConfig::default();
@@ -327,6 +405,9 @@ impl VariantConfig {
                 }
             }
             final_config.zip_keys = config.zip_keys;
+            if config.prioritize_variants.is_some() {
+                final_config.prioritize_variants = config.prioritize_variants;
+            }
         }
 
         // always insert target_platform and build_platform
@@ -345,25 +426,85 @@ impl VariantConfig {
     fn validate_zip_keys(&self) -> Result<(), VariantError> {
         if let Some(zip_keys) = &self.zip_keys {
             for zip in zip_keys {
-                let mut prev_len = None;
+                let mut prev: Option<(&NormalizedKey, usize)> = None;
                 for key in zip {
                     let value = match self.variants.get(key) {
-                        None => return Err(VariantError::InvalidZipKeyLength(key.normalize())),
+                        None => {
+                            return Err(VariantError::InvalidZipKeyLength(format!(
+                                "`{}` has no values",
+                                key.normalize()
+                            )))
+                        }
                         Some(value) => value,
                     };
 
-                    if let Some(l) = prev_len {
-                        if l != value.len() {
-                            return Err(VariantError::InvalidZipKeyLength(key.normalize()));
+                    if let Some((prev_key, prev_len)) = prev {
+                        if prev_len != value.len() {
+                            return Err(VariantError::InvalidZipKeyLength(format!(
+                                "`{}` has {} values, but `{}` has {}",
+                                key.normalize(),
+                                value.len(),
+                                prev_key.normalize(),
+                                prev_len
+                            )));
                         }
                     }
-                    prev_len = Some(value.len());
+                    prev = Some((key, value.len()));
                 }
             }
         }
         Ok(())
     }
 
+    /// Returns the priority of a variant (lower is built first), i.e. the index of the first
+    /// entry of `prioritize_variants` that matches the variant.
+    pub fn variant_priority(&self, variant: &BTreeMap<NormalizedKey, String>) -> usize {
+        let Some(priorities) = &self.prioritize_variants else {
+            return 0;
+        };
+        priorities
+            .iter()
+            .position(|entry| {
+                entry
+                    .iter()
+                    .all(|(key, value)| variant.get(key).map_or(true, |v| v == value))
+            })
+            .unwrap_or(priorities.len())
+    }
+
+    /// Reorder the variants of each output by their priority. The outputs keep their
+    /// (topologically sorted) positions, only the variants of the same output are swapped.
+    fn prioritize(&self, outputs: IndexSet<DiscoveredOutput>) -> IndexSet<DiscoveredOutput> {
+        if self.prioritize_variants.is_none() {
+            return outputs;
+        }
+
+        let names = outputs
+            .iter()
+            .map(|output| output.name.clone())
+            .collect::<Vec<_>>();
+        let mut variants = HashMap::<String, Vec<DiscoveredOutput>>::new();
+        for output in outputs {
+            variants
+                .entry(output.name.clone())
+                .or_default()
+                .push(output);
+        }
+        let mut variants = variants
+            .into_iter()
+            .map(|(name, mut outputs)| {
+                // stable sort, variants with the same priority keep their order
+                outputs.sort_by_key(|output| self.variant_priority(&output.used_vars));
+                (name, VecDeque::from(outputs))
+            })
+            .collect::<HashMap<_, _>>();
+
+        names
+            .iter()
+            .filter_map(|name| variants.get_mut(name)?.pop_front())
+            .collect()
+    }
+
     /// This function returns all possible combinations of variants for the given set of used
     /// variables.
     ///
@@ -375,6 +516,20 @@ impl VariantConfig {
         used_vars: &HashSet<NormalizedKey>,
         already_used_vars: Option<&BTreeMap<NormalizedKey, String>>,
     ) -> Result<Vec<BTreeMap<NormalizedKey, String>>, VariantError> {
+        Ok(self
+            .combinations_iter(used_vars, already_used_vars)?
+            .collect())
+    }
+
+    /// Same as [`VariantConfig::combinations`], but the combinations are
+    /// produced lazily. The `already_used_vars` restrict the values of the
+    /// variant keys up front, so that only the matching combinations are ever
+    /// created.
+    pub(crate) fn combinations_iter(
+        &self,
+        used_vars: &HashSet<NormalizedKey>,
+        already_used_vars: Option<&BTreeMap<NormalizedKey, String>>,
+    ) -> Result<VariantCombinations, VariantError> {
         self.validate_zip_keys()?;
         let zip_keys = self.zip_keys.clone().unwrap_or_default();
         let used_zip_keys = zip_keys
@@ -411,39 +566,7 @@ impl VariantConfig {
             .chain(variant_keys)
             .collect::<Vec<_>>();
 
-        // get all combinations of variant keys
-        let mut combinations = Vec::new();
-        let mut current = Vec::new();
-        find_combinations(&variant_keys, 0, &mut current, &mut combinations);
-
-        // zip the combinations
-        let result: Vec<_> = combinations
-            .iter()
-            .map(|combination| {
-                combination
-                    .iter()
-                    .cloned()
-                    .collect::<BTreeMap<NormalizedKey, String>>()
-            })
-            .collect();
-
-        if let Some(already_used_vars) = already_used_vars {
-            let result = result
-                .into_iter()
-                .filter(|combination| {
-                    if already_used_vars.is_empty() {
-                        true
-                    } else {
-                        already_used_vars
-                            .iter()
-                            .all(|(key, value)| combination.get(key).map_or(false, |v| v == value))
-                    }
-                })
-                .collect();
-            Ok(result)
-        } else {
-            Ok(result)
-        }
+        Ok(VariantCombinations::new(variant_keys, already_used_vars))
     }
 
     /// This function finds all used variables in a recipe and expands the recipe to the full
@@ -457,20 +580,25 @@ impl VariantConfig {
     /// 3. The build string of the package.
     /// 4. The recipe node.
     /// 5. The used variant config.
+    ///
+    /// The `recipe_dir` is used to find script files that opt in to variable
+    /// tracking with `build.script.track_variables`.
     pub fn find_variants(
         &self,
         outputs: &[Node],
         recipe: &str,
+        recipe_dir: Option<&Path>,
         selector_config: &SelectorConfig,
     ) -> Result<IndexSet<DiscoveredOutput>, VariantError> {
         // find all jinja variables
-        let stage_0 = stage_0_render(outputs, recipe, selector_config, self)?;
+        let stage_0 = stage_0_render(outputs, recipe, recipe_dir, selector_config, self)?;
         let stage_1 = stage_1_render(stage_0, selector_config, self)?;
 
         // Now we need to convert the stage 1 renders to DiscoveredOutputs
         let mut recipes = IndexSet::new();
+        let mut seen = HashSet::new();
         for sx in stage_1 {
-            for ((node, mut recipe), variant) in sx.into_sorted_outputs()? {
+            for ((node, mut recipe), variant) in sx.into_sorted_outputs(&mut seen)? {
                 let target_platform = if recipe.build().noarch().is_none() {
                     selector_config.target_platform
                 } else {
@@ -492,6 +620,11 @@ impl VariantConfig {
                         .insert("python".parse().unwrap());
                 }
 
+                let hash = HashInfo::from_variant_with_config(
+                    &variant,
+                    recipe.build().noarch(),
+                    &selector_config.hash_config,
+                );
                 recipes.insert(DiscoveredOutput {
                     name: recipe.package().name.as_normalized().to_string(),
                     version: recipe.package().version.to_string(),
@@ -499,14 +632,14 @@ impl VariantConfig {
                     noarch_type: *recipe.build().noarch(),
                     target_platform,
                     node,
-                    used_vars: variant.clone(),
-                    recipe: recipe.clone(),
-                    hash: HashInfo::from_variant(&variant, recipe.build().noarch()),
+                    used_vars: variant,
+                    recipe,
+                    hash,
                 });
             }
         }
 
-        Ok(recipes)
+        Ok(self.prioritize(recipes))
     }
 }
 
@@ -521,6 +654,8 @@ impl TryConvertNode<VariantConfig> for RenderedNode {
 impl TryConvertNode<VariantConfig> for RenderedMappingNode {
     fn try_convert(&self, _name: &str) -> Result<VariantConfig, Vec<PartialParsingError>> {
         let mut config = VariantConfig::default();
+        let mut zip_key_spans = Vec::new();
+        let mut variant_spans = HashMap::new();
 
         for (key, value) in self.iter() {
             let key_str = key.as_str();
@@ -529,21 +664,154 @@ impl TryConvertNode<VariantConfig> for RenderedMappingNode {
                     config.pin_run_as_build = value.try_convert(key_str)?;
                 }
                 "zip_keys" => {
-                    config.zip_keys = value.try_convert(key_str)?;
+                    let zips = parse_zip_keys(value)?;
+                    config.zip_keys = (!zips.is_empty()).then(|| {
+                        zips.iter()
+                            .map(|zip| zip.iter().map(|(key, _)| key.clone()).collect())
+                            .collect()
+                    });
+                    zip_key_spans = zips;
+                }
+                "prioritize_variants" => {
+                    let entries: Vec<BTreeMap<String, String>> = match value {
+                        RenderedNode::Null(_) => Vec::new(),
+                        RenderedNode::Sequence(sequence) => sequence
+                            .iter()
+                            .map(|entry| entry.try_convert(key_str))
+                            .collect::<Result<_, _>>()?,
+                        _ => {
+                            return Err(vec![_partialerror!(
+                                *value.span(),
+                                ErrorKind::ExpectedSequence,
+                                help = "`prioritize_variants` has to be a list of variant combinations (e.g. `- python: \"3.12\"`)"
+                            )])
+                        }
+                    };
+                    config.prioritize_variants = Some(
+                        entries
+                            .into_iter()
+                            .map(|entry| {
+                                entry
+                                    .into_iter()
+                                    .map(|(key, value)| (key.into(), value))
+                                    .collect()
+                            })
+                            .collect(),
+                    );
                 }
                 _ => {
                     let variants: Option<Vec<_>> = value.try_convert(key_str)?;
                     if let Some(variants) = variants {
+                        variant_spans.insert(NormalizedKey::from(key_str), *value.span());
                         config.variants.insert(key_str.into(), variants.clone());
                     }
                 }
             }
         }
 
+        // zipped keys that are defined in the same file need to have the same
+        // number of values (keys from other files are checked after merging)
+        let mut errors = Vec::new();
+        for zip in &zip_key_spans {
+            let defined = zip
+                .iter()
+                .filter_map(|(key, _)| Some((key, config.variants.get(key)?.len())))
+                .collect::<Vec<_>>();
+            let Some((first_key, first_len)) = defined.first() else {
+                continue;
+            };
+            for (key, len) in &defined[1..] {
+                if len != first_len {
+                    errors.push(_partialerror!(
+                        variant_spans[*key],
+                        ErrorKind::Other,
+                        label = format!(
+                            "`{}` has {} values, but `{}` has {}",
+                            key.normalize(),
+                            len,
+                            first_key.normalize(),
+                            first_len
+                        ),
+                        help = "keys that are zipped together in `zip_keys` need to have the same number of values"
+                    ));
+                }
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
         Ok(config)
     }
 }
 
+/// Parse the `zip_keys` entry. It is either a single list of keys that are
+/// zipped together (`[python, numpy]`) or a list of such lists. Conditional
+/// entries are already flattened, so a conditional zip has to be written as a
+/// nested list (`then: [[python, numpy]]`).
+fn parse_zip_keys(
+    node: &RenderedNode,
+) -> Result<Vec<Vec<(NormalizedKey, marked_yaml::Span)>>, Vec<PartialParsingError>> {
+    let parse_key = |node: &RenderedNode| -> Result<_, Vec<PartialParsingError>> {
+        let scalar = node.as_scalar().ok_or_else(|| {
+            vec![_partialerror!(
+                *node.span(),
+                ErrorKind::ExpectedScalar,
+                help = "zip keys have to be the names of variant keys"
+            )]
+        })?;
+        Ok((NormalizedKey::from(scalar.as_str()), *node.span()))
+    };
+
+    let sequence = match node {
+        RenderedNode::Null(_) => return Ok(Vec::new()),
+        RenderedNode::Sequence(sequence) => sequence,
+        _ => {
+            return Err(vec![_partialerror!(
+                *node.span(),
+                ErrorKind::ExpectedSequence,
+                help = "`zip_keys` has to be a list of keys or a list of lists of keys"
+            )])
+        }
+    };
+
+    let zips = if sequence.iter().all(|item| item.as_scalar().is_some()) {
+        // a single zip (e.g. `zip_keys: [python, numpy]`)
+        vec![sequence
+            .iter()
+            .map(parse_key)
+            .collect::<Result<Vec<_>, _>>()?]
+    } else {
+        sequence
+            .iter()
+            .map(|item| {
+                let Some(zip) = item.as_sequence() else {
+                    return Err(vec![_partialerror!(
+                        *item.span(),
+                        ErrorKind::ExpectedSequence,
+                        label = "expected a list of keys",
+                        help = "all entries of `zip_keys` have to be lists of keys when there is more than one zip (use `then: [[python, numpy]]` for a conditional zip)"
+                    )]);
+                };
+                zip.iter().map(parse_key).collect::<Result<Vec<_>, _>>()
+            })
+            .collect::<Result<Vec<_>, _>>()?
+    };
+
+    // a zip with a single key has no effect, but was always accepted
+    for zip in zips.iter().filter(|zip| zip.len() < 2) {
+        tracing::warn!(
+            "Ignoring the zip of `{}` in `zip_keys`, a zip needs at least two keys",
+            zip.iter()
+                .map(|(key, _)| key.normalize())
+                .collect::<Vec<_>>()
+                .join("`, `")
+        );
+    }
+
+    Ok(zips)
+}
+
 #[derive(Debug, Clone)]
 enum VariantKey {
     Key(NormalizedKey, Vec<String>),
@@ -558,6 +826,21 @@ impl VariantKey {
         }
     }
 
+    pub fn contains(&self, key: &NormalizedKey) -> bool {
+        match self {
+            VariantKey::Key(name, _) => name == key,
+            VariantKey::ZipKey(map) => map.contains_key(key),
+        }
+    }
+
+    pub fn value_at(&self, key: &NormalizedKey, index: usize) -> Option<&String> {
+        match self {
+            VariantKey::Key(name, values) if name == key => values.get(index),
+            VariantKey::Key(..) => None,
+            VariantKey::ZipKey(map) => map.get(key)?.get(index),
+        }
+    }
+
     pub fn at(&self, index: usize) -> Option<Vec<(NormalizedKey, String)>> {
         match self {
             VariantKey::Key(key, values) => {
@@ -632,25 +915,73 @@ pub enum VariantError {
     CycleInRecipeOutputs(String),
 }
 
-fn find_combinations(
-    variant_keys: &[VariantKey],
-    index: usize,
-    current: &mut Vec<(NormalizedKey, String)>,
-    result: &mut Vec<Vec<(NormalizedKey, String)>>,
-) {
-    if index == variant_keys.len() {
-        result.push(current.clone());
-        return;
-    }
-
-    for i in 0..variant_keys[index].len() {
-        if let Some(items) = variant_keys[index].at(i) {
-            current.extend(items.clone());
-            find_combinations(variant_keys, index + 1, current, result);
-            for _ in 0..items.len() {
-                current.pop();
+/// A lazy iterator over all combinations of the values of the variant keys.
+/// The first key changes slowest and the last key fastest.
+pub(crate) struct VariantCombinations {
+    keys: Vec<VariantKey>,
+    /// The indices of the values of every key that take part in the combinations
+    indices: Vec<Vec<usize>>,
+    /// The position in `indices` of every key, `None` once all combinations are produced
+    position: Option<Vec<usize>>,
+}
+
+impl VariantCombinations {
+    fn new(
+        keys: Vec<VariantKey>,
+        already_used_vars: Option<&BTreeMap<NormalizedKey, String>>,
+    ) -> Self {
+        let mut indices = keys
+            .iter()
+            .map(|key| (0..key.len()).filter(|i| key.at(*i).is_some()).collect())
+            .collect::<Vec<Vec<usize>>>();
+
+        // only keep the values that match the already used variables. If an already used
+        // variable is not part of the keys, no combination can match it.
+        let mut exhausted = false;
+        for (name, value) in already_used_vars.into_iter().flatten() {
+            match keys.iter().position(|key| key.contains(name)) {
+                Some(pos) => indices[pos].retain(|i| keys[pos].value_at(name, *i) == Some(value)),
+                None => exhausted = true,
             }
         }
+        let exhausted = exhausted || indices.iter().any(Vec::is_empty);
+
+        Self {
+            position: (!exhausted).then(|| vec![0; keys.len()]),
+            keys,
+            indices,
+        }
+    }
+}
+
+impl Iterator for VariantCombinations {
+    type Item = BTreeMap<NormalizedKey, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let position = self.position.as_mut()?;
+        let combination = self
+            .keys
+            .iter()
+            .zip(position.iter())
+            .zip(&self.indices)
+            .flat_map(|((key, pos), indices)| key.at(indices[*pos]).unwrap_or_default())
+            .collect();
+
+        // advance like an odometer, starting with the last key
+        let mut exhausted = true;
+        for (pos, indices) in position.iter_mut().zip(&self.indices).rev() {
+            *pos += 1;
+            if *pos < indices.len() {
+                exhausted = false;
+                break;
+            }
+            *pos = 0;
+        }
+        if exhausted {
+            self.position = None;
+        }
+
+        Some(combination)
     }
 }
 
@@ -710,6 +1041,48 @@ mod tests {
         insta::assert_yaml_snapshot!(variant);
     }
 
+    #[test]
+    fn test_load_platform_config() {
+        let tmp = tempfile::tempdir().unwrap();
+        let variants = tmp.path().join("variants.yaml");
+        fs_err::write(&variants, "python:\n  - \"3.9\"\nfoo:\n  - bar\n").unwrap();
+        fs_err::write(
+            tmp.path().join("variants-linux-64.yaml"),
+            "python:\n  - \"3.12\"\n",
+        )
+        .unwrap();
+
+        let selector_config = |platform| SelectorConfig {
+            target_platform: platform,
+            host_platform: platform,
+            build_platform: platform,
+            ..Default::default()
+        };
+        let values =
+            |config: &VariantConfig, key: &str| config.variants[&NormalizedKey::from(key)].clone();
+
+        let linux =
+            VariantConfig::from_files(&[variants.clone()], &selector_config(Platform::Linux64))
+                .unwrap();
+        assert_eq!(values(&linux, "python"), vec!["3.12"]);
+        assert_eq!(values(&linux, "foo"), vec!["bar"]);
+
+        let osx =
+            VariantConfig::from_files(&[variants.clone()], &selector_config(Platform::OsxArm64))
+                .unwrap();
+        assert_eq!(values(&osx, "python"), vec!["3.9"]);
+
+        // a later file still takes precedence over the platform file
+        let override_file = tmp.path().join("override.yaml");
+        fs_err::write(&override_file, "python:\n  - \"3.13\"\n").unwrap();
+        let config = VariantConfig::from_files(
+            &[variants, override_file],
+            &selector_config(Platform::Linux64),
+        )
+        .unwrap();
+        assert_eq!(values(&config, "python"), vec!["3.13"]);
+    }
+
     #[test]
     fn test_load_config_and_find_variants() {
         let test_data_dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("test-data");
@@ -727,7 +1100,7 @@ mod tests {
         let outputs = crate::recipe::parser::find_outputs_from_src(&recipe_text).unwrap();
         let variant_config = VariantConfig::from_files(&[yaml_file], &selector_config).unwrap();
         let outputs_and_variants = variant_config
-            .find_variants(&outputs, &recipe_text, &selector_config)
+            .find_variants(&outputs, &recipe_text, None, &selector_config)
             .unwrap();
 
         let used_variables_all: Vec<&BTreeMap<NormalizedKey, String>> = outputs_and_variants
@@ -753,6 +1126,7 @@ mod tests {
             variants,
             zip_keys: Some(zip_keys),
             pin_run_as_build: None,
+            prioritize_variants: None,
         };
 
         let combinations = config.combinations(&used_vars, None).unwrap();
@@ -790,6 +1164,69 @@ mod tests {
         assert!(c2.len() == 2 * 3);
     }
 
+    #[test]
+    fn test_lazy_variant_combinations() {
+        let variants = BTreeMap::from_iter(vec![
+            (
+                "python".into(),
+                vec!["3.11".to_string(), "3.12".to_string()],
+            ),
+            ("numpy".into(), vec!["1.26".to_string(), "2.0".to_string()]),
+            (
+                "cuda".into(),
+                vec!["None".to_string(), "11.8".to_string(), "12.0".to_string()],
+            ),
+            (
+                "arch".into(),
+                vec!["x86_64".to_string(), "aarch64".to_string()],
+            ),
+        ]);
+        let config = VariantConfig {
+            variants,
+            zip_keys: Some(vec![vec!["python".into(), "numpy".into()]]),
+            pin_run_as_build: None,
+            prioritize_variants: None,
+        };
+        let used_vars: HashSet<NormalizedKey> = ["python", "numpy", "cuda", "arch"]
+            .into_iter()
+            .map(Into::into)
+            .collect();
+
+        let all = config.combinations(&used_vars, None).unwrap();
+        assert_eq!(all.len(), 2 * 3 * 2);
+        assert_eq!(all.iter().collect::<HashSet<_>>().len(), all.len());
+
+        // the already used variables select the matching combinations, in the same order
+        let already_used_vars = BTreeMap::from_iter(vec![
+            ("numpy".into(), "2.0".to_string()),
+            ("cuda".into(), "12.0".to_string()),
+        ]);
+        let expected = all
+            .iter()
+            .filter(|c| already_used_vars.iter().all(|(k, v)| c.get(k) == Some(v)))
+            .cloned()
+            .collect::<Vec<_>>();
+        let selected = config
+            .combinations(&used_vars, Some(&already_used_vars))
+            .unwrap();
+        assert_eq!(selected, expected);
+        assert_eq!(selected.len(), 2);
+        assert!(selected
+            .iter()
+            .all(|c| c[&NormalizedKey::from("python")] == "3.12"));
+
+        // an already used variable that is not part of the combinations matches nothing
+        let already_used_vars = BTreeMap::from_iter(vec![("rust".into(), "1.80".to_string())]);
+        assert!(config
+            .combinations(&used_vars, Some(&already_used_vars))
+            .unwrap()
+            .is_empty());
+
+        // without any used variables there is a single, empty combination
+        let combinations = config.combinations(&HashSet::new(), None).unwrap();
+        assert_eq!(combinations, vec![BTreeMap::new()]);
+    }
+
     #[test]
     fn test_order() {
         let test_data_dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("test-data");
@@ -808,7 +1245,7 @@ mod tests {
             let outputs = crate::recipe::parser::find_outputs_from_src(&recipe_text).unwrap();
             let variant_config = VariantConfig::from_files(&[], &selector_config).unwrap();
             let outputs_and_variants = variant_config
-                .find_variants(&outputs, &recipe_text, &selector_config)
+                .find_variants(&outputs, &recipe_text, None, &selector_config)
                 .unwrap();
 
             // assert output order
@@ -839,7 +1276,7 @@ mod tests {
         let outputs = crate::recipe::parser::find_outputs_from_src(&recipe_text).unwrap();
         let variant_config = VariantConfig::from_files(&[yaml_file], &selector_config).unwrap();
         let outputs_and_variants = variant_config
-            .find_variants(&outputs, &recipe_text, &selector_config)
+            .find_variants(&outputs, &recipe_text, None, &selector_config)
             .unwrap();
 
         let used_variables_all: Vec<&BTreeMap<NormalizedKey, String>> = outputs_and_variants
@@ -850,4 +1287,139 @@ mod tests {
 
         insta::assert_yaml_snapshot!(used_variables_all);
     }
+
+    fn convert_variant_config(yaml: &str) -> Result<VariantConfig, Vec<PartialParsingError>> {
+        let yaml = Node::parse_yaml(0, yaml).unwrap();
+        let selector_config = SelectorConfig {
+            target_platform: Platform::Linux64,
+            host_platform: Platform::Linux64,
+            build_platform: Platform::Linux64,
+            ..Default::default()
+        };
+        let jinja = Jinja::new(selector_config);
+        let rendered: RenderedNode = yaml.render(&jinja, "variants").unwrap();
+        rendered.try_convert("variants")
+    }
+
+    #[test]
+    fn test_conditional_zip_keys() {
+        let config = convert_variant_config(
+            r#"
+zip_keys:
+  - if: unix
+    then: [[python, numpy]]
+  - if: win
+    then: [[c_compiler, cxx_compiler]]
+python: ["3.8", "3.9"]
+numpy: ["1.12", "1.14"]
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.zip_keys,
+            Some(vec![vec!["python".into(), "numpy".into()]])
+        );
+
+        // a flat list is a single zip
+        let config = convert_variant_config(
+            r#"
+zip_keys: [python, numpy]
+python: ["3.8", "3.9"]
+numpy: ["1.12", "1.14"]
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.zip_keys,
+            Some(vec![vec!["python".into(), "numpy".into()]])
+        );
+
+        // mixing keys and lists of keys is ambiguous
+        assert!(convert_variant_config("zip_keys: [[python, numpy], c_compiler]").is_err());
+
+        // zips with a single key have no effect, but are still accepted
+        let config = convert_variant_config("zip_keys: [[python], [numpy, c_compiler]]").unwrap();
+        assert_eq!(
+            config.zip_keys,
+            Some(vec![
+                vec!["python".into()],
+                vec!["numpy".into(), "c_compiler".into()]
+            ])
+        );
+    }
+
+    #[test]
+    fn test_zip_keys_length_mismatch() {
+        let yaml = r#"
+zip_keys: [[python, numpy]]
+python: ["3.8", "3.9"]
+numpy: ["1.12", "1.14", "1.16"]
+"#;
+        let errors = convert_variant_config(yaml).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].label.as_deref(),
+            Some("`numpy` has 3 values, but `python` has 2")
+        );
+        // the error points at the values of `numpy`
+        let line = errors[0].span.start().unwrap().line();
+        assert_eq!(
+            yaml.lines().nth(line - 1),
+            Some(r#"numpy: ["1.12", "1.14", "1.16"]"#)
+        );
+    }
+
+    #[test]
+    fn test_prioritize_variants() {
+        let config = convert_variant_config(
+            r#"
+prioritize_variants:
+  - python: "3.12"
+    numpy: "2.0"
+  - if: unix
+    then:
+      - python: "3.11"
+python: ["3.10", "3.11", "3.12"]
+numpy: ["1.26", "2.0"]
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.prioritize_variants.as_ref().unwrap().len(), 2);
+
+        let variant = |python: &str, numpy: Option<&str>| {
+            let mut variant = BTreeMap::<NormalizedKey, String>::new();
+            variant.insert("python".into(), python.to_string());
+            if let Some(numpy) = numpy {
+                variant.insert("numpy".into(), numpy.to_string());
+            }
+            variant
+        };
+        assert_eq!(config.variant_priority(&variant("3.12", Some("2.0"))), 0);
+        // keys that are not used by the variant are ignored
+        assert_eq!(config.variant_priority(&variant("3.12", None)), 0);
+        assert_eq!(config.variant_priority(&variant("3.12", Some("1.26"))), 2);
+        assert_eq!(config.variant_priority(&variant("3.11", Some("1.26"))), 1);
+        assert_eq!(config.variant_priority(&variant("3.10", None)), 2);
+    }
+
+    #[test]
+    fn test_channel_sources() {
+        let mut variant_config = VariantConfig::default();
+        assert!(variant_config.channel_sources().unwrap().is_empty());
+
+        variant_config.variants.insert(
+            "channel_sources".into(),
+            vec!["conda-forge/label/rc, conda-forge".to_string()],
+        );
+        assert_eq!(
+            variant_config.channel_sources().unwrap(),
+            vec!["conda-forge/label/rc", "conda-forge"]
+        );
+
+        variant_config.variants.insert(
+            "channel_sources".into(),
+            vec!["conda-forge".to_string(), "bioconda".to_string()],
+        );
+        assert!(variant_config.channel_sources().is_err());
+    }
 }
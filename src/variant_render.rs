@@ -1,10 +1,13 @@
 use std::{
     collections::{BTreeMap, HashMap, HashSet},
-    path::PathBuf,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, Mutex},
 };
 
 use petgraph::graph::DiGraph;
 use rattler_conda_types::PackageName;
+use rayon::iter::{ParallelBridge, ParallelIterator};
 
 use crate::{
     env_vars,
@@ -15,7 +18,7 @@ use crate::{
         parser::{BuildString, Dependency},
         Jinja, ParsingError, Recipe,
     },
-    selectors::SelectorConfig,
+    selectors::{SelectorConfig, ENV_VARIANT_PREFIX, NOARCH_PLATFORM_KEY},
     used_variables::used_vars_from_expressions,
     variant_config::{ParseErrors, VariantConfig, VariantError},
 };
@@ -40,8 +43,8 @@ pub struct Stage0Render {
     /// The used variables with their values
     pub variables: BTreeMap<NormalizedKey, String>,
 
-    /// The raw outputs of the recipe
-    pub raw_outputs: RawOutputVec,
+    /// The raw outputs of the recipe (shared by all renders of the recipe)
+    pub raw_outputs: Arc<RawOutputVec>,
 
     // Pre-rendered recipe nodes
     pub rendered_outputs: Vec<Recipe>,
@@ -50,13 +53,14 @@ pub struct Stage0Render {
 pub(crate) fn stage_0_render(
     outputs: &[Node],
     recipe: &str,
+    recipe_dir: Option<&Path>,
     selector_config: &SelectorConfig,
     variant_config: &VariantConfig,
 ) -> Result<Vec<Stage0Render>, VariantError> {
     let used_vars = outputs
         .iter()
         .map(|output| {
-            used_vars_from_expressions(output, recipe)
+            used_vars_from_expressions(output, recipe, recipe_dir)
                 .map(|x| x.into_iter().map(Into::into).collect())
         })
         .collect::<Result<Vec<HashSet<NormalizedKey>>, Vec<ParsingError>>>()
@@ -65,17 +69,17 @@ pub(crate) fn stage_0_render(
             VariantError::RecipeParseErrors(errs)
         })?;
 
-    let raw_output_vec = RawOutputVec {
+    let raw_output_vec = Arc::new(RawOutputVec {
         vec: outputs.to_vec(),
         used_vars_jinja: used_vars,
         recipe: recipe.to_string(),
-    };
+    });
 
     // find all the jinja variables from all the expressions
     let mut used_vars = HashSet::<NormalizedKey>::new();
     for output in outputs {
         used_vars.extend(
-            used_vars_from_expressions(output, recipe)
+            used_vars_from_expressions(output, recipe, recipe_dir)
                 .map_err(|errs| {
                     let errs: ParseErrors = errs.into();
                     VariantError::RecipeParseErrors(errs)
@@ -85,37 +89,48 @@ pub(crate) fn stage_0_render(
         );
     }
 
-    // Now we need to create all the combinations of the variables x variant config
-    let mut stage0_renders = Vec::new();
-    let combinations = variant_config.combinations(&used_vars, None)?;
-
-    for combination in combinations {
-        let mut rendered_outputs = Vec::new();
-        // TODO: figure out if we can pre-compute the `noarch` value.
-        for output in outputs {
-            let config_with_variant =
-                selector_config.with_variant(combination.clone(), selector_config.target_platform);
+    // Now we need to create all the combinations of the variables x variant config. They
+    // are rendered in parallel as they are produced, and put back in order afterwards.
+    let combinations = variant_config.combinations_iter(&used_vars, None)?;
+    let mut stage0_renders = combinations
+        .enumerate()
+        .par_bridge()
+        .map(|(index, combination)| {
+            let mut rendered_outputs = Vec::new();
+            // TODO: figure out if we can pre-compute the `noarch` value.
+            for output in outputs {
+                let config_with_variant = selector_config
+                    .with_variant(combination.clone(), selector_config.target_platform);
 
-            let parsed_recipe = Recipe::from_node(output, config_with_variant).map_err(|err| {
-                let errs: ParseErrors = err
-                    .into_iter()
-                    .map(|err| ParsingError::from_partial(recipe, err))
-                    .collect::<Vec<ParsingError>>()
-                    .into();
-                errs
-            })?;
+                let parsed_recipe =
+                    Recipe::from_node(output, config_with_variant).map_err(|err| {
+                        let errs: ParseErrors = err
+                            .into_iter()
+                            .map(|err| ParsingError::from_partial(recipe, err))
+                            .collect::<Vec<ParsingError>>()
+                            .into();
+                        errs
+                    })?;
 
-            rendered_outputs.push(parsed_recipe);
-        }
+                rendered_outputs.push(parsed_recipe);
+            }
 
-        stage0_renders.push(Stage0Render {
-            variables: combination,
-            raw_outputs: raw_output_vec.clone(),
-            rendered_outputs,
-        });
-    }
+            Ok::<_, VariantError>((
+                index,
+                Stage0Render {
+                    variables: combination,
+                    raw_outputs: raw_output_vec.clone(),
+                    rendered_outputs,
+                },
+            ))
+        })
+        .collect::<Result<Vec<_>, VariantError>>()?;
 
-    Ok(stage0_renders)
+    stage0_renders.sort_by_key(|(index, _)| *index);
+    Ok(stage0_renders
+        .into_iter()
+        .map(|(_, render)| render)
+        .collect())
 }
 
 #[derive(Debug, Clone)]
@@ -133,7 +148,7 @@ pub struct Stage1Render {
 
     pub(crate) inner: Vec<Stage1Inner>,
 
-    pub(crate) stage_0_render: Stage0Render,
+    pub(crate) stage_0_render: Arc<Stage0Render>,
 }
 
 impl Stage1Render {
@@ -161,6 +176,12 @@ impl Stage1Render {
         for var in all_vars {
             if let Some(val) = self.variables.get(&var) {
                 variant.insert(var, val.clone());
+            } else if let Some(env_var) = var.0.strip_prefix(ENV_VARIANT_PREFIX) {
+                // environment variables that are read by the recipe and are
+                // configured to be part of the hash
+                if let Some(val) = inner.selector_config.env_access.hashed_value(env_var) {
+                    variant.insert(var.clone(), val);
+                }
             }
         }
 
@@ -202,19 +223,32 @@ impl Stage1Render {
 
     pub fn build_string_for_output(&self, idx: usize) -> Result<String, VariantError> {
         let variant = self.variant_for_output(idx)?;
+        Ok(self.build_string_for_variant(idx, &variant))
+    }
+
+    /// The build string of an output with the variant from [`Self::variant_for_output`]
+    fn build_string_for_variant(
+        &self,
+        idx: usize,
+        variant: &BTreeMap<NormalizedKey, String>,
+    ) -> String {
         let recipe = &self.stage_0_render.rendered_outputs[idx];
-        let hash = HashInfo::from_variant(&variant, recipe.build().noarch());
         let inner = &self.inner[idx];
+        let hash = HashInfo::from_variant_with_config(
+            variant,
+            recipe.build().noarch(),
+            &inner.selector_config.hash_config,
+        );
 
         let mut selector_config = inner.selector_config.clone();
         selector_config.hash = Some(hash.clone());
         let jinja = Jinja::new(selector_config.clone()).with_context(&recipe.context);
 
-        Ok(recipe
+        recipe
             .build()
             .string()
             .resolve(&hash, recipe.build().number, &jinja)
-            .into_owned())
+            .into_owned()
     }
 
     /// sort the outputs topologically
@@ -276,31 +310,111 @@ impl Stage1Render {
         Ok(sorted_indices)
     }
 
+    /// The outputs in topological order, with their resolved build string and variant.
+    /// Outputs whose index and variant are already in `seen` were produced by an earlier
+    /// render (many variants only differ in keys that an output does not use) and are skipped.
     #[allow(clippy::type_complexity)]
     pub fn into_sorted_outputs(
         self,
+        seen: &mut HashSet<(usize, BTreeMap<NormalizedKey, String>)>,
     ) -> Result<Vec<((Node, Recipe), BTreeMap<NormalizedKey, String>)>, VariantError> {
         // zip node from stage0 and final render output
         let sorted_indices = self.sorted_indices()?;
 
-        let raw_nodes = self.stage_0_render.raw_outputs.vec.clone().into_iter();
-        let outputs = self.inner.clone().into_iter().map(|i| i.recipe);
-
-        let zipped = raw_nodes.zip(outputs).collect::<Vec<_>>();
         let mut result = Vec::new();
         for idx in sorted_indices {
-            let mut recipe = zipped[idx].clone();
-            let build_string = self.build_string_for_output(idx)?;
-            // Resolve the build string and store the resolved one in the recipe
-            recipe.1.build.string = BuildString::Resolved(build_string);
             let variant = self.variant_for_output(idx)?;
-            result.push((recipe, variant));
+            if !seen.insert((idx, variant.clone())) {
+                continue;
+            }
+            let mut recipe = self.inner[idx].recipe.clone();
+            // Resolve the build string and store the resolved one in the recipe
+            let build_string = self.build_string_for_variant(idx, &variant);
+            recipe.build.string = BuildString::Resolved(build_string);
+            let node = self.stage_0_render.raw_outputs.vec[idx].clone();
+            result.push(((node, recipe), variant));
         }
 
         Ok(result)
     }
 }
 
+/// Parses the outputs of a recipe for the stage 1 variants. An output only depends on the
+/// variables it uses (and the `noarch_platform`), so it is parsed once for every distinct
+/// subset of the variant and cloned for all other variants.
+struct OutputParser<'a> {
+    selector_config: &'a SelectorConfig,
+    noarch_key: NormalizedKey,
+    parsed: Mutex<HashMap<(usize, BTreeMap<NormalizedKey, String>), Recipe>>,
+}
+
+impl<'a> OutputParser<'a> {
+    fn new(selector_config: &'a SelectorConfig) -> Self {
+        Self {
+            selector_config,
+            noarch_key: NormalizedKey::from(NOARCH_PLATFORM_KEY),
+            parsed: Mutex::default(),
+        }
+    }
+
+    fn parse(
+        &self,
+        raw_outputs: &RawOutputVec,
+        idx: usize,
+        variant: BTreeMap<NormalizedKey, String>,
+    ) -> Result<(Recipe, SelectorConfig), VariantError> {
+        let used_vars = &raw_outputs.used_vars_jinja[idx];
+        let used_variant = variant
+            .iter()
+            .filter(|(key, _)| used_vars.contains(*key) || **key == self.noarch_key)
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect::<BTreeMap<_, _>>();
+        let cache_key = (idx, used_variant);
+
+        let config_with_variant = self
+            .selector_config
+            .with_variant(variant, self.selector_config.target_platform);
+        if let Some(recipe) = self.parsed.lock().unwrap().get(&cache_key) {
+            return Ok((recipe.clone(), config_with_variant));
+        }
+
+        let recipe = Recipe::from_node(&raw_outputs.vec[idx], config_with_variant.clone())
+            .map_err(|err| {
+                let errs: ParseErrors = err
+                    .into_iter()
+                    .map(|err| ParsingError::from_partial(&raw_outputs.recipe, err))
+                    .collect::<Vec<ParsingError>>()
+                    .into();
+                errs
+            })?;
+        self.parsed
+            .lock()
+            .unwrap()
+            .insert(cache_key, recipe.clone());
+        Ok((recipe, config_with_variant))
+    }
+}
+
+/// Returns true if the run dependency on `name` is pinned to the version in
+/// the host environment by `pin_run_as_build`: `name` is a host dependency
+/// and only a run dependency without a version or build constraint.
+fn pins_run_as_build(host: &[Dependency], run: &[Dependency], name: &PackageName) -> bool {
+    let specs = |deps: &[Dependency]| {
+        deps.iter()
+            .filter_map(|dep| match dep {
+                Dependency::Spec(spec) if spec.name.as_ref() == Some(name) => Some(spec.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+    let run = specs(run);
+    !specs(host).is_empty()
+        && !run.is_empty()
+        && run
+            .iter()
+            .all(|spec| spec.version.is_none() && spec.build.is_none())
+}
+
 /// Render the stage 1 of the recipe by adding in variants from the dependencies
 pub(crate) fn stage_1_render(
     stage0_renders: Vec<Stage0Render>,
@@ -308,6 +422,8 @@ pub(crate) fn stage_1_render(
     variant_config: &VariantConfig,
 ) -> Result<Vec<Stage1Render>, VariantError> {
     let mut stage_1_renders = Vec::new();
+    let noarch_key = NormalizedKey::from(NOARCH_PLATFORM_KEY);
+    let parser = OutputParser::new(selector_config);
 
     // TODO we need to add variables from the cache output here!
     for r in stage0_renders {
@@ -339,6 +455,25 @@ pub(crate) fn stage_1_render(
                 }
             }
 
+            // The run dependencies of `pin_run_as_build` packages are pinned to
+            // the version in the host environment, so their variant is part of
+            // the hash (see `apply_pin_run_as_build`)
+            if output.build().noarch().is_none() {
+                let requirements = output.requirements();
+                let pins = variant_config
+                    .pin_run_as_build
+                    .iter()
+                    .flat_map(BTreeMap::keys);
+                for name in pins {
+                    let Ok(name) = PackageName::from_str(name) else {
+                        continue;
+                    };
+                    if pins_run_as_build(requirements.host(), requirements.run(), &name) {
+                        additional_variables.insert(name.as_normalized().into());
+                    }
+                }
+            }
+
             // Add in extra `use` keys from the output
             let extra_use_keys = output
                 .build()
@@ -364,9 +499,16 @@ pub(crate) fn stage_1_render(
                 additional_variables.insert("CONDA_BUILD_SYSROOT".into());
             }
 
-            // also always add `target_platform` and `channel_targets`
+            // also always add `target_platform`, `channel_targets` and
+            // `channel_priority`
             additional_variables.insert("target_platform".into());
             additional_variables.insert("channel_targets".into());
+            additional_variables.insert("channel_priority".into());
+
+            // `noarch` packages can be built for multiple platform (families)
+            if !output.build().noarch().is_none() {
+                additional_variables.insert(NOARCH_PLATFORM_KEY.into());
+            }
 
             // Environment variables can be overwritten by the variant configuration
             let env_vars = env_vars::os_vars(&PathBuf::new(), &selector_config.target_platform);
@@ -394,41 +536,48 @@ pub(crate) fn stage_1_render(
 
         all_vars.extend(r.variables.keys().cloned());
 
-        let all_combinations = variant_config.combinations(&all_vars, Some(&r.variables))?;
-        for combination in all_combinations {
-            let mut inner = Vec::new();
-            // TODO: figure out if we can pre-compute the `noarch` value.
-            for (idx, output) in r.raw_outputs.vec.iter().enumerate() {
-                // use the correct target_platform here?
-                let config_with_variant = selector_config
-                    .with_variant(combination.clone(), selector_config.target_platform);
-
-                let parsed_recipe = Recipe::from_node(output, config_with_variant.clone())
-                    .map_err(|err| {
-                        let errs: ParseErrors = err
-                            .into_iter()
-                            .map(|err| ParsingError::from_partial(&r.raw_outputs.recipe, err))
-                            .collect::<Vec<ParsingError>>()
-                            .into();
-                        errs
-                    })?;
+        let r = Arc::new(r);
+        let combinations = variant_config.combinations_iter(&all_vars, Some(&r.variables))?;
+        let mut renders = combinations
+            .enumerate()
+            .par_bridge()
+            .map(|(index, combination)| {
+                let mut inner = Vec::new();
+                // TODO: figure out if we can pre-compute the `noarch` value.
+                for idx in 0..r.raw_outputs.vec.len() {
+                    // use the correct target_platform here?
+                    let parse = |variant| parser.parse(&r.raw_outputs, idx, variant);
+
+                    let (mut parsed_recipe, mut config_with_variant) = parse(combination.clone())?;
+
+                    // the `noarch_platform` only affects the selectors of `noarch` outputs
+                    if parsed_recipe.build().noarch().is_none()
+                        && combination.contains_key(&noarch_key)
+                    {
+                        let mut variant = combination.clone();
+                        variant.remove(&noarch_key);
+                        (parsed_recipe, config_with_variant) = parse(variant)?;
+                    }
 
-                inner.push(Stage1Inner {
-                    used_vars_from_dependencies: extra_vars_per_output[idx].clone(),
-                    exact_pins: exact_pins_per_output[idx].clone(),
-                    recipe: parsed_recipe,
-                    selector_config: config_with_variant,
-                })
-            }
+                    inner.push(Stage1Inner {
+                        used_vars_from_dependencies: extra_vars_per_output[idx].clone(),
+                        exact_pins: exact_pins_per_output[idx].clone(),
+                        recipe: parsed_recipe,
+                        selector_config: config_with_variant,
+                    })
+                }
 
-            let stage_1 = Stage1Render {
-                inner,
-                variables: combination,
-                stage_0_render: r.clone(),
-            };
+                let stage_1 = Stage1Render {
+                    inner,
+                    variables: combination,
+                    stage_0_render: r.clone(),
+                };
+                Ok::<_, VariantError>((index, stage_1))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
 
-            stage_1_renders.push(stage_1);
-        }
+        renders.sort_by_key(|(index, _)| *index);
+        stage_1_renders.extend(renders.into_iter().map(|(_, render)| render));
     }
 
     Ok(stage_1_renders)